@@ -0,0 +1,3 @@
+-- When a Discord link was found to be dead (the refresh token was rejected).
+-- NULL if the link is still alive.
+ALTER TABLE discord_auth ADD COLUMN dead_at TIMESTAMP;
@@ -0,0 +1,25 @@
+-- A log of bailouts, so they can be counted after the fact
+CREATE TABLE bailout (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    -- The match that bankrupted the user
+    match_id INTEGER NOT NULL REFERENCES battle(id),
+    inserted_at TIMESTAMP NOT NULL
+);
+
+-- Daily aggregates, rolled up nightly
+CREATE TABLE stats_daily (
+    id INTEGER PRIMARY KEY,
+    -- The UTC day these stats are for
+    day DATE NOT NULL UNIQUE,
+    -- How many matches concluded on this day
+    matches INTEGER NOT NULL,
+    -- How many wagers were placed on those matches
+    wagers INTEGER NOT NULL,
+    -- The sum of all pots of those matches
+    total_pot BIGINT NOT NULL,
+    -- How many bailouts were issued on this day
+    bailouts INTEGER NOT NULL,
+    inserted_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,17 @@
+-- Users can send chat messages over the socket, so a message is sent by
+-- either a player or a user
+CREATE TABLE message_new (
+    id INTEGER PRIMARY KEY,
+    player_id INTEGER REFERENCES player(id),
+    user_id INTEGER REFERENCES user(id),
+    content VARCHAR(255) NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+INSERT INTO message_new (id, player_id, content, inserted_at)
+SELECT id, player_id, content, inserted_at
+FROM message;
+
+DROP TABLE message;
+
+ALTER TABLE message_new RENAME TO message;
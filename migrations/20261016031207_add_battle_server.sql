@@ -0,0 +1,6 @@
+-- The server that created the match
+-- NULL for matches created before this was tracked
+ALTER TABLE battle ADD COLUMN server_id INTEGER REFERENCES server(id);
+
+-- A server can only have one ongoing match at a time
+CREATE UNIQUE INDEX battle_ongoing_server_id ON battle(server_id) WHERE status = 0;
@@ -0,0 +1,3 @@
+-- How many mobiums the wager won (or lost, if negative) when it was settled
+-- NULL if the wager hasn't been, or couldn't be, settled
+ALTER TABLE wager ADD COLUMN payout BIGINT;
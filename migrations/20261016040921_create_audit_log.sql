@@ -0,0 +1,20 @@
+-- A log of every mutating request
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY,
+    -- Who made the request
+    -- 0 for anonymous, 1 for users, 2 for servers, 3 for admins
+    actor_kind INTEGER NOT NULL DEFAULT 0,
+    -- The user or server id of the actor, NULL if anonymous
+    actor_id INTEGER,
+    -- The HTTP method, or WS for socket ops
+    method VARCHAR(16) NOT NULL,
+    -- The requested path, including any entity ids
+    path VARCHAR(255) NOT NULL,
+    -- The status of the response
+    status INTEGER NOT NULL,
+    -- A summary of what changed, if the endpoint provided one
+    summary TEXT,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX audit_log_actor ON audit_log(actor_kind, actor_id);
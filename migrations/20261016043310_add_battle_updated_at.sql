@@ -0,0 +1,4 @@
+-- When the match or any of its participants last changed
+ALTER TABLE battle ADD COLUMN updated_at TIMESTAMP;
+
+UPDATE battle SET updated_at = IFNULL(concluded_at, inserted_at);
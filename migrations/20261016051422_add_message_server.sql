@@ -0,0 +1,6 @@
+-- The server that crossposted the message, so only it can edit or delete it
+-- NULL for messages sent by users, or before this was tracked
+ALTER TABLE message ADD COLUMN server_id INTEGER REFERENCES server(id);
+
+-- When the message was last edited
+ALTER TABLE message ADD COLUMN edited_at TIMESTAMP;
@@ -0,0 +1,28 @@
+-- Outbound webhooks for third-party integrations
+CREATE TABLE webhook (
+    id INTEGER PRIMARY KEY,
+    url VARCHAR(2048) NOT NULL,
+    -- The secret deliveries are signed with
+    secret VARCHAR(255) NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+-- Events waiting to be delivered, or that were delivered
+CREATE TABLE webhook_delivery (
+    id INTEGER PRIMARY KEY,
+    webhook_id INTEGER NOT NULL REFERENCES webhook(id) ON DELETE CASCADE,
+    -- The name of the event, like match-concluded
+    event VARCHAR(64) NOT NULL,
+    -- The JSON body sent to the webhook
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- NULL once the delivery succeeded or was given up on
+    next_attempt_at TIMESTAMP,
+    delivered_at TIMESTAMP,
+    failed_at TIMESTAMP,
+    -- Why the last attempt failed
+    last_error TEXT,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX webhook_delivery_next_attempt ON webhook_delivery(next_attempt_at);
@@ -0,0 +1,5 @@
+-- Whether the wager was placed on a sandbox instance with fake mobiums
+--
+-- Sandbox wagers are not canonical, and should never be merged into
+-- production records
+ALTER TABLE wager ADD COLUMN sandbox BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,12 @@
+-- Mobiums granted to users outside of wagers
+CREATE TABLE ledger (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    -- How many mobiums were granted, or taken if negative
+    amount BIGINT NOT NULL,
+    -- Why the mobiums were granted, like starting-balance
+    reason VARCHAR(64) NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX ledger_user ON ledger(user_id, reason);
@@ -0,0 +1,7 @@
+-- How wagers on the match are paid out
+-- 0 for pool, 1 for locked odds
+ALTER TABLE battle ADD COLUMN odds_mode INTEGER NOT NULL DEFAULT 0;
+
+-- The payout multiplier locked in when the wager was placed, NULL for pool
+-- matches
+ALTER TABLE wager ADD COLUMN odds REAL;
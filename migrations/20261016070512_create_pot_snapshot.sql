@@ -0,0 +1,14 @@
+-- Pot totals over a match's betting window
+-- A snapshot is taken every time a wager changes
+CREATE TABLE pot_snapshot (
+    id INTEGER PRIMARY KEY,
+    match_id INTEGER NOT NULL REFERENCES battle(id),
+    -- The total mobiums wagered on each team
+    red INTEGER NOT NULL,
+    blue INTEGER NOT NULL,
+    -- How many wagers there were
+    wagers INTEGER NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX pot_snapshot_match_id ON pot_snapshot(match_id);
@@ -0,0 +1,12 @@
+-- The user this user was merged into, if it was linked to another account
+ALTER TABLE user ADD COLUMN merged_into INTEGER REFERENCES user(id);
+
+-- One-time codes to link accounts together
+CREATE TABLE link_code (
+    id INTEGER PRIMARY KEY,
+    -- The user that will be merged into whoever redeems the code
+    user_id INTEGER NOT NULL UNIQUE REFERENCES user(id),
+    code VARCHAR(255) NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,6 @@
+-- The chance the red team wins, from the participants' ratings at the start
+-- of the match
+-- NULL if any participant is unrated
+ALTER TABLE battle ADD COLUMN red_win_probability REAL;
+-- How evenly matched the teams are, from 0 to 1
+ALTER TABLE battle ADD COLUMN quality REAL;
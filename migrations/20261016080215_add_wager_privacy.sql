@@ -0,0 +1,2 @@
+-- Users can hide their name from their wagers.
+ALTER TABLE user ADD COLUMN show_wagers_publicly BOOLEAN NOT NULL DEFAULT TRUE;
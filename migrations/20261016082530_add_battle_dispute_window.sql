@@ -0,0 +1,5 @@
+-- When a provisional match is finalized, and paid out
+-- NULL if the match never entered a dispute window
+ALTER TABLE battle ADD COLUMN finalizes_at TIMESTAMP;
+
+CREATE INDEX battle_finalizes_at ON battle(finalizes_at) WHERE status = 3;
@@ -0,0 +1,2 @@
+-- Consecutive correct predictions, for streak bonuses.
+ALTER TABLE user ADD COLUMN streak INTEGER NOT NULL DEFAULT 0;
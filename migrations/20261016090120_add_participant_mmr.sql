@@ -0,0 +1,4 @@
+-- The displayed MMR of participants before and after their ratings were
+-- updated for a match.
+ALTER TABLE participant ADD COLUMN mmr_before INTEGER;
+ALTER TABLE participant ADD COLUMN mmr_after INTEGER;
@@ -0,0 +1,2 @@
+-- Unlisted matches are left out of match lists and the room.
+ALTER TABLE battle ADD COLUMN visibility INTEGER NOT NULL DEFAULT 0;
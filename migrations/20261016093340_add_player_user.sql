@@ -0,0 +1,5 @@
+-- Let users claim the players they race as
+ALTER TABLE player ADD COLUMN user_id INTEGER REFERENCES user(id);
+ALTER TABLE player ADD COLUMN linked_at TIMESTAMP;
+
+CREATE INDEX player_user ON player(user_id);
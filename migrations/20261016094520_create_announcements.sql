@@ -0,0 +1,11 @@
+-- Site-wide announcements, like maintenance notices
+CREATE TABLE announcement (
+    id INTEGER PRIMARY KEY,
+    message TEXT NOT NULL,
+    severity INTEGER NOT NULL DEFAULT 0,
+    user_id INTEGER REFERENCES user(id),
+    inserted_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP
+);
+
+CREATE INDEX announcement_expires_at ON announcement(expires_at);
@@ -0,0 +1,2 @@
+-- Flag concluded matches worth watching again
+ALTER TABLE battle ADD COLUMN highlights INTEGER NOT NULL DEFAULT 0;
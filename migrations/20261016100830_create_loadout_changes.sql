@@ -0,0 +1,11 @@
+-- Loadouts participants switched to mid-match
+CREATE TABLE loadout_change (
+    id INTEGER PRIMARY KEY,
+    participant_id INTEGER NOT NULL REFERENCES participant(id) ON DELETE CASCADE,
+    kart_speed INTEGER NOT NULL,
+    kart_weight INTEGER NOT NULL,
+    skin TEXT NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX loadout_change_participant_id ON loadout_change(participant_id);
@@ -0,0 +1,2 @@
+-- Let servers keep the wager bot out of individual matches
+ALTER TABLE battle ADD COLUMN disable_bot BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Player stats look up every match a player took part in
+CREATE INDEX participant_player_id ON participant(player_id);
@@ -0,0 +1,2 @@
+-- Why an administrator made a correction, NULL for automatic grants
+ALTER TABLE ledger ADD COLUMN note TEXT;
@@ -0,0 +1,2 @@
+-- When the user passed a CAPTCHA, NULL if they never had to
+ALTER TABLE user ADD COLUMN verified_at TIMESTAMP;
@@ -0,0 +1,10 @@
+-- Features that can be turned on at runtime
+CREATE TABLE feature_flag (
+    id INTEGER PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    enabled BOOLEAN NOT NULL,
+    -- The percentage of users the flag is on for, from 0 to 100
+    rollout INTEGER NOT NULL DEFAULT 100,
+    inserted_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
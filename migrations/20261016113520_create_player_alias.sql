@@ -0,0 +1,10 @@
+-- Display names players used before their current one
+CREATE TABLE player_alias (
+    id INTEGER PRIMARY KEY,
+    player_id INTEGER NOT NULL REFERENCES player(id),
+    display_name VARCHAR(255) NOT NULL,
+    -- When the player stopped using the name
+    replaced_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX player_alias_player_id ON player_alias(player_id, replaced_at);
@@ -0,0 +1,4 @@
+-- How the wager bot wagers, set at runtime
+-- NULL falls back to the server config
+ALTER TABLE user ADD COLUMN bot_wager_amount BIGINT;
+ALTER TABLE user ADD COLUMN bot_strategy INTEGER;
@@ -0,0 +1,29 @@
+-- Trigram index of player names and short ids, for fuzzy player search
+CREATE VIRTUAL TABLE player_search USING fts5(
+    display_name,
+    short_id,
+    content = 'player',
+    content_rowid = 'id',
+    tokenize = 'trigram'
+);
+
+INSERT INTO player_search(player_search) VALUES ('rebuild');
+
+-- Keep the index in sync with the player table
+CREATE TRIGGER player_search_insert AFTER INSERT ON player BEGIN
+    INSERT INTO player_search(rowid, display_name, short_id)
+    VALUES (new.id, new.display_name, new.short_id);
+END;
+
+CREATE TRIGGER player_search_delete AFTER DELETE ON player BEGIN
+    INSERT INTO player_search(player_search, rowid, display_name, short_id)
+    VALUES ('delete', old.id, old.display_name, old.short_id);
+END;
+
+-- Ratings change far more often than names, so only names reindex
+CREATE TRIGGER player_search_update AFTER UPDATE OF display_name, short_id ON player BEGIN
+    INSERT INTO player_search(player_search, rowid, display_name, short_id)
+    VALUES ('delete', old.id, old.display_name, old.short_id);
+    INSERT INTO player_search(rowid, display_name, short_id)
+    VALUES (new.id, new.display_name, new.short_id);
+END;
@@ -0,0 +1,25 @@
+-- Time-boxed promotions, like boosted payouts during a special stream
+CREATE TABLE promo_event (
+    id INTEGER PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    payout_multiplier REAL NOT NULL DEFAULT 1.0,
+    first_wager_bonus BIGINT NOT NULL DEFAULT 0,
+    user_id INTEGER REFERENCES user(id),
+    starts_at TIMESTAMP NOT NULL,
+    ends_at TIMESTAMP NOT NULL,
+    -- When connected clients were told the event started
+    announced_at TIMESTAMP,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX promo_event_ends_at ON promo_event(ends_at);
+
+-- Users who were given an event's first wager bonus
+CREATE TABLE promo_event_bonus (
+    id INTEGER PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES promo_event(id),
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    inserted_at TIMESTAMP NOT NULL,
+
+    UNIQUE(event_id, user_id)
+);
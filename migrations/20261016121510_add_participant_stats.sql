@@ -0,0 +1,2 @@
+-- Race telemetry reported by servers, as a JSON object
+ALTER TABLE participant ADD COLUMN stats TEXT;
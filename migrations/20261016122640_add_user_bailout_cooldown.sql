@@ -0,0 +1,3 @@
+-- When a user's wagers stop being capped after a bailout
+-- NULL if the user was never put on a cooldown
+ALTER TABLE user ADD COLUMN bailout_cooldown_until TIMESTAMP;
@@ -0,0 +1,13 @@
+-- The most recent events broadcast to the room, for debugging
+-- Only written if room event logging is persisted
+CREATE TABLE room_event (
+    id INTEGER PRIMARY KEY,
+    kind TEXT NOT NULL,
+    -- JSON of the message sent, if every socket was sent the same one
+    message TEXT,
+    -- JSON array of the users the event was meant for
+    recipients TEXT NOT NULL,
+    private_to INTEGER,
+    receivers INTEGER NOT NULL,
+    sent_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Penalties given to abusive users
+CREATE TABLE penalty (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    -- 0 = warning, 1 = wager ban, 2 = chat mute
+    kind INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    -- The administrator who gave the penalty
+    issued_by INTEGER REFERENCES user(id),
+    -- NULL if the penalty lasts until it is revoked
+    expires_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX penalty_user_id ON penalty(user_id);
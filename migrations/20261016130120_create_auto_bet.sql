@@ -0,0 +1,23 @@
+-- Rules that wager on new matches for users
+CREATE TABLE auto_bet (
+    user_id INTEGER PRIMARY KEY REFERENCES user(id),
+    -- 0 = favourite, 1 = underdog, 2 = red, 3 = blue
+    pick INTEGER NOT NULL,
+    mobiums INTEGER NOT NULL,
+    -- NULL if the rule can wager any amount in a day
+    daily_cap INTEGER,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    inserted_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+-- Wagers placed by auto-bet rules, to enforce daily caps
+CREATE TABLE auto_bet_placement (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    match_id INTEGER NOT NULL REFERENCES battle(id),
+    mobiums INTEGER NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX auto_bet_placement_user_id ON auto_bet_placement(user_id, inserted_at);
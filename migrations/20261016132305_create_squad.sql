@@ -0,0 +1,19 @@
+-- Groups of users whose wagers are pooled
+CREATE TABLE squad (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    -- The member who leads the squad
+    owner_id INTEGER NOT NULL REFERENCES user(id),
+    inserted_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
+
+-- A user can only be in one squad at a time
+CREATE TABLE squad_member (
+    user_id INTEGER PRIMARY KEY REFERENCES user(id),
+    squad_id INTEGER NOT NULL REFERENCES squad(id),
+    -- Only wagers placed after this count towards the squad
+    joined_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX squad_member_squad_id ON squad_member(squad_id);
@@ -0,0 +1,10 @@
+-- Reactions users left on chat messages
+CREATE TABLE message_reaction (
+    message_id INTEGER NOT NULL REFERENCES message(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    reaction INTEGER NOT NULL,
+    inserted_at TIMESTAMP NOT NULL,
+
+    -- Each user can only use each reaction once per message
+    PRIMARY KEY (message_id, user_id, reaction)
+);
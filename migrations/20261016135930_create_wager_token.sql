@@ -0,0 +1,14 @@
+-- The last client token each user placed a wager on a match with, so retried
+-- requests aren't placed twice
+CREATE TABLE wager_token (
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    match_id INTEGER NOT NULL REFERENCES battle(id),
+    client_token TEXT NOT NULL,
+    -- The receipt sent for the wager, as JSON
+    receipt TEXT NOT NULL,
+    inserted_at TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (user_id, match_id)
+);
+
+CREATE INDEX wager_token_inserted_at ON wager_token(inserted_at);
@@ -0,0 +1,3 @@
+-- Set when an admin corrects a player's name, so game servers can't change it
+-- back
+ALTER TABLE player ADD COLUMN display_name_locked BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,9 @@
+-- Notes on what happened in a match, for overlay tickers
+CREATE TABLE commentary (
+    id INTEGER PRIMARY KEY,
+    match_id INTEGER NOT NULL REFERENCES battle(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX commentary_match_id ON commentary(match_id);
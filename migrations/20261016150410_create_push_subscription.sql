@@ -0,0 +1,21 @@
+-- Browsers subscribed to Web Push notifications for a user
+CREATE TABLE push_subscription (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    endpoint VARCHAR(2048) NOT NULL UNIQUE,
+    -- The browser's public key and auth secret, as unpadded base64url
+    p256dh VARCHAR(255) NOT NULL,
+    auth VARCHAR(255) NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX push_subscription_user_id ON push_subscription(user_id);
+
+-- Notifications waiting to be pushed to every subscription of a user
+CREATE TABLE push_notification (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    -- The JSON payload, before it is encrypted for each subscription
+    payload TEXT NOT NULL,
+    inserted_at TIMESTAMP NOT NULL
+);
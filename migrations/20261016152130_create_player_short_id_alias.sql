@@ -0,0 +1,9 @@
+-- Short ids players had before picking a new one
+CREATE TABLE player_short_id_alias (
+    short_id CHAR(6) PRIMARY KEY,
+    player_id INTEGER NOT NULL REFERENCES player(id),
+    -- When the player stopped using the id
+    replaced_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX player_short_id_alias_player_id ON player_short_id_alias(player_id, replaced_at);
@@ -0,0 +1,3 @@
+-- When the user was last seen in the community Discord server, NULL if they
+-- weren't in it the last time they logged in
+ALTER TABLE user ADD COLUMN guild_verified_at TIMESTAMP;
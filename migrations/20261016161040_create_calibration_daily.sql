@@ -0,0 +1,27 @@
+-- Daily odds calibration aggregates, rolled up nightly
+--
+-- Concluded matches are bucketed by how likely their favorite was to win,
+-- going by either ratings or pots
+CREATE TABLE calibration_daily (
+    id INTEGER PRIMARY KEY,
+    -- The UTC day the matches concluded on
+    day DATE NOT NULL,
+    -- Where the odds came from
+    -- 0 for ratings, 1 for pots
+    source INTEGER NOT NULL,
+    -- The tenth the favorite's win probability falls in, from 5 (50% to 60%)
+    -- to 9 (90% to 100%)
+    bucket INTEGER NOT NULL,
+    -- How many matches are in this bucket
+    matches INTEGER NOT NULL,
+    -- How many of those matches the favorite won
+    favorite_wins INTEGER NOT NULL,
+    -- The sum of the favorites' win probabilities
+    probability_sum REAL NOT NULL,
+    -- The sum of the squared errors of those probabilities
+    squared_error_sum REAL NOT NULL,
+    inserted_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+
+    UNIQUE (day, source, bucket)
+);
@@ -0,0 +1,41 @@
+-- The line of a match's over/under market on the winner's finish time
+-- NULL if the match has no such market
+ALTER TABLE battle ADD COLUMN finish_time_line INTEGER;
+
+-- Users can now have a wager on each market of a match
+--
+-- SQLite can't change constraints, so the table is rebuilt
+CREATE TABLE wager_new (
+    id INTEGER PRIMARY KEY,
+    -- Who made the bet
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    -- On what match was the bet made
+    match_id INTEGER NOT NULL REFERENCES battle(id),
+    -- What the bet is on
+    -- 0 for the winner, 1 for the winner's finish time
+    market INTEGER NOT NULL DEFAULT 0,
+    -- What the bet picked
+    -- On the winner: 0 for red, 1 for blue
+    -- On the finish time: 0 for under the line, 1 for over
+    victor INTEGER NOT NULL,
+    -- How many monoys are on the bet
+    mobiums BIGINT NOT NULL,
+    inserted_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    payout BIGINT,
+    sandbox BOOLEAN NOT NULL DEFAULT FALSE,
+    odds REAL,
+
+    -- A user can only have one bet on each market of a match
+    UNIQUE (user_id, match_id, market)
+);
+
+INSERT INTO wager_new
+    (id, user_id, match_id, victor, mobiums, inserted_at, updated_at, payout, sandbox, odds)
+SELECT
+    id, user_id, match_id, victor, mobiums, inserted_at, updated_at, payout, sandbox, odds
+FROM wager;
+
+DROP TABLE wager;
+
+ALTER TABLE wager_new RENAME TO wager;
@@ -0,0 +1,26 @@
+-- Wagers on each market of a match are retried on their own
+--
+-- SQLite can't change constraints, so the table is rebuilt
+CREATE TABLE wager_token_new (
+    user_id INTEGER NOT NULL REFERENCES user(id),
+    match_id INTEGER NOT NULL REFERENCES battle(id),
+    -- The market the wager was placed on
+    -- 0 for the winner, 1 for the winner's finish time
+    market INTEGER NOT NULL DEFAULT 0,
+    client_token TEXT NOT NULL,
+    -- The receipt sent for the wager, as JSON
+    receipt TEXT NOT NULL,
+    inserted_at TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (user_id, match_id, market)
+);
+
+INSERT INTO wager_token_new (user_id, match_id, client_token, receipt, inserted_at)
+SELECT user_id, match_id, client_token, receipt, inserted_at
+FROM wager_token;
+
+DROP TABLE wager_token;
+
+ALTER TABLE wager_token_new RENAME TO wager_token;
+
+CREATE INDEX wager_token_inserted_at ON wager_token(inserted_at);
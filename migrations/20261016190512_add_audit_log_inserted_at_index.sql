@@ -0,0 +1,2 @@
+-- Old audit log entries are cleaned up by age
+CREATE INDEX audit_log_inserted_at ON audit_log(inserted_at);
@@ -0,0 +1,32 @@
+-- The pots on each side of every market of a match
+--
+-- Market 0 is the winner (victor 0 for red, 1 for blue), market 1 is the
+-- winner's finish time (victor 0 for under the line, 1 for over)
+--
+-- Each pot is a subquery and not a GROUP BY so SQLite can flatten the view
+-- into whatever query joins it, and only sums the matches it reads
+CREATE VIEW battle_pot AS
+SELECT
+    b.id AS match_id,
+    (
+        SELECT IFNULL(SUM(w.mobiums), 0)
+        FROM wager w
+        WHERE w.match_id = b.id AND w.market = 0 AND w.victor = 0
+    ) AS red_pot,
+    (
+        SELECT IFNULL(SUM(w.mobiums), 0)
+        FROM wager w
+        WHERE w.match_id = b.id AND w.market = 0 AND w.victor = 1
+    ) AS blue_pot,
+    (
+        SELECT IFNULL(SUM(w.mobiums), 0)
+        FROM wager w
+        WHERE w.match_id = b.id AND w.market = 1 AND w.victor = 0
+    ) AS under_pot,
+    (
+        SELECT IFNULL(SUM(w.mobiums), 0)
+        FROM wager w
+        WHERE w.match_id = b.id AND w.market = 1 AND w.victor = 1
+    ) AS over_pot,
+    (SELECT MAX(w.updated_at) FROM wager w WHERE w.match_id = b.id) AS wagered_at
+FROM battle b;
@@ -0,0 +1,298 @@
+//! Administrative representations.
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{User, UserId, message::Message};
+
+/// The wager bot's runtime settings.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WagerBot {
+    /// Whether the bot is wagering on matches.
+    ///
+    /// Servers can still keep it out of individual matches.
+    pub enabled: bool,
+    /// The bot's display name.
+    pub display_name: String,
+    /// A URL to the bot's avatar.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+    /// How many mobiums the bot wagers.
+    pub wager_amount: i64,
+    /// How the bot decides how much to wager.
+    pub strategy: BotStrategy,
+}
+
+/// How the wager bot decides how much to wager.
+///
+/// The bot only ever wagers on a team nobody else wagered on.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Serialize,
+    PartialEq,
+    Eq,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum BotStrategy {
+    /// Wagers the wager amount.
+    #[default]
+    Fixed = 0,
+    /// Wagers as much as is on the other team, and at least the wager
+    /// amount.
+    MatchPot = 1,
+}
+
+/// A feature that can be turned on at runtime.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FeatureFlag {
+    /// The name of the flag.
+    pub name: String,
+    /// Whether the flag is on.
+    pub enabled: bool,
+    /// The percentage of users the flag is on for, from 0 to 100.
+    pub rollout: u8,
+    /// When the flag was last changed.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Wager bot bankroll statistics.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BotStats {
+    /// How many mobiums the bot has right now.
+    pub mobiums: i64,
+    /// The bot's configured daily bankroll, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bankroll: Option<i64>,
+    /// Daily profit and loss, newest first.
+    ///
+    /// Days the bot didn't wager on are left out.
+    pub days: Vec<BotDailyStats>,
+}
+
+/// Wager bot statistics for a single UTC day.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BotDailyStats {
+    /// The day these stats are for.
+    pub day: NaiveDate,
+    /// How many settled matches the bot wagered on.
+    pub wagers: i64,
+    /// How many mobiums the bot put into pots.
+    pub wagered: i64,
+    /// How many mobiums the bot won, or lost if negative.
+    ///
+    /// A loss is how much the house subsidized pots that day.
+    pub profit: i64,
+}
+
+/// A single audit log entry.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditLogEntry {
+    /// The id of the entry.
+    pub id: i32,
+    /// What kind of client made the request.
+    pub actor_kind: ActorKind,
+    /// The id of the user or server that made the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor_id: Option<i32>,
+    /// The username or server name of the actor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor_name: Option<String>,
+    /// The HTTP method of the request, or `WS` for socket ops.
+    pub method: String,
+    /// The requested path.
+    pub path: String,
+    /// The status of the response.
+    pub status: u16,
+    /// A summary of what changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// When the request was made.
+    pub created_at: DateTime<Utc>,
+}
+
+/// The kind of client that made a request.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, TryFromPrimitive, IntoPrimitive,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum ActorKind {
+    /// The client was not authenticated.
+    Anonymous = 0,
+    /// A logged in user.
+    User = 1,
+    /// A game server.
+    Server = 2,
+    /// A logged in administrator.
+    Admin = 3,
+}
+
+/// A statement that took longer than the slow query threshold.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SlowQuery {
+    /// The first few words of the statement.
+    pub summary: String,
+    /// The full statement, with literals redacted.
+    ///
+    /// Bound parameters are never included.
+    pub statement: String,
+    /// How long the statement took, in milliseconds.
+    pub elapsed_ms: f64,
+    /// How many rows the statement changed.
+    pub rows_affected: u64,
+    /// How many rows the statement returned.
+    pub rows_returned: u64,
+    /// Steps of the query plan that could use an index, like full table
+    /// scans.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub advice: Vec<String>,
+    /// When the statement finished.
+    pub created_at: DateTime<Utc>,
+}
+
+/// The health of a supervised background task.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TaskHealth {
+    /// The name of the task.
+    pub name: String,
+    /// Whether the task is healthy.
+    ///
+    /// Tasks are unhealthy once they fail a few times in a row.
+    pub healthy: bool,
+    /// Whether the task is running right now.
+    pub running: bool,
+    /// When the task last started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// When the task last finished without failing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success_at: Option<DateTime<Utc>>,
+    /// What the task last failed with, including panics.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// When the task last failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// How many times in a row the task failed.
+    pub failures: u32,
+    /// How many times the task was restarted after failing.
+    pub restarts: u64,
+    /// When the task is tried again, if it is backing off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+/// A socket connected to the room.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Socket {
+    /// The id of the socket.
+    pub id: String,
+    /// The id of the socket's session.
+    ///
+    /// Sessions outlive their sockets, so this stays the same across
+    /// reconnects.
+    pub session_id: String,
+    /// The user the socket is authenticated as.
+    ///
+    /// Anonymous sockets have no user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    /// When the socket connected.
+    pub connected_at: DateTime<Utc>,
+    /// The sequence number of the last heartbeat the socket sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_heartbeat: Option<i32>,
+    /// The round trip time of the last ping the socket answered, in ms.
+    ///
+    /// Missing if pings are off, or the socket hasn't answered one yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtt: Option<i64>,
+    /// How many times the socket's session fell behind on room events and
+    /// had to be resynced.
+    pub lag_count: u64,
+    /// How many times the socket's session was resumed.
+    #[serde(default)]
+    pub reconnects: u64,
+}
+
+/// An event the room broadcast to its sockets.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RoomEvent {
+    /// The id of the event. Later events have higher ids.
+    pub id: i64,
+    /// The kind of event, like `update-battle`.
+    pub kind: String,
+    /// The message sent to sockets subscribed to the event.
+    ///
+    /// Missing for events that are different for every user, like payouts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<Message>,
+    /// The users the event was meant for.
+    ///
+    /// Empty if the event was for every socket.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<UserId>,
+    /// The user whose private wager this is. Other sockets were sent the
+    /// wager without its user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_to: Option<UserId>,
+    /// How many sockets were listening when the event was sent.
+    pub receivers: u64,
+    /// When the event was sent.
+    pub sent_at: DateTime<Utc>,
+}
+
+/// A snapshot of the health of the mobiums economy.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EconomyReport {
+    /// How many mobiums users hold in total.
+    ///
+    /// Automated users, like the wager bot, are left out.
+    pub circulation: i64,
+    /// How many users hold mobiums.
+    pub users: i64,
+    /// How concentrated mobiums are, from `0` (everyone holds the same amount)
+    /// to `1` (one user holds everything).
+    pub gini: f64,
+    /// How many bailouts were issued in the last week.
+    pub bailouts_this_week: i64,
+    /// How many mobiums automated users won, or lost if negative, over all
+    /// settled matches.
+    pub house_profit: i64,
+    /// The users with the most mobiums, richest first.
+    pub top_balances: Vec<Balance>,
+    /// When the report was made.
+    pub created_at: DateTime<Utc>,
+}
+
+/// The result of importing balance corrections.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImportedCorrections {
+    /// How many corrections were applied.
+    pub corrections: i64,
+    /// The sum of all corrections, or how many mobiums were taken if
+    /// negative.
+    pub net_amount: i64,
+}
+
+/// A user's balance.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Balance {
+    /// The username of the user.
+    pub username: String,
+    /// The display name of the user.
+    pub display_name: String,
+    /// How many mobiums the user has.
+    pub mobiums: i64,
+}
@@ -0,0 +1,53 @@
+//! Announcements.
+
+use chrono::{DateTime, Utc};
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use serde::{Deserialize, Serialize};
+
+/// A message shown to everyone on the site, like a maintenance notice.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Announcement {
+    /// The id of the announcement.
+    pub id: i32,
+    /// The announcement itself.
+    pub message: String,
+    /// How urgent the announcement is.
+    pub severity: Severity,
+    /// When the announcement was made.
+    pub created_at: DateTime<Utc>,
+    /// When the announcement should stop being shown.
+    ///
+    /// Announcements without an expiry are shown until they are removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// How urgent an announcement is.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Serialize,
+    PartialEq,
+    Eq,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Severity {
+    /// Nice to know.
+    #[default]
+    Info = 0,
+    /// Something users should plan around, like upcoming maintenance.
+    Warning = 1,
+    /// Something is wrong right now.
+    Critical = 2,
+}
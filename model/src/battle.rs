@@ -3,16 +3,24 @@
 use derive_more::Deref;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
+use std::cmp::Ordering;
+
 use chrono::{DateTime, Utc};
 
 use serde::{Deserialize, Serialize};
 
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
-use crate::{player::Player, user::User};
+use crate::{
+    id::PlayerShortId,
+    mobiums::Mobiums,
+    player::Player,
+    user::{User, WagerBalance},
+};
 
 /// A single match.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Battle {
     /// The unique identifier of the match.
     pub id: String,
@@ -29,10 +37,96 @@ pub struct Battle {
     /// The amount of time that will pass before wagers close, in ms.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub closes_in: Option<i64>,
+    /// When wagers close, or closed.
+    ///
+    /// Compare against [`Battle::server_time`], not the client's clock.
+    #[serde(default)]
+    pub bets_close_at: DateTime<Utc>,
+    /// When the match was concluded or cancelled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concluded_at: Option<DateTime<Utc>>,
+    /// The server's clock when the match was sent.
+    #[serde(default)]
+    pub server_time: DateTime<Utc>,
+    /// How wagers on the match are paid out.
+    #[serde(default)]
+    pub odds_mode: OddsMode,
+    /// How the match is expected to go, from the participants' ratings.
+    ///
+    /// Missing if ratings are disabled, or any participant is unrated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prediction: Option<BattlePrediction>,
+    /// When a [`BattleStatus::Provisional`] match will be finalized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finalizes_at: Option<DateTime<Utc>>,
+    /// Who can find the match.
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// What made the match worth watching again, if anything.
+    ///
+    /// Matches are only checked for highlights once they are concluded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<HighlightTag>,
+    /// The name of the game server that created the match.
+    ///
+    /// Missing for matches created before servers were tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    /// How many mobiums are riding on the red team.
+    #[serde(default)]
+    pub red_pot: i64,
+    /// How many mobiums are riding on the blue team.
+    #[serde(default)]
+    pub blue_pot: i64,
+    /// The over/under market on the winner's finish time.
+    ///
+    /// Missing if the match doesn't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_time_line: Option<FinishTimeLine>,
+}
+
+/// An over/under market on the finish time of a match's winner.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct FinishTimeLine {
+    /// The line, in the same units as finish times.
+    pub line: i32,
+    /// How many mobiums are riding on the winner finishing under the line.
+    pub under_pot: i64,
+    /// How many mobiums are riding on the winner finishing over the line.
+    pub over_pot: i64,
+}
+
+/// How a match is expected to go.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct BattlePrediction {
+    /// The chance the red team wins, from 0 to 1.
+    pub red: f64,
+    /// The chance the blue team wins, from 0 to 1.
+    pub blue: f64,
+    /// How evenly matched the teams are, from 0 to 1.
+    ///
+    /// A coin flip is `1`, a sure thing is `0`.
+    pub quality: f64,
+}
+
+impl BattlePrediction {
+    /// Creates a prediction from the chance the red team wins.
+    pub fn from_red(red: f64) -> BattlePrediction {
+        let red = red.clamp(0.0, 1.0);
+
+        BattlePrediction {
+            red,
+            blue: 1.0 - red,
+            quality: 1.0 - (2.0 * red - 1.0).abs(),
+        }
+    }
 }
 
 /// A participant in a match.
 #[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Participant {
     /// The player participating.
     #[deref]
@@ -55,6 +149,37 @@ pub struct Participant {
     /// The skin the player is running.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub skin: Option<String>,
+    /// How much the player's MMR moved because of the match.
+    ///
+    /// Only set once the match is over, and ratings were updated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mmr_delta: Option<i32>,
+    /// How many users have wagered on the player's team.
+    #[serde(default)]
+    pub backers: i64,
+    /// How many mobiums are riding on the player's team.
+    #[serde(default)]
+    pub backed_mobiums: i64,
+    /// Race telemetry reported by the server, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ParticipantStats>,
+}
+
+/// Race telemetry of a participant.
+///
+/// Every field is optional, since not every server reports everything.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ParticipantStats {
+    /// The player's fastest lap, in tics.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(range(min = 0)))]
+    pub fastest_lap: Option<i32>,
+    /// How many rings the player was holding when they finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(range(min = 0)))]
+    pub rings: Option<i32>,
 }
 
 /// The match's status.
@@ -70,7 +195,9 @@ pub struct Participant {
     TryFromPrimitive,
     IntoPrimitive,
 )]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
 #[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema_repr))]
 pub enum BattleStatus {
     /// The match is ongoing. No victors have been determined.
     Ongoing = 0,
@@ -80,6 +207,11 @@ pub enum BattleStatus {
     ///
     /// Wagers were refunded, and the pot was cancelled.
     Cancelled = 2,
+    /// The match finished, but its results can still be amended.
+    ///
+    /// Wagers are paid out and ratings updated once the match is finalized,
+    /// either when its dispute window runs out, or explicitly.
+    Provisional = 3,
 }
 
 /// A team side.
@@ -95,7 +227,9 @@ pub enum BattleStatus {
     TryFromPrimitive,
     IntoPrimitive,
 )]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
 #[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema_repr))]
 pub enum PlayerTeam {
     /// The red team.
     ///
@@ -107,16 +241,315 @@ pub enum PlayerTeam {
     Blue = 1,
 }
 
+/// What a wager is on.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize_repr,
+    Serialize_repr,
+    PartialEq,
+    Eq,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
+)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema_repr))]
+pub enum WagerMarket {
+    /// Which team wins.
+    #[default]
+    Winner = 0,
+    /// Which side of the match's [`FinishTimeLine`] the winner finishes on.
+    FinishTime = 1,
+}
+
+/// A side of a [`FinishTimeLine`].
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize_repr,
+    Serialize_repr,
+    PartialEq,
+    Eq,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
+)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema_repr))]
+pub enum LineSide {
+    /// The winner finishes faster than the line.
+    Under = 0,
+    /// The winner finishes slower than the line.
+    Over = 1,
+}
+
+impl LineSide {
+    /// The side a finish time lands on.
+    ///
+    /// Returns `None` if it lands right on the line, which is a push.
+    pub fn of(finish_time: i32, line: i32) -> Option<LineSide> {
+        match finish_time.cmp(&line) {
+            Ordering::Less => Some(LineSide::Under),
+            Ordering::Greater => Some(LineSide::Over),
+            Ordering::Equal => None,
+        }
+    }
+}
+
+/// How wagers on a match are paid out.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize_repr,
+    Serialize_repr,
+    PartialEq,
+    Eq,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
+)]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema_repr))]
+pub enum OddsMode {
+    /// Winners split the final pots.
+    ///
+    /// Late wagers dilute the payouts of early ones.
+    #[default]
+    Pool = 0,
+    /// Every wager is paid out at the odds the pots implied when it was
+    /// placed.
+    ///
+    /// Winnings are scaled down if they add up to more than the losing pot,
+    /// and whatever is left over is burned.
+    Locked = 1,
+}
+
+/// Who can find a match.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize_repr,
+    Serialize_repr,
+    PartialEq,
+    Eq,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
+)]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema_repr))]
+pub enum Visibility {
+    /// The match is listed, and streamed to the room.
+    #[default]
+    Public = 0,
+    /// The match can only be found by its id.
+    ///
+    /// It is left out of match lists, and clients in the room are never told
+    /// about it.
+    Unlisted = 1,
+}
+
+impl Visibility {
+    /// Whether the match is listed, and streamed to the room.
+    pub fn is_public(self) -> bool {
+        self == Visibility::Public
+    }
+}
+
+/// Something that made a match worth watching again.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, TryFromPrimitive, IntoPrimitive,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum HighlightTag {
+    /// The top two finishers were on different teams, and finished within a
+    /// hair of each other.
+    PhotoFinish = 0,
+    /// The team that was expected to lose won.
+    Upset = 1,
+    /// The match had the biggest pot yet.
+    RecordPot = 2,
+}
+
+impl HighlightTag {
+    /// All highlight tags.
+    pub const ALL: [HighlightTag; 3] = [
+        HighlightTag::PhotoFinish,
+        HighlightTag::Upset,
+        HighlightTag::RecordPot,
+    ];
+
+    /// Packs highlight tags into a bitmask.
+    pub fn to_mask(tags: &[HighlightTag]) -> u32 {
+        tags.iter().fold(0, |mask, tag| mask | 1 << u8::from(*tag))
+    }
+
+    /// Unpacks highlight tags from a bitmask.
+    ///
+    /// Unknown bits are ignored.
+    pub fn from_mask(mask: u32) -> Vec<HighlightTag> {
+        HighlightTag::ALL
+            .into_iter()
+            .filter(|tag| mask & 1 << u8::from(*tag) != 0)
+            .collect()
+    }
+}
+
 /// A battle bet.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct BattleWager {
     /// The user that made this wager.
+    ///
+    /// Missing if the user keeps their wagers private.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub user: Option<User>,
     /// The wager amount.
-    pub mobiums: i64,
+    pub mobiums: Mobiums,
     /// What team the player is betting to win.
     pub victor: PlayerTeam,
+    /// The payout multiplier locked in when the wager was placed.
+    ///
+    /// Only set on matches with [`OddsMode::Locked`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub odds: Option<f64>,
+    /// Whether the wager was placed by an automated user, like the wager bot.
+    ///
+    /// Set even if the user is missing.
+    #[serde(default)]
+    pub is_bot: bool,
     /// When the wager was last updated at.
     pub updated_at: DateTime<Utc>,
 }
+
+/// A wager that was just placed.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+pub struct WagerReceipt {
+    /// The wager.
+    #[deref]
+    #[serde(flatten)]
+    pub wager: BattleWager,
+    /// The user's mobiums, now that the wager is placed.
+    pub balance_after_wager: WagerBalance,
+}
+
+/// A bet on which side of a match's [`FinishTimeLine`] the winner finishes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LineWager {
+    /// The user that made this wager.
+    ///
+    /// Missing if the user keeps their wagers private.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    /// The wager amount.
+    pub mobiums: Mobiums,
+    /// What side of the line the player is betting the winner finishes on.
+    pub side: LineSide,
+    /// When the wager was last updated at.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A line wager that was just placed.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+pub struct LineWagerReceipt {
+    /// The wager.
+    #[deref]
+    #[serde(flatten)]
+    pub wager: LineWager,
+    /// The user's mobiums, now that the wager is placed.
+    pub balance_after_wager: WagerBalance,
+}
+
+/// Quick-bet amounts for a user on a match.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WagerSuggestions {
+    /// The most the user can wager on the match.
+    pub max_mobiums: Mobiums,
+    /// The suggested wagers, smallest first.
+    pub suggestions: Vec<WagerSuggestion>,
+}
+
+/// A suggested wager.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WagerSuggestion {
+    /// The share of the user's available mobiums, from `0.0` to `1.0`.
+    pub share: f64,
+    /// How many mobiums to wager.
+    pub mobiums: Mobiums,
+}
+
+/// The pots of a match at some point during betting.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PotSnapshot {
+    /// The total mobiums wagered on the red team.
+    pub red: i64,
+    /// The total mobiums wagered on the blue team.
+    pub blue: i64,
+    /// How many wagers were placed.
+    pub wagers: i64,
+    /// When the snapshot was taken.
+    pub created_at: DateTime<Utc>,
+}
+
+/// A participant changing their loadout mid-match, like between rounds.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LoadoutChange {
+    /// The id of the player.
+    pub player_id: PlayerShortId,
+    /// The player's new kartspeed.
+    pub kart_speed: i32,
+    /// The player's new kartweight.
+    pub kart_weight: i32,
+    /// The skin the player is now running.
+    pub skin: String,
+    /// When the loadout was changed.
+    pub changed_at: DateTime<Utc>,
+}
+
+/// A note on what is happening in a match, like "red spins out in sector 2".
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct CommentaryNote {
+    /// The id of the note.
+    pub id: i32,
+    /// What happened.
+    pub content: String,
+    /// When the note was made.
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_highlight_mask() {
+        let tags = [HighlightTag::PhotoFinish, HighlightTag::RecordPot];
+        assert_eq!(HighlightTag::to_mask(&tags), 0b101);
+        assert_eq!(HighlightTag::from_mask(0b101), tags);
+        assert_eq!(HighlightTag::from_mask(0), vec![]);
+        // bits from newer versions are dropped
+        assert_eq!(HighlightTag::from_mask(0b1010), vec![HighlightTag::Upset]);
+    }
+
+    #[test]
+    pub fn test_line_side() {
+        assert_eq!(LineSide::of(59_000, 60_000), Some(LineSide::Under));
+        assert_eq!(LineSide::of(61_000, 60_000), Some(LineSide::Over));
+        assert_eq!(LineSide::of(60_000, 60_000), None);
+    }
+}
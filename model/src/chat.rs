@@ -1,16 +1,70 @@
 //! Chat crossposting module.
 
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
 use serde::{Deserialize, Serialize};
 
-use crate::Player;
+use crate::{Player, User};
 
 /// A chat message.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "schemars",
+    derive(schemars::JsonSchema),
+    schemars(rename = "ChatMessage")
+)]
 pub struct Message {
-    /// The player that sent this message.
-    pub player: Player,
+    /// The unique identifier of the message.
+    pub id: i32,
+    /// The player that sent this message, if it was sent in-game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<Player>,
+    /// The user that sent this message, if it was sent over the socket.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
     /// The content of the player's message.
     pub content: String,
     /// When the message was created.
     pub created_at: String,
+    /// When the message was last edited, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<String>,
+    /// How many users reacted to the message, with each reaction.
+    ///
+    /// Reactions nobody used are left out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<ReactionCount>,
+}
+
+/// A reaction users can leave on a chat message.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, TryFromPrimitive, IntoPrimitive,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schemars", schemars(rename_all = "snake_case"))]
+pub enum Reaction {
+    /// The fire emoji.
+    Fire = 0,
+    /// The laughing emoji.
+    Laugh = 1,
+    /// The surprised emoji.
+    Wow = 2,
+    /// The crying emoji.
+    Sad = 3,
+    /// The clapping emoji.
+    Clap = 4,
+    /// The heart emoji.
+    Heart = 5,
+}
+
+/// How many users reacted to a message with a reaction.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ReactionCount {
+    /// The reaction.
+    pub reaction: Reaction,
+    /// How many users reacted with it.
+    pub count: i32,
 }
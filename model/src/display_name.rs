@@ -0,0 +1,67 @@
+//! Display names.
+//!
+//! Display names come from Discord and from game servers, and end up in
+//! overlays and chat. They are cleaned up before they are stored.
+
+use unicode_normalization::UnicodeNormalization;
+
+/// How many characters display names are cut down to by default.
+pub const DEFAULT_MAX_LENGTH: usize = 32;
+
+/// Converts plaintext to a "usable display name."
+///
+/// The name is NFC normalized, control and text direction characters are
+/// stripped, surrounding whitespace is trimmed, and anything past
+/// `max_length` characters is cut off.
+///
+/// The result may be empty if there was nothing usable in the name.
+pub fn to_display_name_lossy(name: &str, max_length: usize) -> String {
+    let name = name
+        .nfc()
+        .filter(|ch| !is_stripped_char(ch))
+        .collect::<String>();
+
+    name.trim()
+        .chars()
+        .take(max_length)
+        .collect::<String>()
+        .trim_end()
+        .to_owned()
+}
+
+fn is_stripped_char(ch: &char) -> bool {
+    // direction overrides and isolates can flip the rest of an overlay
+    ch.is_control() || matches!(ch, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_to_display_name_lossy() {
+        // Valid display names
+        assert_eq!(to_display_name_lossy("Tails", 32), "Tails");
+        assert_eq!(to_display_name_lossy("Dr. Robotnik", 32), "Dr. Robotnik");
+        assert_eq!(to_display_name_lossy("ソニック", 32), "ソニック");
+
+        // Decomposed characters are composed
+        assert_eq!(
+            to_display_name_lossy("Pok\u{65}\u{301}mon", 32),
+            "Pok\u{e9}mon"
+        );
+
+        // Control and direction characters are stripped
+        assert_eq!(to_display_name_lossy("Knuck\u{0}les\n", 32), "Knuckles");
+        assert_eq!(to_display_name_lossy("\u{202E}Amy", 32), "Amy");
+
+        // Long names are cut short, by character
+        assert_eq!(to_display_name_lossy("Metal Sonic", 5), "Metal");
+        assert_eq!(to_display_name_lossy("ソニック", 2), "ソニ");
+        assert_eq!(to_display_name_lossy("Big the Cat", 4), "Big");
+
+        // Sanity check
+        assert_eq!(to_display_name_lossy("", 32), "");
+        assert_eq!(to_display_name_lossy(" \t\u{7}", 32), "");
+    }
+}
@@ -1,5 +1,7 @@
 //! API error structs.
 
+use chrono::{DateTime, Utc};
+
 use derive_more::{Display, Error};
 
 use serde::{Deserialize, Serialize};
@@ -8,5 +10,66 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, Display, Deserialize, Error, Serialize)]
 #[display("{message}")]
 pub struct ApiError {
+    /// A machine-readable code for the error.
+    ///
+    /// Unlike messages, codes never change. See [`ErrorCode`].
+    #[serde(default)]
+    pub code: String,
     pub message: String,
+    /// The time left before wagers on the match close, in ms, as the server
+    /// sees it.
+    ///
+    /// Only sent when a wager is turned away because bets have closed, so
+    /// clients can resync their countdowns. Zero or negative once bets are
+    /// closed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closes_in: Option<i64>,
+    /// When the user's post-bailout cooldown ends.
+    ///
+    /// Only sent when a wager is turned away because of the cooldown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_ends_at: Option<DateTime<Utc>>,
+    /// The most the user can wager until their cooldown ends.
+    ///
+    /// Only sent alongside `cooldown_ends_at`, or when a wager is turned away
+    /// because of the user's wager limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_mobiums: Option<i64>,
+    /// When the user's account stops being limited for being new.
+    ///
+    /// Only sent when a wager is turned away because the account is new.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_ends_at: Option<DateTime<Utc>>,
+    /// When the penalty keeping the user from doing this expires.
+    ///
+    /// Only sent when a user is turned away because of a penalty that
+    /// expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub penalty_ends_at: Option<DateTime<Utc>>,
+}
+
+impl ApiError {
+    /// Creates a new `ApiError`.
+    pub fn new(message: impl Into<String>) -> ApiError {
+        ApiError {
+            code: String::new(),
+            message: message.into(),
+            closes_in: None,
+            cooldown_ends_at: None,
+            max_mobiums: None,
+            limit_ends_at: None,
+            penalty_ends_at: None,
+        }
+    }
+}
+
+/// An error code the API can respond with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ErrorCode {
+    /// The code, as sent in [`ApiError::code`].
+    pub code: String,
+    /// The HTTP status the error is sent with.
+    pub status: u16,
+    /// What the error means.
+    pub description: String,
 }
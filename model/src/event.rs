@@ -0,0 +1,27 @@
+//! Promotional events.
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+/// A time-boxed promotion, like a special stream with boosted payouts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct PromoEvent {
+    /// The id of the event.
+    pub id: i32,
+    /// The name of the event, shown to users.
+    pub name: String,
+    /// How much winnings are multiplied by.
+    ///
+    /// Applies to matches whose bets close while the event is running. A
+    /// multiplier of `1.5` pays out 50% more winnings; stakes aren't
+    /// multiplied.
+    pub payout_multiplier: f64,
+    /// The mobiums given to each user on their first wager during the event.
+    pub first_wager_bonus: i64,
+    /// When the event starts.
+    pub starts_at: DateTime<Utc>,
+    /// When the event ends.
+    pub ends_at: DateTime<Utc>,
+}
@@ -0,0 +1,60 @@
+//! Typed identifiers.
+//!
+//! Raw database ids and short ids are easy to mix up, so they are wrapped
+//! in newtypes. All of them serialize as the value they wrap.
+
+use derive_more::{Deref, Display, From};
+
+use serde::{Deserialize, Serialize};
+
+/// The database id of a user.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, Display, From, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct UserId(pub i32);
+
+impl From<UserId> for i32 {
+    fn from(value: UserId) -> Self {
+        value.0
+    }
+}
+
+/// The database id of a match.
+///
+/// Not to be confused with the match's public UUID.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, Display, From, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+pub struct BattleId(pub i32);
+
+impl From<BattleId> for i32 {
+    fn from(value: BattleId) -> Self {
+        value.0
+    }
+}
+
+/// The 6-digit short id of a player.
+#[derive(
+    Clone, Debug, Deref, Deserialize, Serialize, Display, From, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct PlayerShortId(String);
+
+impl PlayerShortId {
+    /// The short id as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for PlayerShortId {
+    fn from(value: &str) -> Self {
+        PlayerShortId(value.to_owned())
+    }
+}
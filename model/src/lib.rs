@@ -1,16 +1,31 @@
 //! API model representations.
 
+pub mod admin;
+pub mod announcement;
 pub mod battle;
 pub mod chat;
+pub mod display_name;
 pub mod error;
+pub mod event;
+pub mod id;
+pub mod matchmaking;
 pub mod message;
+pub mod mmr;
+pub mod mobiums;
+pub mod page;
 pub mod player;
+pub mod push;
 pub mod request;
 pub mod response;
 pub mod server;
+pub mod squad;
+pub mod stats;
 pub mod user;
+pub mod webhook;
 
 pub use battle::{Battle, BattleWager};
 pub use error::ApiError;
+pub use id::{BattleId, PlayerShortId, UserId};
+pub use mobiums::Mobiums;
 pub use player::{Player, Rrid};
 pub use user::User;
@@ -0,0 +1,19 @@
+//! Matchmaking representations.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{battle::BattlePrediction, player::Player};
+
+/// A suggested split of players into teams.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TeamSuggestion {
+    /// The players suggested for the red team.
+    pub red: Vec<Player>,
+    /// The players suggested for the blue team.
+    pub blue: Vec<Player>,
+    /// How the match is expected to go with these teams.
+    ///
+    /// Missing if ratings are disabled, or any player is unrated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prediction: Option<BattlePrediction>,
+}
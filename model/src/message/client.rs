@@ -2,9 +2,107 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::{battle::PlayerTeam, mobiums::Mobiums, request::battle::WagerAmount};
+
 /// A heartbeat.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Heartbeat {
     /// The sequence number of the heartbeat.
     pub seq: i32,
 }
+
+/// An echo of a [`Ping`](crate::message::server::Ping).
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Pong {
+    /// The timestamp of the ping, exactly as it was sent.
+    pub ts: i64,
+}
+
+/// Replaces the set of topics the client receives events for.
+///
+/// Clients are subscribed to every topic when they connect.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Subscribe {
+    /// The topics to subscribe to.
+    pub topics: Vec<Topic>,
+}
+
+/// A topic of room events.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum Topic {
+    /// Chat messages.
+    Chat,
+    /// New and updated matches.
+    Battles,
+    /// Wagers made on matches.
+    Wagers,
+    /// Periodic summaries of wagers on the current match.
+    Heatmap,
+}
+
+impl Topic {
+    /// All topics.
+    pub const ALL: [Topic; 4] = [Topic::Chat, Topic::Battles, Topic::Wagers, Topic::Heatmap];
+}
+
+/// Authenticates an anonymous connection.
+///
+/// This is for clients that cannot send the session cookie with the upgrade
+/// request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Authenticate {
+    /// A ticket from `POST /socket/tickets`.
+    pub ticket: String,
+}
+
+/// Places a wager on a match.
+///
+/// Works the same as `PUT /matches/{match_id}/wagers/~me`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct PlaceWager {
+    /// The match to wager on.
+    pub match_id: String,
+    /// The victor the user is betting on.
+    pub victor: PlayerTeam,
+    /// The mobiums the user bets. If this is 0, this removes the wager.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mobiums: Option<Mobiums>,
+    /// The mobiums to add to the user's current wager, instead of replacing
+    /// it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adjust: Option<Mobiums>,
+    /// A CAPTCHA response, if the server asks for one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captcha: Option<String>,
+}
+
+impl PlaceWager {
+    /// How many mobiums the wager is for.
+    ///
+    /// Returns `None` unless exactly one of `mobiums` and `adjust` is set.
+    pub fn amount(&self) -> Option<WagerAmount> {
+        WagerAmount::from_fields(self.mobiums, self.adjust)
+    }
+}
+
+/// Sends a chat message to the room.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SendChat {
+    /// The content of the message.
+    pub content: String,
+}
+
+/// Asks the server to resend the state of the room.
+///
+/// Useful after the client has lagged behind or missed events.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct RequestResync {}
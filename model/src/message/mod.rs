@@ -7,31 +7,155 @@ use derive_more::From;
 
 use serde::{Deserialize, Serialize};
 
-use crate::message::{
-    client::Heartbeat,
-    server::{BattleUpdate, HeartbeatAck, MobiumsChange, NewBattle, NewMessage, WagerUpdate},
+use crate::{
+    announcement::Announcement,
+    event::PromoEvent,
+    message::{
+        client::{Authenticate, Heartbeat, PlaceWager, Pong, RequestResync, SendChat, Subscribe},
+        server::{
+            Authenticated, AutoBetPlaced, BattleSettled, BattleUpdate, Commentary, HeartbeatAck,
+            Hello, Highlight, LineSettled, LineWagerUpdate, LoadoutChanged, MessageDeleted,
+            MessageEdited, MessageReaction, Milestone, MobiumsChange, NewBattle, NewMessage,
+            OpError, Ping, Reconnect, SettlementProgress, SquadUpdate, WagerHeatmap, WagerUpdate,
+        },
+    },
 };
 
+/// The version of the socket protocol.
+///
+/// Bumped whenever messages change in a way that breaks existing clients.
+pub const PROTOCOL_VERSION: u32 = 2;
+
 /// A WebSocket message.
 ///
 /// This has both client and server messages.
 #[derive(Clone, Debug, Deserialize, Serialize, From)]
 #[serde(tag = "op", content = "d", rename_all = "kebab-case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum Message {
     /// Periodic keepalive meessage from client.
     Heartbeat(Heartbeat),
+    /// Client request to change what events it receives.
+    Subscribe(Subscribe),
+    /// Client request to authenticate with a ticket.
+    Authenticate(Authenticate),
+    /// Client request to place a wager.
+    PlaceWager(PlaceWager),
+    /// Client request to send a chat message.
+    SendChat(SendChat),
+    /// Client request to resend the room state.
+    RequestResync(RequestResync),
+    /// Response for a [`Message::Ping`].
+    Pong(Pong),
+    /// The first message sent to every client.
+    Hello(Hello),
     /// Response for a [`Message::Heartbeat`].
     HeartbeatAck(HeartbeatAck),
+    /// A periodic server ping, to measure latency.
+    Ping(Ping),
     /// A new message was sent in the server.
     NewMessage(NewMessage),
+    /// A message was edited by the server that sent it.
+    MessageEdited(MessageEdited),
+    /// A message was deleted by the server that sent it.
+    MessageDeleted(MessageDeleted),
+    /// The reactions to a message changed.
+    MessageReaction(MessageReaction),
     /// A server notification for a new match.
     NewBattle(NewBattle),
     /// A server notification for a concluded match.
     BattleUpdate(BattleUpdate),
+    /// A periodic server summary of the wagers on the match.
+    WagerHeatmap(WagerHeatmap),
+    /// A server notification of progress paying out the wagers on a match.
+    SettlementProgress(SettlementProgress),
+    /// A server notification that the wagers on a match were paid out.
+    BattleSettled(BattleSettled),
+    /// A server notification that the wagers on the finish time line of a
+    /// match were paid out.
+    LineSettled(LineSettled),
+    /// A server notification that a concluded match was a highlight.
+    Highlight(Highlight),
+    /// A server notification that a participant changed their loadout.
+    LoadoutChanged(LoadoutChanged),
+    /// A server notification that commentary was added to a match.
+    Commentary(Commentary),
+    /// A server notification that the current match crossed a milestone.
+    Milestone(Milestone),
     /// A server notification that a user has made a wager on the match.
     WagerUpdate(WagerUpdate),
+    /// A server notification that a user has made a wager on the finish time
+    /// line of the match.
+    LineWagerUpdate(LineWagerUpdate),
+    /// A server notification summarizing each squad's wagers on a match.
+    SquadUpdate(SquadUpdate),
+    /// A server notification that your auto-bet rule wagered on a match.
+    AutoBetPlaced(AutoBetPlaced),
     /// A server notification for mobiums change on your acc.
     ///
     /// This is most of the time because a wager resolved
     MobiumsChange(MobiumsChange),
+    /// A server notification of a new announcement.
+    Announcement(Announcement),
+    /// A server notification that a promotional event started, or that its
+    /// schedule changed.
+    PromoActive(PromoEvent),
+    /// Response for a successful [`Message::Authenticate`].
+    Authenticated(Authenticated),
+    /// A server notification that the client should reconnect elsewhere.
+    Reconnect(Reconnect),
+    /// A client message could not be processed.
+    Error(OpError),
+}
+
+impl Message {
+    /// The op of the message, as it is serialized.
+    pub fn op(&self) -> &'static str {
+        match self {
+            Message::Heartbeat(_) => "heartbeat",
+            Message::Subscribe(_) => "subscribe",
+            Message::Authenticate(_) => "authenticate",
+            Message::PlaceWager(_) => "place-wager",
+            Message::SendChat(_) => "send-chat",
+            Message::RequestResync(_) => "request-resync",
+            Message::Pong(_) => "pong",
+            Message::Hello(_) => "hello",
+            Message::HeartbeatAck(_) => "heartbeat-ack",
+            Message::Ping(_) => "ping",
+            Message::NewMessage(_) => "new-message",
+            Message::MessageEdited(_) => "message-edited",
+            Message::MessageDeleted(_) => "message-deleted",
+            Message::MessageReaction(_) => "message-reaction",
+            Message::NewBattle(_) => "new-battle",
+            Message::BattleUpdate(_) => "battle-update",
+            Message::WagerHeatmap(_) => "wager-heatmap",
+            Message::SettlementProgress(_) => "settlement-progress",
+            Message::BattleSettled(_) => "battle-settled",
+            Message::LineSettled(_) => "line-settled",
+            Message::Highlight(_) => "highlight",
+            Message::LoadoutChanged(_) => "loadout-changed",
+            Message::Commentary(_) => "commentary",
+            Message::Milestone(_) => "milestone",
+            Message::WagerUpdate(_) => "wager-update",
+            Message::LineWagerUpdate(_) => "line-wager-update",
+            Message::SquadUpdate(_) => "squad-update",
+            Message::AutoBetPlaced(_) => "auto-bet-placed",
+            Message::MobiumsChange(_) => "mobiums-change",
+            Message::Announcement(_) => "announcement",
+            Message::PromoActive(_) => "promo-active",
+            Message::Authenticated(_) => "authenticated",
+            Message::Reconnect(_) => "reconnect",
+            Message::Error(_) => "error",
+        }
+    }
+}
+
+/// Generates a JSON Schema of every client and server message.
+///
+/// The schema's `version` is the [`PROTOCOL_VERSION`].
+#[cfg(feature = "schemars")]
+pub fn schema() -> schemars::Schema {
+    let mut schema = schemars::schema_for!(Message);
+    schema.insert("version".into(), PROTOCOL_VERSION.into());
+    schema
 }
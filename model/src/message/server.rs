@@ -1,38 +1,346 @@
 //! Messages sent by servers.
 
+use derive_more::Deref;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{BattleWager, battle::Battle, chat::Message};
+use crate::{
+    BattleWager, User,
+    battle::{
+        Battle, CommentaryNote, HighlightTag, LineSide, LineWager, LoadoutChange, PlayerTeam,
+    },
+    chat::{Message, ReactionCount},
+    squad::SquadPot,
+    user::AutoBetPick,
+};
+
+/// The first message sent on every connection.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Hello {
+    /// The id of the connection's session.
+    ///
+    /// Reconnecting with `?session={session_id}` shortly after disconnecting
+    /// resumes the session, keeping the user the connection was
+    /// authenticated as.
+    pub session_id: String,
+    /// Whether a previous session was resumed.
+    pub resumed: bool,
+}
+
+/// A notification that the server is going away.
+///
+/// Clients should reconnect, to `url` if it is set. The connection is closed
+/// once `closes_in` passes, if the client hasn't left by then.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Reconnect {
+    /// Where to reconnect to.
+    ///
+    /// Missing if clients should reconnect to the same address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// How long before the connection is closed, in ms.
+    pub closes_in: i64,
+}
 
 /// Heartbeat acknowledgement.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct HeartbeatAck {
     /// The sequence number this is acknowledging.
     pub seq: i32,
 }
 
+/// A latency ping.
+///
+/// Clients should echo this back in a [`Pong`](crate::message::client::Pong)
+/// as soon as they get it, so the server can measure the round trip.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Ping {
+    /// When the ping was sent, in ms since the Unix epoch.
+    pub ts: i64,
+}
+
 /// A chat message notification.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct NewMessage(pub Message);
 
+/// A notification that a chat message was edited.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct MessageEdited(pub Message);
+
+/// A notification that a chat message was deleted.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct MessageDeleted {
+    /// The id of the deleted message.
+    pub id: i32,
+}
+
+/// A notification that the reactions to a chat message changed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct MessageReaction {
+    /// The id of the message.
+    pub id: i32,
+    /// How many users reacted to the message, with each reaction.
+    pub reactions: Vec<ReactionCount>,
+}
+
 /// A notification for a new match.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct NewBattle(pub Battle);
 
 /// A notification that a match has closed.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct BattleUpdate(pub Battle);
 
-/// A notification that someone has made a wager on the room's battle.
+/// A notification that someone has made a wager on one of the room's
+/// matches.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct WagerUpdate {
+    /// The id of the match.
+    pub match_id: String,
+    /// The wager.
+    #[deref]
+    #[serde(flatten)]
+    pub wager: BattleWager,
+}
+
+/// A notification that someone has made a wager on the finish time line of
+/// one of the room's matches.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LineWagerUpdate {
+    /// The id of the match.
+    pub match_id: String,
+    /// The wager.
+    #[deref]
+    #[serde(flatten)]
+    pub wager: LineWager,
+}
+
+/// A notification that your auto-bet rule wagered on a match.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct AutoBetPlaced {
+    /// The id of the match.
+    pub match_id: String,
+    /// Which team the rule picked.
+    pub pick: AutoBetPick,
+    /// The wager.
+    #[deref]
+    #[serde(flatten)]
+    pub wager: BattleWager,
+}
+
+/// A periodic summary of the wagers on a match, sent while betting is open.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct WagerHeatmap {
+    /// The id of the match.
+    pub match_id: String,
+    /// Wagers on the red team.
+    pub red: TeamHeat,
+    /// Wagers on the blue team.
+    pub blue: TeamHeat,
+}
+
+/// A periodic summary of each squad's wagers on a match, sent while betting
+/// is open.
+///
+/// Only sent when a squad's wagers changed since the last summary.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SquadUpdate {
+    /// The id of the match.
+    pub match_id: String,
+    /// Every squad with wagers on the match, biggest pot first.
+    pub squads: Vec<SquadPot>,
+}
+
+/// A summary of the wagers on a single team.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct TeamHeat {
+    /// The sum of all wagers on the team.
+    pub pot: i64,
+    /// How many wagers are on the team.
+    pub wagers: i32,
+    /// How much the pot changed since the last summary.
+    pub pot_delta: i64,
+    /// How much the wager count changed since the last summary.
+    pub wagers_delta: i32,
+}
+
+/// A notification that the wagers on a match were paid out.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct WagerUpdate(pub BattleWager);
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct BattleSettled {
+    /// The id of the match.
+    pub match_id: String,
+    /// The team that won.
+    pub victor: PlayerTeam,
+    /// The sum of all wagers on the match.
+    pub total_pot: i64,
+    /// How many wagers were on the winning team.
+    pub winners: i32,
+    /// The biggest single payout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biggest_payout: Option<Payout>,
+}
+
+/// A notification that the wagers on the finish time line of a match were
+/// paid out.
+///
+/// Sent alongside the match's [`BattleSettled`], if any.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LineSettled {
+    /// The id of the match.
+    pub match_id: String,
+    /// The line of the match.
+    pub line: i32,
+    /// The winner's finish time.
+    pub finish_time: i32,
+    /// The side of the line the winner finished on.
+    pub side: LineSide,
+    /// The sum of all wagers on the line.
+    pub total_pot: i64,
+    /// How many wagers were on the winning side.
+    pub winners: i32,
+}
+
+/// Progress on paying out the wagers on a match.
+///
+/// Only sent for matches with enough wagers that paying them out takes a
+/// while. A [`BattleSettled`] follows once every wager is paid out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SettlementProgress {
+    /// The id of the match.
+    pub match_id: String,
+    /// How many wagers have been paid out so far.
+    pub settled: i32,
+    /// How many wagers there are to pay out.
+    pub total: i32,
+}
+
+/// A payout to a single user.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Payout {
+    /// The user that was paid out.
+    ///
+    /// Missing if the server hides who won.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    /// How many mobiums they won, not including their wager.
+    pub mobiums: i64,
+}
+
+/// A notification that a concluded match was flagged as a highlight.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Highlight {
+    /// The id of the match.
+    pub match_id: String,
+    /// What made the match a highlight.
+    pub tags: Vec<HighlightTag>,
+}
+
+/// A notification that a participant changed their loadout mid-match.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LoadoutChanged {
+    /// The id of the match.
+    pub match_id: String,
+    /// The change.
+    #[serde(flatten)]
+    pub change: LoadoutChange,
+}
+
+/// A notification that commentary was added to a match.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Commentary {
+    /// The id of the match.
+    pub match_id: String,
+    /// The note.
+    #[serde(flatten)]
+    pub note: CommentaryNote,
+}
+
+/// A notification that the current match crossed a milestone.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Milestone {
+    /// The id of the match.
+    pub match_id: String,
+    /// What crossed the milestone.
+    pub kind: MilestoneKind,
+    /// The milestone that was crossed.
+    pub value: i64,
+}
+
+/// What crossed a [`Milestone`].
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum MilestoneKind {
+    /// How many clients are connected to the room.
+    Viewers,
+    /// The sum of the pots of the match.
+    PotSize,
+}
 
 /// A notification of a mobiums change.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct MobiumsChange {
     /// How many mobiums you have now.
     pub mobiums: i64,
     /// Whether or not the final result of this change was affected by a
     /// bailout.
     pub bailout: bool,
+    /// How the change affected your streak, if it settled a wager.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streak: Option<StreakChange>,
+}
+
+/// A change to a user's streak of correct predictions.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct StreakChange {
+    /// How many correct predictions you have made in a row now.
+    pub streak: i32,
+    /// The multiplier applied to your winnings.
+    ///
+    /// This is `1` if the streak didn't earn you anything.
+    pub multiplier: f64,
+    /// How many extra mobiums the streak earned you.
+    pub bonus: i64,
+}
+
+/// A notification that the connection was authenticated.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Authenticated(pub User);
+
+/// An error processing a client message.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct OpError {
+    /// The op of the message that failed, if it could be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub op: Option<String>,
+    /// A human-readable message.
+    pub message: String,
 }
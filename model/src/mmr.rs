@@ -0,0 +1,28 @@
+//! Rating period representations.
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+/// The rating period schedule returned by `/mmr/periods`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RatingPeriods {
+    /// The current rating period.
+    ///
+    /// This is `None` if no ratings have happened yet.
+    pub current: Option<RatingPeriod>,
+    /// The amount of time until the current period ends, in ms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends_in: Option<i64>,
+    /// Past rating periods, newest first.
+    pub history: Vec<RatingPeriod>,
+}
+
+/// A single rating period.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RatingPeriod {
+    /// When the period started.
+    pub started_at: DateTime<Utc>,
+    /// When the period ends, or ended.
+    pub ended_at: DateTime<Utc>,
+}
@@ -0,0 +1,115 @@
+//! Mobiums, the currency of the realm.
+
+use std::fmt::{self, Display, Formatter};
+
+use derive_more::From;
+
+use serde::{Deserialize, Serialize};
+
+/// An amount of mobiums.
+///
+/// Serializes as a plain integer. Arithmetic is checked, so overflows are
+/// caught instead of wrapping around. The [`Display`] implementation groups
+/// digits with commas, like `1,234,567`.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Serialize, From, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type), sqlx(transparent))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Mobiums(pub i64);
+
+impl Mobiums {
+    /// No mobiums.
+    pub const ZERO: Mobiums = Mobiums(0);
+
+    /// The amount as a raw integer.
+    pub fn get(self) -> i64 {
+        self.0
+    }
+
+    /// Adds two amounts, returning `None` on overflow.
+    pub fn checked_add(self, rhs: Mobiums) -> Option<Mobiums> {
+        self.0.checked_add(rhs.0).map(Mobiums)
+    }
+
+    /// Subtracts two amounts, returning `None` on overflow.
+    pub fn checked_sub(self, rhs: Mobiums) -> Option<Mobiums> {
+        self.0.checked_sub(rhs.0).map(Mobiums)
+    }
+
+    /// Whether this is a negative amount.
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Validates that a requested amount is not negative.
+    #[cfg(feature = "garde")]
+    pub fn validate_non_negative(value: &Mobiums, _ctx: &()) -> garde::Result {
+        if value.is_negative() {
+            Err(garde::Error::new("must not be negative"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl From<Mobiums> for i64 {
+    fn from(value: Mobiums) -> Self {
+        value.0
+    }
+}
+
+impl PartialEq<i64> for Mobiums {
+    fn eq(&self, other: &i64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialOrd<i64> for Mobiums {
+    fn partial_cmp(&self, other: &i64) -> Option<std::cmp::Ordering> {
+        self.0.partial_cmp(other)
+    }
+}
+
+impl Display for Mobiums {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let digits = self.0.unsigned_abs().to_string();
+
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", digit)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_display() {
+        assert_eq!(Mobiums(0).to_string(), "0");
+        assert_eq!(Mobiums(400).to_string(), "400");
+        assert_eq!(Mobiums(1_000).to_string(), "1,000");
+        assert_eq!(Mobiums(1_234_567).to_string(), "1,234,567");
+        assert_eq!(Mobiums(-12_345).to_string(), "-12,345");
+        assert_eq!(Mobiums(i64::MIN).to_string(), "-9,223,372,036,854,775,808");
+    }
+
+    #[test]
+    pub fn test_checked() {
+        assert_eq!(Mobiums(400).checked_add(Mobiums(200)), Some(Mobiums(600)));
+        assert_eq!(Mobiums(400).checked_sub(Mobiums(600)), Some(Mobiums(-200)));
+        assert_eq!(Mobiums(i64::MAX).checked_add(Mobiums(1)), None);
+        assert_eq!(Mobiums(i64::MIN).checked_sub(Mobiums(1)), None);
+    }
+}
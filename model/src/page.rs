@@ -0,0 +1,27 @@
+//! Paginated lists.
+
+use serde::{Deserialize, Serialize};
+
+/// How a list endpoint should format its response.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ListFormat {
+    /// A bare array of items.
+    #[default]
+    Array,
+    /// A [`Page`] of items.
+    Page,
+}
+
+/// A page of a longer list.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct Page<T> {
+    /// The items on this page.
+    pub data: Vec<T>,
+    /// An opaque cursor to pass back to get the next page.
+    ///
+    /// This is `None` on the last page.
+    pub next_cursor: Option<String>,
+    /// Whether there are more items after this page.
+    pub has_more: bool,
+}
@@ -2,6 +2,8 @@
 
 use std::str::FromStr;
 
+use chrono::{DateTime, Utc};
+
 use derive_more::{Deref, Display, Error};
 
 use serde::{
@@ -9,16 +11,31 @@ use serde::{
     de::{Error as _, Unexpected},
 };
 
+use crate::{
+    battle::{BattleStatus, PlayerTeam},
+    id::PlayerShortId,
+};
+
 /// A player on the Ring Racers server.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Player {
     /// The 6-digit short id for the player.
-    pub id: String,
+    pub id: PlayerShortId,
     /// The last display name used by the player.
     pub display_name: String,
     /// The player's MMR.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mmr: Option<i32>,
+    /// Whether the player's MMR is still too uncertain to mean much.
+    ///
+    /// New players start out provisional, with an MMR far below what they
+    /// will settle at. Always `false` if the server doesn't rate players.
+    #[serde(default)]
+    pub provisional: bool,
+    /// How many concluded matches the player was in.
+    #[serde(default)]
+    pub games_played: i32,
     /// The public rrid of the player.
     ///
     /// The base16 encoded public key of the player, which is a 64-character
@@ -28,6 +45,118 @@ pub struct Player {
     pub public_key: Option<Rrid>,
 }
 
+/// A display name a player used before.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlayerAlias {
+    /// The display name.
+    pub display_name: String,
+    /// When the player stopped using the name.
+    pub replaced_at: DateTime<Utc>,
+}
+
+/// A player found by name.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+pub struct FoundPlayer {
+    /// The player.
+    #[deref]
+    #[serde(flatten)]
+    pub player: Player,
+    /// The former display name that matched, if the current one didn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub former_name: Option<String>,
+}
+
+/// A player claimed by a user.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize)]
+pub struct LinkedPlayer {
+    /// The player.
+    #[deref]
+    #[serde(flatten)]
+    pub player: Player,
+    /// When the player was linked to the user.
+    pub linked_at: DateTime<Utc>,
+    /// The player's most recent matches, newest first.
+    pub recent_matches: Vec<PlayerMatch>,
+}
+
+/// A match a player took part in, from the player's point of view.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlayerMatch {
+    /// The unique identifier of the match.
+    pub id: String,
+    /// The level name the match played on.
+    pub level_name: String,
+    /// The status of the match.
+    pub status: BattleStatus,
+    /// The team the player was on.
+    pub team: PlayerTeam,
+    /// The player's finish time, if they finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_time: Option<i32>,
+    /// If the player no contest'd.
+    #[serde(default)]
+    pub no_contest: bool,
+    /// How much the player's MMR moved because of the match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mmr_delta: Option<i32>,
+    /// When the match started.
+    pub started_at: DateTime<Utc>,
+}
+
+/// Lifetime statistics of a player.
+///
+/// Only concluded matches count.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct PlayerStats {
+    /// How many matches the player took part in.
+    pub matches: i64,
+    /// How many of those matches the player's team won.
+    pub wins: i64,
+    /// The player's record on the red team.
+    pub red: TeamRecord,
+    /// The player's record on the blue team.
+    pub blue: TeamRecord,
+    /// The player's average finish time, if they ever finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub average_finish_time: Option<f64>,
+    /// How many matches the player no contest'd.
+    pub no_contests: i64,
+    /// The share of matches the player no contest'd, from 0 to 1.
+    pub no_contest_rate: f64,
+    /// The most matches the player's team won in a row.
+    pub longest_win_streak: i64,
+    /// The player's record on each level, most played first.
+    pub levels: Vec<LevelStats>,
+}
+
+/// A player's record on one team.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct TeamRecord {
+    /// How many matches the player was on the team for.
+    pub matches: i64,
+    /// How many of those matches the team won.
+    pub wins: i64,
+    /// The share of those matches the team won, from 0 to 1.
+    pub win_rate: f64,
+}
+
+/// A player's record on a single level.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct LevelStats {
+    /// The name of the level.
+    pub level_name: String,
+    /// How many matches the player took part in on the level.
+    pub matches: i64,
+    /// How many of those matches the player's team won.
+    pub wins: i64,
+    /// The player's average finish time on the level, if they ever finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub average_finish_time: Option<f64>,
+    /// The player's fastest finish time on the level, if they ever finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub best_finish_time: Option<i32>,
+}
+
 /// A character a player has selected.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Skin {
@@ -48,6 +177,7 @@ pub struct Skin {
 
 /// Ring Racers ID.
 #[derive(Clone, Debug, Deref, Display)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Rrid(String);
 
 impl Rrid {
@@ -0,0 +1,47 @@
+//! Web Push representations.
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+/// The server's VAPID public key.
+///
+/// Browsers need this as the `applicationServerKey` to subscribe.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VapidKey {
+    /// The key, as unpadded base64url.
+    pub public_key: String,
+}
+
+/// A browser subscribed to push notifications.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PushSubscription {
+    /// The push service endpoint of the subscription.
+    pub endpoint: String,
+    /// When the browser subscribed.
+    pub created_at: DateTime<Utc>,
+}
+
+/// The payload of a push notification.
+///
+/// This is the JSON the service worker gets in its `push` event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PushNotification {
+    /// A match the user wagered on concluded.
+    MatchConcluded {
+        /// The id of the match.
+        match_id: String,
+        /// How many mobiums the wager won, or lost if negative.
+        payout: i64,
+        /// How many mobiums the user has now.
+        mobiums: i64,
+    },
+    /// The user lost everything on a match and was bailed out.
+    Bailout {
+        /// The id of the match.
+        match_id: String,
+        /// How many mobiums the user was given.
+        mobiums: i64,
+    },
+}
@@ -0,0 +1,24 @@
+//! Announcement requests.
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::announcement::Severity;
+
+/// Makes a new announcement.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreateAnnouncementRequest {
+    /// The announcement itself.
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 1000)))]
+    pub message: String,
+    /// How urgent the announcement is.
+    #[serde(default)]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub severity: Severity,
+    /// When the announcement should stop being shown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub expires_at: Option<DateTime<Utc>>,
+}
@@ -1,35 +1,80 @@
 //! Match endpoint request bodies.
 
+use chrono::{DateTime, Utc};
+
 use serde::{Deserialize, Serialize};
 
-use crate::battle::{BattleStatus, PlayerTeam};
+use crate::{
+    battle::{BattleStatus, LineSide, OddsMode, ParticipantStats, PlayerTeam, Visibility},
+    id::PlayerShortId,
+    mobiums::Mobiums,
+};
 
 /// Request to create a match.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct CreateBattleRequest {
     /// The level the battle is taking place on.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 64)))]
     pub level_name: String,
     /// The players to register for this battle.
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 32), dive))]
     pub participants: Vec<CreateBattleParticipant>,
     /// How long bets should last for, in seconds.
     ///
     /// Uses `20` seconds as the default.
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(range(min = 1, max = 600)))]
     pub bet_time: Option<i64>,
+    /// Cancels the server's ongoing match, if there is one.
+    ///
+    /// Otherwise, the match will not be created if the server already has an
+    /// ongoing match.
+    #[serde(default)]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub force: bool,
+    /// How wagers on the match are paid out.
+    ///
+    /// Uses the server's configured mode as the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub odds_mode: Option<OddsMode>,
+    /// Who can find the match.
+    ///
+    /// Matches are public by default.
+    #[serde(default)]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub visibility: Visibility,
+    /// Keeps the wager bot from wagering on the match.
+    #[serde(default)]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub disable_bot: bool,
+    /// The line of an over/under market on the winner's finish time.
+    ///
+    /// The match has no such market if this isn't given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(range(min = 1)))]
+    pub finish_time_line: Option<i32>,
 }
 
 /// A participant in a [`CreateBattleRequest`].
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct CreateBattleParticipant {
     /// The ID of the participant.
-    pub id: String,
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub id: PlayerShortId,
     /// What team they are on.
+    #[cfg_attr(feature = "garde", garde(skip))]
     pub team: PlayerTeam,
     /// The player's kartspeed.
+    #[cfg_attr(feature = "garde", garde(range(min = 1, max = 9)))]
     pub kart_speed: i32,
     /// The player's kartweight.
+    #[cfg_attr(feature = "garde", garde(range(min = 1, max = 9)))]
     pub kart_weight: i32,
     /// The skin the player is running.
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 32)))]
     pub skin: String,
 }
 
@@ -37,16 +82,47 @@ pub struct CreateBattleParticipant {
 ///
 /// This may be updated continuously until the match is ended.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct UpdatePlayerPlacementRequest {
     /// The finishing time of the player.
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(range(min = 0)))]
     pub finish_time: Option<i32>,
+    /// The player's new loadout.
+    ///
+    /// Loadouts can only be changed while the match is ongoing. Each change
+    /// is kept in the match's loadout history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(dive))]
+    pub loadout: Option<UpdateLoadout>,
+    /// The player's race telemetry.
+    ///
+    /// Fields that are left out keep the value they were last given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(dive))]
+    pub stats: Option<ParticipantStats>,
+}
+
+/// A loadout in an [`UpdatePlayerPlacementRequest`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateLoadout {
+    /// The player's kartspeed.
+    #[cfg_attr(feature = "garde", garde(range(min = 1, max = 9)))]
+    pub kart_speed: i32,
+    /// The player's kartweight.
+    #[cfg_attr(feature = "garde", garde(range(min = 1, max = 9)))]
+    pub kart_weight: i32,
+    /// The skin the player is running.
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 32)))]
+    pub skin: String,
 }
 
 /// Request to update a match.
 ///
 /// Concluded matches cannot be updated.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct UpdateBattleRequest {
     /// Match status.
     ///
@@ -63,24 +139,160 @@ pub struct UpdateBattleRequest {
     ///
     /// **This action is irreversible.** Be careful!
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
     pub status: Option<BattleStatus>,
+    /// Participants to swap out.
+    ///
+    /// Each swap removes a participant and adds another in their place.
+    /// Swaps can only be made while bets are still open. Wagers on the teams
+    /// of any swapped participants are voided and refunded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "garde", garde(length(max = 32), dive))]
+    pub swap: Vec<SwapParticipant>,
+    /// Reopens betting on an ongoing match.
+    ///
+    /// Useful when the match was amended after bets closed. Reopening happens
+    /// before any swaps, so both can be done in one request. Bets that are
+    /// still open can be kept open for longer this way, too.
+    ///
+    /// Bets can't be reopened once any participant has a finish time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub reopen_bets: Option<ReopenBets>,
+}
+
+/// Reopens betting in an [`UpdateBattleRequest`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReopenBets {
+    /// When bets close again.
+    pub closed_at: DateTime<Utc>,
+}
+
+/// A participant swap in an [`UpdateBattleRequest`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct SwapParticipant {
+    /// The ID of the participant to remove.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub replace: PlayerShortId,
+    /// The participant to add in their place.
+    #[cfg_attr(feature = "garde", garde(dive))]
+    pub with: CreateBattleParticipant,
 }
 
 /// Request to update a wager.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct UpdateWager {
     /// The mobiums the user bets.
     ///
     /// This can only be between 0 and the mobiums the user has.
     ///
-    /// If this is 0, this removes the wager.
-    pub mobiums: i64,
+    /// If this is 0, this removes the wager. Exactly one of this and
+    /// `adjust` must be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub mobiums: Option<Mobiums>,
+    /// The mobiums to add to the user's current wager.
+    ///
+    /// Negative amounts take mobiums off the wager. The wager must still end
+    /// up between 0 and the mobiums the user has.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub adjust: Option<Mobiums>,
     /// The victor the user is betting on.
     ///
     /// If this team wins, they will be paid out.
+    #[cfg_attr(feature = "garde", garde(skip))]
     pub victor: PlayerTeam,
     /// The [CSRF token].
     ///
     /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+    /// A CAPTCHA response, if the server asks for one.
+    ///
+    /// New accounts have to pass a CAPTCHA before they wager.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(max = 4096)))]
+    pub captcha: Option<String>,
+    /// A token the client picks for this wager, so it can be retried safely.
+    ///
+    /// If the user's last wager on the match was placed with the same token,
+    /// the wager isn't placed again, and the original receipt is sent back
+    /// instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 64)))]
+    pub client_token: Option<String>,
+}
+
+impl UpdateWager {
+    /// How many mobiums the wager is for.
+    ///
+    /// Returns `None` unless exactly one of `mobiums` and `adjust` is set.
+    pub fn amount(&self) -> Option<WagerAmount> {
+        WagerAmount::from_fields(self.mobiums, self.adjust)
+    }
+}
+
+/// Request to update a wager on a match's finish time line.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateLineWager {
+    /// The mobiums the user bets.
+    ///
+    /// This can only be between 0 and the mobiums the user has. If this is 0,
+    /// this removes the wager.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub mobiums: Mobiums,
+    /// The side of the line the user is betting the winner finishes on.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub side: LineSide,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
     pub csrf: String,
+    /// A CAPTCHA response, if the server asks for one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(max = 4096)))]
+    pub captcha: Option<String>,
+    /// A token the client picks for this wager, so it can be retried safely.
+    ///
+    /// Works like [`UpdateWager::client_token`], for the user's last wager on
+    /// the match's line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 64)))]
+    pub client_token: Option<String>,
+}
+
+/// How many mobiums a wager is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WagerAmount {
+    /// Replaces the wager. If this is 0, the wager is removed.
+    Mobiums(Mobiums),
+    /// Adds to the wager, which must be on the same team.
+    Adjust(Mobiums),
+}
+
+impl WagerAmount {
+    /// Picks the amount out of a request's `mobiums` and `adjust` fields.
+    ///
+    /// Returns `None` unless exactly one of them is set.
+    pub fn from_fields(mobiums: Option<Mobiums>, adjust: Option<Mobiums>) -> Option<WagerAmount> {
+        match (mobiums, adjust) {
+            (Some(mobiums), None) => Some(WagerAmount::Mobiums(mobiums)),
+            (None, Some(adjust)) => Some(WagerAmount::Adjust(adjust)),
+            _ => None,
+        }
+    }
+}
+
+/// Request to add commentary to a match.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreateCommentary {
+    /// What happened.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 280)))]
+    pub content: String,
 }
@@ -0,0 +1,37 @@
+//! Wager bot request bodies.
+
+use serde::{Deserialize, Serialize};
+
+use crate::admin::BotStrategy;
+
+/// Request to update the wager bot.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateWagerBotRequest {
+    /// Turns the wager bot on or off.
+    ///
+    /// This only lasts until the server restarts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub enabled: Option<bool>,
+    /// The bot's new display name.
+    ///
+    /// Cleaned up and cut short to the server's configured length.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 256)))]
+    pub display_name: Option<String>,
+    /// A URL to the bot's new avatar.
+    ///
+    /// An empty string removes the avatar.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(max = 2048)))]
+    pub avatar: Option<String>,
+    /// How many mobiums the bot wagers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(range(min = 1)))]
+    pub wager_amount: Option<i64>,
+    /// How the bot decides how much to wager.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub strategy: Option<BotStrategy>,
+}
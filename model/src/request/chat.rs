@@ -2,11 +2,39 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::{chat::Reaction, id::PlayerShortId};
+
 /// A player sent a chat message.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct CreateChatMessage {
     /// The ID of the player that sent the chat message.
-    pub player_id: String,
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub player_id: PlayerShortId,
     /// The content of their message.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 255)))]
+    pub content: String,
+}
+
+/// A server edited a chat message it crossposted.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateChatMessage {
+    /// The new content of the message.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 255)))]
     pub content: String,
 }
+
+/// A user reacted to a chat message, or took their reaction back.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct ReactToMessage {
+    /// The reaction.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub reaction: Reaction,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
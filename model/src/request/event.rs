@@ -0,0 +1,32 @@
+//! Promotional event requests.
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+/// Schedules a new promotional event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreatePromoEventRequest {
+    /// The name of the event, shown to users.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 100)))]
+    pub name: String,
+    /// How much winnings are multiplied by.
+    #[serde(default = "payout_multiplier_default")]
+    #[cfg_attr(feature = "garde", garde(range(min = 1.0, max = 10.0)))]
+    pub payout_multiplier: f64,
+    /// The mobiums given to each user on their first wager during the event.
+    #[serde(default)]
+    #[cfg_attr(feature = "garde", garde(range(min = 0, max = 1_000_000)))]
+    pub first_wager_bonus: i64,
+    /// When the event starts.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub starts_at: DateTime<Utc>,
+    /// When the event ends.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub ends_at: DateTime<Utc>,
+}
+
+fn payout_multiplier_default() -> f64 {
+    1.0
+}
@@ -0,0 +1,22 @@
+//! Feature flag request bodies.
+
+use serde::{Deserialize, Serialize};
+
+/// Request to set a feature flag.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct SetFeatureFlagRequest {
+    /// Turns the flag on or off.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub enabled: bool,
+    /// The percentage of users the flag is on for, from 0 to 100.
+    ///
+    /// Defaults to everyone.
+    #[serde(default = "default_rollout")]
+    #[cfg_attr(feature = "garde", garde(range(max = 100)))]
+    pub rollout: u8,
+}
+
+fn default_rollout() -> u8 {
+    100
+}
@@ -0,0 +1,14 @@
+//! Matchmaking request bodies.
+
+use serde::{Deserialize, Serialize};
+
+use crate::id::PlayerShortId;
+
+/// Request to split players into teams.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct SuggestTeamsRequest {
+    /// The players to split.
+    #[cfg_attr(feature = "garde", garde(length(min = 2, max = 32)))]
+    pub players: Vec<PlayerShortId>,
+}
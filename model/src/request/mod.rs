@@ -1,6 +1,15 @@
 //! Request bodies.
 
+pub mod announcement;
 pub mod battle;
+pub mod bot;
 pub mod chat;
+pub mod event;
+pub mod flag;
+pub mod matchmaking;
 pub mod player;
 pub mod server;
+pub mod socket;
+pub mod squad;
+pub mod user;
+pub mod webhook;
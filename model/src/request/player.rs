@@ -6,9 +6,36 @@ use crate::Rrid;
 
 /// Request body for registering a player.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct RegisterPlayerRequest {
     /// The public key of the player.
+    #[cfg_attr(feature = "garde", garde(skip))]
     pub public_key: Rrid,
     /// The display name of the player.
+    ///
+    /// Cleaned up and cut short to the server's configured length.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 256)))]
     pub display_name: String,
 }
+
+/// Request body for an admin setting a player's display name.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct SetPlayerDisplayNameRequest {
+    /// The new display name of the player.
+    ///
+    /// Cleaned up and cut short like any other, but never filtered.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 256)))]
+    pub display_name: String,
+    /// Keeps game servers from changing the name back.
+    ///
+    /// Defaults to `true`. Set to `false` to let servers rename the player
+    /// again.
+    #[serde(default = "default_locked")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub locked: bool,
+}
+
+fn default_locked() -> bool {
+    true
+}
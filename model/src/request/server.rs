@@ -8,13 +8,16 @@ use crate::server::MapConfig;
 
 /// An update server request.
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
 pub struct UpdateServerRequest {
     /// The new name of the server.
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 64)))]
     pub name: Option<String>,
     /// The list of map bans.
     ///
     /// These are replaced as-is.
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
     pub bans: Option<HashMap<String, MapConfig>>,
 }
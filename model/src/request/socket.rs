@@ -0,0 +1,15 @@
+//! Socket requests.
+
+use serde::{Deserialize, Serialize};
+
+/// Drains the sockets connected to an instance.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct DrainSocketsRequest {
+    /// Where clients should reconnect to.
+    ///
+    /// Defaults to the configured reconnect URL, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 2048)))]
+    pub url: Option<String>,
+}
@@ -0,0 +1,33 @@
+//! Squad requests.
+
+use serde::{Deserialize, Serialize};
+
+/// Creates a squad, with the current user as its owner.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreateSquad {
+    /// The unique name of the squad.
+    ///
+    /// Names are compared case-insensitively.
+    #[cfg_attr(
+        feature = "garde",
+        garde(length(chars, min = 3, max = 24), pattern(r"^[\w\- ]+$"))
+    )]
+    pub name: String,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// Joins a squad, or leaves the current user's squad.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateSquadMembership {
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
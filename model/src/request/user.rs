@@ -0,0 +1,202 @@
+//! User requests.
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mobiums::Mobiums,
+    user::{AutoBetPick, PenaltyKind},
+};
+
+/// Updates the current user's settings.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateCurrentUser {
+    /// The name shown for the user.
+    ///
+    /// Cleaned up and cut short to the server's configured length.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 256)))]
+    pub display_name: Option<String>,
+    /// Whether other users can see who made your wagers.
+    ///
+    /// Private wagers still count towards pots and show up in wager lists,
+    /// just without a name attached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub show_wagers_publicly: Option<bool>,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// Generates a code to link this account to another.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreateLinkCode {
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// Unlinks a player from the current account.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UnlinkPlayer {
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// Changes a linked player's short id.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateLinkedPlayer {
+    /// The player's new short id.
+    ///
+    /// Six letters or digits. Letters are uppercased.
+    #[cfg_attr(
+        feature = "garde",
+        garde(length(min = 6, max = 6), pattern(r"^[0-9A-Za-z]+$"))
+    )]
+    pub short_id: String,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// Redeems a code generated on another account, merging that account into
+/// this one.
+///
+/// **This action is irreversible.** The other account's mobiums, stats and
+/// wager history are moved to this account.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct RedeemLinkCode {
+    /// The code generated on the other account.
+    #[cfg_attr(feature = "garde", garde(length(max = 64)))]
+    pub code: String,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// Sets the current user's auto-bet rule.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct UpdateAutoBet {
+    /// Which team to wager on.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub pick: AutoBetPick,
+    /// How many mobiums to wager on each match.
+    #[cfg_attr(feature = "garde", garde(range(min = Mobiums(1))))]
+    pub mobiums: Mobiums,
+    /// The most the rule can wager in a day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(inner(range(min = Mobiums(1)))))]
+    pub daily_cap: Option<Mobiums>,
+    /// Whether the rule is wagering.
+    #[serde(default = "auto_bet_enabled_default")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub enabled: bool,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+fn auto_bet_enabled_default() -> bool {
+    true
+}
+
+/// Deletes the current user's auto-bet rule.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct DeleteAutoBet {
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// Gives a user a penalty.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreatePenaltyRequest {
+    /// What the penalty keeps the user from doing.
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub kind: PenaltyKind,
+    /// Why the penalty is given.
+    #[cfg_attr(feature = "garde", garde(length(chars, min = 1, max = 500)))]
+    pub reason: String,
+    /// When the penalty expires.
+    ///
+    /// If this is missing, the penalty lasts longer the more penalties of the
+    /// same kind the user already has.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Makes the penalty last until it is revoked.
+    #[serde(default)]
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub permanent: bool,
+}
+
+/// Subscribes a browser to the current user's push notifications.
+///
+/// The endpoint and keys are what the browser gives in
+/// `PushSubscription.toJSON()`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreatePushSubscription {
+    /// The push service endpoint. Must be HTTPS.
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 2048)))]
+    pub endpoint: String,
+    /// The subscription's keys.
+    #[cfg_attr(feature = "garde", garde(dive))]
+    pub keys: PushSubscriptionKeys,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
+
+/// The keys of a push subscription, as unpadded base64url.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct PushSubscriptionKeys {
+    /// The browser's P-256 public key.
+    #[cfg_attr(feature = "garde", garde(length(max = 255)))]
+    pub p256dh: String,
+    /// The browser's auth secret.
+    #[cfg_attr(feature = "garde", garde(length(max = 255)))]
+    pub auth: String,
+}
+
+/// Unsubscribes a browser from the current user's push notifications.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct DeletePushSubscription {
+    /// The push service endpoint of the subscription.
+    #[cfg_attr(feature = "garde", garde(length(max = 2048)))]
+    pub endpoint: String,
+    /// The [CSRF token].
+    ///
+    /// [CSRF token]: crate::session::Session::shuffle_csrf
+    #[cfg_attr(feature = "garde", garde(skip))]
+    pub csrf: String,
+}
@@ -0,0 +1,18 @@
+//! Webhook requests.
+
+use serde::{Deserialize, Serialize};
+
+/// Registers a new webhook.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "garde", derive(garde::Validate))]
+pub struct CreateWebhookRequest {
+    /// The URL to send events to.
+    #[cfg_attr(feature = "garde", garde(length(min = 1, max = 2048)))]
+    pub url: String,
+    /// The secret to sign deliveries with.
+    ///
+    /// One is generated if this is missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "garde", garde(length(max = 255)))]
+    pub secret: Option<String>,
+}
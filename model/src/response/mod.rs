@@ -1 +1,12 @@
 //! API responses.
+
+use serde::{Deserialize, Serialize};
+
+/// A one-time ticket to authenticate a socket with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SocketTicket {
+    /// The ticket.
+    pub ticket: String,
+    /// How long until the ticket expires, in ms.
+    pub expires_in: i64,
+}
@@ -0,0 +1,64 @@
+//! Squad representations.
+
+use chrono::{DateTime, Utc};
+
+use derive_more::Deref;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{mobiums::Mobiums, user::User};
+
+/// A group of users whose wagers are pooled.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct Squad {
+    /// The unique name of the squad.
+    pub name: String,
+    /// The username of the member who leads the squad.
+    pub owner: String,
+    /// How many users are in the squad.
+    pub members: i32,
+    /// When the squad was created.
+    pub created_at: DateTime<Utc>,
+}
+
+/// A member of a squad.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct SquadMember {
+    /// The user.
+    #[deref]
+    #[serde(flatten)]
+    pub user: User,
+    /// When the user joined the squad.
+    pub joined_at: DateTime<Utc>,
+}
+
+/// A squad's place on the squad leaderboard.
+#[derive(Clone, Debug, Deref, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct SquadStanding {
+    /// The squad.
+    #[deref]
+    #[serde(flatten)]
+    pub squad: Squad,
+    /// How many mobiums the squad's settled wagers won, less what they lost.
+    ///
+    /// Only wagers members placed while in the squad count.
+    pub net_mobiums: Mobiums,
+    /// How many of the squad's wagers were settled.
+    pub wagers: i32,
+    /// How many of the squad's settled wagers won.
+    pub wins: i32,
+}
+
+/// The sum of a squad's wagers on a match.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SquadPot {
+    /// The name of the squad.
+    pub name: String,
+    /// The sum of the squad's wagers on the red team.
+    pub red: i64,
+    /// The sum of the squad's wagers on the blue team.
+    pub blue: i64,
+    /// How many members wagered on the match.
+    pub wagers: i32,
+}
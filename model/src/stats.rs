@@ -0,0 +1,94 @@
+//! Aggregate statistics.
+
+use chrono::NaiveDate;
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use serde::{Deserialize, Serialize};
+
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+/// Statistics for a single UTC day.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct DailyStats {
+    /// The day these stats are for.
+    pub day: NaiveDate,
+    /// How many matches concluded on this day.
+    pub matches: i64,
+    /// How many wagers were placed on those matches.
+    pub wagers: i64,
+    /// The sum of all pots of those matches.
+    pub total_pot: i64,
+    /// The average pot of those matches.
+    pub average_pot: i64,
+    /// How many bailouts were issued on this day.
+    pub bailouts: i64,
+}
+
+/// Where the pre-match odds of a match came from.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize_repr,
+    Serialize_repr,
+    PartialEq,
+    Eq,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
+)]
+#[repr(u8)]
+pub enum OddsSource {
+    /// The win probability from the participants' ratings at the start of the
+    /// match.
+    Ratings = 0,
+    /// The odds the pots implied when wagers closed.
+    Pots = 1,
+}
+
+/// How well pre-match odds predicted the outcomes of matches.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct OddsCalibration {
+    /// The first day included, if any.
+    pub from: Option<NaiveDate>,
+    /// The last day included.
+    pub to: NaiveDate,
+    /// The calibration of the win probabilities from ratings.
+    pub ratings: SourceCalibration,
+    /// The calibration of the odds implied by the pots.
+    pub pots: SourceCalibration,
+}
+
+/// How well a single [`OddsSource`] predicted the outcomes of matches.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SourceCalibration {
+    /// How many matches had a favorite.
+    pub matches: i64,
+    /// The mean squared error of the favorites' win probabilities, from 0 to
+    /// 1. Lower is better.
+    ///
+    /// `None` if there were no matches.
+    pub brier_score: Option<f64>,
+    /// The matches, bucketed by how likely the favorite was to win.
+    ///
+    /// Empty buckets are left out.
+    pub buckets: Vec<CalibrationBucket>,
+}
+
+/// Matches whose favorites had around the same chance to win.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CalibrationBucket {
+    /// The lowest win probability in this bucket, inclusive.
+    pub min_probability: f64,
+    /// The highest win probability in this bucket, exclusive, unless it is 1.
+    pub max_probability: f64,
+    /// How many matches are in this bucket.
+    pub matches: i64,
+    /// How many of those matches the favorite won.
+    pub favorite_wins: i64,
+    /// The mean win probability of the favorites.
+    pub expected_win_rate: f64,
+    /// How often the favorites actually won.
+    pub win_rate: f64,
+}
@@ -2,10 +2,16 @@
 
 use std::borrow::Cow;
 
+use chrono::{DateTime, Utc};
+
 use serde::{Deserialize, Serialize};
 
 use bytemuck::cast;
 
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::mobiums::Mobiums;
+
 /// The current user returned by `/users/~me`.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct CurrentUser {
@@ -19,17 +25,160 @@ pub struct CurrentUser {
     /// The display name of the user.
     pub display_name: String,
     /// How many mobiums they have.
-    pub mobiums: i64,
+    pub mobiums: Mobiums,
+    /// How many mobiums they can still wager.
+    ///
+    /// This is `mobiums`, less what is staked on matches that haven't been
+    /// paid out yet.
+    #[serde(default)]
+    pub available_mobiums: Mobiums,
     /// How many mobiums they have gained in their lifetime.
-    pub mobiums_gained: i64,
+    pub mobiums_gained: Mobiums,
     /// How many mobiums they have lost in their lifetime.
-    pub mobiums_lost: i64,
+    pub mobiums_lost: Mobiums,
+    /// How many correct predictions they have made in a row.
+    #[serde(default)]
+    pub streak: i32,
     /// The user flags.
     pub flags: UserFlags,
+    /// Whether other users can see who made this user's wagers.
+    pub show_wagers_publicly: bool,
+    /// The status of the user's Discord link, if they have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discord: Option<DiscordLink>,
+    /// The user's penalties that haven't expired yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub penalties: Vec<Penalty>,
+}
+
+/// A user's standing rule to wager on every new match.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct AutoBet {
+    /// Which team to wager on.
+    pub pick: AutoBetPick,
+    /// How many mobiums to wager on each match.
+    ///
+    /// Wagers are cut down to what the user can wager at the time.
+    pub mobiums: Mobiums,
+    /// The most the rule can wager in a day.
+    ///
+    /// If this is `None`, the rule wagers on every match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_cap: Option<Mobiums>,
+    /// Whether the rule is wagering.
+    pub enabled: bool,
+    /// When the rule was last changed.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Which team an [`AutoBet`] wagers on.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, TryFromPrimitive, IntoPrimitive,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schemars", schemars(rename_all = "snake_case"))]
+pub enum AutoBetPick {
+    /// The team with the higher rating.
+    ///
+    /// Matches without a prediction are skipped.
+    Favourite = 0,
+    /// The team with the lower rating.
+    ///
+    /// Matches without a prediction are skipped.
+    Underdog = 1,
+    /// Always the red team.
+    Red = 2,
+    /// Always the blue team.
+    Blue = 3,
+}
+
+/// A penalty given to a user by an administrator.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct Penalty {
+    /// The id of the penalty.
+    pub id: i32,
+    /// What the penalty keeps the user from doing.
+    pub kind: PenaltyKind,
+    /// Why the penalty was given.
+    pub reason: String,
+    /// When the penalty expires.
+    ///
+    /// If this is `None`, the penalty lasts until it is revoked.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the penalty was revoked, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// When the penalty was given.
+    pub created_at: DateTime<Utc>,
+}
+
+/// What a penalty keeps a user from doing.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, TryFromPrimitive, IntoPrimitive,
+)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum PenaltyKind {
+    /// Nothing; the user was only warned.
+    Warning = 0,
+    /// The user can't place or raise wagers.
+    WagerBan = 1,
+    /// The user can't send chat messages.
+    ChatMute = 2,
+}
+
+/// A user's mobiums, split by what is staked on matches that haven't been
+/// paid out yet.
+///
+/// Wagers don't take mobiums until they are paid out, so mobiums staked on
+/// one match can't be wagered on another in the meantime.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct WagerBalance {
+    /// How many mobiums the user has.
+    pub mobiums: Mobiums,
+    /// How many mobiums are staked on matches that haven't been paid out.
+    pub reserved: Mobiums,
+    /// How many mobiums the user can still wager.
+    pub available: Mobiums,
+}
+
+/// A user's link to their Discord account.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct DiscordLink {
+    /// Whether the link is still usable.
+    pub status: LinkStatus,
+    /// When the link's tokens were last refreshed.
+    pub last_fetched_at: DateTime<Utc>,
+}
+
+/// The status of an account link.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStatus {
+    /// The link is alive and its tokens are being refreshed.
+    Active,
+    /// The link's tokens were rejected. The user will have to log in again
+    /// before their profile can be refreshed.
+    Dead,
+}
+
+/// A one-time code to link another account to this one.
+///
+/// Redeeming the code on another account moves everything over to that
+/// account, and merges this one into it.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct LinkCode {
+    /// The code.
+    pub code: String,
+    /// When the code can no longer be redeemed.
+    pub expires_at: DateTime<Utc>,
 }
 
 /// A single user.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct User {
     /// The unique username of the user.
     pub username: String,
@@ -38,11 +187,14 @@ pub struct User {
     /// The display name of the user.
     pub display_name: String,
     /// How many mobiums they have.
-    pub mobiums: i64,
+    pub mobiums: Mobiums,
     /// How many mobiums they have gained in their lifetime.
-    pub mobiums_gained: i64,
+    pub mobiums_gained: Mobiums,
     /// How many mobiums they have lost in their lifetime.
-    pub mobiums_lost: i64,
+    pub mobiums_lost: Mobiums,
+    /// How many correct predictions they have made in a row.
+    #[serde(default)]
+    pub streak: i32,
     /// The user flags.
     pub flags: UserFlags,
 }
@@ -61,6 +213,24 @@ bitflags::bitflags! {
         const AUTOMATED_USER = 0b00000010;
         /// This user helped beta test. Thanks!
         const BETA_TESTER = 0b00000100;
+        /// The user can access administrative endpoints.
+        const ADMINISTRATOR = 0b00001000;
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for UserFlags {
+    fn schema_name() -> Cow<'static, str> {
+        "UserFlags".into()
+    }
+
+    fn json_schema(_generator: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        // bitflags serializes the names of the set flags, joined by `|`
+        schemars::json_schema!({
+            "type": "string",
+            "description": "The names of the user's flags, joined by ` | `.",
+            "examples": ["BETA_TESTER | ADMINISTRATOR"],
+        })
     }
 }
 
@@ -0,0 +1,63 @@
+//! Outbound webhooks.
+//!
+//! Third-party integrations can register a URL to be sent match lifecycle
+//! events as they happen.
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Battle;
+
+/// A registered webhook.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Webhook {
+    /// The id of the webhook.
+    pub id: i32,
+    /// The URL events are sent to.
+    pub url: String,
+    /// The secret deliveries are signed with.
+    ///
+    /// This is only ever sent when the webhook is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// How many deliveries are waiting to be sent.
+    pub pending: i64,
+    /// How many deliveries were given up on.
+    pub failed: i64,
+    /// When the webhook was registered.
+    pub created_at: DateTime<Utc>,
+}
+
+/// The body of a webhook delivery.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookPayload {
+    /// When the event happened.
+    pub created_at: DateTime<Utc>,
+    /// The event.
+    #[serde(flatten)]
+    pub event: WebhookEvent,
+}
+
+/// A match lifecycle event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    /// A match was started.
+    MatchCreated(Battle),
+    /// A match was concluded, and wagers were paid out.
+    MatchConcluded(Battle),
+    /// A match was cancelled.
+    MatchCancelled(Battle),
+}
+
+impl WebhookEvent {
+    /// The name of the event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::MatchCreated(_) => "match-created",
+            WebhookEvent::MatchConcluded(_) => "match-concluded",
+            WebhookEvent::MatchCancelled(_) => "match-cancelled",
+        }
+    }
+}
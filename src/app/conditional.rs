@@ -0,0 +1,134 @@
+//! Conditional requests.
+//!
+//! Lets clients that poll a resource skip the body if their copy is still
+//! fresh, with `If-None-Match` and `If-Modified-Since`.
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::FromRequestParts,
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+};
+
+use chrono::{DateTime, SubsecRound as _, Utc};
+
+use http::{HeaderValue, StatusCode, header, request::Parts};
+
+/// The format of an HTTP-date.
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// The conditional headers of a request.
+#[derive(Clone, Debug, Default)]
+pub struct Conditional {
+    if_none_match: Option<String>,
+    if_modified_since: Option<DateTime<Utc>>,
+}
+
+impl Conditional {
+    /// Checks if the client's copy of a resource is still fresh.
+    ///
+    /// `If-None-Match` takes precedence over `If-Modified-Since`.
+    pub fn is_fresh(&self, validators: &Validators) -> bool {
+        if let Some(if_none_match) = self.if_none_match.as_ref() {
+            if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == validators.etag)
+        } else if let Some(if_modified_since) = self.if_modified_since {
+            // HTTP-dates only have second precision
+            validators.last_modified.trunc_subsecs(0) <= if_modified_since
+        } else {
+            false
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for Conditional
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let if_none_match = parts
+            .headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.to_owned());
+        let if_modified_since = parts
+            .headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| DateTime::parse_from_rfc2822(h).ok())
+            .map(|h| h.to_utc());
+
+        Ok(Conditional {
+            if_none_match,
+            if_modified_since,
+        })
+    }
+}
+
+/// Cache validators of a resource.
+///
+/// Sent as the `ETag` and `Last-Modified` headers.
+#[derive(Clone, Debug)]
+pub struct Validators {
+    etag: String,
+    last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// Creates new validators.
+    ///
+    /// `tag` must change whenever the resource does.
+    pub fn new(tag: impl std::fmt::Display, last_modified: DateTime<Utc>) -> Validators {
+        Validators {
+            etag: format!("\"{}\"", tag),
+            last_modified,
+        }
+    }
+}
+
+impl IntoResponseParts for Validators {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let last_modified = self.last_modified.format(HTTP_DATE_FORMAT).to_string();
+
+        let headers = res.headers_mut();
+        if let Ok(etag) = HeaderValue::try_from(self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Ok(last_modified) = HeaderValue::try_from(last_modified) {
+            headers.insert(header::LAST_MODIFIED, last_modified);
+        }
+        // allow caches to store the response, as long as they revalidate
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        Ok(res)
+    }
+}
+
+/// A response to a conditional request.
+#[derive(Debug)]
+pub enum Cached<T> {
+    /// The client's copy is still fresh.
+    NotModified(Validators),
+    /// The resource changed.
+    Modified(Validators, T),
+}
+
+impl<T> IntoResponse for Cached<T>
+where
+    T: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        match self {
+            Cached::NotModified(validators) => {
+                (StatusCode::NOT_MODIFIED, validators, ()).into_response()
+            }
+            Cached::Modified(validators, body) => (validators, body).into_response(),
+        }
+    }
+}
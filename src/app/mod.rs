@@ -1,5 +1,8 @@
 //! Application interface and state.
 
+pub mod conditional;
+pub mod page;
+
 use std::{any::Any, sync::Arc};
 
 use axum_valid::{Garde, GardeRejection, HasValidate};
@@ -19,7 +22,22 @@ use serde::de::DeserializeOwned;
 
 use sqlx::SqlitePool;
 
-use crate::{config::Config, player::mmr, room};
+use crate::{
+    auth::captcha::Captcha,
+    avatar::{Avatars, proxy_url},
+    config::Config,
+    deadline::BetDeadlines,
+    flags::Flags,
+    health::Health,
+    metrics::Metrics,
+    player::mmr,
+    replica::ReadPool,
+    room,
+    slow_query::SlowQueries,
+    supervisor::Supervisor,
+    user::bot::BotSwitch,
+    wager_queue::WagerQueue,
+};
 
 use crate::error::{Error, ErrorKind};
 
@@ -30,12 +48,61 @@ use crate::error::{Error, ErrorKind};
 pub struct AppState {
     /// The database connection pool.
     pub db: SqlitePool,
+    /// The pools read-heavy handlers query.
+    pub replica: ReadPool,
     /// The WebSocket room.
     pub room: room::Room,
     /// Server config.
     ///
     /// May be missing secrets as they are taken at initialization.
     pub config: Arc<Config>,
+    /// Service health.
+    pub health: Health,
+    /// The avatar proxy, if enabled.
+    pub avatars: Option<Avatars>,
+    /// Service metrics.
+    pub metrics: Metrics,
+    /// The most recent slow queries.
+    pub slow_queries: SlowQueries,
+    /// Wagers waiting to be written.
+    pub wagers: WagerQueue,
+    /// Whether the wager bot is running.
+    pub bot: BotSwitch,
+    /// When bets close on matches created by this instance.
+    pub deadlines: BetDeadlines,
+    /// CAPTCHA verification, if enabled.
+    pub captcha: Option<Captcha>,
+    /// Feature flags.
+    pub flags: Flags,
+    /// Background tasks.
+    pub tasks: Supervisor,
+}
+
+impl AppState {
+    /// The pool read-only handlers should query.
+    ///
+    /// This is the read replica, if one is configured and healthy. Anything
+    /// that writes, or needs to see its own writes, should use `db`.
+    pub fn read_db(&self) -> &SqlitePool {
+        self.replica.pool()
+    }
+
+    /// Creates the URL a user's avatar should be served from.
+    ///
+    /// This is the avatar proxy if it is enabled.
+    pub fn avatar_url(&self, username: &str, avatar: Option<String>) -> Option<String> {
+        match self.avatars {
+            Some(_) => {
+                avatar.map(|avatar| proxy_url(&self.config.server.base_url, username, &avatar))
+            }
+            None => avatar,
+        }
+    }
+}
+
+// Request bodies in the model validate without any context.
+impl FromRef<AppState> for () {
+    fn from_ref(_state: &AppState) -> Self {}
 }
 
 /// Rating model.
@@ -160,6 +227,14 @@ where
     }
 }
 
+impl<T> HasValidate for Payload<T> {
+    type Validate = T;
+
+    fn get_validate(&self) -> &Self::Validate {
+        &self.0
+    }
+}
+
 /// App Garde extrarctor.
 #[derive(Deref)]
 pub struct AppGarde<T>(pub T);
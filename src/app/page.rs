@@ -0,0 +1,62 @@
+//! Paginated lists.
+//!
+//! List endpoints send bare arrays, unless the client asks for a [`Page`] with
+//! `format=page`. Pages carry a cursor the client can pass back as `cursor`
+//! to get the next page.
+
+use std::str::FromStr;
+
+use ring_channel_model::page::{ListFormat, Page};
+
+use serde::Serialize;
+
+use crate::error::{Error, ErrorKind};
+
+/// A list, in the format the client asked for.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Listing<T> {
+    Array(Vec<T>),
+    Page(Page<T>),
+}
+
+impl<T> Listing<T> {
+    /// Creates a new `Listing`.
+    ///
+    /// `next_cursor` should only be given if there are more items.
+    pub fn new(format: ListFormat, data: Vec<T>, next_cursor: Option<String>) -> Listing<T> {
+        match format {
+            ListFormat::Array => Listing::Array(data),
+            ListFormat::Page => Listing::Page(Page {
+                data,
+                has_more: next_cursor.is_some(),
+                next_cursor,
+            }),
+        }
+    }
+}
+
+/// Trims a page of rows down to `count`.
+///
+/// Lists fetch one more row than they show to tell if there is another page.
+/// Returns `true` if there is.
+pub fn trim_page<T>(rows: &mut Vec<T>, count: i32) -> bool {
+    let count = usize::try_from(count).unwrap_or_default();
+
+    if rows.len() > count {
+        rows.truncate(count);
+        true
+    } else {
+        false
+    }
+}
+
+/// Parses a cursor a client passed back.
+pub fn parse_cursor<C>(cursor: &str) -> Result<C, Error>
+where
+    C: FromStr,
+{
+    cursor
+        .parse()
+        .map_err(|_| ErrorKind::InvalidData(format!("Invalid cursor {}", cursor)).into())
+}
@@ -0,0 +1,154 @@
+//! Audit logging.
+//!
+//! Every mutating request is logged in the `audit_log` table, along with who
+//! made it. Extractors that authenticate the client fill in the actor of the
+//! [`Audit`] attached to the request, and handlers can add notes on what they
+//! changed.
+
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use chrono::Utc;
+
+use http::{Method, request::Parts};
+
+use ring_channel_model::admin::ActorKind;
+
+use sqlx::SqlitePool;
+
+use crate::{app::AppState, error::Error};
+
+/// The audit entry of a request.
+///
+/// Cheaply cloneable. If this is extracted outside of the [`audit_log`]
+/// middleware, it is detached and nothing will be written.
+#[derive(Clone, Debug, Default)]
+pub struct Audit {
+    inner: Arc<Mutex<AuditEntry>>,
+}
+
+#[derive(Debug, Default)]
+struct AuditEntry {
+    actor: Option<(ActorKind, i32)>,
+    notes: Vec<String>,
+}
+
+impl Audit {
+    /// Creates a new `Audit`.
+    pub fn new() -> Audit {
+        Audit::default()
+    }
+
+    /// Sets the actor of the request.
+    pub fn set_actor(&self, kind: ActorKind, id: impl Into<i32>) {
+        self.inner.lock().expect("audit poisoned").actor = Some((kind, id.into()));
+    }
+
+    /// Adds a note about what the request changed.
+    pub fn note(&self, note: impl Into<String>) {
+        self.inner
+            .lock()
+            .expect("audit poisoned")
+            .notes
+            .push(note.into());
+    }
+
+    /// Writes the entry to the database.
+    pub async fn write(
+        &self,
+        method: &str,
+        path: &str,
+        status: u16,
+        db: &SqlitePool,
+    ) -> Result<(), Error> {
+        let (actor, summary) = {
+            let entry = self.inner.lock().expect("audit poisoned");
+            let summary = if entry.notes.is_empty() {
+                None
+            } else {
+                Some(entry.notes.join("; "))
+            };
+            (entry.actor, summary)
+        };
+
+        let (kind, id) = match actor {
+            Some((kind, id)) => (kind, Some(id)),
+            None => (ActorKind::Anonymous, None),
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log
+                (actor_kind, actor_id, method, path, status, summary, inserted_at)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(u8::from(kind))
+        .bind(id)
+        .bind(method)
+        .bind(path)
+        .bind(status)
+        .bind(summary)
+        .bind(Utc::now())
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl<S> FromRequestParts<S> for Audit
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Audit>().cloned().unwrap_or_default())
+    }
+}
+
+/// Sets the actor of the request being processed, if it is being audited.
+pub fn set_actor(parts: &Parts, kind: ActorKind, id: impl Into<i32>) {
+    if let Some(audit) = parts.extensions.get::<Audit>() {
+        audit.set_actor(kind, id);
+    }
+}
+
+/// Middleware that logs all mutating requests.
+pub async fn audit_log(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if !matches!(
+        *request.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    ) {
+        return next.run(request).await;
+    }
+
+    let audit = Audit::new();
+    request.extensions_mut().insert(audit.clone());
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16();
+    if let Err(err) = audit.write(method.as_str(), &path, status, &state.db).await {
+        tracing::error!(?err, "failed to write audit log");
+    }
+
+    response
+}
@@ -5,8 +5,11 @@ use axum::extract::{FromRef, FromRequestParts};
 use http::{header::HeaderName, request::Parts};
 use sqlx::FromRow;
 
+use ring_channel_model::admin::ActorKind;
+
 use crate::{
     app::AppState,
+    audit,
     error::{Error, ErrorKind},
 };
 
@@ -85,6 +88,7 @@ where
 
                     // cache toe xtensions
                     parts.extensions.insert(auth.clone());
+                    audit::set_actor(parts, ActorKind::Server, id);
 
                     Ok(auth)
                 }
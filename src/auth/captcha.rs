@@ -0,0 +1,150 @@
+//! CAPTCHA verification.
+//!
+//! Throwaway accounts are an easy way to push pots around, so new accounts
+//! can be made to pass a CAPTCHA before they wager. Responses are verified
+//! with the provider server-side.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{TimeDelta, Utc};
+
+use ring_channel_model::{UserId, user::UserFlags};
+
+use serde::Deserialize;
+
+use sqlx::SqliteConnection;
+
+use crate::{
+    config::{CaptchaConfig, CaptchaProvider},
+    error::{Error, ErrorKind},
+};
+
+/// How long the provider has to verify a response.
+pub const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Verifies CAPTCHA responses with the configured provider.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct Captcha {
+    provider: CaptchaProvider,
+    secret: Arc<str>,
+    min_account_age: TimeDelta,
+    http_client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct VerifyResponse {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+impl Captcha {
+    /// Creates a new `Captcha` from config.
+    pub fn new(config: &CaptchaConfig) -> Result<Captcha, Error> {
+        let http_client = reqwest::Client::builder().timeout(VERIFY_TIMEOUT).build()?;
+
+        Ok(Captcha {
+            provider: config.provider,
+            secret: config.secret.as_str().into(),
+            min_account_age: config.min_account_age,
+            http_client,
+        })
+    }
+
+    /// Checks if a user has to pass a CAPTCHA before they wager.
+    ///
+    /// Users who passed one before are never asked again, and neither are
+    /// automated users.
+    pub async fn required(
+        &self,
+        user_id: UserId,
+        conn: &mut SqliteConnection,
+    ) -> Result<bool, Error> {
+        let (required,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            SELECT
+                u.verified_at IS NULL
+                AND NOT u.flags & $2
+                AND (
+                    u.inserted_at > $3
+                    OR NOT EXISTS (SELECT 1 FROM wager w WHERE w.user_id = u.id)
+                )
+            FROM user u
+            WHERE u.id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(i32::from(UserFlags::AUTOMATED_USER))
+        .bind(Utc::now() - self.min_account_age)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(required)
+    }
+
+    /// Makes sure a user has passed a CAPTCHA, if they have to.
+    ///
+    /// `response` is the token the CAPTCHA widget gave the user. If it
+    /// passes, the user is remembered as verified.
+    pub async fn check(
+        &self,
+        user_id: UserId,
+        response: Option<&str>,
+        conn: &mut SqliteConnection,
+    ) -> Result<(), Error> {
+        if !self.required(user_id, conn).await? {
+            return Ok(());
+        }
+
+        let Some(response) = response.filter(|response| !response.is_empty()) else {
+            return Err(ErrorKind::CaptchaRequired.into());
+        };
+
+        if !self.verify(response).await? {
+            return Err(ErrorKind::CaptchaRequired.into());
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE user
+            SET verified_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Verifies a CAPTCHA response with the provider.
+    pub async fn verify(&self, response: &str) -> Result<bool, Error> {
+        let url = match self.provider {
+            CaptchaProvider::Turnstile => {
+                "https://challenges.cloudflare.com/turnstile/v0/siteverify"
+            }
+            CaptchaProvider::Hcaptcha => "https://api.hcaptcha.com/siteverify",
+        };
+
+        let body = self
+            .http_client
+            .post(url)
+            .form(&[("secret", &*self.secret), ("response", response)])
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let verified = serde_json::from_slice::<VerifyResponse>(&body).map_err(Error::new)?;
+        if !verified.success {
+            tracing::debug!(errors = ?verified.error_codes, "captcha verification failed");
+        }
+
+        Ok(verified.success)
+    }
+}
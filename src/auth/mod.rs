@@ -1,4 +1,5 @@
 //! Client authentication.
 
 pub mod api_key;
+pub mod captcha;
 pub mod oauth2;
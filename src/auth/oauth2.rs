@@ -1,16 +1,21 @@
 //! OAuth Authorization Grant flow.
 
+use chrono::{DateTime, TimeDelta, Utc};
+
 use eyre::Error;
 
 use oauth2::{
-    AuthUrl, ClientId, ClientSecret, EndpointNotSet, EndpointSet, RedirectUrl, RevocationUrl,
-    TokenUrl, basic::BasicClient,
+    AuthUrl, ClientId, ClientSecret, EndpointNotSet, EndpointSet, RedirectUrl, RefreshToken,
+    RequestTokenError, RevocationUrl, TokenResponse as _, TokenUrl,
+    basic::{BasicClient, BasicErrorResponseType},
 };
-use sqlx::SqlitePool;
+use sqlx::{FromRow, SqlitePool};
 
 use std::sync::Arc;
 
-use crate::config::DiscordConfig;
+use ring_channel_model::display_name;
+
+use crate::{config::DiscordConfig, user::DEFAULT_STARTING_MOBIUMS};
 
 pub use crate::session::Session;
 
@@ -40,6 +45,15 @@ pub struct OauthState {
     pub http_client: reqwest::Client,
     /// The URL to redirect to after a successful authorization code grant.
     pub redirect_to: Option<Arc<str>>,
+    /// How many mobiums new users start with.
+    pub starting_mobiums: i64,
+    /// How many characters new users' display names are cut down to.
+    pub max_display_name_length: usize,
+    /// Whether to use PKCE for authorization code grants.
+    pub pkce: bool,
+    /// The Discord server users are checked for membership of when they log
+    /// in.
+    pub guild_id: Option<u64>,
 }
 
 impl OauthState {
@@ -70,6 +84,10 @@ impl OauthState {
             client,
             http_client,
             redirect_to: None,
+            starting_mobiums: DEFAULT_STARTING_MOBIUMS,
+            max_display_name_length: display_name::DEFAULT_MAX_LENGTH,
+            pkce: config.pkce,
+            guild_id: None,
         })
     }
 
@@ -80,4 +98,176 @@ impl OauthState {
             ..self
         }
     }
+
+    /// Sets the `starting_mobiums`.
+    pub fn with_starting_mobiums(self, starting_mobiums: i64) -> OauthState {
+        OauthState {
+            starting_mobiums,
+            ..self
+        }
+    }
+
+    /// Sets the `guild_id`.
+    pub fn with_guild_id(self, guild_id: Option<u64>) -> OauthState {
+        OauthState { guild_id, ..self }
+    }
+
+    /// Sets the `max_display_name_length`.
+    pub fn with_max_display_name_length(self, max_display_name_length: usize) -> OauthState {
+        OauthState {
+            max_display_name_length,
+            ..self
+        }
+    }
+}
+
+/// Checks if the Discord user a client is authorized as is a member of a
+/// Discord server.
+///
+/// Returns `None` if their servers couldn't be fetched.
+pub async fn is_guild_member(http_client: &twilight_http::Client, guild_id: u64) -> Option<bool> {
+    // nobody can be in more than 200 servers
+    let guilds = match http_client.current_user_guilds().limit(200).await {
+        Ok(response) => response.models().await.map_err(Error::new),
+        Err(err) => Err(err.into()),
+    };
+
+    match guilds {
+        Ok(guilds) => Some(guilds.iter().any(|guild| guild.id.get() == guild_id)),
+        Err(err) => {
+            tracing::warn!(%err, "failed to fetch Discord servers");
+            None
+        }
+    }
+}
+
+/// Rotates every Discord refresh token that hasn't been used for
+/// `refresh_after`.
+///
+/// Server membership is checked again with the new token, so users that left
+/// the server lose their verification. Links whose refresh tokens get
+/// rejected by Discord are marked as dead, and their users lose their
+/// verification too; the user will have to log in again to revive them.
+pub async fn refresh_stale_tokens(
+    oauth_state: &OauthState,
+    refresh_after: TimeDelta,
+) -> Result<(), Error> {
+    #[derive(FromRow)]
+    struct StaleLinkQuery {
+        user_id: i32,
+        refresh_token: String,
+    }
+
+    let now = Utc::now();
+
+    let stale_links = sqlx::query_as::<_, StaleLinkQuery>(
+        r#"
+        SELECT user_id, refresh_token
+        FROM discord_auth
+        WHERE dead_at IS NULL AND last_fetched_at < $1
+        "#,
+    )
+    .bind(now - refresh_after)
+    .fetch_all(&oauth_state.db)
+    .await?;
+
+    for link in stale_links {
+        let token_result = oauth_state
+            .client
+            .exchange_refresh_token(&RefreshToken::new(link.refresh_token))
+            .request_async(&oauth_state.http_client)
+            .await;
+
+        let now = Utc::now();
+
+        match token_result {
+            Ok(token_result) => {
+                // Discord always rotates refresh tokens, but just in case
+                let Some(refresh_token) = token_result.refresh_token() else {
+                    tracing::warn!(user_id = link.user_id, "missing refresh token on refresh");
+                    continue;
+                };
+
+                sqlx::query(
+                    r#"
+                    UPDATE discord_auth
+                    SET refresh_token = $2, last_fetched_at = $3, updated_at = $3
+                    WHERE user_id = $1
+                    "#,
+                )
+                .bind(link.user_id)
+                .bind(refresh_token.secret())
+                .bind(now)
+                .execute(&oauth_state.db)
+                .await?;
+
+                if let Some(guild_id) = oauth_state.guild_id {
+                    let token = format!("Bearer {}", token_result.access_token().secret());
+                    let http_client = twilight_http::Client::builder().token(token).build();
+
+                    if let Some(guild_verified) = is_guild_member(&http_client, guild_id).await {
+                        set_guild_verified(
+                            link.user_id,
+                            guild_verified.then_some(now),
+                            oauth_state,
+                        )
+                        .await?;
+                    }
+                }
+
+                tracing::debug!(user_id = link.user_id, "refreshed discord token");
+            }
+            Err(RequestTokenError::ServerResponse(res))
+                if *res.error() == BasicErrorResponseType::InvalidGrant =>
+            {
+                // the refresh token was revoked or expired
+                sqlx::query(
+                    r#"
+                    UPDATE discord_auth
+                    SET dead_at = $2, updated_at = $2
+                    WHERE user_id = $1
+                    "#,
+                )
+                .bind(link.user_id)
+                .bind(now)
+                .execute(&oauth_state.db)
+                .await?;
+
+                // membership can't be checked anymore
+                if oauth_state.guild_id.is_some() {
+                    set_guild_verified(link.user_id, None, oauth_state).await?;
+                }
+
+                tracing::info!(user_id = link.user_id, "discord link is dead");
+            }
+            Err(err) => {
+                // probably transient, try again next time
+                tracing::warn!(user_id = link.user_id, "failed to refresh token: {}", err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets when a linked user was last verified as a member of the Discord
+/// server, on the account they were merged into if they were.
+async fn set_guild_verified(
+    user_id: i32,
+    guild_verified_at: Option<DateTime<Utc>>,
+    oauth_state: &OauthState,
+) -> Result<(), Error> {
+    sqlx::query(
+        r#"
+        UPDATE user
+        SET guild_verified_at = $2
+        WHERE id = (SELECT IFNULL(merged_into, id) FROM user WHERE id = $1)
+        "#,
+    )
+    .bind(user_id)
+    .bind(guild_verified_at)
+    .execute(&oauth_state.db)
+    .await?;
+
+    Ok(())
 }
@@ -0,0 +1,83 @@
+//! Filesystem avatar storage.
+
+use std::{io::ErrorKind as IoErrorKind, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use tokio::fs;
+
+use crate::error::Error;
+
+use super::{Avatar, AvatarStore};
+
+/// Stores avatars in a directory.
+///
+/// Each avatar is stored as two files: the image itself, and a small JSON
+/// file describing where it came from.
+#[derive(Debug)]
+pub struct FilesystemStore {
+    root: PathBuf,
+}
+
+/// The metadata stored alongside an avatar.
+#[derive(Deserialize, Serialize)]
+struct AvatarMeta {
+    source: String,
+    content_type: String,
+}
+
+impl FilesystemStore {
+    /// Creates a new `FilesystemStore`, storing avatars in `root`.
+    pub fn new(root: PathBuf) -> FilesystemStore {
+        FilesystemStore { root }
+    }
+
+    fn image_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{}.img", key))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.root.join(format!("{}.json", key))
+    }
+}
+
+impl AvatarStore for FilesystemStore {
+    async fn load(&self, key: &str) -> Result<Option<Avatar>, Error> {
+        let meta = match fs::read(self.meta_path(key)).await {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == IoErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::new(err)),
+        };
+        let meta = serde_json::from_slice::<AvatarMeta>(&meta)?;
+
+        let data = match fs::read(self.image_path(key)).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == IoErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::new(err)),
+        };
+
+        Ok(Some(Avatar {
+            source: meta.source,
+            content_type: meta.content_type,
+            data,
+        }))
+    }
+
+    async fn store(&self, key: &str, avatar: &Avatar) -> Result<(), Error> {
+        let meta = serde_json::to_vec(&AvatarMeta {
+            source: avatar.source.clone(),
+            content_type: avatar.content_type.clone(),
+        })?;
+
+        fs::create_dir_all(&self.root).await.map_err(Error::new)?;
+        fs::write(self.image_path(key), &avatar.data)
+            .await
+            .map_err(Error::new)?;
+        // written last, so an interrupted write is downloaded again
+        fs::write(self.meta_path(key), meta)
+            .await
+            .map_err(Error::new)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+//! Avatar proxy.
+//!
+//! Avatars are hotlinked from wherever the user's account lives, which breaks
+//! when the link rotates. Instead, avatars are downloaded and stored, then
+//! served from `/avatars/{username}`.
+
+pub mod fs;
+
+use std::sync::Arc;
+
+use http::header;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::{config::AvatarConfig, error::Error};
+
+use fs::FilesystemStore;
+
+/// The largest avatar that will be downloaded, in bytes.
+pub const MAX_AVATAR_SIZE: usize = 1024 * 1024;
+
+/// A stored avatar.
+#[derive(Clone, Debug)]
+pub struct Avatar {
+    /// The URL the avatar was downloaded from.
+    pub source: String,
+    /// The MIME type of the image.
+    pub content_type: String,
+    /// The image.
+    pub data: Vec<u8>,
+}
+
+/// Storage for avatars.
+pub trait AvatarStore: Send + Sync {
+    /// Loads the avatar stored under `key`.
+    fn load(&self, key: &str) -> impl Future<Output = Result<Option<Avatar>, Error>> + Send;
+
+    /// Stores an avatar under `key`, replacing any old one.
+    fn store(&self, key: &str, avatar: &Avatar) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// The avatar proxy.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct Avatars {
+    storage: Arc<Storage>,
+    http_client: reqwest::Client,
+}
+
+/// The configured [`AvatarStore`].
+#[derive(Debug)]
+enum Storage {
+    Filesystem(FilesystemStore),
+}
+
+impl AvatarStore for Storage {
+    async fn load(&self, key: &str) -> Result<Option<Avatar>, Error> {
+        match self {
+            Storage::Filesystem(store) => store.load(key).await,
+        }
+    }
+
+    async fn store(&self, key: &str, avatar: &Avatar) -> Result<(), Error> {
+        match self {
+            Storage::Filesystem(store) => store.store(key, avatar).await,
+        }
+    }
+}
+
+impl Avatars {
+    /// Creates a new avatar proxy from config.
+    pub fn new(config: &AvatarConfig) -> Avatars {
+        let storage = match config {
+            AvatarConfig::Filesystem { path } => {
+                Storage::Filesystem(FilesystemStore::new(path.clone()))
+            }
+        };
+
+        Avatars {
+            storage: Arc::new(storage),
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// Fetches the avatar stored under `key`.
+    ///
+    /// If the stored avatar is missing or was downloaded from a different
+    /// `source`, it is downloaded again. If that fails, the old avatar is
+    /// returned, if there is one.
+    pub async fn fetch(&self, key: &str, source: &str) -> Result<Option<Avatar>, Error> {
+        let stored = self.storage.load(key).await?;
+
+        if let Some(stored) = stored.as_ref()
+            && stored.source == source
+        {
+            return Ok(Some(stored.clone()));
+        }
+
+        match self.download(source).await {
+            Ok(avatar) => {
+                self.storage.store(key, &avatar).await?;
+                Ok(Some(avatar))
+            }
+            Err(err) => {
+                tracing::warn!(key, source, "failed to download avatar: {}", err);
+                Ok(stored)
+            }
+        }
+    }
+
+    async fn download(&self, source: &str) -> Result<Avatar, Error> {
+        let res = self
+            .http_client
+            .get(source)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let content_type = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .filter(|h| h.starts_with("image/"))
+            .map(|h| h.to_owned())
+            .ok_or_else(|| Error::new(std::io::Error::other("avatar is not an image")))?;
+
+        if res
+            .content_length()
+            .is_some_and(|len| len > MAX_AVATAR_SIZE as u64)
+        {
+            return Err(Error::new(std::io::Error::other("avatar too large")));
+        }
+
+        let data = res.bytes().await?;
+        if data.len() > MAX_AVATAR_SIZE {
+            return Err(Error::new(std::io::Error::other("avatar too large")));
+        }
+
+        Ok(Avatar {
+            source: source.to_owned(),
+            content_type,
+            data: data.to_vec(),
+        })
+    }
+}
+
+/// Creates the URL an avatar is proxied at.
+///
+/// The URL includes a digest of `source`, so it changes when the avatar does.
+pub fn proxy_url(base_url: &str, username: &str, source: &str) -> String {
+    let digest = Sha256::digest(source.as_bytes());
+
+    format!(
+        "{}/avatars/{}?v={}",
+        base_url,
+        username,
+        base16::encode_lower(&digest[..4])
+    )
+}
@@ -2,26 +2,53 @@
 
 use std::{
     cmp::{max, min},
+    collections::HashMap,
     fmt::Debug,
 };
 
 use chrono::{DateTime, Utc};
 
 use ring_channel_model::{
-    Battle,
-    battle::{BattleStatus, PlayerTeam},
-    message::server::MobiumsChange,
+    Battle, BattleId, User, UserId,
+    battle::{
+        BattlePrediction, BattleStatus, FinishTimeLine, HighlightTag, LineSide, OddsMode,
+        ParticipantStats, PlayerTeam, Visibility, WagerMarket,
+    },
+    message::server::{
+        BattleSettled, Highlight, LineSettled, MobiumsChange, Payout, SettlementProgress,
+        StreakChange,
+    },
+    push::PushNotification,
     user::UserFlags,
+    webhook::WebhookEvent,
 };
 
-use sqlx::{FromRow, SqliteConnection};
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqliteConnection};
 
 use crate::{
+    app::{self, AppState},
     error::Error,
-    player::mmr::{Model, RatingRecord, RawRatingRecord, update_rating},
-    room::Room,
+    event,
+    highlight::detect_highlights,
+    payout::{self, Parimutuel, Pots, Stake},
+    player::mmr::{Model, RatingRecord, RawRatingRecord, update_rating_at},
+    push,
+    room::BattleData,
+    routes::battle::preload_participants,
+    user::UserSchema,
+    webhook,
 };
 
+/// How many wagers are paid out at a time.
+///
+/// Each batch is written with a handful of queries, and sent to the room as a
+/// single event. Matches with more wagers than this send progress to the room
+/// as they are paid out.
+pub const SETTLEMENT_BATCH_SIZE: usize = 500;
+
+/// How many mobiums a user is left with when they are bailed out.
+pub const BAILOUT_MOBIUMS: i64 = 100;
+
 /// A schema for battles stored in database.
 ///
 /// Used primarily to construct [`Battle`]s.
@@ -29,10 +56,43 @@ use crate::{
 pub struct BattleSchema {
     pub uuid: String,
     pub level_name: String,
-    #[sqlx(try_from = "u8")]
     pub status: BattleStatus,
     pub inserted_at: DateTime<Utc>,
     pub closed_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub concluded_at: Option<DateTime<Utc>>,
+    #[sqlx(try_from = "u8")]
+    pub odds_mode: OddsMode,
+    pub finish_time_line: Option<i32>,
+    pub red_win_probability: Option<f64>,
+    pub quality: Option<f64>,
+    pub finalizes_at: Option<DateTime<Utc>>,
+    #[sqlx(try_from = "u8")]
+    pub visibility: Visibility,
+    pub highlights: u32,
+    pub server_name: Option<String>,
+    pub red_pot: i64,
+    pub blue_pot: i64,
+    pub under_pot: i64,
+    pub over_pot: i64,
+    /// When a wager on the match last changed.
+    pub wagered_at: Option<DateTime<Utc>>,
+}
+
+impl BattleSchema {
+    /// When the [`Battle`] built from this schema last changed.
+    ///
+    /// Betting closing and wagers changing the pots change the match without
+    /// touching `updated_at`, so these are taken into account too.
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        let updated_at = max(self.updated_at, self.wagered_at.unwrap_or(self.updated_at));
+
+        if Utc::now() >= self.closed_at {
+            max(updated_at, self.closed_at)
+        } else {
+            updated_at
+        }
+    }
 }
 
 impl From<BattleSchema> for Battle {
@@ -58,16 +118,320 @@ impl From<&BattleSchema> for Battle {
             } else {
                 None
             },
+            bets_close_at: value.closed_at,
+            concluded_at: value.concluded_at,
+            server_time: now,
+            odds_mode: value.odds_mode,
+            prediction: value
+                .red_win_probability
+                .zip(value.quality)
+                .map(|(red, quality)| BattlePrediction {
+                    red,
+                    blue: 1.0 - red,
+                    quality,
+                }),
+            finalizes_at: value.finalizes_at,
+            visibility: value.visibility,
+            highlights: HighlightTag::from_mask(value.highlights),
+            server_name: value.server_name.clone(),
+            red_pot: value.red_pot,
+            blue_pot: value.blue_pot,
+            finish_time_line: value.finish_time_line.map(|line| FinishTimeLine {
+                line,
+                under_pot: value.under_pot,
+                over_pot: value.over_pot,
+            }),
         }
     }
 }
 
+/// Fetches the schema of a match.
+pub async fn get_battle_schema(
+    battle_id: BattleId,
+    conn: &mut SqliteConnection,
+) -> Result<BattleSchema, Error> {
+    let schema = sqlx::query_as::<_, BattleSchema>(
+        r#"
+        SELECT
+            uuid, level_name, status, inserted_at, closed_at, updated_at, concluded_at,
+            odds_mode, finish_time_line,
+            red_win_probability, quality, finalizes_at, visibility,
+            highlights,
+            (SELECT s.server_name FROM server s WHERE s.id = battle.server_id) AS server_name,
+            pot.red_pot, pot.blue_pot, pot.under_pot, pot.over_pot, pot.wagered_at
+        FROM battle
+        INNER JOIN battle_pot pot ON pot.match_id = battle.id
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(schema)
+}
+
+/// Reads a participant's stats, as they are stored in the database.
+pub fn parse_participant_stats(stats: Option<&str>) -> Result<Option<ParticipantStats>, Error> {
+    stats
+        .map(serde_json::from_str::<ParticipantStats>)
+        .transpose()
+        .map_err(Error::new)
+}
+
+/// Cancels an ongoing match.
+///
+/// All participants without a finish time are set to NO CONTEST, and ratings
+/// are updated like any other cancelled match. Wagers are left alone.
+pub async fn cancel_battle<T>(
+    battle_id: BattleId,
+    model: &T,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error>
+where
+    T: Model + Debug,
+    T::Data: Debug,
+{
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"
+        UPDATE participant
+        SET no_contest = TRUE
+        WHERE finish_time IS NULL AND match_id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .execute(&mut *conn)
+    .await?;
+
+    let (closed_at,) = sqlx::query_as::<_, (DateTime<Utc>,)>(
+        r#"
+        SELECT closed_at
+        FROM battle
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE battle
+        SET status = $2, closed_at = $3, concluded_at = $4, updated_at = $4
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .bind(BattleStatus::Cancelled)
+    .bind(min(closed_at, now))
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    update_participant_ratings(battle_id, model, &mut *conn).await
+}
+
+/// Finalizes a provisional match.
+///
+/// Ratings are updated and the pots divvied up, just like a match concluded
+/// without a dispute window. Clients watching the match are updated, if it is
+/// still the room's match.
+///
+/// Returns `None` if the match isn't provisional anymore, like if it was
+/// finalized by someone else first.
+pub async fn finalize_battle<T>(
+    battle_id: BattleId,
+    model: &app::Model<T>,
+    state: &AppState,
+    conn: &mut SqliteConnection,
+) -> Result<Option<Battle>, Error>
+where
+    T: Model + Debug + 'static,
+    T::Data: Debug,
+{
+    let now = Utc::now();
+
+    // only one finalization gets to pay out
+    let finalized = sqlx::query(
+        r#"
+        UPDATE battle
+        SET status = $2, updated_at = $3
+        WHERE id = $1 AND status = $4
+        "#,
+    )
+    .bind(battle_id)
+    .bind(BattleStatus::Concluded)
+    .bind(now)
+    .bind(BattleStatus::Provisional)
+    .execute(&mut *conn)
+    .await?;
+
+    if finalized.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let mut schema = get_battle_schema(battle_id, &mut *conn).await?;
+
+    update_participant_ratings(battle_id, model, &mut *conn).await?;
+
+    let highlights =
+        detect_highlights(battle_id, &state.config.server.highlights, &mut *conn).await?;
+    schema.highlights = HighlightTag::to_mask(&highlights);
+
+    let mut battle = Battle::from(&schema);
+    preload_participants(model, &mut battle, &mut *conn).await?;
+
+    let in_room = state.room.battle(&schema.uuid).await.is_some();
+    if in_room {
+        state
+            .room
+            .update_battle(BattleData {
+                schema: schema.clone(),
+                participants: battle.participants.clone(),
+            })
+            .await;
+    }
+
+    // distribute pots!
+    calculate_winnings(battle_id, state, &mut *conn).await?;
+
+    if !highlights.is_empty() && schema.visibility.is_public() {
+        state.room.send_highlight(Highlight {
+            match_id: schema.uuid,
+            tags: highlights,
+        });
+    }
+
+    webhook::enqueue(WebhookEvent::MatchConcluded(battle.clone()), &mut *conn).await?;
+
+    Ok(Some(battle))
+}
+
+/// Finalizes every provisional match whose dispute window has run out.
+pub async fn finalize_due_battles<T>(model: &app::Model<T>, state: &AppState) -> Result<(), Error>
+where
+    T: Model + Debug + 'static,
+    T::Data: Debug,
+{
+    let due = sqlx::query_as::<_, (BattleId,)>(
+        r#"
+        SELECT id
+        FROM battle
+        WHERE status = $1 AND finalizes_at <= $2
+        "#,
+    )
+    .bind(BattleStatus::Provisional)
+    .bind(Utc::now())
+    .fetch_all(&state.db)
+    .await?;
+
+    for (battle_id,) in due {
+        let mut tx = state.db.begin().await?;
+
+        let Some(battle) = finalize_battle(battle_id, model, state, &mut tx).await? else {
+            tracing::debug!(%battle_id, "provisional match was already finalized");
+            continue;
+        };
+
+        tx.commit().await?;
+
+        tracing::info!(id = battle.id, "finalized provisional match");
+    }
+
+    Ok(())
+}
+
+/// Cancels every ongoing match that went unfinished for longer than the
+/// no-show window.
+///
+/// Wagers on them are released, and clients watching them are updated.
+pub async fn cancel_no_show_battles<T>(model: &app::Model<T>, state: &AppState) -> Result<(), Error>
+where
+    T: Model + Debug + 'static,
+    T::Data: Debug,
+{
+    let due = sqlx::query_as::<_, (BattleId,)>(
+        r#"
+        SELECT id
+        FROM battle
+        WHERE status = $1 AND inserted_at <= $2
+        "#,
+    )
+    .bind(BattleStatus::Ongoing)
+    .bind(Utc::now() - state.config.server.no_show.window)
+    .fetch_all(&state.db)
+    .await?;
+
+    for (battle_id,) in due {
+        let mut tx = state.db.begin().await?;
+
+        // the server may have finished the match in the meantime
+        let (status,) = sqlx::query_as::<_, (BattleStatus,)>(
+            r#"
+            SELECT status
+            FROM battle
+            WHERE id = $1
+            "#,
+        )
+        .bind(battle_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if status != BattleStatus::Ongoing {
+            continue;
+        }
+
+        cancel_battle(battle_id, model, &mut tx).await?;
+
+        let schema = get_battle_schema(battle_id, &mut tx).await?;
+        let mut battle = Battle::from(&schema);
+        preload_participants(model, &mut battle, &mut tx).await?;
+
+        webhook::enqueue(WebhookEvent::MatchCancelled(battle.clone()), &mut tx).await?;
+
+        tx.commit().await?;
+
+        state.deadlines.remove(battle_id);
+
+        // unlisted matches are kept out of the room
+        if state.room.battle(&schema.uuid).await.is_some() {
+            state
+                .room
+                .update_battle(BattleData {
+                    schema,
+                    participants: battle.participants,
+                })
+                .await;
+        }
+
+        tracing::info!(id = battle.id, "cancelled no-show match");
+    }
+
+    Ok(())
+}
+
 /// Update ratings of all participants in a match.
 pub async fn update_participant_ratings<T>(
-    battle_id: i32,
+    battle_id: BattleId,
     model: &T,
     conn: &mut SqliteConnection,
 ) -> Result<(), Error>
+where
+    T: Model + Debug,
+    T::Data: Debug,
+{
+    update_participant_ratings_at(battle_id, model, Utc::now(), conn).await
+}
+
+/// Updates the ratings of a match's participants at the given time.
+pub async fn update_participant_ratings_at<T>(
+    battle_id: BattleId,
+    model: &T,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error>
 where
     T: Model + Debug,
     T::Data: Debug,
@@ -98,7 +462,7 @@ where
     if ratings.len() > 1 {
         for rating in ratings {
             let rating = RatingRecord::<T::Data>::try_from(rating).map_err(Error::new)?;
-            update_rating(&rating, model, &mut *conn).await?;
+            update_rating_at(&rating, battle_id, model, now, &mut *conn).await?;
         }
     }
 
@@ -106,45 +470,60 @@ where
 }
 
 /// Closes a match, divying up the pots in each.
+///
+/// Each market is settled on its own, and is left alone if one of its pots
+/// is empty. Users are paid out once for all of their wagers on the match.
 pub async fn calculate_winnings(
-    battle_id: i32,
-    room: &Room,
+    battle_id: BattleId,
+    state: &AppState,
     conn: &mut SqliteConnection,
 ) -> Result<(), Error> {
     #[derive(FromRow)]
-    struct ParticipantQuery {
+    struct BattleQuery {
+        uuid: String,
         #[sqlx(try_from = "u8")]
+        odds_mode: OddsMode,
+        visibility: u8,
+        closed_at: DateTime<Utc>,
+        finish_time_line: Option<i32>,
+    }
+
+    #[derive(FromRow)]
+    struct ParticipantQuery {
         team: PlayerTeam,
+        finish_time: Option<i32>,
     }
 
     #[derive(FromRow)]
     struct WagerQuery {
-        user_id: i32,
-        #[sqlx(try_from = "u8")]
-        victor: PlayerTeam,
+        id: i32,
+        user_id: UserId,
+        market: WagerMarket,
+        victor: u8,
         mobiums: i64,
+        odds: Option<f64>,
         user_mobiums: i64,
+        user_streak: i32,
         #[sqlx(try_from = "i32")]
         user_flags: UserFlags,
     }
 
-    // To figure out how much money we owe to each player, we first need to
-    // figure out the total sum of each pot alone
-
-    let red_pot = get_total_pot(battle_id, PlayerTeam::Red, &mut *conn).await?;
-    let blue_pot = get_total_pot(battle_id, PlayerTeam::Blue, &mut *conn).await?;
-
-    // If a pot has 0 mobiums to its name, nullify the wagers
-    if red_pot <= 0 || blue_pot <= 0 {
-        return Ok(());
-    }
-
-    let total_winnings = red_pot + blue_pot;
+    let battle = sqlx::query_as::<_, BattleQuery>(
+        r#"
+        SELECT uuid, odds_mode, visibility, closed_at, finish_time_line
+        FROM battle
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    let match_id = battle.uuid;
 
     // We need to figure out who won first
     let winner = sqlx::query_as::<_, ParticipantQuery>(
         r#"
-        SELECT team
+        SELECT team, finish_time
         FROM participant
         WHERE
             match_id = $1
@@ -162,12 +541,53 @@ pub async fn calculate_winnings(
         return Ok(());
     };
 
+    // To figure out how much money we owe to each player, we first need to
+    // figure out the total sum of each pot alone
+
+    let pots = Pots {
+        red: get_total_pot(battle_id, PlayerTeam::Red, &mut *conn).await?,
+        blue: get_total_pot(battle_id, PlayerTeam::Blue, &mut *conn).await?,
+    };
+
+    // If a pot has 0 mobiums to its name, nullify the wagers
+    let winner_settles = pots.red > 0 && pots.blue > 0;
+
+    // The line pushes if the winner finished right on it
+    let mut line = None;
+    if let Some(line_at) = battle.finish_time_line
+        && let Some(finish_time) = winner.finish_time
+        && let Some(side) = LineSide::of(finish_time, line_at)
+    {
+        let pots = FinishTimeLine {
+            line: line_at,
+            under_pot: get_line_pot(battle_id, LineSide::Under, &mut *conn).await?,
+            over_pot: get_line_pot(battle_id, LineSide::Over, &mut *conn).await?,
+        };
+
+        if pots.under_pot > 0 && pots.over_pot > 0 {
+            line = Some(LineOutcome {
+                pots,
+                finish_time,
+                side,
+            });
+        }
+    }
+
+    if !winner_settles && line.is_none() {
+        return Ok(());
+    }
+
+    let strategy = payout::strategy(battle.odds_mode);
+
+    // Events boost matches whose bets closed while they ran
+    let event_multiplier = event::payout_multiplier(battle.closed_at, &mut *conn).await?;
+
     // Go over all wagers to see what players are entitled to what
     let wagers = sqlx::query_as::<_, WagerQuery>(
         r#"
         SELECT
-            w.user_id, w.victor, w.mobiums,
-            u.mobiums AS user_mobiums, u.flags AS user_flags
+            w.id, w.user_id, w.market, w.victor, w.mobiums, w.odds,
+            u.mobiums AS user_mobiums, u.streak AS user_streak, u.flags AS user_flags
         FROM
             wager w, user u
         WHERE
@@ -179,6 +599,34 @@ pub async fn calculate_winnings(
     .fetch_all(&mut *conn)
     .await?;
 
+    // Winnings can't add up to more than the losers put up
+    let promised = wagers
+        .iter()
+        .filter(|wager| {
+            winner_settles
+                && wager.market == WagerMarket::Winner
+                && wager.mobiums > 0
+                && wager.victor == u8::from(winner.team)
+        })
+        .map(|wager| {
+            let stake = Stake {
+                victor: winner.team,
+                mobiums: wager.mobiums,
+                odds: wager.odds,
+            };
+            strategy.payout(&stake, &pots) - wager.mobiums
+        })
+        .sum::<i64>();
+    let losing_pot = pots.total() - pots.get(winner.team);
+
+    let mut winners = 0;
+    let mut line_winners = 0;
+    // (user_id, mobiums) of the biggest payout
+    let mut biggest_payout = None::<(UserId, i64)>;
+
+    let mut settlements = Vec::<Settlement>::with_capacity(wagers.len());
+    let mut settlement_of = HashMap::<UserId, usize>::new();
+
     for wager in wagers {
         // Skip empty wagers
         // Wagers can't be deleted, just set to zero
@@ -186,26 +634,114 @@ pub async fn calculate_winnings(
             continue;
         }
 
-        // Did this user win or lose money?
-        let mobiums_change = if wager.victor == winner.team {
-            // They won! Give them some of the winnings
-            let pot = if wager.victor == PlayerTeam::Red {
-                red_pot
-            } else {
-                blue_pot
-            };
-            let pie_slice = total_winnings * wager.mobiums / pot;
-            // Do not re-award them the money they put on the bet
-            pie_slice - wager.mobiums
+        // Users on a streak get a bigger slice, but not the bot
+        let automated = wager.user_flags.contains(UserFlags::AUTOMATED_USER);
+        let multiplier = if automated {
+            1.0
         } else {
-            // They lost... STEAL their money.
-            -wager.mobiums
+            state.config.server.streaks.multiplier(wager.user_streak)
+        };
+        let promo_multiplier = if automated { 1.0 } else { event_multiplier };
+
+        // Did this user win or lose money?
+        let (mobiums_change, streak) = match wager.market {
+            WagerMarket::Winner if winner_settles => {
+                let victor = PlayerTeam::try_from(wager.victor).map_err(Error::new)?;
+                let mut streak_bonus = 0;
+
+                let mobiums_change = if victor == winner.team {
+                    // They won! Give them some of the winnings
+                    let stake = Stake {
+                        victor,
+                        mobiums: wager.mobiums,
+                        odds: wager.odds,
+                    };
+                    let pie_slice = strategy.payout(&stake, &pots);
+                    // Do not re-award them the money they put on the bet
+                    let winnings =
+                        payout::cap_winnings(pie_slice - wager.mobiums, promised, losing_pot);
+                    streak_bonus = (winnings as f64 * (multiplier - 1.0)).floor() as i64;
+                    let promo_bonus = (winnings as f64 * (promo_multiplier - 1.0)).floor() as i64;
+                    winnings + streak_bonus + promo_bonus
+                } else {
+                    // They lost... STEAL their money.
+                    -wager.mobiums
+                };
+
+                // Small wins keep the streak going, but don't grow it
+                let streak = if victor != winner.team {
+                    0
+                } else if state
+                    .config
+                    .server
+                    .streaks
+                    .counts(wager.mobiums, wager.user_mobiums)
+                {
+                    wager.user_streak + 1
+                } else {
+                    wager.user_streak
+                };
+
+                if victor == winner.team {
+                    winners += 1;
+
+                    if biggest_payout.is_none_or(|(_, mobiums)| mobiums_change > mobiums) {
+                        biggest_payout = Some((wager.user_id, mobiums_change));
+                    }
+                }
+
+                let streak = StreakChange {
+                    streak,
+                    multiplier: if streak_bonus > 0 { multiplier } else { 1.0 },
+                    bonus: streak_bonus,
+                };
+
+                (mobiums_change, Some(streak))
+            }
+            // Lines don't count towards streaks
+            WagerMarket::FinishTime if let Some(line) = &line => {
+                let side = LineSide::try_from(wager.victor).map_err(Error::new)?;
+
+                let mobiums_change = if side == line.side {
+                    let pot = match side {
+                        LineSide::Under => line.pots.under_pot,
+                        LineSide::Over => line.pots.over_pot,
+                    };
+                    let total = line.pots.under_pot + line.pots.over_pot;
+                    let winnings = Parimutuel::split(wager.mobiums, pot, total) - wager.mobiums;
+                    let promo_bonus = (winnings as f64 * (promo_multiplier - 1.0)).floor() as i64;
+                    line_winners += 1;
+                    winnings + promo_bonus
+                } else {
+                    -wager.mobiums
+                };
+
+                (mobiums_change, None)
+            }
+            // The market isn't being settled
+            _ => continue,
         };
 
-        let mut new_mobiums = wager.user_mobiums + mobiums_change;
+        let index = *settlement_of.entry(wager.user_id).or_insert_with(|| {
+            settlements.push(Settlement {
+                user_id: wager.user_id,
+                wagers: Vec::new(),
+                payout: 0,
+                change: MobiumsChange {
+                    mobiums: wager.user_mobiums,
+                    bailout: false,
+                    streak: None,
+                },
+            });
+            settlements.len() - 1
+        });
+        let settlement = &mut settlements[index];
 
-        let mobiums_gained = max(0, mobiums_change);
-        let mobiums_lost = min(0, mobiums_change) * -1;
+        settlement.wagers.push((wager.id, mobiums_change));
+        settlement.payout += mobiums_change;
+        settlement.change.streak = settlement.change.streak.take().or(streak);
+
+        let mut new_mobiums = wager.user_mobiums + settlement.payout;
 
         // Do bailouts if user does not have infinite funds
         let mut bailout = false;
@@ -213,63 +749,512 @@ pub async fn calculate_winnings(
             // GG bro...
             if new_mobiums <= 0 {
                 bailout = true;
-                new_mobiums = 100; // TODO: magic number?
+                new_mobiums = BAILOUT_MOBIUMS;
             }
         }
 
-        // Update database record
-        sqlx::query(
-            r#"
-            UPDATE user
-            SET
-                mobiums = $1,
-                bailout_count = bailout_count + $2,
-                mobiums_gained = mobiums_gained + $3,
-                mobiums_lost = mobiums_lost + $4
-            WHERE
-                id = $5
-            "#,
-        )
-        .bind(new_mobiums)
-        .bind(if bailout { 1 } else { 0 })
-        .bind(mobiums_gained)
-        .bind(mobiums_lost)
-        .bind(wager.user_id)
-        .execute(&mut *conn)
-        .await?;
+        settlement.change.mobiums = new_mobiums;
+        settlement.change.bailout = bailout;
+    }
 
-        // Send mobiums change to player
-        room.send_mobiums_change(
-            wager.user_id,
-            MobiumsChange {
-                mobiums: new_mobiums,
-                bailout,
-            },
+    let cooldown = &state.config.server.bailout_cooldown;
+    let cooldown_until = cooldown.enabled.then(|| Utc::now() + cooldown.duration);
+
+    let public = Visibility::try_from(battle.visibility).is_ok_and(Visibility::is_public);
+    let total = settlements.len() as i32;
+    let mut settled = 0;
+
+    for batch in settlements.chunks(SETTLEMENT_BATCH_SIZE) {
+        write_settlements(battle_id, batch, cooldown_until, &mut *conn).await?;
+
+        // Send mobiums changes to players
+        state.room.send_mobiums_changes(
+            batch
+                .iter()
+                .map(|settlement| (settlement.user_id, settlement.change.clone()))
+                .collect(),
         );
+
+        // Let wagerers who aren't watching know how it went
+        if state.config.push.is_some() {
+            let notifications = batch
+                .iter()
+                .map(|settlement| {
+                    let notification = if settlement.change.bailout {
+                        PushNotification::Bailout {
+                            match_id: match_id.clone(),
+                            mobiums: settlement.change.mobiums,
+                        }
+                    } else {
+                        PushNotification::MatchConcluded {
+                            match_id: match_id.clone(),
+                            payout: settlement.payout,
+                            mobiums: settlement.change.mobiums,
+                        }
+                    };
+                    (settlement.user_id, notification)
+                })
+                .collect::<Vec<_>>();
+            push::enqueue(&notifications, &mut *conn).await?;
+        }
+
+        settled += batch.len() as i32;
+
+        // only bother with progress if there is more than one batch
+        if public && settlements.len() > SETTLEMENT_BATCH_SIZE {
+            state.room.send_settlement_progress(SettlementProgress {
+                match_id: match_id.clone(),
+                settled,
+                total,
+            });
+        }
+    }
+
+    // unlisted matches are kept out of the room
+    if public && let Some(line) = line {
+        state.room.send_line_settled(LineSettled {
+            match_id: match_id.clone(),
+            line: line.pots.line,
+            finish_time: line.finish_time,
+            side: line.side,
+            total_pot: line.pots.under_pot + line.pots.over_pot,
+            winners: line_winners,
+        });
+    }
+
+    if !winner_settles {
+        return Ok(());
+    }
+
+    // Let everyone know how it went
+    let biggest_payout = match biggest_payout {
+        Some((user_id, mobiums)) => {
+            let user = if state.config.server.anonymous_payouts {
+                None
+            } else {
+                sqlx::query_as::<_, UserSchema>(
+                    r#"
+                    SELECT
+                        id, username, avatar, display_name, mobiums, mobiums_gained,
+                        mobiums_lost, streak, flags
+                    FROM user
+                    WHERE id = $1 AND show_wagers_publicly
+                    "#,
+                )
+                .bind(user_id)
+                .fetch_optional(&mut *conn)
+                .await?
+                .map(|user| {
+                    let mut user = User::from(user);
+                    user.avatar = state.avatar_url(&user.username, user.avatar.take());
+                    user
+                })
+            };
+
+            Some(Payout { user, mobiums })
+        }
+        None => None,
+    };
+
+    // unlisted matches are kept out of the room
+    if public {
+        state.room.send_battle_settled(BattleSettled {
+            match_id,
+            victor: winner.team,
+            total_pot: pots.total(),
+            winners,
+            biggest_payout,
+        });
     }
 
     // All the dirty work has been done
     Ok(())
 }
 
+/// How the finish time line of a match was settled.
+#[derive(Clone, Copy, Debug)]
+struct LineOutcome {
+    /// The line and its final pots.
+    pots: FinishTimeLine,
+    /// The winner's finish time.
+    finish_time: i32,
+    /// The side of the line the winner finished on.
+    side: LineSide,
+}
+
+/// A user being paid out.
+#[derive(Clone, Debug)]
+struct Settlement {
+    user_id: UserId,
+    /// How many mobiums each of the user's wagers won or lost, by wager id.
+    wagers: Vec<(i32, i64)>,
+    /// How many mobiums the user's wagers won or lost together.
+    payout: i64,
+    /// The user's balance and streak after the payout.
+    change: MobiumsChange,
+}
+
+/// Writes a batch of payouts for a match.
+///
+/// Every user in the batch is updated once, however many wagers they had on
+/// the match.
+async fn write_settlements(
+    battle_id: BattleId,
+    batch: &[Settlement],
+    cooldown_until: Option<DateTime<Utc>>,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(
+        "WITH settled (id, mobiums, bailout, gained, lost, streak) AS (",
+    );
+    query.push_values(batch, |mut row, settlement| {
+        row.push_bind(settlement.user_id)
+            .push_bind(settlement.change.mobiums)
+            .push_bind(i32::from(settlement.change.bailout))
+            .push_bind(max(0, settlement.payout))
+            .push_bind(max(0, -settlement.payout))
+            .push_bind(settlement.change.streak.as_ref().map(|s| s.streak));
+    });
+    query.push(
+        r#"
+        )
+        UPDATE user
+        SET
+            mobiums = settled.mobiums,
+            bailout_count = bailout_count + settled.bailout,
+            mobiums_gained = mobiums_gained + settled.gained,
+            mobiums_lost = mobiums_lost + settled.lost,
+            streak = IFNULL(settled.streak, user.streak)
+        FROM settled
+        WHERE user.id = settled.id
+        "#,
+    );
+    query.build().execute(&mut *conn).await?;
+
+    let wagers = batch
+        .iter()
+        .flat_map(|settlement| settlement.wagers.iter())
+        .collect::<Vec<_>>();
+
+    let mut query = QueryBuilder::<Sqlite>::new("WITH settled (id, payout) AS (");
+    query.push_values(wagers, |mut row, (wager_id, payout)| {
+        row.push_bind(*wager_id).push_bind(*payout);
+    });
+    query.push(
+        r#"
+        )
+        UPDATE wager
+        SET payout = settled.payout
+        FROM settled
+        WHERE wager.id = settled.id
+        "#,
+    );
+    query.build().execute(&mut *conn).await?;
+
+    let bailouts = batch
+        .iter()
+        .filter(|settlement| settlement.change.bailout)
+        .collect::<Vec<_>>();
+
+    if !bailouts.is_empty() {
+        let now = Utc::now();
+
+        // bailed out users can't wager it all again right away
+        if let Some(cooldown_until) = cooldown_until {
+            let mut query =
+                QueryBuilder::<Sqlite>::new("UPDATE user SET bailout_cooldown_until = ");
+            query.push_bind(cooldown_until).push(" WHERE id IN (");
+            let mut ids = query.separated(", ");
+            for settlement in bailouts.iter() {
+                ids.push_bind(settlement.user_id);
+            }
+            ids.push_unseparated(")");
+            query.build().execute(&mut *conn).await?;
+        }
+
+        let mut query =
+            QueryBuilder::<Sqlite>::new("INSERT INTO bailout (user_id, match_id, inserted_at) ");
+        query.push_values(bailouts, |mut row, settlement| {
+            row.push_bind(settlement.user_id)
+                .push_bind(battle_id)
+                .push_bind(now);
+        });
+        query.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Locks in the odds of a user's wager on a match with
+/// [`OddsMode::Locked`].
+///
+/// The odds are what the pots imply right now, including the wager itself.
+/// Returns the locked odds.
+pub async fn lock_odds(
+    battle_id: BattleId,
+    user_id: UserId,
+    victor: PlayerTeam,
+    conn: &mut SqliteConnection,
+) -> Result<f64, Error> {
+    let red_pot = get_total_pot(battle_id, PlayerTeam::Red, &mut *conn).await?;
+    let blue_pot = get_total_pot(battle_id, PlayerTeam::Blue, &mut *conn).await?;
+
+    let pot = if victor == PlayerTeam::Red {
+        red_pot
+    } else {
+        blue_pot
+    };
+    let odds = if pot > 0 {
+        (red_pot + blue_pot) as f64 / pot as f64
+    } else {
+        1.0
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE wager
+        SET odds = $3
+        WHERE user_id = $1 AND match_id = $2 AND market = $4
+        "#,
+    )
+    .bind(user_id)
+    .bind(battle_id)
+    .bind(odds)
+    .bind(WagerMarket::Winner)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(odds)
+}
+
+/// Records the current pots of a match in its pot history.
+///
+/// Run this in the same transaction as the wager change.
+pub async fn snapshot_pot(battle_id: BattleId, conn: &mut SqliteConnection) -> Result<(), Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO pot_snapshot (match_id, red, blue, wagers, inserted_at)
+        SELECT
+            $1,
+            IFNULL(SUM(CASE WHEN w.victor = $2 THEN w.mobiums END), 0),
+            IFNULL(SUM(CASE WHEN w.victor = $3 THEN w.mobiums END), 0),
+            COUNT(w.id),
+            $4
+        FROM wager w
+        WHERE w.match_id = $1 AND w.market = $5 AND w.mobiums > 0
+        "#,
+    )
+    .bind(battle_id)
+    .bind(PlayerTeam::Red)
+    .bind(PlayerTeam::Blue)
+    .bind(Utc::now())
+    .bind(WagerMarket::Winner)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
 async fn get_total_pot(
-    battle_id: i32,
+    battle_id: BattleId,
     team: PlayerTeam,
     conn: &mut SqliteConnection,
 ) -> Result<i64, Error> {
-    sqlx::query_as::<_, (i64,)>(
+    sqlx::query_as::<_, (Option<i64>,)>(
         r#"
         SELECT SUM(w.mobiums)
         FROM wager w
         WHERE
             match_id = $1
-            AND w.victor = $2
+            AND w.market = $2
+            AND w.victor = $3
         "#,
     )
     .bind(battle_id)
-    .bind(u8::from(team))
+    .bind(WagerMarket::Winner)
+    .bind(team)
     .fetch_one(&mut *conn)
     .await
-    .map(|(mobiums,)| mobiums)
+    .map(|(mobiums,)| mobiums.unwrap_or(0))
     .map_err(Error::from)
 }
+
+/// Gets the total pot on one side of a match's finish time line.
+async fn get_line_pot(
+    battle_id: BattleId,
+    side: LineSide,
+    conn: &mut SqliteConnection,
+) -> Result<i64, Error> {
+    sqlx::query_as::<_, (Option<i64>,)>(
+        r#"
+        SELECT SUM(w.mobiums)
+        FROM wager w
+        WHERE
+            match_id = $1
+            AND w.market = $2
+            AND w.victor = $3
+        "#,
+    )
+    .bind(battle_id)
+    .bind(WagerMarket::FinishTime)
+    .bind(side)
+    .fetch_one(&mut *conn)
+    .await
+    .map(|(mobiums,)| mobiums.unwrap_or(0))
+    .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        player::mmr::glicko2::{Glicko2, Glicko2Config},
+        testing,
+    };
+
+    #[tokio::test]
+    async fn test_finalize_battle_pays_out_once() {
+        let state = testing::state().await;
+        let model = app::Model::new(Glicko2::new(Glicko2Config::default()));
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let red = testing::create_user("red", 400, &mut conn).await;
+        let blue = testing::create_user("blue", 400, &mut conn).await;
+        let battle_id = testing::create_battle(BattleStatus::Provisional, &mut conn).await;
+        testing::place(red, battle_id, WagerMarket::Winner, 0, 100, &mut conn).await;
+        testing::place(blue, battle_id, WagerMarket::Winner, 1, 100, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Red, 3000, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Blue, 3100, &mut conn).await;
+
+        let finalized = finalize_battle(battle_id, &model, &state, &mut conn)
+            .await
+            .unwrap();
+        assert!(finalized.is_some());
+        assert_eq!(testing::mobiums(red, &mut conn).await, 500);
+        assert_eq!(testing::mobiums(blue, &mut conn).await, 300);
+
+        // a second finalization finds the match already concluded
+        let finalized = finalize_battle(battle_id, &model, &state, &mut conn)
+            .await
+            .unwrap();
+        assert!(finalized.is_none());
+        assert_eq!(testing::mobiums(red, &mut conn).await, 500);
+        assert_eq!(testing::mobiums(blue, &mut conn).await, 300);
+    }
+
+    /// Settles a match with only line wagers, where red wins in 3000 with a
+    /// line at `line`, returning the mobiums of each user after.
+    async fn settle_line(line: i32, wagers: &[(LineSide, i64)]) -> Vec<i64> {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let battle_id = testing::create_battle(BattleStatus::Concluded, &mut conn).await;
+        testing::set_line(battle_id, line, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Red, 3000, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Blue, 3100, &mut conn).await;
+
+        let mut users = Vec::new();
+        for (i, (side, mobiums)) in wagers.iter().enumerate() {
+            let user_id = testing::create_user(&format!("user{}", i), 400, &mut conn).await;
+            testing::place(
+                user_id,
+                battle_id,
+                WagerMarket::FinishTime,
+                *side as u8,
+                *mobiums,
+                &mut conn,
+            )
+            .await;
+            users.push(user_id);
+        }
+
+        calculate_winnings(battle_id, &state, &mut conn)
+            .await
+            .unwrap();
+
+        let mut balances = Vec::new();
+        for user_id in users {
+            balances.push(testing::mobiums(user_id, &mut conn).await);
+        }
+        balances
+    }
+
+    #[tokio::test]
+    async fn test_small_wins_do_not_grow_streaks() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let small = testing::create_user("small", 1000, &mut conn).await;
+        let big = testing::create_user("big", 400, &mut conn).await;
+        let loser = testing::create_user("loser", 400, &mut conn).await;
+        let battle_id = testing::create_battle(BattleStatus::Concluded, &mut conn).await;
+        testing::place(small, battle_id, WagerMarket::Winner, 0, 10, &mut conn).await;
+        testing::place(big, battle_id, WagerMarket::Winner, 0, 100, &mut conn).await;
+        testing::place(loser, battle_id, WagerMarket::Winner, 1, 110, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Red, 3000, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Blue, 3100, &mut conn).await;
+
+        calculate_winnings(battle_id, &state, &mut conn)
+            .await
+            .unwrap();
+
+        let streaks = sqlx::query_as::<_, (i32,)>("SELECT streak FROM user ORDER BY id")
+            .fetch_all(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(streaks, [(0,), (1,), (0,)]);
+    }
+
+    #[tokio::test]
+    async fn test_locked_odds_pay_at_most_losing_pot() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let red = testing::create_user("red", 400, &mut conn).await;
+        let blue = testing::create_user("blue", 400, &mut conn).await;
+        let battle_id = testing::create_battle(BattleStatus::Concluded, &mut conn).await;
+        testing::place(red, battle_id, WagerMarket::Winner, 0, 100, &mut conn).await;
+        testing::place(blue, battle_id, WagerMarket::Winner, 1, 100, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Red, 3000, &mut conn).await;
+        testing::finish(battle_id, PlayerTeam::Blue, 3100, &mut conn).await;
+
+        // odds locked in against a blue pot that was since taken back
+        sqlx::query("UPDATE battle SET odds_mode = $2 WHERE id = $1")
+            .bind(battle_id)
+            .bind(u8::from(OddsMode::Locked))
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE wager SET odds = 5.0 WHERE user_id = $1")
+            .bind(red)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        calculate_winnings(battle_id, &state, &mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(testing::mobiums(red, &mut conn).await, 500);
+        assert_eq!(testing::mobiums(blue, &mut conn).await, 300);
+    }
+
+    #[tokio::test]
+    async fn test_line_pays_out_winning_side() {
+        let balances = settle_line(3050, &[(LineSide::Under, 100), (LineSide::Over, 300)]).await;
+        assert_eq!(balances, [700, 100]);
+    }
+
+    #[tokio::test]
+    async fn test_line_pushes_on_the_line() {
+        let balances = settle_line(3000, &[(LineSide::Under, 100), (LineSide::Over, 300)]).await;
+        assert_eq!(balances, [400, 400]);
+    }
+
+    #[tokio::test]
+    async fn test_line_is_left_alone_with_one_sided_pot() {
+        let balances = settle_line(3050, &[(LineSide::Under, 100), (LineSide::Under, 300)]).await;
+        assert_eq!(balances, [400, 400]);
+    }
+}
@@ -0,0 +1,244 @@
+//! Odds calibration rollup.
+//!
+//! Pre-match odds are compared against how matches actually turned out, so
+//! the odds from ratings and pots can be checked ("70% favorites won 68% of
+//! the time"). Like [`stats`](crate::stats), aggregates are rolled up into
+//! `calibration_daily` once a day.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+use ring_channel_model::{
+    battle::{BattleStatus, PlayerTeam},
+    stats::OddsSource,
+};
+
+use sqlx::{Connection as _, FromRow, SqliteConnection};
+
+use crate::error::Error;
+
+/// How many buckets the favorite's win probability is split into, from 0% to
+/// 100%.
+///
+/// Only the upper half is ever used, since the favorite is always at least
+/// even.
+pub const BUCKETS: u8 = 10;
+
+/// The running totals of a single bucket.
+#[derive(Clone, Debug, Default)]
+struct Tally {
+    matches: i64,
+    favorite_wins: i64,
+    probability_sum: f64,
+    squared_error_sum: f64,
+}
+
+/// Rolls up all finished days that haven't been rolled up yet.
+///
+/// The most recently rolled up day is always recomputed, in case it was
+/// rolled up before it was over.
+pub async fn rollup_calibration(conn: &mut SqliteConnection) -> Result<(), Error> {
+    let (last_day,) = sqlx::query_as::<_, (Option<NaiveDate>,)>(
+        r#"
+        SELECT MAX(day)
+        FROM calibration_daily
+        "#,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let first_day = match last_day {
+        Some(last_day) => last_day,
+        None => {
+            let (first_battle,) = sqlx::query_as::<_, (Option<DateTime<Utc>>,)>(
+                r#"
+                SELECT MIN(concluded_at)
+                FROM battle
+                "#,
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+
+            match first_battle {
+                Some(first_battle) => first_battle.date_naive(),
+                // nothing to roll up
+                None => return Ok(()),
+            }
+        }
+    };
+
+    let today = Utc::now().date_naive();
+
+    for day in first_day.iter_days().take_while(|day| *day < today) {
+        rollup_day(day, &mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Rolls up a single day.
+pub async fn rollup_day(day: NaiveDate, conn: &mut SqliteConnection) -> Result<(), Error> {
+    #[derive(FromRow)]
+    struct BattleQuery {
+        red_win_probability: Option<f64>,
+        red_pot: i64,
+        blue_pot: i64,
+        winner: Option<PlayerTeam>,
+    }
+
+    let now = Utc::now();
+
+    let start = day.and_time(Default::default()).and_utc();
+    let end = start + TimeDelta::days(1);
+
+    let battles = sqlx::query_as::<_, BattleQuery>(
+        r#"
+        SELECT
+            b.red_win_probability,
+            IFNULL((
+                SELECT SUM(w.mobiums)
+                FROM wager w
+                WHERE w.match_id = b.id AND w.market = 0 AND w.victor = $4 AND w.mobiums > 0
+            ), 0) AS red_pot,
+            IFNULL((
+                SELECT SUM(w.mobiums)
+                FROM wager w
+                WHERE w.match_id = b.id AND w.market = 0 AND w.victor = $5 AND w.mobiums > 0
+            ), 0) AS blue_pot,
+            (
+                SELECT p.team
+                FROM participant p
+                WHERE p.match_id = b.id AND NOT p.no_contest
+                ORDER BY p.finish_time ASC
+                LIMIT 1
+            ) AS winner
+        FROM battle b
+        WHERE
+            b.status = $1
+            AND b.concluded_at >= $2
+            AND b.concluded_at < $3
+        "#,
+    )
+    .bind(BattleStatus::Concluded)
+    .bind(start)
+    .bind(end)
+    .bind(PlayerTeam::Red)
+    .bind(PlayerTeam::Blue)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut tallies = HashMap::<(OddsSource, u8), Tally>::new();
+    let mut tally = |source: OddsSource, red: f64, winner: PlayerTeam| {
+        let Some((probability, favorite_won)) = favorite(red, winner) else {
+            return;
+        };
+
+        let outcome = if favorite_won { 1.0 } else { 0.0 };
+        let tally = tallies.entry((source, bucket(probability))).or_default();
+        tally.matches += 1;
+        tally.favorite_wins += favorite_won as i64;
+        tally.probability_sum += probability;
+        tally.squared_error_sum += (probability - outcome).powi(2);
+    };
+
+    for battle in battles {
+        // no one finished, so there's nothing to check against
+        let Some(winner) = battle.winner else {
+            continue;
+        };
+
+        if let Some(red_win_probability) = battle.red_win_probability {
+            tally(OddsSource::Ratings, red_win_probability, winner);
+        }
+
+        // the pots only imply odds if both teams were bet on
+        if battle.red_pot > 0 && battle.blue_pot > 0 {
+            let red = battle.red_pot as f64 / (battle.red_pot + battle.blue_pot) as f64;
+            tally(OddsSource::Pots, red, winner);
+        }
+    }
+
+    let mut tx = conn.begin().await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM calibration_daily
+        WHERE day = $1
+        "#,
+    )
+    .bind(day)
+    .execute(&mut *tx)
+    .await?;
+
+    for ((source, bucket), tally) in tallies {
+        sqlx::query(
+            r#"
+            INSERT INTO calibration_daily
+                (
+                    day, source, bucket, matches, favorite_wins,
+                    probability_sum, squared_error_sum, inserted_at, updated_at
+                )
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7, $8, $8)
+            "#,
+        )
+        .bind(day)
+        .bind(u8::from(source))
+        .bind(bucket)
+        .bind(tally.matches)
+        .bind(tally.favorite_wins)
+        .bind(tally.probability_sum)
+        .bind(tally.squared_error_sum)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    tracing::debug!(%day, "rolled up odds calibration");
+
+    Ok(())
+}
+
+/// Finds the favorite of a match from the red team's chance to win.
+///
+/// Returns the favorite's chance to win and whether they did, or `None` if
+/// the match was a coin flip.
+pub fn favorite(red: f64, winner: PlayerTeam) -> Option<(f64, bool)> {
+    if red > 0.5 {
+        Some((red, winner == PlayerTeam::Red))
+    } else if red < 0.5 {
+        Some((1.0 - red, winner == PlayerTeam::Blue))
+    } else {
+        None
+    }
+}
+
+/// The bucket a win probability falls in.
+pub fn bucket(probability: f64) -> u8 {
+    ((probability * BUCKETS as f64).floor() as u8).min(BUCKETS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_favorite() {
+        assert_eq!(favorite(0.7, PlayerTeam::Red), Some((0.7, true)));
+        assert_eq!(favorite(0.7, PlayerTeam::Blue), Some((0.7, false)));
+        assert_eq!(favorite(0.25, PlayerTeam::Blue), Some((0.75, true)));
+        assert_eq!(favorite(0.5, PlayerTeam::Red), None);
+    }
+
+    #[test]
+    fn test_bucket() {
+        assert_eq!(bucket(0.5), 5);
+        assert_eq!(bucket(0.69), 6);
+        assert_eq!(bucket(0.7), 7);
+        assert_eq!(bucket(0.99), 9);
+        assert_eq!(bucket(1.0), 9);
+    }
+}
@@ -1,16 +1,31 @@
 //! Ring Channel server command-line interface.
 
-use std::path::PathBuf;
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+};
 
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
-use eyre::Error;
+use eyre::{Error, eyre};
 
-use sqlx::SqliteConnection;
+use ring_channel_model::{admin, user::UserFlags};
 
-use crate::auth::api_key::{generate_api_key, hash_api_key};
+use sqlx::{Connection as _, SqliteConnection};
+
+use crate::{
+    auth::api_key::{generate_api_key, hash_api_key},
+    config::RetentionConfig,
+    economy,
+    health::MIGRATOR,
+    player::mmr::{Model, glicko2::Glicko2Config, openskill::OpenSkillConfig, simulate},
+    retention,
+    seed::{self, SeedOptions},
+};
 
 /// The command line arguments.
 #[derive(Parser, Debug)]
@@ -31,8 +46,18 @@ pub enum Command {
     RegisterServer(RegisterServer),
     #[command(name = "generate-key")]
     GenerateKey(GenerateKey),
+    #[command(name = "generate-vapid-keys")]
+    GenerateVapidKeys(GenerateVapidKeys),
     #[command(name = "mmr")]
     Mmr(Mmr),
+    #[command(name = "admin")]
+    Admin(Admin),
+    #[command(name = "economy")]
+    Economy(Economy),
+    #[command(name = "cleanup")]
+    Cleanup(Cleanup),
+    #[command(name = "seed")]
+    Seed(Seed),
 }
 
 /// Registers a server with the ring channel API.
@@ -48,6 +73,10 @@ pub struct RegisterServer {
 #[derive(clap::Args, Debug)]
 pub struct GenerateKey;
 
+/// Generates a VAPID key pair to sign push notifications with.
+#[derive(clap::Args, Debug)]
+pub struct GenerateVapidKeys;
+
 /// Does some Mmr things.
 #[derive(clap::Args, Debug)]
 pub struct Mmr {
@@ -62,6 +91,8 @@ pub enum MmrCommand {
     Reset(MmrReset),
     #[command(name = "dump")]
     Dump(MmrDump),
+    #[command(name = "simulate")]
+    Simulate(MmrSimulate),
 }
 
 /// Sample's a given player's MMR.
@@ -76,6 +107,161 @@ pub struct MmrDump {
 #[derive(clap::Args, Debug)]
 pub struct MmrReset;
 
+/// Replays every match under a changed rating config.
+///
+/// Ratings are rebuilt from scratch under both the current config and the
+/// changed one, and compared by how well they predicted each match. Nothing
+/// is saved.
+#[derive(clap::Args, Debug)]
+pub struct MmrSimulate {
+    /// The length of a rating period, like `12h`.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub period: Option<std::time::Duration>,
+    /// The tau of the rating model.
+    #[arg(long)]
+    pub tau: Option<f32>,
+    /// The rating new players start at.
+    #[arg(long)]
+    pub rating: Option<f32>,
+    /// The deviation new players start at.
+    #[arg(long)]
+    pub deviation: Option<f32>,
+    /// The volatility new players start at, for Glicko-2.
+    #[arg(long)]
+    pub volatility: Option<f32>,
+    /// Write every player's MMR after each match to a CSV file.
+    #[arg(short, long)]
+    pub trajectories: Option<PathBuf>,
+}
+
+impl MmrSimulate {
+    /// Applies the changes to a Glicko-2 config.
+    pub fn glicko2(&self, mut config: Glicko2Config) -> Result<Glicko2Config, Error> {
+        if let Some(period) = self.period {
+            config.period = TimeDelta::from_std(period)?;
+        }
+        if let Some(tau) = self.tau {
+            config.tau = tau;
+        }
+        if let Some(rating) = self.rating {
+            config.defaults.rating = rating;
+        }
+        if let Some(deviation) = self.deviation {
+            config.defaults.deviation = deviation;
+        }
+        if let Some(volatility) = self.volatility {
+            config.defaults.volatility = volatility;
+        }
+
+        Ok(config)
+    }
+
+    /// Applies the changes to an OpenSkill config.
+    pub fn openskill(&self, mut config: OpenSkillConfig) -> Result<OpenSkillConfig, Error> {
+        if self.volatility.is_some() {
+            return Err(eyre!("openskill ratings have no volatility"));
+        }
+
+        if let Some(period) = self.period {
+            config.period = TimeDelta::from_std(period)?;
+        }
+        if let Some(tau) = self.tau {
+            config.tau = tau;
+        }
+        if let Some(rating) = self.rating {
+            config.defaults.rating = rating;
+        }
+        if let Some(deviation) = self.deviation {
+            config.defaults.deviation = deviation;
+        }
+
+        Ok(config)
+    }
+}
+
+/// Grants a user administrator access.
+#[derive(clap::Args, Debug)]
+pub struct Admin {
+    /// The username of the user.
+    pub username: String,
+    /// Revoke administrator access instead.
+    #[arg(long)]
+    pub revoke: bool,
+}
+
+/// Inspects the mobiums economy.
+#[derive(clap::Args, Debug)]
+pub struct Economy {
+    /// The command to run.
+    #[command(subcommand)]
+    pub command: Option<EconomyCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum EconomyCommand {
+    #[command(name = "report")]
+    Report(EconomyReport),
+}
+
+/// Prints a snapshot of the economy.
+///
+/// These are the same numbers as `GET /admin/economy`.
+#[derive(clap::Args, Debug)]
+pub struct EconomyReport {
+    /// The format to print the report in.
+    #[arg(short, long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+    /// Write the report to a file instead of stdout.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Deletes expired sessions and link codes, old wager tokens and audit log
+/// entries, and old chat messages.
+///
+/// This also runs once a day while the server is up. Chat messages are only
+/// deleted if `server.retention.prune_chat` is set.
+#[derive(clap::Args, Debug)]
+pub struct Cleanup {
+    /// Count what would be deleted, without deleting anything.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Fills an empty database with made-up data for local development.
+///
+/// Players, users and a history of matches are generated, with wagers settled
+/// and ratings replayed like the real thing. Pending migrations are run first.
+#[derive(clap::Args, Debug)]
+pub struct Seed {
+    /// How many players to create.
+    #[arg(long, default_value_t = 40)]
+    pub players: usize,
+    /// How many matches to play out.
+    #[arg(long, default_value_t = 300)]
+    pub matches: usize,
+    /// How many users to create.
+    #[arg(long, default_value_t = 25)]
+    pub users: usize,
+    /// How many days the matches are spread over, ending now.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+    pub days: u32,
+    /// Seed the generator, so the same data comes out every time.
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+/// The format of a report.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Human-readable text.
+    Text,
+    /// A single JSON object.
+    Json,
+    /// `metric,value` rows.
+    Csv,
+}
+
 /// Registers a server.
 pub async fn register_server(
     command: &RegisterServer,
@@ -105,3 +291,264 @@ pub async fn register_server(
 
     Ok(())
 }
+
+/// Grants or revokes administrator access.
+pub async fn set_admin(command: &Admin, conn: &mut SqliteConnection) -> Result<(), Error> {
+    let flags = sqlx::query_as::<_, (i32,)>(
+        r#"
+        SELECT flags
+        FROM user
+        WHERE username = $1
+        "#,
+    )
+    .bind(&command.username)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((flags,)) = flags else {
+        return Err(eyre!("user {} not found", command.username));
+    };
+
+    let mut flags = UserFlags::from(flags);
+    flags.set(UserFlags::ADMINISTRATOR, !command.revoke);
+
+    sqlx::query(
+        r#"
+        UPDATE user
+        SET flags = $2, updated_at = $3
+        WHERE username = $1
+        "#,
+    )
+    .bind(&command.username)
+    .bind(i32::from(flags))
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Cleans up old data, printing how much was deleted.
+pub async fn cleanup(
+    command: &Cleanup,
+    config: &RetentionConfig,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let mut tx = conn.begin().await?;
+
+    let report = retention::cleanup(config, Utc::now(), &mut tx).await?;
+
+    if command.dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+
+    let verb = if command.dry_run {
+        "would delete"
+    } else {
+        "deleted"
+    };
+    println!("{} {} expired sessions", verb, report.sessions);
+    println!("{} {} expired link codes", verb, report.link_codes);
+    println!("{} {} old wager tokens", verb, report.wager_tokens);
+    println!("{} {} chat messages", verb, report.messages);
+    println!("{} {} audit log entries", verb, report.audit_log);
+
+    Ok(())
+}
+
+/// Seeds an empty database, printing what was made.
+pub async fn seed<T>(command: &Seed, model: &T, conn: &mut SqliteConnection) -> Result<(), Error>
+where
+    T: Model + Debug,
+    T::Data: Debug,
+{
+    MIGRATOR.run(&mut *conn).await?;
+
+    // seeding replays every rating, which would trample real ones
+    let (populated,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM player) OR EXISTS (SELECT 1 FROM battle)
+        "#,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if populated {
+        return Err(eyre!(
+            "refusing to seed a database that has players or matches"
+        ));
+    }
+
+    let options = SeedOptions {
+        players: command.players,
+        matches: command.matches,
+        users: command.users,
+        days: command.days,
+        seed: command.seed,
+    };
+
+    let mut tx = conn.begin().await?;
+    let report = seed::seed(&options, model, &mut tx).await?;
+    tx.commit().await?;
+
+    println!("created {} players", report.players);
+    println!("created {} users", report.users);
+    println!(
+        "played {} matches with {} wagers",
+        report.matches, report.wagers
+    );
+    println!("bailed out users {} times", report.bailouts);
+
+    Ok(())
+}
+
+/// Simulates ratings under the current and the changed rating model, and
+/// prints how well each predicted the matches.
+pub async fn simulate_mmr<T>(
+    command: &MmrSimulate,
+    current: &T,
+    changed: &T,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error>
+where
+    T: Model + Debug,
+    T::Data: Debug,
+{
+    let mut simulations = Vec::new();
+    for (name, model) in [("current", current), ("changed", changed)] {
+        // simulations overwrite every rating
+        let mut tx = conn.begin().await?;
+        let simulation = simulate::simulate(model, &mut tx).await?;
+        tx.rollback().await?;
+
+        simulations.push((name, simulation));
+    }
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:<8} {:>8} {:>10} {:>9} {:>8} {:>8}",
+        "config", "matches", "predicted", "accuracy", "brier", "log loss"
+    )?;
+    for (name, simulation) in &simulations {
+        let score = |score: Option<f32>| match score {
+            Some(score) => format!("{:.4}", score),
+            None => "-".to_owned(),
+        };
+
+        writeln!(
+            out,
+            "{:<8} {:>8} {:>10} {:>9} {:>8} {:>8}",
+            name,
+            simulation.matches,
+            simulation.predictions.len(),
+            match simulation.accuracy() {
+                Some(accuracy) => format!("{:.2}%", accuracy * 100.0),
+                None => "-".to_owned(),
+            },
+            score(simulation.brier_score()),
+            score(simulation.log_loss()),
+        )?;
+    }
+
+    if let Some(path) = &command.trajectories {
+        let mut file = io::BufWriter::new(File::create(path)?);
+
+        writeln!(file, "config,match_id,concluded_at,player,mmr")?;
+        for (name, simulation) in &simulations {
+            for point in &simulation.trajectories {
+                writeln!(
+                    file,
+                    "{},{},{},{},{}",
+                    name,
+                    point.match_id,
+                    point.concluded_at.to_rfc3339(),
+                    csv_field(&point.short_id),
+                    point.mmr
+                )?;
+            }
+        }
+
+        file.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Writes an economy report.
+pub async fn economy_report(
+    command: &EconomyReport,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let report = economy::economy_report(conn).await?;
+
+    let mut out: Box<dyn Write> = match &command.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    match command.format {
+        ReportFormat::Text => write_economy_text(&report, &mut out)?,
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &report)?;
+            writeln!(out)?;
+        }
+        ReportFormat::Csv => write_economy_csv(&report, &mut out)?,
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+fn write_economy_text(report: &admin::EconomyReport, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "economy report for {}", report.created_at)?;
+    writeln!(
+        out,
+        "mobiums in circulation: {} across {} users",
+        report.circulation, report.users
+    )?;
+    writeln!(out, "concentration (gini): {:.3}", report.gini)?;
+    writeln!(out, "bailouts this week: {}", report.bailouts_this_week)?;
+    writeln!(out, "house profit: {}", report.house_profit)?;
+    writeln!(out, "top balances:")?;
+    for (i, balance) in report.top_balances.iter().enumerate() {
+        writeln!(
+            out,
+            "{:>4}. {} ({}): {}",
+            i + 1,
+            balance.username,
+            balance.display_name,
+            balance.mobiums
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_economy_csv(report: &admin::EconomyReport, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "metric,value")?;
+    writeln!(out, "created_at,{}", report.created_at.to_rfc3339())?;
+    writeln!(out, "circulation,{}", report.circulation)?;
+    writeln!(out, "users,{}", report.users)?;
+    writeln!(out, "gini,{}", report.gini)?;
+    writeln!(out, "bailouts_this_week,{}", report.bailouts_this_week)?;
+    writeln!(out, "house_profit,{}", report.house_profit)?;
+    for balance in &report.top_balances {
+        let metric = format!("balance:{}", balance.username);
+        writeln!(out, "{},{}", csv_field(&metric), balance.mobiums)?;
+    }
+
+    Ok(())
+}
+
+/// Quotes a CSV field, if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
@@ -1,6 +1,6 @@
 //! Application configuration.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use chrono::TimeDelta;
 
@@ -11,13 +11,18 @@ use figment::{
 };
 
 use humantime::format_duration;
-use ring_channel_model::user::to_username_lossy;
+use ring_channel_model::{
+    admin::BotStrategy, display_name, mobiums::Mobiums, user::to_username_lossy,
+};
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
 
 use eyre::Error;
 
-use crate::player::mmr::{glicko2::Glicko2Config, openskill::OpenSkillConfig};
+use crate::{
+    player::mmr::{glicko2::Glicko2Config, openskill::OpenSkillConfig},
+    user::DEFAULT_STARTING_MOBIUMS,
+};
 
 /// Full application configuration.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -30,6 +35,16 @@ pub struct Config {
     pub http: HttpConfig,
     /// Discord configuration.
     pub discord: Option<DiscordConfig>,
+    /// Avatar proxy configuration.
+    ///
+    /// If this is missing, avatars are linked to directly.
+    pub avatars: Option<AvatarConfig>,
+    /// Slow query logging configuration.
+    pub slow_queries: SlowQueryConfig,
+    /// Web Push configuration.
+    ///
+    /// If this is missing, push notifications are disabled.
+    pub push: Option<PushConfig>,
 }
 
 /// General server configuration.
@@ -42,6 +57,11 @@ pub struct ServerConfig {
     pub redirect_url: Option<String>,
     /// The database url to connect to.
     pub database_url: Option<String>,
+    /// The url of a read-only replica of the database.
+    ///
+    /// If set, read-heavy endpoints are served from the replica while it is
+    /// healthy.
+    pub replica_database_url: Option<String>,
     /// Whether to send session cookies (used for auth) with `Secure`.
     ///
     /// By default, this is `true` to avoid misconfiguration.
@@ -50,6 +70,54 @@ pub struct ServerConfig {
     pub encryption_key: Option<String>,
     /// Wager bot config.
     pub bot: WagerBotConfig,
+    /// Hides who won the biggest payout when a match is settled.
+    pub anonymous_payouts: bool,
+    /// How many mobiums new users start with.
+    pub starting_mobiums: i64,
+    /// How many characters user and player display names are cut down to.
+    pub max_display_name_length: usize,
+    /// Player display name filter config.
+    pub name_filter: NameFilterConfig,
+    /// How many mobiums users are granted when they place their first wager.
+    ///
+    /// Set to `0` to disable.
+    pub onboarding_bonus: i64,
+    /// Pays out wagers at the odds they were placed at, instead of splitting
+    /// the final pots.
+    ///
+    /// Servers can still pick either mode when creating a match.
+    pub locked_odds: bool,
+    /// How long after bets close wagers are still taken.
+    ///
+    /// Covers clients whose clocks run a little behind, and wagers still in
+    /// flight when the countdown ends.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub bet_grace_period: TimeDelta,
+    /// Sandbox config.
+    pub sandbox: SandboxConfig,
+    /// Result dispute config.
+    pub dispute: DisputeConfig,
+    /// No-show config.
+    pub no_show: NoShowConfig,
+    /// Wager streak config.
+    pub streaks: StreakConfig,
+    /// Post-bailout cooldown config.
+    pub bailout_cooldown: BailoutCooldownConfig,
+    /// Highlight detection config.
+    pub highlights: HighlightConfig,
+    /// Room milestone config.
+    pub milestones: MilestoneConfig,
+    /// Anti-abuse config.
+    pub anti_abuse: AntiAbuseConfig,
+    /// Data retention config.
+    pub retention: RetentionConfig,
+    /// Auto-bet config.
+    pub auto_bets: AutoBetConfig,
+    /// LiteFS write forwarding config.
+    pub litefs: LiteFsConfig,
 }
 
 impl Default for ServerConfig {
@@ -58,14 +126,472 @@ impl Default for ServerConfig {
             base_url: "http://localhost:4000".into(),
             redirect_url: None,
             database_url: None,
+            replica_database_url: None,
             secure_sessions: true,
             encryption_key: None,
             bot: WagerBotConfig::default(),
+            anonymous_payouts: false,
+            starting_mobiums: DEFAULT_STARTING_MOBIUMS,
+            max_display_name_length: display_name::DEFAULT_MAX_LENGTH,
+            name_filter: NameFilterConfig::default(),
+            onboarding_bonus: 200,
+            locked_odds: false,
+            bet_grace_period: TimeDelta::seconds(3),
+            sandbox: SandboxConfig::default(),
+            dispute: DisputeConfig::default(),
+            no_show: NoShowConfig::default(),
+            streaks: StreakConfig::default(),
+            bailout_cooldown: BailoutCooldownConfig::default(),
+            highlights: HighlightConfig::default(),
+            milestones: MilestoneConfig::default(),
+            anti_abuse: AntiAbuseConfig::default(),
+            retention: RetentionConfig::default(),
+            auto_bets: AutoBetConfig::default(),
+            litefs: LiteFsConfig::default(),
+        }
+    }
+}
+
+/// Player display name filter configuration.
+///
+/// Player names come straight from game servers, so they are checked against
+/// a denylist before they end up on overlays. Admins can still set any name
+/// by hand.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NameFilterConfig {
+    /// Words that can't appear in player display names.
+    ///
+    /// Words are matched ignoring case, spaces and punctuation, and common
+    /// digits standing in for letters.
+    pub denylist: Vec<String>,
+    /// What happens to names with a denied word in them.
+    pub replacement: NameReplacement,
+    /// The name players get when their name is replaced outright.
+    pub placeholder: String,
+}
+
+impl Default for NameFilterConfig {
+    fn default() -> Self {
+        NameFilterConfig {
+            denylist: Vec::new(),
+            replacement: NameReplacement::default(),
+            placeholder: "Racer".into(),
+        }
+    }
+}
+
+/// What happens to player display names with a denied word in them.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NameReplacement {
+    /// Denied words are starred out.
+    #[default]
+    Mask,
+    /// The whole name is replaced with the placeholder.
+    Placeholder,
+}
+
+/// Anti-abuse configuration.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AntiAbuseConfig {
+    /// CAPTCHA verification for new accounts.
+    ///
+    /// If this is missing, nobody is asked to verify.
+    pub captcha: Option<CaptchaConfig>,
+    /// Wager caps for new and unverified accounts.
+    #[serde(default)]
+    pub wager_limits: WagerLimitsConfig,
+}
+
+/// Wager limit configuration.
+///
+/// New accounts, and accounts that aren't members of the community's Discord
+/// server, can only wager so much on a match. This keeps throwaway accounts
+/// from swinging pots. Users under both limits get the lower one.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WagerLimitsConfig {
+    /// Accounts younger than this are new.
+    #[serde(
+        default = "default_new_account_age",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub new_account_age: TimeDelta,
+    /// The most new accounts can wager on a match.
+    ///
+    /// If this is missing, new accounts aren't limited.
+    pub new_account_max_wager: Option<i64>,
+    /// The Discord server users have to be members of.
+    ///
+    /// Membership is checked when users log in, and again whenever their
+    /// Discord token is refreshed.
+    pub guild_id: Option<u64>,
+    /// The most users that aren't members of `guild_id` can wager on a
+    /// match.
+    ///
+    /// If this or `guild_id` is missing, nobody is limited for it.
+    pub unverified_max_wager: Option<i64>,
+}
+
+impl WagerLimitsConfig {
+    /// The most users that aren't members of the Discord server can wager,
+    /// if they are limited at all.
+    pub fn unverified_max_wager(&self) -> Option<i64> {
+        self.guild_id.and(self.unverified_max_wager)
+    }
+}
+
+impl Default for WagerLimitsConfig {
+    fn default() -> Self {
+        WagerLimitsConfig {
+            new_account_age: default_new_account_age(),
+            new_account_max_wager: None,
+            guild_id: None,
+            unverified_max_wager: None,
+        }
+    }
+}
+
+fn default_new_account_age() -> TimeDelta {
+    TimeDelta::days(7)
+}
+
+/// CAPTCHA configuration.
+///
+/// Users have to pass a CAPTCHA before their first wager, or before any
+/// wager while their account is young. Once they pass, they are never asked
+/// again.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CaptchaConfig {
+    /// Who verifies CAPTCHA responses.
+    #[serde(default)]
+    pub provider: CaptchaProvider,
+    /// The secret key given by the provider.
+    pub secret: String,
+    /// Accounts younger than this have to verify, even if they wagered
+    /// before.
+    #[serde(
+        default = "default_min_account_age",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub min_account_age: TimeDelta,
+}
+
+fn default_min_account_age() -> TimeDelta {
+    TimeDelta::days(7)
+}
+
+/// A CAPTCHA provider.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptchaProvider {
+    /// Cloudflare Turnstile.
+    #[default]
+    Turnstile,
+    /// hCaptcha.
+    Hcaptcha,
+}
+
+/// Result dispute configuration.
+///
+/// Concluding a match makes it provisional instead, giving admins and the
+/// reporting server a window to amend botched finish times before anything
+/// irreversible happens.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DisputeConfig {
+    /// Enables the dispute window.
+    pub enabled: bool,
+    /// How long matches stay provisional before they are finalized.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub window: TimeDelta,
+}
+
+impl Default for DisputeConfig {
+    fn default() -> Self {
+        DisputeConfig {
+            enabled: false,
+            window: TimeDelta::minutes(5),
+        }
+    }
+}
+
+/// No-show configuration.
+///
+/// Matches the reporting server never finishes, like when it crashes mid-race,
+/// are cancelled once they have run too long. Wagers on them are released.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NoShowConfig {
+    /// Enables no-show cancellation.
+    pub enabled: bool,
+    /// How long after a match starts it is cancelled if it isn't finished.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub window: TimeDelta,
+}
+
+impl Default for NoShowConfig {
+    fn default() -> Self {
+        NoShowConfig {
+            enabled: false,
+            window: TimeDelta::minutes(30),
+        }
+    }
+}
+
+/// Wager streak configuration.
+///
+/// Users who predict several matches correctly in a row get their winnings
+/// multiplied. Streaks are tracked either way.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StreakConfig {
+    /// Enables streak bonuses.
+    pub enabled: bool,
+    /// How much the multiplier grows with every correct prediction in a row.
+    pub bonus: f64,
+    /// The largest multiplier a streak can earn.
+    pub max_multiplier: f64,
+    /// The smallest share of their mobiums a user has to wager for a win to
+    /// grow their streak.
+    ///
+    /// Keeps streaks from being built up with pocket change, and cashed in
+    /// with a big wager.
+    pub min_stake_share: f64,
+}
+
+impl StreakConfig {
+    /// The multiplier a user on a `streak` gets for their next win.
+    pub fn multiplier(&self, streak: i32) -> f64 {
+        if self.enabled {
+            (1.0 + self.bonus * streak.max(0) as f64).clamp(1.0, self.max_multiplier.max(1.0))
+        } else {
+            1.0
+        }
+    }
+
+    /// Whether winning a wager of `mobiums` grows the streak of a user with
+    /// `balance` mobiums.
+    pub fn counts(&self, mobiums: i64, balance: i64) -> bool {
+        mobiums > 0 && mobiums as f64 >= balance.max(0) as f64 * self.min_stake_share
+    }
+}
+
+impl Default for StreakConfig {
+    fn default() -> Self {
+        StreakConfig {
+            enabled: false,
+            bonus: 0.1,
+            max_multiplier: 2.0,
+            min_stake_share: 0.05,
+        }
+    }
+}
+
+/// Highlight detection configuration.
+///
+/// Concluded matches are checked for anything that makes them worth watching
+/// again, like a close finish or an upset.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HighlightConfig {
+    /// Enables highlight detection.
+    pub enabled: bool,
+    /// The widest gap between the top two finishers that still counts as a
+    /// photo finish, in tics.
+    pub photo_finish_margin: i32,
+    /// The highest chance the winning team could have had for the match to
+    /// count as an upset.
+    pub upset_chance: f64,
+    /// The smallest pot that can set a record.
+    ///
+    /// Keeps the first few matches on a fresh server from all being records.
+    pub min_record_pot: i64,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            enabled: true,
+            photo_finish_margin: 10,
+            upset_chance: 0.25,
+            min_record_pot: 1000,
+        }
+    }
+}
+
+/// Post-bailout cooldown configuration.
+///
+/// Users who were just bailed out can only wager a share of their balance for
+/// a while, so the bailout isn't gone on the very next match.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BailoutCooldownConfig {
+    /// Enables the cooldown.
+    pub enabled: bool,
+    /// How long the cooldown lasts after a bailout.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub duration: TimeDelta,
+    /// The share of their balance users can wager during the cooldown, from
+    /// `0.0` to `1.0`.
+    pub max_wager_share: f64,
+}
+
+impl BailoutCooldownConfig {
+    /// The most a user with `mobiums` can wager during the cooldown.
+    pub fn max_wager(&self, mobiums: Mobiums) -> Mobiums {
+        Mobiums((mobiums.0 as f64 * self.max_wager_share.clamp(0.0, 1.0)).floor() as i64)
+    }
+}
+
+impl Default for BailoutCooldownConfig {
+    fn default() -> Self {
+        BailoutCooldownConfig {
+            enabled: false,
+            duration: TimeDelta::minutes(30),
+            max_wager_share: 0.25,
+        }
+    }
+}
+
+/// Auto-bet configuration.
+///
+/// Users can set a rule to wager on every public match as soon as it opens.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AutoBetConfig {
+    /// Places auto-bets when matches are created.
+    ///
+    /// Users can still set rules while this is off; they just don't wager.
+    pub enabled: bool,
+}
+
+/// LiteFS configuration.
+///
+/// LiteFS replicas can't be written to. When this is enabled, requests that
+/// write are forwarded to the primary when they land on a replica, or turned
+/// away with a `Fly-Replay` header, so the proxy in front can replay them
+/// there.
+///
+/// Sessions are written to on almost every request that has one, so those
+/// requests must be routed to the primary too.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LiteFsConfig {
+    /// Checks the node's role before every request that writes.
+    pub enabled: bool,
+    /// The directory LiteFS is mounted at.
+    ///
+    /// LiteFS keeps a `.primary` file here while the node is a replica.
+    pub mount_dir: PathBuf,
+    /// The url of the primary, to forward writes to.
+    ///
+    /// If this is missing, writes on replicas are turned away with a `503`
+    /// instead.
+    pub primary_url: Option<String>,
+}
+
+impl Default for LiteFsConfig {
+    fn default() -> Self {
+        LiteFsConfig {
+            enabled: false,
+            mount_dir: PathBuf::from("/litefs"),
+            primary_url: None,
+        }
+    }
+}
+
+/// Data retention configuration.
+///
+/// Expired sessions and link codes are always cleaned up, and so are audit
+/// log entries past `audit_log_retention`. Chat history is kept forever
+/// unless pruning is turned on.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RetentionConfig {
+    /// Deletes chat messages once they are older than `chat_retention`.
+    pub prune_chat: bool,
+    /// How long chat messages are kept.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub chat_retention: TimeDelta,
+    /// How long audit log entries are kept.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub audit_log_retention: TimeDelta,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            prune_chat: false,
+            chat_retention: TimeDelta::days(90),
+            audit_log_retention: TimeDelta::days(180),
+        }
+    }
+}
+
+/// Milestone configuration.
+///
+/// Clients in the room are told when the current match crosses one of these
+/// thresholds, so overlays can celebrate.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MilestoneConfig {
+    /// Enables milestones.
+    pub enabled: bool,
+    /// The viewer counts to celebrate.
+    pub viewers: Vec<i64>,
+    /// The pot sizes to celebrate, in mobiums.
+    pub pot_sizes: Vec<i64>,
+}
+
+impl Default for MilestoneConfig {
+    fn default() -> Self {
+        MilestoneConfig {
+            enabled: true,
+            viewers: vec![10, 25, 50, 100, 250, 500, 1000],
+            pot_sizes: vec![1_000, 10_000, 50_000, 100_000, 500_000, 1_000_000],
+        }
+    }
+}
+
+/// Sandbox configuration.
+///
+/// Sandboxes behave just like production, but their mobiums are fake. They
+/// are meant for testing frontends and game server integrations.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SandboxConfig {
+    /// Marks the instance as a sandbox.
+    ///
+    /// Every response is sent with `X-Sandbox: true`, and wagers are flagged
+    /// as non-canonical.
+    pub enabled: bool,
+    /// How many mobiums new users start with.
+    ///
+    /// This replaces the server's `starting_mobiums`.
+    pub starting_mobiums: i64,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            enabled: false,
+            starting_mobiums: 1_000_000,
         }
     }
 }
 
 /// Wager bot configuration.
+///
+/// The bot's profile and how it wagers can be changed by administrators at
+/// runtime. Those changes are kept with the bot's user, and take precedence
+/// over this.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct WagerBotConfig {
     /// Enables the wager bot.
@@ -81,6 +607,13 @@ pub struct WagerBotConfig {
     pub avatar: Option<String>,
     /// How much money the bot will wager on an empty side.
     pub wager_amount: i64,
+    /// How the bot decides how much to wager.
+    pub strategy: BotStrategy,
+    /// If set, the bot's mobiums are reset to this every day at midnight UTC.
+    ///
+    /// The bot can always bet money it doesn't have, so this only keeps its
+    /// balance readable.
+    pub bankroll: Option<i64>,
 }
 
 impl Default for WagerBotConfig {
@@ -91,6 +624,8 @@ impl Default for WagerBotConfig {
             display_name: "Metal Sonic".into(),
             avatar: None,
             wager_amount: 400,
+            strategy: BotStrategy::default(),
+            bankroll: None,
         }
     }
 }
@@ -116,11 +651,208 @@ impl Default for RatingModelConfig {
 pub struct HttpConfig {
     /// The port to listen on.
     pub port: u16,
+    /// How long a request can take before it is logged as slow.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub latency_budget: TimeDelta,
+    /// The region this instance is deployed in.
+    ///
+    /// Attached to request latency metrics and logs, so instances in
+    /// different regions can be told apart.
+    pub region: Option<String>,
+    /// WebSocket configuration.
+    pub websocket: WebSocketConfig,
+    /// Public API configuration.
+    pub public_api: PublicApiConfig,
 }
 
 impl Default for HttpConfig {
     fn default() -> Self {
-        HttpConfig { port: 4000 }
+        HttpConfig {
+            port: 4000,
+            latency_budget: TimeDelta::milliseconds(500),
+            region: None,
+            websocket: WebSocketConfig::default(),
+            public_api: PublicApiConfig::default(),
+        }
+    }
+}
+
+/// Public API configuration.
+///
+/// The public API serves read-only endpoints under `/public` to anyone,
+/// without sessions, so community sites can use them. Every client is rate
+/// limited by IP address. It is off by default.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PublicApiConfig {
+    /// Enables the public API.
+    pub enabled: bool,
+    /// How many requests a client can make a minute, on average.
+    pub requests_per_minute: u32,
+    /// How many requests a client can make at once.
+    pub burst: u32,
+    /// The header to read client addresses from, like `X-Forwarded-For`.
+    ///
+    /// The last address in the header is used, which is the one the proxy in
+    /// front of the server added. Only set this behind exactly one proxy that
+    /// appends to it, or clients can pick their own address. The peer address
+    /// is used otherwise.
+    pub client_ip_header: Option<String>,
+}
+
+impl Default for PublicApiConfig {
+    fn default() -> Self {
+        PublicApiConfig {
+            enabled: false,
+            requests_per_minute: 30,
+            burst: 10,
+            client_ip_header: None,
+        }
+    }
+}
+
+/// WebSocket configuration.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WebSocketConfig {
+    /// Message compression.
+    pub compression: CompressionConfig,
+    /// Draining sockets before shutting down.
+    pub drain: DrainConfig,
+    /// Latency pings.
+    pub ping: PingConfig,
+    /// Room event logging.
+    pub event_log: EventLogConfig,
+}
+
+/// Room event logging.
+///
+/// This is a debugging aid; the most recent events broadcast to the room are
+/// kept for `GET /admin/room/events`, so operators can see exactly what
+/// clients were sent, and when.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EventLogConfig {
+    /// Enables event logging.
+    pub enabled: bool,
+    /// How many events are kept.
+    pub capacity: usize,
+    /// Also writes events to the database, so they outlive restarts.
+    pub persist: bool,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        EventLogConfig {
+            enabled: false,
+            capacity: 500,
+            persist: false,
+        }
+    }
+}
+
+/// Latency pings.
+///
+/// The server pings every client periodically, and clients echo the ping
+/// back. The round trip time of each socket shows up in the socket
+/// diagnostics, which helps tell server lag apart from client network issues.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PingConfig {
+    /// Enables pings.
+    pub enabled: bool,
+    /// How often clients are pinged.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub interval: TimeDelta,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        PingConfig {
+            enabled: false,
+            interval: TimeDelta::seconds(15),
+        }
+    }
+}
+
+/// Socket draining.
+///
+/// When the server is shutting down, or an administrator asks, clients are
+/// told to reconnect, and given some time to do so before their sockets are
+/// closed. New sockets are turned away in the meantime.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DrainConfig {
+    /// How long clients have to reconnect before their sockets are closed.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub timeout: TimeDelta,
+    /// Where clients should reconnect to.
+    ///
+    /// Clients reconnect to the same address if this is missing, which is
+    /// what you want behind a load balancer.
+    pub reconnect_url: Option<String>,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        DrainConfig {
+            timeout: TimeDelta::seconds(30),
+            reconnect_url: None,
+        }
+    }
+}
+
+/// WebSocket message compression.
+///
+/// Clients opt in by offering the
+/// [`GZIP_SUBPROTOCOL`](crate::room::protocol::GZIP_SUBPROTOCOL) subprotocol.
+/// Messages over the threshold are then sent gzipped in binary frames.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CompressionConfig {
+    /// Enables compression.
+    pub enabled: bool,
+    /// How large a message has to be, in bytes, before it is compressed.
+    pub threshold: usize,
+    /// The gzip compression level, from 0 to 9.
+    pub level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: false,
+            threshold: 1024,
+            level: 6,
+        }
+    }
+}
+
+/// Slow query logging configuration.
+///
+/// This is a debugging aid; statements that take longer than the threshold
+/// are logged and kept for `GET /admin/slow-queries`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SlowQueryConfig {
+    /// Enables slow query logging.
+    pub enabled: bool,
+    /// How long a statement can take before it is considered slow.
+    #[serde(
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub threshold: TimeDelta,
+}
+
+impl Default for SlowQueryConfig {
+    fn default() -> Self {
+        SlowQueryConfig {
+            enabled: false,
+            threshold: TimeDelta::milliseconds(100),
+        }
     }
 }
 
@@ -131,6 +863,85 @@ pub struct DiscordConfig {
     pub client_id: u64,
     /// The client secret.
     pub client_secret: String,
+    /// How long a refresh token can sit unused before it is rotated.
+    #[serde(
+        default = "default_refresh_after",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub refresh_after: TimeDelta,
+    /// Whether to use PKCE when logging in.
+    ///
+    /// Turning this off falls back to the plain authorization code grant.
+    #[serde(default = "default_pkce")]
+    pub pkce: bool,
+}
+
+fn default_refresh_after() -> TimeDelta {
+    TimeDelta::days(5)
+}
+
+fn default_pkce() -> bool {
+    true
+}
+
+/// Web Push configuration.
+///
+/// Generate a key pair with `ring-channel generate-vapid-keys`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PushConfig {
+    /// The VAPID public key, as unpadded base64url of the uncompressed point.
+    pub public_key: String,
+    /// The VAPID private key, as unpadded base64url of the scalar.
+    pub private_key: String,
+    /// Who push services can contact about our notifications, as a `mailto:`
+    /// or `https:` URL.
+    pub subject: String,
+    /// How long push services hold notifications for browsers that are
+    /// offline.
+    #[serde(
+        default = "default_push_ttl",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub ttl: TimeDelta,
+    /// The hosts of the push services browsers can subscribe through.
+    ///
+    /// Subdomains of these hosts are let in too. The server posts to every
+    /// endpoint, so anything else is turned away.
+    #[serde(default = "default_push_services")]
+    pub services: Vec<String>,
+}
+
+fn default_push_ttl() -> TimeDelta {
+    TimeDelta::days(1)
+}
+
+fn default_push_services() -> Vec<String> {
+    [
+        // Chrome, Edge and other Chromium browsers
+        "fcm.googleapis.com",
+        "android.googleapis.com",
+        // Firefox
+        "push.services.mozilla.com",
+        // Safari
+        "push.apple.com",
+        // Windows
+        "notify.windows.com",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Avatar proxy configuration.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "storage", rename_all = "snake_case")]
+pub enum AvatarConfig {
+    /// Stores avatars on the local filesystem.
+    Filesystem {
+        /// The directory to store avatars in.
+        path: PathBuf,
+    },
 }
 
 /// Reads the configuration.
@@ -139,11 +950,14 @@ pub fn read_config(config_file: impl AsRef<Path>) -> Result<Config, Error> {
         .merge(Toml::file(config_file))
         .merge(Env::prefixed("DUELCHANNEL_"))
         .merge(Env::raw().filter_map(|k| match k.as_str() {
+            "CAPTCHA_SECRET" => Some(Uncased::from("server.anti_abuse.captcha.secret")),
             "DATABASE_URL" => Some(Uncased::from("server.database_url")),
             "DISCORD_CLIENT_ID" => Some(Uncased::from("discord.client_id")),
             "DISCORD_CLIENT_SECRET" => Some(Uncased::from("discord.client_secret")),
             "ENCRYPTION_KEY" => Some(Uncased::from("server.encryption_key")),
             "PORT" => Some(Uncased::from("http.port")),
+            "REPLICA_DATABASE_URL" => Some(Uncased::from("server.replica_database_url")),
+            "VAPID_PRIVATE_KEY" => Some(Uncased::from("push.private_key")),
             _ => None,
         }))
         .extract()
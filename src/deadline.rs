@@ -0,0 +1,64 @@
+//! Betting deadlines.
+//!
+//! The wall clock can jump around under the server, so when a match is
+//! created here, the moment its bets close is also kept as an [`Instant`].
+//! Matches created before a restart, or by another instance, fall back to the
+//! `closed_at` stored with the match.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use ring_channel_model::BattleId;
+
+/// How long deadlines are kept around after they pass.
+///
+/// Matches are usually forgotten as soon as they stop being ongoing; this
+/// only cleans up after matches that never do.
+const DEADLINE_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// The monotonic betting deadlines of ongoing matches.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug, Default)]
+pub struct BetDeadlines(Arc<Mutex<HashMap<BattleId, Instant>>>);
+
+impl BetDeadlines {
+    /// Remembers when bets on a match close.
+    pub fn insert(&self, battle_id: BattleId, deadline: Instant) {
+        let mut deadlines = self.0.lock().unwrap();
+
+        let now = Instant::now();
+        deadlines
+            .retain(|_, deadline| now.saturating_duration_since(*deadline) < DEADLINE_RETENTION);
+        deadlines.insert(battle_id, deadline);
+    }
+
+    /// Forgets a match's deadline, once bets on it can't be placed anymore.
+    pub fn remove(&self, battle_id: BattleId) {
+        self.0.lock().unwrap().remove(&battle_id);
+    }
+
+    /// The time left before bets on a match close.
+    ///
+    /// This is negative once they have closed. If the match's deadline isn't
+    /// known, `closed_at` is compared against the wall clock instead.
+    pub fn closes_in(&self, battle_id: BattleId, closed_at: DateTime<Utc>) -> TimeDelta {
+        let deadline = self.0.lock().unwrap().get(&battle_id).copied();
+
+        match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                match deadline.checked_duration_since(now) {
+                    Some(left) => TimeDelta::from_std(left).unwrap_or(TimeDelta::MAX),
+                    None => -TimeDelta::from_std(now - deadline).unwrap_or(TimeDelta::MAX),
+                }
+            }
+            None => closed_at - Utc::now(),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Economy health reports.
+//!
+//! Shared by the admin economy endpoint and the `economy report` command, so
+//! operators get the same numbers either way.
+
+use chrono::{TimeDelta, Utc};
+
+use ring_channel_model::{
+    admin::{Balance, EconomyReport},
+    battle::BattleStatus,
+    user::UserFlags,
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::error::Error;
+
+/// How many balances are included in a report.
+pub const TOP_BALANCES: i64 = 10;
+
+/// Builds a snapshot of the economy.
+pub async fn economy_report(conn: &mut SqliteConnection) -> Result<EconomyReport, Error> {
+    #[derive(FromRow)]
+    struct BalanceQuery {
+        username: String,
+        display_name: String,
+        mobiums: i64,
+    }
+
+    let now = Utc::now();
+    let automated = i32::from(UserFlags::AUTOMATED_USER);
+
+    let balances = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT mobiums
+        FROM user
+        WHERE NOT flags & $1
+        ORDER BY mobiums
+        "#,
+    )
+    .bind(automated)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|(mobiums,)| mobiums)
+    .collect::<Vec<_>>();
+
+    let (bailouts_this_week,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COUNT(*)
+        FROM bailout
+        WHERE inserted_at >= $1
+        "#,
+    )
+    .bind(now - TimeDelta::weeks(1))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (house_profit,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT IFNULL(SUM(w.payout), 0)
+        FROM wager w, user u, battle b
+        WHERE
+            w.user_id = u.id
+            AND w.match_id = b.id
+            AND u.flags & $1
+            AND b.status = $2
+        "#,
+    )
+    .bind(automated)
+    .bind(BattleStatus::Concluded)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let top_balances = sqlx::query_as::<_, BalanceQuery>(
+        r#"
+        SELECT username, display_name, mobiums
+        FROM user
+        WHERE NOT flags & $1 AND username IS NOT NULL
+        ORDER BY mobiums DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(automated)
+    .bind(TOP_BALANCES)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| Balance {
+        username: row.username,
+        display_name: row.display_name,
+        mobiums: row.mobiums,
+    })
+    .collect();
+
+    Ok(EconomyReport {
+        circulation: balances.iter().sum(),
+        users: balances.len() as i64,
+        gini: gini(&balances),
+        bailouts_this_week,
+        house_profit,
+        top_balances,
+        created_at: now,
+    })
+}
+
+/// The gini coefficient of a set of balances, sorted ascending.
+///
+/// Users can dip into negative mobiums, these count as holding nothing.
+pub fn gini(balances: &[i64]) -> f64 {
+    let n = balances.len() as f64;
+    let total = balances.iter().map(|b| (*b).max(0) as f64).sum::<f64>();
+
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    let weighted = balances
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (i + 1) as f64 * (*b).max(0) as f64)
+        .sum::<f64>();
+
+    (2.0 * weighted) / (n * total) - (n + 1.0) / n
+}
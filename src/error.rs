@@ -13,18 +13,191 @@ use axum::{
     response::{IntoResponse, Response},
 };
 
+use chrono::{DateTime, TimeDelta, Utc};
+
 use garde::error::Report;
 
 use derive_more::{Display, From};
 
-use http::StatusCode;
+use http::{HeaderName, HeaderValue, StatusCode, header};
 
-use ring_channel_model::ApiError;
+use ring_channel_model::{ApiError, PlayerShortId, mobiums::Mobiums, user::PenaltyKind};
 
 use uuid::Uuid;
 
 use crate::app::AppJson;
 
+/// The header telling the Fly.io proxy where to replay a request.
+pub const FLY_REPLAY: HeaderName = HeaderName::from_static("fly-replay");
+
+/// The code sent with internal server errors.
+pub const INTERNAL_ERROR_CODE: &str = "internal_error";
+
+/// Every error code the API responds with, with the status it is sent with,
+/// and what it means.
+///
+/// Codes are stable, so clients can match on them instead of messages.
+pub const ERROR_CODES: &[(&str, StatusCode, &str)] = &[
+    (
+        "not_found",
+        StatusCode::NOT_FOUND,
+        "The resource does not exist.",
+    ),
+    (
+        "method_not_allowed",
+        StatusCode::METHOD_NOT_ALLOWED,
+        "The route exists, but not for the request's method.",
+    ),
+    (
+        "already_concluded",
+        StatusCode::BAD_REQUEST,
+        "The match already concluded.",
+    ),
+    (
+        "battle_ongoing",
+        StatusCode::CONFLICT,
+        "The server tried to start a match while another is still ongoing.",
+    ),
+    (
+        "missing_participant",
+        StatusCode::BAD_REQUEST,
+        "A participant of the match was not found.",
+    ),
+    (
+        "validation_failed",
+        StatusCode::BAD_REQUEST,
+        "The request body was well-formed, but a field failed validation.",
+    ),
+    (
+        "malformed_body",
+        StatusCode::BAD_REQUEST,
+        "The request body could not be parsed.",
+    ),
+    (
+        "unsupported_content_type",
+        StatusCode::BAD_REQUEST,
+        "The request body is in a format the server does not accept.",
+    ),
+    (
+        "missing_content_type",
+        StatusCode::BAD_REQUEST,
+        "The request has a body, but no content type.",
+    ),
+    (
+        "api_key_unauthenticated",
+        StatusCode::UNAUTHORIZED,
+        "The route needs an API key, and none was sent.",
+    ),
+    (
+        "api_key_bad_credentials",
+        StatusCode::UNAUTHORIZED,
+        "The API key sent was malformed or unknown.",
+    ),
+    (
+        "unauthenticated",
+        StatusCode::UNAUTHORIZED,
+        "The route needs a logged in user.",
+    ),
+    (
+        "forbidden",
+        StatusCode::FORBIDDEN,
+        "The user is logged in, but is not allowed to do that.",
+    ),
+    (
+        "invalid_session",
+        StatusCode::UNAUTHORIZED,
+        "The session is invalid or expired.",
+    ),
+    (
+        "invalid_oauth_state",
+        StatusCode::BAD_REQUEST,
+        "The OAuth2 state did not match the one the login started with.",
+    ),
+    (
+        "missing_host_header",
+        StatusCode::BAD_REQUEST,
+        "The request has no Host header.",
+    ),
+    (
+        "invalid_csrf_token",
+        StatusCode::BAD_REQUEST,
+        "The CSRF token sent was not the session's.",
+    ),
+    (
+        "captcha_required",
+        StatusCode::FORBIDDEN,
+        "The user has to pass a CAPTCHA first, or the one sent failed.",
+    ),
+    (
+        "not_enough_mobiums",
+        StatusCode::BAD_REQUEST,
+        "The user does not have enough mobiums available.",
+    ),
+    (
+        "bets_closed",
+        StatusCode::BAD_REQUEST,
+        "Bets on the match have closed. Sent with `closes_in`.",
+    ),
+    (
+        "bailout_cooldown",
+        StatusCode::BAD_REQUEST,
+        "The user was bailed out recently, and wagered more than they can \
+        until their cooldown ends. Sent with `cooldown_ends_at` and \
+        `max_mobiums`.",
+    ),
+    (
+        "wager_limited",
+        StatusCode::BAD_REQUEST,
+        "The user's account is new, or not a member of the community Discord \
+        server, and wagered more than it can. Sent with `max_mobiums`, and \
+        `limit_ends_at` if the account is new.",
+    ),
+    (
+        "wager_banned",
+        StatusCode::FORBIDDEN,
+        "The user is banned from wagering. Sent with `penalty_ends_at`, unless \
+        the ban lasts until it is revoked.",
+    ),
+    (
+        "chat_muted",
+        StatusCode::FORBIDDEN,
+        "The user is muted in chat. Sent with `penalty_ends_at`, unless the \
+        mute lasts until it is revoked.",
+    ),
+    (
+        "invalid_data",
+        StatusCode::BAD_REQUEST,
+        "The request was well-formed, but otherwise invalid. The message says \
+        why.",
+    ),
+    (
+        "rate_limited",
+        StatusCode::TOO_MANY_REQUESTS,
+        "Too many requests were made. Sent with a Retry-After header.",
+    ),
+    (
+        "busy",
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The server has too much work queued. Try again in a moment.",
+    ),
+    (
+        "draining",
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The server is restarting, and won't take new sockets.",
+    ),
+    (
+        "read_only_replica",
+        StatusCode::SERVICE_UNAVAILABLE,
+        "The request writes, and landed on a read-only replica. Sent with a \
+        Retry-After header, and a Fly-Replay header naming the primary.",
+    ),
+    (
+        INTERNAL_ERROR_CODE,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Something went wrong on the server.",
+    ),
+];
+
 /// Application error that may occur during the processing of a request.
 ///
 /// This includes both internal errors and user errors.
@@ -90,123 +263,137 @@ impl Error {
     }
 
     fn to_status_and_api_error(self) -> (StatusCode, ApiError) {
+        let code = self.kind.code();
+
         let (status, mut error) = match self.kind {
-            ErrorKind::NotFound => (
-                StatusCode::NOT_FOUND,
-                ApiError {
-                    message: "Resource not found".into(),
-                },
+            ErrorKind::NotFound => (StatusCode::NOT_FOUND, ApiError::new("Resource not found")),
+            ErrorKind::MethodNotAllowed => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                ApiError::new("Method not allowed"),
             ),
             error_kind @ ErrorKind::AlreadyConcluded(_) => (
                 StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: error_kind.to_string(),
-                },
+                ApiError::new(error_kind.to_string()),
             ),
+            error_kind @ ErrorKind::BattleOngoing(_) => {
+                (StatusCode::CONFLICT, ApiError::new(error_kind.to_string()))
+            }
             error_kind @ ErrorKind::MissingParticipant(_) => (
                 StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: error_kind.to_string(),
-                },
-            ),
-            ErrorKind::Garde(error) => (
-                StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: error.to_string(),
-                },
-            ),
-            ErrorKind::Json(error) => (
-                StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: error.to_string(),
-                },
-            ),
-            ErrorKind::SerdeJson(error) => (
-                StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: error.to_string(),
-                },
-            ),
-            ErrorKind::Form(error) => (
-                StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: error.to_string(),
-                },
+                ApiError::new(error_kind.to_string()),
             ),
+            ErrorKind::Garde(error) => (StatusCode::BAD_REQUEST, ApiError::new(error.to_string())),
+            ErrorKind::Json(error) => (StatusCode::BAD_REQUEST, ApiError::new(error.to_string())),
+            ErrorKind::SerdeJson(error) => {
+                (StatusCode::BAD_REQUEST, ApiError::new(error.to_string()))
+            }
+            ErrorKind::Form(error) => (StatusCode::BAD_REQUEST, ApiError::new(error.to_string())),
             ErrorKind::UnsupportedContentType(mime) => (
                 StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: format!("Unrecognized MIME type: {}", mime),
-                },
+                ApiError::new(format!("Unrecognized MIME type: {}", mime)),
             ),
             ErrorKind::MissingContentType => (
                 StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: "Missing request content type".into(),
-                },
+                ApiError::new("Missing request content type"),
             ),
             ErrorKind::ApiKeyUnauthenticated => (
                 StatusCode::UNAUTHORIZED,
-                ApiError {
-                    message: "No API key passed; set an X-API-Key header!".into(),
-                },
+                ApiError::new("No API key passed; set an X-API-Key header!"),
             ),
             ErrorKind::ApiKeyBadCredentials => (
                 StatusCode::UNAUTHORIZED,
-                ApiError {
-                    message: "API key was malformed".into(),
-                },
+                ApiError::new("API key was malformed"),
             ),
             ErrorKind::UserUnauthenticated => (
                 StatusCode::UNAUTHORIZED,
-                ApiError {
-                    message: "User is unauthenticated".into(),
-                },
+                ApiError::new("User is unauthenticated"),
+            ),
+            ErrorKind::Forbidden => (
+                StatusCode::FORBIDDEN,
+                ApiError::new("You are not allowed to do that"),
             ),
             ErrorKind::InvalidSession => (
                 StatusCode::UNAUTHORIZED,
-                ApiError {
-                    message: "Session is invalid or bad; perhaps this is an old cookie?".into(),
-                },
+                ApiError::new("Session is invalid or bad; perhaps this is an old cookie?"),
             ),
-            ErrorKind::InvalidState { .. } => (
+            ErrorKind::InvalidState { .. } => {
+                (StatusCode::BAD_REQUEST, ApiError::new("Invalid state sent"))
+            }
+            ErrorKind::CookieFetch((code, message)) => (code, ApiError::new(message)),
+            ErrorKind::MissingHostHeader => (
                 StatusCode::BAD_REQUEST,
-                ApiError {
-                    message: "Invalid state sent".into(),
-                },
+                ApiError::new("Missing Host header"),
+            ),
+            ErrorKind::InvalidCsrfToken => (
+                StatusCode::BAD_REQUEST,
+                ApiError::new("Invalid csrf token passed"),
+            ),
+            ErrorKind::CaptchaRequired => (
+                StatusCode::FORBIDDEN,
+                ApiError::new("Complete the CAPTCHA to place a wager"),
+            ),
+            ErrorKind::NotEnoughMobiums => (
+                StatusCode::BAD_REQUEST,
+                ApiError::new("You don't have that kind of money :("),
+            ),
+            ErrorKind::Busy => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ApiError::new("The server is busy, try again in a moment"),
+            ),
+            ErrorKind::Draining => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ApiError::new("The server is restarting, reconnect in a moment"),
+            ),
+            ErrorKind::ReadOnlyReplica(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ApiError::new("This server can't take writes, try again on the primary"),
             ),
-            ErrorKind::CookieFetch((code, message)) => (
-                code,
+            ErrorKind::RateLimited(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ApiError::new("Too many requests, slow down"),
+            ),
+            error_kind @ ErrorKind::BetsClosed(closes_in) => (
+                StatusCode::BAD_REQUEST,
                 ApiError {
-                    message: message.into(),
+                    closes_in: Some(closes_in.num_milliseconds()),
+                    ..ApiError::new(error_kind.to_string())
                 },
             ),
-            ErrorKind::MissingHostHeader => (
+            error_kind @ ErrorKind::BailoutCooldown {
+                ends_at,
+                max_mobiums,
+            } => (
                 StatusCode::BAD_REQUEST,
                 ApiError {
-                    message: "Missing Host header".into(),
+                    cooldown_ends_at: Some(ends_at),
+                    max_mobiums: Some(max_mobiums.0),
+                    ..ApiError::new(error_kind.to_string())
                 },
             ),
-            ErrorKind::InvalidCsrfToken => (
+            error_kind @ ErrorKind::WagerLimited {
+                max_mobiums,
+                ends_at,
+            } => (
                 StatusCode::BAD_REQUEST,
                 ApiError {
-                    message: "Invalid csrf token passed".into(),
+                    max_mobiums: Some(max_mobiums.0),
+                    limit_ends_at: ends_at,
+                    ..ApiError::new(error_kind.to_string())
                 },
             ),
-            ErrorKind::NotEnoughMobiums => (
-                StatusCode::BAD_REQUEST,
+            error_kind @ ErrorKind::Penalized { expires_at, .. } => (
+                StatusCode::FORBIDDEN,
                 ApiError {
-                    message: "You don't have that kind of money :(".into(),
+                    penalty_ends_at: expires_at,
+                    ..ApiError::new(error_kind.to_string())
                 },
             ),
-            ErrorKind::InvalidData(message) => (StatusCode::BAD_REQUEST, ApiError { message }),
+            ErrorKind::InvalidData(message) => (StatusCode::BAD_REQUEST, ApiError::new(message)),
             // fallthrough for internal server errors not turned into user
             // errors here
             _error_kind => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                ApiError {
-                    message: "An internal server error occured".into(),
-                },
+                ApiError::new("An internal server error occured"),
             ),
         };
 
@@ -215,6 +402,8 @@ impl Error {
             error.message = message;
         }
 
+        error.code = code.to_owned();
+
         (status, error)
     }
 }
@@ -275,13 +464,21 @@ pub enum ErrorKind {
     /// A resource was not found.
     #[display("Resource not found")]
     NotFound,
+    /// The route exists, but not for the request's method.
+    #[display("Method not allowed")]
+    MethodNotAllowed,
     /// A battle with the given UUID already concluded.
     #[display("Battle {_0} concluded")]
     #[from(ignore)]
     AlreadyConcluded(Uuid),
+    /// A server attempted to start a battle while it had another battle
+    /// ongoing.
+    #[display("Match {_0} is still ongoing")]
+    #[from(ignore)]
+    BattleOngoing(String),
     /// A battle was attempted to be started with a bad participant.
     #[display("Participant {_0} not found")]
-    MissingParticipant(String),
+    MissingParticipant(PlayerShortId),
     /// A content type was not provided.
     MissingContentType,
     /// The server cannot serve this content type.
@@ -297,6 +494,9 @@ pub enum ErrorKind {
     /// user session.
     #[display("No authentication given")]
     UserUnauthenticated,
+    /// The user is authenticated, but is not allowed to access the endpoint.
+    #[display("Forbidden")]
+    Forbidden,
     /// The client attempted to access a protected endpoint without a valid
     /// user session.
     #[display("Session invalid")]
@@ -304,9 +504,74 @@ pub enum ErrorKind {
     /// An invalid csrf token was passed.
     #[display("Csrf verification failed")]
     InvalidCsrfToken,
+    /// The user has to pass a CAPTCHA first.
+    #[display("CAPTCHA required")]
+    CaptchaRequired,
     /// No mobiums?
     #[display("Not enough mobiums")]
     NotEnoughMobiums,
+    /// The server has too much work queued to take on more.
+    #[display("Server busy")]
+    Busy,
+    /// The server is draining its sockets, and won't take new ones.
+    #[display("Server draining")]
+    Draining,
+    /// The request writes, but this node is a read-only replica.
+    ///
+    /// Holds the hostname of the primary.
+    #[display("Read-only replica")]
+    #[from(ignore)]
+    ReadOnlyReplica(String),
+    /// The client made too many requests, and should wait this many seconds
+    /// before trying again.
+    #[display("Rate limited")]
+    RateLimited(u64),
+    /// A wager came in after bets closed.
+    ///
+    /// Holds the time left before bets close, as the server sees it, which is
+    /// zero or negative.
+    #[display("Bets have closed for this match.")]
+    #[from(ignore)]
+    BetsClosed(TimeDelta),
+    /// The user was bailed out recently, and the wager is bigger than they
+    /// can place until their cooldown ends.
+    #[display("You were just bailed out! Wager at most {max_mobiums} for now.")]
+    #[from(ignore)]
+    BailoutCooldown {
+        ends_at: DateTime<Utc>,
+        max_mobiums: Mobiums,
+    },
+    /// The user's account is new, or not a member of the community Discord
+    /// server, and the wager is bigger than it can place.
+    ///
+    /// Holds when the account stops being new, if that is the limit.
+    #[display("{}", match ends_at {
+        Some(ends_at) => format!(
+            "New accounts can wager at most {max_mobiums} until {}.",
+            ends_at.format("%Y-%m-%d")
+        ),
+        None => format!(
+            "Join the Discord server and log in again to wager more than {max_mobiums}."
+        ),
+    })]
+    #[from(ignore)]
+    WagerLimited {
+        max_mobiums: Mobiums,
+        ends_at: Option<DateTime<Utc>>,
+    },
+    /// The user is under a penalty that keeps them from doing this.
+    ///
+    /// Holds when the penalty expires, if it does.
+    #[display("{}", match kind {
+        PenaltyKind::WagerBan => "You are banned from wagering.",
+        PenaltyKind::ChatMute => "You are muted in chat.",
+        PenaltyKind::Warning => "You were warned.",
+    })]
+    #[from(ignore)]
+    Penalized {
+        kind: PenaltyKind,
+        expires_at: Option<DateTime<Utc>>,
+    },
     /// A valid schema was passed, but the data was otherwise invalid.
     #[display("{_0}")]
     #[from(ignore)]
@@ -343,10 +608,75 @@ pub enum ErrorKind {
     Other(eyre::Report),
 }
 
+impl ErrorKind {
+    /// The machine-readable code of the error.
+    ///
+    /// See [`ERROR_CODES`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::MethodNotAllowed => "method_not_allowed",
+            ErrorKind::AlreadyConcluded(_) => "already_concluded",
+            ErrorKind::BattleOngoing(_) => "battle_ongoing",
+            ErrorKind::MissingParticipant(_) => "missing_participant",
+            ErrorKind::Garde(_) => "validation_failed",
+            ErrorKind::Json(_) | ErrorKind::SerdeJson(_) | ErrorKind::Form(_) => "malformed_body",
+            ErrorKind::UnsupportedContentType(_) => "unsupported_content_type",
+            ErrorKind::MissingContentType => "missing_content_type",
+            ErrorKind::ApiKeyUnauthenticated => "api_key_unauthenticated",
+            ErrorKind::ApiKeyBadCredentials => "api_key_bad_credentials",
+            ErrorKind::UserUnauthenticated => "unauthenticated",
+            ErrorKind::Forbidden => "forbidden",
+            ErrorKind::InvalidSession => "invalid_session",
+            ErrorKind::InvalidState { .. } => "invalid_oauth_state",
+            ErrorKind::MissingHostHeader => "missing_host_header",
+            ErrorKind::InvalidCsrfToken => "invalid_csrf_token",
+            ErrorKind::CaptchaRequired => "captcha_required",
+            ErrorKind::NotEnoughMobiums => "not_enough_mobiums",
+            ErrorKind::BetsClosed(_) => "bets_closed",
+            ErrorKind::BailoutCooldown { .. } => "bailout_cooldown",
+            ErrorKind::WagerLimited { .. } => "wager_limited",
+            ErrorKind::Penalized { kind, .. } => match kind {
+                PenaltyKind::WagerBan => "wager_banned",
+                PenaltyKind::ChatMute => "chat_muted",
+                // warnings never keep users from doing anything
+                PenaltyKind::Warning => "forbidden",
+            },
+            ErrorKind::InvalidData(_) => "invalid_data",
+            ErrorKind::RateLimited(_) => "rate_limited",
+            ErrorKind::Busy => "busy",
+            ErrorKind::Draining => "draining",
+            ErrorKind::ReadOnlyReplica(_) => "read_only_replica",
+            // internal errors all look the same to clients
+            ErrorKind::CookieFetch(_)
+            | ErrorKind::Session(_)
+            | ErrorKind::Discord(_)
+            | ErrorKind::HttpClient(_)
+            | ErrorKind::WebSocket(_)
+            | ErrorKind::Database(_)
+            | ErrorKind::OutOfIds
+            | ErrorKind::Other(_) => INTERNAL_ERROR_CODE,
+        }
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(mut self) -> Response {
         let mut internal_error = None;
 
+        let retry_after = match self.kind {
+            ErrorKind::RateLimited(secs) => Some(secs),
+            // the primary is only ever a hop away
+            ErrorKind::ReadOnlyReplica(_) => Some(1),
+            _ => None,
+        };
+        let replay = match &self.kind {
+            ErrorKind::ReadOnlyReplica(primary) => {
+                HeaderValue::try_from(format!("instance={}", primary)).ok()
+            }
+            _ => None,
+        };
+
         let (status, error) = if self.is_internal() {
             internal_error = Some(Error {
                 kind: self.kind,
@@ -355,7 +685,8 @@ impl IntoResponse for Error {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 ApiError {
-                    message: "An internal server error occured.".into(),
+                    code: INTERNAL_ERROR_CODE.to_owned(),
+                    ..ApiError::new("An internal server error occured.")
                 },
             )
         } else {
@@ -363,6 +694,14 @@ impl IntoResponse for Error {
         };
 
         let mut response = (status, AppJson(error)).into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        if let Some(replay) = replay {
+            response.headers_mut().insert(FLY_REPLAY, replay);
+        }
         if let Some(error) = internal_error {
             response.extensions_mut().insert(Arc::new(error));
         }
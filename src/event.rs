@@ -0,0 +1,195 @@
+//! Promotional events.
+//!
+//! Admins schedule events to drive engagement for special streams. While an
+//! event runs, winnings are multiplied, and users can get a bonus on their
+//! first wager.
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::{UserId, event::PromoEvent};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{
+    app::AppState,
+    error::Error,
+    user::ledger::{LedgerReason, record_with_note},
+};
+
+/// A promotional event, as it is stored.
+#[derive(FromRow)]
+pub struct PromoEventSchema {
+    pub id: i32,
+    pub name: String,
+    pub payout_multiplier: f64,
+    pub first_wager_bonus: i64,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+impl From<PromoEventSchema> for PromoEvent {
+    fn from(value: PromoEventSchema) -> Self {
+        PromoEvent {
+            id: value.id,
+            name: value.name,
+            payout_multiplier: value.payout_multiplier,
+            first_wager_bonus: value.first_wager_bonus,
+            starts_at: value.starts_at,
+            ends_at: value.ends_at,
+        }
+    }
+}
+
+/// A first wager bonus given to a user.
+#[derive(Clone, Debug)]
+pub struct GrantedBonus {
+    /// The event the bonus was for.
+    pub event_id: i32,
+    /// How many mobiums the user was given.
+    pub bonus: i64,
+    /// The user's new mobiums.
+    pub mobiums: i64,
+}
+
+/// Lists the events running at `now`, soonest to end first.
+pub async fn active_events(
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<PromoEvent>, Error> {
+    let events = sqlx::query_as::<_, PromoEventSchema>(
+        r#"
+        SELECT id, name, payout_multiplier, first_wager_bonus, starts_at, ends_at
+        FROM promo_event
+        WHERE starts_at <= $1 AND ends_at > $1
+        ORDER BY ends_at ASC
+        "#,
+    )
+    .bind(now)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(events.into_iter().map(PromoEvent::from).collect())
+}
+
+/// Gets what winnings are multiplied by for a match whose bets closed at
+/// `closed_at`.
+///
+/// If events overlap, the biggest multiplier wins.
+pub async fn payout_multiplier(
+    closed_at: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<f64, Error> {
+    let (multiplier,) = sqlx::query_as::<_, (f64,)>(
+        r#"
+        SELECT IFNULL(MAX(payout_multiplier), 1.0)
+        FROM promo_event
+        WHERE starts_at <= $1 AND ends_at > $1
+        "#,
+    )
+    .bind(closed_at)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(multiplier.max(1.0))
+}
+
+/// Gives a user the first wager bonus of the running event, if they haven't
+/// gotten it yet.
+///
+/// If events overlap, the biggest bonus is given, once.
+pub async fn grant_first_wager_bonus(
+    user_id: UserId,
+    conn: &mut SqliteConnection,
+) -> Result<Option<GrantedBonus>, Error> {
+    let now = Utc::now();
+
+    let event = sqlx::query_as::<_, (i32, String, i64)>(
+        r#"
+        SELECT id, name, first_wager_bonus
+        FROM promo_event
+        WHERE starts_at <= $1 AND ends_at > $1 AND first_wager_bonus > 0
+        ORDER BY first_wager_bonus DESC, id ASC
+        LIMIT 1
+        "#,
+    )
+    .bind(now)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((event_id, name, bonus)) = event else {
+        return Ok(None);
+    };
+
+    let granted = sqlx::query(
+        r#"
+        INSERT INTO promo_event_bonus (event_id, user_id, inserted_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (event_id, user_id) DO NOTHING
+        "#,
+    )
+    .bind(event_id)
+    .bind(user_id)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if !granted {
+        return Ok(None);
+    }
+
+    let (mobiums,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        UPDATE user
+        SET mobiums = mobiums + $2
+        WHERE id = $1
+        RETURNING mobiums
+        "#,
+    )
+    .bind(user_id)
+    .bind(bonus)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    record_with_note(
+        user_id,
+        bonus,
+        LedgerReason::PromoBonus,
+        Some(&name),
+        &mut *conn,
+    )
+    .await?;
+
+    Ok(Some(GrantedBonus {
+        event_id,
+        bonus,
+        mobiums,
+    }))
+}
+
+/// Tells connected clients about events that just started.
+///
+/// Each event is only announced once.
+pub async fn announce_started_events(state: &AppState) -> Result<(), Error> {
+    let now = Utc::now();
+
+    let events = sqlx::query_as::<_, PromoEventSchema>(
+        r#"
+        UPDATE promo_event
+        SET announced_at = $1
+        WHERE announced_at IS NULL AND starts_at <= $1 AND ends_at > $1
+        RETURNING id, name, payout_multiplier, first_wager_bonus, starts_at, ends_at
+        "#,
+    )
+    .bind(now)
+    .fetch_all(&state.db)
+    .await?;
+
+    for event in events {
+        tracing::info!(id = event.id, name = %event.name, "promo event started");
+        state.room.send_promo_active(event.into());
+    }
+
+    Ok(())
+}
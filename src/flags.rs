@@ -0,0 +1,218 @@
+//! Feature flags.
+//!
+//! Flags live in the `feature_flag` table, so features can be turned on for
+//! a deployment, or rolled out to a percentage of users, without a release.
+//! Each instance keeps a copy of the flags, refreshed periodically.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::{UserId, admin::FeatureFlag};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::error::Error;
+
+/// Makes matches pay out at locked odds by default.
+///
+/// Servers can still pick either mode when creating a match.
+pub const LOCKED_ODDS: &str = "locked_odds";
+
+/// The longest a flag name can be.
+pub const MAX_FLAG_NAME_LENGTH: usize = 64;
+
+/// The feature flags, as of the last refresh.
+///
+/// Flags that don't exist are off. Cheaply cloneable.
+#[derive(Clone, Debug, Default)]
+pub struct Flags(Arc<RwLock<HashMap<String, Flag>>>);
+
+#[derive(Clone, Copy, Debug)]
+struct Flag {
+    enabled: bool,
+    rollout: u8,
+}
+
+#[derive(FromRow)]
+struct FlagQuery {
+    name: String,
+    enabled: bool,
+    rollout: u8,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<FlagQuery> for FeatureFlag {
+    fn from(value: FlagQuery) -> Self {
+        FeatureFlag {
+            name: value.name,
+            enabled: value.enabled,
+            rollout: value.rollout,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
+impl Flags {
+    /// Whether a flag is on for everyone.
+    ///
+    /// Flags that are only rolled out to some users are off here.
+    pub fn enabled(&self, name: &str) -> bool {
+        self.get(name)
+            .is_some_and(|flag| flag.enabled && flag.rollout >= 100)
+    }
+
+    /// Whether a flag is on for a user.
+    ///
+    /// Users stay on the same side of a rollout as it grows.
+    pub fn enabled_for(&self, name: &str, user_id: UserId) -> bool {
+        self.get(name)
+            .is_some_and(|flag| flag.enabled && rollout_bucket(name, user_id) < flag.rollout)
+    }
+
+    /// Reloads the flags from the database.
+    pub async fn refresh(&self, conn: &mut SqliteConnection) -> Result<(), Error> {
+        let flags = list_flags(conn)
+            .await?
+            .into_iter()
+            .map(|flag| {
+                let state = Flag {
+                    enabled: flag.enabled,
+                    rollout: flag.rollout,
+                };
+                (flag.name, state)
+            })
+            .collect();
+
+        *self.0.write().expect("flags poisoned") = flags;
+        Ok(())
+    }
+
+    /// Updates a single flag, without waiting for the next refresh.
+    pub fn update(&self, flag: &FeatureFlag) {
+        self.0.write().expect("flags poisoned").insert(
+            flag.name.clone(),
+            Flag {
+                enabled: flag.enabled,
+                rollout: flag.rollout,
+            },
+        );
+    }
+
+    fn get(&self, name: &str) -> Option<Flag> {
+        self.0.read().expect("flags poisoned").get(name).copied()
+    }
+}
+
+/// Lists every flag, by name.
+pub async fn list_flags(conn: &mut SqliteConnection) -> Result<Vec<FeatureFlag>, Error> {
+    let flags = sqlx::query_as::<_, FlagQuery>(
+        r#"
+        SELECT name, enabled, rollout, updated_at
+        FROM feature_flag
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(flags.into_iter().map(FeatureFlag::from).collect())
+}
+
+/// Creates or updates a flag.
+pub async fn set_flag(
+    name: &str,
+    enabled: bool,
+    rollout: u8,
+    conn: &mut SqliteConnection,
+) -> Result<FeatureFlag, Error> {
+    let flag = sqlx::query_as::<_, FlagQuery>(
+        r#"
+        INSERT INTO feature_flag (name, enabled, rollout, inserted_at, updated_at)
+        VALUES ($1, $2, $3, $4, $4)
+        ON CONFLICT (name) DO UPDATE
+        SET enabled = excluded.enabled, rollout = excluded.rollout, updated_at = excluded.updated_at
+        RETURNING name, enabled, rollout, updated_at
+        "#,
+    )
+    .bind(name)
+    .bind(enabled)
+    .bind(rollout)
+    .bind(Utc::now())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(flag.into())
+}
+
+/// Checks if a flag name is made of lowercase letters, digits, `_` and `-`.
+pub fn is_valid_flag_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_FLAG_NAME_LENGTH
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'-')
+}
+
+/// Places a user somewhere from 0 to 99 in a flag's rollout.
+///
+/// Each flag shuffles users differently, so the same users aren't always
+/// first to try new features.
+fn rollout_bucket(name: &str, user_id: UserId) -> u8 {
+    // FNV-1a, which is stable between builds, unlike the std hasher
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in name.bytes().chain(user_id.0.to_le_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    (hash % 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(name: &str, enabled: bool, rollout: u8) -> Flags {
+        let flags = Flags::default();
+        flags.update(&FeatureFlag {
+            name: name.to_owned(),
+            enabled,
+            rollout,
+            updated_at: DateTime::UNIX_EPOCH,
+        });
+        flags
+    }
+
+    #[test]
+    pub fn test_rollout_grows() {
+        let users = (0..1000).map(UserId).collect::<Vec<_>>();
+
+        let mut previous = Vec::new();
+        for rollout in [0, 10, 50, 100] {
+            let flags = flags("parlays", true, rollout);
+            let on = users
+                .iter()
+                .copied()
+                .filter(|&user_id| flags.enabled_for("parlays", user_id))
+                .collect::<Vec<_>>();
+
+            // nobody is rolled back as the rollout grows
+            assert!(previous.iter().all(|user_id| on.contains(user_id)));
+            previous = on;
+        }
+
+        assert_eq!(previous.len(), users.len());
+    }
+
+    #[test]
+    pub fn test_partial_rollout_is_off_globally() {
+        assert!(!flags("gifts", true, 50).enabled("gifts"));
+        assert!(flags("gifts", true, 100).enabled("gifts"));
+        assert!(!flags("gifts", false, 100).enabled("gifts"));
+        assert!(!Flags::default().enabled("gifts"));
+    }
+}
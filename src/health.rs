@@ -0,0 +1,78 @@
+//! Service health tracking.
+//!
+//! Used by the `/healthz` and `/readyz` probes.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicI64, Ordering},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use sqlx::{SqlitePool, migrate::Migrator};
+
+/// The migrations the server was built with.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// How long the scheduler can go without ticking before it is considered
+/// dead.
+pub const SCHEDULER_TIMEOUT: TimeDelta = TimeDelta::seconds(60);
+
+/// Health state of the server.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug, Default)]
+pub struct Health {
+    /// Unix timestamp of the last scheduler tick, or `0` if it hasn't ticked
+    /// yet.
+    last_tick: Arc<AtomicI64>,
+}
+
+impl Health {
+    /// Creates a new `Health`.
+    pub fn new() -> Health {
+        Health::default()
+    }
+
+    /// Records a scheduler tick.
+    pub fn tick(&self) {
+        self.last_tick
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    /// The time of the last scheduler tick.
+    pub fn last_tick(&self) -> Option<DateTime<Utc>> {
+        match self.last_tick.load(Ordering::Relaxed) {
+            0 => None,
+            secs => DateTime::from_timestamp(secs, 0),
+        }
+    }
+
+    /// Checks if the scheduler has ticked recently.
+    pub fn scheduler_running(&self) -> bool {
+        self.last_tick()
+            .map(|last_tick| Utc::now() - last_tick < SCHEDULER_TIMEOUT)
+            .unwrap_or(false)
+    }
+}
+
+/// Counts how many migrations have yet to be applied to the database.
+pub async fn pending_migrations(db: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let applied = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT version
+        FROM _sqlx_migrations
+        WHERE success = TRUE
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    let pending = MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .filter(|migration| !applied.iter().any(|(v,)| *v == migration.version))
+        .count();
+
+    Ok(pending)
+}
@@ -0,0 +1,125 @@
+//! Highlight detection.
+//!
+//! Once a match is concluded, it is checked for anything that makes it worth
+//! watching again. Highlights are stored on the match as a bitmask of
+//! [`HighlightTag`]s.
+
+use ring_channel_model::{
+    BattleId,
+    battle::{BattleStatus, HighlightTag, PlayerTeam},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{config::HighlightConfig, error::Error};
+
+/// Checks a concluded match for highlights, storing any it finds.
+///
+/// Returns the highlights found, if any.
+pub async fn detect_highlights(
+    battle_id: BattleId,
+    config: &HighlightConfig,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<HighlightTag>, Error> {
+    #[derive(FromRow)]
+    struct FinisherQuery {
+        team: PlayerTeam,
+        finish_time: i32,
+    }
+
+    if !config.enabled {
+        return Ok(vec![]);
+    }
+
+    let mut tags = Vec::new();
+
+    let finishers = sqlx::query_as::<_, FinisherQuery>(
+        r#"
+        SELECT team, finish_time
+        FROM participant
+        WHERE
+            match_id = $1
+            AND NOT no_contest
+            AND finish_time IS NOT NULL
+        ORDER BY finish_time ASC
+        LIMIT 2
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // nobody finished, so there is nothing to celebrate
+    let Some(winner) = finishers.first() else {
+        return Ok(tags);
+    };
+
+    if let [first, second] = finishers.as_slice()
+        && first.team != second.team
+        && second.finish_time - first.finish_time <= config.photo_finish_margin
+    {
+        tags.push(HighlightTag::PhotoFinish);
+    }
+
+    let (red_win_probability,) = sqlx::query_as::<_, (Option<f64>,)>(
+        r#"
+        SELECT red_win_probability
+        FROM battle
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // unrated matches can't be upsets
+    if let Some(red) = red_win_probability {
+        let chance = match winner.team {
+            PlayerTeam::Red => red,
+            PlayerTeam::Blue => 1.0 - red,
+        };
+
+        if chance <= config.upset_chance {
+            tags.push(HighlightTag::Upset);
+        }
+    }
+
+    let (pot, record) = sqlx::query_as::<_, (i64, i64)>(
+        r#"
+        SELECT
+            (SELECT IFNULL(SUM(mobiums), 0) FROM wager WHERE match_id = $1),
+            (
+                SELECT IFNULL(MAX(pot), 0)
+                FROM (
+                    SELECT SUM(w.mobiums) AS pot
+                    FROM wager w
+                    INNER JOIN battle b ON b.id = w.match_id
+                    WHERE b.id != $1 AND b.status = $2
+                    GROUP BY w.match_id
+                )
+            )
+        "#,
+    )
+    .bind(battle_id)
+    .bind(BattleStatus::Concluded)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if pot >= config.min_record_pot && pot > record {
+        tags.push(HighlightTag::RecordPot);
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE battle
+        SET highlights = $2
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .bind(HighlightTag::to_mask(&tags))
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(tags)
+}
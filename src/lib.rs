@@ -3,13 +3,40 @@
 //! This provides a backend for the betting system of the Duel Channel.
 
 pub mod app;
+pub mod audit;
 pub mod auth;
+pub mod avatar;
 pub mod battle;
+pub mod calibration;
 pub mod cli;
 pub mod config;
+pub mod deadline;
+pub mod economy;
 pub mod error;
+pub mod event;
+pub mod flags;
+pub mod health;
+pub mod highlight;
+pub mod litefs;
+pub mod matchmaking;
+pub mod metrics;
+pub mod payout;
 pub mod player;
+pub mod push;
+pub mod ratelimit;
+pub mod replica;
+pub mod retention;
 pub mod room;
 pub mod routes;
+pub mod seed;
 pub mod session;
+pub mod slow_query;
+pub mod squad;
+pub mod stats;
+pub mod supervisor;
+#[cfg(test)]
+pub mod testing;
+pub mod timings;
 pub mod user;
+pub mod wager_queue;
+pub mod webhook;
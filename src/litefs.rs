@@ -0,0 +1,199 @@
+//! LiteFS write forwarding.
+//!
+//! LiteFS replicates the database to every node, but only the primary can
+//! write to it. Requests that write are checked against the node's role
+//! before they reach a handler: on replicas, they are forwarded to the primary
+//! if one is configured, or turned away with a `Fly-Replay` header otherwise.
+//!
+//! A few reads write too, like the Discord login callback, and are forwarded
+//! like writes.
+//!
+//! Websockets aren't forwarded. Clients should connect to the primary, since
+//! the room lives there anyway. The same goes for sessions: the session store
+//! writes whenever a session is touched, so requests carrying a session cookie
+//! have to be routed to the primary by the proxy in front.
+//!
+//! Background work that writes, like the wager writer and most scheduled
+//! jobs, only runs while the node is the primary.
+
+use std::{io, path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use http::{HeaderName, HeaderValue, Method, header};
+
+use crate::{
+    config::LiteFsConfig,
+    error::{Error, ErrorKind},
+};
+
+/// The header marking a request that was already forwarded once.
+///
+/// A request forwarded to a node that turned out to be a replica, like during
+/// a failover, is turned away instead of forwarded again.
+pub const X_FORWARDED_WRITE: HeaderName = HeaderName::from_static("x-forwarded-write");
+
+/// How long forwarded requests can take.
+pub const FORWARD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest request body that is forwarded.
+pub const MAX_FORWARDED_BODY: usize = 2 * 1024 * 1024;
+
+/// How often a replica checks whether it was promoted.
+pub const PROMOTION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Paths that write even on reads.
+///
+/// Logging in with Discord creates the user and writes to the session.
+const WRITING_READS: [&str; 2] = ["/users/~redirect", "/users/~login"];
+
+/// Headers that only mean something for a single connection.
+const HOP_BY_HOP_HEADERS: [HeaderName; 4] = [
+    header::CONNECTION,
+    header::HOST,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Where writes made on a replica go.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct WriteForwarding {
+    inner: Arc<WriteForwardingInner>,
+}
+
+#[derive(Debug)]
+struct WriteForwardingInner {
+    primary_file: PathBuf,
+    primary_url: Option<String>,
+    http_client: reqwest::Client,
+}
+
+impl WriteForwarding {
+    /// Creates a new `WriteForwarding` from config.
+    pub fn new(config: &LiteFsConfig) -> Result<WriteForwarding, Error> {
+        // redirects are for the client to follow
+        let http_client = reqwest::Client::builder()
+            .timeout(FORWARD_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(WriteForwarding {
+            inner: Arc::new(WriteForwardingInner {
+                primary_file: config.mount_dir.join(".primary"),
+                primary_url: config
+                    .primary_url
+                    .as_ref()
+                    .map(|url| url.trim_end_matches('/').to_owned()),
+                http_client,
+            }),
+        })
+    }
+
+    /// The hostname of the primary, or `None` if this node is the primary.
+    pub async fn primary(&self) -> Result<Option<String>, Error> {
+        match tokio::fs::read_to_string(&self.inner.primary_file).await {
+            Ok(primary) => Ok(Some(primary.trim().to_owned())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::new(err)),
+        }
+    }
+
+    /// Whether this node is the primary.
+    pub async fn is_primary(&self) -> Result<bool, Error> {
+        self.primary().await.map(|primary| primary.is_none())
+    }
+
+    /// Waits until this node is the primary.
+    pub async fn wait_until_primary(&self) {
+        loop {
+            match self.is_primary().await {
+                Ok(true) => return,
+                Ok(false) => (),
+                Err(err) => tracing::warn!(%err, "failed to check litefs primary"),
+            }
+
+            tokio::time::sleep(PROMOTION_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn forward(&self, primary_url: &str, request: Request) -> Result<Response, Error> {
+        let (parts, body) = request.into_parts();
+
+        let path = parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+        let body = body::to_bytes(body, MAX_FORWARDED_BODY)
+            .await
+            .map_err(|_| ErrorKind::InvalidData("Request body is too large".into()))?;
+
+        let mut headers = parts.headers;
+        for name in HOP_BY_HOP_HEADERS {
+            headers.remove(name);
+        }
+        headers.insert(X_FORWARDED_WRITE, HeaderValue::from_static("true"));
+
+        let forwarded = self
+            .inner
+            .http_client
+            .request(parts.method, format!("{}{}", primary_url, path))
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+
+        let status = forwarded.status();
+        let mut headers = forwarded.headers().clone();
+        for name in HOP_BY_HOP_HEADERS {
+            headers.remove(name);
+        }
+        let body = forwarded.bytes().await?;
+
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+
+        Ok(response)
+    }
+}
+
+/// Middleware that keeps writes off of replicas.
+pub async fn forward_writes(
+    State(forwarding): State<WriteForwarding>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if is_read(request.method()) && !WRITING_READS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let primary = match forwarding.primary().await {
+        Ok(Some(primary)) => primary,
+        Ok(None) => return next.run(request).await,
+        Err(err) => return err.into_response(),
+    };
+
+    match forwarding.inner.primary_url.as_deref() {
+        Some(primary_url) if !request.headers().contains_key(X_FORWARDED_WRITE) => {
+            tracing::debug!(%primary, uri = %request.uri(), "forwarding write to primary");
+
+            forwarding
+                .forward(primary_url, request)
+                .await
+                .unwrap_or_else(IntoResponse::into_response)
+        }
+        _ => Error::from(ErrorKind::ReadOnlyReplica(primary)).into_response(),
+    }
+}
+
+fn is_read(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
@@ -1,36 +1,72 @@
-use std::{env, fmt::Debug, io, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{env, fmt::Debug, io, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc};
 
 use eyre::OptionExt as _;
-use http::{HeaderValue, Method, header};
+use http::{HeaderName, HeaderValue, Method, header};
 
 // :(
 use time::Duration;
 
+use chrono::Utc;
+
 use clap::{CommandFactory as _, Parser};
 
 use axum::{
     Extension, Router,
     extract::{MatchedPath, Request},
-    middleware::{Next, from_fn},
+    middleware::{Next, from_fn, from_fn_with_state},
     response::{IntoResponse, Response},
-    routing::{get, patch, post, put},
+    routing::{delete, get, patch, post, put},
 };
 
 use axum_server::Handle;
 
 use ring_channel::{
     app::{AppState, Model, Unrated},
-    auth::oauth2::OauthState,
-    cli::{self, Args, Command, MmrCommand, MmrDump},
-    config::{Config, RatingModelConfig, read_config},
+    audit,
+    auth::{
+        captcha::Captcha,
+        oauth2::{OauthState, refresh_stale_tokens},
+    },
+    avatar::Avatars,
+    battle::{cancel_no_show_battles, finalize_due_battles},
+    calibration::rollup_calibration,
+    cli::{self, Args, Command, EconomyCommand, MmrCommand, MmrDump, MmrSimulate},
+    config::{Config, DrainConfig, RatingModelConfig, read_config},
+    deadline::BetDeadlines,
     error::Error,
-    player::mmr::{self, glicko2::Glicko2, init_rating, next_rating_period, openskill::OpenSkill},
-    room, routes,
+    event::announce_started_events,
+    flags::Flags,
+    health::Health,
+    litefs::{WriteForwarding, forward_writes},
+    metrics::Metrics,
+    player::mmr::{
+        self, distribution::record_distribution, glicko2::Glicko2, init_rating, next_rating_period,
+        openskill::OpenSkill,
+    },
+    push::{Notifier, generate_vapid_keys},
+    ratelimit::{RateLimiter, rate_limit},
+    replica::ReadPool,
+    retention::{self, SESSION_TABLE},
+    room::{
+        self,
+        event_log::{EventLog, run_event_log_writer},
+    },
+    routes,
+    slow_query::SlowQueries,
+    stats::rollup_daily_stats,
+    supervisor::Supervisor,
+    timings,
+    user::bot::{BotSwitch, reset_bankroll},
+    wager_queue::{WagerQueue, run_wager_writer},
+    webhook::Dispatcher,
 };
 
-use sqlx::{Connection, SqliteConnection, pool::PoolOptions};
+use sqlx::{
+    ConnectOptions as _, Connection, SqliteConnection, pool::PoolOptions,
+    sqlite::SqliteConnectOptions,
+};
 
-use tokio::{main, select, signal, sync::Semaphore};
+use tokio::{main, select, signal, sync::Mutex};
 
 use tokio_cron_scheduler::{Job, JobScheduler};
 use tower_http::{
@@ -53,11 +89,15 @@ use tracing_subscriber::{
 const OPENAPI_FILE: &str =
     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/openapi/openapi.yaml"));
 
+/// Marks responses from a sandbox.
+const X_SANDBOX: HeaderName = HeaderName::from_static("x-sandbox");
+
 #[main]
 async fn main() -> eyre::Result<()> {
     dotenv::dotenv().ok();
 
     let registry = tracing_subscriber::registry();
+    let slow_queries = SlowQueries::new();
 
     let fmt_layer = fmt::layer().with_writer(io::stderr);
     let filter_layer = EnvFilter::builder()
@@ -67,7 +107,10 @@ async fn main() -> eyre::Result<()> {
     #[cfg(feature = "tracy")]
     let registry = registry.with(tracing_tracy::TracyLayer::default());
 
-    let registry = registry.with(filter_layer).with(fmt_layer);
+    let registry = registry
+        .with(filter_layer)
+        .with(fmt_layer)
+        .with(slow_queries.layer());
     tracing::subscriber::set_global_default(registry)?;
 
     let cli = Args::parse();
@@ -80,21 +123,63 @@ async fn main() -> eyre::Result<()> {
     // Read config file
     let config = read_config(config_path)?;
 
+    // Simulations compare two rating models, so they don't use the server's
+    if let Some(Command::Mmr(cli::Mmr {
+        command: Some(MmrCommand::Simulate(simulate)),
+    })) = &cli.command
+    {
+        return simulate_mmr(simulate, &config).await;
+    }
+
     // Setup MMR w/ config
     match &config.mmr {
-        RatingModelConfig::Unrated => with_rating_model(cli, config, Unrated).await,
+        RatingModelConfig::Unrated => with_rating_model(cli, config, Unrated, slow_queries).await,
         RatingModelConfig::Glicko2(mmr_config) => {
             let model = Glicko2::new(mmr_config.clone());
-            with_rating_model(cli, config, model).await
+            with_rating_model(cli, config, model, slow_queries).await
         }
         RatingModelConfig::OpenSkill(mmr_config) => {
             let model = OpenSkill::new(mmr_config.clone()).await?;
-            with_rating_model(cli, config, model).await
+            with_rating_model(cli, config, model, slow_queries).await
         }
     }
 }
 
-async fn with_rating_model<T>(cli: Args, mut config: Config, model: T) -> eyre::Result<()>
+async fn simulate_mmr(command: &MmrSimulate, config: &Config) -> eyre::Result<()> {
+    let database_url = config
+        .server
+        .database_url
+        .as_deref()
+        .ok_or_eyre("No `DATABASE_URL` set!")?;
+
+    // establish connection
+    let mut conn = SqliteConnection::connect(database_url).await?;
+
+    match &config.mmr {
+        RatingModelConfig::Unrated => return Err(eyre::eyre!("ratings are disabled")),
+        RatingModelConfig::Glicko2(mmr_config) => {
+            let current = Glicko2::new(mmr_config.clone());
+            let changed = Glicko2::new(command.glicko2(mmr_config.clone())?);
+            cli::simulate_mmr(command, &current, &changed, &mut conn).await?;
+        }
+        RatingModelConfig::OpenSkill(mmr_config) => {
+            let current = OpenSkill::new(mmr_config.clone()).await?;
+            let changed = OpenSkill::new(command.openskill(mmr_config.clone())?).await?;
+            cli::simulate_mmr(command, &current, &changed, &mut conn).await?;
+        }
+    }
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+async fn with_rating_model<T>(
+    cli: Args,
+    mut config: Config,
+    model: T,
+    slow_queries: SlowQueries,
+) -> eyre::Result<()>
 where
     T: Debug + Clone + Send + Sync + mmr::Model + 'static,
     T::Data: Debug,
@@ -104,6 +189,7 @@ where
         .database_url
         .take()
         .ok_or_eyre("No `DATABASE_URL` set!")?;
+    let replica_database_url = config.server.replica_database_url.take();
 
     // Run any pending commands
     if let Some(command) = cli.command.as_ref() {
@@ -120,6 +206,22 @@ where
                 tx.commit().await?;
                 conn.close().await?;
             }
+            Command::Admin(admin) => {
+                // establish connection
+                let mut conn = SqliteConnection::connect(&database_url).await?;
+                let mut tx = conn.begin().await?;
+
+                if admin.revoke {
+                    tracing::info!("revoking admin from {}", admin.username);
+                } else {
+                    tracing::info!("granting admin to {}", admin.username);
+                }
+
+                cli::set_admin(admin, &mut tx).await?;
+
+                tx.commit().await?;
+                conn.close().await?;
+            }
             Command::GenerateKey(_) => {
                 tracing::info!("generated! set ENCRYPTION_KEY or server.encryption_key on boot");
 
@@ -127,6 +229,13 @@ where
                 let key = base16::encode_lower(key.master());
                 println!("{}", key);
             }
+            Command::GenerateVapidKeys(_) => {
+                tracing::info!("generated! set push.public_key and push.private_key on boot");
+
+                let (public_key, private_key) = generate_vapid_keys();
+                println!("public_key = \"{}\"", public_key);
+                println!("private_key = \"{}\"", private_key);
+            }
             Command::Mmr(cli::Mmr {
                 command: Some(MmrCommand::Reset(_)),
             }) => {
@@ -183,9 +292,45 @@ where
                 // rollback transaction
                 tx.rollback().await?;
             }
+            Command::Mmr(cli::Mmr {
+                command: Some(MmrCommand::Simulate(_)),
+            }) => {
+                // already run before the rating model was set up
+            }
             Command::Mmr(cli::Mmr { command: None }) => {
                 Args::command().print_help().unwrap();
             }
+            Command::Economy(cli::Economy {
+                command: Some(EconomyCommand::Report(report)),
+            }) => {
+                // establish connection
+                let mut conn = SqliteConnection::connect(&database_url).await?;
+
+                cli::economy_report(report, &mut conn).await?;
+
+                conn.close().await?;
+            }
+            Command::Economy(cli::Economy { command: None }) => {
+                Args::command().print_help().unwrap();
+            }
+            Command::Cleanup(cleanup) => {
+                // establish connection
+                let mut conn = SqliteConnection::connect(&database_url).await?;
+
+                cli::cleanup(cleanup, &config.server.retention, &mut conn).await?;
+
+                conn.close().await?;
+            }
+            Command::Seed(seed) => {
+                // establish connection
+                let mut conn = SqliteConnection::connect(&database_url).await?;
+
+                tracing::info!("seeding database...");
+
+                cli::seed(seed, &model, &mut conn).await?;
+
+                conn.close().await?;
+            }
         }
 
         return Ok(());
@@ -218,23 +363,116 @@ where
     tracing::info!("establishing connection to database");
 
     // Connect to sqlite database
-    let db = PoolOptions::new().connect(&database_url).await?;
+    let db = PoolOptions::new()
+        .connect_with(connect_options(&database_url, &config)?)
+        .await?;
+
+    // The replica may not be up yet, so it is connected to lazily, and only
+    // used once it passes a health check
+    let replica = match replica_database_url {
+        Some(replica_database_url) => {
+            tracing::info!("using read replica");
+
+            let replica_options = connect_options(&replica_database_url, &config)?.read_only(true);
+            Some(PoolOptions::new().connect_lazy_with(replica_options))
+        }
+        None => None,
+    };
+
+    let captcha = config
+        .server
+        .anti_abuse
+        .captcha
+        .as_mut()
+        .map(|captcha| {
+            let verifier = Captcha::new(captcha);
+            // keep the secret out of the shared config
+            captcha.secret.clear();
+            verifier
+        })
+        .transpose()?;
+
+    let flags = Flags::default();
+    flags.refresh(&mut *db.acquire().await?).await?;
+
+    // LiteFS replicas can't write, so writes have to happen on the primary
+    let forwarding = config
+        .server
+        .litefs
+        .enabled
+        .then(|| WriteForwarding::new(&config.server.litefs))
+        .transpose()?;
 
     // Create app state
+    let metrics = Metrics::new();
+    let (wagers, wager_receiver) = WagerQueue::new(metrics.clone());
+    let (event_log, event_log_receiver) = EventLog::new(&config.http.websocket.event_log);
     let state = AppState {
         config: Arc::new(config.clone()),
         db: db.clone(),
-        room: room::Room::new(),
+        replica: ReadPool::new(db.clone(), replica),
+        room: room::Room::new(config.server.milestones.clone(), metrics.clone(), event_log),
+        health: Health::new(),
+        avatars: config.avatars.as_ref().map(Avatars::new),
+        metrics,
+        slow_queries,
+        wagers,
+        bot: BotSwitch::new(config.server.bot.enabled),
+        deadlines: BetDeadlines::default(),
+        captcha,
+        flags,
+        tasks: match forwarding.clone() {
+            Some(forwarding) => Supervisor::new().with_write_forwarding(forwarding),
+            None => Supervisor::new(),
+        },
     };
 
+    // the receivers are kept around, so restarted writers pick up where
+    // they left off
+    let state_clone = state.clone();
+    let wager_receiver = Arc::new(Mutex::new(wager_receiver));
+    state.tasks.spawn_writer("wager_writer", move || {
+        let state = state_clone.clone();
+        let receiver = wager_receiver.clone();
+
+        async move {
+            run_wager_writer(state, &mut *receiver.lock().await).await;
+            Ok::<_, Error>(())
+        }
+    });
+    if let Some(receiver) = event_log_receiver {
+        let db = db.clone();
+        let receiver = Arc::new(Mutex::new(receiver));
+        state.tasks.spawn_writer("event_log_writer", move || {
+            let db = db.clone();
+            let receiver = receiver.clone();
+
+            async move {
+                run_event_log_writer(db, &mut *receiver.lock().await).await;
+                Ok::<_, Error>(())
+            }
+        });
+    }
+
     // Build routes
     let mut api_routes = Router::<AppState>::new()
+        .route("/announcements/active", get(routes::announcement::active))
+        .route("/avatars/{username}", get(routes::avatar::show))
+        .route("/errors", get(routes::error::list))
+        .route("/events/active", get(routes::event::active))
+        .route("/leaderboard", get(routes::leaderboard::show))
+        .route("/push/vapid-key", get(routes::user::push::vapid_key))
         .route("/socket", get(routes::ws::handler))
+        .route("/socket/schema", get(routes::ws::schema))
+        .route("/socket/tickets", post(routes::ws::create_ticket))
         .nest(
             "/players",
             Router::<AppState>::new()
+                .route("/", get(routes::player::search::<T>))
                 .route("/", post(routes::player::register::<T>))
-                .route("/{player_id}", get(routes::player::show::<T>)),
+                .route("/{player_id}", get(routes::player::show::<T>))
+                .route("/{player_id}/aliases", get(routes::player::aliases))
+                .route("/{player_id}/stats", get(routes::player::stats)),
         )
         .nest(
             "/matches",
@@ -246,16 +484,41 @@ where
                     Router::<AppState>::new()
                         .route("/", get(routes::battle::show::<T>))
                         .route("/", patch(routes::battle::update::<T>))
+                        .route("/commentary", get(routes::battle::commentary::list))
+                        .route("/commentary", post(routes::battle::commentary::create))
                         .route(
                             "/players/{short_id}",
                             patch(routes::battle::player::update::<T>),
                         )
+                        .route("/line-wagers", get(routes::battle::line::list))
+                        .route("/line-wagers/~me", get(routes::battle::line::show_self))
+                        .route("/line-wagers/~me", put(routes::battle::line::create))
+                        .route("/loadouts", get(routes::battle::player::loadouts))
+                        .route("/pot-history", get(routes::battle::wager::pot_history))
+                        .route("/squads", get(routes::battle::wager::squads))
                         .route("/wagers", get(routes::battle::wager::list))
                         .route("/wagers/~me", get(routes::battle::wager::show_self))
                         .route("/wagers/~me", put(routes::battle::wager::create))
+                        .route(
+                            "/wagers/suggestions",
+                            get(routes::battle::wager::suggestions),
+                        )
                         .route("/wagers/{username}", get(routes::battle::wager::show)),
                 ),
         )
+        .nest(
+            "/squads",
+            Router::<AppState>::new()
+                .route("/", get(routes::squad::list))
+                .route("/", post(routes::squad::create))
+                .route("/{name}", get(routes::squad::show))
+                .route("/{name}/members", get(routes::squad::members))
+                .route("/{name}/members", post(routes::squad::join)),
+        )
+        .route(
+            "/matchmaking/suggest",
+            post(routes::matchmaking::suggest::<T>),
+        )
         .nest(
             "/servers",
             Router::<AppState>::new()
@@ -264,22 +527,149 @@ where
         )
         .nest(
             "/chat",
-            Router::<AppState>::new().route("/messages", post(routes::chat::create::<T>)),
+            Router::<AppState>::new()
+                .route("/messages", get(routes::chat::list::<T>))
+                .route("/messages", post(routes::chat::create::<T>))
+                .route("/messages/{message_id}", patch(routes::chat::update::<T>))
+                .route("/messages/{message_id}", delete(routes::chat::delete))
+                .route("/messages/{message_id}/reactions", put(routes::chat::react))
+                .route(
+                    "/messages/{message_id}/reactions",
+                    delete(routes::chat::unreact),
+                ),
+        )
+        .nest(
+            "/admin",
+            Router::<AppState>::new()
+                .route("/announcements", post(routes::admin::announcement::create))
+                .route(
+                    "/announcements/{announcement_id}",
+                    delete(routes::admin::announcement::delete),
+                )
+                .route("/audit", get(routes::admin::audit::list))
+                .route("/bot", get(routes::admin::bot::show))
+                .route("/bot", patch(routes::admin::bot::update))
+                .route("/bot/stats", get(routes::admin::bot::stats))
+                .route("/economy", get(routes::admin::economy::show))
+                .route(
+                    "/economy/corrections",
+                    post(routes::admin::economy::import_corrections),
+                )
+                .route(
+                    "/economy/ledger",
+                    get(routes::admin::economy::export_ledger),
+                )
+                .route(
+                    "/economy/wagers",
+                    get(routes::admin::economy::export_wagers),
+                )
+                .route("/events", get(routes::admin::event::list))
+                .route("/events", post(routes::admin::event::create))
+                .route("/events/{event_id}", delete(routes::admin::event::delete))
+                .route("/flags", get(routes::admin::flag::list))
+                .route("/flags/{name}", put(routes::admin::flag::update))
+                .route(
+                    "/matches/{battle_id}/commentary",
+                    post(routes::admin::battle::add_commentary),
+                )
+                .route(
+                    "/matches/{battle_id}/finalize",
+                    post(routes::admin::battle::finalize::<T>),
+                )
+                .route(
+                    "/matches/{battle_id}/players/{short_id}",
+                    patch(routes::admin::battle::update_placement::<T>),
+                )
+                .route(
+                    "/penalties/{penalty_id}",
+                    delete(routes::admin::penalty::delete),
+                )
+                .route(
+                    "/players/{short_id}/display-name",
+                    put(routes::admin::player::update_display_name::<T>),
+                )
+                .route("/room/events", get(routes::admin::room::events))
+                .route("/slow-queries", get(routes::admin::slow_query::list))
+                .route("/tasks", get(routes::admin::task::list))
+                .route("/sockets", get(routes::admin::socket::list))
+                .route("/sockets/drain", post(routes::admin::socket::drain))
+                .route(
+                    "/sockets/{socket_id}",
+                    delete(routes::admin::socket::delete),
+                )
+                .route(
+                    "/users/{username}/penalties",
+                    get(routes::admin::penalty::list),
+                )
+                .route(
+                    "/users/{username}/penalties",
+                    post(routes::admin::penalty::create),
+                )
+                .route("/webhooks", get(routes::admin::webhook::list))
+                .route("/webhooks", post(routes::admin::webhook::create))
+                .route(
+                    "/webhooks/{webhook_id}",
+                    delete(routes::admin::webhook::delete),
+                ),
+        )
+        .nest(
+            "/mmr",
+            Router::<AppState>::new().route("/periods", get(routes::mmr::periods::<T>)),
+        )
+        .nest(
+            "/stats",
+            Router::<AppState>::new()
+                .route("/daily", get(routes::stats::daily))
+                .route("/calibration", get(routes::stats::calibration)),
         )
         .nest(
             "/users",
-            Router::<AppState>::new().route("/~me", get(routes::user::show_me)),
+            Router::<AppState>::new()
+                .route("/~me", get(routes::user::show_me))
+                .route("/~me", patch(routes::user::update_me))
+                .route("/~me/auto-bet", get(routes::user::auto_bet::show))
+                .route("/~me/auto-bet", put(routes::user::auto_bet::update))
+                .route("/~me/auto-bet", delete(routes::user::auto_bet::delete))
+                .route("/~me/link-code", post(routes::user::link::create_code))
+                .route("/~me/link", post(routes::user::link::redeem))
+                .route("/~me/push-subscriptions", post(routes::user::push::create))
+                .route(
+                    "/~me/push-subscriptions",
+                    delete(routes::user::push::delete),
+                )
+                .route("/~me/players", get(routes::user::player::list::<T>))
+                .route("/~me/squad", get(routes::squad::show_self))
+                .route("/~me/squad", delete(routes::squad::leave))
+                .route(
+                    "/~me/players/{player_id}",
+                    delete(routes::user::player::delete),
+                )
+                .route(
+                    "/~me/players/{player_id}",
+                    patch(routes::user::player::update::<T>),
+                ),
         )
         .with_state(state.clone());
 
+    let mut oauth_state = None;
+
     if let Some(discord_config) = config.discord.as_ref() {
-        let oauth_state = OauthState::new(&config.server.base_url, db.clone(), &discord_config)?
-            .with_redirect_to(config.server.redirect_url.clone());
+        let state = OauthState::new(&config.server.base_url, db.clone(), &discord_config)?
+            .with_redirect_to(config.server.redirect_url.clone())
+            .with_starting_mobiums(if config.server.sandbox.enabled {
+                config.server.sandbox.starting_mobiums
+            } else {
+                config.server.starting_mobiums
+            })
+            .with_max_display_name_length(config.server.max_display_name_length)
+            .with_guild_id(config.server.anti_abuse.wager_limits.guild_id);
 
         let oauth_router = Router::<OauthState>::new()
             .route("/users/~redirect", get(routes::user::auth::redirect))
             .route("/users/~login", get(routes::user::auth::login))
-            .with_state(oauth_state);
+            .with_state(state.clone());
+
+        oauth_state = Some((state, discord_config.refresh_after));
 
         api_routes = api_routes.merge(oauth_router);
 
@@ -289,11 +679,51 @@ where
         );
     }
 
+    // unknown routes get an api error too
+    // this has to come after every route is added
+    let api_routes = api_routes
+        .fallback(routes::fallback::not_found)
+        .method_not_allowed_fallback(routes::fallback::method_not_allowed);
+
+    // Build the public api
+    let public_routes = if config.http.public_api.enabled {
+        let limiter = RateLimiter::new(&config.http.public_api, state.metrics.clone())?;
+
+        Router::new().nest(
+            "/public",
+            Router::<AppState>::new()
+                .route("/leaderboard", get(routes::leaderboard::show))
+                .route("/matches", get(routes::battle::list::<T>))
+                .route("/matches/{battle_id}", get(routes::battle::show::<T>))
+                .route("/players/{player_id}", get(routes::player::show::<T>))
+                .fallback(routes::fallback::not_found)
+                .method_not_allowed_fallback(routes::fallback::method_not_allowed)
+                .with_state(state.clone())
+                .layer(from_fn(security_headers))
+                .layer(from_fn_with_state(limiter, rate_limit))
+                .layer(Extension(Model::new(model.clone())))
+                .layer(
+                    CorsLayer::new()
+                        .allow_methods([Method::GET])
+                        .allow_origin(Any),
+                ),
+        )
+    } else {
+        Router::new()
+    };
+
     // Create session management
     let db_session_store = SqliteStore::new(db.clone())
-        .with_table_name("_session")
+        .with_table_name(SESSION_TABLE)
         .map_err(eyre::Report::msg)?;
-    db_session_store.migrate().await?;
+    // replicas get the session table from the primary
+    let is_primary = match forwarding.as_ref() {
+        Some(forwarding) => forwarding.is_primary().await?,
+        None => true,
+    };
+    if is_primary {
+        db_session_store.migrate().await?;
+    }
 
     let caching_session_store = MokaStore::new(Some(2_000));
 
@@ -308,7 +738,11 @@ where
 
     // Finalize router
     let router = Router::new()
-        .merge(api_routes.layer(from_fn(security_headers)))
+        .merge(
+            api_routes
+                .layer(from_fn_with_state(state.clone(), audit::audit_log))
+                .layer(from_fn(security_headers)),
+        )
         // serve openapi spec
         .merge(
             Router::new()
@@ -321,6 +755,16 @@ where
         )
         .layer(Extension(Model::new(model.clone())))
         .layer(session_layer)
+        // the public api is for anyone, so it gets no sessions either
+        .merge(public_routes)
+        // health probes shouldn't create sessions
+        .merge(
+            Router::new()
+                .route("/healthz", get(routes::health::live))
+                .route("/readyz", get(routes::health::ready))
+                .route("/metrics", get(routes::metrics::show))
+                .with_state(state.clone()),
+        )
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|req: &Request| {
@@ -339,12 +783,37 @@ where
                 // logging of errors so disable that
                 .on_failure(()),
         )
+        .layer(from_fn_with_state(state.clone(), timings::request_timings))
         .layer(from_fn(log_app_errors));
 
+    // sandboxes mark every response, so nobody mistakes them for production
+    let router = if config.server.sandbox.enabled {
+        tracing::warn!("running in sandbox mode! mobiums on this instance are fake");
+        router.layer(from_fn(sandbox_marker))
+    } else {
+        router
+    };
+
+    // replicas can't take writes, so they are sent on to the primary
+    let router = if let Some(forwarding) = forwarding {
+        match config.server.litefs.primary_url.as_deref() {
+            Some(primary_url) => tracing::info!(primary_url, "forwarding writes on replicas"),
+            None => tracing::info!("replaying writes on replicas"),
+        }
+
+        router.layer(from_fn_with_state(forwarding, forward_writes))
+    } else {
+        router
+    };
+
     let handle = Handle::new();
 
     // run shutdown task to detect shutdowns
-    tokio::spawn(shutdown_signal(handle.clone()));
+    tokio::spawn(shutdown_signal(
+        handle.clone(),
+        state.room.clone(),
+        config.http.websocket.drain.clone(),
+    ));
 
     // start cron jobs
     let sched = JobScheduler::new().await?;
@@ -352,25 +821,289 @@ where
     let model_clone = model.clone();
 
     // Start the rating period updater
-    // This has to be locked so multiple threads aren't doing this together
-    let semaphore = Arc::new(Semaphore::new(1));
+    // The supervisor makes sure multiple threads aren't doing this together
     sched
         .add(Job::new_async("1/60 * * * * *", move |_uuid, _l| {
             let state = state_clone.clone();
-            let semaphore = semaphore.clone();
             let model = model_clone.clone();
 
             Box::pin(async move {
-                if let Ok(_permit) = semaphore.try_acquire() {
-                    let mut conn = state.db.acquire().await.expect("conn acquire");
-                    let _period = next_rating_period(&model, &mut conn)
+                state
+                    .tasks
+                    .run_on_primary("rating_period", || async {
+                        let mut conn = state.db.acquire().await?;
+                        next_rating_period(&model, &mut conn).await.map(|_| ())
+                    })
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start the Discord token refresher
+    if let Some((oauth_state, refresh_after)) = oauth_state {
+        let tasks = state.tasks.clone();
+        sched
+            .add(Job::new_async("0 0 * * * *", move |_uuid, _l| {
+                let oauth_state = oauth_state.clone();
+                let tasks = tasks.clone();
+
+                Box::pin(async move {
+                    tasks
+                        .run_on_primary("discord_token_refresh", || {
+                            refresh_stale_tokens(&oauth_state, refresh_after)
+                        })
                         .await
-                        .expect("update period");
-                }
+                })
+            })?)
+            .await?;
+    }
+
+    // Start the rating distribution metrics
+    let state_clone = state.clone();
+    let model_clone = Model::new(model.clone());
+    sched
+        .add(Job::new_async("0 */5 * * * *", move |_uuid, _l| {
+            let state = state_clone.clone();
+            let model = model_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run("rating_distribution", || async {
+                        let mut conn = state.read_db().acquire().await?;
+                        record_distribution(&model, &state.metrics, &mut conn).await
+                    })
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start the nightly stats rollup
+    let state_clone = state.clone();
+    sched
+        .add(Job::new_async("0 5 0 * * *", move |_uuid, _l| {
+            let state = state_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run_on_primary("daily_stats_rollup", || async {
+                        let mut conn = state.db.acquire().await?;
+                        rollup_daily_stats(&mut conn).await
+                    })
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start the nightly odds calibration rollup
+    let state_clone = state.clone();
+    sched
+        .add(Job::new_async("0 10 0 * * *", move |_uuid, _l| {
+            let state = state_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run_on_primary("odds_calibration_rollup", || async {
+                        let mut conn = state.db.acquire().await?;
+                        rollup_calibration(&mut conn).await
+                    })
+                    .await
             })
         })?)
         .await?;
 
+    // Start the feature flag refresh, so flags set through other instances
+    // are picked up
+    let state_clone = state.clone();
+    sched
+        .add(Job::new_async("0/10 * * * * *", move |_uuid, _l| {
+            let state = state_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run("feature_flag_refresh", || async {
+                        let mut conn = state.db.acquire().await?;
+                        state.flags.refresh(&mut conn).await
+                    })
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start announcing promotional events as they start
+    let state_clone = state.clone();
+    sched
+        .add(Job::new_async("0 * * * * *", move |_uuid, _l| {
+            let state = state_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run_on_primary("promo_event_announcer", || announce_started_events(&state))
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start the nightly cleanup of expired and old data
+    let state_clone = state.clone();
+    sched
+        .add(Job::new_async("0 15 0 * * *", move |_uuid, _l| {
+            let state = state_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run_on_primary("retention_cleanup", || async {
+                        let mut conn = state.db.acquire().await?;
+                        let report = retention::cleanup(
+                            &state.config.server.retention,
+                            Utc::now(),
+                            &mut conn,
+                        )
+                        .await?;
+
+                        tracing::info!(
+                            sessions = report.sessions,
+                            link_codes = report.link_codes,
+                            wager_tokens = report.wager_tokens,
+                            messages = report.messages,
+                            audit_log = report.audit_log,
+                            "cleaned up {} rows",
+                            report.total()
+                        );
+                        Ok::<_, Error>(())
+                    })
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start the wager bot bankroll reset
+    if config.server.bot.enabled && config.server.bot.bankroll.is_some() {
+        let state_clone = state.clone();
+        sched
+            .add(Job::new_async("0 0 0 * * *", move |_uuid, _l| {
+                let state = state_clone.clone();
+
+                Box::pin(async move {
+                    state
+                        .tasks
+                        .run_on_primary("bot_bankroll_reset", || async {
+                            let mut conn = state.db.acquire().await?;
+                            reset_bankroll(&state.config.server.bot, &mut conn).await
+                        })
+                        .await
+                })
+            })?)
+            .await?;
+    }
+
+    // Start the webhook dispatcher
+    let dispatcher = Dispatcher::new()?;
+    let state_clone = state.clone();
+    sched
+        .add(Job::new_async("0/5 * * * * *", move |_uuid, _l| {
+            let dispatcher = dispatcher.clone();
+            let state = state_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run_on_primary("webhook_dispatcher", || dispatcher.dispatch(&state.db))
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start the push notifier
+    if let Some(push_config) = state.config.push.as_ref() {
+        let notifier = Notifier::new(push_config)?;
+        let state_clone = state.clone();
+        sched
+            .add(Job::new_async("0/5 * * * * *", move |_uuid, _l| {
+                let notifier = notifier.clone();
+                let state = state_clone.clone();
+
+                Box::pin(async move {
+                    state
+                        .tasks
+                        .run_on_primary("push_notifier", || notifier.dispatch(&state.db))
+                        .await
+                })
+            })?)
+            .await?;
+    }
+
+    // Start the provisional match finalizer
+    let state_clone = state.clone();
+    let model_clone = Model::new(model.clone());
+    sched
+        .add(Job::new_async("0/10 * * * * *", move |_uuid, _l| {
+            let state = state_clone.clone();
+            let model = model_clone.clone();
+
+            Box::pin(async move {
+                state
+                    .tasks
+                    .run_on_primary("provisional_finalizer", || {
+                        finalize_due_battles(&model, &state)
+                    })
+                    .await
+            })
+        })?)
+        .await?;
+
+    // Start the no-show canceller
+    if config.server.no_show.enabled {
+        let state_clone = state.clone();
+        let model_clone = Model::new(model.clone());
+        sched
+            .add(Job::new_async("0 * * * * *", move |_uuid, _l| {
+                let state = state_clone.clone();
+                let model = model_clone.clone();
+
+                Box::pin(async move {
+                    state
+                        .tasks
+                        .run_on_primary("no_show_canceller", || {
+                            cancel_no_show_battles(&model, &state)
+                        })
+                        .await
+                })
+            })?)
+            .await?;
+    }
+
+    // Start the read replica health check
+    if state.replica.replica_healthy().is_some() {
+        let state_clone = state.clone();
+        sched
+            .add(Job::new_async("0/5 * * * * *", move |_uuid, _l| {
+                let state = state_clone.clone();
+
+                Box::pin(async move {
+                    state
+                        .tasks
+                        .run("replica_health_check", || async {
+                            state.replica.check(&state.metrics).await;
+                            Ok::<_, Error>(())
+                        })
+                        .await
+                })
+            })?)
+            .await?;
+    }
+
+    // Keep track of the scheduler for readiness checks
+    let health = state.health.clone();
+    sched
+        .add(Job::new("1/10 * * * * *", move |_uuid, _l| health.tick())?)
+        .await?;
+
     sched.shutdown_on_ctrl_c();
     sched.start().await?;
 
@@ -380,16 +1113,29 @@ where
 
     axum_server::bind(addr)
         .handle(handle)
-        .serve(router.into_make_service())
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     tracing::info!("shutting down");
 
     db.close().await;
+    state.replica.close().await;
 
     Ok(())
 }
 
+/// Creates the options for connecting to a database.
+fn connect_options(url: &str, config: &Config) -> eyre::Result<SqliteConnectOptions> {
+    let mut options = SqliteConnectOptions::from_str(url)?;
+    if config.slow_queries.enabled {
+        options = options.log_slow_statements(
+            log::LevelFilter::Warn,
+            config.slow_queries.threshold.to_std()?,
+        );
+    }
+    Ok(options)
+}
+
 async fn serve_openapi() -> impl IntoResponse {
     (
         [(
@@ -403,8 +1149,11 @@ async fn serve_openapi() -> impl IntoResponse {
 async fn security_headers(request: Request, next: Next) -> Response {
     let mut res = next.run(request).await;
 
+    // conditional responses may be cached, but only if they say so
+    res.headers_mut()
+        .entry(header::CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static("no-store"));
     res.headers_mut().extend([
-        (header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
         (
             header::CONTENT_SECURITY_POLICY,
             HeaderValue::from_static("frame-ancestors 'none'"),
@@ -423,6 +1172,15 @@ async fn security_headers(request: Request, next: Next) -> Response {
     res
 }
 
+async fn sandbox_marker(request: Request, next: Next) -> Response {
+    let mut res = next.run(request).await;
+
+    res.headers_mut()
+        .insert(X_SANDBOX, HeaderValue::from_static("true"));
+
+    res
+}
+
 // Stolen from: https://github.com/tokio-rs/axum/blob/main/examples/error-handling/src/main.rs
 async fn log_app_errors(request: Request, next: Next) -> Response {
     let response = next.run(request).await;
@@ -435,7 +1193,7 @@ async fn log_app_errors(request: Request, next: Next) -> Response {
 
 // Stolen from: https://github.com/maxcountryman/tower-sessions-stores/tree/main/sqlx-store
 // Lol
-async fn shutdown_signal(handle: Handle) {
+async fn shutdown_signal(handle: Handle, room: room::Room, drain: DrainConfig) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -454,7 +1212,14 @@ async fn shutdown_signal(handle: Handle) {
     let terminate = std::future::pending::<()>();
 
     select! {
-        _ = ctrl_c => { handle.shutdown() }
-        _ = terminate => { handle.shutdown() }
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
+
+    // give clients a chance to move to another instance before their
+    // sockets are cut
+    let timeout = drain.timeout.to_std().unwrap_or_default();
+    room.drain(drain.reconnect_url, timeout).await;
+
+    handle.shutdown();
 }
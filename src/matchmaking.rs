@@ -0,0 +1,153 @@
+//! Team balancing.
+//!
+//! Game servers can ask for a set of players to be split into two teams.
+//! Players are drafted by ordinal, strongest first, and then traded between
+//! the teams for as long as it brings the predicted match closer to a coin
+//! flip.
+
+use ring_channel_model::battle::PlayerTeam;
+
+/// A player being placed on a team.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Seed {
+    /// The ordinal of the player's rating.
+    pub ordinal: f32,
+    /// The player's rating and deviation.
+    pub rating: (f32, f32),
+}
+
+/// Rates a team as its average player.
+///
+/// Returns `None` if the team is empty.
+pub fn team_rating(ratings: &[(f32, f32)]) -> Option<(f32, f32)> {
+    if ratings.is_empty() {
+        return None;
+    }
+
+    let count = ratings.len() as f32;
+    let rating = ratings.iter().map(|(r, _)| r).sum::<f32>() / count;
+    let deviation = (ratings.iter().map(|(_, d)| d.powi(2)).sum::<f32>() / count).sqrt();
+
+    Some((rating, deviation))
+}
+
+/// The chance the red team wins, if both teams have players.
+///
+/// `win_probability` is the chance a team beats another, like
+/// [`ModelData::win_probability`].
+///
+/// [`ModelData::win_probability`]: crate::player::mmr::ModelData::win_probability
+pub fn red_win_probability(
+    seeds: &[Seed],
+    teams: &[PlayerTeam],
+    win_probability: impl Fn((f32, f32), (f32, f32)) -> f32,
+) -> Option<f32> {
+    let rating_of = |team: PlayerTeam| {
+        let ratings = seeds
+            .iter()
+            .zip(teams)
+            .filter(|(_, t)| **t == team)
+            .map(|(seed, _)| seed.rating)
+            .collect::<Vec<_>>();
+
+        team_rating(&ratings)
+    };
+
+    rating_of(PlayerTeam::Red)
+        .zip(rating_of(PlayerTeam::Blue))
+        .map(|(red, blue)| win_probability(red, blue))
+}
+
+/// Splits players into two teams, as evenly matched as possible.
+///
+/// Returns the team of each player, in the same order. Team sizes never
+/// differ by more than one.
+pub fn balance_teams(
+    seeds: &[Seed],
+    win_probability: impl Fn((f32, f32), (f32, f32)) -> f32,
+) -> Vec<PlayerTeam> {
+    let mut order = (0..seeds.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| seeds[b].ordinal.total_cmp(&seeds[a].ordinal));
+
+    // snake draft, so one team doesn't get every first pick
+    let mut teams = vec![PlayerTeam::Red; seeds.len()];
+    for (pick, &i) in order.iter().enumerate() {
+        if matches!(pick % 4, 1 | 2) {
+            teams[i] = PlayerTeam::Blue;
+        }
+    }
+
+    let imbalance = |teams: &[PlayerTeam]| {
+        red_win_probability(seeds, teams, &win_probability).map(|red| (red - 0.5).abs())
+    };
+
+    let Some(mut best) = imbalance(&teams) else {
+        return teams;
+    };
+
+    // trading players keeps the team sizes as they are
+    loop {
+        let mut best_trade = None;
+        let (reds, blues) =
+            (0..teams.len()).partition::<Vec<_>, _>(|&i| teams[i] == PlayerTeam::Red);
+
+        for &red in &reds {
+            for &blue in &blues {
+                teams.swap(red, blue);
+                if let Some(score) = imbalance(&teams)
+                    && score < best - f32::EPSILON
+                {
+                    best = score;
+                    best_trade = Some((red, blue));
+                }
+                teams.swap(red, blue);
+            }
+        }
+
+        match best_trade {
+            Some((red, blue)) => teams.swap(red, blue),
+            None => break teams,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeds(ratings: &[f32]) -> Vec<Seed> {
+        ratings
+            .iter()
+            .map(|&rating| Seed {
+                ordinal: rating,
+                rating: (rating, 0.0),
+            })
+            .collect()
+    }
+
+    fn logistic(rating: (f32, f32), opponent: (f32, f32)) -> f32 {
+        1.0 / (1.0 + (-(rating.0 - opponent.0) / 100.0).exp())
+    }
+
+    #[test]
+    pub fn test_team_sizes_are_even() {
+        for count in 2..=9 {
+            let ratings = (0..count).map(|i| (i * i * 10) as f32).collect::<Vec<_>>();
+            let teams = balance_teams(&seeds(&ratings), logistic);
+
+            let red = teams.iter().filter(|&&t| t == PlayerTeam::Red).count();
+            let blue = teams.len() - red;
+            assert!(red.abs_diff(blue) <= 1, "{red} red, {blue} blue");
+        }
+    }
+
+    #[test]
+    pub fn test_players_are_traded_to_balance() {
+        // the draft alone puts 100, 30 and 20 against 50, 40 and 0
+        let seeds = seeds(&[100.0, 50.0, 40.0, 30.0, 20.0, 0.0]);
+        let teams = balance_teams(&seeds, logistic);
+
+        let red = red_win_probability(&seeds, &teams, logistic).unwrap();
+        assert!((red - 0.5).abs() < 1e-6, "red wins {red} of the time");
+    }
+}
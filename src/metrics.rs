@@ -0,0 +1,188 @@
+//! Service metrics.
+//!
+//! Served in the Prometheus text format at `/metrics`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+};
+
+/// Service metrics.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    counters: Arc<Mutex<BTreeMap<Series, u64>>>,
+    gauges: Arc<Mutex<BTreeMap<Series, f64>>>,
+    histograms: Arc<Mutex<BTreeMap<Series, Histogram>>>,
+}
+
+/// The upper bounds of histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A single labeled series of a metric.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Series {
+    name: &'static str,
+    labels: Vec<(&'static str, String)>,
+}
+
+impl Series {
+    fn new(name: &'static str, labels: &[(&'static str, &str)]) -> Series {
+        Series {
+            name,
+            labels: labels
+                .iter()
+                .map(|(key, value)| (*key, (*value).to_owned()))
+                .collect(),
+        }
+    }
+}
+
+/// A histogram over [`LATENCY_BUCKETS`].
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    /// Creates a new, empty `Metrics`.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Increments a counter by one.
+    pub fn increment(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    /// Increments a counter.
+    pub fn add(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        let series = Series::new(name, labels);
+
+        let mut counters = self.counters.lock().expect("metrics poisoned");
+        *counters.entry(series).or_default() += value;
+    }
+
+    /// Sets a gauge.
+    pub fn set(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let series = Series::new(name, labels);
+
+        let mut gauges = self.gauges.lock().expect("metrics poisoned");
+        gauges.insert(series, value);
+    }
+
+    /// Records an observation, in seconds, in a histogram.
+    pub fn observe(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let series = Series::new(name, labels);
+
+        let mut histograms = self.histograms.lock().expect("metrics poisoned");
+        let histogram = histograms.entry(series).or_default();
+
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut last_name = None;
+
+        let counters = self.counters.lock().expect("metrics poisoned");
+
+        for (series, value) in counters.iter() {
+            if last_name != Some(series.name) {
+                let _ = writeln!(out, "# TYPE {} counter", series.name);
+                last_name = Some(series.name);
+            }
+
+            write_series(&mut out, series.name, "", &series.labels, None);
+            let _ = writeln!(out, " {}", value);
+        }
+
+        drop(counters);
+
+        let gauges = self.gauges.lock().expect("metrics poisoned");
+
+        for (series, value) in gauges.iter() {
+            if last_name != Some(series.name) {
+                let _ = writeln!(out, "# TYPE {} gauge", series.name);
+                last_name = Some(series.name);
+            }
+
+            write_series(&mut out, series.name, "", &series.labels, None);
+            let _ = writeln!(out, " {}", value);
+        }
+
+        drop(gauges);
+
+        let histograms = self.histograms.lock().expect("metrics poisoned");
+
+        for (series, histogram) in histograms.iter() {
+            if last_name != Some(series.name) {
+                let _ = writeln!(out, "# TYPE {} histogram", series.name);
+                last_name = Some(series.name);
+            }
+
+            for (bucket, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+                let le = bound.to_string();
+                write_series(&mut out, series.name, "_bucket", &series.labels, Some(&le));
+                let _ = writeln!(out, " {}", bucket);
+            }
+            write_series(
+                &mut out,
+                series.name,
+                "_bucket",
+                &series.labels,
+                Some("+Inf"),
+            );
+            let _ = writeln!(out, " {}", histogram.count);
+
+            write_series(&mut out, series.name, "_sum", &series.labels, None);
+            let _ = writeln!(out, " {}", histogram.sum);
+            write_series(&mut out, series.name, "_count", &series.labels, None);
+            let _ = writeln!(out, " {}", histogram.count);
+        }
+
+        out
+    }
+}
+
+/// Writes the name and labels of a series.
+fn write_series(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &[(&'static str, String)],
+    le: Option<&str>,
+) {
+    out.push_str(name);
+    out.push_str(suffix);
+
+    let labels = labels
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .chain(le.map(|le| ("le", le)));
+
+    let mut first = true;
+    for (key, value) in labels {
+        out.push(if first { '{' } else { ',' });
+        first = false;
+
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = write!(out, "{}=\"{}\"", key, value);
+    }
+    if !first {
+        out.push('}');
+    }
+}
@@ -0,0 +1,241 @@
+//! Payout strategies.
+//!
+//! A [`PayoutStrategy`] decides how much each winning wager on a match is
+//! paid. Which one is used follows the match's [`OddsMode`], which servers
+//! pick when creating a match, falling back to the `locked_odds` config.
+
+use ring_channel_model::battle::{OddsMode, PlayerTeam};
+
+/// A wager being settled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stake {
+    /// The team the wager is on.
+    pub victor: PlayerTeam,
+    /// How many mobiums were wagered.
+    pub mobiums: i64,
+    /// The odds locked in when the wager was placed, if any.
+    pub odds: Option<f64>,
+}
+
+/// The pots of a match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pots {
+    /// The sum of all wagers on the red team.
+    pub red: i64,
+    /// The sum of all wagers on the blue team.
+    pub blue: i64,
+}
+
+impl Pots {
+    /// Sums up the pots of some wagers.
+    pub fn from_stakes<'a>(stakes: impl IntoIterator<Item = &'a Stake>) -> Pots {
+        stakes.into_iter().fold(Pots::default(), |mut pots, stake| {
+            match stake.victor {
+                PlayerTeam::Red => pots.red += stake.mobiums,
+                PlayerTeam::Blue => pots.blue += stake.mobiums,
+            }
+            pots
+        })
+    }
+
+    /// The pot of a single team.
+    pub fn get(&self, team: PlayerTeam) -> i64 {
+        match team {
+            PlayerTeam::Red => self.red,
+            PlayerTeam::Blue => self.blue,
+        }
+    }
+
+    /// The sum of both pots.
+    pub fn total(&self) -> i64 {
+        self.red + self.blue
+    }
+}
+
+/// A way to pay out winning wagers.
+pub trait PayoutStrategy: Send + Sync {
+    /// How many mobiums a winning wager is paid, including the wager itself.
+    ///
+    /// `pots` are the final pots of the match. Both are non-empty.
+    fn payout(&self, stake: &Stake, pots: &Pots) -> i64;
+}
+
+/// Winners split the final pots, in proportion to their wagers.
+///
+/// Payouts are rounded down, so the pots never pay out more than they hold.
+/// Whatever is left over is burned.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Parimutuel;
+
+impl Parimutuel {
+    /// Splits `total` between the wagers in `pot` by their share of it.
+    ///
+    /// Wagers are refunded if the pot is empty.
+    pub fn split(mobiums: i64, pot: i64, total: i64) -> i64 {
+        if pot <= 0 {
+            return mobiums;
+        }
+
+        // big pots can overflow before they are divided
+        (total as i128 * mobiums as i128 / pot as i128) as i64
+    }
+}
+
+impl PayoutStrategy for Parimutuel {
+    fn payout(&self, stake: &Stake, pots: &Pots) -> i64 {
+        Parimutuel::split(stake.mobiums, pots.get(stake.victor), pots.total())
+    }
+}
+
+/// Wagers are paid out at the odds locked in when they were placed.
+///
+/// Wagers without locked odds are split like [`Parimutuel`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FixedOdds;
+
+impl PayoutStrategy for FixedOdds {
+    fn payout(&self, stake: &Stake, pots: &Pots) -> i64 {
+        match stake.odds {
+            Some(odds) => (stake.mobiums as f64 * odds).floor() as i64,
+            None => Parimutuel.payout(stake, pots),
+        }
+    }
+}
+
+/// Scales a wager's winnings down, so that all `promised` winnings on a match
+/// fit in its `losing_pot`.
+///
+/// Locked odds can promise more than the losers put up, like when the wagers
+/// that set the odds are taken back before bets close.
+pub fn cap_winnings(winnings: i64, promised: i64, losing_pot: i64) -> i64 {
+    if promised <= losing_pot || promised <= 0 {
+        return winnings;
+    }
+
+    (winnings as i128 * losing_pot.max(0) as i128 / promised as i128) as i64
+}
+
+/// The strategy matches with `odds_mode` are paid out with.
+pub fn strategy(odds_mode: OddsMode) -> &'static dyn PayoutStrategy {
+    match odds_mode {
+        OddsMode::Pool => &Parimutuel,
+        OddsMode::Locked => &FixedOdds,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    fn team() -> impl Strategy<Value = PlayerTeam> {
+        prop_oneof![Just(PlayerTeam::Red), Just(PlayerTeam::Blue)]
+    }
+
+    fn stakes() -> impl Strategy<Value = Vec<Stake>> {
+        prop::collection::vec(
+            (team(), 1..1_000_000i64).prop_map(|(victor, mobiums)| Stake {
+                victor,
+                mobiums,
+                odds: None,
+            }),
+            2..50,
+        )
+        .prop_filter("both pots need wagers", |stakes| {
+            let pots = Pots::from_stakes(stakes);
+            pots.red > 0 && pots.blue > 0
+        })
+    }
+
+    /// Sums the payouts of every wager on `winner`.
+    fn paid_out(strategy: &dyn PayoutStrategy, stakes: &[Stake], winner: PlayerTeam) -> (i64, i64) {
+        let pots = Pots::from_stakes(stakes);
+
+        stakes
+            .iter()
+            .filter(|stake| stake.victor == winner)
+            .fold((0, 0), |(total, winners), stake| {
+                (total + strategy.payout(stake, &pots), winners + 1)
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn test_parimutuel_conserves_mobiums(stakes in stakes(), winner in team()) {
+            let pots = Pots::from_stakes(&stakes);
+            let (total, winners) = paid_out(&Parimutuel, &stakes, winner);
+
+            // nothing is minted, and at most a mobium per winner is burned
+            prop_assert!(total <= pots.total());
+            prop_assert!(pots.total() - total < winners);
+        }
+
+        #[test]
+        fn test_fixed_odds_conserves_mobiums_at_fair_odds(
+            stakes in stakes(),
+            winner in team(),
+        ) {
+            // odds locked after the last wager are exactly the pot ratio
+            let pots = Pots::from_stakes(&stakes);
+            let stakes = stakes
+                .into_iter()
+                .map(|stake| Stake {
+                    odds: Some(pots.total() as f64 / pots.get(stake.victor) as f64),
+                    ..stake
+                })
+                .collect::<Vec<_>>();
+
+            let (total, winners) = paid_out(&FixedOdds, &stakes, winner);
+
+            // float rounding can cost a winner a mobium, but never mints one
+            prop_assert!(total <= pots.total());
+            prop_assert!(pots.total() - total <= winners);
+        }
+
+        #[test]
+        fn test_fixed_odds_pays_locked_odds(
+            mobiums in 1..1_000_000i64,
+            odds in 1.0..20.0f64,
+            pots in (1..1_000_000i64, 1..1_000_000i64),
+        ) {
+            let stake = Stake { victor: PlayerTeam::Red, mobiums, odds: Some(odds) };
+            let pots = Pots { red: pots.0, blue: pots.1 };
+            let payout = FixedOdds.payout(&stake, &pots);
+
+            // winners always get their wager back, and never more than the odds
+            prop_assert!(payout >= mobiums);
+            prop_assert!(payout as f64 <= mobiums as f64 * odds);
+        }
+
+        #[test]
+        fn test_capped_winnings_fit_losing_pot(
+            stakes in stakes(),
+            odds in prop::collection::vec(1.0..20.0f64, 50),
+            winner in team(),
+        ) {
+            // odds locked before wagers were taken back can be anything
+            let pots = Pots::from_stakes(&stakes);
+            let stakes = stakes
+                .into_iter()
+                .zip(odds)
+                .map(|(stake, odds)| Stake { odds: Some(odds), ..stake })
+                .collect::<Vec<_>>();
+
+            let winnings = stakes
+                .iter()
+                .filter(|stake| stake.victor == winner)
+                .map(|stake| FixedOdds.payout(stake, &pots) - stake.mobiums)
+                .collect::<Vec<_>>();
+            let promised = winnings.iter().sum::<i64>();
+            let losing_pot = pots.total() - pots.get(winner);
+
+            let capped = winnings
+                .iter()
+                .map(|winnings| cap_winnings(*winnings, promised, losing_pot))
+                .sum::<i64>();
+            prop_assert!(capped <= losing_pot);
+            prop_assert!(capped <= promised);
+        }
+    }
+}
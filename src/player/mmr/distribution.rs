@@ -0,0 +1,104 @@
+//! Rating distribution metrics.
+//!
+//! Operators can watch these to catch ratings inflating or compressing over
+//! time.
+
+use sqlx::SqliteConnection;
+
+use crate::{app, error::Error, metrics::Metrics};
+
+use super::{Model, Rating, RawRating};
+
+/// The quantiles of player ordinals that are exported.
+pub const ORDINAL_QUANTILES: [(f64, &str); 7] = [
+    (0.01, "0.01"),
+    (0.1, "0.1"),
+    (0.25, "0.25"),
+    (0.5, "0.5"),
+    (0.75, "0.75"),
+    (0.9, "0.9"),
+    (0.99, "0.99"),
+];
+
+/// Records the current rating distribution in the service metrics.
+///
+/// Only rated players count towards ordinals and deviations. Unrated players
+/// count as provisional, like they are shown.
+pub async fn record_distribution<T>(
+    model: &app::Model<T>,
+    metrics: &Metrics,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error>
+where
+    T: Model + 'static,
+{
+    if !model.ratings_enabled() {
+        return Ok(());
+    }
+
+    let ratings = sqlx::query_as::<_, RawRating>(
+        r#"
+        SELECT id AS player_id, rating, deviation, rating_extra AS extra
+        FROM player
+        WHERE rating IS NOT NULL AND deviation IS NOT NULL
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let (unrated,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COUNT(*)
+        FROM player
+        WHERE rating IS NULL OR deviation IS NULL
+        "#,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut ordinals = Vec::with_capacity(ratings.len());
+    let mut provisional = unrated;
+    let mut deviation_sum = 0.0;
+
+    for rating in ratings {
+        let rating = Rating::<T::Data>::try_from(rating).map_err(Error::new)?;
+
+        if rating.is_provisional() {
+            provisional += 1;
+        }
+        deviation_sum += f64::from(rating.deviation);
+        ordinals.push(f64::from(rating.ordinal()));
+    }
+
+    ordinals.sort_by(f64::total_cmp);
+
+    let rated = ordinals.len();
+    metrics.set("mmr_rated_players", &[], rated as f64);
+    metrics.set("mmr_provisional_players", &[], provisional as f64);
+
+    if rated == 0 {
+        return Ok(());
+    }
+
+    for (quantile, label) in ORDINAL_QUANTILES {
+        metrics.set(
+            "mmr_ordinal",
+            &[("quantile", label)],
+            nearest_rank(&ordinals, quantile),
+        );
+    }
+    metrics.set(
+        "mmr_ordinal_average",
+        &[],
+        ordinals.iter().sum::<f64>() / rated as f64,
+    );
+    metrics.set("mmr_deviation_average", &[], deviation_sum / rated as f64);
+
+    Ok(())
+}
+
+/// Picks the value at `quantile` of sorted, non-empty `values`.
+fn nearest_rank(values: &[f64], quantile: f64) -> f64 {
+    let rank = (quantile * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
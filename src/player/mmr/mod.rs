@@ -1,16 +1,19 @@
 //! Skill-based placements.
 
+pub mod distribution;
 pub mod glicko2;
 pub mod openskill;
+pub mod simulate;
 
 use std::any::Any;
+use std::cmp::min;
 use std::fmt::Debug;
 
 use derive_more::{Deref, DerefMut};
 
 use chrono::{DateTime, TimeDelta, Utc};
 
-use ring_channel_model::battle::BattleStatus;
+use ring_channel_model::{BattleId, battle::BattleStatus};
 use serde::{
     Deserialize, Serialize,
     de::{DeserializeOwned, value::UnitDeserializer},
@@ -46,10 +49,29 @@ pub trait Model: Send + Sync {
 }
 
 pub trait ModelData: Send + Sync + Sized + 'static {
+    /// The deviation above which ratings are provisional.
+    ///
+    /// This is a third of a new Glicko player's deviation by default.
+    const PROVISIONAL_DEVIATION: f32 = 350.0 / 3.0;
+
     /// The ordinal of the rating.
     fn ordinal(rating: &Rating<Self>) -> f32 {
         rating.rating - rating.deviation * 2.0
     }
+
+    /// The chance a side rated `rating` beats a side rated `opponent`.
+    ///
+    /// Ratings are given as `(rating, deviation)` pairs. This is the Glicko
+    /// expected score by default.
+    fn win_probability(rating: (f32, f32), opponent: (f32, f32)) -> f32 {
+        let q = std::f32::consts::LN_10 / 400.0;
+        let deviation = (rating.1.powi(2) + opponent.1.powi(2)).sqrt();
+        let g = (1.0 + 3.0 * q.powi(2) * deviation.powi(2) / std::f32::consts::PI.powi(2))
+            .sqrt()
+            .recip();
+
+        (1.0 + 10f32.powf(-g * (rating.0 - opponent.0) / 400.0)).recip()
+    }
 }
 
 impl ModelData for () {}
@@ -83,7 +105,6 @@ pub struct Matchup<T = ()> {
 struct MatchupQuery {
     #[sqlx(flatten)]
     pub opponent: RawRatingRecord,
-    #[sqlx(try_from = "u8")]
     pub status: BattleStatus,
     pub position: i32,
     pub no_contest: bool,
@@ -137,6 +158,11 @@ where
     pub fn ordinal(&self) -> f32 {
         T::ordinal(self)
     }
+
+    /// Whether the rating is still too uncertain to mean much.
+    pub fn is_provisional(&self) -> bool {
+        self.deviation > T::PROVISIONAL_DEVIATION
+    }
 }
 
 /// A historic player rating.
@@ -372,11 +398,14 @@ where
 ///
 /// Should be called when a match is finished.
 ///
+/// The player's displayed MMR before and after the update is recorded on
+/// their participant row for `battle_id`.
+///
 /// Ensure both player's ratings exist (by calling [`get_rating`] for each of
 /// them) before calling this!
-#[instrument(skip(conn))]
 pub async fn update_rating<T>(
     rating: &RatingRecord<T::Data>,
+    battle_id: BattleId,
     model: &T,
     conn: &mut SqliteConnection,
 ) -> Result<Rating<T::Data>, Error>
@@ -384,11 +413,42 @@ where
     T: Model + Debug,
     T::Data: Debug,
 {
-    let now = Utc::now();
+    update_rating_at(rating, battle_id, model, Utc::now(), conn).await
+}
+
+/// Updates a player's current rating at the given time.
+///
+/// Only matches concluded before `now` count towards the rating.
+#[instrument(skip(conn))]
+pub async fn update_rating_at<T>(
+    rating: &RatingRecord<T::Data>,
+    battle_id: BattleId,
+    model: &T,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Rating<T::Data>, Error>
+where
+    T: Model + Debug,
+    T::Data: Debug,
+{
+    // The live rating, which already includes earlier matches this period
+    let old_rating = sqlx::query_as::<_, RawRating>(
+        r#"
+        SELECT id AS player_id, rating, deviation, rating_extra AS extra
+        FROM player
+        WHERE id = $1 AND rating IS NOT NULL AND deviation IS NOT NULL
+        "#,
+    )
+    .bind(rating.player_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(Rating::<T::Data>::try_from)
+    .transpose()
+    .map_err(Error::new)?;
 
     // Get the current period start
-    let period = next_rating_period(model, &mut *conn).await?;
-    let ends_at = period.started_at + model.period();
+    let period = next_rating_period_at(model, now, &mut *conn).await?;
+    let ends_at = min(period.started_at + model.period(), now);
 
     let matchups = fetch_matchups(rating.player_id, period.started_at, ends_at, &mut *conn).await?;
 
@@ -420,6 +480,20 @@ where
     .execute(&mut *conn)
     .await?;
 
+    sqlx::query(
+        r#"
+        UPDATE participant
+        SET mmr_before = $3, mmr_after = $4
+        WHERE match_id = $1 AND player_id = $2
+        "#,
+    )
+    .bind(battle_id)
+    .bind(new_rating.player_id)
+    .bind(old_rating.map(|rating| rating.ordinal() as i32))
+    .bind(new_rating.ordinal() as i32)
+    .execute(&mut *conn)
+    .await?;
+
     Ok(new_rating)
 }
 
@@ -591,7 +665,7 @@ where
         .filter(|matchup| match matchup.status {
             BattleStatus::Concluded => true,
             BattleStatus::Cancelled => matchup.finish_time > 35 * 30,
-            BattleStatus::Ongoing => false,
+            BattleStatus::Ongoing | BattleStatus::Provisional => false,
         })
         .map(|matchup| Matchup::<T>::try_from(matchup))
         .collect::<Result<Vec<_>, _>>()
@@ -702,7 +776,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use ring_channel_model::Rrid;
+    use ring_channel_model::{BattleId, Rrid, battle::PlayerTeam};
     use sqlx::sqlite::SqlitePoolOptions;
     use uuid::Uuid;
 
@@ -752,23 +826,24 @@ mod tests {
         // Register battle
         let now = Utc::now();
         let uuid = Uuid::new_v4();
-        let (battle_id,) = sqlx::query_as::<_, (i32,)>(
+        let (battle_id,) = sqlx::query_as::<_, (BattleId,)>(
             r#"
-            INSERT INTO battle (uuid, level_name, inserted_at, concluded_at, closed_at, status)
-            VALUES ($1, $2, $3, $3, $3, $4)
+            INSERT INTO battle
+                (uuid, level_name, inserted_at, concluded_at, closed_at, updated_at, status)
+            VALUES ($1, $2, $3, $3, $3, $3, $4)
             RETURNING id
             "#,
         )
         .bind(uuid.hyphenated().to_string())
         .bind("Withering Chateau Zone")
         .bind(now)
-        .bind(u8::from(BattleStatus::Concluded))
+        .bind(BattleStatus::Concluded)
         .fetch_one(&mut *conn)
         .await
         .unwrap();
 
-        for (i, player) in [&player1, &player2].into_iter().enumerate() {
-            let no_contest = i == 1;
+        for (player, team) in [(&player1, PlayerTeam::Red), (&player2, PlayerTeam::Blue)] {
+            let no_contest = team == PlayerTeam::Blue;
             let finish_time = if no_contest { None } else { Some(3050) };
 
             // add player to match
@@ -781,7 +856,7 @@ mod tests {
             )
             .bind(battle_id)
             .bind(player.id)
-            .bind(i as u8)
+            .bind(team)
             .bind("aigis")
             .bind(6)
             .bind(7)
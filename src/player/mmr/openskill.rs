@@ -121,10 +121,23 @@ pub struct OpenSkillData {
     ordinal: f32,
 }
 
+/// The default performance variance of openskill ratings.
+const BETA: f32 = 25.0 / 6.0;
+
 impl ModelData for OpenSkillData {
+    const PROVISIONAL_DEVIATION: f32 = 25.0 / 9.0;
+
     fn ordinal(rating: &Rating<Self>) -> f32 {
         rating.extra.ordinal
     }
+
+    fn win_probability(rating: (f32, f32), opponent: (f32, f32)) -> f32 {
+        let c = (2.0 * BETA.powi(2) + rating.1.powi(2) + opponent.1.powi(2)).sqrt();
+        let x = (rating.0 - opponent.0) / c;
+
+        // logistic approximation of the normal cdf
+        (1.0 + (-1.702 * x).exp()).recip()
+    }
 }
 
 /// A request.
@@ -0,0 +1,335 @@
+//! Rating simulations.
+//!
+//! The match history is replayed from scratch under a rating model, so rating
+//! configs can be compared by how well they would have predicted each match.
+//! Simulations write ratings just like the real thing, so they should be run
+//! in a transaction that gets rolled back.
+
+use std::fmt::Debug;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use ring_channel_model::{
+    BattleId,
+    battle::{BattleStatus, PlayerTeam},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{battle::update_participant_ratings_at, error::Error, matchmaking};
+
+use super::{Model, ModelData, Rating, RawRating, init_rating, next_rating_period_at};
+
+/// A player's MMR after a match.
+#[derive(Clone, Debug)]
+pub struct TrajectoryPoint {
+    /// The UUID of the match.
+    pub match_id: String,
+    /// When the match concluded.
+    pub concluded_at: DateTime<Utc>,
+    /// The short ID of the player.
+    pub short_id: String,
+    /// The player's MMR after the match.
+    pub mmr: i32,
+}
+
+/// A prediction made before a match was rated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Prediction {
+    /// The chance the red team wins, from 0 to 1.
+    pub red: f32,
+    /// Whether the red team won.
+    pub red_won: bool,
+}
+
+/// The results of a simulation.
+#[derive(Clone, Debug, Default)]
+pub struct Simulation {
+    /// How many matches were replayed.
+    pub matches: usize,
+    /// The predictions of every concluded match with a winner.
+    pub predictions: Vec<Prediction>,
+    /// Every player's MMR after each match they played, in order.
+    pub trajectories: Vec<TrajectoryPoint>,
+}
+
+impl Simulation {
+    /// How often the favourite won, from 0 to 1.
+    ///
+    /// Coin flips count as half right.
+    pub fn accuracy(&self) -> Option<f32> {
+        self.mean(|prediction| {
+            if prediction.red == 0.5 {
+                0.5
+            } else if (prediction.red > 0.5) == prediction.red_won {
+                1.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    /// The mean squared error of the predictions.
+    ///
+    /// Lower is better. Predicting a coin flip every time scores `0.25`.
+    pub fn brier_score(&self) -> Option<f32> {
+        self.mean(|prediction| {
+            let outcome = if prediction.red_won { 1.0 } else { 0.0 };
+            (prediction.red - outcome).powi(2)
+        })
+    }
+
+    /// The log loss of the predictions.
+    ///
+    /// Lower is better. Predicting a coin flip every time scores `ln 2`.
+    pub fn log_loss(&self) -> Option<f32> {
+        self.mean(|prediction| {
+            let chance = if prediction.red_won {
+                prediction.red
+            } else {
+                1.0 - prediction.red
+            };
+            -chance.clamp(f32::EPSILON, 1.0).ln()
+        })
+    }
+
+    fn mean(&self, score: impl Fn(&Prediction) -> f32) -> Option<f32> {
+        if self.predictions.is_empty() {
+            return None;
+        }
+
+        let total = self.predictions.iter().map(score).sum::<f32>();
+        Some(total / self.predictions.len() as f32)
+    }
+}
+
+/// Replays every concluded or cancelled match, in the order they concluded.
+///
+/// Every rating is thrown out first. Players are rated when they play their
+/// first match, and each match is predicted from its participants' ratings
+/// right before it is rated.
+pub async fn simulate<T>(model: &T, conn: &mut SqliteConnection) -> Result<Simulation, Error>
+where
+    T: Model + Debug,
+    T::Data: Debug,
+{
+    #[derive(FromRow)]
+    struct BattleQuery {
+        id: BattleId,
+        uuid: String,
+        status: BattleStatus,
+        concluded_at: DateTime<Utc>,
+    }
+
+    #[derive(FromRow)]
+    struct ParticipantQuery {
+        player_id: i32,
+        rated: bool,
+    }
+
+    #[derive(FromRow)]
+    struct MmrQuery {
+        short_id: String,
+        #[sqlx(flatten)]
+        rating: RawRating,
+    }
+
+    sqlx::query("DELETE FROM rating")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM rating_period")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE player SET rating = NULL, deviation = NULL, rating_extra = NULL")
+        .execute(&mut *conn)
+        .await?;
+
+    let battles = sqlx::query_as::<_, BattleQuery>(
+        r#"
+        SELECT id, uuid, status, concluded_at
+        FROM battle
+        WHERE status IN ($1, $2) AND concluded_at IS NOT NULL
+        ORDER BY concluded_at ASC, id ASC
+        "#,
+    )
+    .bind(BattleStatus::Concluded)
+    .bind(BattleStatus::Cancelled)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut simulation = Simulation::default();
+
+    for battle in battles {
+        // ratings are updated right after a match concludes
+        let now = battle.concluded_at + TimeDelta::milliseconds(1);
+        next_rating_period_at(model, now, &mut *conn).await?;
+
+        let participants = sqlx::query_as::<_, ParticipantQuery>(
+            r#"
+            SELECT p.id AS player_id, p.rating IS NOT NULL AS rated
+            FROM participant pt
+            INNER JOIN player p ON p.id = pt.player_id
+            WHERE pt.match_id = $1
+            "#,
+        )
+        .bind(battle.id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        for participant in participants.iter().filter(|p| !p.rated) {
+            init_rating(participant.player_id, model, &mut *conn).await?;
+        }
+
+        if battle.status == BattleStatus::Concluded
+            && let Some(prediction) = predict::<T::Data>(battle.id, &mut *conn).await?
+        {
+            simulation.predictions.push(prediction);
+        }
+
+        update_participant_ratings_at(battle.id, model, now, &mut *conn).await?;
+
+        let ratings = sqlx::query_as::<_, MmrQuery>(
+            r#"
+            SELECT
+                p.short_id, p.id AS player_id, p.rating, p.deviation,
+                p.rating_extra AS extra
+            FROM participant pt
+            INNER JOIN player p ON p.id = pt.player_id
+            WHERE pt.match_id = $1 AND p.rating IS NOT NULL AND p.deviation IS NOT NULL
+            ORDER BY pt.team ASC, p.short_id ASC
+            "#,
+        )
+        .bind(battle.id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        for MmrQuery { short_id, rating } in ratings {
+            let rating = Rating::<T::Data>::try_from(rating).map_err(Error::new)?;
+
+            simulation.trajectories.push(TrajectoryPoint {
+                match_id: battle.uuid.clone(),
+                concluded_at: battle.concluded_at,
+                short_id,
+                mmr: rating.ordinal() as i32,
+            });
+        }
+
+        simulation.matches += 1;
+    }
+
+    Ok(simulation)
+}
+
+/// Predicts a match from its participants' current ratings, like matches
+/// are when they are created.
+///
+/// Returns `None` if the match has no winner, or a team is empty.
+async fn predict<T>(
+    battle_id: BattleId,
+    conn: &mut SqliteConnection,
+) -> Result<Option<Prediction>, Error>
+where
+    T: ModelData,
+{
+    #[derive(FromRow)]
+    struct RatingQuery {
+        team: PlayerTeam,
+        rating: f32,
+        deviation: f32,
+    }
+
+    let ratings = sqlx::query_as::<_, RatingQuery>(
+        r#"
+        SELECT pt.team, p.rating, p.deviation
+        FROM participant pt
+        INNER JOIN player p ON p.id = pt.player_id
+        WHERE pt.match_id = $1 AND p.rating IS NOT NULL AND p.deviation IS NOT NULL
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // the winner is found the same way as when the pots are divvied up
+    let winner = sqlx::query_as::<_, (PlayerTeam,)>(
+        r#"
+        SELECT team
+        FROM participant
+        WHERE
+            match_id = $1
+            AND NOT no_contest
+        ORDER BY finish_time ASC
+        LIMIT 1
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((winner,)) = winner else {
+        return Ok(None);
+    };
+
+    let team_rating = |team: PlayerTeam| {
+        let ratings = ratings
+            .iter()
+            .filter(|r| r.team == team)
+            .map(|r| (r.rating, r.deviation))
+            .collect::<Vec<_>>();
+
+        matchmaking::team_rating(&ratings)
+    };
+
+    let prediction = team_rating(PlayerTeam::Red)
+        .zip(team_rating(PlayerTeam::Blue))
+        .map(|(red, blue)| Prediction {
+            red: T::win_probability(red, blue),
+            red_won: winner == PlayerTeam::Red,
+        });
+
+    Ok(prediction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulation(predictions: &[(f32, bool)]) -> Simulation {
+        Simulation {
+            predictions: predictions
+                .iter()
+                .map(|&(red, red_won)| Prediction { red, red_won })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn test_coin_flips_score_as_chance() {
+        let simulation = simulation(&[(0.5, true), (0.5, false)]);
+
+        assert_eq!(simulation.accuracy(), Some(0.5));
+        assert_eq!(simulation.brier_score(), Some(0.25));
+        assert!((simulation.log_loss().unwrap() - std::f32::consts::LN_2).abs() < 1e-6);
+    }
+
+    #[test]
+    pub fn test_confident_predictions_score_better() {
+        let sure = simulation(&[(0.9, true), (0.1, false)]);
+        let unsure = simulation(&[(0.6, true), (0.4, false)]);
+
+        assert_eq!(sure.accuracy(), Some(1.0));
+        assert_eq!(unsure.accuracy(), Some(1.0));
+        assert!(sure.brier_score() < unsure.brier_score());
+        assert!(sure.log_loss() < unsure.log_loss());
+    }
+
+    #[test]
+    pub fn test_no_predictions() {
+        let simulation = Simulation::default();
+
+        assert_eq!(simulation.accuracy(), None);
+        assert_eq!(simulation.brier_score(), None);
+        assert_eq!(simulation.log_loss(), None);
+    }
+}
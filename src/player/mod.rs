@@ -1,8 +1,10 @@
 pub mod mmr;
+pub mod name_filter;
+pub mod stats;
 
-use chrono::Utc;
+use chrono::{DateTime, TimeDelta, Utc};
 use rand::{Rng, SeedableRng, distr::Alphanumeric};
-use ring_channel_model::{Player, Rrid};
+use ring_channel_model::{Player, PlayerShortId, Rrid, battle::BattleStatus};
 use sqlx::{FromRow, SqliteConnection};
 
 use crate::{
@@ -14,17 +16,26 @@ use mmr::{Rating, RawRating};
 
 const MAX_INSERT_ATTEMPTS: usize = 5;
 
+/// How long a player keeps a short id they picked before they can pick
+/// another.
+///
+/// Old short ids are kept as aliases for good, so this keeps players from
+/// hoarding them.
+pub const SHORT_ID_COOLDOWN: TimeDelta = TimeDelta::days(30);
+
 /// A row in the database representing a player.
 #[derive(FromRow)]
 pub struct PlayerRow {
     #[sqlx(rename = "player_id")]
     pub id: i32,
-    pub short_id: String,
+    pub short_id: PlayerShortId,
     pub display_name: String,
     pub rating: Option<f32>,
     pub deviation: Option<f32>,
     #[sqlx(rename = "rating_extra")]
     pub extra: Option<String>,
+    /// How many concluded matches the player was in.
+    pub games_played: i32,
 }
 
 impl PlayerRow {
@@ -48,16 +59,24 @@ impl PlayerRow {
             None
         };
 
+        // unrated players are as uncertain as it gets
+        let provisional =
+            model.ratings_enabled() && rating.as_ref().is_none_or(|rating| rating.is_provisional());
+
         Ok(Player {
             id: self.short_id,
             display_name: self.display_name,
             mmr: rating.map(|rating| rating.ordinal() as i32),
+            provisional,
+            games_played: self.games_played,
             public_key: None,
         })
     }
 }
 
 /// Gets a player by their short id.
+///
+/// Short ids the player had before are looked up too.
 pub async fn get_player(
     short_id: &str,
     conn: &mut SqliteConnection,
@@ -65,19 +84,27 @@ pub async fn get_player(
     sqlx::query_as::<_, PlayerRow>(
         r#"
         SELECT
-            id AS player_id,
-            short_id,
-            display_name,
-            rating,
-            deviation,
-            rating_extra
+            p.id AS player_id,
+            p.short_id,
+            p.display_name,
+            p.rating,
+            p.deviation,
+            p.rating_extra,
+            (
+                SELECT COUNT(*)
+                FROM participant gp
+                INNER JOIN battle gb ON gb.id = gp.match_id
+                WHERE gp.player_id = p.id AND gb.status = $2
+            ) AS games_played
         FROM
-            player
+            player p
         WHERE
-            short_id = $1
+            p.short_id = $1
+            OR p.id = (SELECT player_id FROM player_short_id_alias WHERE short_id = $1)
         "#,
     )
     .bind(short_id)
+    .bind(BattleStatus::Concluded)
     .fetch_optional(&mut *conn)
     .await
     .map_err(Error::from)
@@ -117,7 +144,7 @@ where
             .map(|c| char::to_ascii_uppercase(&c))
             .collect::<String>();
 
-        // try to insert with short_id
+        // try to insert with short_id, if no one had it before
         let result = sqlx::query_as::<_, PlayerRow>(
             r#"
             INSERT INTO player
@@ -128,22 +155,28 @@ where
                     inserted_at,
                     updated_at
                 )
-            VALUES ($1, $2, $3, $4, $4)
-            RETURNING id AS player_id, short_id, display_name, rating, deviation, rating_extra
+            SELECT $1, $2, $3, $4, $4
+            WHERE NOT EXISTS (SELECT 1 FROM player_short_id_alias WHERE short_id = $1)
+            RETURNING
+                id AS player_id, short_id, display_name, rating, deviation, rating_extra,
+                0 AS games_played
             "#,
         )
         .bind(&short_id)
         .bind(public_key.as_str())
         .bind(display_name)
         .bind(now)
-        .fetch_one(&mut *conn)
+        .fetch_optional(&mut *conn)
         .await;
 
         match result {
-            Ok(player) => {
+            Ok(Some(player)) => {
                 inserted_player = Some(player);
                 break;
             }
+            Ok(None) => {
+                tracing::debug!("short id {} was taken before, regenerating", short_id);
+            }
             Err(err) => {
                 if let Some(db_err) = err.as_database_error() {
                     // if this is a unique violation, simply try again
@@ -161,3 +194,99 @@ where
 
     inserted_player.ok_or_else(|| ErrorKind::OutOfIds.into())
 }
+
+/// Changes a player's short id.
+///
+/// The old short id is kept as an alias, so lookups with it still find the
+/// player. Players can take their own old short ids back.
+pub async fn change_short_id(
+    player: &PlayerRow,
+    short_id: &PlayerShortId,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let now = Utc::now();
+
+    let last_changed = sqlx::query_as::<_, (DateTime<Utc>,)>(
+        r#"
+        SELECT replaced_at
+        FROM player_short_id_alias
+        WHERE player_id = $1
+        ORDER BY replaced_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(player.id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some((replaced_at,)) = last_changed
+        && replaced_at + SHORT_ID_COOLDOWN > now
+    {
+        return Err(ErrorKind::InvalidData(format!(
+            "Player {} can pick a new short id after {}",
+            player.short_id,
+            (replaced_at + SHORT_ID_COOLDOWN).format("%Y-%m-%d")
+        ))
+        .into());
+    }
+
+    let taken = || ErrorKind::InvalidData(format!("Short id {} is taken", short_id)).into();
+
+    let result = sqlx::query(
+        r#"
+        UPDATE player
+        SET short_id = $2, updated_at = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(player.id)
+    .bind(short_id)
+    .bind(now)
+    .execute(&mut *conn)
+    .await;
+
+    match result {
+        Ok(_) => (),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => return Err(taken()),
+        Err(err) => return Err(err.into()),
+    }
+
+    sqlx::query(
+        r#"
+        DELETE FROM player_short_id_alias
+        WHERE short_id = $1 AND player_id = $2
+        "#,
+    )
+    .bind(short_id)
+    .bind(player.id)
+    .execute(&mut *conn)
+    .await?;
+
+    // someone else's old short id still points to them
+    let (aliased,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM player_short_id_alias WHERE short_id = $1)
+        "#,
+    )
+    .bind(short_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if aliased {
+        return Err(taken());
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO player_short_id_alias (short_id, player_id, replaced_at)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(&player.short_id)
+    .bind(player.id)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Player display name filtering.
+//!
+//! Names are checked against the server's denylist when players register, and
+//! again when they are put in a match, in case the denylist grew since.
+
+use chrono::{DateTime, Utc};
+
+use sqlx::SqliteConnection;
+
+use crate::{
+    config::{NameFilterConfig, NameReplacement},
+    error::Error,
+};
+
+/// Replaces the denied words in a display name.
+///
+/// Returns `None` if the name has none.
+pub fn censor(name: &str, config: &NameFilterConfig) -> Option<String> {
+    let chars = name.chars().collect::<Vec<_>>();
+
+    // the characters that can make up a word, and where they are in the name
+    let folded = chars
+        .iter()
+        .enumerate()
+        .filter_map(|(i, ch)| fold(*ch).map(|ch| (i, ch)))
+        .collect::<Vec<_>>();
+
+    let mut denied = vec![false; chars.len()];
+    for word in config.denylist.iter() {
+        let word = word.chars().filter_map(fold).collect::<Vec<_>>();
+        if word.is_empty() || word.len() > folded.len() {
+            continue;
+        }
+
+        for window in folded.windows(word.len()) {
+            if window.iter().map(|(_, ch)| ch).eq(word.iter()) {
+                let (start, end) = (window[0].0, window[word.len() - 1].0);
+                denied[start..=end].fill(true);
+            }
+        }
+    }
+
+    if !denied.contains(&true) {
+        return None;
+    }
+
+    match config.replacement {
+        NameReplacement::Mask => Some(
+            chars
+                .iter()
+                .zip(denied)
+                .map(|(ch, denied)| if denied { '*' } else { *ch })
+                .collect(),
+        ),
+        NameReplacement::Placeholder => Some(config.placeholder.clone()),
+    }
+}
+
+/// Censors a stored player's display name, if it has a denied word in it.
+///
+/// Names set by admins are left alone. Returns the player's new display name,
+/// if it changed.
+pub async fn censor_player_name(
+    player_id: i32,
+    config: &NameFilterConfig,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Option<String>, Error> {
+    if config.denylist.is_empty() {
+        return Ok(None);
+    }
+
+    let (display_name, locked) = sqlx::query_as::<_, (String, bool)>(
+        r#"
+        SELECT display_name, display_name_locked
+        FROM player
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if locked {
+        return Ok(None);
+    }
+
+    let Some(display_name) = censor(&display_name, config) else {
+        return Ok(None);
+    };
+
+    // the old name isn't kept as an alias, since it shouldn't show up anywhere
+    sqlx::query(
+        r#"
+        UPDATE player
+        SET display_name = $2, updated_at = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .bind(&display_name)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Some(display_name))
+}
+
+/// Folds a character down to what it would be in a word, or `None` if it
+/// separates words.
+fn fold(ch: char) -> Option<char> {
+    let ch = match ch {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        ch if ch.is_alphanumeric() => ch.to_lowercase().next().unwrap_or(ch),
+        _ => return None,
+    };
+
+    Some(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(replacement: NameReplacement) -> NameFilterConfig {
+        NameFilterConfig {
+            denylist: vec!["badnik".into(), "eggman".into()],
+            replacement,
+            placeholder: "Racer".into(),
+        }
+    }
+
+    #[test]
+    fn test_censor_mask() {
+        let config = config(NameReplacement::Mask);
+
+        // Clean names are left alone
+        assert_eq!(censor("Tails", &config), None);
+        assert_eq!(censor("Dr. Robotnik", &config), None);
+        assert_eq!(censor("", &config), None);
+
+        // Denied words are starred out, wherever they are
+        assert_eq!(censor("Badnik", &config).as_deref(), Some("******"));
+        assert_eq!(censor("xXbadnikXx", &config).as_deref(), Some("xX******Xx"));
+        assert_eq!(
+            censor("Eggman's Badnik", &config).as_deref(),
+            Some("******'s ******")
+        );
+
+        // Separators and stand-ins don't get around the filter
+        assert_eq!(
+            censor("B.A.D.N.I.K", &config).as_deref(),
+            Some("***********")
+        );
+        assert_eq!(censor("3GGM4N", &config).as_deref(), Some("******"));
+        assert_eq!(censor("egg man", &config).as_deref(), Some("*******"));
+    }
+
+    #[test]
+    fn test_censor_placeholder() {
+        let config = config(NameReplacement::Placeholder);
+
+        assert_eq!(censor("Knuckles", &config), None);
+        assert_eq!(censor("Mecha Badnik", &config).as_deref(), Some("Racer"));
+    }
+}
@@ -0,0 +1,222 @@
+//! Lifetime player statistics.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::{
+    battle::{BattleStatus, PlayerTeam},
+    player::{LevelStats, PlayerStats},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::error::Error;
+
+/// A concluded match a player took part in.
+#[derive(Clone, Debug, FromRow)]
+pub struct PlayedMatch {
+    pub level_name: String,
+    pub team: PlayerTeam,
+    pub finish_time: Option<i32>,
+    pub no_contest: bool,
+    /// The team that won, if anybody finished.
+    pub winner: Option<PlayerTeam>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PlayedMatch {
+    fn won(&self) -> bool {
+        self.winner == Some(self.team)
+    }
+}
+
+/// Gets every concluded match a player took part in, oldest first.
+pub async fn played_matches(
+    player_id: i32,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<PlayedMatch>, Error> {
+    sqlx::query_as::<_, PlayedMatch>(
+        r#"
+        SELECT
+            b.level_name, pt.team, pt.finish_time, pt.no_contest, b.updated_at,
+            (
+                SELECT w.team
+                FROM participant w
+                WHERE
+                    w.match_id = b.id
+                    AND NOT w.no_contest
+                    AND w.finish_time IS NOT NULL
+                ORDER BY w.finish_time ASC
+                LIMIT 1
+            ) AS winner
+        FROM participant pt
+        INNER JOIN battle b ON b.id = pt.match_id
+        WHERE pt.player_id = $1 AND b.status = $2
+        ORDER BY b.concluded_at ASC
+        "#,
+    )
+    .bind(player_id)
+    .bind(BattleStatus::Concluded)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(Error::from)
+}
+
+/// Sums up the matches a player took part in.
+///
+/// `matches` must be oldest first, for win streaks to make sense.
+pub fn summarize(matches: &[PlayedMatch]) -> PlayerStats {
+    let mut stats = PlayerStats::default();
+    let mut finishes = Finishes::default();
+    let mut levels = HashMap::<&str, (LevelStats, Finishes)>::new();
+    let mut streak = 0;
+
+    for played in matches {
+        let won = played.won();
+
+        stats.matches += 1;
+        stats.wins += i64::from(won);
+        stats.no_contests += i64::from(played.no_contest);
+
+        let record = match played.team {
+            PlayerTeam::Red => &mut stats.red,
+            PlayerTeam::Blue => &mut stats.blue,
+        };
+        record.matches += 1;
+        record.wins += i64::from(won);
+
+        streak = if won { streak + 1 } else { 0 };
+        stats.longest_win_streak = stats.longest_win_streak.max(streak);
+
+        let (level, level_finishes) = levels.entry(&played.level_name).or_insert_with(|| {
+            let level = LevelStats {
+                level_name: played.level_name.clone(),
+                matches: 0,
+                wins: 0,
+                average_finish_time: None,
+                best_finish_time: None,
+            };
+            (level, Finishes::default())
+        });
+        level.matches += 1;
+        level.wins += i64::from(won);
+
+        // no contests don't have a meaningful finish time
+        if let Some(finish_time) = played.finish_time.filter(|_| !played.no_contest) {
+            finishes.add(finish_time);
+            level_finishes.add(finish_time);
+            level.best_finish_time = Some(
+                level
+                    .best_finish_time
+                    .map_or(finish_time, |best| best.min(finish_time)),
+            );
+        }
+    }
+
+    stats.average_finish_time = finishes.average();
+    stats.no_contest_rate = rate(stats.no_contests, stats.matches);
+    stats.red.win_rate = rate(stats.red.wins, stats.red.matches);
+    stats.blue.win_rate = rate(stats.blue.wins, stats.blue.matches);
+
+    stats.levels = levels
+        .into_values()
+        .map(|(level, finishes)| LevelStats {
+            average_finish_time: finishes.average(),
+            ..level
+        })
+        .collect();
+    stats.levels.sort_by(|a, b| {
+        b.matches
+            .cmp(&a.matches)
+            .then_with(|| a.level_name.cmp(&b.level_name))
+    });
+
+    stats
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Finishes {
+    total: i64,
+    count: i64,
+}
+
+impl Finishes {
+    fn add(&mut self, finish_time: i32) {
+        self.total += i64::from(finish_time);
+        self.count += 1;
+    }
+
+    fn average(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total as f64 / self.count as f64)
+    }
+}
+
+fn rate(count: i64, total: i64) -> f64 {
+    if total > 0 {
+        count as f64 / total as f64
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn played(
+        level_name: &str,
+        team: PlayerTeam,
+        finish_time: Option<i32>,
+        winner: Option<PlayerTeam>,
+    ) -> PlayedMatch {
+        PlayedMatch {
+            level_name: level_name.to_owned(),
+            team,
+            finish_time,
+            no_contest: finish_time.is_none(),
+            winner,
+            updated_at: DateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    pub fn test_summarize() {
+        use PlayerTeam::{Blue, Red};
+
+        let stats = summarize(&[
+            played("RR_GreenTriangle", Red, Some(100), Some(Red)),
+            played("RR_GreenTriangle", Red, Some(200), Some(Red)),
+            played("RR_ScarletGardens", Blue, None, Some(Red)),
+            played("RR_GreenTriangle", Blue, Some(300), Some(Blue)),
+            // nobody finished
+            played("RR_ScarletGardens", Blue, None, None),
+        ]);
+
+        assert_eq!(stats.matches, 5);
+        assert_eq!(stats.wins, 3);
+        assert_eq!(stats.red.win_rate, 1.0);
+        assert_eq!(stats.blue.matches, 3);
+        assert_eq!(stats.blue.wins, 1);
+        assert_eq!(stats.average_finish_time, Some(200.0));
+        assert_eq!(stats.no_contests, 2);
+        assert_eq!(stats.no_contest_rate, 0.4);
+        assert_eq!(stats.longest_win_streak, 2);
+
+        let [green, scarlet] = stats.levels.as_slice() else {
+            panic!("expected two levels, got {:?}", stats.levels);
+        };
+        assert_eq!(green.level_name, "RR_GreenTriangle");
+        assert_eq!(green.wins, 3);
+        assert_eq!(green.best_finish_time, Some(100));
+        assert_eq!(scarlet.matches, 2);
+        assert_eq!(scarlet.average_finish_time, None);
+    }
+
+    #[test]
+    pub fn test_summarize_nothing() {
+        let stats = summarize(&[]);
+
+        assert_eq!(stats, PlayerStats::default());
+    }
+}
@@ -0,0 +1,480 @@
+//! Web Push notifications.
+//!
+//! Users subscribe their browsers with `POST /users/~me/push-subscriptions`.
+//! Notifications are queued in the database alongside the change that caused
+//! them, and pushed to every subscription by a [`Notifier`] in the
+//! background.
+//!
+//! Payloads are encrypted for each subscription with `aes128gcm`
+//! ([RFC 8291]), and requests are signed with the server's VAPID key
+//! ([RFC 8292]).
+//!
+//! [RFC 8291]: https://www.rfc-editor.org/rfc/rfc8291
+//! [RFC 8292]: https://www.rfc-editor.org/rfc/rfc8292
+
+use std::{collections::HashMap, time::Duration};
+
+use aes_gcm::{
+    Aes128Gcm, Nonce,
+    aead::{Aead as _, KeyInit as _},
+};
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+
+use chrono::{TimeDelta, Utc};
+
+use futures_util::{StreamExt as _, stream};
+
+use hkdf::Hkdf;
+
+use http::{HeaderName, StatusCode, header};
+
+use p256::{
+    PublicKey, SecretKey,
+    ecdh::diffie_hellman,
+    ecdsa::{Signature, SigningKey, signature::Signer as _},
+    elliptic_curve::sec1::ToEncodedPoint as _,
+};
+
+use rand::Rng as _;
+
+use reqwest::Url;
+
+use ring_channel_model::{UserId, push::PushNotification};
+
+use sha2::Sha256;
+
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+
+use crate::{
+    config::PushConfig,
+    error::{Error, ErrorKind},
+};
+
+pub const TTL: HeaderName = HeaderName::from_static("ttl");
+
+/// How many notifications are sent per dispatch.
+pub const DISPATCH_BATCH_SIZE: i32 = 100;
+
+/// How many pushes are sent at once.
+pub const DISPATCH_CONCURRENCY: usize = 8;
+
+/// How long push services have to respond.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long VAPID tokens are valid for.
+///
+/// Push services reject tokens that expire more than a day out.
+pub const VAPID_TOKEN_LIFETIME: TimeDelta = TimeDelta::hours(12);
+
+/// The record size written in encrypted payloads.
+///
+/// Payloads are always sent as a single record, so this only has to be
+/// bigger than they are.
+const RECORD_SIZE: u32 = 4096;
+
+/// Checks if an endpoint belongs to one of the push `services`.
+///
+/// Endpoints have to be HTTPS on the default port, and their host has to be
+/// one of `services` or a subdomain of one.
+pub fn is_push_service(endpoint: &Url, services: &[String]) -> bool {
+    // addresses are never push services
+    let Some(host) = endpoint.domain() else {
+        return false;
+    };
+
+    endpoint.scheme() == "https"
+        && endpoint.port().is_none()
+        && services.iter().any(|service| {
+            host.strip_suffix(service.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+        })
+}
+
+/// Queues notifications for users.
+///
+/// Notifications for users without any subscriptions are dropped. Run this in
+/// the same transaction as the change, so nothing is pushed for changes that
+/// were rolled back.
+pub async fn enqueue(
+    notifications: &[(UserId, PushNotification)],
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    if notifications.is_empty() {
+        return Ok(());
+    }
+
+    let payloads = notifications
+        .iter()
+        .map(|(user_id, notification)| {
+            serde_json::to_string(notification).map(|payload| (*user_id, payload))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::new)?;
+
+    let mut query = QueryBuilder::<Sqlite>::new("WITH queued (user_id, payload) AS (");
+    query.push_values(payloads, |mut row, (user_id, payload)| {
+        row.push_bind(user_id).push_bind(payload);
+    });
+    query.push(
+        r#"
+        )
+        INSERT INTO push_notification (user_id, payload, inserted_at)
+        SELECT q.user_id, q.payload,
+        "#,
+    );
+    query.push_bind(Utc::now());
+    query.push(
+        r#"
+        FROM queued q
+        WHERE EXISTS (SELECT 1 FROM push_subscription s WHERE s.user_id = q.user_id)
+        "#,
+    );
+    query.build().execute(&mut *conn).await?;
+
+    Ok(())
+}
+
+/// Decodes the keys of a browser's subscription.
+///
+/// Returns the browser's public key and auth secret.
+pub fn decode_keys(p256dh: &str, auth: &str) -> Result<(PublicKey, [u8; 16]), Error> {
+    let public_key = decode(p256dh)
+        .and_then(|key| PublicKey::from_sec1_bytes(&key).ok())
+        .ok_or_else(|| ErrorKind::InvalidData("Invalid p256dh key".into()))?;
+    let auth = decode(auth)
+        .and_then(|auth| <[u8; 16]>::try_from(auth).ok())
+        .ok_or_else(|| ErrorKind::InvalidData("Invalid auth secret".into()))?;
+
+    Ok((public_key, auth))
+}
+
+/// Generates a new VAPID key pair.
+///
+/// Returns the public and private key, as unpadded base64url.
+pub fn generate_vapid_keys() -> (String, String) {
+    let secret = random_secret_key();
+    let public = secret.public_key().to_encoded_point(false);
+
+    (
+        URL_SAFE_NO_PAD.encode(public.as_bytes()),
+        URL_SAFE_NO_PAD.encode(secret.to_bytes()),
+    )
+}
+
+/// Encrypts a payload for a subscription, as an `aes128gcm` body.
+pub fn encrypt(payload: &[u8], ua_public: &PublicKey, auth: &[u8; 16]) -> Result<Vec<u8>, Error> {
+    let as_secret = random_secret_key();
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let as_public = as_public.as_bytes();
+    let ua_public_bytes = ua_public.to_encoded_point(false);
+
+    let ecdh_secret = diffie_hellman(as_secret.to_nonzero_scalar(), ua_public.as_affine());
+
+    // mix the auth secret in first, then derive the content key with a salt
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(ua_public_bytes.as_bytes());
+    key_info.extend_from_slice(as_public);
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(auth), ecdh_secret.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .map_err(|_| ErrorKind::Other(eyre::eyre!("bad ikm length")))?;
+
+    let salt = rand::rng().random::<[u8; 16]>();
+    let prk = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .and_then(|_| prk.expand(b"Content-Encoding: nonce\0", &mut nonce))
+        .map_err(|_| ErrorKind::Other(eyre::eyre!("bad content key length")))?;
+
+    // the only record is the last one
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+
+    let ciphertext = Aes128Gcm::new(&cek.into())
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| ErrorKind::Other(eyre::eyre!("failed to encrypt push payload")))?;
+
+    let mut body = Vec::with_capacity(16 + 4 + 1 + as_public.len() + ciphertext.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(as_public);
+    body.extend_from_slice(&ciphertext);
+
+    Ok(body)
+}
+
+#[derive(FromRow)]
+struct SubscriptionQuery {
+    id: i32,
+    user_id: UserId,
+    endpoint: String,
+    p256dh: String,
+    auth: String,
+}
+
+/// Sends queued push notifications.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    http_client: reqwest::Client,
+    signing_key: SigningKey,
+    public_key: String,
+    subject: String,
+    ttl: TimeDelta,
+    services: Vec<String>,
+}
+
+impl Notifier {
+    /// Creates a new `Notifier`.
+    pub fn new(config: &PushConfig) -> Result<Notifier, Error> {
+        let signing_key = decode(&config.private_key)
+            .and_then(|key| SigningKey::from_slice(&key).ok())
+            .ok_or_else(|| ErrorKind::InvalidData("Invalid VAPID private key".into()))?;
+
+        let public_key = URL_SAFE_NO_PAD.encode(
+            signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        );
+        if decode(&config.public_key).is_none_or(|key| URL_SAFE_NO_PAD.encode(key) != public_key) {
+            return Err(ErrorKind::InvalidData(
+                "VAPID public key does not match the private key".into(),
+            )
+            .into());
+        }
+
+        let http_client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            // a redirect could lead anywhere
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(Notifier {
+            http_client,
+            signing_key,
+            public_key,
+            subject: config.subject.clone(),
+            ttl: config.ttl,
+            services: config.services.clone(),
+        })
+    }
+
+    /// Sends a batch of queued notifications.
+    ///
+    /// Push services hold notifications for offline browsers themselves, so
+    /// failed pushes aren't retried. Subscriptions the push service says are
+    /// gone are deleted.
+    pub async fn dispatch(&self, db: &SqlitePool) -> Result<(), Error> {
+        #[derive(FromRow)]
+        struct NotificationQuery {
+            user_id: UserId,
+            payload: String,
+        }
+
+        let notifications = sqlx::query_as::<_, NotificationQuery>(
+            r#"
+            DELETE FROM push_notification
+            WHERE id IN (SELECT id FROM push_notification ORDER BY id LIMIT $1)
+            RETURNING user_id, payload
+            "#,
+        )
+        .bind(DISPATCH_BATCH_SIZE)
+        .fetch_all(db)
+        .await?;
+
+        if notifications.is_empty() {
+            return Ok(());
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, user_id, endpoint, p256dh, auth FROM push_subscription WHERE user_id IN (",
+        );
+        let mut ids = query.separated(", ");
+        for notification in notifications.iter() {
+            ids.push_bind(notification.user_id);
+        }
+        ids.push_unseparated(")");
+        let subscriptions = query
+            .build_query_as::<SubscriptionQuery>()
+            .fetch_all(db)
+            .await?;
+
+        let mut by_user = HashMap::<UserId, Vec<&SubscriptionQuery>>::new();
+        for subscription in subscriptions.iter() {
+            by_user
+                .entry(subscription.user_id)
+                .or_default()
+                .push(subscription);
+        }
+
+        // tokens are per push service, and most browsers share a few
+        let mut tokens = HashMap::<String, String>::new();
+        let mut pushes = Vec::new();
+
+        for notification in notifications.iter() {
+            for subscription in by_user.get(&notification.user_id).into_iter().flatten() {
+                // the list of push services may have changed since
+                let Some(url) = Url::parse(&subscription.endpoint)
+                    .ok()
+                    .filter(|url| is_push_service(url, &self.services))
+                else {
+                    continue;
+                };
+                let audience = url.origin().ascii_serialization();
+                let token = match tokens.get(&audience) {
+                    Some(token) => token.clone(),
+                    None => {
+                        let token = self.sign(&audience)?;
+                        tokens.insert(audience, token.clone());
+                        token
+                    }
+                };
+
+                pushes.push((*subscription, token, notification.payload.as_bytes()));
+            }
+        }
+
+        let deliveries = pushes
+            .into_iter()
+            .map(|(subscription, token, payload)| self.deliver(subscription, token, payload))
+            .collect::<Vec<_>>();
+        let gone = stream::iter(deliveries)
+            .buffer_unordered(DISPATCH_CONCURRENCY)
+            .filter_map(|id| async move { id })
+            .collect::<Vec<_>>()
+            .await;
+
+        if !gone.is_empty() {
+            let mut query =
+                QueryBuilder::<Sqlite>::new("DELETE FROM push_subscription WHERE id IN (");
+            let mut ids = query.separated(", ");
+            for id in gone.iter() {
+                ids.push_bind(*id);
+            }
+            ids.push_unseparated(")");
+            query.build().execute(db).await?;
+
+            tracing::debug!(count = gone.len(), "deleted expired push subscriptions");
+        }
+
+        Ok(())
+    }
+
+    /// Delivers a notification to a browser.
+    ///
+    /// Returns the subscription's id if it is gone.
+    async fn deliver(
+        &self,
+        subscription: &SubscriptionQuery,
+        token: String,
+        payload: &[u8],
+    ) -> Option<i32> {
+        match self.send(subscription, &token, payload).await {
+            Ok(StatusCode::NOT_FOUND | StatusCode::GONE) => Some(subscription.id),
+            Ok(status) if !status.is_success() => {
+                tracing::debug!(id = subscription.id, %status, "push was rejected");
+                None
+            }
+            Ok(_) => None,
+            Err(err) => {
+                tracing::debug!(id = subscription.id, %err, "push failed");
+                None
+            }
+        }
+    }
+
+    async fn send(
+        &self,
+        subscription: &SubscriptionQuery,
+        token: &str,
+        payload: &[u8],
+    ) -> Result<StatusCode, Error> {
+        let (public_key, auth) = decode_keys(&subscription.p256dh, &subscription.auth)?;
+        let body = encrypt(payload, &public_key, &auth)?;
+
+        let response = self
+            .http_client
+            .post(&subscription.endpoint)
+            .header(
+                header::AUTHORIZATION,
+                format!("vapid t={}, k={}", token, self.public_key),
+            )
+            .header(header::CONTENT_ENCODING, "aes128gcm")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(TTL, self.ttl.num_seconds().to_string())
+            .body(body)
+            .send()
+            .await?;
+
+        Ok(response.status())
+    }
+
+    /// Signs a VAPID token for a push service.
+    fn sign(&self, audience: &str) -> Result<String, Error> {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = serde_json::json!({
+            "aud": audience,
+            "exp": (Utc::now() + VAPID_TOKEN_LIFETIME).timestamp(),
+            "sub": self.subject,
+        });
+        let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).map_err(Error::new)?);
+
+        let message = format!("{}.{}", header, claims);
+        let signature: Signature = self.signing_key.sign(message.as_bytes());
+
+        Ok(format!(
+            "{}.{}",
+            message,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        ))
+    }
+}
+
+/// Decodes base64url, with or without padding.
+fn decode(value: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).ok()
+}
+
+/// Generates a random P-256 secret key.
+fn random_secret_key() -> SecretKey {
+    let mut rng = rand::rng();
+
+    // practically every 32 bytes is a valid scalar
+    loop {
+        if let Ok(key) = SecretKey::from_slice(&rng.random::<[u8; 32]>()) {
+            return key;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(endpoint: &str) -> bool {
+        let services = vec![
+            "fcm.googleapis.com".to_owned(),
+            "notify.windows.com".to_owned(),
+        ];
+        is_push_service(&Url::parse(endpoint).unwrap(), &services)
+    }
+
+    #[test]
+    fn test_only_push_services_are_allowed() {
+        assert!(allowed("https://fcm.googleapis.com/fcm/send/abc"));
+        assert!(allowed("https://wns2-by3p.notify.windows.com/w/?token=abc"));
+
+        assert!(!allowed("http://fcm.googleapis.com/fcm/send/abc"));
+        assert!(!allowed("https://fcm.googleapis.com:8443/fcm/send/abc"));
+        assert!(!allowed("https://evilfcm.googleapis.com.example/"));
+        assert!(!allowed("https://notfcm.googleapis.com/"));
+        assert!(!allowed("https://localhost/"));
+        assert!(!allowed("https://127.0.0.1/"));
+        assert!(!allowed("https://[::1]/"));
+    }
+}
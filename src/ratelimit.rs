@@ -0,0 +1,276 @@
+//! Per-client rate limiting.
+//!
+//! Clients are told apart by their IP address, and each gets a token bucket.
+//! Every request takes a token, and tokens trickle back in over time, so
+//! clients can burst a little but not keep it up.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use http::HeaderName;
+
+use crate::{
+    config::PublicApiConfig,
+    error::{Error, ErrorKind},
+    metrics::Metrics,
+};
+
+/// How many clients are tracked before some are forgotten.
+pub const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// How many of the least recently seen clients are forgotten at once, when
+/// forgetting clients with full buckets isn't enough.
+///
+/// Forgetting a batch keeps new clients from paying for a sweep every
+/// request once the limit is reached.
+pub const EVICTION_BATCH: usize = MAX_TRACKED_CLIENTS / 10;
+
+/// A rate limiter.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    state: Arc<RateLimiterState>,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    capacity: f64,
+    per_second: f64,
+    client_ip_header: Option<HeaderName>,
+    metrics: Metrics,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter`.
+    pub fn new(config: &PublicApiConfig, metrics: Metrics) -> Result<RateLimiter, Error> {
+        let client_ip_header = config
+            .client_ip_header
+            .as_deref()
+            .map(HeaderName::try_from)
+            .transpose()
+            .map_err(Error::new)?;
+
+        Ok(RateLimiter {
+            state: Arc::new(RateLimiterState {
+                buckets: Mutex::default(),
+                capacity: config.burst.max(1) as f64,
+                per_second: config.requests_per_minute as f64 / 60.0,
+                client_ip_header,
+                metrics,
+            }),
+        })
+    }
+
+    /// Takes a token for a client.
+    ///
+    /// If the client is out of tokens, returns how long until they get
+    /// another one.
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let state = &self.state;
+        let mut buckets = state.buckets.lock().expect("rate limiter poisoned");
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&client) {
+            evict(&mut buckets, now, state);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: state.capacity,
+            updated_at: now,
+        });
+        *bucket = bucket.refill(now, state);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if state.per_second > 0.0 {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / state.per_second,
+            ))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    /// Figures out who sent a request.
+    ///
+    /// Requests that can't be traced back to anyone share a bucket.
+    fn client_ip(&self, request: &Request) -> IpAddr {
+        let forwarded = self.state.client_ip_header.as_ref().and_then(|header| {
+            // clients can send whatever they want in front, but the last
+            // address is the one our proxy appended
+            request
+                .headers()
+                .get(header)?
+                .to_str()
+                .ok()?
+                .rsplit(',')
+                .next()?
+                .trim()
+                .parse()
+                .ok()
+        });
+
+        forwarded
+            .or_else(|| {
+                request
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip())
+            })
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
+
+/// Forgets clients until there is room for at least [`EVICTION_BATCH`] more.
+fn evict(buckets: &mut HashMap<IpAddr, Bucket>, now: Instant, state: &RateLimiterState) {
+    // full buckets are no different from new ones
+    buckets.retain(|_, bucket| bucket.refill(now, state).tokens < state.capacity);
+
+    if buckets.len() + EVICTION_BATCH > MAX_TRACKED_CLIENTS {
+        let mut seen = buckets
+            .values()
+            .map(|bucket| bucket.updated_at)
+            .collect::<Vec<_>>();
+        let evict = (buckets.len() + EVICTION_BATCH - MAX_TRACKED_CLIENTS).min(seen.len());
+        if evict > 0 {
+            let (_, cutoff, _) = seen.select_nth_unstable(evict - 1);
+            let cutoff = *cutoff;
+            buckets.retain(|_, bucket| bucket.updated_at > cutoff);
+        }
+    }
+}
+
+impl Bucket {
+    fn refill(self, now: Instant, state: &RateLimiterState) -> Bucket {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+
+        Bucket {
+            tokens: (self.tokens + elapsed.as_secs_f64() * state.per_second).min(state.capacity),
+            updated_at: now,
+        }
+    }
+}
+
+/// Middleware that turns away clients that have made too many requests.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    let client = limiter.client_ip(&request);
+
+    if let Err(retry_after) = limiter.check(client, Instant::now()) {
+        limiter.state.metrics.increment("rate_limited_total", &[]);
+
+        // round up, so clients don't come back a moment too early
+        let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        return Err(ErrorKind::RateLimited(secs).into());
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests_per_minute: u32, burst: u32) -> RateLimiter {
+        let config = PublicApiConfig {
+            requests_per_minute,
+            burst,
+            ..Default::default()
+        };
+        RateLimiter::new(&config, Metrics::new()).unwrap()
+    }
+
+    #[test]
+    pub fn test_burst_then_refill() {
+        let limiter = limiter(60, 3);
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(client, now), Ok(()));
+        }
+        assert_eq!(limiter.check(client, now), Err(Duration::from_secs(1)));
+
+        // one token a second
+        assert_eq!(limiter.check(client, now + Duration::from_secs(1)), Ok(()));
+    }
+
+    #[test]
+    pub fn test_clients_are_separate() {
+        let limiter = limiter(60, 1);
+        let now = Instant::now();
+
+        assert_eq!(limiter.check(IpAddr::V4(Ipv4Addr::LOCALHOST), now), Ok(()));
+        assert_eq!(limiter.check(IpAddr::V4(Ipv4Addr::BROADCAST), now), Ok(()));
+        assert!(limiter.check(IpAddr::V4(Ipv4Addr::LOCALHOST), now).is_err());
+    }
+
+    #[test]
+    pub fn test_least_recently_seen_clients_are_evicted() {
+        let limiter = limiter(1, 1);
+        let now = Instant::now();
+        let client = |i: usize| IpAddr::V4(Ipv4Addr::from(i as u32 + 1));
+
+        // every bucket is drained, so none can be pruned for being full
+        for i in 0..MAX_TRACKED_CLIENTS {
+            let seen_at = now + Duration::from_millis(i as u64);
+            assert_eq!(limiter.check(client(i), seen_at), Ok(()));
+        }
+
+        let later = now + Duration::from_millis(MAX_TRACKED_CLIENTS as u64);
+        assert_eq!(limiter.check(client(MAX_TRACKED_CLIENTS), later), Ok(()));
+
+        let buckets = limiter.state.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_TRACKED_CLIENTS - EVICTION_BATCH + 1);
+        assert!(!buckets.contains_key(&client(0)));
+        assert!(buckets.contains_key(&client(MAX_TRACKED_CLIENTS - 1)));
+    }
+
+    #[test]
+    pub fn test_spoofed_forwarded_addresses_share_a_bucket() {
+        let config = PublicApiConfig {
+            requests_per_minute: 60,
+            burst: 1,
+            client_ip_header: Some("x-forwarded-for".into()),
+            ..Default::default()
+        };
+        let limiter = RateLimiter::new(&config, Metrics::new()).unwrap();
+        let now = Instant::now();
+
+        let request = |spoofed: &str| {
+            Request::builder()
+                .header("x-forwarded-for", format!("{}, 203.0.113.7", spoofed))
+                .body(Default::default())
+                .unwrap()
+        };
+
+        let first = limiter.client_ip(&request("10.0.0.1"));
+        let second = limiter.client_ip(&request("10.0.0.2"));
+        assert_eq!(first, IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)));
+        assert_eq!(first, second);
+
+        assert_eq!(limiter.check(first, now), Ok(()));
+        assert!(limiter.check(second, now).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Read replicas.
+//!
+//! Read-heavy endpoints can be served from a read-only copy of the database,
+//! like a LiteFS or Litestream replica, to take load off the primary. The
+//! replica is checked periodically, and reads fall back to the primary while
+//! it can't be reached.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use sqlx::SqlitePool;
+
+use crate::metrics::Metrics;
+
+/// The pools read-only queries can be made against.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct ReadPool {
+    primary: SqlitePool,
+    replica: Option<Replica>,
+}
+
+#[derive(Clone, Debug)]
+struct Replica {
+    pool: SqlitePool,
+    healthy: Arc<AtomicBool>,
+}
+
+impl ReadPool {
+    /// Creates a new `ReadPool`.
+    ///
+    /// The replica isn't used until it passes its first [check].
+    ///
+    /// [check]: ReadPool::check
+    pub fn new(primary: SqlitePool, replica: Option<SqlitePool>) -> ReadPool {
+        ReadPool {
+            primary,
+            replica: replica.map(|pool| Replica {
+                pool,
+                healthy: Arc::default(),
+            }),
+        }
+    }
+
+    /// The pool reads should be made against.
+    ///
+    /// This is the replica if it is healthy, and the primary otherwise.
+    pub fn pool(&self) -> &SqlitePool {
+        match &self.replica {
+            Some(replica) if replica.healthy.load(Ordering::Relaxed) => &replica.pool,
+            _ => &self.primary,
+        }
+    }
+
+    /// Whether the replica is healthy, or `None` if there is no replica.
+    pub fn replica_healthy(&self) -> Option<bool> {
+        self.replica
+            .as_ref()
+            .map(|replica| replica.healthy.load(Ordering::Relaxed))
+    }
+
+    /// Closes the replica's connections.
+    ///
+    /// The primary is left to its owner.
+    pub async fn close(&self) {
+        if let Some(replica) = &self.replica {
+            replica.pool.close().await;
+        }
+    }
+
+    /// Checks if the replica can be reached, falling back to the primary if
+    /// it can't.
+    pub async fn check(&self, metrics: &Metrics) {
+        let Some(replica) = &self.replica else {
+            return;
+        };
+
+        let healthy = match sqlx::query("SELECT 1").execute(&replica.pool).await {
+            Ok(_) => true,
+            Err(err) => {
+                tracing::debug!(%err, "read replica check failed");
+                false
+            }
+        };
+
+        let was_healthy = replica.healthy.swap(healthy, Ordering::Relaxed);
+        if healthy && !was_healthy {
+            tracing::info!("read replica is healthy, serving reads from it");
+        } else if !healthy && was_healthy {
+            tracing::warn!("read replica is unreachable, serving reads from the primary");
+            metrics.increment("replica_failovers_total", &[]);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Data retention.
+//!
+//! Some tables only ever grow: expired sessions, link codes and wager tokens
+//! are never read again, and chat and the audit log keep every message and
+//! request ever sent. These are cleaned up once a day, or on demand with
+//! `ring-channel cleanup`.
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use sqlx::SqliteConnection;
+
+use crate::{config::RetentionConfig, error::Error};
+
+/// The table sessions are stored in.
+pub const SESSION_TABLE: &str = "_session";
+
+/// How long wager client tokens are kept.
+///
+/// Clients only retry wagers for a few seconds, and betting is long closed
+/// by the time this passes.
+pub const WAGER_TOKEN_RETENTION: TimeDelta = TimeDelta::days(1);
+
+/// How many rows a cleanup deleted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CleanupReport {
+    /// Expired sessions.
+    pub sessions: u64,
+    /// Expired link codes.
+    pub link_codes: u64,
+    /// Wager client tokens older than [`WAGER_TOKEN_RETENTION`].
+    pub wager_tokens: u64,
+    /// Chat messages older than the retention window.
+    pub messages: u64,
+    /// Audit log entries older than the retention window.
+    pub audit_log: u64,
+}
+
+impl CleanupReport {
+    /// How many rows were deleted in total.
+    pub fn total(&self) -> u64 {
+        self.sessions + self.link_codes + self.wager_tokens + self.messages + self.audit_log
+    }
+}
+
+/// Deletes everything that expired, or fell out of retention, by `now`.
+pub async fn cleanup(
+    config: &RetentionConfig,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<CleanupReport, Error> {
+    let mut report = CleanupReport::default();
+
+    // the session store makes its table when the server first starts
+    let (has_sessions,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM sqlite_master
+            WHERE type = 'table' AND name = $1
+        )
+        "#,
+    )
+    .bind(SESSION_TABLE)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if has_sessions {
+        // session expiry dates are unix timestamps
+        report.sessions = sqlx::query(
+            r#"
+            DELETE FROM _session
+            WHERE expiry_date < $1
+            "#,
+        )
+        .bind(now.timestamp())
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    }
+
+    report.link_codes = sqlx::query(
+        r#"
+        DELETE FROM link_code
+        WHERE expires_at < $1
+        "#,
+    )
+    .bind(now)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    report.wager_tokens = sqlx::query(
+        r#"
+        DELETE FROM wager_token
+        WHERE inserted_at < $1
+        "#,
+    )
+    .bind(now - WAGER_TOKEN_RETENTION)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    report.audit_log = sqlx::query(
+        r#"
+        DELETE FROM audit_log
+        WHERE inserted_at < $1
+        "#,
+    )
+    .bind(now - config.audit_log_retention)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    if config.prune_chat {
+        report.messages = sqlx::query(
+            r#"
+            DELETE FROM message
+            WHERE inserted_at < $1
+            "#,
+        )
+        .bind(now - config.chat_retention)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing;
+
+    #[tokio::test]
+    async fn test_cleanup_prunes_old_audit_log() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+        let config = RetentionConfig::default();
+        let now = Utc::now();
+
+        for inserted_at in [now - config.audit_log_retention - TimeDelta::days(1), now] {
+            sqlx::query(
+                r#"
+                INSERT INTO audit_log (actor_kind, method, path, status, inserted_at)
+                VALUES (0, 'POST', '/users/~me/link', 401, $1)
+                "#,
+            )
+            .bind(inserted_at)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        }
+
+        let report = cleanup(&config, now, &mut conn).await.unwrap();
+        assert_eq!(report.audit_log, 1);
+
+        let (left,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM audit_log")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(left, 1);
+    }
+}
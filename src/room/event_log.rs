@@ -0,0 +1,217 @@
+//! Room event logging.
+//!
+//! When a user says they never saw a match update, there's little to go on
+//! after the fact. The [`EventLog`] keeps the most recent events the room
+//! broadcast, and optionally writes them to the database through
+//! [`run_event_log_writer`], so operators can see what was sent, and when.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicI64, Ordering},
+    },
+};
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::{UserId, admin::RoomEvent, message::Message};
+
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+
+use tokio::sync::mpsc;
+
+use crate::{config::EventLogConfig, error::Error};
+
+/// The most recent events broadcast to the room.
+///
+/// Does nothing if event logging is disabled. Cheaply cloneable.
+#[derive(Clone, Debug, Default)]
+pub struct EventLog {
+    inner: Option<Arc<EventLogInner>>,
+}
+
+#[derive(Debug)]
+struct EventLogInner {
+    events: Mutex<VecDeque<RoomEvent>>,
+    capacity: usize,
+    next_id: AtomicI64,
+    writer: Option<mpsc::UnboundedSender<RoomEvent>>,
+}
+
+/// The receiving end of a persisted [`EventLog`].
+///
+/// Pass this to [`run_event_log_writer`].
+#[derive(Debug)]
+pub struct EventLogReceiver {
+    rx: mpsc::UnboundedReceiver<RoomEvent>,
+    capacity: usize,
+}
+
+impl EventLog {
+    /// Creates a new `EventLog` from config.
+    ///
+    /// If events are persisted, this also returns the receiver the writer
+    /// needs.
+    pub fn new(config: &EventLogConfig) -> (EventLog, Option<EventLogReceiver>) {
+        if !config.enabled {
+            return (EventLog::default(), None);
+        }
+
+        let (writer, receiver) = if config.persist {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let receiver = EventLogReceiver {
+                rx,
+                capacity: config.capacity,
+            };
+            (Some(tx), Some(receiver))
+        } else {
+            (None, None)
+        };
+
+        let log = EventLog {
+            inner: Some(Arc::new(EventLogInner {
+                events: Mutex::default(),
+                capacity: config.capacity,
+                next_id: AtomicI64::new(1),
+                writer,
+            })),
+        };
+
+        (log, receiver)
+    }
+
+    /// The most recent events, newest first.
+    pub fn list(&self) -> Vec<RoomEvent> {
+        let Some(inner) = &self.inner else {
+            return vec![];
+        };
+
+        let events = inner.events.lock().expect("event log poisoned");
+        events.iter().rev().cloned().collect()
+    }
+
+    /// Records an event.
+    ///
+    /// `event` is only called if events are being logged, so events that
+    /// aren't kept aren't built either.
+    pub(super) fn record(&self, event: impl FnOnce(i64) -> RoomEvent) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+
+        let event = event(inner.next_id.fetch_add(1, Ordering::Relaxed));
+
+        if let Some(writer) = &inner.writer {
+            // the writer only stops when the server does
+            let _ = writer.send(event.clone());
+        }
+
+        let mut events = inner.events.lock().expect("event log poisoned");
+        if events.len() >= inner.capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+}
+
+#[derive(FromRow)]
+struct RoomEventQuery {
+    id: i64,
+    kind: String,
+    message: Option<String>,
+    recipients: String,
+    private_to: Option<UserId>,
+    receivers: i64,
+    sent_at: DateTime<Utc>,
+}
+
+/// Writes logged events to the database until the [`EventLog`] is dropped.
+///
+/// Only the most recent events are kept, like in memory.
+pub async fn run_event_log_writer(db: SqlitePool, receiver: &mut EventLogReceiver) {
+    while let Some(event) = receiver.rx.recv().await {
+        if let Err(err) = write_event(&event, receiver.capacity, &db).await {
+            tracing::warn!(%err, "failed to persist room event");
+        }
+    }
+}
+
+async fn write_event(event: &RoomEvent, capacity: usize, db: &SqlitePool) -> Result<(), Error> {
+    let message = event
+        .message
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(Error::new)?;
+    let recipients = serde_json::to_string(&event.recipients).map_err(Error::new)?;
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO room_event
+            (kind, message, recipients, private_to, receivers, sent_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(&event.kind)
+    .bind(message)
+    .bind(recipients)
+    .bind(event.private_to)
+    .bind(event.receivers as i64)
+    .bind(event.sent_at)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM room_event
+        WHERE id <= (SELECT MAX(id) FROM room_event) - $1
+        "#,
+    )
+    .bind(capacity as i64)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Lists the events written to the database, newest first.
+pub async fn list_persisted_events(conn: &mut SqliteConnection) -> Result<Vec<RoomEvent>, Error> {
+    let events = sqlx::query_as::<_, RoomEventQuery>(
+        r#"
+        SELECT id, kind, message, recipients, private_to, receivers, sent_at
+        FROM room_event
+        ORDER BY id DESC
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    events
+        .into_iter()
+        .map(|query| {
+            let message = query
+                .message
+                .as_deref()
+                .map(serde_json::from_str::<Message>)
+                .transpose()
+                .map_err(Error::new)?;
+            let recipients = serde_json::from_str(&query.recipients).map_err(Error::new)?;
+
+            Ok(RoomEvent {
+                id: query.id,
+                kind: query.kind,
+                message,
+                recipients,
+                private_to: query.private_to,
+                receivers: query.receivers as u64,
+                sent_at: query.sent_at,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,138 @@
+//! Wager heatmaps.
+//!
+//! While a match is accepting bets, its wagers are periodically summarized
+//! and sent to the room, so clients can follow the pots without watching
+//! every single wager. Each squad's wagers are summarized alongside, but only
+//! sent when they change.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::{
+    BattleId,
+    battle::{BattleStatus, PlayerTeam, WagerMarket},
+    message::server::{MilestoneKind, SquadUpdate, TeamHeat, WagerHeatmap},
+    squad::SquadPot,
+};
+
+use sqlx::FromRow;
+
+use crate::{app::AppState, error::Error, squad::squad_pots};
+
+/// How often wagers are summarized.
+pub const HEATMAP_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Periodically summarizes the wagers on a match until it stops accepting
+/// bets.
+///
+/// This should be spawned as its own task when the match is created.
+pub async fn run_heatmap(app: AppState, battle_id: BattleId, match_id: String) {
+    let mut interval = tokio::time::interval(HEATMAP_INTERVAL);
+    let mut last = WagerHeatmap {
+        match_id,
+        red: TeamHeat::default(),
+        blue: TeamHeat::default(),
+    };
+    let mut last_squads = Vec::new();
+
+    loop {
+        interval.tick().await;
+
+        match tick(&app, battle_id, &mut last, &mut last_squads).await {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => {
+                tracing::error!(?err, %battle_id, "failed to summarize wagers");
+                break;
+            }
+        }
+    }
+}
+
+/// Sends a single summary, returning whether the match is still accepting
+/// bets.
+async fn tick(
+    app: &AppState,
+    battle_id: BattleId,
+    last: &mut WagerHeatmap,
+    last_squads: &mut Vec<SquadPot>,
+) -> Result<bool, Error> {
+    #[derive(FromRow)]
+    struct PotQuery {
+        victor: PlayerTeam,
+        pot: i64,
+        wagers: i32,
+    }
+
+    let (status, closed_at) = sqlx::query_as::<_, (BattleStatus, DateTime<Utc>)>(
+        r#"
+        SELECT status, closed_at
+        FROM battle
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_one(&app.db)
+    .await?;
+
+    // the match may have ended early
+    if status != BattleStatus::Ongoing {
+        return Ok(false);
+    }
+
+    let pots = sqlx::query_as::<_, PotQuery>(
+        r#"
+        SELECT
+            victor,
+            IFNULL(SUM(mobiums), 0) AS pot,
+            SUM(mobiums > 0) AS wagers
+        FROM wager
+        WHERE match_id = $1 AND market = $2
+        GROUP BY victor
+        "#,
+    )
+    .bind(battle_id)
+    .bind(WagerMarket::Winner)
+    .fetch_all(&app.db)
+    .await?;
+
+    let mut heatmap = WagerHeatmap {
+        match_id: last.match_id.clone(),
+        red: TeamHeat::default(),
+        blue: TeamHeat::default(),
+    };
+
+    for pot in pots {
+        let (heat, last_heat) = match pot.victor {
+            PlayerTeam::Red => (&mut heatmap.red, &last.red),
+            PlayerTeam::Blue => (&mut heatmap.blue, &last.blue),
+        };
+
+        heat.pot = pot.pot;
+        heat.wagers = pot.wagers;
+        heat.pot_delta = pot.pot - last_heat.pot;
+        heat.wagers_delta = pot.wagers - last_heat.wagers;
+    }
+
+    app.room.reach_milestone(
+        &heatmap.match_id,
+        MilestoneKind::PotSize,
+        heatmap.red.pot + heatmap.blue.pot,
+    );
+    app.room.send_wager_heatmap(heatmap.clone()).await;
+
+    let squads = squad_pots(battle_id, &mut *app.db.acquire().await?).await?;
+    if squads != *last_squads {
+        app.room.send_squad_update(SquadUpdate {
+            match_id: heatmap.match_id.clone(),
+            squads: squads.clone(),
+        });
+        *last_squads = squads;
+    }
+
+    *last = heatmap;
+
+    // the last summary is sent as betting closes
+    Ok(Utc::now() < closed_at)
+}
@@ -0,0 +1,129 @@
+//! Room milestones.
+//!
+//! While a match is ongoing, the room's viewer count and the match's pots are
+//! watched, and the room is told whenever one of them crosses a configured
+//! threshold. Each threshold is only celebrated once per match.
+
+use std::collections::HashMap;
+
+use ring_channel_model::{battle::BattleStatus, message::server::MilestoneKind};
+
+use crate::room::BattleData;
+
+/// The milestones reached by the room's ongoing matches, by match id.
+#[derive(Debug, Default)]
+pub struct Milestones {
+    matches: HashMap<String, Reached>,
+}
+
+#[derive(Debug, Default)]
+struct Reached {
+    viewers: i64,
+    pot_size: i64,
+}
+
+impl Milestones {
+    /// Follows one of the room's matches.
+    ///
+    /// Milestones start being reached when the match is first seen, and are
+    /// forgotten once the match is no longer ongoing.
+    pub fn track(&mut self, battle: &BattleData) {
+        if battle.status != BattleStatus::Ongoing {
+            self.matches.remove(&battle.uuid);
+        } else {
+            self.matches.entry(battle.uuid.clone()).or_default();
+        }
+    }
+
+    /// The ids of the matches being tracked.
+    pub fn match_ids(&self) -> impl Iterator<Item = &str> {
+        self.matches.keys().map(String::as_str)
+    }
+
+    /// Records a new value for a match.
+    ///
+    /// Returns the highest threshold the value crossed that hasn't been
+    /// reached before, if any.
+    pub fn reach(
+        &mut self,
+        match_id: &str,
+        kind: MilestoneKind,
+        value: i64,
+        thresholds: &[i64],
+    ) -> Option<i64> {
+        let reached = self.matches.get_mut(match_id)?;
+
+        let last = match kind {
+            MilestoneKind::Viewers => &mut reached.viewers,
+            MilestoneKind::PotSize => &mut reached.pot_size,
+        };
+
+        let threshold = thresholds
+            .iter()
+            .copied()
+            .filter(|&threshold| threshold > *last && threshold <= value)
+            .max()?;
+
+        *last = threshold;
+        Some(threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn milestones(match_id: &str) -> Milestones {
+        Milestones {
+            matches: HashMap::from([(match_id.to_owned(), Reached::default())]),
+        }
+    }
+
+    #[test]
+    pub fn test_thresholds_are_reached_once() {
+        let mut milestones = milestones("a");
+        let thresholds = [100, 1000, 10];
+
+        assert_eq!(
+            milestones.reach("a", MilestoneKind::PotSize, 5, &thresholds),
+            None
+        );
+        // skipping a threshold only celebrates the highest
+        assert_eq!(
+            milestones.reach("a", MilestoneKind::PotSize, 150, &thresholds),
+            Some(100)
+        );
+        assert_eq!(
+            milestones.reach("a", MilestoneKind::PotSize, 120, &thresholds),
+            None
+        );
+        assert_eq!(
+            milestones.reach("a", MilestoneKind::PotSize, 150, &thresholds),
+            None
+        );
+        assert_eq!(
+            milestones.reach("a", MilestoneKind::PotSize, 1000, &thresholds),
+            Some(1000)
+        );
+
+        // kinds are tracked separately
+        assert_eq!(
+            milestones.reach("a", MilestoneKind::Viewers, 10, &thresholds),
+            Some(10)
+        );
+    }
+
+    #[test]
+    pub fn test_other_matches_are_ignored() {
+        let mut milestones = milestones("a");
+
+        assert_eq!(
+            milestones.reach("b", MilestoneKind::Viewers, 10, &[10]),
+            None
+        );
+        assert_eq!(
+            Milestones::default().reach("a", MilestoneKind::Viewers, 10, &[10]),
+            None
+        );
+    }
+}
@@ -3,32 +3,101 @@
 //! Users can connect to a server room, which streams events directly from that
 //! server into websockets! The future is NOW.
 
+pub mod event_log;
+pub mod heatmap;
+pub mod milestone;
 pub mod protocol;
 
-pub use protocol::{Error, WebSocket};
+pub use protocol::{Error, GZIP_SUBPROTOCOL, WebSocket};
 pub use ring_channel_model::message::Message;
 
+use event_log::EventLog;
+use milestone::Milestones;
+
 use derive_more::Deref;
 
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
 
 use futures_util::SinkExt as _;
 
 use ring_channel_model::{
-    Battle, BattleWager,
-    battle::Participant,
+    Battle, BattleWager, User, UserId,
+    admin::{self, ActorKind, Socket},
+    announcement::Announcement,
+    battle::{BattleStatus, LineWager, LoadoutChange, Participant, PlayerTeam},
     chat::Message as ChatMessage,
-    message::server::{BattleUpdate, MobiumsChange, NewBattle, NewMessage, WagerUpdate},
+    event::PromoEvent,
+    message::{
+        client::{Authenticate, PlaceWager, Pong, SendChat, Topic},
+        server::{
+            Authenticated, AutoBetPlaced, BattleSettled, BattleUpdate, Commentary, Hello,
+            Highlight, LineSettled, LineWagerUpdate, LoadoutChanged, MessageDeleted, MessageEdited,
+            MessageReaction, Milestone, MilestoneKind, MobiumsChange, NewBattle, NewMessage,
+            OpError, Ping, Reconnect, SettlementProgress, SquadUpdate, WagerHeatmap, WagerUpdate,
+        },
+    },
+    user::PenaltyKind,
 };
 
-use tokio::sync::{
-    RwLock,
-    broadcast::{self, Receiver, Sender, error::RecvError},
+use tokio::{
+    sync::{
+        RwLock,
+        broadcast::{self, Receiver, Sender, error::RecvError},
+        oneshot,
+    },
+    time::{Interval, MissedTickBehavior},
 };
 
 use tracing::instrument;
 
-use crate::{battle::BattleSchema, session::SessionUser};
+use uuid::Uuid;
+
+use crate::{
+    app::AppState,
+    audit::Audit,
+    battle::BattleSchema,
+    config::MilestoneConfig,
+    error::{Error as AppError, ErrorKind},
+    metrics::Metrics,
+    routes::{battle::wager::place_wager, chat::send_user_message},
+    session::{SessionUser, generate_csrf},
+    user::penalty::check_penalty,
+    wager_queue::WagerRequest,
+};
+
+/// How long a socket ticket can be redeemed for.
+pub const TICKET_LIFETIME: Duration = Duration::from_secs(60);
+
+/// How long a socket's session can be resumed for after it disconnects.
+pub const SESSION_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// How many times sending an event to a client is attempted before it is
+/// dropped.
+pub const MAX_DELIVERY_ATTEMPTS: usize = 3;
+
+/// The close code sent to sockets disconnected by an administrator.
+pub const DISCONNECTED_CLOSE_CODE: u16 = 4000;
+
+/// The close code sent to sockets still connected when a drain times out.
+pub const DRAINED_CLOSE_CODE: u16 = 4001;
+
+/// How long a finished match stays in the room.
+///
+/// This gives clients that join just after a match ends a chance to see how
+/// it went.
+pub const FINISHED_BATTLE_RETENTION: TimeDelta = TimeDelta::minutes(5);
+
+/// How often a drain checks if every socket has left.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 /// An open room.
 ///
@@ -43,7 +112,45 @@ pub struct Room {
 #[derive(Debug)]
 struct RoomState {
     tx: Sender<RoomEvent>,
-    current_battle: RwLock<Option<BattleData>>,
+    metrics: Metrics,
+    event_log: EventLog,
+    battles: RwLock<HashMap<String, BattleData>>,
+    tickets: Mutex<HashMap<String, Ticket>>,
+    sessions: Mutex<HashMap<String, SuspendedSession>>,
+    sockets: Mutex<HashMap<String, SocketEntry>>,
+    milestones: Mutex<Milestones>,
+    milestone_config: MilestoneConfig,
+    draining: AtomicBool,
+}
+
+#[derive(Debug)]
+struct SocketEntry {
+    info: Socket,
+    disconnect: Option<oneshot::Sender<CloseReason>>,
+}
+
+/// Why the server closed a socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CloseReason {
+    /// An administrator disconnected the socket.
+    Disconnected,
+    /// The socket was still connected when a drain timed out.
+    Drained,
+}
+
+#[derive(Debug)]
+struct Ticket {
+    user_id: UserId,
+    expires_at: Instant,
+}
+
+/// The session of a socket that disconnected.
+#[derive(Debug)]
+struct SuspendedSession {
+    user: Option<SessionUser>,
+    lag_count: u64,
+    reconnects: u64,
+    expires_at: Instant,
 }
 
 /// Internal battle data held by the server.
@@ -62,6 +169,63 @@ impl From<BattleData> for Battle {
     }
 }
 
+impl BattleData {
+    /// Checks if the match is still being played, or can still be amended.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self.status,
+            BattleStatus::Ongoing | BattleStatus::Provisional
+        )
+    }
+
+    /// Checks if the match finished long enough ago to be dropped from the
+    /// room.
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        !self.is_active() && now - self.updated_at >= FINISHED_BATTLE_RETENTION
+    }
+
+    /// Applies a loadout change to the match's participants.
+    ///
+    /// Changes to other matches are ignored.
+    fn apply_loadout(&mut self, match_id: &str, change: &LoadoutChange) {
+        if self.schema.uuid != match_id {
+            return;
+        }
+
+        if let Some(participant) = self
+            .participants
+            .iter_mut()
+            .find(|participant| participant.id == change.player_id)
+        {
+            participant.kart_speed = Some(change.kart_speed);
+            participant.kart_weight = Some(change.kart_weight);
+            participant.skin = Some(change.skin.clone());
+        }
+    }
+
+    /// Applies a wager summary to the match's participants.
+    ///
+    /// Summaries of other matches are ignored.
+    fn apply_heatmap(&mut self, heatmap: &WagerHeatmap) {
+        if self.schema.uuid != heatmap.match_id {
+            return;
+        }
+
+        for participant in self.participants.iter_mut() {
+            let heat = match participant.team {
+                PlayerTeam::Red => &heatmap.red,
+                PlayerTeam::Blue => &heatmap.blue,
+            };
+
+            participant.backers = i64::from(heat.wagers);
+            participant.backed_mobiums = heat.pot;
+        }
+
+        self.schema.red_pot = heatmap.red.pot;
+        self.schema.blue_pot = heatmap.blue.pot;
+    }
+}
+
 impl From<&BattleData> for Battle {
     fn from(value: &BattleData) -> Self {
         let mut battle = Battle::from(&value.schema);
@@ -72,59 +236,571 @@ impl From<&BattleData> for Battle {
 
 impl Room {
     /// Creates a new `Room`.
-    pub fn new() -> Room {
+    pub fn new(milestone_config: MilestoneConfig, metrics: Metrics, event_log: EventLog) -> Room {
         let (tx, _rx) = broadcast::channel(16);
 
         Room {
             state: Arc::new(RoomState {
                 tx,
-                current_battle: RwLock::default(),
+                metrics,
+                event_log,
+                battles: RwLock::default(),
+                tickets: Mutex::default(),
+                sessions: Mutex::default(),
+                sockets: Mutex::default(),
+                milestones: Mutex::default(),
+                milestone_config,
+                draining: AtomicBool::new(false),
             }),
         }
     }
 
     /// Sends a new message in the room.
     pub async fn send_message(&self, message: ChatMessage) {
-        let _ = self.state.tx.send(RoomEvent::NewMessage { message });
+        self.broadcast(RoomEvent::NewMessage { message });
+    }
+
+    /// Notifies the room of an edited message.
+    pub fn edit_message(&self, message: ChatMessage) {
+        self.broadcast(RoomEvent::EditMessage { message });
+    }
+
+    /// Notifies the room of a deleted message.
+    pub fn delete_message(&self, id: i32) {
+        self.broadcast(RoomEvent::DeleteMessage { id });
+    }
+
+    /// Notifies the room that the reactions to a message changed.
+    pub fn send_message_reaction(&self, message: MessageReaction) {
+        self.broadcast(RoomEvent::MessageReaction { message });
     }
 
-    /// Sets a new match for the room, broadcasting it to all clients.
+    /// Adds or updates one of the room's matches, broadcasting it to all
+    /// clients.
+    ///
+    /// Any number of matches can be in the room at once. Finished matches
+    /// are dropped after [`FINISHED_BATTLE_RETENTION`].
     pub async fn update_battle(&self, new_battle: BattleData) {
-        *self.state.current_battle.write().await = Some(new_battle.clone());
-        let _ = self
+        self.track_milestones(&new_battle);
+        self.store_battle(&new_battle).await;
+        self.broadcast(RoomEvent::UpdateBattle { battle: new_battle });
+    }
+
+    /// Replaces one of the room's matches, broadcasting it to all clients as
+    /// if it were a new match.
+    ///
+    /// Use this when the match changed enough that clients should start
+    /// over, such as when participants are swapped.
+    pub async fn replace_battle(&self, new_battle: BattleData) {
+        self.track_milestones(&new_battle);
+        self.store_battle(&new_battle).await;
+        self.broadcast(RoomEvent::ReplaceBattle { battle: new_battle });
+    }
+
+    async fn store_battle(&self, battle: &BattleData) {
+        let mut battles = self.state.battles.write().await;
+        battles.insert(battle.uuid.clone(), battle.clone());
+        retain_unexpired(&mut battles);
+    }
+
+    /// Updates users with a wager change on a match.
+    ///
+    /// If `private_to` is set, the wager's user is hidden from everyone but
+    /// that user and administrators.
+    pub fn send_wager_update(
+        &self,
+        match_id: &str,
+        wager: BattleWager,
+        private_to: Option<UserId>,
+    ) {
+        self.broadcast(RoomEvent::WagerUpdate {
+            message: WagerUpdate {
+                match_id: match_id.to_owned(),
+                wager,
+            },
+            private_to,
+        });
+    }
+
+    /// Updates users with a wager change on the finish time line of a match.
+    ///
+    /// If `private_to` is set, the wager's user is hidden from everyone but
+    /// that user and administrators.
+    pub fn send_line_wager_update(
+        &self,
+        match_id: &str,
+        wager: LineWager,
+        private_to: Option<UserId>,
+    ) {
+        self.broadcast(RoomEvent::LineWagerUpdate {
+            message: LineWagerUpdate {
+                match_id: match_id.to_owned(),
+                wager,
+            },
+            private_to,
+        });
+    }
+
+    /// Sends a wager summary to the room.
+    ///
+    /// The summarized match is kept up to date, so clients that join later
+    /// see the same pots.
+    pub async fn send_wager_heatmap(&self, message: WagerHeatmap) {
+        if let Some(battle) = self.state.battles.write().await.get_mut(&message.match_id) {
+            battle.apply_heatmap(&message);
+        }
+        self.broadcast(RoomEvent::WagerHeatmap { message });
+    }
+
+    /// Sends a summary of each squad's wagers to the room.
+    pub fn send_squad_update(&self, message: SquadUpdate) {
+        self.broadcast(RoomEvent::SquadUpdate { message });
+    }
+
+    /// Notifies the room of progress paying out a match.
+    pub fn send_settlement_progress(&self, message: SettlementProgress) {
+        self.broadcast(RoomEvent::SettlementProgress { message });
+    }
+
+    /// Notifies the room that a match was paid out.
+    pub fn send_battle_settled(&self, message: BattleSettled) {
+        self.broadcast(RoomEvent::BattleSettled { message });
+    }
+
+    /// Notifies the room that the finish time line of a match was paid out.
+    pub fn send_line_settled(&self, message: LineSettled) {
+        self.broadcast(RoomEvent::LineSettled { message });
+    }
+
+    /// Notifies the room that a match was a highlight.
+    pub fn send_highlight(&self, message: Highlight) {
+        self.broadcast(RoomEvent::Highlight { message });
+    }
+
+    /// Notifies the room that a participant changed their loadout.
+    pub async fn send_loadout_change(&self, message: LoadoutChanged) {
+        if let Some(battle) = self.state.battles.write().await.get_mut(&message.match_id) {
+            battle.apply_loadout(&message.match_id, &message.change);
+        }
+        self.broadcast(RoomEvent::LoadoutChanged { message });
+    }
+
+    /// Notifies the room of commentary on a match.
+    pub fn send_commentary(&self, message: Commentary) {
+        self.broadcast(RoomEvent::Commentary { message });
+    }
+
+    /// Notifies the room if one of its matches crossed a milestone.
+    ///
+    /// Values for matches that aren't ongoing are ignored.
+    pub fn reach_milestone(&self, match_id: &str, kind: MilestoneKind, value: i64) {
+        let config = &self.state.milestone_config;
+        if !config.enabled {
+            return;
+        }
+
+        let thresholds = match kind {
+            MilestoneKind::Viewers => &config.viewers,
+            MilestoneKind::PotSize => &config.pot_sizes,
+        };
+
+        let reached = self
             .state
-            .tx
-            .send(RoomEvent::UpdateBattle { battle: new_battle });
+            .milestones
+            .lock()
+            .expect("milestones poisoned")
+            .reach(match_id, kind, value, thresholds);
+
+        if let Some(value) = reached {
+            self.broadcast(RoomEvent::Milestone {
+                message: Milestone {
+                    match_id: match_id.to_owned(),
+                    kind,
+                    value,
+                },
+            });
+        }
     }
 
-    /// Updates users with a wager change.
-    pub fn send_wager_update(&self, wager: BattleWager) {
-        let _ = self.state.tx.send(RoomEvent::WagerUpdate { wager });
+    fn track_milestones(&self, battle: &BattleData) {
+        self.state
+            .milestones
+            .lock()
+            .expect("milestones poisoned")
+            .track(battle);
     }
 
     /// Notifies a connected client of mobiums loss (or gain).
-    pub fn send_mobiums_change(&self, user_id: i32, change: MobiumsChange) {
-        let _ = self.state.tx.send(RoomEvent::MobiumsChange {
+    pub fn send_mobiums_change(&self, user_id: UserId, change: MobiumsChange) {
+        self.broadcast(RoomEvent::MobiumsChange {
             user_id,
             message: change,
         });
     }
 
+    /// Notifies a connected client that their auto-bet rule placed a wager.
+    pub fn send_auto_bet(&self, user_id: UserId, auto_bet: AutoBetPlaced) {
+        self.broadcast(RoomEvent::AutoBetPlaced {
+            user_id,
+            message: auto_bet,
+        });
+    }
+
+    /// Notifies connected clients of mobiums changes to many users at once.
+    ///
+    /// This is a single room event, no matter how many users changed.
+    pub fn send_mobiums_changes(&self, changes: HashMap<UserId, MobiumsChange>) {
+        if changes.is_empty() {
+            return;
+        }
+
+        self.broadcast(RoomEvent::MobiumsChanges {
+            changes: Arc::new(changes),
+        });
+    }
+
+    /// Shows an announcement to every connected client.
+    pub fn send_announcement(&self, announcement: Announcement) {
+        self.broadcast(RoomEvent::Announcement { announcement });
+    }
+
+    /// Tells every connected client about a promotional event.
+    pub fn send_promo_active(&self, event: PromoEvent) {
+        self.broadcast(RoomEvent::PromoActive { event });
+    }
+
+    /// Broadcasts an event to every connected client.
+    fn broadcast(&self, event: RoomEvent) {
+        let kind = event.kind();
+
+        self.state
+            .event_log
+            .record(|id| event.to_logged(id, self.state.tx.receiver_count() as u64, Utc::now()));
+
+        match self.state.tx.send(event) {
+            Ok(_) => {
+                self.state
+                    .metrics
+                    .increment("room_events_sent_total", &[("event", kind)]);
+            }
+            // nobody is connected, this isn't really an error
+            Err(_) => {
+                tracing::debug!(kind, "no clients to receive room event");
+                self.state
+                    .metrics
+                    .increment("room_events_undelivered_total", &[("event", kind)]);
+            }
+        }
+    }
+
+    /// The most recent events broadcast to the room, newest first.
+    ///
+    /// Empty if event logging is disabled.
+    pub fn events(&self) -> Vec<admin::RoomEvent> {
+        self.state.event_log.list()
+    }
+
+    /// One of the room's matches.
+    pub async fn battle(&self, match_id: &str) -> Option<BattleData> {
+        self.state.battles.read().await.get(match_id).cloned()
+    }
+
+    /// Every match in the room, oldest first.
+    pub async fn battles(&self) -> Vec<BattleData> {
+        let mut battles = self
+            .state
+            .battles
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        battles.sort_by(|a, b| a.inserted_at.cmp(&b.inserted_at).then(a.uuid.cmp(&b.uuid)));
+        battles
+    }
+
+    /// Issues a one-time ticket a socket can use to authenticate as a user.
+    pub fn issue_ticket(&self, user_id: UserId) -> String {
+        let now = Instant::now();
+        let ticket = generate_csrf();
+
+        let mut tickets = self.state.tickets.lock().expect("tickets poisoned");
+        // clean up old tickets while we're here
+        tickets.retain(|_, ticket| ticket.expires_at > now);
+        tickets.insert(
+            ticket.clone(),
+            Ticket {
+                user_id,
+                expires_at: now + TICKET_LIFETIME,
+            },
+        );
+
+        ticket
+    }
+
+    /// Redeems a ticket, returning the user it was issued for.
+    fn redeem_ticket(&self, ticket: &str) -> Option<UserId> {
+        let mut tickets = self.state.tickets.lock().expect("tickets poisoned");
+
+        tickets
+            .remove(ticket)
+            .filter(|ticket| ticket.expires_at > Instant::now())
+            .map(|ticket| ticket.user_id)
+    }
+
+    /// Resumes the session of a socket that disconnected.
+    ///
+    /// Sessions can only be resumed once; the new socket suspends it again
+    /// when it disconnects.
+    fn resume_session(&self, session_id: &str) -> Option<SuspendedSession> {
+        let mut sessions = self.state.sessions.lock().expect("sessions poisoned");
+
+        sessions
+            .remove(session_id)
+            .filter(|session| session.expires_at > Instant::now())
+    }
+
+    /// Keeps the session of a disconnected socket around, so it can be
+    /// resumed.
+    fn suspend_session(&self, session_id: String, user: Option<SessionUser>, info: Socket) {
+        let now = Instant::now();
+
+        let mut sessions = self.state.sessions.lock().expect("sessions poisoned");
+        // clean up old sessions while we're here
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(
+            session_id,
+            SuspendedSession {
+                user,
+                lag_count: info.lag_count,
+                reconnects: info.reconnects,
+                expires_at: now + SESSION_LIFETIME,
+            },
+        );
+    }
+
+    /// Lists every connected socket, oldest first.
+    pub fn sockets(&self) -> Vec<Socket> {
+        let sockets = self.state.sockets.lock().expect("sockets poisoned");
+
+        let mut sockets = sockets
+            .values()
+            .map(|socket| socket.info.clone())
+            .collect::<Vec<_>>();
+        sockets.sort_by_key(|socket| socket.connected_at);
+        sockets
+    }
+
+    /// Disconnects a socket.
+    ///
+    /// Returns `false` if there is no socket with that id.
+    pub fn disconnect(&self, socket_id: &str) -> bool {
+        let mut sockets = self.state.sockets.lock().expect("sockets poisoned");
+
+        let Some(socket) = sockets.get_mut(socket_id) else {
+            return false;
+        };
+
+        // the socket may already be on its way out
+        if let Some(disconnect) = socket.disconnect.take() {
+            let _ = disconnect.send(CloseReason::Disconnected);
+        }
+
+        true
+    }
+
+    /// Whether the room is draining, and turning away new sockets.
+    pub fn is_draining(&self) -> bool {
+        self.state.draining.load(Ordering::Acquire)
+    }
+
+    /// Drains the room.
+    ///
+    /// New sockets are turned away from now on, and every connected client is
+    /// told to reconnect, to `url` if it is set. This waits until every
+    /// client leaves, or `timeout` passes, after which the stragglers are
+    /// disconnected.
+    pub async fn drain(&self, url: Option<String>, timeout: Duration) {
+        self.state.draining.store(true, Ordering::Release);
+
+        let sockets = self.state.sockets.lock().expect("sockets poisoned").len();
+        tracing::info!(sockets, ?timeout, "draining sockets");
+
+        self.broadcast(RoomEvent::Reconnect {
+            message: Reconnect {
+                url,
+                closes_in: timeout.as_millis() as i64,
+            },
+        });
+
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if self
+                .state
+                .sockets
+                .lock()
+                .expect("sockets poisoned")
+                .is_empty()
+            {
+                tracing::info!("every socket drained");
+                return;
+            }
+
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        let mut sockets = self.state.sockets.lock().expect("sockets poisoned");
+        tracing::info!(sockets = sockets.len(), "drain timed out, closing sockets");
+        for socket in sockets.values_mut() {
+            if let Some(disconnect) = socket.disconnect.take() {
+                let _ = disconnect.send(CloseReason::Drained);
+            }
+        }
+    }
+
+    fn register_socket(
+        &self,
+        session_id: &str,
+        user: Option<User>,
+        resumed: Option<&SuspendedSession>,
+    ) -> (String, oneshot::Receiver<CloseReason>) {
+        let id = Uuid::new_v4().to_string();
+        let (tx, rx) = oneshot::channel();
+
+        let mut sockets = self.state.sockets.lock().expect("sockets poisoned");
+        sockets.insert(
+            id.clone(),
+            SocketEntry {
+                info: Socket {
+                    id: id.clone(),
+                    session_id: session_id.to_owned(),
+                    user,
+                    connected_at: Utc::now(),
+                    last_heartbeat: None,
+                    rtt: None,
+                    lag_count: resumed.map_or(0, |session| session.lag_count),
+                    reconnects: resumed.map_or(0, |session| session.reconnects + 1),
+                },
+                disconnect: Some(tx),
+            },
+        );
+
+        (id, rx)
+    }
+
+    fn update_socket(&self, socket_id: &str, f: impl FnOnce(&mut Socket)) {
+        let mut sockets = self.state.sockets.lock().expect("sockets poisoned");
+
+        if let Some(socket) = sockets.get_mut(socket_id) {
+            f(&mut socket.info);
+        }
+    }
+
+    fn unregister_socket(&self, socket_id: &str) -> Option<Socket> {
+        let mut sockets = self.state.sockets.lock().expect("sockets poisoned");
+        sockets.remove(socket_id).map(|socket| socket.info)
+    }
+
     /// Serves a new client, with additional authentication information.
     ///
+    /// `identity` is the user of the client's session cookie, if any.
+    /// `session_id` is the session the client wants to resume, if any.
+    ///
     /// **This commandeers the calling task!**
-    pub async fn serve(self, ws: axum::extract::ws::WebSocket, user: Option<SessionUser>) {
-        let battle = self.state.current_battle.read().await.clone();
+    pub async fn serve(
+        self,
+        app: AppState,
+        ws: axum::extract::ws::WebSocket,
+        identity: Option<UserId>,
+        session_id: Option<String>,
+    ) {
+        let battles = self.state.battles.read().await.clone();
+
+        tracing::debug!(battles = battles.len(), "serving new client");
+
+        let resumed = session_id
+            .as_deref()
+            .and_then(|session_id| self.resume_session(session_id));
+        let session_id = match (session_id, &resumed) {
+            (Some(session_id), Some(_)) => {
+                self.state
+                    .metrics
+                    .increment("socket_sessions_resumed_total", &[]);
+                session_id
+            }
+            _ => generate_csrf(),
+        };
+
+        // a resumed session already knows who the client is
+        let known = resumed.as_ref().and_then(|session| session.user.clone());
+        let user = match (identity, known) {
+            (Some(identity), Some(user)) if user.identity() == identity => Some(user),
+            (None, Some(user)) => Some(user),
+            (Some(identity), _) => match SessionUser::fetch(identity, &app).await {
+                Ok(user) => user,
+                Err(err) => {
+                    tracing::error!(?err, "failed to fetch socket user");
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+
+        // compression is negotiated when the socket is upgraded
+        let compression = &app.config.http.websocket.compression;
+        let gzip = ws
+            .protocol()
+            .is_some_and(|protocol| protocol.as_bytes() == GZIP_SUBPROTOCOL.as_bytes());
+        let mut ws = WebSocket::from(ws);
+        if compression.enabled && gzip {
+            ws = ws.with_compression(compression.into());
+        }
 
-        tracing::debug!(?battle, "serving new client");
+        let (socket_id, disconnect) = self.register_socket(
+            &session_id,
+            user.as_ref().map(|user| user.clone().into_inner()),
+            resumed.as_ref(),
+        );
 
-        serve(WebSocketState {
-            ws: ws.into(),
+        let viewers = self.state.sockets.lock().expect("sockets poisoned").len();
+        let match_ids = self
+            .state
+            .milestones
+            .lock()
+            .expect("milestones poisoned")
+            .match_ids()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        for match_id in match_ids {
+            self.reach_milestone(&match_id, MilestoneKind::Viewers, viewers as i64);
+        }
+
+        let ping = &app.config.http.websocket.ping;
+        let ping = ping.enabled.then(|| {
+            let period = ping.interval.to_std().unwrap_or(Duration::from_secs(15));
+            let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ping
+        });
+
+        let user = serve(WebSocketState {
+            ws,
             handle: self.get_handle(),
+            app,
+            socket_id: socket_id.clone(),
+            session_id: session_id.clone(),
+            resumed: resumed.is_some(),
+            disconnect,
             user,
-            battle,
+            ping,
+            topics: HashSet::from(Topic::ALL),
+            battles,
         })
         .await;
+
+        if let Some(info) = self.unregister_socket(&socket_id) {
+            self.suspend_session(session_id, user, info);
+        }
     }
 
     fn get_handle(&self) -> Handle {
@@ -145,40 +821,216 @@ enum RoomEvent {
     NewMessage {
         message: ChatMessage,
     },
+    EditMessage {
+        message: ChatMessage,
+    },
+    DeleteMessage {
+        id: i32,
+    },
+    MessageReaction {
+        message: MessageReaction,
+    },
     UpdateBattle {
         battle: BattleData,
     },
+    ReplaceBattle {
+        battle: BattleData,
+    },
     WagerUpdate {
-        wager: BattleWager,
+        message: WagerUpdate,
+        private_to: Option<UserId>,
+    },
+    LineWagerUpdate {
+        message: LineWagerUpdate,
+        private_to: Option<UserId>,
+    },
+    SettlementProgress {
+        message: SettlementProgress,
+    },
+    BattleSettled {
+        message: BattleSettled,
+    },
+    LineSettled {
+        message: LineSettled,
+    },
+    WagerHeatmap {
+        message: WagerHeatmap,
+    },
+    SquadUpdate {
+        message: SquadUpdate,
+    },
+    Highlight {
+        message: Highlight,
+    },
+    LoadoutChanged {
+        message: LoadoutChanged,
+    },
+    Commentary {
+        message: Commentary,
+    },
+    Milestone {
+        message: Milestone,
     },
     MobiumsChange {
-        user_id: i32,
+        user_id: UserId,
         message: MobiumsChange,
     },
+    MobiumsChanges {
+        changes: Arc<HashMap<UserId, MobiumsChange>>,
+    },
+    AutoBetPlaced {
+        user_id: UserId,
+        message: AutoBetPlaced,
+    },
+    Announcement {
+        announcement: Announcement,
+    },
+    PromoActive {
+        event: PromoEvent,
+    },
+    Reconnect {
+        message: Reconnect,
+    },
+}
+
+impl RoomEvent {
+    /// The name of the event, for logs and metrics.
+    fn kind(&self) -> &'static str {
+        match self {
+            RoomEvent::NewMessage { .. } => "new-message",
+            RoomEvent::EditMessage { .. } => "edit-message",
+            RoomEvent::DeleteMessage { .. } => "delete-message",
+            RoomEvent::MessageReaction { .. } => "message-reaction",
+            RoomEvent::UpdateBattle { .. } => "update-battle",
+            RoomEvent::ReplaceBattle { .. } => "replace-battle",
+            RoomEvent::WagerUpdate { .. } => "wager-update",
+            RoomEvent::LineWagerUpdate { .. } => "line-wager-update",
+            RoomEvent::SettlementProgress { .. } => "settlement-progress",
+            RoomEvent::BattleSettled { .. } => "battle-settled",
+            RoomEvent::LineSettled { .. } => "line-settled",
+            RoomEvent::WagerHeatmap { .. } => "wager-heatmap",
+            RoomEvent::SquadUpdate { .. } => "squad-update",
+            RoomEvent::Highlight { .. } => "highlight",
+            RoomEvent::LoadoutChanged { .. } => "loadout-changed",
+            RoomEvent::Commentary { .. } => "commentary",
+            RoomEvent::Milestone { .. } => "milestone",
+            RoomEvent::MobiumsChange { .. } | RoomEvent::MobiumsChanges { .. } => "mobiums-change",
+            RoomEvent::AutoBetPlaced { .. } => "auto-bet-placed",
+            RoomEvent::Announcement { .. } => "announcement",
+            RoomEvent::PromoActive { .. } => "promo-active",
+            RoomEvent::Reconnect { .. } => "reconnect",
+        }
+    }
+
+    /// Describes the event for the [`EventLog`].
+    ///
+    /// Events that are the same for everyone are logged as the message a
+    /// socket subscribed to everything would be sent.
+    fn to_logged(&self, id: i64, receivers: u64, sent_at: DateTime<Utc>) -> admin::RoomEvent {
+        let mut recipients = Vec::new();
+        let mut private_to = None;
+
+        let message: Option<Message> = match self {
+            RoomEvent::NewMessage { message } => Some(NewMessage(message.clone()).into()),
+            RoomEvent::EditMessage { message } => Some(MessageEdited(message.clone()).into()),
+            RoomEvent::DeleteMessage { id } => Some(MessageDeleted { id: *id }.into()),
+            RoomEvent::MessageReaction { message } => Some(message.clone().into()),
+            RoomEvent::UpdateBattle { battle } => Some(BattleUpdate(battle.into()).into()),
+            RoomEvent::ReplaceBattle { battle } => Some(NewBattle(battle.into()).into()),
+            RoomEvent::WagerUpdate {
+                message,
+                private_to: user_id,
+            } => {
+                private_to = *user_id;
+                Some(message.clone().into())
+            }
+            RoomEvent::LineWagerUpdate {
+                message,
+                private_to: user_id,
+            } => {
+                private_to = *user_id;
+                Some(message.clone().into())
+            }
+            RoomEvent::SettlementProgress { message } => Some(message.clone().into()),
+            RoomEvent::BattleSettled { message } => Some(message.clone().into()),
+            RoomEvent::LineSettled { message } => Some(message.clone().into()),
+            RoomEvent::WagerHeatmap { message } => Some(message.clone().into()),
+            RoomEvent::SquadUpdate { message } => Some(message.clone().into()),
+            RoomEvent::Highlight { message } => Some(message.clone().into()),
+            RoomEvent::LoadoutChanged { message } => Some(message.clone().into()),
+            RoomEvent::Commentary { message } => Some(message.clone().into()),
+            RoomEvent::Milestone { message } => Some(message.clone().into()),
+            RoomEvent::MobiumsChange { user_id, message } => {
+                recipients.push(*user_id);
+                Some(message.clone().into())
+            }
+            // every user gets a different change
+            RoomEvent::MobiumsChanges { changes } => {
+                recipients.extend(changes.keys().copied());
+                recipients.sort();
+                None
+            }
+            RoomEvent::AutoBetPlaced { user_id, message } => {
+                recipients.push(*user_id);
+                Some(message.clone().into())
+            }
+            RoomEvent::Announcement { announcement } => Some(announcement.clone().into()),
+            RoomEvent::PromoActive { event } => Some(event.clone().into()),
+            RoomEvent::Reconnect { message } => Some(message.clone().into()),
+        };
+
+        admin::RoomEvent {
+            id,
+            kind: self.kind().to_owned(),
+            message,
+            recipients,
+            private_to,
+            receivers,
+            sent_at,
+        }
+    }
 }
 
-#[allow(dead_code)]
 struct WebSocketState {
     // Connection details
     ws: WebSocket,
     handle: Handle,
+    app: AppState,
+    socket_id: String,
+    session_id: String,
+    resumed: bool,
+    disconnect: oneshot::Receiver<CloseReason>,
+    ping: Option<Interval>,
 
     // Authentication
     user: Option<SessionUser>,
 
     // Room state things
-    battle: Option<BattleData>,
+    topics: HashSet<Topic>,
+    battles: HashMap<String, BattleData>,
 }
 
 /// Serves a websocket.
-async fn serve(mut state: WebSocketState) {
+///
+/// Returns the user the socket ended up authenticated as.
+async fn serve(mut state: WebSocketState) -> Option<SessionUser> {
+    let hello = Hello {
+        session_id: state.session_id.clone(),
+        resumed: state.resumed,
+    };
+    let _ = state.ws.send(&hello.into()).await;
+
     // Give client the rundown on what's happening
-    if let Some(battle) = state.battle.as_ref() {
-        let _ = state.ws.send(&NewBattle(battle.into()).into()).await;
-    }
+    let _ = send_battles(&mut state).await;
 
     while !state.ws.is_closed() {
-        let WebSocketState { ws, handle, .. } = &mut state;
+        let WebSocketState {
+            ws,
+            handle,
+            disconnect,
+            ping,
+            ..
+        } = &mut state;
 
         tokio::select! {
             ev = ws.recv() => {
@@ -189,6 +1041,16 @@ async fn serve(mut state: WebSocketState) {
                             tracing::error!("ws error: {}", err);
                         }
                     }
+                    // the client sent something we couldn't read, tell them
+                    Some(Err(Error::Serde(err))) => {
+                        let error = OpError {
+                            op: None,
+                            message: format!("Invalid message: {}", err),
+                        };
+                        if let Err(err) = ws.send(&error.into()).await {
+                            tracing::error!("ws error: {}", err);
+                        }
+                    }
                     // a fatal transfer error occured
                     Some(Err(err)) => {
                         tracing::error!("error receiving message: {}", err);
@@ -202,65 +1064,447 @@ async fn serve(mut state: WebSocketState) {
             ev = handle.rx.recv() => {
                 tracing::trace!(?ev, "got server event");
                 match ev {
-                    Ok(event) => {
-                        if let Err(err) = handle_server_event(&mut state, event).await {
-                            tracing::error!("ws error: {}", err);
+                    Ok(event) => deliver_server_event(&mut state, event).await,
+                    // the client missed some events, catch them back up
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "ws lagged");
+                        state
+                            .app
+                            .metrics
+                            .add("room_events_lagged_total", &[], skipped);
+                        state
+                            .app
+                            .room
+                            .update_socket(&state.socket_id, |socket| socket.lag_count += 1);
+
+                        if let Err(err) = handle_resync(&mut state).await {
+                            tracing::error!(?err, "failed to resync lagged client");
                         }
                     }
-                    // Lagged errors are fine
-                    Err(RecvError::Lagged(err)) => {
-                        tracing::warn!("ws lagged: {}", err);
-                    }
                     Err(RecvError::Closed) => break,
                 }
             }
+            _ = next_ping(ping) => {
+                let ping = Ping {
+                    ts: Utc::now().timestamp_millis(),
+                };
+                if let Err(err) = ws.send(&ping.into()).await {
+                    tracing::error!("ws error: {}", err);
+                }
+            }
+            // an administrator, or a drain, wants this socket gone
+            reason = disconnect => {
+                tracing::info!(socket_id = state.socket_id, ?reason, "disconnecting socket");
+                let (code, message) = match reason {
+                    Ok(CloseReason::Drained) => (DRAINED_CLOSE_CODE, "The server is restarting"),
+                    _ => (DISCONNECTED_CLOSE_CODE, "Disconnected by an administrator"),
+                };
+                let _ = state.ws.send_close(code, message).await;
+                break;
+            }
         }
     }
 
     // the websocket closes when it falls out of scope
+    state.user
+}
+
+/// Waits until the next ping is due, if pings are on.
+async fn next_ping(ping: &mut Option<Interval>) {
+    match ping {
+        Some(ping) => {
+            ping.tick().await;
+        }
+        None => std::future::pending().await,
+    }
 }
 
 /// Handles a message from the client.
-#[instrument(skip(_state))]
-async fn handle_message(_state: &mut WebSocketState, message: Message) -> Result<(), Error> {
-    match message {
-        // lol
-        _ => (),
+#[instrument(skip(state))]
+async fn handle_message(state: &mut WebSocketState, message: Message) -> Result<(), Error> {
+    let op = message.op();
+
+    let result = match message {
+        // acknowledged by the protocol
+        Message::Heartbeat(heartbeat) => {
+            state.app.room.update_socket(&state.socket_id, |socket| {
+                // stale heartbeats are ignored
+                socket.last_heartbeat = socket.last_heartbeat.max(Some(heartbeat.seq));
+            });
+            Ok(())
+        }
+        Message::Pong(pong) => {
+            handle_pong(state, pong);
+            Ok(())
+        }
+        Message::Subscribe(subscribe) => {
+            state.topics = subscribe.topics.into_iter().collect();
+            Ok(())
+        }
+        Message::Authenticate(authenticate) => handle_authenticate(state, authenticate).await,
+        Message::PlaceWager(wager) => handle_place_wager(state, wager).await,
+        Message::SendChat(chat) => handle_send_chat(state, chat).await,
+        Message::RequestResync(_) => handle_resync(state).await,
+        _ => Err(ErrorKind::InvalidData(format!("Clients cannot send {}", op)).into()),
+    };
+
+    match result {
+        Ok(()) => Ok(()),
+        // the socket itself is broken, the next recv will close it
+        Err(err) if matches!(err.kind(), ErrorKind::WebSocket(_)) => {
+            tracing::error!("ws error: {}", err);
+            Ok(())
+        }
+        Err(err) => {
+            if err.is_internal() {
+                tracing::error!(?err, op, "an unexpected error occurred inside a ws op");
+            }
+
+            let error = OpError {
+                op: Some(op.to_owned()),
+                message: err.to_api_error().message,
+            };
+            state.ws.send(&error.into()).await
+        }
+    }
+}
+
+fn handle_pong(state: &mut WebSocketState, pong: Pong) {
+    let rtt = Utc::now().timestamp_millis() - pong.ts;
+
+    // pongs from the future weren't sent by us
+    if rtt < 0 {
+        return;
+    }
+
+    state
+        .app
+        .metrics
+        .observe("socket_rtt_seconds", &[], rtt as f64 / 1000.0);
+    state
+        .app
+        .room
+        .update_socket(&state.socket_id, |socket| socket.rtt = Some(rtt));
+}
+
+async fn handle_authenticate(
+    state: &mut WebSocketState,
+    authenticate: Authenticate,
+) -> Result<(), AppError> {
+    if state.user.is_some() {
+        return Err(ErrorKind::InvalidData("Already authenticated".into()).into());
+    }
+
+    let Some(user_id) = state.app.room.redeem_ticket(&authenticate.ticket) else {
+        return Err(ErrorKind::InvalidData("Invalid or expired ticket".into()).into());
+    };
+
+    let user = SessionUser::fetch(user_id, &state.app)
+        .await?
+        .ok_or(ErrorKind::InvalidSession)?;
+
+    state
+        .ws
+        .send(&Authenticated(user.clone().into_inner()).into())
+        .await?;
+    state.app.room.update_socket(&state.socket_id, |socket| {
+        socket.user = Some(user.clone().into_inner());
+    });
+    state.user = Some(user);
+
+    Ok(())
+}
+
+async fn handle_place_wager(state: &mut WebSocketState, wager: PlaceWager) -> Result<(), AppError> {
+    let Some(user) = state.user.as_ref() else {
+        return Err(ErrorKind::UserUnauthenticated.into());
+    };
+
+    let match_id = Uuid::parse_str(&wager.match_id)
+        .map_err(|_| ErrorKind::InvalidData(format!("Invalid match id {}", wager.match_id)))?;
+
+    // the user's mobiums may have changed since they connected
+    let user = SessionUser::fetch(user.identity(), &state.app)
+        .await?
+        .ok_or(ErrorKind::InvalidSession)?;
+
+    // the socket skips the extractor wagers over HTTP go through
+    check_penalty(
+        user.identity(),
+        PenaltyKind::WagerBan,
+        &mut *state.app.db.acquire().await?,
+    )
+    .await?;
+
+    let audit = Audit::new();
+    audit.set_actor(ActorKind::User, user.identity());
+
+    let amount = wager.amount().ok_or_else(|| {
+        ErrorKind::InvalidData("Exactly one of `mobiums` and `adjust` must be set".into())
+    })?;
+
+    place_wager(
+        &state.app,
+        WagerRequest {
+            user: user.clone(),
+            audit: audit.clone(),
+            match_id,
+            victor: wager.victor,
+            amount,
+            client_token: None,
+            auto_bet: false,
+        },
+        wager.captcha.as_deref(),
+    )
+    .await?;
+    state.user = Some(user);
+
+    if let Err(err) = audit.write("WS", "place-wager", 200, &state.app.db).await {
+        tracing::error!(?err, "failed to write audit log");
     }
 
     Ok(())
 }
 
-/// Handles an internal server event.
+async fn handle_send_chat(state: &mut WebSocketState, chat: SendChat) -> Result<(), AppError> {
+    let Some(user) = state.user.as_ref() else {
+        return Err(ErrorKind::UserUnauthenticated.into());
+    };
+
+    send_user_message(&state.app, user, chat.content).await?;
+
+    Ok(())
+}
+
+async fn handle_resync(state: &mut WebSocketState) -> Result<(), AppError> {
+    let battles = state.app.room.battles().await;
+    state.battles = battles
+        .into_iter()
+        .map(|battle| (battle.uuid.clone(), battle))
+        .collect();
+
+    send_battles(state).await?;
+
+    Ok(())
+}
+
+/// Sends every match the socket knows of to the client, oldest first.
+async fn send_battles(state: &mut WebSocketState) -> Result<(), Error> {
+    let mut battles = state.battles.values().collect::<Vec<_>>();
+    battles.sort_by(|a, b| a.inserted_at.cmp(&b.inserted_at).then(a.uuid.cmp(&b.uuid)));
+
+    for battle in battles {
+        state.ws.send(&NewBattle(battle.into()).into()).await?;
+    }
+
+    Ok(())
+}
+
+/// Drops every match that finished more than
+/// [`FINISHED_BATTLE_RETENTION`] ago.
+fn retain_unexpired(battles: &mut HashMap<String, BattleData>) {
+    let now = Utc::now();
+    battles.retain(|_, battle| !battle.is_expired(now));
+}
+
+/// Delivers an internal server event to the client.
+///
+/// Transient send failures are retried, up to [`MAX_DELIVERY_ATTEMPTS`].
+/// Events that could not be delivered are logged and counted.
+async fn deliver_server_event(state: &mut WebSocketState, ev: RoomEvent) {
+    let kind = ev.kind();
+
+    let Some(message) = handle_server_event(state, ev) else {
+        return;
+    };
+
+    let mut attempts = 0;
+    let reason = loop {
+        attempts += 1;
+
+        match state.ws.send(&message).await {
+            Ok(()) => return,
+            // this will never succeed
+            Err(Error::Serde(err)) => {
+                tracing::error!(%err, kind, "failed to serialize room event");
+                break "serialize";
+            }
+            Err(Error::Io(err)) => {
+                tracing::error!(%err, kind, "failed to compress room event");
+                break "compress";
+            }
+            Err(Error::Ws(err)) if attempts < MAX_DELIVERY_ATTEMPTS && !state.ws.is_closed() => {
+                tracing::warn!(%err, kind, attempts, "retrying room event");
+                state
+                    .app
+                    .metrics
+                    .increment("room_event_retries_total", &[("event", kind)]);
+            }
+            Err(Error::Ws(err)) => {
+                tracing::error!(%err, kind, attempts, "failed to send room event");
+                break "send";
+            }
+        }
+    };
+
+    state.app.metrics.increment(
+        "room_event_failures_total",
+        &[("event", kind), ("reason", reason)],
+    );
+}
+
+/// Handles an internal server event, returning the message that should be
+/// sent to the client, if any.
 #[instrument(skip(state))]
-async fn handle_server_event(state: &mut WebSocketState, ev: RoomEvent) -> Result<(), Error> {
+fn handle_server_event(state: &mut WebSocketState, ev: RoomEvent) -> Option<Message> {
     match ev {
-        RoomEvent::NewMessage { message } => {
-            state.ws.send(&NewMessage(message).into()).await?;
+        RoomEvent::NewMessage { message } if state.topics.contains(&Topic::Chat) => {
+            Some(NewMessage(message).into())
+        }
+        RoomEvent::EditMessage { message } if state.topics.contains(&Topic::Chat) => {
+            Some(MessageEdited(message).into())
+        }
+        RoomEvent::DeleteMessage { id } if state.topics.contains(&Topic::Chat) => {
+            Some(MessageDeleted { id }.into())
+        }
+        RoomEvent::MessageReaction { message } if state.topics.contains(&Topic::Chat) => {
+            Some(message.into())
         }
         RoomEvent::UpdateBattle { battle } => {
-            let old_battle = std::mem::replace(&mut state.battle, Some(battle.clone()));
+            let old_battle = state.battles.insert(battle.uuid.clone(), battle.clone());
+            retain_unexpired(&mut state.battles);
+
+            if !state.topics.contains(&Topic::Battles) {
+                return None;
+            }
 
             // A new match was started, or updated
-            // Check if the match we have is the same
-            if old_battle.as_ref().map(|b| &b.uuid) != Some(&battle.uuid) {
+            // Check if we already know of the match
+            if old_battle.is_none() {
                 // This is a new battle!
-                state.ws.send(&NewBattle(battle.into()).into()).await?;
+                Some(NewBattle(battle.into()).into())
             } else {
                 // This is the same battle, it just got updated
-                state.ws.send(&BattleUpdate(battle.into()).into()).await?;
+                Some(BattleUpdate(battle.into()).into())
+            }
+        }
+        RoomEvent::ReplaceBattle { battle } => {
+            state.battles.insert(battle.uuid.clone(), battle.clone());
+            retain_unexpired(&mut state.battles);
+
+            if state.topics.contains(&Topic::Battles) {
+                Some(NewBattle(battle.into()).into())
+            } else {
+                None
+            }
+        }
+        RoomEvent::WagerUpdate {
+            message,
+            private_to,
+        } if state.topics.contains(&Topic::Wagers) => {
+            let visible = private_to.is_none_or(|user_id| {
+                state
+                    .user
+                    .as_ref()
+                    .is_some_and(|user| user.can_see_wagers_of(user_id))
+            });
+
+            if visible {
+                Some(message.into())
+            } else {
+                Some(
+                    WagerUpdate {
+                        wager: BattleWager {
+                            user: None,
+                            ..message.wager
+                        },
+                        ..message
+                    }
+                    .into(),
+                )
+            }
+        }
+        RoomEvent::LineWagerUpdate {
+            message,
+            private_to,
+        } if state.topics.contains(&Topic::Wagers) => {
+            let visible = private_to.is_none_or(|user_id| {
+                state
+                    .user
+                    .as_ref()
+                    .is_some_and(|user| user.can_see_wagers_of(user_id))
+            });
+
+            if visible {
+                Some(message.into())
+            } else {
+                Some(
+                    LineWagerUpdate {
+                        wager: LineWager {
+                            user: None,
+                            ..message.wager
+                        },
+                        ..message
+                    }
+                    .into(),
+                )
             }
         }
-        RoomEvent::WagerUpdate { wager } => {
-            state.ws.send(&WagerUpdate(wager).into()).await?;
+        RoomEvent::SettlementProgress { message } if state.topics.contains(&Topic::Wagers) => {
+            Some(message.into())
+        }
+        RoomEvent::BattleSettled { message } if state.topics.contains(&Topic::Wagers) => {
+            Some(message.into())
+        }
+        RoomEvent::LineSettled { message } if state.topics.contains(&Topic::Wagers) => {
+            Some(message.into())
+        }
+        RoomEvent::WagerHeatmap { message } if state.topics.contains(&Topic::Heatmap) => {
+            Some(message.into())
+        }
+        RoomEvent::SquadUpdate { message } if state.topics.contains(&Topic::Heatmap) => {
+            Some(message.into())
+        }
+        RoomEvent::Highlight { message } if state.topics.contains(&Topic::Battles) => {
+            Some(message.into())
+        }
+        RoomEvent::LoadoutChanged { message } => {
+            if let Some(battle) = state.battles.get_mut(&message.match_id) {
+                battle.apply_loadout(&message.match_id, &message.change);
+            }
+
+            if state.topics.contains(&Topic::Battles) {
+                Some(message.into())
+            } else {
+                None
+            }
+        }
+        RoomEvent::Commentary { message } if state.topics.contains(&Topic::Battles) => {
+            Some(message.into())
+        }
+        RoomEvent::Milestone { message } if state.topics.contains(&Topic::Battles) => {
+            Some(message.into())
         }
         RoomEvent::MobiumsChange { user_id, message }
             if Some(user_id) == state.user.as_ref().map(|u| u.identity()) =>
         {
-            state.ws.send(&message.into()).await?;
+            Some(message.into())
         }
-        _ => (),
+        RoomEvent::MobiumsChanges { changes } => state
+            .user
+            .as_ref()
+            .and_then(|user| changes.get(&user.identity()))
+            .map(|change| change.clone().into()),
+        RoomEvent::AutoBetPlaced { user_id, message }
+            if Some(user_id) == state.user.as_ref().map(|u| u.identity()) =>
+        {
+            Some(message.into())
+        }
+        // announcements are for everyone, regardless of topic
+        RoomEvent::Announcement { announcement } => Some(announcement.into()),
+        RoomEvent::PromoActive { event } => Some(event.into()),
+        // so is being told to leave
+        RoomEvent::Reconnect { message } => Some(message.into()),
+        _ => None,
     }
-
-    Ok(())
 }
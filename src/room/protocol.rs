@@ -1,5 +1,6 @@
 //! Thin protocol wrapper for [`WebSocket`].
 
+use std::io::{self, Read as _, Write as _};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -8,6 +9,8 @@ use axum::extract::ws::{self, CloseFrame};
 
 use derive_more::{Display, Error, From};
 
+use flate2::{read::GzDecoder, write::GzEncoder};
+
 use futures_core::ready;
 use futures_util::{Sink, SinkExt, Stream, StreamExt};
 
@@ -17,10 +20,68 @@ use pin_project::pin_project;
 
 use tokio::time::{Sleep, sleep};
 
+use crate::{config::CompressionConfig, error::ErrorKind};
+
 /// Gives clients some time to send heartbeats over unstable network
 /// conditions.
 pub const HEARTBEAT_GRACE_DURATION: Duration = Duration::from_secs(5);
 
+/// The subprotocol clients offer to receive compressed messages.
+pub const GZIP_SUBPROTOCOL: &str = "ring-channel.gzip";
+
+/// The largest a compressed message from a client can inflate to.
+pub const MAX_INFLATED_SIZE: u64 = 1024 * 1024;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Message compression settings.
+#[derive(Clone, Copy, Debug)]
+pub struct Compression {
+    threshold: usize,
+    level: flate2::Compression,
+}
+
+impl Compression {
+    /// Encodes a message, compressing it if it is large enough.
+    ///
+    /// Compressed messages are sent in binary frames, so clients can tell
+    /// them apart.
+    fn encode(compression: Option<&Compression>, message: &Message) -> Result<ws::Message, Error> {
+        let text = serde_json::to_string(message)?;
+
+        match compression {
+            Some(compression) if text.len() >= compression.threshold => {
+                let mut encoder = GzEncoder::new(Vec::new(), compression.level);
+                encoder.write_all(text.as_bytes())?;
+                Ok(ws::Message::Binary(encoder.finish()?.into()))
+            }
+            _ => Ok(ws::Message::Text(text.into())),
+        }
+    }
+
+    /// Decodes a binary message, inflating it if it is compressed.
+    fn decode(bytes: &[u8]) -> Result<Message, Error> {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            let mut text = Vec::new();
+            GzDecoder::new(bytes)
+                .take(MAX_INFLATED_SIZE)
+                .read_to_end(&mut text)?;
+            Ok(serde_json::from_slice::<Message>(&text)?)
+        } else {
+            Ok(serde_json::from_slice::<Message>(bytes)?)
+        }
+    }
+}
+
+impl From<&CompressionConfig> for Compression {
+    fn from(value: &CompressionConfig) -> Self {
+        Compression {
+            threshold: value.threshold,
+            level: flate2::Compression::new(value.level.min(9)),
+        }
+    }
+}
+
 /// A connection to a client.
 #[derive(Debug)]
 #[pin_project]
@@ -28,6 +89,7 @@ pub struct WebSocket {
     #[pin]
     inner: ws::WebSocket,
     close_timeout: Duration,
+    compression: Option<Compression>,
 
     // Heartbeats
     heartbeater: Heartbeater,
@@ -57,6 +119,14 @@ enum HeartbeatStage {
 }
 
 impl WebSocket {
+    /// Compresses large messages sent over the websocket.
+    ///
+    /// Only do this if the client negotiated [`GZIP_SUBPROTOCOL`].
+    pub fn with_compression(mut self, compression: Compression) -> WebSocket {
+        self.compression = Some(compression);
+        self
+    }
+
     /// Checks if the websocket is closed.
     pub fn is_closed(&self) -> bool {
         matches!(self.close_stage, CloseStage::Closed)
@@ -95,8 +165,8 @@ impl WebSocket {
             Message::Heartbeat(heartbeat) => {
                 if let Some(resp) = this.heartbeater.ack(heartbeat) {
                     let message: Message = resp.into();
-                    let text = serde_json::to_string(&message)?;
-                    this.inner.start_send(ws::Message::Text(text.into()))?;
+                    let frame = Compression::encode(this.compression.as_ref(), &message)?;
+                    this.inner.start_send(frame)?;
                     *this.heartbeat_stage = HeartbeatStage::Flushing;
                 }
             }
@@ -199,7 +269,7 @@ impl Stream for WebSocket {
                     return Poll::Ready(Some(Ok(message)));
                 }
                 Some(Ok(ws::Message::Binary(bytes))) => {
-                    let message = serde_json::from_slice::<Message>(&bytes)?;
+                    let message = Compression::decode(&bytes)?;
                     self.preprocess_message(&message)?;
                     return Poll::Ready(Some(Ok(message)));
                 }
@@ -236,12 +306,10 @@ impl Sink<&Message> for WebSocket {
     }
 
     fn start_send(self: Pin<&mut Self>, item: &Message) -> Result<(), Self::Error> {
-        let msg = serde_json::to_string(item)?;
-
         let this = self.project();
-        this.inner
-            .start_send(ws::Message::Text(msg.into()))
-            .map_err(Error::from)
+        let frame = Compression::encode(this.compression.as_ref(), item)?;
+
+        this.inner.start_send(frame).map_err(Error::from)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -264,6 +332,7 @@ impl From<ws::WebSocket> for WebSocket {
             heartbeater: Heartbeater::default(),
             heartbeat_stage: HeartbeatStage::None,
             close_timeout: Duration::from_secs(5),
+            compression: None,
             close_stage: CloseStage::Running,
             closed_client: false,
             closed_server: false,
@@ -325,4 +394,17 @@ pub enum Error {
     /// A serialization error occured.
     #[display("{_0}")]
     Serde(serde_json::Error),
+    /// A compression error occured.
+    #[display("{_0}")]
+    Io(io::Error),
+}
+
+impl From<Error> for crate::error::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Ws(err) => ErrorKind::WebSocket(err).into(),
+            Error::Serde(err) => ErrorKind::SerdeJson(err).into(),
+            Error::Io(err) => crate::error::Error::new(err),
+        }
+    }
 }
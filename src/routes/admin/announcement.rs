@@ -0,0 +1,105 @@
+//! Announcement management.
+
+use axum::extract::{Path, State};
+
+use chrono::Utc;
+
+use http::StatusCode;
+
+use ring_channel_model::{
+    announcement::Announcement, request::announcement::CreateAnnouncementRequest,
+};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    routes::announcement::AnnouncementSchema,
+    session::AdminUser,
+};
+
+/// Makes an announcement, showing it to everyone connected.
+pub async fn create(
+    admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateAnnouncementRequest>>,
+) -> Result<(StatusCode, AppJson<Announcement>), Error> {
+    let now = Utc::now();
+
+    if request
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(
+            ErrorKind::InvalidData("Announcements must expire in the future".into()).into(),
+        );
+    }
+
+    let (id,) = sqlx::query_as::<_, (i32,)>(
+        r#"
+        INSERT INTO announcement (message, severity, user_id, inserted_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
+    )
+    .bind(&request.message)
+    .bind(u8::from(request.severity))
+    .bind(admin.identity())
+    .bind(now)
+    .bind(request.expires_at)
+    .fetch_one(&state.db)
+    .await?;
+
+    audit.note(format!("made announcement {}", id));
+
+    let announcement = Announcement {
+        id,
+        message: request.message,
+        severity: request.severity,
+        created_at: now,
+        expires_at: request.expires_at,
+    };
+
+    state.room.send_announcement(announcement.clone());
+
+    Ok((StatusCode::CREATED, AppJson(announcement)))
+}
+
+/// Expires an announcement early.
+///
+/// The expired announcement is sent to everyone connected, so clients can
+/// take it down.
+pub async fn delete(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((announcement_id,)): Path<(i32,)>,
+) -> Result<StatusCode, Error> {
+    let now = Utc::now();
+
+    let announcement = sqlx::query_as::<_, AnnouncementSchema>(
+        r#"
+        UPDATE announcement
+        SET expires_at = $2
+        WHERE id = $1 AND (expires_at IS NULL OR expires_at > $2)
+        RETURNING id, message, severity, inserted_at, expires_at
+        "#,
+    )
+    .bind(announcement_id)
+    .bind(now)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| {
+        Error::not_found(format!(
+            "Announcement {} not found, or already expired",
+            announcement_id
+        ))
+    })?;
+
+    audit.note(format!("expired announcement {}", announcement_id));
+
+    state.room.send_announcement(announcement.into());
+
+    Ok(StatusCode::NO_CONTENT)
+}
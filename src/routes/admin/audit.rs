@@ -0,0 +1,115 @@
+//! Audit log queries.
+
+use axum::extract::State;
+
+use chrono::{DateTime, Utc};
+
+use garde::Validate;
+
+use ring_channel_model::admin::{ActorKind, AuditLogEntry};
+
+use serde::Deserialize;
+
+use sqlx::FromRow;
+
+use crate::{
+    app::{AppForm, AppGarde, AppJson, AppState},
+    error::Error,
+    session::AdminUser,
+};
+
+/// A query for [`list`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct ListAuditLogQuery {
+    #[garde(range(min = 1, max = 100))]
+    #[serde(default = "list_audit_log_count_default")]
+    pub count: i32,
+    /// Only show entries before this time.
+    #[garde(skip)]
+    pub before: Option<DateTime<Utc>>,
+    /// Only show entries made by this kind of actor.
+    #[garde(skip)]
+    pub actor_kind: Option<ActorKind>,
+    /// Only show entries made by the actor with this id.
+    #[garde(skip)]
+    pub actor_id: Option<i32>,
+    /// Only show entries with paths containing this, like a match id.
+    #[garde(length(max = 255))]
+    pub path: Option<String>,
+}
+
+fn list_audit_log_count_default() -> i32 {
+    50
+}
+
+/// Lists audit log entries, newest first.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<ListAuditLogQuery>>,
+) -> Result<AppJson<Vec<AuditLogEntry>>, Error> {
+    #[derive(FromRow)]
+    struct AuditLogQuery {
+        id: i32,
+        #[sqlx(try_from = "u8")]
+        actor_kind: ActorKind,
+        actor_id: Option<i32>,
+        actor_name: Option<String>,
+        method: String,
+        path: String,
+        status: u16,
+        summary: Option<String>,
+        inserted_at: DateTime<Utc>,
+    }
+
+    let entries = sqlx::query_as::<_, AuditLogQuery>(
+        r#"
+        SELECT
+            a.id, a.actor_kind, a.actor_id, a.method, a.path, a.status,
+            a.summary, a.inserted_at,
+            CASE a.actor_kind
+                WHEN $6 THEN s.server_name
+                ELSE u.username
+            END AS actor_name
+        FROM
+            audit_log a
+        LEFT OUTER JOIN
+            user u ON a.actor_id = u.id
+        LEFT OUTER JOIN
+            server s ON a.actor_id = s.id
+        WHERE
+            ($1 IS NULL OR a.inserted_at < $1)
+            AND ($2 IS NULL OR a.actor_kind = $2)
+            AND ($3 IS NULL OR a.actor_id = $3)
+            AND ($4 IS NULL OR instr(a.path, $4) > 0)
+        ORDER BY
+            a.inserted_at DESC
+        LIMIT $5
+        "#,
+    )
+    .bind(query.before)
+    .bind(query.actor_kind.map(u8::from))
+    .bind(query.actor_id)
+    .bind(query.path)
+    .bind(query.count)
+    .bind(u8::from(ActorKind::Server))
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|row| AuditLogEntry {
+        id: row.id,
+        actor_kind: row.actor_kind,
+        actor_id: row.actor_id,
+        // anonymous actors have no id, so this would be NULL anyways
+        actor_name: row.actor_name,
+        method: row.method,
+        path: row.path,
+        status: row.status,
+        summary: row.summary,
+        created_at: row.inserted_at,
+    })
+    .collect();
+
+    Ok(AppJson(entries))
+}
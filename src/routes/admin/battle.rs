@@ -0,0 +1,108 @@
+//! Match administration.
+
+use std::fmt::Debug;
+
+use axum::{
+    Extension,
+    extract::{Path, State},
+};
+
+use ring_channel_model::{
+    Battle, BattleId, PlayerShortId,
+    battle::{BattleStatus, CommentaryNote, Participant},
+    request::battle::{CreateCommentary, UpdatePlayerPlacementRequest},
+};
+
+use http::StatusCode;
+
+use uuid::Uuid;
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Model, Payload},
+    audit::Audit,
+    battle::finalize_battle,
+    error::{Error, ErrorKind},
+    player::mmr,
+    routes::battle::{commentary, player},
+    session::AdminUser,
+};
+
+/// Amends the placement of a player.
+///
+/// Unlike servers, admins are expected to use this to fix the results of
+/// provisional matches.
+pub async fn update_placement<T>(
+    _admin: AdminUser,
+    audit: Audit,
+    Path((uuid, short_id)): Path<(Uuid, PlayerShortId)>,
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdatePlayerPlacementRequest>>,
+) -> Result<AppJson<Participant>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    player::update_placement(uuid, short_id, &request, &model, &state, &audit)
+        .await
+        .map(AppJson)
+}
+
+/// Adds commentary to a match.
+pub async fn add_commentary(
+    _admin: AdminUser,
+    audit: Audit,
+    Path((uuid,)): Path<(Uuid,)>,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateCommentary>>,
+) -> Result<(StatusCode, AppJson<CommentaryNote>), Error> {
+    commentary::add_commentary(uuid, &request, &state, &audit)
+        .await
+        .map(|note| (StatusCode::CREATED, AppJson(note)))
+}
+
+/// Finalizes a provisional match without waiting for its dispute window to
+/// run out.
+pub async fn finalize<T>(
+    _admin: AdminUser,
+    audit: Audit,
+    Path((uuid,)): Path<(Uuid,)>,
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Battle>, Error>
+where
+    T: Debug + mmr::Model + 'static,
+    T::Data: Debug,
+{
+    let mut tx = state.db.begin().await?;
+
+    let battle = sqlx::query_as::<_, (BattleId, BattleStatus)>(
+        r#"
+        SELECT id, status
+        FROM battle
+        WHERE uuid = $1
+        "#,
+    )
+    .bind(uuid.hyphenated().to_string())
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((battle_id, status)) = battle else {
+        return Err(Error::not_found(format!("Match {} not found", uuid)));
+    };
+
+    if status != BattleStatus::Provisional {
+        return Err(
+            ErrorKind::InvalidData("Only provisional matches can be finalized".into()).into(),
+        );
+    }
+
+    let Some(battle) = finalize_battle(battle_id, &model, &state, &mut tx).await? else {
+        return Err(ErrorKind::AlreadyConcluded(uuid).into());
+    };
+
+    tx.commit().await?;
+
+    audit.note(format!("finalized match {}", uuid));
+
+    Ok(AppJson(battle))
+}
@@ -0,0 +1,208 @@
+//! Wager bot administration.
+
+use axum::extract::State;
+
+use chrono::{NaiveDate, TimeDelta, Utc};
+
+use garde::Validate;
+
+use reqwest::Url;
+
+use ring_channel_model::{
+    admin::{BotDailyStats, BotStats, WagerBot},
+    battle::BattleStatus,
+    display_name::to_display_name_lossy,
+    request::bot::UpdateWagerBotRequest,
+    user::UserFlags,
+};
+
+use serde::Deserialize;
+
+use sqlx::FromRow;
+
+use crate::{
+    app::{AppForm, AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    session::AdminUser,
+    user::bot::{BotProfileUpdate, WagerBotUser, get_wager_bot, update_wager_bot},
+};
+
+/// Shows the wager bot's runtime settings.
+pub async fn show(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<WagerBot>, Error> {
+    let mut conn = state.db.acquire().await?;
+
+    let bot = get_wager_bot(&state.config.server.bot, &mut conn).await?;
+
+    Ok(AppJson(wager_bot(&state, bot)))
+}
+
+/// Updates the wager bot's runtime settings.
+///
+/// Profile changes are kept with the bot's user, so they outlast restarts.
+/// Wagers the bot already placed are left alone until the bot next wagers on
+/// the match.
+pub async fn update(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateWagerBotRequest>>,
+) -> Result<AppJson<WagerBot>, Error> {
+    let display_name = match request.display_name {
+        Some(display_name) => {
+            let display_name =
+                to_display_name_lossy(&display_name, state.config.server.max_display_name_length);
+            if display_name.is_empty() {
+                return Err(
+                    ErrorKind::InvalidData("Display name has no usable characters".into()).into(),
+                );
+            }
+            Some(display_name)
+        }
+        None => None,
+    };
+
+    let avatar = match request.avatar {
+        Some(avatar) if avatar.is_empty() => Some(None),
+        Some(avatar) => {
+            Url::parse(&avatar)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .ok_or_else(|| ErrorKind::InvalidData(format!("Invalid avatar url {}", avatar)))?;
+            Some(Some(avatar))
+        }
+        None => None,
+    };
+
+    let update = BotProfileUpdate {
+        display_name,
+        avatar,
+        wager_amount: request.wager_amount,
+        strategy: request.strategy,
+    };
+
+    let mut conn = state.db.acquire().await?;
+
+    let bot = update_wager_bot(&state.config.server.bot, &update, &mut conn).await?;
+
+    if let Some(enabled) = request.enabled {
+        state.bot.set(enabled);
+        audit.note(format!("set wager bot enabled to {}", enabled));
+    }
+    if let Some(display_name) = update.display_name {
+        audit.note(format!("set wager bot display name to {}", display_name));
+    }
+    match update.avatar {
+        Some(Some(avatar)) => audit.note(format!("set wager bot avatar to {}", avatar)),
+        Some(None) => audit.note("removed wager bot avatar"),
+        None => (),
+    }
+    if let Some(wager_amount) = update.wager_amount {
+        audit.note(format!("set wager bot wager amount to {}", wager_amount));
+    }
+    if let Some(strategy) = update.strategy {
+        audit.note(format!("set wager bot strategy to {:?}", strategy));
+    }
+
+    Ok(AppJson(wager_bot(&state, bot)))
+}
+
+fn wager_bot(state: &AppState, bot: WagerBotUser) -> WagerBot {
+    WagerBot {
+        enabled: state.bot.enabled(),
+        display_name: bot.user.display_name,
+        avatar: bot.user.avatar,
+        wager_amount: bot.wager_amount,
+        strategy: bot.strategy,
+    }
+}
+
+/// A query for [`stats`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct BotStatsQuery {
+    /// How many days back to show.
+    #[garde(range(min = 1, max = 366))]
+    #[serde(default = "bot_stats_days_default")]
+    pub days: i64,
+}
+
+fn bot_stats_days_default() -> i64 {
+    30
+}
+
+/// Shows the wager bot's profit and loss.
+pub async fn stats(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<BotStatsQuery>>,
+) -> Result<AppJson<BotStats>, Error> {
+    #[derive(FromRow)]
+    struct DailyQuery {
+        day: NaiveDate,
+        wagers: i64,
+        wagered: i64,
+        profit: i64,
+    }
+
+    let config = &state.config.server.bot;
+
+    let bot = sqlx::query_as::<_, (i32, i64)>(
+        r#"
+        SELECT id, mobiums
+        FROM user
+        WHERE username = $1 AND flags & $2
+        "#,
+    )
+    .bind(&config.username)
+    .bind(i32::from(UserFlags::AUTOMATED_USER))
+    .fetch_optional(&state.db)
+    .await?;
+
+    let Some((bot_id, mobiums)) = bot else {
+        return Err(Error::not_found("The wager bot has never run"));
+    };
+
+    let since = Utc::now() - TimeDelta::days(query.days);
+
+    let days = sqlx::query_as::<_, DailyQuery>(
+        r#"
+        SELECT
+            date(b.concluded_at) AS day,
+            COUNT(w.id) AS wagers,
+            SUM(w.mobiums) AS wagered,
+            SUM(IFNULL(w.payout, 0)) AS profit
+        FROM wager w, battle b
+        WHERE
+            w.match_id = b.id
+            AND w.user_id = $1
+            AND w.mobiums > 0
+            AND b.status = $2
+            AND b.concluded_at >= $3
+        GROUP BY day
+        ORDER BY day DESC
+        "#,
+    )
+    .bind(bot_id)
+    .bind(BattleStatus::Concluded)
+    .bind(since)
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|row| BotDailyStats {
+        day: row.day,
+        wagers: row.wagers,
+        wagered: row.wagered,
+        profit: row.profit,
+    })
+    .collect();
+
+    Ok(AppJson(BotStats {
+        mobiums,
+        bankroll: config.bankroll,
+        days,
+    }))
+}
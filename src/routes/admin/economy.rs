@@ -0,0 +1,411 @@
+//! Economy overview.
+
+use std::borrow::Cow;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    response::{IntoResponse, Response},
+};
+
+use chrono::{DateTime, Utc};
+
+use futures_util::{StreamExt as _, stream};
+
+use http::header;
+
+use ring_channel_model::{
+    UserId,
+    admin::{EconomyReport, ImportedCorrections},
+    message::server::MobiumsChange,
+};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use sqlx::{FromRow, SqlitePool, sqlite::SqliteRow};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    app::{AppForm, AppJson, AppState},
+    audit::Audit,
+    economy::economy_report,
+    error::{Error, ErrorKind},
+    session::AdminUser,
+    user::ledger::{LedgerReason, record_with_note},
+};
+
+/// How many bytes of CSV are buffered before they are sent.
+const EXPORT_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The most corrections that can be imported at once.
+pub const MAX_CORRECTIONS: usize = 10_000;
+
+/// Shows a snapshot of the mobiums economy.
+pub async fn show(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<EconomyReport>, Error> {
+    let mut conn = state.db.acquire().await?;
+
+    let report = economy_report(&mut conn).await?;
+
+    Ok(AppJson(report))
+}
+
+/// A query for the economy exports.
+#[derive(Deserialize, Debug)]
+pub struct ExportQuery {
+    /// Only export records made at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Only export records made before this time.
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Exports every wager placed in a time range as CSV, oldest first.
+pub async fn export_wagers(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    AppForm(query): AppForm<ExportQuery>,
+) -> Result<Response, Error> {
+    #[derive(FromRow, Serialize)]
+    struct WagerRow {
+        id: i32,
+        match_id: String,
+        #[serde(serialize_with = "serialize_text")]
+        level_name: String,
+        user_id: UserId,
+        #[serde(serialize_with = "serialize_optional_text")]
+        username: Option<String>,
+        /// The team, or the side of the finish time line, that was bet on.
+        victor: String,
+        mobiums: i64,
+        odds: Option<f64>,
+        payout: Option<i64>,
+        sandbox: bool,
+        inserted_at: DateTime<Utc>,
+        updated_at: DateTime<Utc>,
+    }
+
+    stream_csv::<WagerRow>(
+        "wagers.csv",
+        r#"
+        SELECT
+            w.id, b.uuid AS match_id, b.level_name, w.user_id, u.username,
+            CASE w.market
+                WHEN 0 THEN (CASE w.victor WHEN 0 THEN 'red' ELSE 'blue' END)
+                ELSE (CASE w.victor WHEN 0 THEN 'under' ELSE 'over' END)
+            END AS victor,
+            w.mobiums, w.odds, w.payout, w.sandbox, w.inserted_at,
+            w.updated_at
+        FROM wager w
+        INNER JOIN battle b ON b.id = w.match_id
+        INNER JOIN user u ON u.id = w.user_id
+        WHERE
+            ($1 IS NULL OR w.inserted_at >= $1)
+            AND ($2 IS NULL OR w.inserted_at < $2)
+        ORDER BY w.inserted_at ASC, w.id ASC
+        "#,
+        query,
+        state.db.clone(),
+    )
+}
+
+/// Exports every ledger entry made in a time range as CSV, oldest first.
+pub async fn export_ledger(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    AppForm(query): AppForm<ExportQuery>,
+) -> Result<Response, Error> {
+    #[derive(FromRow, Serialize)]
+    struct LedgerRow {
+        id: i32,
+        user_id: UserId,
+        #[serde(serialize_with = "serialize_optional_text")]
+        username: Option<String>,
+        amount: i64,
+        reason: String,
+        #[serde(serialize_with = "serialize_optional_text")]
+        note: Option<String>,
+        inserted_at: DateTime<Utc>,
+    }
+
+    stream_csv::<LedgerRow>(
+        "ledger.csv",
+        r#"
+        SELECT
+            l.id, l.user_id, u.username, l.amount, l.reason, l.note, l.inserted_at
+        FROM ledger l
+        INNER JOIN user u ON u.id = l.user_id
+        WHERE
+            ($1 IS NULL OR l.inserted_at >= $1)
+            AND ($2 IS NULL OR l.inserted_at < $2)
+        ORDER BY l.inserted_at ASC, l.id ASC
+        "#,
+        query,
+        state.db.clone(),
+    )
+}
+
+/// A row of a correction import.
+#[derive(Deserialize, Debug)]
+struct CorrectionRow {
+    username: String,
+    amount: i64,
+    note: String,
+}
+
+/// Imports balance corrections from CSV.
+///
+/// The CSV needs `username`, `amount` and `note` columns. Every correction is
+/// recorded in the ledger and the audit log, and either all of them are
+/// applied or none are.
+pub async fn import_corrections(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<AppJson<ImportedCorrections>, Error> {
+    let mut reader = csv::Reader::from_reader(&body[..]);
+
+    let mut rows = Vec::new();
+    for (i, row) in reader.deserialize::<CorrectionRow>().enumerate() {
+        // the header is line 1
+        let line = i + 2;
+        let row = row.map_err(|err| ErrorKind::InvalidData(format!("Line {}: {}", line, err)))?;
+
+        if row.amount == 0 {
+            return Err(ErrorKind::InvalidData(format!("Line {}: amount is zero", line)).into());
+        }
+        if row.note.trim().is_empty() {
+            return Err(
+                ErrorKind::InvalidData(format!("Line {}: a note is required", line)).into(),
+            );
+        }
+
+        rows.push((line, row));
+    }
+
+    if rows.is_empty() {
+        return Err(ErrorKind::InvalidData("No corrections to import".into()).into());
+    }
+    if rows.len() > MAX_CORRECTIONS {
+        return Err(ErrorKind::InvalidData(format!(
+            "Only {} corrections can be imported at once",
+            MAX_CORRECTIONS
+        ))
+        .into());
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let mut changes = Vec::with_capacity(rows.len());
+    let mut net_amount = 0i64;
+
+    for (line, row) in rows.iter() {
+        let corrected = sqlx::query_as::<_, (UserId, i64)>(
+            r#"
+            UPDATE user
+            SET mobiums = mobiums + $2
+            WHERE username = $1
+            RETURNING id, mobiums
+            "#,
+        )
+        .bind(&row.username)
+        .bind(row.amount)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some((user_id, mobiums)) = corrected else {
+            return Err(ErrorKind::InvalidData(format!(
+                "Line {}: user {} not found",
+                line, row.username
+            ))
+            .into());
+        };
+
+        if mobiums < 0 {
+            return Err(ErrorKind::InvalidData(format!(
+                "Line {}: {} would be left with {} mobiums",
+                line, row.username, mobiums
+            ))
+            .into());
+        }
+
+        let note = row.note.trim();
+        record_with_note(
+            user_id,
+            row.amount,
+            LedgerReason::Correction,
+            Some(note),
+            &mut tx,
+        )
+        .await?;
+
+        net_amount = net_amount.saturating_add(row.amount);
+        changes.push((user_id, mobiums));
+    }
+
+    tx.commit().await?;
+
+    // only note what was actually applied
+    for (_, row) in rows.iter() {
+        audit.note(format!(
+            "corrected {} by {}: {}",
+            row.username,
+            row.amount,
+            row.note.trim()
+        ));
+    }
+
+    for (user_id, mobiums) in changes {
+        state.room.send_mobiums_change(
+            user_id,
+            MobiumsChange {
+                mobiums,
+                bailout: false,
+                streak: None,
+            },
+        );
+    }
+
+    Ok(AppJson(ImportedCorrections {
+        corrections: rows.len() as i64,
+        net_amount,
+    }))
+}
+
+/// Streams the results of a query as a CSV file.
+///
+/// `sql` is bound with the start and end of the export's time range.
+fn stream_csv<T>(
+    filename: &str,
+    sql: &'static str,
+    query: ExportQuery,
+    db: SqlitePool,
+) -> Result<Response, Error>
+where
+    T: for<'r> FromRow<'r, SqliteRow> + Serialize + Send + Unpin + 'static,
+{
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return Err(ErrorKind::InvalidData("from must come before to".into()).into());
+    }
+
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(4);
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as::<_, T>(sql)
+            .bind(query.from)
+            .bind(query.to)
+            .fetch(&db);
+
+        let mut chunk = Vec::with_capacity(EXPORT_CHUNK_SIZE);
+        let mut headers = true;
+
+        while let Some(row) = rows.next().await {
+            let record = row
+                .map_err(Error::from)
+                .and_then(|row| write_record(&row, headers).map_err(Error::new));
+
+            let record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    tracing::error!(%err, "failed to export csv");
+                    // cut the response short, so it isn't mistaken for a
+                    // complete export
+                    let _ = tx.send(Err(std::io::Error::other(err.to_string()))).await;
+                    return;
+                }
+            };
+
+            headers = false;
+            chunk.extend_from_slice(&record);
+            if chunk.len() >= EXPORT_CHUNK_SIZE {
+                let full = std::mem::replace(&mut chunk, Vec::with_capacity(EXPORT_CHUNK_SIZE));
+                if tx.send(Ok(full.into())).await.is_err() {
+                    // the client went away
+                    return;
+                }
+            }
+        }
+
+        if !chunk.is_empty() {
+            let _ = tx.send(Ok(chunk.into())).await;
+        }
+    });
+
+    let body = Body::from_stream(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv".to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Escapes text that spreadsheets would run as a formula.
+///
+/// Usernames and level names come from users and game servers, so they can't
+/// be trusted to open safely.
+fn escape_formula(text: &str) -> Cow<'_, str> {
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{}", text))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn serialize_text<T, S>(text: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: AsRef<str>,
+    S: Serializer,
+{
+    serializer.serialize_str(&escape_formula(text.as_ref()))
+}
+
+fn serialize_optional_text<T, S>(text: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: AsRef<str>,
+    S: Serializer,
+{
+    match text {
+        Some(text) => serialize_text(text, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Writes a single CSV record, with a header before it if `headers` is set.
+fn write_record<T: Serialize>(row: &T, headers: bool) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(headers)
+        .from_writer(Vec::new());
+    writer.serialize(row)?;
+    writer
+        .into_inner()
+        .map_err(|err| csv::Error::from(err.into_error()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_formula() {
+        assert_eq!(escape_formula("=HYPERLINK(\"x\")"), "'=HYPERLINK(\"x\")");
+        assert_eq!(escape_formula("+1"), "'+1");
+        assert_eq!(escape_formula("-1"), "'-1");
+        assert_eq!(escape_formula("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(escape_formula("Green Hills Zone"), "Green Hills Zone");
+        assert_eq!(escape_formula(""), "");
+    }
+}
@@ -0,0 +1,147 @@
+//! Promotional event management.
+
+use axum::extract::{Path, State};
+
+use chrono::Utc;
+
+use http::StatusCode;
+
+use ring_channel_model::{event::PromoEvent, request::event::CreatePromoEventRequest};
+
+use sqlx::FromRow;
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    event::PromoEventSchema,
+    session::AdminUser,
+};
+
+/// Lists the events that are running or haven't started yet, soonest first.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<PromoEvent>>, Error> {
+    let events = sqlx::query_as::<_, PromoEventSchema>(
+        r#"
+        SELECT id, name, payout_multiplier, first_wager_bonus, starts_at, ends_at
+        FROM promo_event
+        WHERE ends_at > $1
+        ORDER BY starts_at ASC
+        "#,
+    )
+    .bind(Utc::now())
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(PromoEvent::from)
+    .collect();
+
+    Ok(AppJson(events))
+}
+
+/// Schedules an event.
+///
+/// Events that have already started are announced right away; the rest are
+/// announced when they start.
+pub async fn create(
+    admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreatePromoEventRequest>>,
+) -> Result<(StatusCode, AppJson<PromoEvent>), Error> {
+    let now = Utc::now();
+
+    if request.ends_at <= request.starts_at {
+        return Err(ErrorKind::InvalidData("Events must end after they start".into()).into());
+    }
+    if request.ends_at <= now {
+        return Err(ErrorKind::InvalidData("Events must end in the future".into()).into());
+    }
+
+    let started = request.starts_at <= now;
+
+    let (id,) = sqlx::query_as::<_, (i32,)>(
+        r#"
+        INSERT INTO promo_event (
+            name, payout_multiplier, first_wager_bonus, user_id, starts_at, ends_at,
+            announced_at, inserted_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id
+        "#,
+    )
+    .bind(&request.name)
+    .bind(request.payout_multiplier)
+    .bind(request.first_wager_bonus)
+    .bind(admin.identity())
+    .bind(request.starts_at)
+    .bind(request.ends_at)
+    .bind(started.then_some(now))
+    .bind(now)
+    .fetch_one(&state.db)
+    .await?;
+
+    audit.note(format!(
+        "scheduled event {}: x{} payouts, {} first wager bonus",
+        id, request.payout_multiplier, request.first_wager_bonus
+    ));
+
+    let event = PromoEvent {
+        id,
+        name: request.name,
+        payout_multiplier: request.payout_multiplier,
+        first_wager_bonus: request.first_wager_bonus,
+        starts_at: request.starts_at,
+        ends_at: request.ends_at,
+    };
+
+    if started {
+        state.room.send_promo_active(event.clone());
+    }
+
+    Ok((StatusCode::CREATED, AppJson(event)))
+}
+
+/// Ends an event early, or cancels it if it hasn't started.
+///
+/// If clients were told the event started, the event is sent again with its
+/// new end, so they can take it down.
+pub async fn delete(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((event_id,)): Path<(i32,)>,
+) -> Result<StatusCode, Error> {
+    #[derive(FromRow)]
+    struct EndedEvent {
+        #[sqlx(flatten)]
+        event: PromoEventSchema,
+        announced: bool,
+    }
+
+    let ended = sqlx::query_as::<_, EndedEvent>(
+        r#"
+        UPDATE promo_event
+        SET ends_at = $2
+        WHERE id = $1 AND ends_at > $2
+        RETURNING
+            id, name, payout_multiplier, first_wager_bonus, starts_at, ends_at,
+            announced_at IS NOT NULL AS announced
+        "#,
+    )
+    .bind(event_id)
+    .bind(Utc::now())
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| Error::not_found(format!("Event {} not found, or already over", event_id)))?;
+
+    audit.note(format!("ended event {}", event_id));
+
+    if ended.announced {
+        state.room.send_promo_active(ended.event.into());
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,59 @@
+//! Feature flag management.
+
+use axum::extract::{Path, State};
+
+use ring_channel_model::{admin::FeatureFlag, request::flag::SetFeatureFlagRequest};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    flags::{MAX_FLAG_NAME_LENGTH, is_valid_flag_name, list_flags, set_flag},
+    session::AdminUser,
+};
+
+/// Lists every feature flag.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<FeatureFlag>>, Error> {
+    let mut conn = state.db.acquire().await?;
+
+    let flags = list_flags(&mut conn).await?;
+
+    Ok(AppJson(flags))
+}
+
+/// Creates or updates a feature flag.
+///
+/// The change takes effect on this instance immediately, and on others the
+/// next time they refresh their flags.
+pub async fn update(
+    _admin: AdminUser,
+    audit: Audit,
+    Path((name,)): Path<(String,)>,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<SetFeatureFlagRequest>>,
+) -> Result<AppJson<FeatureFlag>, Error> {
+    if !is_valid_flag_name(&name) {
+        return Err(ErrorKind::InvalidData(format!(
+            "Flag names must be 1 to {} lowercase letters, digits, _ or -",
+            MAX_FLAG_NAME_LENGTH
+        ))
+        .into());
+    }
+
+    let mut conn = state.db.acquire().await?;
+
+    let flag = set_flag(&name, request.enabled, request.rollout, &mut conn).await?;
+
+    state.flags.update(&flag);
+    audit.note(format!(
+        "set feature flag {} to {} at {}% rollout",
+        flag.name,
+        if flag.enabled { "on" } else { "off" },
+        flag.rollout
+    ));
+
+    Ok(AppJson(flag))
+}
@@ -0,0 +1,20 @@
+//! Administrative routes.
+//!
+//! All of these require an [`AdminUser`].
+//!
+//! [`AdminUser`]: crate::session::AdminUser
+
+pub mod announcement;
+pub mod audit;
+pub mod battle;
+pub mod bot;
+pub mod economy;
+pub mod event;
+pub mod flag;
+pub mod penalty;
+pub mod player;
+pub mod room;
+pub mod slow_query;
+pub mod socket;
+pub mod task;
+pub mod webhook;
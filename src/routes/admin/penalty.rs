@@ -0,0 +1,161 @@
+//! Penalty management.
+
+use axum::extract::{Path, State};
+
+use chrono::Utc;
+
+use http::StatusCode;
+
+use ring_channel_model::{UserId, request::user::CreatePenaltyRequest, user::Penalty};
+
+use sqlx::SqliteConnection;
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    session::AdminUser,
+    user::penalty::{PenaltySchema, escalated_expiry},
+};
+
+/// Lists every penalty a user was given, newest first.
+///
+/// This includes penalties that expired or were revoked.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Path((username,)): Path<(String,)>,
+) -> Result<AppJson<Vec<Penalty>>, Error> {
+    let mut conn = state.db.acquire().await?;
+
+    let user_id = get_user_id(&username, &mut conn).await?;
+
+    let penalties = sqlx::query_as::<_, PenaltySchema>(
+        r#"
+        SELECT id, kind, reason, expires_at, revoked_at, inserted_at
+        FROM penalty
+        WHERE user_id = $1
+        ORDER BY inserted_at DESC, id DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(AppJson(penalties.into_iter().map(Penalty::from).collect()))
+}
+
+/// Gives a user a penalty.
+///
+/// Penalties given without an expiry last longer the more penalties of the
+/// same kind the user already has.
+pub async fn create(
+    admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((username,)): Path<(String,)>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreatePenaltyRequest>>,
+) -> Result<(StatusCode, AppJson<Penalty>), Error> {
+    let now = Utc::now();
+
+    let mut tx = state.db.begin().await?;
+
+    let user_id = get_user_id(&username, &mut tx).await?;
+
+    let expires_at = match (request.permanent, request.expires_at) {
+        (true, Some(_)) => {
+            return Err(
+                ErrorKind::InvalidData("Permanent penalties can't have an expiry".into()).into(),
+            );
+        }
+        (true, None) => None,
+        (false, Some(expires_at)) if expires_at <= now => {
+            return Err(
+                ErrorKind::InvalidData("Penalties must expire in the future".into()).into(),
+            );
+        }
+        (false, Some(expires_at)) => Some(expires_at),
+        (false, None) => Some(escalated_expiry(user_id, request.kind, now, &mut tx).await?),
+    };
+
+    let penalty = sqlx::query_as::<_, PenaltySchema>(
+        r#"
+        INSERT INTO penalty (user_id, kind, reason, issued_by, expires_at, inserted_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, kind, reason, expires_at, revoked_at, inserted_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(u8::from(request.kind))
+    .bind(&request.reason)
+    .bind(admin.identity())
+    .bind(expires_at)
+    .bind(now)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    match expires_at {
+        Some(expires_at) => audit.note(format!(
+            "gave {} a {:?} until {}: {}",
+            username, request.kind, expires_at, request.reason
+        )),
+        None => audit.note(format!(
+            "gave {} a permanent {:?}: {}",
+            username, request.kind, request.reason
+        )),
+    }
+
+    Ok((StatusCode::CREATED, AppJson(penalty.into())))
+}
+
+/// Revokes a penalty.
+///
+/// Revoked penalties stay on the user's record, but don't count towards how
+/// long their next penalty lasts.
+pub async fn delete(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((penalty_id,)): Path<(i32,)>,
+) -> Result<StatusCode, Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE penalty
+        SET revoked_at = $2
+        WHERE id = $1 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(penalty_id)
+    .bind(Utc::now())
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::not_found(format!(
+            "Penalty {} not found, or already revoked",
+            penalty_id
+        )));
+    }
+
+    audit.note(format!("revoked penalty {}", penalty_id));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_user_id(username: &str, conn: &mut SqliteConnection) -> Result<UserId, Error> {
+    let user = sqlx::query_as::<_, (UserId,)>(
+        r#"
+        SELECT id
+        FROM user
+        WHERE username = $1 AND merged_into IS NULL
+        "#,
+    )
+    .bind(username)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    user.map(|(user_id,)| user_id)
+        .ok_or_else(|| Error::not_found(format!("User {} not found", username)))
+}
@@ -0,0 +1,82 @@
+//! Player administration.
+
+use axum::{
+    Extension,
+    extract::{Path, State},
+};
+
+use chrono::Utc;
+
+use ring_channel_model::{
+    Player, PlayerShortId, display_name::to_display_name_lossy,
+    request::player::SetPlayerDisplayNameRequest,
+};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Model, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    player::{get_player, mmr},
+    session::AdminUser,
+};
+
+/// Sets a player's display name by hand.
+///
+/// Use this to correct names the filter missed, or caught by mistake. The
+/// name is locked by default, so game servers can't change it back.
+pub async fn update_display_name<T>(
+    _admin: AdminUser,
+    audit: Audit,
+    Path((short_id,)): Path<(PlayerShortId,)>,
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<SetPlayerDisplayNameRequest>>,
+) -> Result<AppJson<Player>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    let display_name = to_display_name_lossy(
+        &request.display_name,
+        state.config.server.max_display_name_length,
+    );
+    if display_name.is_empty() {
+        return Err(ErrorKind::InvalidData("Display name has no usable characters".into()).into());
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let player = get_player(&short_id, &mut tx)
+        .await?
+        .ok_or_else(|| Error::not_found(format!("Player {} not found", short_id)))?;
+
+    // the old name isn't kept as an alias, since it is usually being
+    // corrected for a reason
+    sqlx::query(
+        r#"
+        UPDATE player
+        SET display_name = $2, display_name_locked = $3, updated_at = $4
+        WHERE id = $1
+        "#,
+    )
+    .bind(player.id)
+    .bind(&display_name)
+    .bind(request.locked)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    audit.note(format!(
+        "renamed player {} from {:?} to {:?}{}",
+        short_id,
+        player.display_name,
+        display_name,
+        if request.locked { " (locked)" } else { "" }
+    ));
+
+    let mut player = player.normalize(&model)?;
+    player.display_name = display_name;
+
+    Ok(AppJson(player))
+}
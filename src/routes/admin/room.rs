@@ -0,0 +1,33 @@
+//! Room event inspection.
+
+use axum::extract::State;
+
+use ring_channel_model::admin::RoomEvent;
+
+use crate::{
+    app::{AppJson, AppState},
+    error::Error,
+    room::event_log::list_persisted_events,
+    session::AdminUser,
+};
+
+/// Lists the most recent events broadcast to the room, newest first.
+///
+/// If events are persisted, this lists the events in the database, which
+/// includes events from before the server last restarted.
+pub async fn events(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<RoomEvent>>, Error> {
+    let event_log = &state.config.http.websocket.event_log;
+    if !event_log.enabled {
+        return Err(Error::not_found("Room event logging is disabled"));
+    }
+
+    if event_log.persist {
+        let mut conn = state.db.acquire().await?;
+        Ok(AppJson(list_persisted_events(&mut conn).await?))
+    } else {
+        Ok(AppJson(state.room.events()))
+    }
+}
@@ -0,0 +1,58 @@
+//! Slow query inspection.
+
+use axum::extract::State;
+
+use ring_channel_model::admin::SlowQuery;
+
+use sqlx::{Executor as _, Row as _, SqliteConnection};
+
+use crate::{
+    app::{AppJson, AppState},
+    error::Error,
+    session::AdminUser,
+};
+
+/// Lists the most recent slow queries, newest first.
+///
+/// Each query is explained again to point out where an index might help.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<SlowQuery>>, Error> {
+    if !state.config.slow_queries.enabled {
+        return Err(Error::not_found("Slow query logging is disabled"));
+    }
+
+    let mut conn = state.db.acquire().await?;
+
+    let mut queries = state.slow_queries.list();
+    for query in queries.iter_mut() {
+        query.advice = advise(&query.statement, &mut conn).await;
+    }
+
+    Ok(AppJson(queries))
+}
+
+/// Finds the steps of a statement's query plan that don't use an index.
+///
+/// Statements that can't be explained get no advice.
+async fn advise(statement: &str, conn: &mut SqliteConnection) -> Vec<String> {
+    // statements aren't bound, so placeholders are left as NULL
+    let sql = format!("EXPLAIN QUERY PLAN {}", statement);
+    let plan = conn.fetch_all(sql.as_str()).await;
+
+    match plan {
+        Ok(plan) => plan
+            .into_iter()
+            .filter_map(|row| row.try_get::<String, _>("detail").ok())
+            .filter(|detail| {
+                (detail.starts_with("SCAN ") && !detail.contains(" USING "))
+                    || detail.contains("TEMP B-TREE")
+            })
+            .collect(),
+        Err(err) => {
+            tracing::debug!(%err, "failed to explain slow query");
+            vec![]
+        }
+    }
+}
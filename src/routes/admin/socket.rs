@@ -0,0 +1,74 @@
+//! Connected socket diagnostics.
+
+use axum::extract::{Path, State};
+
+use http::StatusCode;
+
+use reqwest::Url;
+
+use ring_channel_model::{admin::Socket, request::socket::DrainSocketsRequest};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    session::AdminUser,
+};
+
+/// Lists every socket connected to the room.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<Socket>>, Error> {
+    Ok(AppJson(state.room.sockets()))
+}
+
+/// Force-disconnects a socket.
+pub async fn delete(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((socket_id,)): Path<(String,)>,
+) -> Result<StatusCode, Error> {
+    if !state.room.disconnect(&socket_id) {
+        return Err(Error::not_found(format!("Socket {} not found", socket_id)));
+    }
+
+    audit.note(format!("disconnected socket {}", socket_id));
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Drains every socket, ahead of a deploy.
+///
+/// Clients are told to reconnect, and new sockets are turned away until the
+/// server restarts. This returns right away; the sockets left when the drain
+/// times out are closed in the background.
+pub async fn drain(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<DrainSocketsRequest>>,
+) -> Result<StatusCode, Error> {
+    let drain = &state.config.http.websocket.drain;
+
+    let url = request.url.or_else(|| drain.reconnect_url.clone());
+    if let Some(url) = url.as_deref() {
+        Url::parse(url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "ws" | "wss" | "http" | "https"))
+            .ok_or_else(|| ErrorKind::InvalidData(format!("Invalid reconnect url {}", url)))?;
+    }
+
+    let timeout = drain.timeout.to_std().unwrap_or_default();
+
+    audit.note(match url.as_deref() {
+        Some(url) => format!("draining sockets to {}", url),
+        None => "draining sockets".to_owned(),
+    });
+
+    let room = state.room.clone();
+    tokio::spawn(async move { room.drain(url, timeout).await });
+
+    Ok(StatusCode::ACCEPTED)
+}
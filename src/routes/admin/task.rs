@@ -0,0 +1,19 @@
+//! Background task health.
+
+use axum::extract::State;
+
+use ring_channel_model::admin::TaskHealth;
+
+use crate::{
+    app::{AppJson, AppState},
+    error::Error,
+    session::AdminUser,
+};
+
+/// Lists the health of every background task, by name.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<TaskHealth>>, Error> {
+    Ok(AppJson(state.tasks.health()))
+}
@@ -0,0 +1,159 @@
+//! Webhook management.
+
+use axum::extract::{Path, State};
+
+use chrono::{DateTime, Utc};
+
+use http::StatusCode;
+
+use rand::distr::{Alphanumeric, SampleString};
+
+use reqwest::Url;
+
+use ring_channel_model::{request::webhook::CreateWebhookRequest, webhook::Webhook};
+
+use sqlx::FromRow;
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    session::AdminUser,
+};
+
+/// The length of generated webhook secrets.
+pub const WEBHOOK_SECRET_LENGTH: usize = 32;
+
+/// The shortest secret an operator can pick.
+pub const MIN_WEBHOOK_SECRET_LENGTH: usize = 16;
+
+#[derive(FromRow)]
+struct WebhookQuery {
+    id: i32,
+    url: String,
+    pending: i64,
+    failed: i64,
+    inserted_at: DateTime<Utc>,
+}
+
+impl From<WebhookQuery> for Webhook {
+    fn from(value: WebhookQuery) -> Self {
+        Webhook {
+            id: value.id,
+            url: value.url,
+            secret: None,
+            pending: value.pending,
+            failed: value.failed,
+            created_at: value.inserted_at,
+        }
+    }
+}
+
+/// Lists all registered webhooks.
+pub async fn list(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<Webhook>>, Error> {
+    let webhooks = sqlx::query_as::<_, WebhookQuery>(
+        r#"
+        SELECT
+            w.id, w.url, w.inserted_at,
+            IFNULL(SUM(d.next_attempt_at IS NOT NULL), 0) AS pending,
+            IFNULL(SUM(d.failed_at IS NOT NULL), 0) AS failed
+        FROM webhook w
+        LEFT JOIN webhook_delivery d ON d.webhook_id = w.id
+        GROUP BY w.id
+        ORDER BY w.id
+        "#,
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(Webhook::from)
+    .collect();
+
+    Ok(AppJson(webhooks))
+}
+
+/// Registers a new webhook.
+pub async fn create(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateWebhookRequest>>,
+) -> Result<(StatusCode, AppJson<Webhook>), Error> {
+    let url = Url::parse(&request.url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or_else(|| ErrorKind::InvalidData(format!("Invalid webhook url {}", request.url)))?;
+
+    let secret = match request.secret {
+        Some(secret) if secret.len() < MIN_WEBHOOK_SECRET_LENGTH => {
+            return Err(ErrorKind::InvalidData(format!(
+                "Webhook secrets must be at least {} characters",
+                MIN_WEBHOOK_SECRET_LENGTH
+            ))
+            .into());
+        }
+        Some(secret) => secret,
+        None => Alphanumeric.sample_string(&mut rand::rng(), WEBHOOK_SECRET_LENGTH),
+    };
+
+    let now = Utc::now();
+
+    let (id,) = sqlx::query_as::<_, (i32,)>(
+        r#"
+        INSERT INTO webhook (url, secret, inserted_at)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(url.as_str())
+    .bind(&secret)
+    .bind(now)
+    .fetch_one(&state.db)
+    .await?;
+
+    audit.note(format!("registered webhook {} for {}", id, url));
+
+    Ok((
+        StatusCode::CREATED,
+        AppJson(Webhook {
+            id,
+            url: url.into(),
+            secret: Some(secret),
+            pending: 0,
+            failed: 0,
+            created_at: now,
+        }),
+    ))
+}
+
+/// Removes a webhook, dropping any pending deliveries.
+pub async fn delete(
+    _admin: AdminUser,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((webhook_id,)): Path<(i32,)>,
+) -> Result<StatusCode, Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM webhook
+        WHERE id = $1
+        "#,
+    )
+    .bind(webhook_id)
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::not_found(format!(
+            "Webhook {} not found",
+            webhook_id
+        )));
+    }
+
+    audit.note(format!("removed webhook {}", webhook_id));
+
+    Ok(StatusCode::NO_CONTENT)
+}
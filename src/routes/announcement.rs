@@ -0,0 +1,57 @@
+//! Announcement routes.
+
+use axum::extract::State;
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::announcement::{Announcement, Severity};
+
+use sqlx::FromRow;
+
+use crate::{
+    app::{AppJson, AppState},
+    error::Error,
+};
+
+/// An announcement, as it is stored.
+#[derive(FromRow)]
+pub struct AnnouncementSchema {
+    pub id: i32,
+    pub message: String,
+    #[sqlx(try_from = "u8")]
+    pub severity: Severity,
+    pub inserted_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<AnnouncementSchema> for Announcement {
+    fn from(value: AnnouncementSchema) -> Self {
+        Announcement {
+            id: value.id,
+            message: value.message,
+            severity: value.severity,
+            created_at: value.inserted_at,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+/// Lists the announcements that haven't expired, newest first.
+pub async fn active(State(state): State<AppState>) -> Result<AppJson<Vec<Announcement>>, Error> {
+    let announcements = sqlx::query_as::<_, AnnouncementSchema>(
+        r#"
+        SELECT id, message, severity, inserted_at, expires_at
+        FROM announcement
+        WHERE expires_at IS NULL OR expires_at > $1
+        ORDER BY inserted_at DESC
+        "#,
+    )
+    .bind(Utc::now())
+    .fetch_all(state.read_db())
+    .await?
+    .into_iter()
+    .map(Announcement::from)
+    .collect();
+
+    Ok(AppJson(announcements))
+}
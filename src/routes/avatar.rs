@@ -0,0 +1,74 @@
+//! Avatar proxy routes.
+
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+};
+
+use http::{HeaderValue, header};
+
+use crate::{app::AppState, error::Error};
+
+/// Serves a user's avatar through the proxy.
+pub async fn show(
+    Path((username,)): Path<(String,)>,
+    State(state): State<AppState>,
+) -> Result<Response, Error> {
+    let Some(avatars) = state.avatars.as_ref() else {
+        return Err(Error::not_found("Avatar proxy is disabled"));
+    };
+
+    let source = sqlx::query_as::<_, (Option<String>, Option<String>, Option<String>)>(
+        r#"
+        SELECT u.avatar, m.username, m.avatar
+        FROM user u
+        LEFT JOIN user m ON m.id = u.merged_into
+        WHERE u.username = $1
+        "#,
+    )
+    .bind(&username)
+    .fetch_optional(&state.db)
+    .await?;
+
+    // linked accounts redirect to the account they were merged into
+    let source = match source {
+        Some((_, Some(merged_username), merged_avatar)) => {
+            return match state.avatar_url(&merged_username, merged_avatar) {
+                Some(url) => Ok(Redirect::permanent(&url).into_response()),
+                None => Err(Error::not_found(format!(
+                    "User {} has no avatar",
+                    merged_username
+                ))),
+            };
+        }
+        Some((avatar, None, _)) => avatar,
+        None => None,
+    };
+
+    let Some(source) = source else {
+        return Err(Error::not_found(format!("User {} has no avatar", username)));
+    };
+
+    let Some(avatar) = avatars.fetch(&username, &source).await? else {
+        return Err(Error::not_found(format!(
+            "Avatar of {} is unavailable",
+            username
+        )));
+    };
+
+    let content_type = HeaderValue::try_from(avatar.content_type)
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            // avatar urls change when the avatar does
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=86400"),
+            ),
+        ],
+        avatar.data,
+    )
+        .into_response())
+}
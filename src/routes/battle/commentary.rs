@@ -0,0 +1,158 @@
+//! Match commentary routes.
+
+use axum::extract::{Path, State};
+
+use chrono::{DateTime, Utc};
+
+use http::StatusCode;
+
+use ring_channel_model::{
+    BattleId,
+    battle::{BattleStatus, CommentaryNote, Visibility},
+    message::server::Commentary,
+    request::battle::CreateCommentary,
+};
+
+use sqlx::FromRow;
+
+use uuid::Uuid;
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    auth::api_key::ServerAuthentication,
+    error::{Error, ErrorKind},
+    routes::battle::get_battle_id,
+};
+
+/// Shows the commentary on a match, oldest first.
+pub async fn list(
+    Path((match_id,)): Path<(Uuid,)>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<CommentaryNote>>, Error> {
+    #[derive(FromRow)]
+    struct CommentaryQuery {
+        id: i32,
+        content: String,
+        inserted_at: DateTime<Utc>,
+    }
+
+    let mut conn = state.read_db().acquire().await?;
+
+    let battle_id = get_battle_id(match_id, &mut conn).await?;
+
+    let notes = sqlx::query_as::<_, CommentaryQuery>(
+        r#"
+        SELECT id, content, inserted_at
+        FROM commentary
+        WHERE match_id = $1
+        ORDER BY inserted_at, id
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(AppJson(
+        notes
+            .into_iter()
+            .map(|query| CommentaryNote {
+                id: query.id,
+                content: query.content,
+                created_at: query.inserted_at,
+            })
+            .collect(),
+    ))
+}
+
+/// Adds commentary to a match.
+pub async fn create(
+    _auth_guard: ServerAuthentication,
+    audit: Audit,
+    Path((uuid,)): Path<(Uuid,)>,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateCommentary>>,
+) -> Result<(StatusCode, AppJson<CommentaryNote>), Error> {
+    add_commentary(uuid, &request, &state, &audit)
+        .await
+        .map(|note| (StatusCode::CREATED, AppJson(note)))
+}
+
+/// Adds commentary to a match, and sends it to the room.
+///
+/// Commentary can be added while the match is ongoing or
+/// [provisional](BattleStatus::Provisional).
+pub async fn add_commentary(
+    uuid: Uuid,
+    request: &CreateCommentary,
+    state: &AppState,
+    audit: &Audit,
+) -> Result<CommentaryNote, Error> {
+    #[derive(FromRow)]
+    struct BattleQuery {
+        id: BattleId,
+        status: BattleStatus,
+        #[sqlx(try_from = "u8")]
+        visibility: Visibility,
+    }
+
+    let content = request.content.trim();
+    if content.is_empty() {
+        return Err(ErrorKind::InvalidData("Commentary cannot be blank".into()).into());
+    }
+
+    let battle = sqlx::query_as::<_, BattleQuery>(
+        r#"
+        SELECT id, status, visibility
+        FROM battle
+        WHERE uuid = $1
+        "#,
+    )
+    .bind(uuid.hyphenated().to_string())
+    .fetch_optional(&state.db)
+    .await?;
+
+    let Some(battle) = battle else {
+        return Err(Error::not_found(format!("Match {} not found", uuid)));
+    };
+
+    if !matches!(
+        battle.status,
+        BattleStatus::Ongoing | BattleStatus::Provisional
+    ) {
+        return Err(ErrorKind::AlreadyConcluded(uuid).into());
+    }
+
+    let now = Utc::now();
+
+    let (id,) = sqlx::query_as::<_, (i32,)>(
+        r#"
+        INSERT INTO commentary (match_id, content, inserted_at)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(battle.id)
+    .bind(content)
+    .bind(now)
+    .fetch_one(&state.db)
+    .await?;
+
+    audit.note(format!("commentary on {}: {}", uuid, content));
+
+    let note = CommentaryNote {
+        id,
+        content: content.to_owned(),
+        created_at: now,
+    };
+
+    // unlisted matches are kept out of the room
+    if battle.visibility.is_public() {
+        state.room.send_commentary(Commentary {
+            match_id: uuid.hyphenated().to_string(),
+            note: note.clone(),
+        });
+    }
+
+    Ok(note)
+}
@@ -0,0 +1,440 @@
+//! Finish time line wager routes.
+//!
+//! Besides which team wins, matches with a [`FinishTimeLine`] can be bet on
+//! by whether the winner finishes over or under the line. These wagers are
+//! always split like a pool, and don't count towards streaks.
+//!
+//! [`FinishTimeLine`]: ring_channel_model::battle::FinishTimeLine
+
+use axum::extract::{Path, State};
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::{
+    BattleId, Mobiums, User, UserId,
+    battle::{BattleStatus, LineSide, LineWager, LineWagerReceipt, Visibility, WagerMarket},
+    request::battle::UpdateLineWager,
+    user::{UserFlags, WagerBalance},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use uuid::Uuid;
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    routes::battle::{
+        get_battle_id,
+        wager::{check_bets_open, check_stake, check_wager_bounds},
+    },
+    session::{Session, SessionUser},
+    user::{penalty::WagerUser, wager_balance},
+    wager_queue::LineWagerRequest,
+};
+
+/// Shows the finish time line wagers on a match.
+pub async fn list(
+    Path((match_id,)): Path<(Uuid,)>,
+    viewer: Option<SessionUser>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<LineWager>>, Error> {
+    #[derive(FromRow)]
+    struct WagerQuery {
+        victor: LineSide,
+        mobiums: Mobiums,
+        updated_at: DateTime<Utc>,
+        // user structs
+        user_id: UserId,
+        show_wagers_publicly: bool,
+        username: String,
+        avatar: Option<String>,
+        display_name: String,
+        user_mobiums: Mobiums,
+        mobiums_gained: Mobiums,
+        mobiums_lost: Mobiums,
+        streak: i32,
+        #[sqlx(try_from = "i32")]
+        flags: UserFlags,
+    }
+
+    let mut conn = state.read_db().acquire().await?;
+
+    let battle_id = get_battle_id(match_id, &mut conn).await?;
+
+    let rows = sqlx::query_as::<_, WagerQuery>(
+        r#"
+        SELECT
+            w.victor, w.mobiums, w.updated_at, w.user_id,
+            u.show_wagers_publicly, u.username, u.display_name, u.avatar,
+            u.mobiums AS user_mobiums, u.mobiums_gained, u.mobiums_lost, u.streak, u.flags
+        FROM
+            wager w, user u
+        WHERE
+            w.user_id = u.id
+            AND w.mobiums > 0
+            AND w.match_id = $1
+            AND w.market = $2
+        ORDER BY w.id ASC
+        "#,
+    )
+    .bind(battle_id)
+    .bind(WagerMarket::FinishTime)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let wagers = rows
+        .into_iter()
+        .map(|row| {
+            let visible = row.show_wagers_publicly
+                || viewer
+                    .as_ref()
+                    .is_some_and(|viewer| viewer.can_see_wagers_of(row.user_id));
+
+            LineWager {
+                user: visible.then(|| User {
+                    avatar: state.avatar_url(&row.username, row.avatar),
+                    username: row.username,
+                    display_name: row.display_name,
+                    mobiums: row.user_mobiums,
+                    mobiums_gained: row.mobiums_gained,
+                    mobiums_lost: row.mobiums_lost,
+                    streak: row.streak,
+                    flags: row.flags,
+                }),
+                mobiums: row.mobiums,
+                side: row.victor,
+                updated_at: row.updated_at,
+            }
+        })
+        .collect();
+
+    Ok(AppJson(wagers))
+}
+
+/// Shows your finish time line wager on a match.
+pub async fn show_self(
+    Path((match_id,)): Path<(Uuid,)>,
+    session: SessionUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<LineWager>, Error> {
+    let mut conn = state.db.acquire().await?;
+
+    let battle_id = get_battle_id(match_id, &mut conn).await?;
+
+    let wager = sqlx::query_as::<_, (LineSide, Mobiums, DateTime<Utc>)>(
+        r#"
+        SELECT victor, mobiums, updated_at
+        FROM wager
+        WHERE
+            user_id = $1
+            AND match_id = $2
+            AND market = $3
+            AND mobiums > 0
+        "#,
+    )
+    .bind(session.identity())
+    .bind(battle_id)
+    .bind(WagerMarket::FinishTime)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((side, mobiums, updated_at)) = wager else {
+        return Err(Error::not_found("Wager not found"));
+    };
+
+    Ok(AppJson(LineWager {
+        user: Some(session.into_inner()),
+        mobiums,
+        side,
+        updated_at,
+    }))
+}
+
+/// Places a wager on a match's finish time line.
+///
+/// Responds with the user's balance after the wager, like wagers on the
+/// winner. The wager is written by the
+/// [`WagerQueue`](crate::wager_queue::WagerQueue), like wagers on the winner.
+pub async fn create(
+    Path((match_id,)): Path<(Uuid,)>,
+    WagerUser(user): WagerUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateLineWager>>,
+) -> Result<AppJson<LineWagerReceipt>, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    check_wager_bounds(request.mobiums, user.mobiums)?;
+
+    if let Some(verifier) = &state.captcha {
+        let mut conn = state.db.acquire().await?;
+        verifier
+            .check(user.identity(), request.captcha.as_deref(), &mut conn)
+            .await?;
+    }
+
+    let receipt = state
+        .wagers
+        .place_line(LineWagerRequest {
+            user,
+            audit,
+            match_id,
+            side: request.side,
+            mobiums: request.mobiums,
+            client_token: request.client_token,
+        })
+        .await?;
+
+    // shuffle csrf after the action is done
+    session.shuffle_csrf().await?;
+
+    Ok(AppJson(receipt))
+}
+
+/// A line wager written, but not yet announced to the room.
+///
+/// Announce it with [`PlacedLineWager::announce`] once the transaction it was
+/// written in commits.
+#[derive(Debug)]
+pub struct PlacedLineWager {
+    pub wager: LineWager,
+    /// The user's mobiums, now that the wager is placed.
+    pub balance_after_wager: WagerBalance,
+    match_id: String,
+    private_to: Option<UserId>,
+    visibility: Visibility,
+    /// Whether the wager was a retry of one already placed.
+    replayed: bool,
+}
+
+impl PlacedLineWager {
+    /// Notifies the room of the wager, returning it.
+    pub fn announce(self, state: &AppState) -> LineWagerReceipt {
+        // update clients, unless the match is unlisted
+        if !self.replayed && self.visibility.is_public() {
+            state
+                .room
+                .send_line_wager_update(&self.match_id, self.wager.clone(), self.private_to);
+        }
+
+        LineWagerReceipt {
+            wager: self.wager,
+            balance_after_wager: self.balance_after_wager,
+        }
+    }
+}
+
+/// Writes a line wager for a user.
+///
+/// Run this in a transaction.
+pub async fn write_line_wager(
+    state: &AppState,
+    request: &LineWagerRequest,
+    conn: &mut SqliteConnection,
+) -> Result<PlacedLineWager, Error> {
+    #[derive(FromRow)]
+    struct BattleQuery {
+        id: BattleId,
+        status: BattleStatus,
+        closed_at: DateTime<Utc>,
+        #[sqlx(try_from = "u8")]
+        visibility: Visibility,
+        finish_time_line: Option<i32>,
+    }
+
+    let user = &request.user;
+    let match_id = request.match_id;
+
+    let now = Utc::now();
+
+    let battle = sqlx::query_as::<_, BattleQuery>(
+        r#"
+        SELECT id, status, closed_at, visibility, finish_time_line
+        FROM battle
+        WHERE uuid = $1
+        "#,
+    )
+    .bind(match_id.hyphenated().to_string())
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(battle) = battle else {
+        return Err(Error::not_found(format!("Match {} not found", match_id)));
+    };
+
+    // retries get the original receipt, even if bets have closed since
+    if let Some(client_token) = &request.client_token {
+        let placed = sqlx::query_as::<_, (String,)>(
+            r#"
+            SELECT receipt
+            FROM wager_token
+            WHERE user_id = $1 AND match_id = $2 AND market = $4 AND client_token = $3
+            "#,
+        )
+        .bind(user.identity())
+        .bind(battle.id)
+        .bind(client_token)
+        .bind(WagerMarket::FinishTime)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        if let Some((receipt,)) = placed {
+            let receipt = serde_json::from_str::<LineWagerReceipt>(&receipt).map_err(Error::new)?;
+
+            request
+                .audit
+                .note(format!("replayed line wager on {}", match_id));
+
+            return Ok(PlacedLineWager {
+                wager: receipt.wager,
+                balance_after_wager: receipt.balance_after_wager,
+                match_id: match_id.hyphenated().to_string(),
+                private_to: None,
+                visibility: battle.visibility,
+                replayed: true,
+            });
+        }
+    }
+
+    if battle.finish_time_line.is_none() {
+        return Err(Error::not_found(format!(
+            "Match {} has no finish time line",
+            match_id
+        )));
+    }
+
+    check_bets_open(state, battle.id, battle.status, battle.closed_at)?;
+
+    let previous = sqlx::query_as::<_, (LineSide, i64)>(
+        r#"
+        SELECT victor, mobiums
+        FROM wager
+        WHERE user_id = $1 AND match_id = $2 AND market = $3
+        "#,
+    )
+    .bind(user.identity())
+    .bind(battle.id)
+    .bind(WagerMarket::FinishTime)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    // mobiums staked on the winner can't be wagered on the line too
+    check_stake(
+        state,
+        user.identity(),
+        (battle.id, WagerMarket::FinishTime),
+        request.mobiums,
+        previous.map_or(Mobiums::ZERO, |(_, mobiums)| Mobiums(mobiums)),
+        now,
+        &mut *conn,
+    )
+    .await?;
+
+    match previous {
+        Some((old_side, old_mobiums)) => request.audit.note(format!(
+            "line wager on {}: {:?} {} -> {:?} {}",
+            match_id, old_side, old_mobiums, request.side, request.mobiums
+        )),
+        None => request.audit.note(format!(
+            "line wager on {}: {:?} {}",
+            match_id, request.side, request.mobiums
+        )),
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO wager
+            (user_id, match_id, market, victor, mobiums, inserted_at, updated_at, sandbox)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $6, $7)
+        ON CONFLICT (user_id, match_id, market) DO UPDATE
+        SET
+            victor = $4,
+            mobiums = $5,
+            updated_at = $6
+        "#,
+    )
+    .bind(user.identity())
+    .bind(battle.id)
+    .bind(WagerMarket::FinishTime)
+    .bind(request.side)
+    .bind(request.mobiums)
+    .bind(now)
+    .bind(state.config.server.sandbox.enabled)
+    .execute(&mut *conn)
+    .await?;
+
+    let balance_after_wager = wager_balance(user.identity(), None, &mut *conn).await?;
+
+    let (show_wagers_publicly,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT show_wagers_publicly
+        FROM user
+        WHERE id = $1
+        "#,
+    )
+    .bind(user.identity())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let wager = LineWager {
+        user: Some(User {
+            username: user.username.clone(),
+            avatar: user.avatar.clone(),
+            display_name: user.display_name.clone(),
+            mobiums: balance_after_wager.mobiums,
+            mobiums_gained: user.mobiums_gained,
+            mobiums_lost: user.mobiums_lost,
+            streak: user.streak,
+            flags: user.flags,
+        }),
+        mobiums: request.mobiums,
+        side: request.side,
+        updated_at: now,
+    };
+
+    // remember the wager, in case the client retries it
+    if let Some(client_token) = &request.client_token {
+        let receipt = LineWagerReceipt {
+            wager: wager.clone(),
+            balance_after_wager,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO wager_token
+                (user_id, match_id, market, client_token, receipt, inserted_at)
+            VALUES
+                ($1, $2, $6, $3, $4, $5)
+            ON CONFLICT (user_id, match_id, market) DO UPDATE
+            SET
+                client_token = $3,
+                receipt = $4,
+                inserted_at = $5
+            "#,
+        )
+        .bind(user.identity())
+        .bind(battle.id)
+        .bind(client_token)
+        .bind(serde_json::to_string(&receipt).map_err(Error::new)?)
+        .bind(now)
+        .bind(WagerMarket::FinishTime)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(PlacedLineWager {
+        wager,
+        balance_after_wager,
+        match_id: match_id.hyphenated().to_string(),
+        private_to: (!show_wagers_publicly).then(|| user.identity()),
+        visibility: battle.visibility,
+        replayed: false,
+    })
+}
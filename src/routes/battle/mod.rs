@@ -1,5 +1,7 @@
 //! Match management routes.
 
+pub mod commentary;
+pub mod line;
 pub mod player;
 pub mod wager;
 
@@ -8,16 +10,23 @@ use axum::{
     extract::{Path, State},
 };
 
-use chrono::{DateTime, TimeDelta, Utc};
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
 
 use derive_more::{Deref, DerefMut};
 
 use garde::Validate;
 
 use ring_channel_model::{
-    Player,
-    battle::{Battle, BattleStatus, Participant, PlayerTeam},
-    request::battle::{CreateBattleRequest, UpdateBattleRequest},
+    BattleId, Mobiums, Player, PlayerShortId, User, UserId,
+    battle::{
+        Battle, BattlePrediction, BattleStatus, BattleWager, HighlightTag, LineSide, LineWager,
+        OddsMode, Participant, PlayerTeam, Visibility, WagerMarket,
+    },
+    message::server::Highlight,
+    page::ListFormat,
+    request::battle::{CreateBattleRequest, SwapParticipant, UpdateBattleRequest},
+    user::UserFlags,
+    webhook::WebhookEvent,
 };
 
 use http::StatusCode;
@@ -30,17 +39,39 @@ use tracing::instrument;
 
 use uuid::Uuid;
 
-use std::fmt::Debug;
+use std::{fmt::Debug, time::Instant};
 
 use crate::{
-    app::{AppForm, AppGarde, AppJson, AppState, Model, Payload},
+    app::{
+        AppForm, AppGarde, AppJson, AppState, Model, Payload,
+        conditional::{Cached, Conditional, Validators},
+        page::{Listing, parse_cursor, trim_page},
+    },
+    audit::Audit,
     auth::api_key::ServerAuthentication,
-    battle::{BattleSchema, calculate_winnings, update_participant_ratings},
+    battle::{
+        BattleSchema, calculate_winnings, cancel_battle, get_battle_schema,
+        parse_participant_stats, snapshot_pot, update_participant_ratings,
+    },
+    config::NameFilterConfig,
     error::{Error, ErrorKind},
-    player::mmr::{self, Rating, RawRating},
-    room::BattleData,
+    flags::LOCKED_ODDS,
+    highlight::detect_highlights,
+    matchmaking,
+    player::{
+        mmr::{self, ModelData, Rating, RawRating},
+        name_filter::censor_player_name,
+    },
+    room::{BattleData, heatmap::run_heatmap},
+    user::auto_bet::run_auto_bets,
+    webhook,
 };
 
+/// The furthest out bets can be reopened until.
+///
+/// This is the same as the longest `bet_time` a match can be created with.
+pub const MAX_REOPENED_BET_TIME: TimeDelta = TimeDelta::seconds(600);
+
 /// A query for [`list`].
 #[derive(Deserialize, Debug, Validate)]
 #[garde(context(AppState as state))]
@@ -52,6 +83,19 @@ pub struct ListBattlesQuery {
     pub before: Option<DateTime<Utc>>,
     #[garde(skip)]
     pub after: Option<DateTime<Utc>>,
+    /// The cursor of the page to list. Takes precedence over `before`.
+    #[garde(length(max = 64))]
+    pub cursor: Option<String>,
+    /// Whether to list the matches in a page.
+    #[garde(skip)]
+    #[serde(default)]
+    pub format: ListFormat,
+    /// Only list matches that are (or aren't) highlights.
+    #[garde(skip)]
+    pub highlight: Option<bool>,
+    /// Only list matches created by this server.
+    #[garde(skip)]
+    pub server: Option<String>,
 }
 
 fn list_battle_count_default() -> i32 {
@@ -61,62 +105,119 @@ fn list_battle_count_default() -> i32 {
 /// Lists all matches.
 #[instrument(skip(state, model))]
 pub async fn list<T>(
+    conditional: Conditional,
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
     AppGarde(AppForm(query)): AppGarde<AppForm<ListBattlesQuery>>,
-) -> Result<AppJson<Vec<Battle>>, Error>
+) -> Result<Cached<AppJson<Listing<Battle>>>, Error>
 where
     T: mmr::Model + 'static,
 {
-    let mut conn = state.db.acquire().await?;
+    let before = match query.cursor.as_deref() {
+        Some(cursor) => Some(parse_cursor::<DateTime<Utc>>(cursor)?),
+        None => query.before,
+    };
+
+    let mut conn = state.read_db().acquire().await?;
 
-    let mut battles = sqlx::query_as::<_, BattleSchema>(
+    let mut schemas = sqlx::query_as::<_, BattleSchema>(
         r#"
         SELECT
-            uuid, level_name, status, inserted_at, closed_at
+            uuid, level_name, status, inserted_at, closed_at, updated_at, concluded_at,
+            odds_mode, finish_time_line,
+            red_win_probability, quality, finalizes_at, visibility,
+            highlights,
+            (SELECT s.server_name FROM server s WHERE s.id = battle.server_id) AS server_name,
+            pot.red_pot, pot.blue_pot, pot.under_pot, pot.over_pot, pot.wagered_at
         FROM
             battle
+            INNER JOIN battle_pot pot ON pot.match_id = battle.id
         WHERE
             ($1 IS NULL OR inserted_at < $1)
             AND ($2 IS NULL OR inserted_at > $2)
+            AND visibility = $4
+            AND ($5 IS NULL OR (highlights != 0) = $5)
+            AND (
+                $6 IS NULL
+                OR server_id = (SELECT s.id FROM server s WHERE s.server_name = $6)
+            )
         ORDER BY
             inserted_at DESC
         LIMIT $3
         "#,
     )
-    .bind(query.before)
+    .bind(before)
     .bind(query.after)
-    .bind(query.count)
+    .bind(query.count + 1)
+    .bind(u8::from(Visibility::Public))
+    .bind(query.highlight)
+    .bind(query.server.as_deref())
     .fetch_all(&mut *conn)
-    .await?
-    .into_iter()
-    .map(|b| Battle::from(b))
-    .collect::<Vec<_>>();
+    .await?;
+
+    let has_more = trim_page(&mut schemas, query.count);
+    let next_cursor = schemas
+        .last()
+        .filter(|_| has_more)
+        .map(|b| b.inserted_at.to_rfc3339_opts(SecondsFormat::AutoSi, true));
+
+    // New matches are always the most recently modified, so this catches
+    // matches entering or leaving the page too
+    let last_modified = schemas
+        .iter()
+        .map(|b| b.last_modified())
+        .max()
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let mut etag = format!("{}-{}", last_modified.timestamp_millis(), schemas.len());
+    if query.format == ListFormat::Page {
+        etag.push_str(if has_more { "-more" } else { "-last" });
+    }
+    let validators = Validators::new(etag, last_modified);
+
+    if conditional.is_fresh(&validators) {
+        return Ok(Cached::NotModified(validators));
+    }
+
+    let mut battles = schemas
+        .into_iter()
+        .map(|b| Battle::from(b))
+        .collect::<Vec<_>>();
 
     // Preload all battles
     for battle in battles.iter_mut() {
         preload_participants(&model, battle, &mut *conn).await?;
     }
 
-    Ok(AppJson(battles))
+    Ok(Cached::Modified(
+        validators,
+        AppJson(Listing::new(query.format, battles, next_cursor)),
+    ))
 }
 
 /// Shows an existing match.
 #[instrument(skip(state, model))]
 pub async fn show<T>(
+    conditional: Conditional,
     Path((uuid,)): Path<(Uuid,)>,
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
-) -> Result<AppJson<Battle>, Error>
+) -> Result<Cached<AppJson<Battle>>, Error>
 where
     T: mmr::Model + 'static,
 {
-    let mut conn = state.db.acquire().await?;
+    let mut conn = state.read_db().acquire().await?;
 
     let battle = sqlx::query_as::<_, BattleSchema>(
         r#"
-        SELECT uuid, level_name, status, inserted_at, closed_at
+        SELECT
+            uuid, level_name, status, inserted_at, closed_at, updated_at, concluded_at,
+            odds_mode, finish_time_line,
+            red_win_probability, quality, finalizes_at, visibility,
+            highlights,
+            (SELECT s.server_name FROM server s WHERE s.id = battle.server_id) AS server_name,
+            pot.red_pot, pot.blue_pot, pot.under_pot, pot.over_pot, pot.wagered_at
         FROM battle
+        INNER JOIN battle_pot pot ON pot.match_id = battle.id
         WHERE uuid = $1
         "#,
     )
@@ -128,58 +229,124 @@ where
         return Err(Error::not_found(format!("Match {} not found", uuid)));
     };
 
+    let last_modified = battle.last_modified();
+    let validators = Validators::new(last_modified.timestamp_millis(), last_modified);
+
+    if conditional.is_fresh(&validators) {
+        return Ok(Cached::NotModified(validators));
+    }
+
     // Create battle struct
     let mut battle = Battle::from(battle);
 
     preload_participants(&model, &mut battle, &mut *conn).await?;
 
-    Ok(AppJson(battle))
+    Ok(Cached::Modified(validators, AppJson(battle)))
 }
 
 /// Creates a match.
 #[instrument(skip(state, model))]
 pub async fn create<T>(
-    _auth_guard: ServerAuthentication,
+    auth: ServerAuthentication,
+    audit: Audit,
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
-    Payload(request): Payload<CreateBattleRequest>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateBattleRequest>>,
 ) -> Result<(StatusCode, AppJson<Battle>), Error>
 where
-    T: mmr::Model + 'static,
+    T: Debug + mmr::Model + 'static,
+    T::Data: Debug,
 {
     #[derive(FromRow)]
     struct PlayerQuery {
         #[sqlx(rename = "player_id")]
         id: i32,
-        short_id: String,
+        short_id: PlayerShortId,
         display_name: String,
         rating: Option<f32>,
         deviation: Option<f32>,
         #[sqlx(rename = "rating_extra")]
         extra: Option<String>,
+        games_played: i32,
     }
 
     let uuid = Uuid::new_v4();
     let now = Utc::now();
+    let started = Instant::now();
 
     let closes_in = TimeDelta::seconds(request.bet_time.unwrap_or(20));
     let closed_at = now + closes_in;
 
+    let odds_mode = request.odds_mode.unwrap_or(
+        if state.config.server.locked_odds || state.flags.enabled(LOCKED_ODDS) {
+            OddsMode::Locked
+        } else {
+            OddsMode::Pool
+        },
+    );
+
     let mut tx = state.db.begin().await?;
 
+    // Servers can only run one match at a time
+    let ongoing = sqlx::query_as::<_, (BattleId, String)>(
+        r#"
+        SELECT id, uuid
+        FROM battle
+        WHERE server_id = $1 AND status = $2
+        "#,
+    )
+    .bind(auth.id)
+    .bind(BattleStatus::Ongoing)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let mut cancelled = None;
+    if let Some((ongoing_id, ongoing_uuid)) = ongoing {
+        if !request.force {
+            return Err(ErrorKind::BattleOngoing(ongoing_uuid).into());
+        }
+
+        tracing::info!(uuid = ongoing_uuid, "force cancelling ongoing match");
+        cancel_battle(ongoing_id, &model, &mut tx).await?;
+        audit.note(format!("cancelled match {}", ongoing_uuid));
+
+        let schema = get_battle_schema(ongoing_id, &mut tx).await?;
+        let mut battle = Battle::from(&schema);
+        preload_participants(&model, &mut battle, &mut tx).await?;
+
+        webhook::enqueue(WebhookEvent::MatchCancelled(battle.clone()), &mut tx).await?;
+
+        cancelled = Some((
+            ongoing_id,
+            BattleData {
+                schema,
+                participants: battle.participants,
+            },
+        ));
+    }
+
     // Create the battle
-    let (match_id,) = sqlx::query_as::<_, (i32,)>(
+    let (match_id,) = sqlx::query_as::<_, (BattleId,)>(
         r#"
-        INSERT INTO battle (uuid, level_name, inserted_at, closed_at, status)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO battle
+            (
+                uuid, level_name, server_id, inserted_at, closed_at, status, updated_at,
+                odds_mode, visibility, disable_bot, finish_time_line
+            )
+        VALUES ($1, $2, $3, $4, $5, $6, $4, $7, $8, $9, $10)
         RETURNING id
         "#,
     )
     .bind(uuid.hyphenated().to_string())
     .bind(&request.level_name)
+    .bind(auth.id)
     .bind(now)
     .bind(closed_at)
-    .bind(u8::from(BattleStatus::Ongoing))
+    .bind(BattleStatus::Ongoing)
+    .bind(u8::from(odds_mode))
+    .bind(u8::from(request.visibility))
+    .bind(request.disable_bot)
+    .bind(request.finish_time_line)
     .fetch_one(&mut *tx)
     .await?;
 
@@ -195,16 +362,31 @@ where
                 p.display_name,
                 p.rating,
                 p.deviation,
-                p.rating_extra
+                p.rating_extra,
+                (
+                    SELECT COUNT(*)
+                    FROM participant gp
+                    INNER JOIN battle gb ON gb.id = gp.match_id
+                    WHERE gp.player_id = p.id AND gb.status = $2
+                ) AS games_played
             FROM player p
-            WHERE short_id = $1
+            WHERE p.short_id = $1 OR p.id = (SELECT player_id FROM player_short_id_alias WHERE short_id = $1)
             "#,
         )
         .bind(&input_player.id)
+        .bind(BattleStatus::Concluded)
         .fetch_optional(&mut *tx)
         .await?;
 
         if let Some(mut player) = player {
+            // the denylist may have grown since the player registered
+            if let Some(display_name) =
+                censor_player_name(player.id, &state.config.server.name_filter, now, &mut tx)
+                    .await?
+            {
+                player.display_name = display_name;
+            }
+
             let rating = if !model.ratings_enabled() {
                 None
             } else if let Some((rating, deviation)) = player.rating.zip(player.deviation) {
@@ -230,7 +412,7 @@ where
             )
             .bind(match_id)
             .bind(player.id)
-            .bind(u8::from(input_player.team))
+            .bind(input_player.team)
             .bind(&input_player.skin)
             .bind(input_player.kart_speed)
             .bind(input_player.kart_weight)
@@ -241,7 +423,10 @@ where
             participants.push(Participant {
                 player: Player {
                     id: player.short_id,
-                    mmr: rating.map(|r| r.ordinal() as i32),
+                    mmr: rating.as_ref().map(|r| r.ordinal() as i32),
+                    provisional: model.ratings_enabled()
+                        && rating.as_ref().is_none_or(|r| r.is_provisional()),
+                    games_played: player.games_played,
                     public_key: None,
                     display_name: player.display_name,
                 },
@@ -251,6 +436,11 @@ where
                 skin: Some(input_player.skin),
                 kart_speed: Some(input_player.kart_speed),
                 kart_weight: Some(input_player.kart_weight),
+                mmr_delta: None,
+                // nobody could have wagered yet
+                backers: 0,
+                backed_mobiums: 0,
+                stats: None,
             })
         } else {
             tx.rollback().await?;
@@ -258,7 +448,7 @@ where
         }
     }
 
-    tx.commit().await?;
+    let prediction = predict_battle(match_id, &model, &mut tx).await?;
 
     // Create battle model
     let schema = BattleSchema {
@@ -267,20 +457,73 @@ where
         status: BattleStatus::Ongoing,
         inserted_at: now,
         closed_at: closed_at,
+        updated_at: now,
+        concluded_at: None,
+        odds_mode,
+        finish_time_line: request.finish_time_line,
+        red_win_probability: prediction.map(|p| p.red),
+        quality: prediction.map(|p| p.quality),
+        finalizes_at: None,
+        visibility: request.visibility,
+        highlights: 0,
+        server_name: Some(auth.server_name.clone()),
+        red_pot: 0,
+        blue_pot: 0,
+        under_pot: 0,
+        over_pot: 0,
+        wagered_at: None,
     };
     let mut battle = Battle::from(&schema);
     battle.participants = participants.clone();
     battle.accepting_bets = true;
     battle.closes_in = Some(closes_in.num_milliseconds());
 
-    // Send the notice of the new battle to all connected clients
-    state
-        .room
-        .update_battle(BattleData {
-            schema,
-            participants,
-        })
-        .await;
+    webhook::enqueue(WebhookEvent::MatchCreated(battle.clone()), &mut tx).await?;
+
+    tx.commit().await?;
+
+    if let Some((cancelled_id, cancelled)) = cancelled {
+        state.deadlines.remove(cancelled_id);
+
+        // unlisted matches are kept out of the room
+        if state.room.battle(&cancelled.schema.uuid).await.is_some() {
+            state.room.update_battle(cancelled).await;
+        }
+    }
+
+    // bet_time is never negative
+    if let Ok(closes_in) = closes_in.to_std() {
+        state.deadlines.insert(match_id, started + closes_in);
+    }
+
+    audit.note(format!("created match {}", uuid));
+
+    // unlisted matches are kept out of the room
+    if request.visibility.is_public() {
+        tokio::spawn(run_heatmap(
+            state.clone(),
+            match_id,
+            uuid.hyphenated().to_string(),
+        ));
+
+        // Send the notice of the new battle to all connected clients
+        state
+            .room
+            .update_battle(BattleData {
+                schema,
+                participants,
+            })
+            .await;
+
+        // wagers are announced to the room, so the match has to be there first
+        if state.config.server.auto_bets.enabled {
+            tokio::spawn(run_auto_bets(
+                state.clone(),
+                uuid,
+                prediction.map(|p| p.red),
+            ));
+        }
+    }
 
     Ok((StatusCode::CREATED, AppJson(battle)))
 }
@@ -289,10 +532,11 @@ where
 #[instrument(skip(state, model))]
 pub async fn update<T>(
     _auth_guard: ServerAuthentication,
+    audit: Audit,
     Path((uuid,)): Path<(Uuid,)>,
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
-    Payload(request): Payload<UpdateBattleRequest>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateBattleRequest>>,
 ) -> Result<AppJson<Battle>, Error>
 where
     T: Debug + mmr::Model + 'static,
@@ -300,7 +544,7 @@ where
 {
     #[derive(FromRow, Deref, DerefMut)]
     struct BattleQuery {
-        id: i32,
+        id: BattleId,
         #[sqlx(flatten)]
         #[deref]
         #[deref_mut]
@@ -314,9 +558,15 @@ where
     let battle_query = sqlx::query_as::<_, BattleQuery>(
         r#"
         SELECT
-            id, uuid, level_name, status, inserted_at, closed_at
+            id, uuid, level_name, status, inserted_at, closed_at, updated_at, concluded_at,
+            odds_mode, finish_time_line,
+            red_win_probability, quality, finalizes_at, visibility,
+            highlights,
+            (SELECT s.server_name FROM server s WHERE s.id = battle.server_id) AS server_name,
+            pot.red_pot, pot.blue_pot, pot.under_pot, pot.over_pot, pot.wagered_at
         FROM
             battle
+            INNER JOIN battle_pot pot ON pot.match_id = battle.id
         WHERE
             uuid = $1
         "#,
@@ -329,23 +579,122 @@ where
         return Err(Error::not_found(format!("Match {} not found", uuid)));
     };
 
+    let old_status = battle_query.status;
+
     // Verify changes
-    let is_status_changed = request
-        .status
-        .map(|s| s != battle_query.status)
-        .unwrap_or(false);
-    if battle_query.status != BattleStatus::Ongoing {
-        return Err(ErrorKind::AlreadyConcluded(uuid).into());
+    let new_status = match (battle_query.status, request.status) {
+        (BattleStatus::Ongoing, None | Some(BattleStatus::Ongoing)) => None,
+        // results get a chance to be disputed before anything is paid out
+        (BattleStatus::Ongoing, Some(BattleStatus::Concluded))
+            if state.config.server.dispute.enabled =>
+        {
+            Some(BattleStatus::Provisional)
+        }
+        (BattleStatus::Ongoing, status) => status,
+        (BattleStatus::Provisional, None | Some(BattleStatus::Provisional)) => None,
+        (BattleStatus::Provisional, Some(BattleStatus::Ongoing)) => {
+            return Err(
+                ErrorKind::InvalidData("Provisional matches cannot be reopened".into()).into(),
+            );
+        }
+        // concluding a provisional match finalizes it early
+        (BattleStatus::Provisional, status) => status,
+        (BattleStatus::Concluded | BattleStatus::Cancelled, _) => {
+            return Err(ErrorKind::AlreadyConcluded(uuid).into());
+        }
+    };
+
+    // Reopen bets, before any swaps that need them open
+    let reopened_bets = now >= battle_query.closed_at && request.reopen_bets.is_some();
+    if let Some(reopen_bets) = request.reopen_bets.as_ref() {
+        if battle_query.status != BattleStatus::Ongoing || new_status.is_some() {
+            return Err(ErrorKind::InvalidData(
+                "Bets can only be reopened on ongoing matches".into(),
+            )
+            .into());
+        }
+
+        // nobody gets to bet on a result that is already known
+        let (finished,) = sqlx::query_as::<_, (bool,)>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM participant
+                WHERE match_id = $1 AND finish_time IS NOT NULL
+            )
+            "#,
+        )
+        .bind(battle_query.id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if finished {
+            return Err(ErrorKind::InvalidData(
+                "Bets cannot be reopened once participants have finished".into(),
+            )
+            .into());
+        }
+
+        if reopen_bets.closed_at <= now || reopen_bets.closed_at > now + MAX_REOPENED_BET_TIME {
+            return Err(ErrorKind::InvalidData(format!(
+                "Bets can only be reopened for up to {} seconds",
+                MAX_REOPENED_BET_TIME.num_seconds()
+            ))
+            .into());
+        }
+
+        battle_query.closed_at = reopen_bets.closed_at;
+        audit.note(format!("reopened bets until {}", reopen_bets.closed_at));
+    }
+
+    // Swap participants, if any
+    let mut voided = Vec::new();
+    let mut voided_lines = Vec::new();
+    if !request.swap.is_empty() {
+        if now >= battle_query.closed_at {
+            return Err(ErrorKind::InvalidData(
+                "Participants can only be swapped while bets are open".into(),
+            )
+            .into());
+        }
+
+        let mut teams = Vec::with_capacity(2);
+        for swap in request.swap.iter() {
+            let old_team = swap_participant(
+                battle_query.id,
+                swap,
+                &state.config.server.name_filter,
+                &mut tx,
+            )
+            .await?;
+
+            audit.note(format!("swapped {} for {}", swap.replace, swap.with.id));
+
+            teams.extend([old_team, swap.with.team]);
+        }
+
+        voided = void_wagers(battle_query.id, &teams, &state, &mut tx).await?;
+        // the winner's finish time is anyone's guess with new participants
+        voided_lines = void_line_wagers(battle_query.id, &state, &mut tx).await?;
+        if !voided.is_empty() || !voided_lines.is_empty() {
+            snapshot_pot(battle_query.id, &mut tx).await?;
+        }
+
+        // the matchup changed
+        let prediction = predict_battle(battle_query.id, &model, &mut tx).await?;
+        battle_query.schema.red_win_probability = prediction.map(|p| p.red);
+        battle_query.schema.quality = prediction.map(|p| p.quality);
     }
 
     let mut set_concluded = None::<DateTime<Utc>>;
 
     // CHECK! We may need to process the end of a match here.
-    if is_status_changed {
-        // is_status_changed conditional gaurantees this is `Some`
-        let new_status = request.status.unwrap();
-
+    if let Some(new_status) = new_status {
         tracing::debug!("setting {} match status to {:?}", uuid, new_status);
+        audit.note(format!(
+            "status: {:?} -> {:?}",
+            battle_query.status, new_status
+        ));
 
         // Set all participants without a clear time to NO CONTEST
         sqlx::query(
@@ -363,7 +712,14 @@ where
         .execute(&mut *tx)
         .await?;
 
-        set_concluded = Some(now);
+        if battle_query.status == BattleStatus::Ongoing {
+            set_concluded = Some(now);
+            battle_query.schema.concluded_at = Some(now);
+        }
+
+        if new_status == BattleStatus::Provisional {
+            battle_query.schema.finalizes_at = Some(now + state.config.server.dispute.window);
+        }
 
         // if this cancels the betting session, we need to stop accepting bets
         if now < battle_query.closed_at {
@@ -375,55 +731,469 @@ where
     }
 
     // Update match details
-    sqlx::query(
+    // the match may have been finalized since it was read, which would pay it
+    // out twice
+    let result = sqlx::query(
         r#"
         UPDATE
             battle
         SET
             status = IFNULL($2, status),
             closed_at = $3,
-            concluded_at = IFNULL($4, concluded_at)
+            concluded_at = IFNULL($4, concluded_at),
+            updated_at = $5,
+            finalizes_at = $6
         WHERE
             id = $1
+            AND status = $7
         "#,
     )
     .bind(battle_query.id)
-    .bind(request.status.map(|s| u8::from(s)))
+    .bind(new_status)
     .bind(battle_query.closed_at)
     .bind(set_concluded)
+    .bind(now)
+    .bind(battle_query.finalizes_at)
+    .bind(old_status)
     .execute(&mut *tx)
     .await?;
 
-    if request.status == Some(BattleStatus::Concluded)
-        || request.status == Some(BattleStatus::Cancelled)
-    {
+    if result.rows_affected() == 0 {
+        return Err(ErrorKind::AlreadyConcluded(uuid).into());
+    }
+
+    if new_status == Some(BattleStatus::Concluded) || new_status == Some(BattleStatus::Cancelled) {
         update_participant_ratings(battle_query.id, &model, &mut *tx).await?;
     }
 
+    let mut highlights = Vec::new();
+    if new_status == Some(BattleStatus::Concluded) {
+        highlights =
+            detect_highlights(battle_query.id, &state.config.server.highlights, &mut tx).await?;
+        battle_query.schema.highlights = HighlightTag::to_mask(&highlights);
+    }
+
+    battle_query.schema.updated_at = now;
+
     // Create battle struct
     let mut battle = Battle::from(&battle_query.schema);
 
     preload_participants(&model, &mut battle, &mut *tx).await?;
 
     // Update websocket listeners
-    state
-        .room
-        .update_battle(BattleData {
+    // unlisted matches are kept out of the room
+    let public = battle_query.visibility.is_public();
+    if public {
+        let battle_data = BattleData {
             schema: battle_query.schema,
             participants: battle.participants.clone(),
-        })
-        .await;
+        };
+        if request.swap.is_empty() {
+            state.room.update_battle(battle_data).await;
+        } else {
+            // the lineup changed, so clients should start over
+            state.room.replace_battle(battle_data).await;
+        }
+    }
 
-    if request.status == Some(BattleStatus::Concluded) {
+    if new_status == Some(BattleStatus::Concluded) {
         // distribute pots!
-        calculate_winnings(battle_query.id, &state.room, &mut *tx).await?;
+        calculate_winnings(battle_query.id, &state, &mut *tx).await?;
+    }
+
+    // Let integrations know the match is over
+    let event = match new_status {
+        Some(BattleStatus::Concluded) => Some(WebhookEvent::MatchConcluded(battle.clone())),
+        Some(BattleStatus::Cancelled) => Some(WebhookEvent::MatchCancelled(battle.clone())),
+        _ => None,
+    };
+    if let Some(event) = event {
+        webhook::enqueue(event, &mut tx).await?;
     }
 
     tx.commit().await?;
 
+    if new_status.is_some() {
+        state.deadlines.remove(battle_query.id);
+    }
+
+    if let Some(reopen_bets) = request.reopen_bets.as_ref()
+        && let Ok(closes_in) = (reopen_bets.closed_at - now).to_std()
+    {
+        state
+            .deadlines
+            .insert(battle_query.id, Instant::now() + closes_in);
+    }
+
+    // the heatmap stopped when bets closed
+    if public && reopened_bets {
+        tokio::spawn(run_heatmap(
+            state.clone(),
+            battle_query.id,
+            battle.id.clone(),
+        ));
+    }
+
+    if public {
+        for (wager, private_to) in voided {
+            state.room.send_wager_update(&battle.id, wager, private_to);
+        }
+
+        for (wager, private_to) in voided_lines {
+            state
+                .room
+                .send_line_wager_update(&battle.id, wager, private_to);
+        }
+
+        if !highlights.is_empty() {
+            state.room.send_highlight(Highlight {
+                match_id: battle.id.clone(),
+                tags: highlights,
+            });
+        }
+    }
+
     Ok(AppJson(battle))
 }
 
+/// Swaps a participant out of a match, returning the team they were on.
+async fn swap_participant(
+    battle_id: BattleId,
+    swap: &SwapParticipant,
+    name_filter: &NameFilterConfig,
+    conn: &mut SqliteConnection,
+) -> Result<PlayerTeam, Error> {
+    #[derive(FromRow)]
+    struct ParticipantQuery {
+        id: i32,
+        team: PlayerTeam,
+    }
+
+    let old_participant = sqlx::query_as::<_, ParticipantQuery>(
+        r#"
+        SELECT pt.id, pt.team
+        FROM participant pt
+        INNER JOIN player p ON p.id = pt.player_id
+        WHERE
+            pt.match_id = $1
+            AND (p.short_id = $2 OR p.id = (SELECT player_id FROM player_short_id_alias WHERE short_id = $2))
+        "#,
+    )
+    .bind(battle_id)
+    .bind(&swap.replace)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| ErrorKind::MissingParticipant(swap.replace.clone()))?;
+
+    let new_player = sqlx::query_as::<_, (i32, bool)>(
+        r#"
+        SELECT
+            p.id,
+            EXISTS (
+                SELECT 1 FROM participant pt
+                WHERE pt.match_id = $1 AND pt.player_id = p.id
+            )
+        FROM player p
+        WHERE p.short_id = $2 OR p.id = (SELECT player_id FROM player_short_id_alias WHERE short_id = $2)
+        "#,
+    )
+    .bind(battle_id)
+    .bind(&swap.with.id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let new_player_id = match new_player {
+        Some((_, true)) => {
+            return Err(ErrorKind::InvalidData(format!(
+                "Participant {} is already in the match",
+                swap.with.id
+            ))
+            .into());
+        }
+        Some((id, false)) => id,
+        None => return Err(ErrorKind::MissingParticipant(swap.with.id.clone()).into()),
+    };
+
+    // the denylist may have grown since the player registered
+    censor_player_name(new_player_id, name_filter, Utc::now(), &mut *conn).await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM participant
+        WHERE id = $1
+        "#,
+    )
+    .bind(old_participant.id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO participant
+            (match_id, player_id, team, no_contest, skin, kart_speed, kart_weight)
+        VALUES ($1, $2, $3, FALSE, $4, $5, $6)
+        "#,
+    )
+    .bind(battle_id)
+    .bind(new_player_id)
+    .bind(swap.with.team)
+    .bind(&swap.with.skin)
+    .bind(swap.with.kart_speed)
+    .bind(swap.with.kart_weight)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(old_participant.team)
+}
+
+/// Voids every wager on `teams`, returning the voided wagers.
+///
+/// Each wager comes with the user it is private to, if its user keeps their
+/// wagers private.
+///
+/// Wagers are only deducted when a match is paid out, so voiding a wager is
+/// all it takes to refund it.
+async fn void_wagers(
+    battle_id: BattleId,
+    teams: &[PlayerTeam],
+    state: &AppState,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<(BattleWager, Option<UserId>)>, Error> {
+    #[derive(FromRow)]
+    struct WagerQuery {
+        id: i32,
+        victor: PlayerTeam,
+        // user structs
+        user_id: UserId,
+        show_wagers_publicly: bool,
+        username: String,
+        avatar: Option<String>,
+        display_name: String,
+        user_mobiums: Mobiums,
+        mobiums_gained: Mobiums,
+        mobiums_lost: Mobiums,
+        streak: i32,
+        #[sqlx(try_from = "i32")]
+        flags: UserFlags,
+    }
+
+    let now = Utc::now();
+
+    let wagers = sqlx::query_as::<_, WagerQuery>(
+        r#"
+        SELECT
+            w.id, w.victor, w.user_id,
+            u.show_wagers_publicly, u.username, u.display_name, u.avatar,
+            u.mobiums AS user_mobiums, u.mobiums_gained, u.mobiums_lost, u.streak, u.flags
+        FROM
+            wager w, user u
+        WHERE
+            w.user_id = u.id
+            AND w.mobiums > 0
+            AND w.match_id = $1
+            AND w.market = $2
+        "#,
+    )
+    .bind(battle_id)
+    .bind(WagerMarket::Winner)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut voided = Vec::new();
+    for wager in wagers.into_iter().filter(|w| teams.contains(&w.victor)) {
+        sqlx::query(
+            r#"
+            UPDATE wager
+            SET mobiums = 0, odds = NULL, updated_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(wager.id)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+
+        let private_to = (!wager.show_wagers_publicly).then_some(wager.user_id);
+
+        voided.push((
+            BattleWager {
+                user: Some(User {
+                    avatar: state.avatar_url(&wager.username, wager.avatar),
+                    username: wager.username,
+                    display_name: wager.display_name,
+                    mobiums: wager.user_mobiums,
+                    mobiums_gained: wager.mobiums_gained,
+                    mobiums_lost: wager.mobiums_lost,
+                    streak: wager.streak,
+                    flags: wager.flags,
+                }),
+                victor: wager.victor,
+                mobiums: Mobiums::ZERO,
+                odds: None,
+                is_bot: wager.flags.contains(UserFlags::AUTOMATED_USER),
+                updated_at: now,
+            },
+            private_to,
+        ));
+    }
+
+    Ok(voided)
+}
+
+/// Voids every wager on the finish time line of a match, returning the voided
+/// wagers like [`void_wagers`].
+async fn void_line_wagers(
+    battle_id: BattleId,
+    state: &AppState,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<(LineWager, Option<UserId>)>, Error> {
+    #[derive(FromRow)]
+    struct WagerQuery {
+        id: i32,
+        victor: LineSide,
+        // user structs
+        user_id: UserId,
+        show_wagers_publicly: bool,
+        username: String,
+        avatar: Option<String>,
+        display_name: String,
+        user_mobiums: Mobiums,
+        mobiums_gained: Mobiums,
+        mobiums_lost: Mobiums,
+        streak: i32,
+        #[sqlx(try_from = "i32")]
+        flags: UserFlags,
+    }
+
+    let now = Utc::now();
+
+    let wagers = sqlx::query_as::<_, WagerQuery>(
+        r#"
+        SELECT
+            w.id, w.victor, w.user_id,
+            u.show_wagers_publicly, u.username, u.display_name, u.avatar,
+            u.mobiums AS user_mobiums, u.mobiums_gained, u.mobiums_lost, u.streak, u.flags
+        FROM
+            wager w, user u
+        WHERE
+            w.user_id = u.id
+            AND w.mobiums > 0
+            AND w.match_id = $1
+            AND w.market = $2
+        "#,
+    )
+    .bind(battle_id)
+    .bind(WagerMarket::FinishTime)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut voided = Vec::with_capacity(wagers.len());
+    for wager in wagers {
+        sqlx::query(
+            r#"
+            UPDATE wager
+            SET mobiums = 0, updated_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(wager.id)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+
+        let private_to = (!wager.show_wagers_publicly).then_some(wager.user_id);
+
+        voided.push((
+            LineWager {
+                user: Some(User {
+                    avatar: state.avatar_url(&wager.username, wager.avatar),
+                    username: wager.username,
+                    display_name: wager.display_name,
+                    mobiums: wager.user_mobiums,
+                    mobiums_gained: wager.mobiums_gained,
+                    mobiums_lost: wager.mobiums_lost,
+                    streak: wager.streak,
+                    flags: wager.flags,
+                }),
+                mobiums: Mobiums::ZERO,
+                side: wager.victor,
+                updated_at: now,
+            },
+            private_to,
+        ));
+    }
+
+    Ok(voided)
+}
+
+/// Predicts how a match will go from its participants' ratings, storing the
+/// prediction on the match.
+///
+/// Returns `None` if ratings are disabled, or any participant is unrated.
+async fn predict_battle<T>(
+    battle_id: BattleId,
+    model: &Model<T>,
+    conn: &mut SqliteConnection,
+) -> Result<Option<BattlePrediction>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    #[derive(FromRow)]
+    struct RatingQuery {
+        team: PlayerTeam,
+        rating: Option<f32>,
+        deviation: Option<f32>,
+    }
+
+    let prediction = if model.ratings_enabled() {
+        let ratings = sqlx::query_as::<_, RatingQuery>(
+            r#"
+            SELECT pt.team, p.rating, p.deviation
+            FROM participant pt
+            INNER JOIN player p ON p.id = pt.player_id
+            WHERE pt.match_id = $1
+            "#,
+        )
+        .bind(battle_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let team_rating = |team: PlayerTeam| {
+            let ratings = ratings
+                .iter()
+                .filter(|r| r.team == team)
+                .map(|r| r.rating.zip(r.deviation))
+                .collect::<Option<Vec<_>>>()?;
+
+            matchmaking::team_rating(&ratings)
+        };
+
+        team_rating(PlayerTeam::Red)
+            .zip(team_rating(PlayerTeam::Blue))
+            .map(|(red, blue)| {
+                let red = <T::Data as ModelData>::win_probability(red, blue);
+                BattlePrediction::from_red(red as f64)
+            })
+    } else {
+        None
+    };
+
+    sqlx::query(
+        r#"
+        UPDATE battle
+        SET red_win_probability = $2, quality = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .bind(prediction.map(|p| p.red))
+    .bind(prediction.map(|p| p.quality))
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(prediction)
+}
+
 /// Preloads the `participants` field of a [`Battle`].
 ///
 /// If this function fails, `battle` will not be modified.
@@ -438,19 +1208,24 @@ where
     #[derive(FromRow)]
     struct ParticipantsQuery {
         player_id: i32,
-        short_id: String,
+        short_id: PlayerShortId,
         display_name: String,
-        #[sqlx(try_from = "u8")]
         team: PlayerTeam,
         finish_time: Option<i32>,
         no_contest: bool,
         skin: Option<String>,
         kart_speed: Option<i32>,
         kart_weight: Option<i32>,
+        mmr_before: Option<i32>,
+        mmr_after: Option<i32>,
         rating: Option<f32>,
         deviation: Option<f32>,
         #[sqlx(rename = "rating_extra")]
         extra: Option<String>,
+        games_played: i32,
+        backers: i64,
+        backed_mobiums: i64,
+        stats: Option<String>,
     }
 
     let participants = sqlx::query_as::<_, ParticipantsQuery>(
@@ -462,7 +1237,23 @@ where
             p.display_name,
             p.rating,
             p.deviation,
-            p.rating_extra
+            p.rating_extra,
+            (
+                SELECT COUNT(*)
+                FROM participant gp
+                INNER JOIN battle gb ON gb.id = gp.match_id
+                WHERE gp.player_id = p.id AND gb.status = $2
+            ) AS games_played,
+            (
+                SELECT COUNT(*)
+                FROM wager w
+                WHERE w.match_id = b.id AND w.market = 0 AND w.victor = pt.team AND w.mobiums > 0
+            ) AS backers,
+            (
+                SELECT IFNULL(SUM(w.mobiums), 0)
+                FROM wager w
+                WHERE w.match_id = b.id AND w.market = 0 AND w.victor = pt.team
+            ) AS backed_mobiums
         FROM
             participant pt, battle b, player p
         WHERE
@@ -472,6 +1263,7 @@ where
         "#,
     )
     .bind(&battle.id)
+    .bind(BattleStatus::Concluded)
     .fetch_all(&mut *conn)
     .await?;
 
@@ -496,19 +1288,34 @@ where
             }
         })
         .map(|res| {
-            res.map(|(p, rating)| Participant {
-                player: Player {
-                    id: p.short_id,
-                    mmr: rating.map(|rating| rating.ordinal() as i32),
-                    display_name: p.display_name,
-                    public_key: None,
-                },
-                team: p.team,
-                finish_time: p.finish_time,
-                no_contest: p.no_contest,
-                skin: p.skin,
-                kart_speed: p.kart_speed,
-                kart_weight: p.kart_weight,
+            res.and_then(|(p, rating)| {
+                Ok(Participant {
+                    player: Player {
+                        id: p.short_id,
+                        mmr: rating.as_ref().map(|rating| rating.ordinal() as i32),
+                        provisional: model.ratings_enabled()
+                            && rating.as_ref().is_none_or(|rating| rating.is_provisional()),
+                        games_played: p.games_played,
+                        display_name: p.display_name,
+                        public_key: None,
+                    },
+                    team: p.team,
+                    finish_time: p.finish_time,
+                    no_contest: p.no_contest,
+                    skin: p.skin,
+                    kart_speed: p.kart_speed,
+                    kart_weight: p.kart_weight,
+                    mmr_delta: if model.ratings_enabled() {
+                        p.mmr_before
+                            .zip(p.mmr_after)
+                            .map(|(before, after)| after - before)
+                    } else {
+                        None
+                    },
+                    backers: p.backers,
+                    backed_mobiums: p.backed_mobiums,
+                    stats: parse_participant_stats(p.stats.as_deref())?,
+                })
             })
         })
         .collect::<Result<Vec<_>, _>>()?;
@@ -516,10 +1323,10 @@ where
     Ok(())
 }
 
-async fn get_battle_id(match_id: Uuid, conn: &mut SqliteConnection) -> Result<i32, Error> {
+async fn get_battle_id(match_id: Uuid, conn: &mut SqliteConnection) -> Result<BattleId, Error> {
     #[derive(FromRow)]
     struct BattleQuery {
-        id: i32,
+        id: BattleId,
     }
 
     let battle = sqlx::query_as::<_, BattleQuery>(
@@ -537,3 +1344,88 @@ async fn get_battle_id(match_id: Uuid, conn: &mut SqliteConnection) -> Result<i3
 
     Ok(battle.id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ring_channel_model::request::battle::ReopenBets;
+
+    use crate::{app::Unrated, testing};
+
+    async fn reopen_bets(battle_id: BattleId, state: &AppState) -> Result<AppJson<Battle>, Error> {
+        let (uuid,) = sqlx::query_as::<_, (String,)>("SELECT uuid FROM battle WHERE id = $1")
+            .bind(battle_id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+
+        let request = UpdateBattleRequest {
+            status: None,
+            swap: Vec::new(),
+            reopen_bets: Some(ReopenBets {
+                closed_at: Utc::now() + TimeDelta::seconds(30),
+            }),
+        };
+        update(
+            ServerAuthentication {
+                id: 0,
+                server_name: "test".into(),
+            },
+            Audit::new(),
+            Path((uuid.parse().unwrap(),)),
+            Extension(Model::new(Unrated)),
+            State(state.clone()),
+            AppGarde(Payload(request)),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_bets_cannot_be_reopened_after_finishes() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let unfinished = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+        let finished = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+        testing::finish(finished, PlayerTeam::Red, 3000, &mut conn).await;
+        drop(conn);
+
+        assert!(reopen_bets(unfinished, &state).await.is_ok());
+        assert!(reopen_bets(finished, &state).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_void_line_wagers_refunds_every_side() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let under = testing::create_user("under", 400, &mut conn).await;
+        let over = testing::create_user("over", 400, &mut conn).await;
+        let battle_id = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+        testing::set_line(battle_id, 3000, &mut conn).await;
+        testing::place(under, battle_id, WagerMarket::FinishTime, 0, 100, &mut conn).await;
+        testing::place(over, battle_id, WagerMarket::FinishTime, 1, 100, &mut conn).await;
+        testing::place(under, battle_id, WagerMarket::Winner, 0, 50, &mut conn).await;
+
+        let voided = void_line_wagers(battle_id, &state, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(voided.len(), 2);
+
+        let (line, winner) = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT
+                SUM(mobiums) FILTER (WHERE market = 1),
+                SUM(mobiums) FILTER (WHERE market = 0)
+            FROM wager
+            WHERE match_id = $1
+            "#,
+        )
+        .bind(battle_id)
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap();
+        assert_eq!((line, winner), (0, 50));
+    }
+}
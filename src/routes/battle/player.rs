@@ -5,9 +5,12 @@ use axum::{
     extract::{Path, State},
 };
 
+use chrono::{DateTime, Utc};
+
 use ring_channel_model::{
-    Player,
-    battle::{BattleStatus, Participant, PlayerTeam},
+    BattleId, Player, PlayerShortId,
+    battle::{BattleStatus, LoadoutChange, Participant, PlayerTeam, Visibility},
+    message::server::LoadoutChanged,
     request::battle::UpdatePlayerPlacementRequest,
 };
 
@@ -18,36 +21,108 @@ use tracing::instrument;
 use uuid::Uuid;
 
 use crate::{
-    app::{AppJson, AppState, Model, Payload},
+    app::{AppGarde, AppJson, AppState, Model, Payload},
+    audit::Audit,
     auth::api_key::ServerAuthentication,
+    battle::parse_participant_stats,
     error::{Error, ErrorKind},
     player::mmr::{self, Rating, RawRating},
+    routes::battle::get_battle_id,
 };
 
 /// Updates the placement of a player for a given match.
 #[instrument(skip(state, model))]
 pub async fn update<T>(
     _auth_guard: ServerAuthentication,
-    Path((uuid, short_id)): Path<(Uuid, String)>,
+    audit: Audit,
+    Path((uuid, short_id)): Path<(Uuid, PlayerShortId)>,
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
-    Payload(request): Payload<UpdatePlayerPlacementRequest>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdatePlayerPlacementRequest>>,
 ) -> Result<AppJson<Participant>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    update_placement(uuid, short_id, &request, &model, &state, &audit)
+        .await
+        .map(AppJson)
+}
+
+/// Shows the loadout changes made during a match, oldest first.
+pub async fn loadouts(
+    Path((match_id,)): Path<(Uuid,)>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<LoadoutChange>>, Error> {
+    #[derive(FromRow)]
+    struct LoadoutChangeQuery {
+        short_id: PlayerShortId,
+        kart_speed: i32,
+        kart_weight: i32,
+        skin: String,
+        inserted_at: DateTime<Utc>,
+    }
+
+    let mut conn = state.read_db().acquire().await?;
+
+    let battle_id = get_battle_id(match_id, &mut conn).await?;
+
+    let changes = sqlx::query_as::<_, LoadoutChangeQuery>(
+        r#"
+        SELECT p.short_id, lc.kart_speed, lc.kart_weight, lc.skin, lc.inserted_at
+        FROM loadout_change lc
+        INNER JOIN participant pt ON pt.id = lc.participant_id
+        INNER JOIN player p ON p.id = pt.player_id
+        WHERE pt.match_id = $1
+        ORDER BY lc.inserted_at, lc.id
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(AppJson(
+        changes
+            .into_iter()
+            .map(|query| LoadoutChange {
+                player_id: query.short_id,
+                kart_speed: query.kart_speed,
+                kart_weight: query.kart_weight,
+                skin: query.skin,
+                changed_at: query.inserted_at,
+            })
+            .collect(),
+    ))
+}
+
+/// Updates the placement of a player.
+///
+/// Placements can be updated while the match is ongoing, or amended while it
+/// is [provisional](BattleStatus::Provisional). Loadouts can only be changed
+/// while the match is ongoing.
+pub async fn update_placement<T>(
+    uuid: Uuid,
+    short_id: PlayerShortId,
+    request: &UpdatePlayerPlacementRequest,
+    model: &Model<T>,
+    state: &AppState,
+    audit: &Audit,
+) -> Result<Participant, Error>
 where
     T: mmr::Model + 'static,
 {
     #[derive(FromRow)]
     struct BattleQuery {
-        id: i32,
-        #[sqlx(try_from = "u8")]
+        id: BattleId,
         status: BattleStatus,
+        #[sqlx(try_from = "u8")]
+        visibility: Visibility,
     }
 
     #[derive(FromRow)]
     struct ParticipantQuery {
         id: Option<i32>,
         player_id: i32,
-        team: Option<u8>,
+        team: Option<PlayerTeam>,
         no_contest: Option<bool>,
         finish_time: Option<i32>,
         skin: Option<String>,
@@ -58,12 +133,16 @@ where
         deviation: Option<f32>,
         #[sqlx(rename = "rating_extra")]
         extra: Option<String>,
+        games_played: i32,
+        backers: i64,
+        backed_mobiums: i64,
+        stats: Option<String>,
     }
 
     // find match first
     let battle = sqlx::query_as::<_, BattleQuery>(
         r#"
-        SELECT id, status
+        SELECT id, status, visibility
         FROM battle
         WHERE uuid = $1
         "#,
@@ -77,7 +156,15 @@ where
     };
 
     // if the battle is closed, it cannot be updated anymore
-    if battle.status != BattleStatus::Ongoing {
+    if !matches!(
+        battle.status,
+        BattleStatus::Ongoing | BattleStatus::Provisional
+    ) {
+        return Err(ErrorKind::AlreadyConcluded(uuid).into());
+    }
+
+    // once the race is over, there are no more rounds to change loadouts for
+    if request.loadout.is_some() && battle.status != BattleStatus::Ongoing {
         return Err(ErrorKind::AlreadyConcluded(uuid).into());
     }
 
@@ -90,19 +177,36 @@ where
             p.display_name,
             p.rating,
             p.deviation,
-            p.rating_extra
+            p.rating_extra,
+            (
+                SELECT COUNT(*)
+                FROM participant gp
+                INNER JOIN battle gb ON gb.id = gp.match_id
+                WHERE gp.player_id = p.id AND gb.status = $3
+            ) AS games_played,
+            (
+                SELECT COUNT(*)
+                FROM wager w
+                WHERE w.match_id = $2 AND w.market = 0 AND w.victor = pt.team AND w.mobiums > 0
+            ) AS backers,
+            (
+                SELECT IFNULL(SUM(w.mobiums), 0)
+                FROM wager w
+                WHERE w.match_id = $2 AND w.market = 0 AND w.victor = pt.team
+            ) AS backed_mobiums
         FROM
             player p
         LEFT OUTER JOIN
             participant pt
             ON pt.player_id = p.id
         WHERE
-            p.short_id = $1
+            (p.short_id = $1 OR p.id = (SELECT player_id FROM player_short_id_alias WHERE short_id = $1))
             AND pt.match_id = $2
         "#,
     )
     .bind(&short_id)
     .bind(battle.id)
+    .bind(BattleStatus::Concluded)
     .fetch_optional(&state.db)
     .await?;
 
@@ -129,12 +233,14 @@ where
     let ParticipantQuery { finish_time, .. } = participant;
 
     // UPDATE THAT SHIT KAKAROT!
+    // players amended with a finish time did finish after all
     sqlx::query(
         r#"
         UPDATE
             participant
         SET
-            finish_time = IFNULL($2, finish_time)
+            finish_time = IFNULL($2, finish_time),
+            no_contest = no_contest AND $2 IS NULL
         WHERE
             id = $1
         "#,
@@ -144,6 +250,144 @@ where
     .execute(&state.db)
     .await?;
 
+    if let Some(new_finish_time) = request.finish_time {
+        sqlx::query(
+            r#"
+            UPDATE battle
+            SET updated_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(battle.id)
+        .bind(Utc::now())
+        .execute(&state.db)
+        .await?;
+
+        audit.note(format!(
+            "finish_time of {}: {:?} -> {}",
+            short_id, finish_time, new_finish_time
+        ));
+    }
+
+    // servers may send the loadout every round, but only changes are kept
+    let loadout = request.loadout.as_ref().filter(|loadout| {
+        participant.skin.as_deref() != Some(loadout.skin.as_str())
+            || participant.kart_speed != Some(loadout.kart_speed)
+            || participant.kart_weight != Some(loadout.kart_weight)
+    });
+
+    let mut loadout_change = None;
+    if let Some(loadout) = loadout {
+        let now = Utc::now();
+        let mut tx = state.db.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE participant
+            SET kart_speed = $2, kart_weight = $3, skin = $4
+            WHERE id = $1
+            "#,
+        )
+        .bind(participant_id)
+        .bind(loadout.kart_speed)
+        .bind(loadout.kart_weight)
+        .bind(&loadout.skin)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO loadout_change
+                (participant_id, kart_speed, kart_weight, skin, inserted_at)
+            VALUES
+                ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(participant_id)
+        .bind(loadout.kart_speed)
+        .bind(loadout.kart_weight)
+        .bind(&loadout.skin)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE battle
+            SET updated_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(battle.id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        audit.note(format!(
+            "loadout of {}: {:?} {:?}/{:?} -> {} {}/{}",
+            short_id,
+            participant.skin,
+            participant.kart_speed,
+            participant.kart_weight,
+            loadout.skin,
+            loadout.kart_speed,
+            loadout.kart_weight
+        ));
+
+        loadout_change = Some(LoadoutChange {
+            player_id: short_id.clone(),
+            kart_speed: loadout.kart_speed,
+            kart_weight: loadout.kart_weight,
+            skin: loadout.skin.clone(),
+            changed_at: now,
+        });
+    }
+
+    let mut stats = participant.stats;
+    if let Some(new_stats) = &request.stats {
+        let patch = serde_json::to_string(new_stats).map_err(Error::new)?;
+        let mut tx = state.db.begin().await?;
+
+        // only the reported fields are replaced
+        let (patched,) = sqlx::query_as::<_, (Option<String>,)>(
+            r#"
+            UPDATE participant
+            SET stats = json_patch(IFNULL(stats, '{}'), $2)
+            WHERE id = $1
+            RETURNING stats
+            "#,
+        )
+        .bind(participant_id)
+        .bind(&patch)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE battle
+            SET updated_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(battle.id)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        audit.note(format!(
+            "stats of {}: {} -> {}",
+            short_id,
+            stats.as_deref().unwrap_or("{}"),
+            patch
+        ));
+
+        stats = patched;
+    }
+
     let rating = if !model.ratings_enabled() {
         None
     } else if let Some((rating, deviation)) = participant.rating.zip(participant.deviation) {
@@ -159,18 +403,51 @@ where
         None
     };
 
-    Ok(AppJson(Participant {
+    let (skin, kart_speed, kart_weight) = match &loadout_change {
+        Some(change) => (
+            Some(change.skin.clone()),
+            Some(change.kart_speed),
+            Some(change.kart_weight),
+        ),
+        None => (
+            participant.skin,
+            participant.kart_speed,
+            participant.kart_weight,
+        ),
+    };
+
+    // unlisted matches are kept out of the room
+    if let Some(change) = loadout_change
+        && battle.visibility.is_public()
+    {
+        state
+            .room
+            .send_loadout_change(LoadoutChanged {
+                match_id: uuid.hyphenated().to_string(),
+                change,
+            })
+            .await;
+    }
+
+    Ok(Participant {
         player: Player {
             id: short_id,
-            mmr: rating.map(|r| r.ordinal() as i32),
+            mmr: rating.as_ref().map(|r| r.ordinal() as i32),
+            provisional: model.ratings_enabled()
+                && rating.as_ref().is_none_or(|r| r.is_provisional()),
+            games_played: participant.games_played,
             public_key: None,
             display_name: participant.display_name,
         },
-        team: PlayerTeam::try_from(team).map_err(Error::new)?,
-        finish_time: finish_time.or(request.finish_time),
-        no_contest,
-        skin: participant.skin,
-        kart_speed: participant.kart_speed,
-        kart_weight: participant.kart_weight,
-    }))
+        team,
+        finish_time: request.finish_time.or(finish_time),
+        no_contest: no_contest && request.finish_time.is_none(),
+        skin,
+        kart_speed,
+        kart_weight,
+        mmr_delta: None,
+        backers: participant.backers,
+        backed_mobiums: participant.backed_mobiums,
+        stats: parse_participant_stats(stats.as_deref())?,
+    })
 }
@@ -2,91 +2,191 @@
 
 use axum::extract::{Path, State};
 
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
+
+use garde::Validate;
 
 use ring_channel_model::{
-    User,
-    battle::{BattleStatus, BattleWager, PlayerTeam},
-    request::battle::UpdateWager,
-    user::UserFlags,
+    BattleId, Mobiums, User, UserId,
+    admin::BotStrategy,
+    battle::{
+        BattleStatus, BattleWager, OddsMode, PlayerTeam, PotSnapshot, Visibility, WagerMarket,
+        WagerReceipt, WagerSuggestion, WagerSuggestions,
+    },
+    message::server::MobiumsChange,
+    page::ListFormat,
+    request::battle::{UpdateWager, WagerAmount},
+    squad::SquadPot,
+    user::{UserFlags, WagerBalance},
 };
 
-use sqlx::{Acquire, FromRow, SqliteConnection};
+use serde::Deserialize;
+
+use sqlx::{FromRow, SqliteConnection};
 
 use uuid::Uuid;
 
 use crate::{
-    app::{AppJson, AppState, Payload},
+    app::{
+        AppForm, AppGarde, AppJson, AppState, Payload,
+        page::{Listing, parse_cursor, trim_page},
+    },
+    audit::Audit,
+    battle::{lock_odds, snapshot_pot},
     error::{Error, ErrorKind},
+    event::grant_first_wager_bonus,
     routes::battle::get_battle_id,
     session::{Session, SessionUser},
-    user::{UserSchema, bot::get_wager_bot},
+    squad::squad_pots,
+    timings::RequestTimings,
+    user::{bot::WagerBotUser, ledger::grant_onboarding_bonus, penalty::WagerUser, wager_balance},
+    wager_queue::WagerRequest,
 };
 
-/// Lists all wagers on a match.
+/// The shares of a user's available mobiums suggested as quick bets.
+pub const SUGGESTED_WAGER_SHARES: [f64; 3] = [0.01, 0.05, 0.10];
+
+/// A query for [`list`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct ListWagersQuery {
+    /// How many wagers to list.
+    ///
+    /// Bare arrays list every wager if this isn't given.
+    #[garde(range(min = 1, max = 100))]
+    pub count: Option<i32>,
+    /// The cursor of the page to list.
+    #[garde(length(max = 64))]
+    pub cursor: Option<String>,
+    /// Whether to list the wagers in a page.
+    #[garde(skip)]
+    #[serde(default)]
+    pub format: ListFormat,
+    /// Whether to list the wagers of automated users, like the wager bot.
+    #[garde(skip)]
+    #[serde(default)]
+    pub include_bots: bool,
+}
+
+fn list_wagers_count_default() -> i32 {
+    100
+}
+
+/// Lists all wagers on a match, oldest first.
+///
+/// Users who keep their wagers private are left out of their wagers, unless
+/// the viewer is them or an administrator. Wagers of automated users are left
+/// out unless asked for.
 pub async fn list(
     Path((match_id,)): Path<(Uuid,)>,
+    viewer: Option<SessionUser>,
+    timings: RequestTimings,
     State(state): State<AppState>,
-) -> Result<AppJson<Vec<BattleWager>>, Error> {
-    let mut conn = state.db.acquire().await?;
+    AppGarde(AppForm(query)): AppGarde<AppForm<ListWagersQuery>>,
+) -> Result<AppJson<Listing<BattleWager>>, Error> {
+    let after = query
+        .cursor
+        .as_deref()
+        .map(parse_cursor::<i32>)
+        .transpose()?;
+    let count = match query.format {
+        ListFormat::Array => query.count,
+        ListFormat::Page => Some(query.count.unwrap_or_else(list_wagers_count_default)),
+    };
+
+    let mut conn = state.read_db().acquire().await?;
 
     #[derive(FromRow)]
     struct WagerQuery {
-        #[sqlx(try_from = "u8")]
+        id: i32,
         victor: PlayerTeam,
-        mobiums: i64,
+        mobiums: Mobiums,
+        odds: Option<f64>,
         updated_at: DateTime<Utc>,
         // user structs
+        user_id: UserId,
+        show_wagers_publicly: bool,
         username: String,
         avatar: Option<String>,
         display_name: String,
-        user_mobiums: i64,
-        mobiums_gained: i64,
-        mobiums_lost: i64,
+        user_mobiums: Mobiums,
+        mobiums_gained: Mobiums,
+        mobiums_lost: Mobiums,
+        streak: i32,
         #[sqlx(try_from = "i32")]
         flags: UserFlags,
     }
 
-    let battle_id = get_battle_id(match_id, &mut *conn).await?;
+    let battle_id = timings.db(get_battle_id(match_id, &mut conn)).await?;
 
     // Fetch all wagers
-    let query = sqlx::query_as::<_, WagerQuery>(
+    let fetch = sqlx::query_as::<_, WagerQuery>(
         r#"
         SELECT
-            w.victor, w.mobiums, w.updated_at,
-            u.username, u.display_name, u.avatar, u.mobiums AS user_mobiums,
-            u.mobiums_gained, u.mobiums_lost, u.flags
+            w.id, w.victor, w.mobiums, w.odds, w.updated_at, w.user_id,
+            u.show_wagers_publicly, u.username, u.display_name, u.avatar,
+            u.mobiums AS user_mobiums, u.mobiums_gained, u.mobiums_lost, u.streak, u.flags
         FROM
             wager w, user u
         WHERE
             w.user_id = u.id
             AND w.mobiums > 0
             AND match_id = $1
+            AND w.market = $6
+            AND ($2 IS NULL OR w.id > $2)
+            AND ($4 OR NOT u.flags & $5)
+        ORDER BY w.id ASC
+        LIMIT $3
         "#,
     )
     .bind(battle_id)
-    .fetch_all(&mut *conn)
-    .await?;
+    .bind(after)
+    // a negative limit is no limit at all
+    .bind(count.map(|count| count + 1).unwrap_or(-1))
+    .bind(query.include_bots)
+    .bind(i32::from(UserFlags::AUTOMATED_USER))
+    .bind(WagerMarket::Winner)
+    .fetch_all(&mut *conn);
 
-    Ok(AppJson(
-        query
-            .into_iter()
-            .map(|query| BattleWager {
-                user: Some(User {
-                    username: query.username,
-                    avatar: query.avatar,
-                    display_name: query.display_name,
-                    mobiums: query.user_mobiums,
-                    mobiums_gained: query.mobiums_gained,
-                    mobiums_lost: query.mobiums_lost,
-                    flags: query.flags,
+    let mut rows = timings.db(fetch).await?;
+
+    let has_more = count.is_some_and(|count| trim_page(&mut rows, count));
+    let next_cursor = rows
+        .last()
+        .filter(|_| has_more)
+        .map(|row| row.id.to_string());
+
+    let wagers = rows
+        .into_iter()
+        .map(|row| {
+            let visible = row.show_wagers_publicly
+                || viewer
+                    .as_ref()
+                    .is_some_and(|viewer| viewer.can_see_wagers_of(row.user_id));
+
+            let is_bot = row.flags.contains(UserFlags::AUTOMATED_USER);
+
+            BattleWager {
+                user: visible.then(|| User {
+                    avatar: state.avatar_url(&row.username, row.avatar),
+                    username: row.username,
+                    display_name: row.display_name,
+                    mobiums: row.user_mobiums,
+                    mobiums_gained: row.mobiums_gained,
+                    mobiums_lost: row.mobiums_lost,
+                    streak: row.streak,
+                    flags: row.flags,
                 }),
-                victor: query.victor,
-                mobiums: query.mobiums,
-                updated_at: query.updated_at,
-            })
-            .collect(),
-    ))
+                victor: row.victor,
+                mobiums: row.mobiums,
+                odds: row.odds,
+                is_bot,
+                updated_at: row.updated_at,
+            }
+        })
+        .collect();
+
+    Ok(AppJson(Listing::new(query.format, wagers, next_cursor)))
 }
 
 /// Shows your wager on a match.
@@ -99,17 +199,18 @@ pub async fn show_self(
 
     #[derive(FromRow)]
     struct WagerQuery {
-        #[sqlx(try_from = "u8")]
         victor: PlayerTeam,
-        mobiums: i64,
+        mobiums: Mobiums,
+        odds: Option<f64>,
         updated_at: DateTime<Utc>,
         // user structs
         username: String,
         avatar: Option<String>,
         display_name: String,
-        user_mobiums: i64,
-        mobiums_gained: i64,
-        mobiums_lost: i64,
+        user_mobiums: Mobiums,
+        mobiums_gained: Mobiums,
+        mobiums_lost: Mobiums,
+        streak: i32,
         #[sqlx(try_from = "i32")]
         flags: UserFlags,
     }
@@ -120,9 +221,9 @@ pub async fn show_self(
     let query = sqlx::query_as::<_, WagerQuery>(
         r#"
         SELECT
-            w.victor, w.mobiums, w.updated_at,
+            w.victor, w.mobiums, w.odds, w.updated_at,
             u.username, u.display_name, u.avatar, u.mobiums AS user_mobiums,
-            u.mobiums_gained, u.mobiums_lost, u.flags
+            u.mobiums_gained, u.mobiums_lost, u.streak, u.flags
         FROM
             wager w, user u
         WHERE
@@ -130,10 +231,12 @@ pub async fn show_self(
             AND w.mobiums > 0
             AND w.user_id = $1
             AND match_id = $2
+            AND w.market = $3
         "#,
     )
     .bind(session.identity())
     .bind(battle_id)
+    .bind(WagerMarket::Winner)
     .fetch_optional(&mut *conn)
     .await?;
 
@@ -143,40 +246,49 @@ pub async fn show_self(
 
     Ok(AppJson(BattleWager {
         user: Some(User {
+            avatar: state.avatar_url(&query.username, query.avatar),
             username: query.username,
-            avatar: query.avatar,
             display_name: query.display_name,
             mobiums: query.user_mobiums,
             mobiums_gained: query.mobiums_gained,
             mobiums_lost: query.mobiums_lost,
+            streak: query.streak,
             flags: query.flags,
         }),
         victor: query.victor,
         mobiums: query.mobiums,
+        odds: query.odds,
+        is_bot: query.flags.contains(UserFlags::AUTOMATED_USER),
         updated_at: query.updated_at,
     }))
 }
 
 /// Shows another player's wager on the match.
+///
+/// Private wagers are only shown to their user and administrators.
 pub async fn show(
     Path((match_id, username)): Path<(Uuid, String)>,
+    viewer: Option<SessionUser>,
     State(state): State<AppState>,
 ) -> Result<AppJson<BattleWager>, Error> {
     let mut conn = state.db.acquire().await?;
 
     #[derive(FromRow)]
     struct WagerQuery {
-        #[sqlx(try_from = "u8")]
         victor: PlayerTeam,
-        mobiums: i64,
+        mobiums: Mobiums,
+        odds: Option<f64>,
         updated_at: DateTime<Utc>,
         // user structs
+        user_id: UserId,
+        show_wagers_publicly: bool,
         username: String,
         avatar: Option<String>,
         display_name: String,
-        user_mobiums: i64,
-        mobiums_gained: i64,
-        mobiums_lost: i64,
+        user_mobiums: Mobiums,
+        mobiums_gained: Mobiums,
+        mobiums_lost: Mobiums,
+        streak: i32,
         #[sqlx(try_from = "i32")]
         flags: UserFlags,
     }
@@ -187,88 +299,534 @@ pub async fn show(
     let query = sqlx::query_as::<_, WagerQuery>(
         r#"
         SELECT
-            w.victor, w.mobiums, w.updated_at,
-            u.username, u.display_name, u.avatar, u.mobiums AS user_mobiums,
-            u.mobiums_gained, u.mobiums_lost, u.flags
+            w.victor, w.mobiums, w.odds, w.updated_at, w.user_id,
+            u.show_wagers_publicly, u.username, u.display_name, u.avatar,
+            u.mobiums AS user_mobiums, u.mobiums_gained, u.mobiums_lost, u.streak, u.flags
         FROM
             wager w, user u
         WHERE
             w.user_id = u.id
-            AND w.username = $1
+            AND u.id = (
+                SELECT IFNULL(merged_into, id) FROM user WHERE username = $1
+            )
             AND match_id = $2
+            AND w.market = $3
         "#,
     )
     .bind(username)
     .bind(battle_id)
+    .bind(WagerMarket::Winner)
     .fetch_optional(&mut *conn)
     .await?;
 
+    // private wagers look like they don't exist
+    let query = query.filter(|query| {
+        query.show_wagers_publicly
+            || viewer
+                .as_ref()
+                .is_some_and(|viewer| viewer.can_see_wagers_of(query.user_id))
+    });
+
     let Some(query) = query else {
         return Err(Error::not_found("Wager not found"));
     };
 
     Ok(AppJson(BattleWager {
         user: Some(User {
+            avatar: state.avatar_url(&query.username, query.avatar),
             username: query.username,
-            avatar: query.avatar,
             display_name: query.display_name,
             mobiums: query.user_mobiums,
             mobiums_gained: query.mobiums_gained,
             mobiums_lost: query.mobiums_lost,
+            streak: query.streak,
             flags: query.flags,
         }),
         victor: query.victor,
         mobiums: query.mobiums,
+        odds: query.odds,
+        is_bot: query.flags.contains(UserFlags::AUTOMATED_USER),
         updated_at: query.updated_at,
     }))
 }
 
 /// Creates a personal wager.
+///
+/// Responds with the user's balance after the wager, so clients don't have to
+/// work out what is left to wager.
 pub async fn create(
     Path((match_id,)): Path<(Uuid,)>,
-    user: SessionUser,
+    WagerUser(user): WagerUser,
     mut session: Session,
+    audit: Audit,
+    timings: RequestTimings,
     State(state): State<AppState>,
-    Payload(update_wager): Payload<UpdateWager>,
-) -> Result<AppJson<BattleWager>, Error> {
-    #[derive(FromRow)]
-    struct BattleQuery {
-        id: i32,
-        #[sqlx(try_from = "u8")]
-        status: BattleStatus,
-        closed_at: DateTime<Utc>,
-    }
-
+    AppGarde(Payload(update_wager)): AppGarde<Payload<UpdateWager>>,
+) -> Result<AppJson<WagerReceipt>, Error> {
     // reject any suspicious requests
     if session.csrf != update_wager.csrf {
         return Err(ErrorKind::InvalidCsrfToken.into());
     }
 
-    if update_wager.mobiums < 0 {
+    let amount = update_wager.amount().ok_or_else(|| {
+        ErrorKind::InvalidData("Exactly one of `mobiums` and `adjust` must be set".into())
+    })?;
+
+    // placing a wager is almost entirely database work
+    let wager = timings
+        .db(place_wager(
+            &state,
+            WagerRequest {
+                user,
+                audit,
+                match_id,
+                victor: update_wager.victor,
+                amount,
+                client_token: update_wager.client_token,
+                auto_bet: false,
+            },
+            update_wager.captcha.as_deref(),
+        ))
+        .await?;
+
+    // shuffle csrf after the action is done
+    session.shuffle_csrf().await?;
+
+    Ok(AppJson(wager))
+}
+
+/// Suggests quick-bet amounts for a match, as shares of the user's available
+/// mobiums.
+///
+/// Suggestions are capped by the most the user can wager on the match, so
+/// every suggestion can be placed as is.
+pub async fn suggestions(
+    Path((match_id,)): Path<(Uuid,)>,
+    user: SessionUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<WagerSuggestions>, Error> {
+    let mut conn = state.db.acquire().await?;
+
+    let battle_id = get_battle_id(match_id, &mut *conn).await?;
+
+    // the user's wager on this match can be moved anywhere up to the max
+    let balance = wager_balance(
+        user.identity(),
+        Some((battle_id, WagerMarket::Winner)),
+        &mut conn,
+    )
+    .await?;
+    let cooldown = bailout_cooldown(
+        &state,
+        user.identity(),
+        balance.mobiums,
+        Utc::now(),
+        &mut conn,
+    )
+    .await?;
+
+    let limit = wager_limit(&state, user.identity(), Utc::now(), &mut conn).await?;
+
+    let max_mobiums = [
+        cooldown.map(|(_, max_mobiums)| max_mobiums),
+        limit.map(|(max_mobiums, _)| max_mobiums),
+    ]
+    .into_iter()
+    .flatten()
+    .fold(balance.available, Mobiums::min);
+
+    let mut suggestions = Vec::<WagerSuggestion>::new();
+    for share in SUGGESTED_WAGER_SHARES {
+        // always suggest at least a single mobium
+        let mobiums = Mobiums((balance.available.0 as f64 * share).floor() as i64)
+            .max(Mobiums(1))
+            .min(max_mobiums);
+
+        // small balances round several shares to the same amount
+        if mobiums <= 0
+            || suggestions
+                .last()
+                .is_some_and(|last| last.mobiums >= mobiums)
+        {
+            continue;
+        }
+
+        suggestions.push(WagerSuggestion { share, mobiums });
+    }
+
+    Ok(AppJson(WagerSuggestions {
+        max_mobiums,
+        suggestions,
+    }))
+}
+
+/// Sums each squad's wagers on a match, biggest pot first.
+///
+/// Only wagers members placed while in their squad count.
+pub async fn squads(
+    Path((match_id,)): Path<(Uuid,)>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<SquadPot>>, Error> {
+    let mut conn = state.read_db().acquire().await?;
+
+    let battle_id = get_battle_id(match_id, &mut conn).await?;
+
+    Ok(AppJson(squad_pots(battle_id, &mut conn).await?))
+}
+
+/// Shows how the pots of a match grew over time, oldest first.
+pub async fn pot_history(
+    Path((match_id,)): Path<(Uuid,)>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<PotSnapshot>>, Error> {
+    #[derive(FromRow)]
+    struct SnapshotQuery {
+        red: i64,
+        blue: i64,
+        wagers: i64,
+        inserted_at: DateTime<Utc>,
+    }
+
+    let mut conn = state.read_db().acquire().await?;
+
+    let battle_id = get_battle_id(match_id, &mut *conn).await?;
+
+    let snapshots = sqlx::query_as::<_, SnapshotQuery>(
+        r#"
+        SELECT red, blue, wagers, inserted_at
+        FROM pot_snapshot
+        WHERE match_id = $1
+        ORDER BY inserted_at, id
+        "#,
+    )
+    .bind(battle_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(AppJson(
+        snapshots
+            .into_iter()
+            .map(|query| PotSnapshot {
+                red: query.red,
+                blue: query.blue,
+                wagers: query.wagers,
+                created_at: query.inserted_at,
+            })
+            .collect(),
+    ))
+}
+
+/// Places a wager for a user, notifying the room.
+///
+/// The wager is written by the [`WagerQueue`](crate::wager_queue::WagerQueue),
+/// alongside any other wagers placed around the same time. Fails fast if the
+/// queue is full.
+///
+/// `captcha` is the user's CAPTCHA response, which is only checked if the
+/// user has to pass one.
+///
+/// This does no CSRF checks! Make sure the user actually wants to do this.
+pub async fn place_wager(
+    state: &AppState,
+    request: WagerRequest,
+    captcha: Option<&str>,
+) -> Result<WagerReceipt, Error> {
+    // adjustments can only be checked against the current wager
+    if let WagerAmount::Mobiums(mobiums) = request.amount {
+        check_wager_bounds(mobiums, request.user.mobiums)?;
+    }
+
+    if let Some(verifier) = &state.captcha {
+        let mut conn = state.db.acquire().await?;
+        verifier
+            .check(request.user.identity(), captcha, &mut conn)
+            .await?;
+    }
+
+    state.wagers.place(request).await
+}
+
+/// Checks that bets on a match are still open.
+pub fn check_bets_open(
+    state: &AppState,
+    battle_id: BattleId,
+    status: BattleStatus,
+    closed_at: DateTime<Utc>,
+) -> Result<(), Error> {
+    let closes_in = state.deadlines.closes_in(battle_id, closed_at);
+
+    // matches that aren't ongoing are automatically closed
+    if status != BattleStatus::Ongoing {
+        return Err(ErrorKind::BetsClosed(closes_in.min(TimeDelta::zero())).into());
+    }
+
+    // give a little bit of wiggle room to prevent jebaits
+    if closes_in + state.config.server.bet_grace_period < TimeDelta::zero() {
+        return Err(ErrorKind::BetsClosed(closes_in).into());
+    }
+
+    Ok(())
+}
+
+/// Checks that a user can change their wager in a market of a match from
+/// `previous` to `mobiums`.
+///
+/// Run this in the transaction the wager is written in.
+pub async fn check_stake(
+    state: &AppState,
+    user_id: UserId,
+    except: (BattleId, WagerMarket),
+    mobiums: Mobiums,
+    previous: Mobiums,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    // the user's session may be out of date, and mobiums staked on other
+    // wagers can't be wagered again until they are paid out
+    let balance = wager_balance(user_id, Some(except), &mut *conn).await?;
+    // lowering a wager is always fine, even if the user is overcommitted
+    check_wager_bounds(mobiums, balance.available.max(previous))?;
+    if mobiums > previous {
+        check_bailout_cooldown(state, user_id, mobiums, balance.mobiums, now, &mut *conn).await?;
+        check_wager_limit(state, user_id, mobiums, now, &mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Checks that a wager is between 0 and the mobiums the user has.
+pub fn check_wager_bounds(mobiums: Mobiums, balance: Mobiums) -> Result<(), Error> {
+    if mobiums < 0 {
         return Err(ErrorKind::InvalidData("Mobiums must be non-negative".into()).into());
     }
 
-    if update_wager.mobiums > user.mobiums {
+    if mobiums > balance {
         return Err(ErrorKind::NotEnoughMobiums.into());
     }
 
-    let now = Utc::now();
+    Ok(())
+}
 
-    let mut conn = state.db.acquire().await?;
+/// Checks that a user still cooling down from a bailout isn't wagering more
+/// than they are allowed to.
+async fn check_bailout_cooldown(
+    state: &AppState,
+    user_id: UserId,
+    mobiums: Mobiums,
+    balance: Mobiums,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let Some((ends_at, max_mobiums)) = bailout_cooldown(state, user_id, balance, now, conn).await?
+    else {
+        return Ok(());
+    };
 
-    // Fetch the wager bot, if we can.
-    let wager_bot = if state.config.server.bot.enabled {
-        Some(get_wager_bot(&state.config.server.bot, &mut *conn).await?)
-    } else {
-        None
+    if mobiums > max_mobiums {
+        return Err(ErrorKind::BailoutCooldown {
+            ends_at,
+            max_mobiums,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Finds when a user's bailout cooldown ends, and the most they can wager
+/// until then.
+///
+/// Returns `None` if the user isn't cooling down.
+async fn bailout_cooldown(
+    state: &AppState,
+    user_id: UserId,
+    balance: Mobiums,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Option<(DateTime<Utc>, Mobiums)>, Error> {
+    let cooldown = &state.config.server.bailout_cooldown;
+    if !cooldown.enabled {
+        return Ok(None);
+    }
+
+    let (cooldown_until,) = sqlx::query_as::<_, (Option<DateTime<Utc>>,)>(
+        r#"
+        SELECT bailout_cooldown_until
+        FROM user
+        WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(cooldown_until
+        .filter(|ends_at| *ends_at > now)
+        .map(|ends_at| (ends_at, cooldown.max_wager(balance))))
+}
+
+/// Checks that a new or unverified user isn't wagering more than they are
+/// allowed to.
+async fn check_wager_limit(
+    state: &AppState,
+    user_id: UserId,
+    mobiums: Mobiums,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let Some((max_mobiums, ends_at)) = wager_limit(state, user_id, now, conn).await? else {
+        return Ok(());
     };
 
-    let mut tx = conn.begin().await?;
+    if mobiums > max_mobiums {
+        return Err(ErrorKind::WagerLimited {
+            max_mobiums,
+            ends_at,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Finds the most a new or unverified user can wager on a match, and when
+/// their account stops being new, if that is the limit.
+///
+/// Returns `None` if the user isn't limited.
+async fn wager_limit(
+    state: &AppState,
+    user_id: UserId,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Option<(Mobiums, Option<DateTime<Utc>>)>, Error> {
+    let limits = &state.config.server.anti_abuse.wager_limits;
+    if limits.new_account_max_wager.is_none() && limits.unverified_max_wager().is_none() {
+        return Ok(None);
+    }
+
+    let (inserted_at, guild_verified_at) =
+        sqlx::query_as::<_, (DateTime<Utc>, Option<DateTime<Utc>>)>(
+            r#"
+            SELECT inserted_at, guild_verified_at
+            FROM user
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let new_until = inserted_at + limits.new_account_age;
+    let new_account = limits
+        .new_account_max_wager
+        .filter(|_| new_until > now)
+        .map(|max_mobiums| (Mobiums(max_mobiums), Some(new_until)));
+    let unverified = limits
+        .unverified_max_wager()
+        .filter(|_| guild_verified_at.is_none())
+        .map(|max_mobiums| (Mobiums(max_mobiums), None));
+
+    // the lower limit is the one that matters
+    Ok(match (new_account, unverified) {
+        (Some(new_account), Some(unverified)) if unverified.0 < new_account.0 => Some(unverified),
+        (new_account, unverified) => new_account.or(unverified),
+    })
+}
+
+/// A wager written, but not yet announced to the room.
+///
+/// Announce it with [`PlacedWager::announce`] once the transaction it was
+/// written in commits.
+#[derive(Debug)]
+pub struct PlacedWager {
+    pub wager: BattleWager,
+    /// The user's mobiums, now that the wager is placed.
+    pub balance_after_wager: WagerBalance,
+    match_id: String,
+    user_id: UserId,
+    /// The user's mobiums, if they were given a bonus for the wager.
+    bonus_mobiums: Option<i64>,
+    private_to: Option<UserId>,
+    visibility: Visibility,
+    /// The wager bot's wagers that changed with the wager.
+    bot_wagers: Vec<BattleWager>,
+    /// Whether the wager was a retry of one already placed, which was
+    /// announced the first time around.
+    replayed: bool,
+}
+
+impl PlacedWager {
+    /// Notifies the room of the wager, returning it.
+    pub fn announce(self, state: &AppState) -> WagerReceipt {
+        if self.replayed {
+            return WagerReceipt {
+                wager: self.wager,
+                balance_after_wager: self.balance_after_wager,
+            };
+        }
+
+        if let Some(mobiums) = self.bonus_mobiums {
+            state.room.send_mobiums_change(
+                self.user_id,
+                MobiumsChange {
+                    mobiums,
+                    bailout: false,
+                    streak: None,
+                },
+            );
+        }
+
+        // update clients, unless the match is unlisted
+        if self.visibility.is_public() {
+            for bot_wager in self.bot_wagers {
+                state
+                    .room
+                    .send_wager_update(&self.match_id, bot_wager, None);
+            }
+
+            state
+                .room
+                .send_wager_update(&self.match_id, self.wager.clone(), self.private_to);
+        }
+
+        WagerReceipt {
+            wager: self.wager,
+            balance_after_wager: self.balance_after_wager,
+        }
+    }
+}
+
+/// Writes a wager for a user.
+///
+/// `wager_bot` is the wager bot, if it is enabled. Run this in a transaction.
+pub async fn write_wager(
+    state: &AppState,
+    wager_bot: Option<&WagerBotUser>,
+    request: &WagerRequest,
+    conn: &mut SqliteConnection,
+) -> Result<PlacedWager, Error> {
+    #[derive(FromRow)]
+    struct BattleQuery {
+        id: BattleId,
+        status: BattleStatus,
+        closed_at: DateTime<Utc>,
+        #[sqlx(try_from = "u8")]
+        odds_mode: OddsMode,
+        #[sqlx(try_from = "u8")]
+        visibility: Visibility,
+        disable_bot: bool,
+    }
+
+    let user = &request.user;
+    let audit = &request.audit;
+    let (match_id, victor) = (request.match_id, request.victor);
+
+    let now = Utc::now();
 
     let battle = sqlx::query_as::<_, BattleQuery>(
         r#"
         SELECT
-            id, status, closed_at
+            id, status, closed_at, odds_mode, visibility, disable_bot
         FROM
             battle
         WHERE
@@ -276,23 +834,50 @@ pub async fn create(
         "#,
     )
     .bind(match_id.hyphenated().to_string())
-    .fetch_optional(&mut *tx)
+    .fetch_optional(&mut *conn)
     .await?;
 
     let Some(battle) = battle else {
         return Err(Error::not_found(format!("Match {} not found", match_id)));
     };
 
-    // matches that aren't ongoing are automatically closed
-    if battle.status != BattleStatus::Ongoing {
-        return Err(ErrorKind::InvalidData("Bets have closed for this match.".into()).into());
-    }
+    // retries get the original receipt, even if bets have closed since
+    if let Some(client_token) = &request.client_token {
+        let placed = sqlx::query_as::<_, (String,)>(
+            r#"
+            SELECT receipt
+            FROM wager_token
+            WHERE user_id = $1 AND match_id = $2 AND market = $4 AND client_token = $3
+            "#,
+        )
+        .bind(user.identity())
+        .bind(battle.id)
+        .bind(client_token)
+        .bind(WagerMarket::Winner)
+        .fetch_optional(&mut *conn)
+        .await?;
 
-    // give a little bit of wiggle room to prevent jebaits
-    if battle.closed_at + Duration::seconds(3) < now {
-        return Err(ErrorKind::InvalidData("Bets have closed for this match.".into()).into());
+        if let Some((receipt,)) = placed {
+            let receipt = serde_json::from_str::<WagerReceipt>(&receipt).map_err(Error::new)?;
+
+            audit.note(format!("replayed wager on {}", match_id));
+
+            return Ok(PlacedWager {
+                wager: receipt.wager,
+                balance_after_wager: receipt.balance_after_wager,
+                match_id: match_id.hyphenated().to_string(),
+                user_id: user.identity(),
+                bonus_mobiums: None,
+                private_to: None,
+                visibility: battle.visibility,
+                bot_wagers: Vec::new(),
+                replayed: true,
+            });
+        }
     }
 
+    check_bets_open(state, battle.id, battle.status, battle.closed_at)?;
+
     // check if the user's team actually exists
     let (team_count,) = sqlx::query_as::<_, (i32,)>(
         r#"
@@ -302,26 +887,123 @@ pub async fn create(
         "#,
     )
     .bind(battle.id)
-    .bind(u8::from(update_wager.victor))
-    .fetch_one(&mut *tx)
+    .bind(victor)
+    .fetch_one(&mut *conn)
     .await?;
 
     if team_count <= 0 {
-        return Err(ErrorKind::InvalidData(format!(
-            "Team {:?} has no participants",
-            update_wager.victor
-        ))
-        .into());
+        return Err(
+            ErrorKind::InvalidData(format!("Team {:?} has no participants", victor)).into(),
+        );
+    }
+
+    // keep track of what the wager was for disputes
+    let previous = sqlx::query_as::<_, (PlayerTeam, i64)>(
+        r#"
+        SELECT victor, mobiums
+        FROM wager
+        WHERE user_id = $1 AND match_id = $2 AND market = $3
+        "#,
+    )
+    .bind(user.identity())
+    .bind(battle.id)
+    .bind(WagerMarket::Winner)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    // rules skip matches the user already wagered on, even by moments
+    if request.auto_bet && previous.is_some() {
+        return Err(ErrorKind::InvalidData("Already wagered on this match".into()).into());
     }
 
+    // the batch transaction holds the write lock, so nothing else (settling,
+    // voiding, merging accounts) can change the wager between reading it
+    // here and writing it back
+    let mobiums = match request.amount {
+        WagerAmount::Mobiums(mobiums) => mobiums,
+        WagerAmount::Adjust(adjust) => {
+            let current = match previous {
+                Some((old_victor, old_mobiums)) if old_victor == victor => Mobiums(old_mobiums),
+                Some((_, old_mobiums)) if old_mobiums > 0 => {
+                    return Err(ErrorKind::InvalidData(
+                        "Wagers can only be adjusted on the team they are on".into(),
+                    )
+                    .into());
+                }
+                _ => Mobiums::ZERO,
+            };
+
+            current
+                .checked_add(adjust)
+                .ok_or_else(|| ErrorKind::InvalidData("Adjustment is out of range".into()))?
+        }
+    };
+
+    let previous_mobiums = previous.map_or(Mobiums::ZERO, |(_, mobiums)| Mobiums(mobiums));
+    check_stake(
+        state,
+        user.identity(),
+        (battle.id, WagerMarket::Winner),
+        mobiums,
+        previous_mobiums,
+        now,
+        &mut *conn,
+    )
+    .await?;
+
+    match previous {
+        Some((old_victor, old_mobiums)) => audit.note(format!(
+            "wager on {}: {:?} {} -> {:?} {}",
+            match_id, old_victor, old_mobiums, victor, mobiums
+        )),
+        None => audit.note(format!("wager on {}: {:?} {}", match_id, victor, mobiums)),
+    }
+
+    // first wagers come with a little something extra
+    let onboarded_mobiums = if mobiums > 0 {
+        grant_onboarding_bonus(
+            user.identity(),
+            state.config.server.onboarding_bonus,
+            &mut *conn,
+        )
+        .await?
+    } else {
+        None
+    };
+
+    if onboarded_mobiums.is_some() {
+        audit.note(format!(
+            "granted onboarding bonus of {}",
+            state.config.server.onboarding_bonus
+        ));
+    }
+
+    // and so do first wagers during an event
+    let promo_bonus = if mobiums > 0 {
+        grant_first_wager_bonus(user.identity(), &mut *conn).await?
+    } else {
+        None
+    };
+
+    if let Some(granted) = &promo_bonus {
+        audit.note(format!(
+            "granted event {} bonus of {}",
+            granted.event_id, granted.bonus
+        ));
+    }
+
+    let bonus_mobiums = promo_bonus
+        .map(|granted| granted.mobiums)
+        .or(onboarded_mobiums);
+
     // update thing
     sqlx::query(
         r#"
         INSERT INTO wager
-            (user_id, match_id, victor, mobiums, inserted_at, updated_at)
+            (user_id, match_id, victor, mobiums, inserted_at, updated_at, sandbox)
         VALUES
-            ($1, $2, $3, $4, $5, $5)
-        ON CONFLICT (user_id, match_id) DO UPDATE
+            ($1, $2, $3, $4, $5, $5, $6)
+        ON CONFLICT (user_id, match_id, market) DO UPDATE
         SET
             victor = $3,
             mobiums = $4,
@@ -330,56 +1012,141 @@ pub async fn create(
     )
     .bind(user.identity())
     .bind(battle.id)
-    .bind(u8::from(update_wager.victor))
-    .bind(update_wager.mobiums)
+    .bind(victor)
+    .bind(mobiums)
     .bind(now)
-    .execute(&mut *tx)
+    .bind(state.config.server.sandbox.enabled)
+    .execute(&mut *conn)
     .await?;
 
+    // counted against the daily cap only if the wager was actually placed
+    if request.auto_bet {
+        sqlx::query(
+            r#"
+            INSERT INTO auto_bet_placement (user_id, match_id, mobiums, inserted_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(user.identity())
+        .bind(battle.id)
+        .bind(mobiums)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    }
+
     // New! Do bot wager if it needs to be added or removed
     // This has to happen in the same transaction to prevent insanity
-    if let Some(wager_bot) = wager_bot {
-        rebalance_automated_wagers(&state, &wager_bot, battle.id, &mut *tx).await?;
-    }
+    let bot_wagers = match wager_bot.filter(|_| !battle.disable_bot) {
+        Some(wager_bot) => {
+            rebalance_automated_wagers(state, wager_bot, battle.id, battle.odds_mode, &mut *conn)
+                .await?
+        }
+        None => Vec::new(),
+    };
 
-    tx.commit().await?;
+    // lock in the odds after the bot had its say, so the user gets to see them
+    let odds = if battle.odds_mode == OddsMode::Locked {
+        Some(lock_odds(battle.id, user.identity(), victor, &mut *conn).await?)
+    } else {
+        None
+    };
 
-    // shuffle csrf after the action is done
-    session.shuffle_csrf().await?;
+    snapshot_pot(battle.id, &mut *conn).await?;
+
+    let balance_after_wager = wager_balance(user.identity(), None, &mut *conn).await?;
+
+    let (show_wagers_publicly,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT show_wagers_publicly
+        FROM user
+        WHERE id = $1
+        "#,
+    )
+    .bind(user.identity())
+    .fetch_one(&mut *conn)
+    .await?;
 
     let wager = BattleWager {
         user: Some(User {
             username: user.username.clone(),
             avatar: user.avatar.clone(),
             display_name: user.display_name.clone(),
-            mobiums: user.mobiums,
+            mobiums: balance_after_wager.mobiums,
             mobiums_gained: user.mobiums_gained,
             mobiums_lost: user.mobiums_lost,
+            streak: user.streak,
             flags: user.flags,
         }),
-        victor: update_wager.victor,
-        mobiums: update_wager.mobiums,
+        victor,
+        mobiums,
+        odds,
+        is_bot: user.flags.contains(UserFlags::AUTOMATED_USER),
         updated_at: now,
     };
 
-    // update clients
-    state.room.send_wager_update(wager.clone());
+    // remember the wager, in case the client retries it
+    if let Some(client_token) = &request.client_token {
+        let receipt = WagerReceipt {
+            wager: wager.clone(),
+            balance_after_wager,
+        };
 
-    Ok(AppJson(wager))
+        sqlx::query(
+            r#"
+            INSERT INTO wager_token
+                (user_id, match_id, market, client_token, receipt, inserted_at)
+            VALUES
+                ($1, $2, $6, $3, $4, $5)
+            ON CONFLICT (user_id, match_id, market) DO UPDATE
+            SET
+                client_token = $3,
+                receipt = $4,
+                inserted_at = $5
+            "#,
+        )
+        .bind(user.identity())
+        .bind(battle.id)
+        .bind(client_token)
+        .bind(serde_json::to_string(&receipt).map_err(Error::new)?)
+        .bind(now)
+        .bind(WagerMarket::Winner)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(PlacedWager {
+        wager,
+        balance_after_wager,
+        match_id: match_id.hyphenated().to_string(),
+        user_id: user.identity(),
+        bonus_mobiums,
+        private_to: (!show_wagers_publicly).then(|| user.identity()),
+        visibility: battle.visibility,
+        bot_wagers,
+        replayed: false,
+    })
 }
 
+/// Adds or removes the wager bot's wager, so no team goes without love.
+///
+/// Returns the bot's wagers that changed, to be announced once the
+/// transaction commits.
 async fn rebalance_automated_wagers(
     state: &AppState,
-    wager_bot: &UserSchema,
-    battle_id: i32,
+    wager_bot: &WagerBotUser,
+    battle_id: BattleId,
+    odds_mode: OddsMode,
     conn: &mut SqliteConnection,
-) -> Result<(), Error> {
+) -> Result<Vec<BattleWager>, Error> {
     #[derive(Debug, FromRow)]
     struct WagerCountQuery {
-        #[sqlx(try_from = "u8")]
         victor: PlayerTeam,
         wager_count: i32,
         bot_wagers: i32,
+        /// The mobiums on the team, not counting the bot's.
+        pot: i64,
+        bot_mobiums: i64,
     }
 
     let now = Utc::now();
@@ -389,12 +1156,14 @@ async fn rebalance_automated_wagers(
         WITH subq AS (
             SELECT *, w.user_id = $2 AS is_bot_wager
             FROM wager w
-            WHERE w.match_id = $1
+            WHERE w.match_id = $1 AND w.market = $3
         )
         SELECT
             p.team AS victor,
             SUM(w.mobiums > 0) AS wager_count,
-            SUM(w.is_bot_wager AND w.mobiums > 0) AS bot_wagers
+            SUM(w.is_bot_wager AND w.mobiums > 0) AS bot_wagers,
+            IFNULL(SUM(CASE WHEN w.is_bot_wager THEN 0 ELSE w.mobiums END), 0) AS pot,
+            IFNULL(SUM(CASE WHEN w.is_bot_wager THEN w.mobiums ELSE 0 END), 0) AS bot_mobiums
         FROM
             (
                 SELECT DISTINCT p.team
@@ -409,9 +1178,15 @@ async fn rebalance_automated_wagers(
     )
     .bind(battle_id)
     .bind(wager_bot.id)
+    .bind(WagerMarket::Winner)
     .fetch_all(&mut *conn)
     .await?;
 
+    let mut bot_user = User::from(&wager_bot.user);
+    bot_user.avatar = state.avatar_url(&bot_user.username, bot_user.avatar.take());
+
+    let mut changed = Vec::new();
+
     // if there is only one team without love, give them some love!
     let empty_wagers = wager_counts
         .iter()
@@ -420,16 +1195,25 @@ async fn rebalance_automated_wagers(
     if empty_wagers.len() == 1 {
         let wager_info = empty_wagers.iter().next().expect("len check");
 
-        if wager_info.bot_wagers <= 0 {
-            let mobiums = state.config.server.bot.wager_amount;
+        let mobiums = match wager_bot.strategy {
+            BotStrategy::Fixed => wager_bot.wager_amount,
+            BotStrategy::MatchPot => wager_counts
+                .iter()
+                .filter(|q| q.victor != wager_info.victor)
+                .map(|q| q.pot)
+                .sum::<i64>()
+                .max(wager_bot.wager_amount),
+        };
 
+        // the bot's wager may already be right
+        if wager_info.bot_mobiums != mobiums {
             sqlx::query(
                 r#"
                 INSERT INTO wager
-                    (user_id, match_id, victor, mobiums, inserted_at, updated_at)
+                    (user_id, match_id, victor, mobiums, inserted_at, updated_at, sandbox)
                 VALUES
-                    ($1, $2, $3, $4, $5, $5)
-                ON CONFLICT DO UPDATE
+                    ($1, $2, $3, $4, $5, $5, $6)
+                ON CONFLICT (user_id, match_id, market) DO UPDATE
                 SET
                     victor = $3,
                     mobiums = $4,
@@ -438,16 +1222,25 @@ async fn rebalance_automated_wagers(
             )
             .bind(wager_bot.id)
             .bind(battle_id)
-            .bind(u8::from(wager_info.victor))
+            .bind(wager_info.victor)
             .bind(mobiums)
             .bind(now)
+            .bind(state.config.server.sandbox.enabled)
             .execute(&mut *conn)
             .await?;
 
-            state.room.send_wager_update(BattleWager {
-                user: Some(User::from(wager_bot)),
-                mobiums,
+            let odds = if odds_mode == OddsMode::Locked {
+                Some(lock_odds(battle_id, wager_bot.id, wager_info.victor, &mut *conn).await?)
+            } else {
+                None
+            };
+
+            changed.push(BattleWager {
+                user: Some(bot_user.clone()),
+                mobiums: mobiums.into(),
                 victor: wager_info.victor,
+                odds,
+                is_bot: true,
                 updated_at: now,
             });
         }
@@ -462,23 +1255,26 @@ async fn rebalance_automated_wagers(
                 r#"
                 UPDATE wager
                 SET mobiums = 0, updated_at = $3
-                WHERE user_id = $1 AND match_id = $2
+                WHERE user_id = $1 AND match_id = $2 AND market = $4
                 "#,
             )
             .bind(wager_bot.id)
             .bind(battle_id)
             .bind(now)
+            .bind(WagerMarket::Winner)
             .execute(&mut *conn)
             .await?;
 
-            state.room.send_wager_update(BattleWager {
-                user: Some(User::from(wager_bot)),
-                mobiums: 0,
+            changed.push(BattleWager {
+                user: Some(bot_user.clone()),
+                mobiums: Mobiums::ZERO,
                 victor: wager_info.victor,
+                odds: None,
+                is_bot: true,
                 updated_at: now,
             });
         }
     }
 
-    Ok(())
+    Ok(changed)
 }
@@ -3,24 +3,179 @@
 //! Since chat is already tracked by clients in logs, it's only fair they can
 //! be stored persistently as long as they can't be accessed anonymously.
 
-use axum::{Extension, extract::State};
+use std::collections::HashMap;
 
-use chrono::Utc;
-use ring_channel_model::{chat::Message, request::chat::CreateChatMessage};
+use axum::{
+    Extension,
+    extract::{Path, State},
+};
+
+use chrono::{DateTime, Utc};
+use garde::Validate;
+use http::StatusCode;
+use ring_channel_model::{
+    Player, PlayerShortId, User, UserId,
+    chat::{Message, Reaction, ReactionCount},
+    message::server::MessageReaction,
+    page::ListFormat,
+    request::chat::{CreateChatMessage, ReactToMessage, UpdateChatMessage},
+    user::PenaltyKind,
+};
+use serde::Deserialize;
+use sqlx::{FromRow, SqliteConnection};
 
 use crate::{
-    app::{AppJson, AppState, Model, Payload},
+    app::{
+        AppForm, AppGarde, AppJson, AppState, Model, Payload,
+        page::{Listing, parse_cursor, trim_page},
+    },
+    audit::Audit,
     auth::api_key::ServerAuthentication,
-    error::Error,
+    error::{Error, ErrorKind},
     player::{get_player, mmr},
+    session::{Session, SessionUser},
+    user::{UserSchema, penalty::check_penalty},
 };
 
+/// The longest a chat message can be, in characters.
+pub const MAX_MESSAGE_LENGTH: usize = 255;
+
+/// A query for [`list`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct ListMessagesQuery {
+    /// How many messages to list.
+    #[garde(range(min = 1, max = 100))]
+    #[serde(default = "list_messages_count_default")]
+    pub count: i32,
+    /// Only list messages older than the message with this id.
+    #[garde(skip)]
+    pub before: Option<i32>,
+    /// The cursor of the page to list. Takes precedence over `before`.
+    #[garde(length(max = 64))]
+    pub cursor: Option<String>,
+    /// Whether to list the messages in a page.
+    #[garde(skip)]
+    #[serde(default)]
+    pub format: ListFormat,
+}
+
+fn list_messages_count_default() -> i32 {
+    50
+}
+
+/// Lists the chat history, newest first.
+///
+/// Only users can read the history, so chat isn't archived for everyone to
+/// see.
+pub async fn list<T>(
+    _user: SessionUser,
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<ListMessagesQuery>>,
+) -> Result<AppJson<Listing<Message>>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    let before = match query.cursor.as_deref() {
+        Some(cursor) => Some(parse_cursor::<i32>(cursor)?),
+        None => query.before,
+    };
+
+    #[derive(FromRow)]
+    struct MessageQuery {
+        id: i32,
+        short_id: Option<PlayerShortId>,
+        user_id: Option<UserId>,
+        content: String,
+        inserted_at: DateTime<Utc>,
+        edited_at: Option<DateTime<Utc>>,
+    }
+
+    let mut conn = state.read_db().acquire().await?;
+
+    let mut rows = sqlx::query_as::<_, MessageQuery>(
+        r#"
+        SELECT m.id, p.short_id, m.user_id, m.content, m.inserted_at, m.edited_at
+        FROM message m
+        LEFT JOIN player p ON p.id = m.player_id
+        WHERE $1 IS NULL OR m.id < $1
+        ORDER BY m.id DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(before)
+    .bind(query.count + 1)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let has_more = trim_page(&mut rows, query.count);
+    let next_cursor = rows
+        .last()
+        .filter(|_| has_more)
+        .map(|row| row.id.to_string());
+
+    let mut reactions = match (rows.last(), rows.first()) {
+        (Some(oldest), Some(newest)) => reaction_counts(oldest.id, newest.id, &mut conn).await?,
+        _ => HashMap::new(),
+    };
+
+    // the same few people tend to do most of the talking
+    let mut players = HashMap::<PlayerShortId, Option<Player>>::new();
+    let mut users = HashMap::<UserId, Option<User>>::new();
+
+    let mut messages = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        let player = match row.short_id {
+            Some(short_id) => match players.get(&short_id) {
+                Some(player) => player.clone(),
+                None => {
+                    let player = get_player(&short_id, &mut conn)
+                        .await?
+                        .map(|player| player.normalize(&model))
+                        .transpose()?;
+                    players.insert(short_id, player.clone());
+                    player
+                }
+            },
+            None => None,
+        };
+
+        let user = match row.user_id {
+            Some(user_id) => match users.get(&user_id) {
+                Some(user) => user.clone(),
+                None => {
+                    let user = get_user(user_id, &state, &mut conn).await?;
+                    users.insert(user_id, user.clone());
+                    user
+                }
+            },
+            None => None,
+        };
+
+        messages.push(Message {
+            id: row.id,
+            player,
+            user,
+            content: row.content,
+            created_at: row.inserted_at.format("%+").to_string(),
+            edited_at: row
+                .edited_at
+                .map(|edited_at| edited_at.format("%+").to_string()),
+            reactions: reactions.remove(&row.id).unwrap_or_default(),
+        });
+    }
+
+    Ok(AppJson(Listing::new(query.format, messages, next_cursor)))
+}
+
 /// Processes a chat message from the server.
 pub async fn create<T>(
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
-    _auth_guard: ServerAuthentication,
-    Payload(request): Payload<CreateChatMessage>,
+    auth: ServerAuthentication,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateChatMessage>>,
 ) -> Result<AppJson<Message>, Error>
 where
     T: mmr::Model + 'static,
@@ -36,22 +191,28 @@ where
             f.ok_or_else(|| Error::not_found(format!("Player {} not found", request.player_id)))
         })?;
 
-    sqlx::query(
+    let (id,) = sqlx::query_as::<_, (i32,)>(
         r#"
-        INSERT INTO message (player_id, content, inserted_at)
-        VALUES ($1, $2, $3)
+        INSERT INTO message (player_id, server_id, content, inserted_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
         "#,
     )
     .bind(player.id)
+    .bind(auth.id)
     .bind(&request.content)
     .bind(now)
-    .execute(&mut *conn)
+    .fetch_one(&mut *conn)
     .await?;
 
     let message = Message {
-        player: player.normalize(&model)?,
+        id,
+        player: Some(player.normalize(&model)?),
+        user: None,
         content: request.content,
         created_at: now.format("%+").to_string(),
+        edited_at: None,
+        reactions: Vec::new(),
     };
 
     // log chat message
@@ -59,3 +220,379 @@ where
 
     Ok(AppJson(message))
 }
+
+/// Sends a chat message from a user, notifying the room.
+pub async fn send_user_message(
+    state: &AppState,
+    user: &SessionUser,
+    content: String,
+) -> Result<Message, Error> {
+    let now = Utc::now();
+
+    let content = content.trim();
+
+    let mut conn = state.db.acquire().await?;
+    check_penalty(user.identity(), PenaltyKind::ChatMute, &mut conn).await?;
+
+    if content.is_empty() {
+        return Err(ErrorKind::InvalidData("Message cannot be empty".into()).into());
+    }
+    if content.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(ErrorKind::InvalidData(format!(
+            "Message cannot be longer than {} characters",
+            MAX_MESSAGE_LENGTH
+        ))
+        .into());
+    }
+
+    let (id,) = sqlx::query_as::<_, (i32,)>(
+        r#"
+        INSERT INTO message (user_id, content, inserted_at)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(user.identity())
+    .bind(content)
+    .bind(now)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let message = Message {
+        id,
+        player: None,
+        user: Some(User::clone(user)),
+        content: content.to_owned(),
+        created_at: now.format("%+").to_string(),
+        edited_at: None,
+        reactions: Vec::new(),
+    };
+
+    state.room.send_message(message.clone()).await;
+
+    Ok(message)
+}
+
+/// Edits a chat message crossposted by the server.
+pub async fn update<T>(
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    auth: ServerAuthentication,
+    audit: Audit,
+    Path((message_id,)): Path<(i32,)>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateChatMessage>>,
+) -> Result<AppJson<Message>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    #[derive(FromRow)]
+    struct MessageQuery {
+        inserted_at: DateTime<Utc>,
+        short_id: Option<PlayerShortId>,
+    }
+
+    let now = Utc::now();
+
+    let mut conn = state.db.acquire().await?;
+
+    check_message_owner(message_id, &auth, &mut conn).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE message
+        SET content = $2, edited_at = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(message_id)
+    .bind(&request.content)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    let message = sqlx::query_as::<_, MessageQuery>(
+        r#"
+        SELECT m.inserted_at, p.short_id
+        FROM message m
+        LEFT JOIN player p ON p.id = m.player_id
+        WHERE m.id = $1
+        "#,
+    )
+    .bind(message_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let player = match message.short_id {
+        Some(short_id) => get_player(&short_id, &mut conn)
+            .await?
+            .map(|player| player.normalize(&model))
+            .transpose()?,
+        None => None,
+    };
+
+    let reactions = reaction_counts(message_id, message_id, &mut conn)
+        .await?
+        .remove(&message_id)
+        .unwrap_or_default();
+
+    audit.note(format!("edited message {}", message_id));
+
+    let message = Message {
+        id: message_id,
+        player,
+        user: None,
+        content: request.content,
+        created_at: message.inserted_at.format("%+").to_string(),
+        edited_at: Some(now.format("%+").to_string()),
+        reactions,
+    };
+
+    state.room.edit_message(message.clone());
+
+    Ok(AppJson(message))
+}
+
+/// Deletes a chat message crossposted by the server.
+pub async fn delete(
+    State(state): State<AppState>,
+    auth: ServerAuthentication,
+    audit: Audit,
+    Path((message_id,)): Path<(i32,)>,
+) -> Result<StatusCode, Error> {
+    let mut conn = state.db.acquire().await?;
+
+    check_message_owner(message_id, &auth, &mut conn).await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM message
+        WHERE id = $1
+        "#,
+    )
+    .bind(message_id)
+    .execute(&mut *conn)
+    .await?;
+
+    audit.note(format!("deleted message {}", message_id));
+
+    state.room.delete_message(message_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reacts to a chat message as the current user.
+///
+/// Reacting twice with the same reaction does nothing.
+pub async fn react(
+    user: SessionUser,
+    mut session: Session,
+    State(state): State<AppState>,
+    Path((message_id,)): Path<(i32,)>,
+    AppGarde(Payload(request)): AppGarde<Payload<ReactToMessage>>,
+) -> Result<AppJson<Vec<ReactionCount>>, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let mut conn = state.db.acquire().await?;
+    check_penalty(user.identity(), PenaltyKind::ChatMute, &mut conn).await?;
+    check_message_exists(message_id, &mut conn).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO message_reaction (message_id, user_id, reaction, inserted_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(message_id)
+    .bind(user.identity())
+    .bind(u8::from(request.reaction))
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+
+    let reactions = send_reactions(message_id, &state, &mut conn).await?;
+
+    session.shuffle_csrf().await?;
+
+    Ok(AppJson(reactions))
+}
+
+/// Takes back the current user's reaction to a chat message.
+pub async fn unreact(
+    user: SessionUser,
+    mut session: Session,
+    State(state): State<AppState>,
+    Path((message_id,)): Path<(i32,)>,
+    AppGarde(Payload(request)): AppGarde<Payload<ReactToMessage>>,
+) -> Result<AppJson<Vec<ReactionCount>>, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let mut conn = state.db.acquire().await?;
+    check_message_exists(message_id, &mut conn).await?;
+
+    let deleted = sqlx::query(
+        r#"
+        DELETE FROM message_reaction
+        WHERE message_id = $1 AND user_id = $2 AND reaction = $3
+        "#,
+    )
+    .bind(message_id)
+    .bind(user.identity())
+    .bind(u8::from(request.reaction))
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    if deleted == 0 {
+        return Err(Error::not_found(format!(
+            "You haven't reacted to message {} with that",
+            message_id
+        )));
+    }
+
+    let reactions = send_reactions(message_id, &state, &mut conn).await?;
+
+    session.shuffle_csrf().await?;
+
+    Ok(AppJson(reactions))
+}
+
+/// Counts the reactions to a message, and notifies the room of them.
+async fn send_reactions(
+    message_id: i32,
+    state: &AppState,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<ReactionCount>, Error> {
+    let reactions = reaction_counts(message_id, message_id, conn)
+        .await?
+        .remove(&message_id)
+        .unwrap_or_default();
+
+    state.room.send_message_reaction(MessageReaction {
+        id: message_id,
+        reactions: reactions.clone(),
+    });
+
+    Ok(reactions)
+}
+
+/// Counts the reactions to every message with an id from `from` to `to`.
+async fn reaction_counts(
+    from: i32,
+    to: i32,
+    conn: &mut SqliteConnection,
+) -> Result<HashMap<i32, Vec<ReactionCount>>, Error> {
+    #[derive(FromRow)]
+    struct ReactionQuery {
+        message_id: i32,
+        #[sqlx(try_from = "u8")]
+        reaction: Reaction,
+        count: i32,
+    }
+
+    let rows = sqlx::query_as::<_, ReactionQuery>(
+        r#"
+        SELECT message_id, reaction, COUNT(*) AS count
+        FROM message_reaction
+        WHERE message_id BETWEEN $1 AND $2
+        GROUP BY message_id, reaction
+        ORDER BY message_id ASC, reaction ASC
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut counts = HashMap::<i32, Vec<ReactionCount>>::new();
+    for row in rows {
+        counts
+            .entry(row.message_id)
+            .or_default()
+            .push(ReactionCount {
+                reaction: row.reaction,
+                count: row.count,
+            });
+    }
+
+    Ok(counts)
+}
+
+async fn get_user(
+    user_id: UserId,
+    state: &AppState,
+    conn: &mut SqliteConnection,
+) -> Result<Option<User>, Error> {
+    let user = sqlx::query_as::<_, UserSchema>(
+        r#"
+        SELECT
+            id, username, avatar, display_name, mobiums, mobiums_gained,
+            mobiums_lost, streak, flags
+        FROM user
+        WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|user| {
+        let mut user = User::from(user);
+        user.avatar = state.avatar_url(&user.username, user.avatar.take());
+        user
+    });
+
+    Ok(user)
+}
+
+async fn check_message_exists(message_id: i32, conn: &mut SqliteConnection) -> Result<(), Error> {
+    let (exists,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM message WHERE id = $1)
+        "#,
+    )
+    .bind(message_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if exists {
+        Ok(())
+    } else {
+        Err(Error::not_found(format!(
+            "Message {} not found",
+            message_id
+        )))
+    }
+}
+
+/// Checks that a message was crossposted by the server.
+async fn check_message_owner(
+    message_id: i32,
+    auth: &ServerAuthentication,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let server_id = sqlx::query_as::<_, (Option<i32>,)>(
+        r#"
+        SELECT server_id
+        FROM message
+        WHERE id = $1
+        "#,
+    )
+    .bind(message_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match server_id {
+        None => Err(Error::not_found(format!(
+            "Message {} not found",
+            message_id
+        ))),
+        Some((Some(server_id),)) if server_id == auth.id => Ok(()),
+        Some(_) => Err(ErrorKind::Forbidden.into()),
+    }
+}
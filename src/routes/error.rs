@@ -0,0 +1,19 @@
+//! Error catalogue.
+
+use ring_channel_model::error::ErrorCode;
+
+use crate::{app::AppJson, error::ERROR_CODES};
+
+/// Lists every error code the API responds with.
+pub async fn list() -> AppJson<Vec<ErrorCode>> {
+    AppJson(
+        ERROR_CODES
+            .iter()
+            .map(|&(code, status, description)| ErrorCode {
+                code: code.to_owned(),
+                status: status.as_u16(),
+                description: description.to_owned(),
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,22 @@
+//! Promotional event routes.
+
+use axum::extract::State;
+
+use chrono::Utc;
+
+use ring_channel_model::event::PromoEvent;
+
+use crate::{
+    app::{AppJson, AppState},
+    error::Error,
+    event::active_events,
+};
+
+/// Lists the events running right now, soonest to end first.
+pub async fn active(State(state): State<AppState>) -> Result<AppJson<Vec<PromoEvent>>, Error> {
+    let mut conn = state.read_db().acquire().await?;
+
+    let events = active_events(Utc::now(), &mut conn).await?;
+
+    Ok(AppJson(events))
+}
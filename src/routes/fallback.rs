@@ -0,0 +1,21 @@
+//! Fallbacks for requests that don't match a route.
+
+use http::{Method, Uri};
+
+use crate::error::{Error, ErrorKind};
+
+/// Responds to requests for a path that doesn't exist.
+pub async fn not_found(uri: Uri) -> Error {
+    Error::not_found(format!("No route for {}", uri.path()))
+}
+
+/// Responds to requests with a method the path doesn't support.
+///
+/// axum fills in the `Allow` header.
+pub async fn method_not_allowed(method: Method, uri: Uri) -> Error {
+    Error::from(ErrorKind::MethodNotAllowed).with_message(format!(
+        "{} is not allowed on {}",
+        method,
+        uri.path()
+    ))
+}
@@ -0,0 +1,93 @@
+//! Health probes.
+//!
+//! These are mounted outside of the session middleware, so load balancers
+//! don't create sessions when hitting them.
+
+use std::collections::BTreeMap;
+
+use axum::{extract::State, response::IntoResponse};
+
+use http::StatusCode;
+
+use serde::Serialize;
+
+use crate::app::{AppJson, AppState};
+
+/// Response of the readiness probe.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// Whether the database is reachable.
+    pub database: bool,
+    /// How many migrations have not been applied yet.
+    pub pending_migrations: Option<usize>,
+    /// Whether the job scheduler is running.
+    pub scheduler: bool,
+    /// Whether each background task is healthy, by name.
+    pub tasks: BTreeMap<String, bool>,
+    /// Whether the read replica is healthy, if there is one.
+    ///
+    /// Reads fall back to the primary, so this doesn't affect readiness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replica: Option<bool>,
+}
+
+impl Readiness {
+    /// Checks if the server is ready to serve requests.
+    pub fn is_ready(&self) -> bool {
+        self.database
+            && self.pending_migrations == Some(0)
+            && self.scheduler
+            && self.tasks.values().all(|healthy| *healthy)
+    }
+}
+
+/// Liveness probe.
+///
+/// Always succeeds if the server can respond.
+pub async fn live() -> StatusCode {
+    StatusCode::NO_CONTENT
+}
+
+/// Readiness probe.
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    let database = match sqlx::query("SELECT 1").execute(&state.db).await {
+        Ok(_) => true,
+        Err(err) => {
+            tracing::warn!(%err, "readiness check failed to reach database");
+            false
+        }
+    };
+
+    let pending_migrations = if database {
+        match crate::health::pending_migrations(&state.db).await {
+            Ok(pending) => Some(pending),
+            Err(err) => {
+                tracing::warn!(%err, "readiness check failed to check migrations");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let readiness = Readiness {
+        database,
+        pending_migrations,
+        scheduler: state.health.scheduler_running(),
+        tasks: state
+            .tasks
+            .health()
+            .into_iter()
+            .map(|task| (task.name, task.healthy))
+            .collect(),
+        replica: state.replica.replica_healthy(),
+    };
+
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, AppJson(readiness))
+}
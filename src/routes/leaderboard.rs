@@ -0,0 +1,89 @@
+//! Leaderboard routes.
+
+use axum::extract::State;
+
+use garde::Validate;
+
+use ring_channel_model::{User, page::ListFormat, user::UserFlags};
+
+use serde::Deserialize;
+
+use crate::{
+    app::{
+        AppForm, AppGarde, AppJson, AppState,
+        page::{Listing, parse_cursor, trim_page},
+    },
+    error::Error,
+    user::UserSchema,
+};
+
+/// A query for [`show`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct LeaderboardQuery {
+    /// How many users to list.
+    #[garde(range(min = 1, max = 100))]
+    #[serde(default = "leaderboard_count_default")]
+    pub count: i32,
+    /// The cursor of the page to list.
+    #[garde(length(max = 64))]
+    pub cursor: Option<String>,
+    /// Whether to list the users in a page.
+    #[garde(skip)]
+    #[serde(default)]
+    pub format: ListFormat,
+}
+
+fn leaderboard_count_default() -> i32 {
+    25
+}
+
+/// Lists the users with the most mobiums.
+///
+/// Bots and users who have not picked a username are left out.
+pub async fn show(
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<LeaderboardQuery>>,
+) -> Result<AppJson<Listing<User>>, Error> {
+    // standings shift too much to key pages on, so the cursor is a rank
+    let offset = query
+        .cursor
+        .as_deref()
+        .map(parse_cursor::<u32>)
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut users = sqlx::query_as::<_, UserSchema>(
+        r#"
+        SELECT
+            id, username, avatar, display_name, mobiums, mobiums_gained,
+            mobiums_lost, streak, flags
+        FROM user
+        WHERE
+            NOT flags & $1
+            AND username IS NOT NULL
+            AND merged_into IS NULL
+        ORDER BY mobiums DESC, id ASC
+        LIMIT $2 OFFSET $3
+        "#,
+    )
+    .bind(i32::from(UserFlags::AUTOMATED_USER))
+    .bind(query.count + 1)
+    .bind(offset)
+    .fetch_all(state.read_db())
+    .await?;
+
+    let has_more = trim_page(&mut users, query.count);
+    let next_cursor = has_more.then(|| (offset + users.len() as u32).to_string());
+
+    let users = users
+        .into_iter()
+        .map(|user| {
+            let mut user = User::from(user);
+            user.avatar = state.avatar_url(&user.username, user.avatar.take());
+            user
+        })
+        .collect();
+
+    Ok(AppJson(Listing::new(query.format, users, next_cursor)))
+}
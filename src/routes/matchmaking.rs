@@ -0,0 +1,99 @@
+//! Matchmaking routes.
+
+use axum::{Extension, extract::State};
+
+use ring_channel_model::{
+    Player,
+    battle::{BattlePrediction, PlayerTeam},
+    matchmaking::TeamSuggestion,
+    request::matchmaking::SuggestTeamsRequest,
+};
+
+use tracing::instrument;
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Model, Payload},
+    auth::api_key::ServerAuthentication,
+    error::{Error, ErrorKind},
+    matchmaking::{Seed, balance_teams, red_win_probability},
+    player::{
+        get_player,
+        mmr::{self, ModelData},
+    },
+};
+
+/// Suggests a balanced split of players into teams.
+///
+/// If ratings are disabled, or any player is unrated, players are split in
+/// the order they were given.
+#[instrument(skip(state, model))]
+pub async fn suggest<T>(
+    _auth_guard: ServerAuthentication,
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<SuggestTeamsRequest>>,
+) -> Result<AppJson<TeamSuggestion>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    let mut conn = state.read_db().acquire().await?;
+
+    let mut players = Vec::with_capacity(request.players.len());
+    let mut seeds = Vec::with_capacity(request.players.len());
+
+    for short_id in request.players {
+        if players.iter().any(|player: &Player| player.id == short_id) {
+            return Err(ErrorKind::InvalidData(format!(
+                "Player {} is listed more than once",
+                short_id
+            ))
+            .into());
+        }
+
+        let player = get_player(&short_id, &mut conn)
+            .await?
+            .ok_or_else(|| Error::not_found(format!("Player {} not found", short_id)))?;
+
+        let rating = player.rating.zip(player.deviation);
+        let player = player.normalize(&model)?;
+
+        // normalized players only have an mmr if ratings are enabled
+        seeds.push(player.mmr.zip(rating).map(|(ordinal, rating)| Seed {
+            ordinal: ordinal as f32,
+            rating,
+        }));
+        players.push(player);
+    }
+
+    let (teams, prediction) = match seeds.into_iter().collect::<Option<Vec<_>>>() {
+        Some(seeds) => {
+            let win_probability = <T::Data as ModelData>::win_probability;
+            let teams = balance_teams(&seeds, win_probability);
+            let prediction = red_win_probability(&seeds, &teams, win_probability)
+                .map(|red| BattlePrediction::from_red(red as f64));
+
+            (teams, prediction)
+        }
+        None => {
+            let teams = (0..players.len())
+                .map(|i| match i % 2 {
+                    0 => PlayerTeam::Red,
+                    _ => PlayerTeam::Blue,
+                })
+                .collect::<Vec<_>>();
+
+            (teams, None)
+        }
+    };
+
+    let (red, blue) = players
+        .into_iter()
+        .zip(teams)
+        .partition::<Vec<_>, _>(|(_, team)| *team == PlayerTeam::Red);
+
+    Ok(AppJson(TeamSuggestion {
+        red: red.into_iter().map(|(player, _)| player).collect(),
+        blue: blue.into_iter().map(|(player, _)| player).collect(),
+        prediction,
+    }))
+}
@@ -0,0 +1,23 @@
+//! Service metrics.
+//!
+//! Mounted next to the health probes, outside of the session middleware.
+
+use axum::{extract::State, response::IntoResponse};
+
+use http::{HeaderValue, header};
+
+use crate::app::AppState;
+
+/// The content type of the Prometheus text format.
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Renders all metrics.
+pub async fn show(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PROMETHEUS_CONTENT_TYPE),
+        )],
+        state.metrics.render(),
+    )
+}
@@ -0,0 +1,99 @@
+//! Rating routes.
+
+use axum::{Extension, extract::State};
+
+use chrono::{DateTime, Utc};
+
+use garde::Validate;
+
+use ring_channel_model::mmr::{RatingPeriod, RatingPeriods};
+
+use serde::Deserialize;
+
+use tracing::instrument;
+
+use crate::{
+    app::{AppForm, AppGarde, AppJson, AppState, Model},
+    error::{Error, ErrorKind},
+    player::mmr,
+};
+
+/// A query for [`periods`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct ListPeriodsQuery {
+    /// How many past periods to show.
+    #[garde(range(min = 0, max = 50))]
+    #[serde(default = "list_periods_count_default")]
+    pub count: i32,
+}
+
+fn list_periods_count_default() -> i32 {
+    10
+}
+
+/// Shows the rating period schedule.
+#[instrument(skip(state, model))]
+pub async fn periods<T>(
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<ListPeriodsQuery>>,
+) -> Result<AppJson<RatingPeriods>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    if !model.ratings_enabled() {
+        return Err(ErrorKind::InvalidData("Ratings are disabled on this server".into()).into());
+    }
+
+    let now = Utc::now();
+
+    // the current period, plus `count` periods before it
+    let started_ats = sqlx::query_as::<_, (DateTime<Utc>,)>(
+        r#"
+        SELECT inserted_at
+        FROM rating_period
+        ORDER BY inserted_at DESC
+        LIMIT $1
+        "#,
+    )
+    .bind(query.count + 1)
+    .fetch_all(state.read_db())
+    .await?;
+
+    let mut started_ats = started_ats.into_iter().map(|(started_at,)| started_at);
+
+    let Some(current_started_at) = started_ats.next() else {
+        return Ok(AppJson(RatingPeriods {
+            current: None,
+            ends_in: None,
+            history: vec![],
+        }));
+    };
+
+    let current = RatingPeriod {
+        started_at: current_started_at,
+        ended_at: current_started_at + model.period(),
+    };
+    // the period may not have been closed yet
+    let ends_in = (current.ended_at - now).num_milliseconds().max(0);
+
+    // each period ends when the next one starts
+    let mut ended_at = current_started_at;
+    let history = started_ats
+        .map(|started_at| {
+            let period = RatingPeriod {
+                started_at,
+                ended_at,
+            };
+            ended_at = started_at;
+            period
+        })
+        .collect();
+
+    Ok(AppJson(RatingPeriods {
+        current: Some(current),
+        ends_in: Some(ends_in),
+        history,
+    }))
+}
@@ -1,8 +1,21 @@
 //! Application routes.
 
+pub mod admin;
+pub mod announcement;
+pub mod avatar;
 pub mod battle;
 pub mod chat;
+pub mod error;
+pub mod event;
+pub mod fallback;
+pub mod health;
+pub mod leaderboard;
+pub mod matchmaking;
+pub mod metrics;
+pub mod mmr;
 pub mod player;
 pub mod server;
+pub mod squad;
+pub mod stats;
 pub mod user;
 pub mod ws;
@@ -5,39 +5,180 @@ use axum::{
     extract::{Path, State},
 };
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 
 use http::StatusCode;
 
-use ring_channel_model::{Player, request::player::RegisterPlayerRequest};
+use garde::Validate;
+
+use ring_channel_model::{
+    Player, PlayerShortId,
+    battle::BattleStatus,
+    display_name::to_display_name_lossy,
+    player::{FoundPlayer, PlayerAlias, PlayerStats},
+    request::player::RegisterPlayerRequest,
+};
+
+use serde::Deserialize;
 
 use sqlx::FromRow;
 
 use tracing::instrument;
 
 use crate::{
-    app::{AppJson, AppState, Model, Payload},
+    app::{
+        AppForm, AppGarde, AppJson, AppState, Model, Payload,
+        conditional::{Cached, Conditional, Validators},
+    },
     auth::api_key::ServerAuthentication,
-    error::Error,
+    error::{Error, ErrorKind},
     player::{
-        create_player, get_player,
+        PlayerRow, create_player, get_player,
         mmr::{self, Rating, RawRating, init_rating},
+        name_filter::censor,
+        stats::{played_matches, summarize},
     },
 };
 
 pub const MAX_INSERT_ATTEMPTS: usize = 25;
 
+/// How many characters a fuzzy search needs.
+///
+/// Names are indexed by trigrams, so anything shorter can only match as a
+/// prefix.
+pub const MIN_FUZZY_SEARCH_LENGTH: usize = 3;
+
+/// A query for [`search`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct SearchQuery {
+    /// Part of a display name or short id.
+    #[garde(length(chars, min = 1, max = 64))]
+    pub search: String,
+    #[garde(range(min = 1, max = 50))]
+    #[serde(default = "search_count_default")]
+    pub count: i32,
+    /// How many results to skip.
+    #[garde(range(min = 0))]
+    #[serde(default)]
+    pub offset: i32,
+}
+
+fn search_count_default() -> i32 {
+    25
+}
+
+/// Finds players by display name or short id.
+///
+/// Players whose name or short id starts with `search` come first, then
+/// players whose name contains it or once did, then close matches.
+#[instrument(skip(state, model))]
+pub async fn search<T>(
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<SearchQuery>>,
+) -> Result<AppJson<Vec<FoundPlayer>>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    #[derive(FromRow)]
+    struct SearchRow {
+        #[sqlx(flatten)]
+        player: PlayerRow,
+        former_name: Option<String>,
+    }
+
+    let escaped = escape_like(&query.search);
+    let prefix = format!("{}%", escaped);
+    let pattern = format!("%{}%", escaped);
+
+    // an empty phrase matches nothing, for searches too short to be fuzzy
+    let rows = sqlx::query_as::<_, SearchRow>(
+        r#"
+        SELECT
+            p.id AS player_id,
+            p.short_id,
+            p.display_name,
+            p.rating,
+            p.deviation,
+            p.rating_extra,
+            (
+                SELECT COUNT(*)
+                FROM participant gp
+                INNER JOIN battle gb ON gb.id = gp.match_id
+                WHERE gp.player_id = p.id AND gb.status = $6
+            ) AS games_played,
+            CASE
+                WHEN p.display_name LIKE $2 ESCAPE '\' THEN NULL
+                ELSE (
+                    SELECT a.display_name
+                    FROM player_alias a
+                    WHERE a.player_id = p.id AND a.display_name LIKE $2 ESCAPE '\'
+                    ORDER BY a.replaced_at DESC
+                    LIMIT 1
+                )
+            END AS former_name
+        FROM player p
+        LEFT JOIN (
+            SELECT rowid AS id, bm25(player_search, 1.0, 0.5) AS score
+            FROM player_search
+            WHERE player_search MATCH IFNULL($3, '""')
+        ) f ON f.id = p.id
+        WHERE
+            p.display_name LIKE $2 ESCAPE '\'
+            OR p.short_id LIKE $1 ESCAPE '\'
+            OR f.id IS NOT NULL
+            OR EXISTS (
+                SELECT 1
+                FROM player_alias a
+                WHERE a.player_id = p.id AND a.display_name LIKE $2 ESCAPE '\'
+            )
+        ORDER BY
+            CASE
+                WHEN p.display_name LIKE $1 ESCAPE '\' OR p.short_id LIKE $1 ESCAPE '\' THEN 0
+                WHEN p.display_name LIKE $2 ESCAPE '\' THEN 1
+                WHEN former_name IS NOT NULL THEN 2
+                ELSE 3
+            END,
+            f.score ASC,
+            p.updated_at DESC,
+            p.id ASC
+        LIMIT $4 OFFSET $5
+        "#,
+    )
+    .bind(&prefix)
+    .bind(&pattern)
+    .bind(fuzzy_match(&query.search))
+    .bind(query.count)
+    .bind(query.offset)
+    .bind(BattleStatus::Concluded)
+    .fetch_all(state.read_db())
+    .await?;
+
+    let players = rows
+        .into_iter()
+        .map(|row| {
+            row.player.normalize(&model).map(|player| FoundPlayer {
+                player,
+                former_name: row.former_name,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(AppJson(players))
+}
+
 /// Shows a player.
 #[instrument(skip(state, model))]
 pub async fn show<T>(
-    Path((short_id,)): Path<(String,)>,
+    Path((short_id,)): Path<(PlayerShortId,)>,
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
 ) -> Result<AppJson<Player>, Error>
 where
     T: mmr::Model + 'static,
 {
-    let mut conn = state.db.acquire().await?;
+    let mut conn = state.read_db().acquire().await?;
 
     get_player(&short_id, &mut conn)
         .await
@@ -46,6 +187,78 @@ where
         .map(|player| AppJson(player))
 }
 
+/// Shows a player's lifetime statistics.
+#[instrument(skip(state))]
+pub async fn stats(
+    Path((short_id,)): Path<(PlayerShortId,)>,
+    conditional: Conditional,
+    State(state): State<AppState>,
+) -> Result<Cached<AppJson<PlayerStats>>, Error> {
+    let mut conn = state.read_db().acquire().await?;
+
+    let player = get_player(&short_id, &mut conn)
+        .await?
+        .ok_or_else(|| Error::not_found(format!("Player {} not found", short_id)))?;
+
+    let matches = played_matches(player.id, &mut conn).await?;
+
+    // amended results touch the match, so this catches those too
+    let last_modified = matches
+        .iter()
+        .map(|played| played.updated_at)
+        .max()
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let validators = Validators::new(
+        format!("{}-{}", last_modified.timestamp_millis(), matches.len()),
+        last_modified,
+    );
+
+    if conditional.is_fresh(&validators) {
+        return Ok(Cached::NotModified(validators));
+    }
+
+    Ok(Cached::Modified(validators, AppJson(summarize(&matches))))
+}
+
+/// Lists the display names a player used before, most recent first.
+#[instrument(skip(state))]
+pub async fn aliases(
+    Path((short_id,)): Path<(PlayerShortId,)>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<PlayerAlias>>, Error> {
+    #[derive(FromRow)]
+    struct AliasQuery {
+        display_name: String,
+        replaced_at: DateTime<Utc>,
+    }
+
+    let mut conn = state.read_db().acquire().await?;
+
+    let player = get_player(&short_id, &mut conn)
+        .await?
+        .ok_or_else(|| Error::not_found(format!("Player {} not found", short_id)))?;
+
+    let aliases = sqlx::query_as::<_, AliasQuery>(
+        r#"
+        SELECT display_name, replaced_at
+        FROM player_alias
+        WHERE player_id = $1
+        ORDER BY replaced_at DESC
+        "#,
+    )
+    .bind(player.id)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|alias| PlayerAlias {
+        display_name: alias.display_name,
+        replaced_at: alias.replaced_at,
+    })
+    .collect();
+
+    Ok(AppJson(aliases))
+}
+
 /// Registers a joined player.
 ///
 /// All players must be registered to create matches for them!
@@ -54,7 +267,7 @@ pub async fn register<T>(
     _auth_guard: ServerAuthentication,
     Extension(model): Extension<Model<T>>,
     State(state): State<AppState>,
-    Payload(request): Payload<RegisterPlayerRequest>,
+    AppGarde(Payload(request)): AppGarde<Payload<RegisterPlayerRequest>>,
 ) -> Result<(StatusCode, AppJson<Player>), Error>
 where
     T: mmr::Model + 'static,
@@ -63,12 +276,27 @@ where
     struct UpsertQuery {
         #[sqlx(rename = "player_id")]
         id: i32,
-        short_id: String,
+        short_id: PlayerShortId,
         display_name: String,
         rating: Option<f32>,
         deviation: Option<f32>,
         #[sqlx(rename = "rating_extra")]
         extra: Option<String>,
+        games_played: i32,
+        display_name_locked: bool,
+    }
+
+    let mut display_name = to_display_name_lossy(
+        &request.display_name,
+        state.config.server.max_display_name_length,
+    );
+    if display_name.is_empty() {
+        return Err(ErrorKind::InvalidData("Display name has no usable characters".into()).into());
+    }
+
+    if let Some(censored) = censor(&display_name, &state.config.server.name_filter) {
+        tracing::info!(%display_name, %censored, "censored player display name");
+        display_name = censored;
     }
 
     let mut tx = state.db.begin().await?;
@@ -78,12 +306,26 @@ where
     // find existing player
     let player_query = sqlx::query_as::<_, UpsertQuery>(
         r#"
-        SELECT id AS player_id, short_id, display_name, rating, deviation, rating_extra
-        FROM player
-        WHERE public_key = $1
+        SELECT
+            p.id AS player_id,
+            p.short_id,
+            p.display_name,
+            p.rating,
+            p.deviation,
+            p.rating_extra,
+            (
+                SELECT COUNT(*)
+                FROM participant gp
+                INNER JOIN battle gb ON gb.id = gp.match_id
+                WHERE gp.player_id = p.id AND gb.status = $2
+            ) AS games_played,
+            p.display_name_locked
+        FROM player p
+        WHERE p.public_key = $1
         "#,
     )
     .bind(request.public_key.as_str())
+    .bind(BattleStatus::Concluded)
     .fetch_optional(&mut *tx)
     .await?;
 
@@ -104,7 +346,21 @@ where
         };
 
         // a player exists already, we just need to update them
-        if player.display_name != request.display_name {
+        // names set by admins stick, though
+        if !player.display_name_locked && player.display_name != display_name {
+            // keep the old name around, so old matches still make sense
+            sqlx::query(
+                r#"
+                INSERT INTO player_alias (player_id, display_name, replaced_at)
+                VALUES ($1, $2, $3)
+                "#,
+            )
+            .bind(player.id)
+            .bind(&player.display_name)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
             sqlx::query(
                 r#"
                 UPDATE player
@@ -112,13 +368,13 @@ where
                 WHERE short_id = $2
                 "#,
             )
-            .bind(&request.display_name)
+            .bind(&display_name)
             .bind(&player.short_id)
             .bind(now)
             .execute(&mut *tx)
             .await?;
 
-            player.display_name = request.display_name.clone();
+            player.display_name = display_name;
         }
 
         tx.commit().await?;
@@ -128,14 +384,17 @@ where
             StatusCode::CREATED,
             AppJson(Player {
                 id: player.short_id,
-                mmr: rating.map(|rating| rating.ordinal() as i32),
+                mmr: rating.as_ref().map(|rating| rating.ordinal() as i32),
+                provisional: model.ratings_enabled()
+                    && rating.as_ref().is_none_or(|rating| rating.is_provisional()),
+                games_played: player.games_played,
                 display_name: player.display_name,
                 public_key: Some(request.public_key),
             }),
         ))
     } else {
         // this is a new player
-        let player = create_player(&request.public_key, &request.display_name, &mut *tx).await?;
+        let player = create_player(&request.public_key, &display_name, &mut *tx).await?;
 
         let rating = if model.ratings_enabled() {
             // Add a historic rating for glicko2 to work
@@ -150,10 +409,52 @@ where
             StatusCode::CREATED,
             AppJson(Player {
                 id: player.short_id,
-                mmr: rating.map(|rating| rating.ordinal() as i32),
+                mmr: rating.as_ref().map(|rating| rating.ordinal() as i32),
+                provisional: rating
+                    .as_ref()
+                    .is_some_and(|rating| rating.is_provisional()),
+                games_played: 0,
                 display_name: player.display_name,
                 public_key: Some(request.public_key),
             }),
         ))
     }
 }
+
+/// Escapes the wildcards in a `LIKE` pattern, with `\` as the escape
+/// character.
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Builds a full-text query that matches names sharing any trigram with
+/// `search`.
+///
+/// Names sharing more trigrams rank higher. Returns `None` if `search` is too
+/// short to have any.
+fn fuzzy_match(search: &str) -> Option<String> {
+    let chars = search.to_lowercase().chars().collect::<Vec<_>>();
+    if chars.len() < MIN_FUZZY_SEARCH_LENGTH {
+        return None;
+    }
+
+    let mut trigrams = chars
+        .windows(MIN_FUZZY_SEARCH_LENGTH)
+        .map(|trigram| trigram.iter().collect::<String>())
+        .collect::<Vec<_>>();
+    trigrams.sort();
+    trigrams.dedup();
+
+    let terms = trigrams
+        .iter()
+        .map(|trigram| format!("\"{}\"", trigram.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    Some(terms.join(" OR "))
+}
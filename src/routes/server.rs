@@ -12,7 +12,7 @@ use ring_channel_model::{
 use sqlx::{FromRow, SqliteConnection};
 
 use crate::{
-    app::{AppJson, AppState, Payload},
+    app::{AppGarde, AppJson, AppState, Payload},
     auth::api_key::ServerAuthentication,
     error::Error,
 };
@@ -47,7 +47,7 @@ pub async fn show_self(
 pub async fn update(
     auth: ServerAuthentication,
     State(state): State<AppState>,
-    Payload(mut request): Payload<UpdateServerRequest>,
+    AppGarde(Payload(mut request)): AppGarde<Payload<UpdateServerRequest>>,
 ) -> Result<AppJson<Server>, Error> {
     let mut tx = state.db.begin().await.map_err(Error::new)?;
 
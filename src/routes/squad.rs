@@ -0,0 +1,389 @@
+//! Squad endpoints.
+
+use axum::extract::{Path, State};
+
+use chrono::{DateTime, Utc};
+
+use garde::Validate;
+
+use http::StatusCode;
+
+use ring_channel_model::{
+    Mobiums, User,
+    page::ListFormat,
+    request::squad::{CreateSquad, UpdateSquadMembership},
+    squad::{Squad, SquadMember, SquadStanding},
+};
+
+use serde::Deserialize;
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{
+    app::{
+        AppForm, AppGarde, AppJson, AppState, Payload,
+        page::{Listing, parse_cursor, trim_page},
+    },
+    audit::Audit,
+    error::{Error, ErrorKind},
+    session::{Session, SessionUser},
+    squad::{SquadSchema, leave_squad},
+    user::UserSchema,
+};
+
+/// A query for [`list`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct SquadLeaderboardQuery {
+    /// How many squads to list.
+    #[garde(range(min = 1, max = 100))]
+    #[serde(default = "squad_count_default")]
+    pub count: i32,
+    /// The cursor of the page to list.
+    #[garde(length(max = 64))]
+    pub cursor: Option<String>,
+    /// Whether to list the squads in a page.
+    #[garde(skip)]
+    #[serde(default)]
+    pub format: ListFormat,
+}
+
+fn squad_count_default() -> i32 {
+    25
+}
+
+#[derive(FromRow)]
+struct StandingQuery {
+    #[sqlx(flatten)]
+    squad: SquadSchema,
+    net_mobiums: Mobiums,
+    wagers: i32,
+    wins: i32,
+}
+
+#[derive(FromRow)]
+struct MemberQuery {
+    #[sqlx(flatten)]
+    user: UserSchema,
+    joined_at: DateTime<Utc>,
+}
+
+/// Lists the squads whose wagers won the most, net of losses.
+///
+/// Only settled wagers members placed while in the squad count.
+pub async fn list(
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<SquadLeaderboardQuery>>,
+) -> Result<AppJson<Listing<SquadStanding>>, Error> {
+    // like the user leaderboard, the cursor is a rank
+    let offset = query
+        .cursor
+        .as_deref()
+        .map(parse_cursor::<u32>)
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut standings = sqlx::query_as::<_, StandingQuery>(
+        r#"
+        SELECT
+            s.id, s.name, o.username AS owner, s.inserted_at,
+            (SELECT COUNT(*) FROM squad_member WHERE squad_id = s.id) AS members,
+            IFNULL(SUM(w.payout), 0) AS net_mobiums,
+            COUNT(w.id) AS wagers,
+            IFNULL(SUM(w.payout > 0), 0) AS wins
+        FROM squad s
+        INNER JOIN user o ON o.id = s.owner_id
+        LEFT JOIN squad_member sm ON sm.squad_id = s.id
+        LEFT JOIN wager w
+            ON w.user_id = sm.user_id
+            AND w.payout IS NOT NULL
+            AND w.inserted_at >= sm.joined_at
+        GROUP BY s.id
+        ORDER BY net_mobiums DESC, s.name ASC
+        LIMIT $1 OFFSET $2
+        "#,
+    )
+    .bind(query.count + 1)
+    .bind(offset)
+    .fetch_all(state.read_db())
+    .await?;
+
+    let has_more = trim_page(&mut standings, query.count);
+    let next_cursor = has_more.then(|| (offset + standings.len() as u32).to_string());
+
+    let standings = standings
+        .into_iter()
+        .map(|standing| SquadStanding {
+            squad: standing.squad.into(),
+            net_mobiums: standing.net_mobiums,
+            wagers: standing.wagers,
+            wins: standing.wins,
+        })
+        .collect();
+
+    Ok(AppJson(Listing::new(query.format, standings, next_cursor)))
+}
+
+/// Shows a squad.
+pub async fn show(
+    State(state): State<AppState>,
+    Path((name,)): Path<(String,)>,
+) -> Result<AppJson<Squad>, Error> {
+    let mut conn = state.read_db().acquire().await?;
+
+    let squad = get_squad(&name, &mut conn).await?;
+
+    Ok(AppJson(squad.into()))
+}
+
+/// Lists the members of a squad, longest-standing first.
+pub async fn members(
+    State(state): State<AppState>,
+    Path((name,)): Path<(String,)>,
+) -> Result<AppJson<Vec<SquadMember>>, Error> {
+    let mut conn = state.read_db().acquire().await?;
+
+    let squad = get_squad(&name, &mut conn).await?;
+
+    let members = sqlx::query_as::<_, MemberQuery>(
+        r#"
+        SELECT
+            u.id, u.username, u.avatar, u.display_name, u.mobiums,
+            u.mobiums_gained, u.mobiums_lost, u.streak, u.flags, sm.joined_at
+        FROM squad_member sm
+        INNER JOIN user u ON u.id = sm.user_id
+        WHERE sm.squad_id = $1
+        ORDER BY sm.joined_at ASC, u.id ASC
+        "#,
+    )
+    .bind(squad.id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(AppJson(
+        members
+            .into_iter()
+            .map(|member| {
+                let mut user = User::from(member.user);
+                user.avatar = state.avatar_url(&user.username, user.avatar.take());
+
+                SquadMember {
+                    user,
+                    joined_at: member.joined_at,
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Shows the current user's squad.
+pub async fn show_self(
+    user: SessionUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<Squad>, Error> {
+    let squad = sqlx::query_as::<_, SquadSchema>(
+        r#"
+        SELECT
+            s.id, s.name, o.username AS owner, s.inserted_at,
+            (SELECT COUNT(*) FROM squad_member WHERE squad_id = s.id) AS members
+        FROM squad_member sm
+        INNER JOIN squad s ON s.id = sm.squad_id
+        INNER JOIN user o ON o.id = s.owner_id
+        WHERE sm.user_id = $1
+        "#,
+    )
+    .bind(user.identity())
+    .fetch_optional(&state.db)
+    .await?;
+
+    squad
+        .map(|squad| AppJson(squad.into()))
+        .ok_or_else(|| Error::not_found("You aren't in a squad"))
+}
+
+/// Creates a squad, led by the current user.
+///
+/// Users can only be in one squad, so this leaves the user's old squad.
+pub async fn create(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateSquad>>,
+) -> Result<(StatusCode, AppJson<Squad>), Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let now = Utc::now();
+    let name = request.name.trim();
+
+    let mut tx = state.db.begin().await?;
+
+    let (taken,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM squad WHERE name = $1)
+        "#,
+    )
+    .bind(name)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if taken {
+        return Err(ErrorKind::InvalidData(format!("Squad {} already exists", name)).into());
+    }
+
+    if let Some(old) = leave_squad(user.identity(), now, &mut tx).await? {
+        audit.note(format!("left squad {}", old));
+    }
+
+    let (squad_id,) = sqlx::query_as::<_, (i32,)>(
+        r#"
+        INSERT INTO squad (name, owner_id, inserted_at, updated_at)
+        VALUES ($1, $2, $3, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(name)
+    .bind(user.identity())
+    .bind(now)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    add_member(squad_id, &user, now, &mut tx).await?;
+
+    let squad = get_squad(name, &mut tx).await?;
+
+    tx.commit().await?;
+
+    audit.note(format!("created squad {}", name));
+
+    session.shuffle_csrf().await?;
+
+    Ok((StatusCode::CREATED, AppJson(squad.into())))
+}
+
+/// Joins a squad.
+///
+/// Users can only be in one squad, so this leaves the user's old squad.
+pub async fn join(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((name,)): Path<(String,)>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateSquadMembership>>,
+) -> Result<AppJson<Squad>, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let now = Utc::now();
+
+    let mut tx = state.db.begin().await?;
+
+    let squad = get_squad(&name, &mut tx).await?;
+
+    let (member,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM squad_member
+            WHERE user_id = $1 AND squad_id = $2
+        )
+        "#,
+    )
+    .bind(user.identity())
+    .bind(squad.id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if member {
+        return Err(ErrorKind::InvalidData(format!("You're already in {}", squad.name)).into());
+    }
+
+    if let Some(old) = leave_squad(user.identity(), now, &mut tx).await? {
+        audit.note(format!("left squad {}", old));
+    }
+
+    add_member(squad.id, &user, now, &mut tx).await?;
+
+    let squad = get_squad(&squad.name, &mut tx).await?;
+
+    tx.commit().await?;
+
+    audit.note(format!("joined squad {}", squad.name));
+
+    session.shuffle_csrf().await?;
+
+    Ok(AppJson(squad.into()))
+}
+
+/// Leaves the current user's squad.
+pub async fn leave(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateSquadMembership>>,
+) -> Result<StatusCode, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let Some(name) = leave_squad(user.identity(), Utc::now(), &mut tx).await? else {
+        return Err(Error::not_found("You aren't in a squad"));
+    };
+
+    tx.commit().await?;
+
+    audit.note(format!("left squad {}", name));
+
+    session.shuffle_csrf().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_squad(name: &str, conn: &mut SqliteConnection) -> Result<SquadSchema, Error> {
+    let squad = sqlx::query_as::<_, SquadSchema>(
+        r#"
+        SELECT
+            s.id, s.name, o.username AS owner, s.inserted_at,
+            (SELECT COUNT(*) FROM squad_member WHERE squad_id = s.id) AS members
+        FROM squad s
+        INNER JOIN user o ON o.id = s.owner_id
+        WHERE s.name = $1
+        "#,
+    )
+    .bind(name)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    squad.ok_or_else(|| Error::not_found(format!("Squad {} not found", name)))
+}
+
+async fn add_member(
+    squad_id: i32,
+    user: &SessionUser,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO squad_member (user_id, squad_id, joined_at)
+        VALUES ($1, $2, $3)
+        "#,
+    )
+    .bind(user.identity())
+    .bind(squad_id)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+//! Aggregate statistics routes.
+
+use axum::extract::State;
+
+use chrono::{NaiveDate, TimeDelta, Utc};
+
+use garde::Validate;
+
+use ring_channel_model::stats::{
+    CalibrationBucket, DailyStats, OddsCalibration, OddsSource, SourceCalibration,
+};
+
+use serde::Deserialize;
+
+use sqlx::FromRow;
+
+use crate::{
+    app::{AppForm, AppGarde, AppJson, AppState},
+    calibration::BUCKETS,
+    error::{Error, ErrorKind},
+};
+
+/// The most days that can be requested at once.
+pub const MAX_DAYS: i64 = 366;
+
+/// A query for [`daily`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct DailyStatsQuery {
+    /// The first day to include. Defaults to 30 days before `to`.
+    #[garde(skip)]
+    pub from: Option<NaiveDate>,
+    /// The last day to include. Defaults to today.
+    #[garde(skip)]
+    pub to: Option<NaiveDate>,
+}
+
+/// Lists daily statistics.
+///
+/// Days that have not been rolled up yet (including today) are not included.
+pub async fn daily(
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<DailyStatsQuery>>,
+) -> Result<AppJson<Vec<DailyStats>>, Error> {
+    #[derive(FromRow)]
+    struct StatsQuery {
+        day: NaiveDate,
+        matches: i64,
+        wagers: i64,
+        total_pot: i64,
+        bailouts: i64,
+    }
+
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - TimeDelta::days(30));
+
+    if from > to {
+        return Err(ErrorKind::InvalidData("`from` must come before `to`".into()).into());
+    }
+    if to - from > TimeDelta::days(MAX_DAYS) {
+        return Err(
+            ErrorKind::InvalidData(format!("Cannot request more than {} days", MAX_DAYS)).into(),
+        );
+    }
+
+    let stats = sqlx::query_as::<_, StatsQuery>(
+        r#"
+        SELECT day, matches, wagers, total_pot, bailouts
+        FROM stats_daily
+        WHERE day >= $1 AND day <= $2
+        ORDER BY day ASC
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(state.read_db())
+    .await?
+    .into_iter()
+    .map(|row| DailyStats {
+        day: row.day,
+        matches: row.matches,
+        wagers: row.wagers,
+        total_pot: row.total_pot,
+        average_pot: if row.matches > 0 {
+            row.total_pot / row.matches
+        } else {
+            0
+        },
+        bailouts: row.bailouts,
+    })
+    .collect();
+
+    Ok(AppJson(stats))
+}
+
+/// A query for [`calibration`].
+#[derive(Deserialize, Debug, Validate)]
+#[garde(context(AppState as state))]
+pub struct CalibrationQuery {
+    /// The first day to include. Defaults to the first day rolled up.
+    #[garde(skip)]
+    pub from: Option<NaiveDate>,
+    /// The last day to include. Defaults to today.
+    #[garde(skip)]
+    pub to: Option<NaiveDate>,
+}
+
+/// Shows how well pre-match odds predicted the outcomes of matches.
+///
+/// Days that have not been rolled up yet (including today) are not included.
+pub async fn calibration(
+    State(state): State<AppState>,
+    AppGarde(AppForm(query)): AppGarde<AppForm<CalibrationQuery>>,
+) -> Result<AppJson<OddsCalibration>, Error> {
+    #[derive(FromRow)]
+    struct BucketQuery {
+        source: u8,
+        bucket: u8,
+        matches: i64,
+        favorite_wins: i64,
+        probability_sum: f64,
+        squared_error_sum: f64,
+    }
+
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+
+    if let Some(from) = query.from
+        && from > to
+    {
+        return Err(ErrorKind::InvalidData("`from` must come before `to`".into()).into());
+    }
+
+    let rows = sqlx::query_as::<_, BucketQuery>(
+        r#"
+        SELECT
+            source, bucket,
+            SUM(matches) AS matches,
+            SUM(favorite_wins) AS favorite_wins,
+            SUM(probability_sum) AS probability_sum,
+            SUM(squared_error_sum) AS squared_error_sum
+        FROM calibration_daily
+        WHERE ($1 IS NULL OR day >= $1) AND day <= $2
+        GROUP BY source, bucket
+        ORDER BY source, bucket
+        "#,
+    )
+    .bind(query.from)
+    .bind(to)
+    .fetch_all(state.read_db())
+    .await?;
+
+    let mut ratings = Vec::new();
+    let mut pots = Vec::new();
+    for row in rows {
+        let source = OddsSource::try_from(row.source).map_err(Error::new)?;
+        let rows = match source {
+            OddsSource::Ratings => &mut ratings,
+            OddsSource::Pots => &mut pots,
+        };
+        rows.push(row);
+    }
+
+    let summarize = |rows: Vec<BucketQuery>| {
+        let matches = rows.iter().map(|row| row.matches).sum::<i64>();
+        let squared_error_sum = rows.iter().map(|row| row.squared_error_sum).sum::<f64>();
+
+        SourceCalibration {
+            matches,
+            brier_score: (matches > 0).then(|| squared_error_sum / matches as f64),
+            buckets: rows
+                .into_iter()
+                .filter(|row| row.matches > 0)
+                .map(|row| CalibrationBucket {
+                    min_probability: row.bucket as f64 / BUCKETS as f64,
+                    max_probability: (row.bucket + 1) as f64 / BUCKETS as f64,
+                    matches: row.matches,
+                    favorite_wins: row.favorite_wins,
+                    expected_win_rate: row.probability_sum / row.matches as f64,
+                    win_rate: row.favorite_wins as f64 / row.matches as f64,
+                })
+                .collect(),
+        }
+    };
+
+    Ok(AppJson(OddsCalibration {
+        from: query.from,
+        to,
+        ratings: summarize(ratings),
+        pots: summarize(pots),
+    }))
+}
@@ -8,11 +8,11 @@ use axum::{
 use chrono::Utc;
 use derive_more::{Display, Error};
 use oauth2::{
-    AuthorizationCode, CsrfToken, HttpClientError, RefreshToken, RequestTokenError, Scope,
-    StandardRevocableToken, TokenResponse as _,
+    AuthorizationCode, CsrfToken, HttpClientError, PkceCodeChallenge, PkceCodeVerifier,
+    RefreshToken, RequestTokenError, Scope, StandardRevocableToken, TokenResponse as _,
 };
 
-use ring_channel_model::user::to_username_lossy;
+use ring_channel_model::{UserId, display_name::to_display_name_lossy, user::to_username_lossy};
 
 use twilight_model::user::CurrentUser as DiscordUser;
 
@@ -23,14 +23,16 @@ use sqlx::{FromRow, SqliteConnection};
 use tracing::instrument;
 
 use crate::{
-    auth::oauth2::{OauthState, Session},
+    auth::oauth2::{OauthState, Session, is_guild_member},
     error::{Error, ErrorKind},
+    user::ledger::{LedgerReason, record},
 };
 
 #[derive(FromRow)]
 struct ExistingUserQuery {
-    pub id: i32,
+    pub id: UserId,
     pub refresh_token: String,
+    pub merged_into: Option<UserId>,
 }
 
 /// A response from the Oauth resource holder.
@@ -49,11 +51,24 @@ pub async fn redirect(
     session.shuffle_csrf().await?;
 
     // we now have a session, build the url
-    let (auth_url, _csrf_token) = oauth_state
+    let mut request = oauth_state
         .client
         .authorize_url(|| CsrfToken::new(session.state.clone()))
-        .add_scope(Scope::new("identify".into()))
-        .url();
+        .add_scope(Scope::new("identify".into()));
+
+    if oauth_state.guild_id.is_some() {
+        request = request.add_scope(Scope::new("guilds".into()));
+    }
+
+    if oauth_state.pkce {
+        let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+        session
+            .set_pkce_verifier(Some(verifier.into_secret()))
+            .await?;
+        request = request.set_pkce_challenge(challenge);
+    }
+
+    let (auth_url, _csrf_token) = request.url();
 
     Ok(Redirect::to(auth_url.as_str()))
 }
@@ -73,13 +88,23 @@ pub async fn login(
         return Err(ErrorKind::InvalidState { state: query.state }.into());
     }
 
+    // a verifier is only good for one exchange
+    let pkce_verifier = session.take_pkce_verifier().await?;
+    if oauth_state.pkce && pkce_verifier.is_none() {
+        tracing::warn!("login without a pkce verifier");
+        return Err(ErrorKind::InvalidState { state: query.state }.into());
+    }
+
     let now = Utc::now();
 
-    let token_result = oauth_state
+    let mut request = oauth_state
         .client
-        .exchange_code(AuthorizationCode::new(query.code))
-        .request_async(&oauth_state.http_client)
-        .await;
+        .exchange_code(AuthorizationCode::new(query.code));
+    if let Some(pkce_verifier) = pkce_verifier {
+        request = request.set_pkce_verifier(PkceCodeVerifier::new(pkce_verifier));
+    }
+
+    let token_result = request.request_async(&oauth_state.http_client).await;
 
     // Get token and update session
     let token_result = match token_result {
@@ -116,6 +141,12 @@ pub async fn login(
         .await
         .map_err(Error::new)?;
 
+    // members of the community server aren't limited in how much they wager
+    let guild_verified = match oauth_state.guild_id {
+        Some(guild_id) => is_guild_member(&http_client, guild_id).await,
+        None => None,
+    };
+
     tracing::debug!("committing authenticated Discord user");
 
     let mut tx = oauth_state.db.begin().await?;
@@ -123,7 +154,7 @@ pub async fn login(
     let existing_user = sqlx::query_as::<_, ExistingUserQuery>(
         r#"
         SELECT
-            u.id, da.refresh_token
+            u.id, da.refresh_token, u.merged_into
         FROM
             user u, discord_auth da
         WHERE
@@ -135,6 +166,9 @@ pub async fn login(
     .fetch_optional(&mut *tx)
     .await?;
 
+    // linked accounts log in as the account they were merged into
+    let merged_into = existing_user.as_ref().and_then(|u| u.merged_into);
+
     let user_id = if let Some(existing_user) = existing_user {
         // revoke refresh token
         let revoke_result = oauth_state
@@ -150,9 +184,29 @@ pub async fn login(
             tracing::warn!("failed to revoke token: {}", err);
         }
 
+        // discord avatar links change with the avatar
+        sqlx::query(
+            r#"
+            UPDATE user
+            SET avatar = $2, updated_at = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(existing_user.id)
+        .bind(discord_avatar_url(&remote_user))
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
         existing_user.id
     } else {
-        try_create_user(&remote_user, &mut *tx).await?
+        try_create_user(
+            &remote_user,
+            oauth_state.starting_mobiums,
+            oauth_state.max_display_name_length,
+            &mut *tx,
+        )
+        .await?
     };
 
     // replace discord refresh token
@@ -166,6 +220,7 @@ pub async fn login(
         SET
             refresh_token = $3,
             last_fetched_at = $4,
+            dead_at = NULL,
             updated_at = $4
         "#,
     )
@@ -176,10 +231,24 @@ pub async fn login(
     .execute(&mut *tx)
     .await?;
 
+    if let Some(guild_verified) = guild_verified {
+        sqlx::query(
+            r#"
+            UPDATE user
+            SET guild_verified_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(merged_into.unwrap_or(user_id))
+        .bind(guild_verified.then_some(now))
+        .execute(&mut *tx)
+        .await?;
+    }
+
     tx.commit().await?;
 
     session.shuffle_csrf().await?;
-    session.set_user(user_id).await?; // attach user to session
+    session.set_user(merged_into.unwrap_or(user_id)).await?; // attach user to session
 
     if let Some(redirect_url) = oauth_state.redirect_to.as_ref() {
         Ok(Redirect::to(&redirect_url))
@@ -200,8 +269,10 @@ pub enum UpdateTokenError {
 
 async fn try_create_user(
     remote_user: &DiscordUser,
+    starting_mobiums: i64,
+    max_display_name_length: usize,
     tx: &mut SqliteConnection,
-) -> Result<i32, Error> {
+) -> Result<UserId, Error> {
     let now = Utc::now();
 
     // user needs to be created
@@ -214,55 +285,74 @@ async fn try_create_user(
     };
     let username = to_username_lossy(username);
 
-    let display_name = remote_user
-        .global_name
-        .as_ref()
-        .unwrap_or(&remote_user.name);
+    // fall back to the username if the global name has nothing usable
+    let display_name = [remote_user.global_name.as_deref(), Some(&remote_user.name)]
+        .into_iter()
+        .flatten()
+        .map(|name| to_display_name_lossy(name, max_display_name_length))
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| username.to_string());
 
-    let avatar_url = remote_user.avatar.map(|avatar_hash| {
-        format!(
-            "https://cdn.discordapp.com/avatars/{}/{}.png",
-            remote_user.id, avatar_hash
-        )
-    });
+    let avatar_url = discord_avatar_url(remote_user);
 
-    let res = sqlx::query_as::<_, (i32,)>(
+    let res = sqlx::query_as::<_, (UserId,)>(
         r#"
-        INSERT INTO user (username, display_name, avatar, inserted_at, updated_at)
-        VALUES ($1, $2, $3, $4, $4)
+        INSERT INTO user (username, display_name, avatar, mobiums, inserted_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $5)
         RETURNING id
         "#,
     )
     .bind(&username)
-    .bind(display_name)
+    .bind(&display_name)
     .bind(avatar_url)
+    .bind(starting_mobiums)
     .bind(now)
     .fetch_one(&mut *tx)
     .await;
 
     // check for unique violation
-    match res {
+    let new_user_id = match res {
         Ok((new_user_id,)) => {
-            tracing::info!(id={new_user_id}, %username, "creating new user");
-            Ok(new_user_id)
+            tracing::info!(id=%new_user_id, %username, "creating new user");
+            new_user_id
         }
         Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
             // create plain user
-            let (new_user_id,) = sqlx::query_as::<_, (i32,)>(
+            let (new_user_id,) = sqlx::query_as::<_, (UserId,)>(
                 r#"
-                INSERT INTO user (username, display_name, inserted_at, updated_at)
-                VALUES (NULL, $1, $2, $2)
+                INSERT INTO user (username, display_name, mobiums, inserted_at, updated_at)
+                VALUES (NULL, $1, $2, $3, $3)
                 RETURNING id
                 "#,
             )
-            .bind(display_name)
+            .bind(&display_name)
+            .bind(starting_mobiums)
             .bind(now)
             .fetch_one(&mut *tx)
             .await?;
 
-            tracing::info!(id = { new_user_id }, "creating new user w/ null username");
-            Ok(new_user_id)
+            tracing::info!(id = %new_user_id, "creating new user w/ null username");
+            new_user_id
         }
-        Err(err) => Err(err.into()),
-    }
+        Err(err) => return Err(err.into()),
+    };
+
+    record(
+        new_user_id,
+        starting_mobiums,
+        LedgerReason::StartingBalance,
+        &mut *tx,
+    )
+    .await?;
+
+    Ok(new_user_id)
+}
+
+fn discord_avatar_url(remote_user: &DiscordUser) -> Option<String> {
+    remote_user.avatar.map(|avatar_hash| {
+        format!(
+            "https://cdn.discordapp.com/avatars/{}/{}.png",
+            remote_user.id, avatar_hash
+        )
+    })
 }
@@ -0,0 +1,123 @@
+//! Auto-bet endpoints.
+
+use axum::extract::State;
+
+use chrono::Utc;
+
+use http::StatusCode;
+
+use ring_channel_model::{
+    request::user::{DeleteAutoBet, UpdateAutoBet},
+    user::AutoBet,
+};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    session::{Session, SessionUser},
+    user::auto_bet::AutoBetSchema,
+};
+
+/// Shows the current user's auto-bet rule.
+pub async fn show(
+    user: SessionUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<AutoBet>, Error> {
+    let rule = sqlx::query_as::<_, AutoBetSchema>(
+        r#"
+        SELECT user_id, pick, mobiums, daily_cap, enabled, updated_at
+        FROM auto_bet
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user.identity())
+    .fetch_optional(&state.db)
+    .await?;
+
+    rule.map(|rule| AppJson(rule.into()))
+        .ok_or_else(|| Error::not_found("You don't have an auto-bet"))
+}
+
+/// Sets the current user's auto-bet rule, replacing the old one.
+pub async fn update(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateAutoBet>>,
+) -> Result<AppJson<AutoBet>, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let now = Utc::now();
+
+    let rule = sqlx::query_as::<_, AutoBetSchema>(
+        r#"
+        INSERT INTO auto_bet
+            (user_id, pick, mobiums, daily_cap, enabled, inserted_at, updated_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $6)
+        ON CONFLICT (user_id) DO UPDATE SET
+            pick = excluded.pick,
+            mobiums = excluded.mobiums,
+            daily_cap = excluded.daily_cap,
+            enabled = excluded.enabled,
+            updated_at = excluded.updated_at
+        RETURNING user_id, pick, mobiums, daily_cap, enabled, updated_at
+        "#,
+    )
+    .bind(user.identity())
+    .bind(u8::from(request.pick))
+    .bind(request.mobiums)
+    .bind(request.daily_cap)
+    .bind(request.enabled)
+    .bind(now)
+    .fetch_one(&state.db)
+    .await?;
+
+    audit.note(format!(
+        "set auto-bet to {} on {:?} (enabled: {})",
+        request.mobiums, request.pick, request.enabled
+    ));
+
+    session.shuffle_csrf().await?;
+
+    Ok(AppJson(rule.into()))
+}
+
+/// Deletes the current user's auto-bet rule.
+pub async fn delete(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<DeleteAutoBet>>,
+) -> Result<StatusCode, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM auto_bet
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user.identity())
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::not_found("You don't have an auto-bet"));
+    }
+
+    audit.note("deleted auto-bet");
+
+    session.shuffle_csrf().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,86 @@
+//! Account linking endpoints.
+
+use axum::extract::State;
+
+use http::StatusCode;
+
+use ring_channel_model::{
+    User,
+    message::server::MobiumsChange,
+    request::user::{CreateLinkCode, RedeemLinkCode},
+    user::LinkCode,
+};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    session::{Session, SessionUser},
+    user::link,
+};
+
+/// Generates a code to link the current account to another.
+pub async fn create_code(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreateLinkCode>>,
+) -> Result<(StatusCode, AppJson<LinkCode>), Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let mut conn = state.db.acquire().await?;
+
+    let code = link::create_code(user.identity(), &mut conn).await?;
+
+    audit.note("generated link code");
+
+    session.shuffle_csrf().await?;
+
+    Ok((StatusCode::CREATED, AppJson(code)))
+}
+
+/// Redeems a link code, merging the account that generated it into the
+/// current account.
+pub async fn redeem(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<RedeemLinkCode>>,
+) -> Result<AppJson<User>, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let from = link::redeem_code(&request.code, &mut tx).await?;
+    let mobiums = link::merge(from, user.identity(), &mut tx).await?;
+
+    tx.commit().await?;
+
+    audit.note(format!("merged user {} into {}", from, user.identity()));
+
+    session.shuffle_csrf().await?;
+
+    state.room.send_mobiums_change(
+        user.identity(),
+        MobiumsChange {
+            mobiums,
+            bailout: false,
+            streak: None,
+        },
+    );
+
+    // the merged user's stats are fetched fresh
+    let Some(user) = SessionUser::fetch(user.identity(), &state).await? else {
+        return Err(ErrorKind::InvalidSession.into());
+    };
+
+    Ok(AppJson(user.into_inner()))
+}
@@ -1,63 +1,183 @@
 //! Users endpoints.
 
 use axum::extract::State;
-use ring_channel_model::user::{CurrentUser, UserFlags};
+use chrono::{DateTime, Utc};
+use ring_channel_model::{
+    Mobiums, UserId,
+    display_name::to_display_name_lossy,
+    request::user::UpdateCurrentUser,
+    user::{CurrentUser, DiscordLink, LinkStatus, UserFlags},
+};
 use sqlx::FromRow;
 
 use crate::{
-    app::{AppJson, AppState},
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
     error::{Error, ErrorKind},
-    session::Session,
+    session::{Session, SessionUser},
+    user::{penalty::active_penalties, wager_balance},
 };
 
 pub mod auth;
+pub mod auto_bet;
+pub mod link;
+pub mod player;
+pub mod push;
 
 /// Returns the currently authenticated user's details.
 pub async fn show_me(
     session: Session,
     State(state): State<AppState>,
 ) -> Result<AppJson<CurrentUser>, Error> {
+    if let Some(identity) = session.identity {
+        fetch_current_user(identity, &state)
+            .await?
+            .map(AppJson)
+            .ok_or_else(|| ErrorKind::InvalidSession.into())
+    } else {
+        Err(ErrorKind::UserUnauthenticated.into())
+    }
+}
+
+/// Updates the currently authenticated user's settings.
+pub async fn update_me(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateCurrentUser>>,
+) -> Result<AppJson<CurrentUser>, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    if let Some(display_name) = request.display_name {
+        let display_name =
+            to_display_name_lossy(&display_name, state.config.server.max_display_name_length);
+        if display_name.is_empty() {
+            return Err(
+                ErrorKind::InvalidData("Display name has no usable characters".into()).into(),
+            );
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE user
+            SET display_name = $2, updated_at = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(user.identity())
+        .bind(&display_name)
+        .bind(Utc::now())
+        .execute(&state.db)
+        .await?;
+
+        audit.note(format!("set display_name to {:?}", display_name));
+    }
+
+    if let Some(show_wagers_publicly) = request.show_wagers_publicly {
+        sqlx::query(
+            r#"
+            UPDATE user
+            SET show_wagers_publicly = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(user.identity())
+        .bind(show_wagers_publicly)
+        .execute(&state.db)
+        .await?;
+
+        audit.note(format!(
+            "set show_wagers_publicly to {}",
+            show_wagers_publicly
+        ));
+    }
+
+    session.shuffle_csrf().await?;
+
+    fetch_current_user(user.identity(), &state)
+        .await?
+        .map(AppJson)
+        .ok_or_else(|| ErrorKind::InvalidSession.into())
+}
+
+/// Fetches a user's details as they would see them.
+///
+/// Returns `None` if the user does not exist, or was merged into another.
+async fn fetch_current_user(
+    identity: UserId,
+    state: &AppState,
+) -> Result<Option<CurrentUser>, Error> {
     #[derive(FromRow)]
     struct MaybeUserQuery {
         username: Option<String>,
         avatar: Option<String>,
         display_name: String,
-        mobiums: i64,
-        mobiums_gained: i64,
-        mobiums_lost: i64,
+        mobiums: Mobiums,
+        mobiums_gained: Mobiums,
+        mobiums_lost: Mobiums,
+        streak: i32,
         #[sqlx(try_from = "i32")]
         flags: UserFlags,
+        show_wagers_publicly: bool,
+        last_fetched_at: Option<DateTime<Utc>>,
+        dead_at: Option<DateTime<Utc>>,
     }
 
-    if let Some(identity) = session.identity {
-        // fetch identity
-        let user = sqlx::query_as::<_, MaybeUserQuery>(
-            r#"
-            SELECT
-                username, avatar, display_name, mobiums, mobiums_gained,
-                mobiums_lost, flags
-            FROM user
-            WHERE id = $1
-            "#,
-        )
-        .bind(identity)
-        .fetch_optional(&state.db)
-        .await?;
+    let mut conn = state.db.acquire().await?;
+
+    let user = sqlx::query_as::<_, MaybeUserQuery>(
+        r#"
+        SELECT
+            u.username, u.avatar, u.display_name, u.mobiums,
+            u.mobiums_gained, u.mobiums_lost, u.streak, u.flags, u.show_wagers_publicly,
+            da.last_fetched_at, da.dead_at
+        FROM user u
+        LEFT JOIN discord_auth da ON da.user_id = u.id
+        WHERE u.id = $1 AND u.merged_into IS NULL
+        "#,
+    )
+    .bind(identity)
+    .fetch_optional(&mut *conn)
+    .await?;
 
-        if let Some(user) = user {
-            Ok(AppJson(CurrentUser {
-                username: user.username,
-                avatar: user.avatar,
-                display_name: user.display_name,
-                mobiums: user.mobiums,
-                mobiums_gained: user.mobiums_gained,
-                mobiums_lost: user.mobiums_lost,
-                flags: user.flags,
-            }))
+    let Some(user) = user else {
+        return Ok(None);
+    };
+
+    let balance = wager_balance(identity, None, &mut conn).await?;
+    let penalties = active_penalties(identity, Utc::now(), &mut conn).await?;
+
+    let discord = user.last_fetched_at.map(|last_fetched_at| DiscordLink {
+        status: if user.dead_at.is_some() {
+            LinkStatus::Dead
         } else {
-            Err(ErrorKind::InvalidSession.into())
-        }
-    } else {
-        Err(ErrorKind::UserUnauthenticated.into())
-    }
+            LinkStatus::Active
+        },
+        last_fetched_at,
+    });
+
+    // users without a username can't be proxied
+    let avatar = match user.username.as_ref() {
+        Some(username) => state.avatar_url(username, user.avatar),
+        None => user.avatar,
+    };
+
+    Ok(Some(CurrentUser {
+        username: user.username,
+        avatar,
+        display_name: user.display_name,
+        mobiums: user.mobiums,
+        available_mobiums: balance.available,
+        mobiums_gained: user.mobiums_gained,
+        mobiums_lost: user.mobiums_lost,
+        streak: user.streak,
+        flags: user.flags,
+        show_wagers_publicly: user.show_wagers_publicly,
+        discord,
+        penalties,
+    }))
 }
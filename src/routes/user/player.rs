@@ -0,0 +1,286 @@
+//! Linked player endpoints.
+
+use axum::{
+    Extension,
+    extract::{Path, State},
+};
+
+use chrono::{DateTime, Utc};
+
+use http::StatusCode;
+
+use ring_channel_model::{
+    PlayerShortId,
+    battle::{BattleStatus, PlayerTeam, Visibility},
+    player::{LinkedPlayer, PlayerMatch},
+    request::user::{UnlinkPlayer, UpdateLinkedPlayer},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Model, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    player::{PlayerRow, change_short_id, mmr, name_filter::censor},
+    session::{Session, SessionUser},
+};
+
+/// How many of each player's matches are shown.
+pub const RECENT_MATCH_COUNT: i32 = 5;
+
+#[derive(FromRow)]
+struct LinkedPlayerQuery {
+    #[sqlx(flatten)]
+    player: PlayerRow,
+    linked_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+struct PlayerMatchQuery {
+    uuid: String,
+    level_name: String,
+    status: BattleStatus,
+    team: PlayerTeam,
+    finish_time: Option<i32>,
+    no_contest: bool,
+    mmr_before: Option<i32>,
+    mmr_after: Option<i32>,
+    inserted_at: DateTime<Utc>,
+}
+
+/// Lists the players linked to the current user.
+pub async fn list<T>(
+    user: SessionUser,
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+) -> Result<AppJson<Vec<LinkedPlayer>>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    let mut conn = state.db.acquire().await?;
+
+    let players = sqlx::query_as::<_, LinkedPlayerQuery>(
+        r#"
+        SELECT
+            p.id AS player_id,
+            p.short_id,
+            p.display_name,
+            p.rating,
+            p.deviation,
+            p.rating_extra,
+            (
+                SELECT COUNT(*)
+                FROM participant gp
+                INNER JOIN battle gb ON gb.id = gp.match_id
+                WHERE gp.player_id = p.id AND gb.status = $2
+            ) AS games_played,
+            p.linked_at
+        FROM player p
+        WHERE p.user_id = $1
+        ORDER BY p.linked_at
+        "#,
+    )
+    .bind(user.identity())
+    .bind(BattleStatus::Concluded)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut linked = Vec::with_capacity(players.len());
+
+    for LinkedPlayerQuery { player, linked_at } in players {
+        let recent_matches = recent_matches(player.id, &model, &mut conn).await?;
+
+        linked.push(LinkedPlayer {
+            player: player.normalize(&model)?,
+            linked_at,
+            recent_matches,
+        });
+    }
+
+    Ok(AppJson(linked))
+}
+
+/// Picks a new short id for a player linked to the current user.
+///
+/// The old short id keeps pointing to the player.
+pub async fn update<T>(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    Extension(model): Extension<Model<T>>,
+    State(state): State<AppState>,
+    Path((short_id,)): Path<(PlayerShortId,)>,
+    AppGarde(Payload(request)): AppGarde<Payload<UpdateLinkedPlayer>>,
+) -> Result<AppJson<LinkedPlayer>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let new_short_id = PlayerShortId::from(request.short_id.to_ascii_uppercase().as_str());
+
+    // short ids show up next to display names, so the same words are kept out
+    if censor(&new_short_id, &state.config.server.name_filter).is_some() {
+        return Err(
+            ErrorKind::InvalidData(format!("Short id {} is not allowed", new_short_id)).into(),
+        );
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    let player = sqlx::query_as::<_, LinkedPlayerQuery>(
+        r#"
+        SELECT
+            p.id AS player_id,
+            p.short_id,
+            p.display_name,
+            p.rating,
+            p.deviation,
+            p.rating_extra,
+            (
+                SELECT COUNT(*)
+                FROM participant gp
+                INNER JOIN battle gb ON gb.id = gp.match_id
+                WHERE gp.player_id = p.id AND gb.status = $3
+            ) AS games_played,
+            p.linked_at
+        FROM player p
+        WHERE
+            (p.short_id = $1 OR p.id = (SELECT player_id FROM player_short_id_alias WHERE short_id = $1))
+            AND p.user_id = $2
+        "#,
+    )
+    .bind(&short_id)
+    .bind(user.identity())
+    .bind(BattleStatus::Concluded)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(LinkedPlayerQuery {
+        mut player,
+        linked_at,
+    }) = player
+    else {
+        return Err(Error::not_found(format!(
+            "Player {} is not linked to you",
+            short_id
+        )));
+    };
+
+    if player.short_id != new_short_id {
+        change_short_id(&player, &new_short_id, &mut tx).await?;
+
+        audit.note(format!(
+            "changed short id of player {} to {}",
+            player.short_id, new_short_id
+        ));
+
+        player.short_id = new_short_id;
+    }
+
+    let recent_matches = recent_matches(player.id, &model, &mut tx).await?;
+
+    tx.commit().await?;
+
+    session.shuffle_csrf().await?;
+
+    Ok(AppJson(LinkedPlayer {
+        player: player.normalize(&model)?,
+        linked_at,
+        recent_matches,
+    }))
+}
+
+/// Unlinks a player from the current user.
+pub async fn delete(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    Path((short_id,)): Path<(PlayerShortId,)>,
+    AppGarde(Payload(request)): AppGarde<Payload<UnlinkPlayer>>,
+) -> Result<StatusCode, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE player
+        SET user_id = NULL, linked_at = NULL
+        WHERE
+            (short_id = $1 OR id = (SELECT player_id FROM player_short_id_alias WHERE short_id = $1))
+            AND user_id = $2
+        "#,
+    )
+    .bind(&short_id)
+    .bind(user.identity())
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::not_found(format!(
+            "Player {} is not linked to you",
+            short_id
+        )));
+    }
+
+    audit.note(format!("unlinked player {}", short_id));
+
+    session.shuffle_csrf().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetches a player's most recent public matches.
+async fn recent_matches<T>(
+    player_id: i32,
+    model: &Model<T>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<PlayerMatch>, Error>
+where
+    T: mmr::Model + 'static,
+{
+    let matches = sqlx::query_as::<_, PlayerMatchQuery>(
+        r#"
+        SELECT
+            b.uuid, b.level_name, b.status, b.inserted_at,
+            pt.team, pt.finish_time, pt.no_contest, pt.mmr_before, pt.mmr_after
+        FROM participant pt
+        INNER JOIN battle b ON b.id = pt.match_id
+        WHERE pt.player_id = $1 AND b.visibility = $2
+        ORDER BY b.inserted_at DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(player_id)
+    .bind(u8::from(Visibility::Public))
+    .bind(RECENT_MATCH_COUNT)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(matches
+        .into_iter()
+        .map(|m| PlayerMatch {
+            id: m.uuid,
+            level_name: m.level_name,
+            status: m.status,
+            team: m.team,
+            finish_time: m.finish_time,
+            no_contest: m.no_contest,
+            mmr_delta: if model.ratings_enabled() {
+                m.mmr_before
+                    .zip(m.mmr_after)
+                    .map(|(before, after)| after - before)
+            } else {
+                None
+            },
+            started_at: m.inserted_at,
+        })
+        .collect())
+}
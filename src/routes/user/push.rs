@@ -0,0 +1,167 @@
+//! Push notification endpoints.
+
+use axum::extract::State;
+
+use chrono::Utc;
+
+use http::StatusCode;
+
+use reqwest::Url;
+
+use ring_channel_model::{
+    push::{PushSubscription, VapidKey},
+    request::user::{CreatePushSubscription, DeletePushSubscription},
+};
+
+use crate::{
+    app::{AppGarde, AppJson, AppState, Payload},
+    audit::Audit,
+    error::{Error, ErrorKind},
+    push::{decode_keys, is_push_service},
+    session::{Session, SessionUser},
+};
+
+/// The most browsers a user can have subscribed at once.
+///
+/// Subscribing another browser drops the oldest one.
+pub const MAX_PUSH_SUBSCRIPTIONS: i32 = 10;
+
+/// Shows the server's VAPID public key, for browsers to subscribe with.
+pub async fn vapid_key(State(state): State<AppState>) -> Result<AppJson<VapidKey>, Error> {
+    let Some(push) = state.config.push.as_ref() else {
+        return Err(Error::not_found("Push notifications are disabled"));
+    };
+
+    Ok(AppJson(VapidKey {
+        public_key: push.public_key.clone(),
+    }))
+}
+
+/// Subscribes a browser to the current user's push notifications.
+///
+/// A browser that was subscribed for another user is moved over.
+pub async fn create(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<CreatePushSubscription>>,
+) -> Result<(StatusCode, AppJson<PushSubscription>), Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    let Some(push) = state.config.push.as_ref() else {
+        return Err(Error::not_found("Push notifications are disabled"));
+    };
+
+    // the server posts to the endpoint, so only the configured push services
+    // are let in
+    let endpoint = Url::parse(&request.endpoint)
+        .ok()
+        .filter(|url| is_push_service(url, &push.services))
+        .ok_or_else(|| {
+            ErrorKind::InvalidData(format!("Invalid push endpoint {}", request.endpoint))
+        })?;
+    decode_keys(&request.keys.p256dh, &request.keys.auth)?;
+
+    let now = Utc::now();
+
+    let mut tx = state.db.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO push_subscription (user_id, endpoint, p256dh, auth, inserted_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (endpoint) DO UPDATE SET
+            user_id = excluded.user_id,
+            p256dh = excluded.p256dh,
+            auth = excluded.auth,
+            inserted_at = excluded.inserted_at
+        "#,
+    )
+    .bind(user.identity())
+    .bind(endpoint.as_str())
+    .bind(&request.keys.p256dh)
+    .bind(&request.keys.auth)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM push_subscription
+        WHERE
+            user_id = $1
+            AND id NOT IN (
+                SELECT id
+                FROM push_subscription
+                WHERE user_id = $1
+                ORDER BY inserted_at DESC, id DESC
+                LIMIT $2
+            )
+        "#,
+    )
+    .bind(user.identity())
+    .bind(MAX_PUSH_SUBSCRIPTIONS)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    audit.note(format!("subscribed to push notifications at {}", endpoint));
+
+    session.shuffle_csrf().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        AppJson(PushSubscription {
+            endpoint: endpoint.into(),
+            created_at: now,
+        }),
+    ))
+}
+
+/// Unsubscribes a browser from the current user's push notifications.
+pub async fn delete(
+    user: SessionUser,
+    mut session: Session,
+    audit: Audit,
+    State(state): State<AppState>,
+    AppGarde(Payload(request)): AppGarde<Payload<DeletePushSubscription>>,
+) -> Result<StatusCode, Error> {
+    // reject any suspicious requests
+    if session.csrf != request.csrf {
+        return Err(ErrorKind::InvalidCsrfToken.into());
+    }
+
+    // endpoints are stored normalized
+    let endpoint = Url::parse(&request.endpoint)
+        .map(String::from)
+        .unwrap_or(request.endpoint);
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM push_subscription
+        WHERE user_id = $1 AND endpoint = $2
+        "#,
+    )
+    .bind(user.identity())
+    .bind(&endpoint)
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::not_found("That browser isn't subscribed"));
+    }
+
+    audit.note(format!(
+        "unsubscribed from push notifications at {}",
+        endpoint
+    ));
+
+    session.shuffle_csrf().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,21 +1,142 @@
 //! WebSocket gateway.
 
+use std::sync::LazyLock;
+
 use axum::{
-    extract::{State, WebSocketUpgrade},
+    extract::{Query, State, WebSocketUpgrade},
     response::Response,
 };
 
-use crate::{app::AppState, error::Error, session::SessionUser};
+use http::{HeaderMap, header};
+
+use reqwest::Url;
+
+use ring_channel_model::{message, response::SocketTicket};
+
+use schemars::Schema;
+
+use serde::Deserialize;
+
+use crate::{
+    app::{AppJson, AppState},
+    config::ServerConfig,
+    error::{Error, ErrorKind},
+    room::{GZIP_SUBPROTOCOL, TICKET_LIFETIME},
+    session::{Session, SessionUser},
+};
+
+/// The schema of every socket message, generated on first use.
+static SCHEMA: LazyLock<Schema> = LazyLock::new(message::schema);
+
+/// Query for [`handler`].
+#[derive(Debug, Deserialize)]
+pub struct SocketQuery {
+    /// The session to resume, from a previous `hello`.
+    pub session: Option<String>,
+}
 
 /// Establishes a connection to the websocket gateway.
+///
+/// The user is fetched once the socket is served, since resumed sessions
+/// already know who they are. New sockets are turned away while the room is
+/// draining.
+///
+/// Browsers send cookies along with sockets opened by any site, so the
+/// session cookie is only trusted from the API's or the frontend's origin.
+/// Sockets from anywhere else have to authenticate with a ticket.
 #[axum::debug_handler]
 pub async fn handler(
-    user: Result<SessionUser, Error>,
+    session: Result<Session, Error>,
+    headers: HeaderMap,
+    Query(query): Query<SocketQuery>,
     State(state): State<AppState>,
     ws: WebSocketUpgrade,
-) -> Response {
-    ws.on_failed_upgrade(|error| {
-        tracing::error!("failed to upgrade websocket: {}", error);
-    })
-    .on_upgrade(move |websocket| state.room.serve(websocket, user.ok()))
+) -> Result<Response, Error> {
+    if state.room.is_draining() {
+        return Err(ErrorKind::Draining.into());
+    }
+
+    // clients that aren't browsers don't send an origin
+    let trusted = headers
+        .get(header::ORIGIN)
+        .is_none_or(|origin| is_trusted_origin(origin.as_bytes(), &state.config.server));
+
+    let identity = session
+        .ok()
+        .and_then(|session| session.identity)
+        .filter(|_| trusted);
+
+    let ws = if state.config.http.websocket.compression.enabled {
+        ws.protocols([GZIP_SUBPROTOCOL])
+    } else {
+        ws
+    };
+
+    Ok(ws
+        .on_failed_upgrade(|error| {
+            tracing::error!("failed to upgrade websocket: {}", error);
+        })
+        .on_upgrade(move |websocket| {
+            let room = state.room.clone();
+            room.serve(state, websocket, identity, query.session)
+        }))
+}
+
+/// Checks if an `Origin` is the API's, or the frontend's.
+fn is_trusted_origin(origin: &[u8], config: &ServerConfig) -> bool {
+    let Some(origin) = std::str::from_utf8(origin)
+        .ok()
+        .and_then(|origin| Url::parse(origin).ok())
+    else {
+        return false;
+    };
+
+    [Some(&config.base_url), config.redirect_url.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter_map(|url| Url::parse(url).ok())
+        .any(|url| url.origin() == origin.origin())
+}
+
+/// Issues a ticket to authenticate a socket with.
+///
+/// For clients that can't send the session cookie when connecting.
+pub async fn create_ticket(
+    user: SessionUser,
+    State(state): State<AppState>,
+) -> Result<AppJson<SocketTicket>, Error> {
+    let ticket = state.room.issue_ticket(user.identity());
+
+    Ok(AppJson(SocketTicket {
+        ticket,
+        expires_in: TICKET_LIFETIME.as_millis() as i64,
+    }))
+}
+
+/// Serves a JSON Schema of every client and server message.
+///
+/// Messages are tagged by `op`, and carry their data in `d`. The schema is
+/// versioned with the socket protocol.
+pub async fn schema() -> AppJson<&'static Schema> {
+    AppJson(&SCHEMA)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_trusted_origin() {
+        let config = ServerConfig {
+            base_url: "https://api.example.com/".into(),
+            redirect_url: Some("https://example.com/matches".into()),
+            ..Default::default()
+        };
+
+        assert!(is_trusted_origin(b"https://api.example.com", &config));
+        assert!(is_trusted_origin(b"https://example.com", &config));
+        assert!(!is_trusted_origin(b"http://example.com", &config));
+        assert!(!is_trusted_origin(b"https://evil.example.com", &config));
+        assert!(!is_trusted_origin(b"null", &config));
+    }
 }
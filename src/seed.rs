@@ -0,0 +1,556 @@
+//! Development data.
+//!
+//! Fills an empty database with made-up players, users and matches, so the
+//! frontend has something to show without a copy of production. Matches are
+//! spread over the past few days, wagered on and settled, and ratings are
+//! rebuilt by replaying them.
+
+use std::fmt::Debug;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use rand::{
+    Rng, SeedableRng,
+    rngs::StdRng,
+    seq::{IndexedRandom as _, SliceRandom as _},
+};
+
+use ring_channel_model::{
+    BattleId, Rrid, UserId,
+    battle::{BattleStatus, OddsMode, PlayerTeam, Visibility},
+    user::UserFlags,
+};
+
+use sqlx::SqliteConnection;
+
+use uuid::Uuid;
+
+use crate::{
+    auth::api_key::{generate_api_key, hash_api_key},
+    error::Error,
+    payout::{self, Pots, Stake},
+    player::{
+        create_player_with,
+        mmr::{Model, init_rating, simulate},
+    },
+};
+
+/// The name of the server seeded matches are played on.
+pub const SEED_SERVER_NAME: &str = "seed";
+
+/// How many tics are in a second.
+const TICS_PER_SECOND: i32 = 35;
+
+/// What bankrupt users are bailed out with.
+const BAILOUT_MOBIUMS: i64 = 100;
+
+const LEVELS: [&str; 8] = [
+    "Withering Chateau Zone",
+    "Sunbeam Paradise Zone",
+    "Robotnik Coaster Zone",
+    "Emerald Coast Zone",
+    "Aerospace Zone",
+    "Lucid Pass Zone",
+    "Hardhat Havoc Zone",
+    "Popcorn Workshop Zone",
+];
+
+const ADJECTIVES: [&str; 16] = [
+    "Swift", "Lucky", "Quiet", "Spiny", "Brave", "Rusty", "Turbo", "Sleepy", "Golden", "Hasty",
+    "Mighty", "Sneaky", "Dizzy", "Frosty", "Bouncy", "Crimson",
+];
+
+const NOUNS: [&str; 16] = [
+    "Echidna",
+    "Hedgehog",
+    "Fox",
+    "Bat",
+    "Chao",
+    "Badnik",
+    "Wisp",
+    "Flicky",
+    "Bee",
+    "Crab",
+    "Armadillo",
+    "Chameleon",
+    "Hawk",
+    "Weasel",
+    "Cat",
+    "Rabbit",
+];
+
+/// What to seed.
+#[derive(Clone, Debug)]
+pub struct SeedOptions {
+    /// How many players to create.
+    pub players: usize,
+    /// How many matches to play out.
+    pub matches: usize,
+    /// How many users to create.
+    pub users: usize,
+    /// How many days the matches are spread over, ending now.
+    pub days: u32,
+    /// The seed of the generator, for reproducible data.
+    pub seed: Option<u64>,
+}
+
+/// What was seeded.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SeedReport {
+    pub players: usize,
+    pub users: usize,
+    pub matches: usize,
+    pub wagers: usize,
+    pub bailouts: usize,
+}
+
+struct SeedPlayer {
+    id: i32,
+    /// How good the player really is, as a standard score.
+    skill: f64,
+}
+
+struct SeedUser {
+    id: UserId,
+    mobiums: i64,
+    mobiums_gained: i64,
+    mobiums_lost: i64,
+    bailout_count: i32,
+    streak: i32,
+    /// Whether the user is never bailed out.
+    unlimited: bool,
+    /// How likely the user is to wager on a match.
+    activity: f64,
+    /// How likely the user is to back the better team.
+    insight: f64,
+}
+
+struct SeedWager {
+    id: i32,
+    user: usize,
+    stake: Stake,
+}
+
+/// Seeds a database.
+///
+/// Ratings of every player are rebuilt from the match history, so this should
+/// only be run on an empty database.
+pub async fn seed<T>(
+    options: &SeedOptions,
+    model: &T,
+    conn: &mut SqliteConnection,
+) -> Result<SeedReport, Error>
+where
+    T: Model + Debug,
+    T::Data: Debug,
+{
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+
+    let now = Utc::now();
+    let started_at = now - TimeDelta::days(options.days.into());
+
+    let mut report = SeedReport::default();
+
+    let server_id = seed_server(now, conn).await?;
+
+    let mut players = Vec::with_capacity(options.players);
+    for _ in 0..options.players {
+        let mut public_key = [0u8; 32];
+        rng.fill(&mut public_key);
+        let public_key = Rrid::new(base16::encode_lower(&public_key)).map_err(Error::new)?;
+
+        let display_name = format!(
+            "{}{}",
+            ADJECTIVES.choose(&mut rng).unwrap(),
+            NOUNS.choose(&mut rng).unwrap()
+        );
+        let player = create_player_with(&public_key, &display_name, conn, &mut rng).await?;
+
+        players.push(SeedPlayer {
+            id: player.id,
+            skill: standard_normal(&mut rng),
+        });
+    }
+    report.players = players.len();
+
+    let mut users = Vec::with_capacity(options.users);
+    for i in 0..options.users {
+        let adjective = ADJECTIVES.choose(&mut rng).unwrap();
+        let noun = NOUNS.choose(&mut rng).unwrap();
+        let username = format!("{}{}{}", adjective.to_lowercase(), noun.to_lowercase(), i);
+        let display_name = format!("{} {}", adjective, noun);
+
+        // the first user gets to poke around the admin pages
+        let mut flags = UserFlags::empty();
+        if i == 0 {
+            flags |= UserFlags::ADMINISTRATOR;
+        }
+        if rng.random_bool(0.1) {
+            flags |= UserFlags::BETA_TESTER;
+        }
+        if rng.random_bool(0.05) {
+            flags |= UserFlags::UNLIMITED_WAGERS;
+        }
+
+        let inserted_at = started_at - TimeDelta::hours(rng.random_range(1..72));
+        let mobiums = *[400, 400, 400, 1_000, 2_500, 10_000]
+            .choose(&mut rng)
+            .unwrap();
+
+        let (id,) = sqlx::query_as::<_, (UserId,)>(
+            r#"
+            INSERT INTO user
+                (username, display_name, mobiums, flags, verified_at, inserted_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            RETURNING id
+            "#,
+        )
+        .bind(&username)
+        .bind(&display_name)
+        .bind(mobiums)
+        .bind(i32::from(flags))
+        .bind(rng.random_bool(0.5).then_some(inserted_at))
+        .bind(inserted_at)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        // some users play too
+        if let Some(player) = players.get(i).filter(|_| rng.random_bool(0.3)) {
+            sqlx::query(
+                r#"
+                UPDATE player
+                SET user_id = $2, linked_at = $3
+                WHERE id = $1
+                "#,
+            )
+            .bind(player.id)
+            .bind(id)
+            .bind(inserted_at)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        users.push(SeedUser {
+            id,
+            mobiums,
+            mobiums_gained: 0,
+            mobiums_lost: 0,
+            bailout_count: 0,
+            streak: 0,
+            unlimited: flags.contains(UserFlags::UNLIMITED_WAGERS),
+            activity: rng.random_range(0.1..0.9),
+            insight: rng.random_range(0.4..0.8),
+        });
+    }
+    report.users = users.len();
+
+    if players.len() >= 2 {
+        let spacing = (now - started_at) / (options.matches.max(1) as i32);
+
+        for i in 0..options.matches {
+            let jitter = TimeDelta::seconds(rng.random_range(0..=spacing.num_seconds().max(1) / 2));
+            let inserted_at = started_at + spacing * (i as i32) + jitter;
+
+            let (wagers, bailouts) =
+                seed_battle(server_id, inserted_at, &players, &mut users, &mut rng, conn).await?;
+
+            report.matches += 1;
+            report.wagers += wagers;
+            report.bailouts += bailouts;
+        }
+    }
+
+    for user in &users {
+        sqlx::query(
+            r#"
+            UPDATE user
+            SET
+                mobiums = $2,
+                mobiums_gained = $3,
+                mobiums_lost = $4,
+                bailout_count = $5,
+                streak = $6
+            WHERE id = $1
+            "#,
+        )
+        .bind(user.id)
+        .bind(user.mobiums)
+        .bind(user.mobiums_gained)
+        .bind(user.mobiums_lost)
+        .bind(user.bailout_count)
+        .bind(user.streak)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // players are rated in the order they played
+    simulate::simulate(model, &mut *conn).await?;
+
+    // the rest never played, but are rated all the same
+    let unrated = sqlx::query_as::<_, (i32,)>("SELECT id FROM player WHERE rating IS NULL")
+        .fetch_all(&mut *conn)
+        .await?;
+    for (player_id,) in unrated {
+        init_rating(player_id, model, &mut *conn).await?;
+    }
+
+    Ok(report)
+}
+
+async fn seed_server(now: DateTime<Utc>, conn: &mut SqliteConnection) -> Result<i32, Error> {
+    // nobody needs the key, it's just here for the matches
+    let (server_id,) = sqlx::query_as::<_, (i32,)>(
+        r#"
+        INSERT INTO server (server_name, key_hash, inserted_at, updated_at)
+        VALUES ($1, $2, $3, $3)
+        ON CONFLICT (server_name) DO UPDATE SET updated_at = $3
+        RETURNING id
+        "#,
+    )
+    .bind(SEED_SERVER_NAME)
+    .bind(hash_api_key(generate_api_key()))
+    .bind(now)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(server_id)
+}
+
+/// Plays out a match, and settles its wagers.
+///
+/// Returns how many wagers were placed, and how many users were bailed out.
+async fn seed_battle<R>(
+    server_id: i32,
+    inserted_at: DateTime<Utc>,
+    players: &[SeedPlayer],
+    users: &mut [SeedUser],
+    rng: &mut R,
+    conn: &mut SqliteConnection,
+) -> Result<(usize, usize), Error>
+where
+    R: Rng,
+{
+    // mostly duels, with the odd team match
+    let team_size = if players.len() >= 4 && rng.random_bool(0.15) {
+        2
+    } else {
+        1
+    };
+
+    let mut lineup = players
+        .choose_multiple(rng, team_size * 2)
+        .collect::<Vec<_>>();
+    lineup.shuffle(rng);
+
+    let closed_at = inserted_at + TimeDelta::seconds(20);
+
+    // better players finish faster, most of the time
+    let mut finish_times = lineup
+        .iter()
+        .map(|player| {
+            let seconds = 120.0 - player.skill * 6.0 + standard_normal(rng) * 8.0;
+            (seconds.max(60.0) * TICS_PER_SECOND as f64) as i32
+        })
+        .collect::<Vec<_>>();
+
+    let cancelled = rng.random_bool(0.03);
+    if cancelled {
+        // someone quit partway
+        let quitter = rng.random_range(0..finish_times.len());
+        finish_times[quitter] = -1;
+    }
+
+    let race_time = finish_times.iter().copied().max().unwrap_or_default();
+    let concluded_at = closed_at + TimeDelta::seconds((race_time / TICS_PER_SECOND).into());
+
+    let status = if cancelled {
+        BattleStatus::Cancelled
+    } else {
+        BattleStatus::Concluded
+    };
+
+    let (battle_id,) = sqlx::query_as::<_, (BattleId,)>(
+        r#"
+        INSERT INTO battle
+            (
+                uuid, level_name, server_id, inserted_at, closed_at, concluded_at, status,
+                updated_at, odds_mode, visibility
+            )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $6, $8, $9)
+        RETURNING id
+        "#,
+    )
+    .bind(Uuid::new_v4().hyphenated().to_string())
+    .bind(LEVELS.choose(rng).unwrap())
+    .bind(server_id)
+    .bind(inserted_at)
+    .bind(closed_at)
+    .bind(concluded_at)
+    .bind(status)
+    .bind(u8::from(OddsMode::Pool))
+    .bind(u8::from(Visibility::Public))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut red_skill = 0.0;
+    let mut blue_skill = 0.0;
+    let mut winner = None::<(PlayerTeam, i32)>;
+
+    for (i, (player, finish_time)) in lineup.iter().zip(&finish_times).enumerate() {
+        let team = if i < team_size {
+            PlayerTeam::Red
+        } else {
+            PlayerTeam::Blue
+        };
+        let finish_time = (*finish_time >= 0).then_some(*finish_time);
+
+        match team {
+            PlayerTeam::Red => red_skill += player.skill,
+            PlayerTeam::Blue => blue_skill += player.skill,
+        }
+
+        if let Some(finish_time) = finish_time
+            && winner.is_none_or(|(_, fastest)| finish_time < fastest)
+        {
+            winner = Some((team, finish_time));
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO participant (match_id, player_id, team, finish_time, no_contest)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(battle_id)
+        .bind(player.id)
+        .bind(team)
+        .bind(finish_time)
+        .bind(finish_time.is_none())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let favourite = if red_skill >= blue_skill {
+        PlayerTeam::Red
+    } else {
+        PlayerTeam::Blue
+    };
+
+    let mut wagers = Vec::new();
+    for (i, user) in users.iter().enumerate() {
+        if user.mobiums <= 0 || !rng.random_bool(user.activity) {
+            continue;
+        }
+
+        let victor = if rng.random_bool(user.insight) {
+            favourite
+        } else {
+            opponent(favourite)
+        };
+        let share = *[0.02, 0.05, 0.1, 0.2, 0.5].choose(rng).unwrap();
+        let mobiums = ((user.mobiums as f64 * share) as i64).max(1);
+        let wagered_at = inserted_at + (closed_at - inserted_at) * rng.random_range(0..100) / 100;
+
+        let (id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            INSERT INTO wager (user_id, match_id, victor, mobiums, inserted_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $5)
+            RETURNING id
+            "#,
+        )
+        .bind(user.id)
+        .bind(battle_id)
+        .bind(victor)
+        .bind(mobiums)
+        .bind(wagered_at)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        wagers.push(SeedWager {
+            id,
+            user: i,
+            stake: Stake {
+                victor,
+                mobiums,
+                odds: None,
+            },
+        });
+    }
+
+    // cancelled matches and one-sided pots are never settled
+    let pots = Pots::from_stakes(wagers.iter().map(|wager| &wager.stake));
+    let Some((winner, _)) = winner.filter(|_| !cancelled && pots.red > 0 && pots.blue > 0) else {
+        return Ok((wagers.len(), 0));
+    };
+
+    let strategy = payout::strategy(OddsMode::Pool);
+    let mut bailouts = Vec::new();
+
+    for wager in &wagers {
+        let user = &mut users[wager.user];
+
+        let change = if wager.stake.victor == winner {
+            strategy.payout(&wager.stake, &pots) - wager.stake.mobiums
+        } else {
+            -wager.stake.mobiums
+        };
+
+        user.mobiums += change;
+        user.mobiums_gained += change.max(0);
+        user.mobiums_lost += (-change).max(0);
+        user.streak = if wager.stake.victor == winner {
+            user.streak + 1
+        } else {
+            0
+        };
+
+        if user.mobiums <= 0 && !user.unlimited {
+            user.mobiums = BAILOUT_MOBIUMS;
+            user.bailout_count += 1;
+            bailouts.push(user.id);
+        }
+
+        sqlx::query("UPDATE wager SET payout = $2 WHERE id = $1")
+            .bind(wager.id)
+            .bind(change)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    for user_id in &bailouts {
+        sqlx::query(
+            r#"
+            INSERT INTO bailout (user_id, match_id, inserted_at)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(battle_id)
+        .bind(concluded_at)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok((wagers.len(), bailouts.len()))
+}
+
+fn opponent(team: PlayerTeam) -> PlayerTeam {
+    match team {
+        PlayerTeam::Red => PlayerTeam::Blue,
+        PlayerTeam::Blue => PlayerTeam::Red,
+    }
+}
+
+/// Samples a standard normal distribution.
+fn standard_normal<R>(rng: &mut R) -> f64
+where
+    R: Rng,
+{
+    // Box-Muller
+    let u = 1.0 - rng.random::<f64>();
+    let v = rng.random::<f64>();
+    (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+}
@@ -2,14 +2,14 @@
 
 use axum::{
     RequestPartsExt as _,
-    extract::{FromRef, FromRequestParts},
+    extract::{FromRef, FromRequestParts, OptionalFromRequestParts},
 };
 
 use cookie::{Cookie, SameSite};
 
 use derive_more::Deref;
 
-use ring_channel_model::{User, user::UserFlags};
+use ring_channel_model::{Mobiums, User, UserId, admin::ActorKind, user::UserFlags};
 
 use sqlx::FromRow;
 
@@ -32,6 +32,7 @@ use tower_sessions::Session as TowerSession;
 
 use crate::{
     app::AppState,
+    audit,
     error::{Error, ErrorKind},
 };
 
@@ -63,7 +64,10 @@ pub struct SessionData {
     ///
     /// This is the user's ID in the database. If this is `None`, this is an
     /// anonymous session.
-    pub identity: Option<i32>,
+    pub identity: Option<UserId>,
+    /// The PKCE code verifier of an ongoing OAuth2 flow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pkce_verifier: Option<String>,
 }
 
 impl Session {
@@ -74,13 +78,33 @@ impl Session {
     ///
     /// **Only call this if you are confident the user has followed the proper
     /// authentication flow!**
-    pub async fn set_user(&mut self, user_id: i32) -> Result<(), SessionError> {
+    pub async fn set_user(&mut self, user_id: UserId) -> Result<(), SessionError> {
         self.data.identity = Some(user_id);
         self.update_data().await?;
 
         Ok(())
     }
 
+    /// Sets the PKCE code verifier of the OAuth2 flow.
+    pub async fn set_pkce_verifier(
+        &mut self,
+        pkce_verifier: Option<String>,
+    ) -> Result<(), SessionError> {
+        self.data.pkce_verifier = pkce_verifier;
+        self.update_data().await
+    }
+
+    /// Takes the PKCE code verifier of the OAuth2 flow, so it can't be used
+    /// again.
+    pub async fn take_pkce_verifier(&mut self) -> Result<Option<String>, SessionError> {
+        let pkce_verifier = self.data.pkce_verifier.take();
+        if pkce_verifier.is_some() {
+            self.update_data().await?;
+        }
+
+        Ok(pkce_verifier)
+    }
+
     /// Shuffles the CSRF token.
     ///
     /// When a mutation is finished on the server, this should always be
@@ -142,6 +166,7 @@ where
                 state: generate_csrf(),
                 csrf: generate_csrf(),
                 identity: None,
+                pkce_verifier: None,
             };
             session.insert(Session::SESSION_KEY, &session_data).await?;
             session_data
@@ -163,7 +188,7 @@ where
 pub struct SessionUser {
     #[deref]
     user: User,
-    identity: i32,
+    identity: UserId,
 }
 
 impl SessionUser {
@@ -176,75 +201,148 @@ impl SessionUser {
     ///
     /// This is simply a copy of [`SessionData::identity`], but you don't have
     /// to work with an [`Option`].
-    pub fn identity(&self) -> i32 {
+    pub fn identity(&self) -> UserId {
         self.identity
     }
-}
 
-impl<S> FromRequestParts<S> for SessionUser
-where
-    AppState: FromRef<S>,
-    S: Send + Sync,
-{
-    type Rejection = Error;
+    /// Whether this user can see who made another user's private wagers.
+    ///
+    /// Users can always see their own wagers, and administrators can see
+    /// everyone's.
+    pub fn can_see_wagers_of(&self, user_id: UserId) -> bool {
+        self.identity == user_id || self.flags.contains(UserFlags::ADMINISTRATOR)
+    }
 
-    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    /// Fetches an authenticated user by their identity.
+    ///
+    /// Returns `None` if the user does not exist, or hasn't set their username
+    /// yet.
+    pub async fn fetch(identity: UserId, state: &AppState) -> Result<Option<SessionUser>, Error> {
         #[derive(FromRow)]
         struct UserQuery {
             username: String,
             avatar: Option<String>,
             display_name: String,
-            mobiums: i64,
-            mobiums_gained: i64,
-            mobiums_lost: i64,
+            mobiums: Mobiums,
+            mobiums_gained: Mobiums,
+            mobiums_lost: Mobiums,
+            streak: i32,
             #[sqlx(try_from = "i32")]
             flags: UserFlags,
         }
 
+        let user = sqlx::query_as::<_, UserQuery>(
+            r#"
+            SELECT
+                username, avatar, display_name, mobiums, mobiums_gained,
+                mobiums_lost, streak, flags
+            FROM
+                user
+            WHERE
+                id = $1
+                AND username IS NOT NULL
+                AND merged_into IS NULL
+            "#,
+        )
+        .bind(identity)
+        .fetch_optional(&state.db)
+        .await?;
+
+        Ok(user.map(|user| SessionUser {
+            user: User {
+                avatar: state.avatar_url(&user.username, user.avatar),
+                username: user.username,
+                display_name: user.display_name,
+                mobiums: user.mobiums,
+                mobiums_gained: user.mobiums_gained,
+                mobiums_lost: user.mobiums_lost,
+                streak: user.streak,
+                flags: user.flags,
+            },
+            identity,
+        }))
+    }
+}
+
+impl<S> FromRequestParts<S> for SessionUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let session = parts.extract_with_state::<Session, S>(state).await?;
 
         let state = AppState::from_ref(state);
 
         if let Some(identity) = session.identity {
-            // fetch identity
-            let user = sqlx::query_as::<_, UserQuery>(
-                r#"
-                SELECT
-                    username, avatar, display_name, mobiums, mobiums_gained,
-                    mobiums_lost, flags
-                FROM
-                    user
-                WHERE
-                    id = $1
-                    AND username IS NOT NULL
-                "#,
-            )
-            .bind(identity)
-            .fetch_optional(&state.db)
-            .await?;
-
-            if let Some(user) = user {
-                Ok(SessionUser {
-                    user: User {
-                        username: user.username,
-                        avatar: user.avatar,
-                        display_name: user.display_name,
-                        mobiums: user.mobiums,
-                        mobiums_gained: user.mobiums_gained,
-                        mobiums_lost: user.mobiums_lost,
-                        flags: user.flags,
-                    },
-                    identity,
-                })
-            } else {
-                Err(ErrorKind::InvalidSession.into())
-            }
+            let user = SessionUser::fetch(identity, &state)
+                .await?
+                .ok_or(ErrorKind::InvalidSession)?;
+
+            audit::set_actor(parts, ActorKind::User, identity);
+
+            Ok(user)
         } else {
             Err(ErrorKind::UserUnauthenticated.into())
         }
     }
 }
 
+impl<S> OptionalFromRequestParts<S> for SessionUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        let session = parts.extract_with_state::<Session, S>(state).await?;
+
+        let state = AppState::from_ref(state);
+
+        // stale sessions are treated as logged out
+        let Some(identity) = session.identity else {
+            return Ok(None);
+        };
+        let user = SessionUser::fetch(identity, &state).await?;
+
+        if user.is_some() {
+            audit::set_actor(parts, ActorKind::User, identity);
+        }
+
+        Ok(user)
+    }
+}
+
+/// An authenticated user with the [`UserFlags::ADMINISTRATOR`] flag.
+#[derive(Clone, Debug, Deref)]
+pub struct AdminUser(SessionUser);
+
+impl<S> FromRequestParts<S> for AdminUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = parts.extract_with_state::<SessionUser, S>(state).await?;
+
+        if user.flags.contains(UserFlags::ADMINISTRATOR) {
+            audit::set_actor(parts, ActorKind::Admin, user.identity());
+
+            Ok(AdminUser(user))
+        } else {
+            Err(ErrorKind::Forbidden.into())
+        }
+    }
+}
+
 /// A random distribution for base 64.
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Base64;
@@ -0,0 +1,178 @@
+//! Slow query instrumentation.
+//!
+//! sqlx already times every statement, and emits an event when one takes
+//! longer than the connection's slow statement threshold. [`SlowQueryLayer`]
+//! picks these events up and keeps the most recent ones around in
+//! [`SlowQueries`], so operators can find them without digging through logs.
+
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+
+use ring_channel_model::admin::SlowQuery;
+
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+
+use tracing_subscriber::{Layer, layer::Context};
+
+/// The target sqlx emits statement events on.
+pub const SQLX_QUERY_TARGET: &str = "sqlx::query";
+
+/// How many slow queries are kept.
+pub const SLOW_QUERY_CAPACITY: usize = 100;
+
+/// The most recent slow queries.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug, Default)]
+pub struct SlowQueries {
+    queries: Arc<Mutex<VecDeque<SlowQuery>>>,
+}
+
+impl SlowQueries {
+    /// Creates a new, empty `SlowQueries`.
+    pub fn new() -> SlowQueries {
+        SlowQueries::default()
+    }
+
+    /// Creates a layer that records slow queries here.
+    pub fn layer(&self) -> SlowQueryLayer {
+        SlowQueryLayer {
+            queries: self.clone(),
+        }
+    }
+
+    /// The most recent slow queries, newest first.
+    pub fn list(&self) -> Vec<SlowQuery> {
+        let queries = self.queries.lock().expect("slow queries poisoned");
+        queries.iter().rev().cloned().collect()
+    }
+
+    fn push(&self, query: SlowQuery) {
+        let mut queries = self.queries.lock().expect("slow queries poisoned");
+        if queries.len() >= SLOW_QUERY_CAPACITY {
+            queries.pop_front();
+        }
+        queries.push_back(query);
+    }
+}
+
+/// A [`Layer`] that records slow statements reported by sqlx.
+#[derive(Clone, Debug)]
+pub struct SlowQueryLayer {
+    queries: SlowQueries,
+}
+
+impl<S> Layer<S> for SlowQueryLayer
+where
+    S: Subscriber,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != SQLX_QUERY_TARGET {
+            return;
+        }
+
+        let mut visitor = SlowQueryVisitor::default();
+        event.record(&mut visitor);
+
+        // only slow statements come with a threshold
+        if !visitor.slow {
+            return;
+        }
+
+        // short statements are only sent as a summary
+        let statement = if visitor.statement.trim().is_empty() {
+            &visitor.summary
+        } else {
+            visitor.statement.trim()
+        };
+
+        self.queries.push(SlowQuery {
+            statement: redact_literals(statement),
+            summary: redact_literals(&visitor.summary),
+            elapsed_ms: visitor.elapsed_secs * 1000.0,
+            rows_affected: visitor.rows_affected,
+            rows_returned: visitor.rows_returned,
+            advice: vec![],
+            created_at: Utc::now(),
+        });
+    }
+}
+
+#[derive(Default)]
+struct SlowQueryVisitor {
+    summary: String,
+    statement: String,
+    elapsed_secs: f64,
+    rows_affected: u64,
+    rows_returned: u64,
+    slow: bool,
+}
+
+impl Visit for SlowQueryVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = value.to_owned(),
+            "db.statement" => self.statement = value.to_owned(),
+            _ => (),
+        }
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.elapsed_secs = value;
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "rows_affected" => self.rows_affected = value,
+            "rows_returned" => self.rows_returned = value,
+            _ => (),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, _value: &dyn Debug) {
+        if field.name() == "slow_threshold" {
+            self.slow = true;
+        }
+    }
+}
+
+/// Replaces string literals in a statement with `'?'`.
+///
+/// Values should always be bound, but this makes sure nothing sensitive slips
+/// into the buffer if one isn't.
+pub fn redact_literals(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\'' {
+            out.push(c);
+            continue;
+        }
+
+        // skip to the closing quote, minding escaped quotes
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+        }
+
+        out.push_str("'?'");
+    }
+
+    out
+}
@@ -0,0 +1,157 @@
+//! Squads.
+//!
+//! Users can band together in a squad. Every wager a member places while in
+//! the squad counts towards the squad's pot on that match, and towards its
+//! standing on the squad leaderboard.
+
+use chrono::{DateTime, Utc};
+
+use ring_channel_model::{
+    BattleId, UserId,
+    battle::{PlayerTeam, WagerMarket},
+    squad::{Squad, SquadPot},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::error::Error;
+
+/// A squad schema.
+#[derive(FromRow)]
+pub struct SquadSchema {
+    pub id: i32,
+    pub name: String,
+    pub owner: String,
+    pub members: i32,
+    pub inserted_at: DateTime<Utc>,
+}
+
+impl From<SquadSchema> for Squad {
+    fn from(value: SquadSchema) -> Self {
+        Squad {
+            name: value.name,
+            owner: value.owner,
+            members: value.members,
+            created_at: value.inserted_at,
+        }
+    }
+}
+
+/// Takes a user out of their squad, if they are in one.
+///
+/// If the user led the squad, the member who has been in it longest takes
+/// over. Squads are deleted once their last member leaves.
+///
+/// Returns the name of the squad the user left.
+pub async fn leave_squad(
+    user_id: UserId,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Option<String>, Error> {
+    let squad = sqlx::query_as::<_, (i32, String, UserId)>(
+        r#"
+        DELETE FROM squad_member
+        WHERE user_id = $1
+        RETURNING
+            squad_id,
+            (SELECT name FROM squad WHERE id = squad_id),
+            (SELECT owner_id FROM squad WHERE id = squad_id)
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((squad_id, name, owner_id)) = squad else {
+        return Ok(None);
+    };
+
+    let successor = sqlx::query_as::<_, (UserId,)>(
+        r#"
+        SELECT user_id
+        FROM squad_member
+        WHERE squad_id = $1
+        ORDER BY joined_at ASC, user_id ASC
+        LIMIT 1
+        "#,
+    )
+    .bind(squad_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match successor {
+        Some((successor,)) if owner_id == user_id => {
+            sqlx::query(
+                r#"
+                UPDATE squad
+                SET owner_id = $2, updated_at = $3
+                WHERE id = $1
+                "#,
+            )
+            .bind(squad_id)
+            .bind(successor)
+            .bind(now)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Some(_) => (),
+        None => {
+            sqlx::query("DELETE FROM squad WHERE id = $1")
+                .bind(squad_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    Ok(Some(name))
+}
+
+/// Sums each squad's wagers on a match, biggest pot first.
+pub async fn squad_pots(
+    battle_id: BattleId,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<SquadPot>, Error> {
+    #[derive(FromRow)]
+    struct PotQuery {
+        name: String,
+        red: i64,
+        blue: i64,
+        wagers: i32,
+    }
+
+    let pots = sqlx::query_as::<_, PotQuery>(
+        r#"
+        SELECT
+            s.name,
+            IFNULL(SUM(w.mobiums) FILTER (WHERE w.victor = $2), 0) AS red,
+            IFNULL(SUM(w.mobiums) FILTER (WHERE w.victor = $3), 0) AS blue,
+            COUNT(*) AS wagers
+        FROM wager w
+        INNER JOIN squad_member sm ON sm.user_id = w.user_id
+        INNER JOIN squad s ON s.id = sm.squad_id
+        WHERE
+            w.match_id = $1
+            AND w.market = $4
+            AND w.mobiums > 0
+            AND w.inserted_at >= sm.joined_at
+        GROUP BY s.id
+        ORDER BY red + blue DESC, s.name ASC
+        "#,
+    )
+    .bind(battle_id)
+    .bind(PlayerTeam::Red)
+    .bind(PlayerTeam::Blue)
+    .bind(WagerMarket::Winner)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(pots
+        .into_iter()
+        .map(|pot| SquadPot {
+            name: pot.name,
+            red: pot.red,
+            blue: pot.blue,
+            wagers: pot.wagers,
+        })
+        .collect())
+}
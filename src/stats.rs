@@ -0,0 +1,137 @@
+//! Daily statistics rollup.
+//!
+//! Aggregating over the whole `battle` and `wager` tables gets slower as they
+//! grow, so aggregates are rolled up into `stats_daily` once a day.
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+use ring_channel_model::battle::BattleStatus;
+
+use sqlx::SqliteConnection;
+
+use crate::error::Error;
+
+/// Rolls up all finished days that haven't been rolled up yet.
+///
+/// The most recently rolled up day is always recomputed, in case it was
+/// rolled up before it was over.
+pub async fn rollup_daily_stats(conn: &mut SqliteConnection) -> Result<(), Error> {
+    let (last_day,) = sqlx::query_as::<_, (Option<NaiveDate>,)>(
+        r#"
+        SELECT MAX(day)
+        FROM stats_daily
+        "#,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let first_day = match last_day {
+        Some(last_day) => last_day,
+        None => {
+            let (first_battle,) = sqlx::query_as::<_, (Option<DateTime<Utc>>,)>(
+                r#"
+                SELECT MIN(concluded_at)
+                FROM battle
+                "#,
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+
+            match first_battle {
+                Some(first_battle) => first_battle.date_naive(),
+                // nothing to roll up
+                None => return Ok(()),
+            }
+        }
+    };
+
+    let today = Utc::now().date_naive();
+
+    for day in first_day.iter_days().take_while(|day| *day < today) {
+        rollup_day(day, &mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Rolls up a single day.
+pub async fn rollup_day(day: NaiveDate, conn: &mut SqliteConnection) -> Result<(), Error> {
+    let now = Utc::now();
+
+    let start = day.and_time(Default::default()).and_utc();
+    let end = start + TimeDelta::days(1);
+
+    let (matches,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COUNT(*)
+        FROM battle
+        WHERE
+            status = $1
+            AND concluded_at >= $2
+            AND concluded_at < $3
+        "#,
+    )
+    .bind(BattleStatus::Concluded)
+    .bind(start)
+    .bind(end)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (wagers, total_pot) = sqlx::query_as::<_, (i64, i64)>(
+        r#"
+        SELECT COUNT(w.id), COALESCE(SUM(w.mobiums), 0)
+        FROM wager w, battle b
+        WHERE
+            w.match_id = b.id
+            AND w.mobiums > 0
+            AND b.status = $1
+            AND b.concluded_at >= $2
+            AND b.concluded_at < $3
+        "#,
+    )
+    .bind(BattleStatus::Concluded)
+    .bind(start)
+    .bind(end)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (bailouts,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COUNT(*)
+        FROM bailout
+        WHERE inserted_at >= $1 AND inserted_at < $2
+        "#,
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO stats_daily
+            (day, matches, wagers, total_pot, bailouts, inserted_at, updated_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $6)
+        ON CONFLICT (day) DO UPDATE
+        SET
+            matches = $2,
+            wagers = $3,
+            total_pot = $4,
+            bailouts = $5,
+            updated_at = $6
+        "#,
+    )
+    .bind(day)
+    .bind(matches)
+    .bind(wagers)
+    .bind(total_pot)
+    .bind(bailouts)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::debug!(%day, "rolled up daily stats");
+
+    Ok(())
+}
@@ -0,0 +1,352 @@
+//! Background task supervision.
+//!
+//! Cron jobs and long-running tasks are run through a [`Supervisor`], which
+//! catches their errors and panics, runs them again after backing off, and
+//! keeps track of how they are doing for `/readyz` and `GET /admin/tasks`.
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt::Display,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+
+use futures_util::FutureExt as _;
+
+use ring_channel_model::admin::TaskHealth;
+
+use crate::litefs::WriteForwarding;
+
+/// How long a task waits before running again after failing once.
+///
+/// This doubles with every failure in a row.
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest a task waits before running again.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// The longest a writer waits before running again.
+///
+/// Requests wait on writers, so they are brought back quickly.
+pub const MAX_WRITER_BACKOFF: Duration = Duration::from_secs(5);
+
+/// How long a long-running task has to run before its earlier failures are
+/// forgotten.
+pub const HEALTHY_RUN_TIME: Duration = Duration::from_secs(60);
+
+/// How many times a scheduled task is tried before it waits for its next
+/// run.
+pub const MAX_JOB_ATTEMPTS: u32 = 3;
+
+/// How many times in a row a task can fail before it is unhealthy.
+pub const UNHEALTHY_FAILURES: u32 = 3;
+
+/// Runs background tasks, and tracks their health.
+///
+/// Tasks show up once they first run. Cheaply cloneable.
+#[derive(Clone, Debug, Default)]
+pub struct Supervisor {
+    tasks: Arc<Mutex<BTreeMap<&'static str, TaskState>>>,
+    forwarding: Option<WriteForwarding>,
+}
+
+#[derive(Debug, Default)]
+struct TaskState {
+    running: bool,
+    last_run_at: Option<DateTime<Utc>>,
+    last_success_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+    failures: u32,
+    restarts: u64,
+    retry_at: Option<DateTime<Utc>>,
+}
+
+impl Supervisor {
+    /// Creates a new `Supervisor`.
+    pub fn new() -> Supervisor {
+        Supervisor::default()
+    }
+
+    /// Holds off tasks that write until the node is the LiteFS primary.
+    pub fn with_write_forwarding(self, forwarding: WriteForwarding) -> Supervisor {
+        Supervisor {
+            forwarding: Some(forwarding),
+            ..self
+        }
+    }
+
+    /// Runs a scheduled task that writes.
+    ///
+    /// Like [`Supervisor::run`], but the run is skipped on LiteFS replicas.
+    pub async fn run_on_primary<F, Fut, E>(&self, name: &'static str, task: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        if let Some(forwarding) = &self.forwarding {
+            match forwarding.is_primary().await {
+                Ok(true) => (),
+                Ok(false) => return,
+                Err(err) => {
+                    self.start(name);
+                    self.fail(name, err.to_string(), MAX_BACKOFF);
+                    self.stop(name);
+                    return;
+                }
+            }
+        }
+
+        self.run(name, task).await
+    }
+
+    /// Runs a scheduled task.
+    ///
+    /// The task is tried again after backing off if it fails, up to
+    /// [`MAX_JOB_ATTEMPTS`] times. Runs are skipped while the task is still
+    /// running from before.
+    pub async fn run<F, Fut, E>(&self, name: &'static str, mut task: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Display,
+    {
+        if !self.start(name) {
+            return;
+        }
+
+        for attempt in 1..=MAX_JOB_ATTEMPTS {
+            match catch(task()).await {
+                Ok(()) => {
+                    self.succeed(name);
+                    return;
+                }
+                Err(err) => {
+                    let backoff = self.fail(name, err, MAX_BACKOFF);
+                    if attempt < MAX_JOB_ATTEMPTS {
+                        tokio::time::sleep(backoff).await;
+                        self.restart(name);
+                    }
+                }
+            }
+        }
+
+        // wait for the next run instead
+        self.stop(name);
+    }
+
+    /// Spawns a long-running task.
+    ///
+    /// The task is restarted after backing off whenever it fails, until it
+    /// finishes. Failures are forgotten once the task has run for
+    /// [`HEALTHY_RUN_TIME`].
+    pub fn spawn<F, Fut, E>(&self, name: &'static str, task: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send,
+        E: Display,
+    {
+        self.spawn_inner(name, false, MAX_BACKOFF, task);
+    }
+
+    /// Spawns a long-running task that writes, like the wager writer.
+    ///
+    /// Like [`Supervisor::spawn`], but the task backs off for at most
+    /// [`MAX_WRITER_BACKOFF`], and on LiteFS replicas waits to start until
+    /// the node is promoted.
+    pub fn spawn_writer<F, Fut, E>(&self, name: &'static str, task: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send,
+        E: Display,
+    {
+        self.spawn_inner(name, true, MAX_WRITER_BACKOFF, task);
+    }
+
+    fn spawn_inner<F, Fut, E>(
+        &self,
+        name: &'static str,
+        on_primary: bool,
+        max_backoff: Duration,
+        mut task: F,
+    ) where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send,
+        E: Display,
+    {
+        let supervisor = self.clone();
+
+        tokio::spawn(async move {
+            // tasks held off show up once they start
+            if on_primary && let Some(forwarding) = &supervisor.forwarding {
+                forwarding.wait_until_primary().await;
+            }
+            supervisor.start(name);
+
+            loop {
+                let started_at = Instant::now();
+                match catch(task()).await {
+                    Ok(()) => {
+                        supervisor.succeed(name);
+                        break;
+                    }
+                    Err(err) => {
+                        // a task that ran fine for a while failed anew
+                        if started_at.elapsed() >= HEALTHY_RUN_TIME {
+                            supervisor.forget_failures(name);
+                        }
+
+                        let backoff = supervisor.fail(name, err, max_backoff);
+                        tokio::time::sleep(backoff).await;
+                        supervisor.restart(name);
+                    }
+                }
+            }
+        });
+    }
+
+    /// The health of every task, by name.
+    pub fn health(&self) -> Vec<TaskHealth> {
+        let tasks = self.tasks.lock().expect("tasks poisoned");
+        tasks
+            .iter()
+            .map(|(name, task)| TaskHealth {
+                name: (*name).to_owned(),
+                healthy: task.failures < UNHEALTHY_FAILURES,
+                running: task.running,
+                last_run_at: task.last_run_at,
+                last_success_at: task.last_success_at,
+                last_error: task.last_error.clone(),
+                last_error_at: task.last_error_at,
+                failures: task.failures,
+                restarts: task.restarts,
+                retry_at: task.retry_at,
+            })
+            .collect()
+    }
+
+    /// Marks a task as running, unless it already is.
+    fn start(&self, name: &'static str) -> bool {
+        let mut tasks = self.tasks.lock().expect("tasks poisoned");
+        let task = tasks.entry(name).or_default();
+        if task.running {
+            return false;
+        }
+
+        task.running = true;
+        task.last_run_at = Some(Utc::now());
+        true
+    }
+
+    fn restart(&self, name: &'static str) {
+        let mut tasks = self.tasks.lock().expect("tasks poisoned");
+        let task = tasks.entry(name).or_default();
+        task.restarts += 1;
+        task.retry_at = None;
+        task.last_run_at = Some(Utc::now());
+    }
+
+    fn stop(&self, name: &'static str) {
+        let mut tasks = self.tasks.lock().expect("tasks poisoned");
+        let task = tasks.entry(name).or_default();
+        task.running = false;
+        task.retry_at = None;
+    }
+
+    fn succeed(&self, name: &'static str) {
+        let mut tasks = self.tasks.lock().expect("tasks poisoned");
+        let task = tasks.entry(name).or_default();
+        task.running = false;
+        task.last_success_at = Some(Utc::now());
+        task.failures = 0;
+        task.retry_at = None;
+    }
+
+    fn forget_failures(&self, name: &'static str) {
+        let mut tasks = self.tasks.lock().expect("tasks poisoned");
+        let task = tasks.entry(name).or_default();
+        task.failures = 0;
+    }
+
+    /// Records a failure, returning how long to back off for.
+    fn fail(&self, name: &'static str, err: String, max_backoff: Duration) -> Duration {
+        tracing::error!(task = name, %err, "task failed");
+
+        let now = Utc::now();
+        let mut tasks = self.tasks.lock().expect("tasks poisoned");
+        let task = tasks.entry(name).or_default();
+        task.failures += 1;
+        task.last_error = Some(err);
+        task.last_error_at = Some(now);
+
+        let backoff = INITIAL_BACKOFF
+            .saturating_mul(1 << (task.failures - 1).min(16))
+            .min(max_backoff);
+        task.retry_at = chrono::Duration::from_std(backoff)
+            .ok()
+            .map(|backoff| now + backoff);
+
+        backoff
+    }
+}
+
+/// Runs a task, turning panics into errors.
+async fn catch<F, E>(task: F) -> Result<(), String>
+where
+    F: Future<Output = Result<(), E>>,
+    E: Display,
+{
+    match AssertUnwindSafe(task).catch_unwind().await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(panic) => Err(format!("panicked: {}", panic_message(&*panic))),
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_resets_after_forgetting_failures() {
+        let supervisor = Supervisor::new();
+
+        for _ in 0..5 {
+            supervisor.fail("task", "oops".into(), MAX_BACKOFF);
+        }
+        assert_eq!(
+            supervisor.fail("task", "oops".into(), MAX_BACKOFF),
+            INITIAL_BACKOFF * 32
+        );
+
+        supervisor.forget_failures("task");
+        assert_eq!(
+            supervisor.fail("task", "oops".into(), MAX_BACKOFF),
+            INITIAL_BACKOFF
+        );
+    }
+
+    #[test]
+    fn test_writer_backoff_is_capped() {
+        let supervisor = Supervisor::new();
+
+        for _ in 0..20 {
+            let backoff = supervisor.fail("writer", "oops".into(), MAX_WRITER_BACKOFF);
+            assert!(backoff <= MAX_WRITER_BACKOFF);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Helpers for tests that need a database.
+//!
+//! Every [`state`] gets its own in-memory database, migrated and empty.
+
+use std::sync::Arc;
+
+use chrono::{TimeDelta, Utc};
+
+use ring_channel_model::{
+    BattleId, UserId,
+    battle::{BattleStatus, PlayerTeam, WagerMarket},
+};
+
+use sqlx::{SqliteConnection, sqlite::SqlitePoolOptions};
+
+use uuid::Uuid;
+
+use crate::{
+    app::AppState,
+    config::Config,
+    deadline::BetDeadlines,
+    flags::Flags,
+    health::{Health, MIGRATOR},
+    metrics::Metrics,
+    replica::ReadPool,
+    room::{Room, event_log::EventLog},
+    slow_query::SlowQueries,
+    supervisor::Supervisor,
+    user::bot::BotSwitch,
+    wager_queue::{WagerQueue, WagerReceiver},
+};
+
+/// Creates app state over a fresh database.
+///
+/// The receiver is handed back so wagers can be written with
+/// [`run_wager_writer`](crate::wager_queue::run_wager_writer).
+pub async fn state_with(config: Config) -> (AppState, WagerReceiver) {
+    // connections to a named in-memory database share it
+    let url = format!("sqlite:file:{}?mode=memory&cache=shared", Uuid::new_v4());
+    let db = SqlitePoolOptions::new()
+        .min_connections(1)
+        .connect(&url)
+        .await
+        .expect("in-memory database");
+    MIGRATOR.run(&db).await.expect("migrations");
+
+    let metrics = Metrics::new();
+    let (wagers, receiver) = WagerQueue::new(metrics.clone());
+    let state = AppState {
+        room: Room::new(
+            config.server.milestones.clone(),
+            metrics.clone(),
+            EventLog::default(),
+        ),
+        config: Arc::new(config),
+        replica: ReadPool::new(db.clone(), None),
+        db,
+        health: Health::new(),
+        avatars: None,
+        metrics,
+        slow_queries: SlowQueries::new(),
+        wagers,
+        bot: BotSwitch::new(false),
+        deadlines: BetDeadlines::default(),
+        captcha: None,
+        flags: Flags::default(),
+        tasks: Supervisor::new(),
+    };
+
+    (state, receiver)
+}
+
+/// Creates app state over a fresh database, with the default config.
+pub async fn state() -> AppState {
+    state_with(Config::default()).await.0
+}
+
+/// Creates a user with `mobiums`.
+pub async fn create_user(username: &str, mobiums: i64, conn: &mut SqliteConnection) -> UserId {
+    let now = Utc::now();
+
+    let (id,) = sqlx::query_as::<_, (UserId,)>(
+        r#"
+        INSERT INTO user (username, display_name, mobiums, inserted_at, updated_at)
+        VALUES ($1, $1, $2, $3, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(username)
+    .bind(mobiums)
+    .bind(now)
+    .fetch_one(&mut *conn)
+    .await
+    .expect("user");
+
+    id
+}
+
+/// Creates a match with one player on each team.
+///
+/// Bets on the match are still open.
+pub async fn create_battle(status: BattleStatus, conn: &mut SqliteConnection) -> BattleId {
+    let now = Utc::now();
+
+    let (battle_id,) = sqlx::query_as::<_, (BattleId,)>(
+        r#"
+        INSERT INTO battle (uuid, level_name, status, inserted_at, closed_at, updated_at)
+        VALUES ($1, 'Green Hills Zone', $2, $3, $4, $3)
+        RETURNING id
+        "#,
+    )
+    .bind(Uuid::new_v4().hyphenated().to_string())
+    .bind(status)
+    .bind(now)
+    .bind(now + TimeDelta::minutes(1))
+    .fetch_one(&mut *conn)
+    .await
+    .expect("battle");
+
+    for team in [PlayerTeam::Red, PlayerTeam::Blue] {
+        let key = Uuid::new_v4().simple().to_string();
+
+        let (player_id,) = sqlx::query_as::<_, (i32,)>(
+            r#"
+            INSERT INTO player (short_id, display_name, public_key, inserted_at, updated_at)
+            VALUES ($1, $2, $3, $4, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(key[..6].to_uppercase())
+        .bind(format!("{:?}", team))
+        .bind(format!("{}{}", key, key))
+        .bind(now)
+        .fetch_one(&mut *conn)
+        .await
+        .expect("player");
+
+        sqlx::query(
+            r#"
+            INSERT INTO participant (match_id, player_id, team)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(battle_id)
+        .bind(player_id)
+        .bind(team)
+        .execute(&mut *conn)
+        .await
+        .expect("participant");
+    }
+
+    battle_id
+}
+
+/// Sets a team's finish time on a match.
+pub async fn finish(
+    battle_id: BattleId,
+    team: PlayerTeam,
+    finish_time: i32,
+    conn: &mut SqliteConnection,
+) {
+    sqlx::query(
+        r#"
+        UPDATE participant
+        SET finish_time = $3
+        WHERE match_id = $1 AND team = $2
+        "#,
+    )
+    .bind(battle_id)
+    .bind(team)
+    .bind(finish_time)
+    .execute(&mut *conn)
+    .await
+    .expect("finish time");
+}
+
+/// Sets the finish time line of a match.
+pub async fn set_line(battle_id: BattleId, line: i32, conn: &mut SqliteConnection) {
+    sqlx::query(
+        r#"
+        UPDATE battle
+        SET finish_time_line = $2
+        WHERE id = $1
+        "#,
+    )
+    .bind(battle_id)
+    .bind(line)
+    .execute(&mut *conn)
+    .await
+    .expect("line");
+}
+
+/// Places a wager, skipping every check.
+///
+/// `victor` is the team or the side of the line, by number.
+pub async fn place(
+    user_id: UserId,
+    battle_id: BattleId,
+    market: WagerMarket,
+    victor: u8,
+    mobiums: i64,
+    conn: &mut SqliteConnection,
+) {
+    sqlx::query(
+        r#"
+        INSERT INTO wager (user_id, match_id, market, victor, mobiums, inserted_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $6)
+        "#,
+    )
+    .bind(user_id)
+    .bind(battle_id)
+    .bind(market)
+    .bind(victor)
+    .bind(mobiums)
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await
+    .expect("wager");
+}
+
+/// Gets a user's mobiums.
+pub async fn mobiums(user_id: UserId, conn: &mut SqliteConnection) -> i64 {
+    let (mobiums,) = sqlx::query_as::<_, (i64,)>("SELECT mobiums FROM user WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await
+        .expect("user");
+
+    mobiums
+}
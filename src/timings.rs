@@ -0,0 +1,141 @@
+//! Request latency tracking.
+//!
+//! Every request is timed by the [`request_timings`] middleware, and its
+//! latency is recorded per route. Requests that go over the configured budget
+//! are logged with a breakdown of where the time went. Handlers can attribute
+//! time to the database through the [`RequestTimings`] of the request.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{FromRequestParts, MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use http::request::Parts;
+
+use crate::app::AppState;
+
+/// The timing breakdown of a request.
+///
+/// Cheaply cloneable. If this is extracted outside of the
+/// [`request_timings`] middleware, it is detached and nothing will be
+/// reported.
+#[derive(Clone, Debug, Default)]
+pub struct RequestTimings {
+    inner: Arc<Mutex<TimingsEntry>>,
+}
+
+#[derive(Debug, Default)]
+struct TimingsEntry {
+    db: Duration,
+}
+
+impl RequestTimings {
+    /// Creates a new `RequestTimings`.
+    pub fn new() -> RequestTimings {
+        RequestTimings::default()
+    }
+
+    /// Attributes time spent to the database.
+    pub fn add_db(&self, elapsed: Duration) {
+        self.inner.lock().expect("timings poisoned").db += elapsed;
+    }
+
+    /// Runs a future, attributing the time it takes to the database.
+    pub async fn db<F>(&self, fut: F) -> F::Output
+    where
+        F: Future,
+    {
+        let start = Instant::now();
+        let output = fut.await;
+        self.add_db(start.elapsed());
+        output
+    }
+
+    /// The time attributed to the database so far.
+    pub fn db_time(&self) -> Duration {
+        self.inner.lock().expect("timings poisoned").db
+    }
+}
+
+impl<S> FromRequestParts<S> for RequestTimings
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<RequestTimings>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Middleware that times every request.
+pub async fn request_timings(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let timings = RequestTimings::new();
+    request.extensions_mut().insert(timings.clone());
+
+    // unmatched requests would blow up the number of series
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".into());
+    let method = request.method().clone();
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed();
+
+    let http = &state.config.http;
+    let region = http.region.as_deref().unwrap_or("unknown");
+    let labels = [
+        ("method", method.as_str()),
+        ("route", route.as_str()),
+        ("region", region),
+    ];
+
+    state.metrics.observe(
+        "http_request_duration_seconds",
+        &labels,
+        elapsed.as_secs_f64(),
+    );
+
+    if http
+        .latency_budget
+        .to_std()
+        .is_ok_and(|budget| elapsed > budget)
+    {
+        let db = timings.db_time();
+        let handler = elapsed.saturating_sub(db);
+
+        state.metrics.increment("http_slow_requests_total", &labels);
+
+        tracing::warn!(
+            %method,
+            route,
+            region,
+            status = response.status().as_u16(),
+            total_ms = elapsed.as_millis() as u64,
+            db_ms = db.as_millis() as u64,
+            handler_ms = handler.as_millis() as u64,
+            "request went over latency budget"
+        );
+    }
+
+    response
+}
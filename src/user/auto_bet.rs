@@ -0,0 +1,269 @@
+//! Auto-bets.
+//!
+//! Users who can't watch every match can set a rule to wager on each public
+//! match as soon as it opens. Rules wager like the user would: they can't
+//! wager mobiums the user doesn't have, and they skip matches the user
+//! already wagered on.
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use ring_channel_model::{
+    Mobiums, UserId,
+    admin::ActorKind,
+    battle::PlayerTeam,
+    message::server::AutoBetPlaced,
+    request::battle::WagerAmount,
+    user::{AutoBet, AutoBetPick, PenaltyKind, UserFlags},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use uuid::Uuid;
+
+use crate::{
+    app::AppState, audit::Audit, error::Error, routes::battle::wager::place_wager,
+    session::SessionUser, wager_queue::WagerRequest,
+};
+
+use super::{penalty::check_penalty, wager_balance};
+
+/// How far back daily caps look.
+pub const DAILY_CAP_WINDOW: TimeDelta = TimeDelta::days(1);
+
+/// An auto-bet schema.
+#[derive(FromRow)]
+pub struct AutoBetSchema {
+    pub user_id: UserId,
+    #[sqlx(try_from = "u8")]
+    pub pick: AutoBetPick,
+    pub mobiums: Mobiums,
+    pub daily_cap: Option<Mobiums>,
+    pub enabled: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<AutoBetSchema> for AutoBet {
+    fn from(value: AutoBetSchema) -> Self {
+        AutoBet {
+            pick: value.pick,
+            mobiums: value.mobiums,
+            daily_cap: value.daily_cap,
+            enabled: value.enabled,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
+/// Picks the team an auto-bet wagers on.
+///
+/// Returns `None` if the rule needs a prediction, and the match doesn't have
+/// one, or is a coin flip.
+pub fn pick_team(pick: AutoBetPick, red_win_probability: Option<f64>) -> Option<PlayerTeam> {
+    let favourite = match red_win_probability {
+        Some(red) if red > 0.5 => Some(PlayerTeam::Red),
+        Some(red) if red < 0.5 => Some(PlayerTeam::Blue),
+        _ => None,
+    };
+
+    match pick {
+        AutoBetPick::Favourite => favourite,
+        AutoBetPick::Underdog => favourite.map(|team| match team {
+            PlayerTeam::Red => PlayerTeam::Blue,
+            PlayerTeam::Blue => PlayerTeam::Red,
+        }),
+        AutoBetPick::Red => Some(PlayerTeam::Red),
+        AutoBetPick::Blue => Some(PlayerTeam::Blue),
+    }
+}
+
+/// Places a wager for every enabled auto-bet on a new match.
+///
+/// Rules that can't wager are skipped. This should be spawned as its own task
+/// when the match is created.
+pub async fn run_auto_bets(state: AppState, match_id: Uuid, red_win_probability: Option<f64>) {
+    let rules = match enabled_auto_bets(&state).await {
+        Ok(rules) => rules,
+        Err(err) => {
+            tracing::error!(?err, "failed to fetch auto-bets");
+            return;
+        }
+    };
+
+    for rule in rules {
+        let Some(victor) = pick_team(rule.pick, red_win_probability) else {
+            continue;
+        };
+
+        let user_id = rule.user_id;
+        if let Err(err) = place_auto_bet(&state, rule, match_id, victor).await {
+            tracing::debug!(%user_id, ?err, "skipped auto-bet");
+        }
+    }
+}
+
+async fn enabled_auto_bets(state: &AppState) -> Result<Vec<AutoBetSchema>, Error> {
+    let rules = sqlx::query_as::<_, AutoBetSchema>(
+        r#"
+        SELECT a.user_id, a.pick, a.mobiums, a.daily_cap, a.enabled, a.updated_at
+        FROM auto_bet a
+        INNER JOIN user u ON u.id = a.user_id
+        WHERE
+            a.enabled
+            AND u.merged_into IS NULL
+            AND u.flags & $1 = 0
+        ORDER BY a.user_id ASC
+        "#,
+    )
+    .bind(i32::from(UserFlags::AUTOMATED_USER))
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(rules)
+}
+
+async fn place_auto_bet(
+    state: &AppState,
+    rule: AutoBetSchema,
+    match_id: Uuid,
+    victor: PlayerTeam,
+) -> Result<(), Error> {
+    let now = Utc::now();
+
+    let mobiums = {
+        let mut conn = state.db.acquire().await?;
+
+        check_penalty(rule.user_id, PenaltyKind::WagerBan, &mut conn).await?;
+
+        let mut mobiums = rule.mobiums;
+
+        if let Some(daily_cap) = rule.daily_cap {
+            let wagered = wagered_since(rule.user_id, now - DAILY_CAP_WINDOW, &mut conn).await?;
+            mobiums = mobiums.min(daily_cap.checked_sub(wagered).unwrap_or(Mobiums::ZERO));
+        }
+
+        let balance = wager_balance(rule.user_id, None, &mut conn).await?;
+        mobiums.min(balance.available)
+    };
+
+    if mobiums <= Mobiums::ZERO {
+        return Ok(());
+    }
+
+    let Some(user) = SessionUser::fetch(rule.user_id, state).await? else {
+        return Ok(());
+    };
+
+    let audit = Audit::new();
+    audit.set_actor(ActorKind::User, user.identity());
+    audit.note(format!("auto-bet {} on match {}", mobiums, match_id));
+
+    let receipt = place_wager(
+        state,
+        WagerRequest {
+            user: user.clone(),
+            audit: audit.clone(),
+            match_id,
+            victor,
+            amount: WagerAmount::Mobiums(mobiums),
+            client_token: None,
+            auto_bet: true,
+        },
+        None,
+    )
+    .await?;
+
+    if let Err(err) = audit.write("AUTO", "auto-bet", 200, &state.db).await {
+        tracing::error!(?err, "failed to write audit log");
+    }
+
+    state.room.send_auto_bet(
+        rule.user_id,
+        AutoBetPlaced {
+            match_id: match_id.hyphenated().to_string(),
+            pick: rule.pick,
+            wager: receipt.wager,
+        },
+    );
+
+    Ok(())
+}
+
+/// How many mobiums a user's auto-bet has wagered since `since`.
+pub async fn wagered_since(
+    user_id: UserId,
+    since: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Mobiums, Error> {
+    let (wagered,) = sqlx::query_as::<_, (Mobiums,)>(
+        r#"
+        SELECT IFNULL(SUM(mobiums), 0)
+        FROM auto_bet_placement
+        WHERE user_id = $1 AND inserted_at > $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(wagered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ring_channel_model::battle::{BattleStatus, WagerMarket};
+
+    use crate::{routes::battle::wager::write_wager, testing};
+
+    async fn auto_bet(user_id: UserId, match_id: Uuid, state: &AppState) -> WagerRequest {
+        WagerRequest {
+            user: SessionUser::fetch(user_id, state).await.unwrap().unwrap(),
+            audit: Audit::new(),
+            match_id,
+            victor: PlayerTeam::Blue,
+            amount: WagerAmount::Mobiums(Mobiums(50)),
+            client_token: None,
+            auto_bet: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_bets_never_replace_wagers() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let user = testing::create_user("knuckles", 1000, &mut conn).await;
+        let fresh = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+        let wagered = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+        testing::place(user, wagered, WagerMarket::Winner, 0, 300, &mut conn).await;
+
+        for battle_id in [fresh, wagered] {
+            let (uuid,) = sqlx::query_as::<_, (String,)>("SELECT uuid FROM battle WHERE id = $1")
+                .bind(battle_id)
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+            let request = auto_bet(user, uuid.parse().unwrap(), &state).await;
+
+            let written = write_wager(&state, None, &request, &mut conn).await;
+            assert_eq!(written.is_ok(), battle_id == fresh);
+        }
+
+        // the manual wager is left alone
+        let (victor, mobiums) = sqlx::query_as::<_, (PlayerTeam, i64)>(
+            "SELECT victor, mobiums FROM wager WHERE match_id = $1",
+        )
+        .bind(wagered)
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap();
+        assert_eq!((victor, mobiums), (PlayerTeam::Red, 300));
+
+        // and only the placed wager counts against the cap
+        let since = Utc::now() - DAILY_CAP_WINDOW;
+        let capped = wagered_since(user, since, &mut conn).await.unwrap();
+        assert_eq!(capped, Mobiums(50));
+    }
+}
@@ -2,28 +2,76 @@
 
 use super::UserSchema;
 
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
 use chrono::Utc;
 
-use ring_channel_model::user::UserFlags;
+use derive_more::Deref;
+
+use ring_channel_model::{admin::BotStrategy, user::UserFlags};
 
-use sqlx::SqliteConnection;
+use sqlx::{FromRow, SqliteConnection};
 
 use crate::{config::WagerBotConfig, error::Error};
 
+/// Whether the wager bot is running.
+///
+/// Starts out as configured, and can be flipped by administrators while the
+/// server is running.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct BotSwitch(Arc<AtomicBool>);
+
+impl BotSwitch {
+    /// Creates a new `BotSwitch`.
+    pub fn new(enabled: bool) -> BotSwitch {
+        BotSwitch(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    /// Whether the wager bot is running.
+    pub fn enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Turns the wager bot on or off.
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// The wager bot's user, and how it wagers.
+#[derive(Deref, FromRow)]
+pub struct WagerBotUser {
+    #[deref]
+    #[sqlx(flatten)]
+    pub user: UserSchema,
+    /// How many mobiums the bot wagers.
+    pub wager_amount: i64,
+    /// How the bot decides how much to wager.
+    #[sqlx(try_from = "u8")]
+    pub strategy: BotStrategy,
+}
+
 /// Gets the user information of the wager bot.
 ///
 /// If it doesn't exist, it will make the wager bot first.
 pub async fn get_wager_bot(
     config: &WagerBotConfig,
     conn: &mut SqliteConnection,
-) -> Result<UserSchema, Error> {
+) -> Result<WagerBotUser, Error> {
     let now = Utc::now();
 
-    let query = sqlx::query_as::<_, UserSchema>(
+    let query = sqlx::query_as::<_, WagerBotUser>(
         r#"
         SELECT
             id, username, avatar, display_name, mobiums, mobiums_gained,
-            mobiums_lost, flags
+            mobiums_lost, streak, flags,
+            IFNULL(bot_wager_amount, $3) AS wager_amount,
+            IFNULL(bot_strategy, $4) AS strategy
         FROM
             user
         WHERE
@@ -33,6 +81,8 @@ pub async fn get_wager_bot(
     )
     .bind(&config.username)
     .bind(i32::from(UserFlags::AUTOMATED_USER))
+    .bind(config.wager_amount)
+    .bind(u8::from(config.strategy))
     .fetch_optional(&mut *conn)
     .await?;
 
@@ -42,7 +92,7 @@ pub async fn get_wager_bot(
         // Create a new bot user
         tracing::info!(?config.username, "creating a new automated user...");
 
-        let query = sqlx::query_as::<_, UserSchema>(
+        let query = sqlx::query_as::<_, WagerBotUser>(
             r#"
             INSERT INTO user
                 (username, display_name, avatar, flags, inserted_at, updated_at)
@@ -50,7 +100,9 @@ pub async fn get_wager_bot(
                 ($1, $2, $3, $4, $5, $5)
             RETURNING
                 id, username, avatar, display_name, mobiums, mobiums_gained,
-                mobiums_lost, flags
+                mobiums_lost, streak, flags,
+                IFNULL(bot_wager_amount, $6) AS wager_amount,
+                IFNULL(bot_strategy, $7) AS strategy
             "#,
         )
         .bind(&config.username)
@@ -60,9 +112,90 @@ pub async fn get_wager_bot(
             UserFlags::AUTOMATED_USER | UserFlags::UNLIMITED_WAGERS,
         ))
         .bind(now)
+        .bind(config.wager_amount)
+        .bind(u8::from(config.strategy))
         .fetch_one(&mut *conn)
         .await?;
 
         Ok(query)
     }
 }
+
+/// Resets the wager bot's mobiums to its configured bankroll.
+///
+/// Does nothing if there is no bankroll configured.
+pub async fn reset_bankroll(
+    config: &WagerBotConfig,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    let Some(bankroll) = config.bankroll else {
+        return Ok(());
+    };
+
+    let bot = get_wager_bot(config, &mut *conn).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE user
+        SET mobiums = $2, updated_at = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(bot.id)
+    .bind(bankroll)
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::info!(previous = bot.mobiums, bankroll, "reset wager bot bankroll");
+
+    Ok(())
+}
+
+/// Changes to the wager bot's profile.
+///
+/// Fields left as `None` are left alone.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BotProfileUpdate {
+    pub display_name: Option<String>,
+    /// `Some(None)` removes the avatar.
+    pub avatar: Option<Option<String>>,
+    pub wager_amount: Option<i64>,
+    pub strategy: Option<BotStrategy>,
+}
+
+/// Updates the wager bot's profile, returning the updated bot.
+pub async fn update_wager_bot(
+    config: &WagerBotConfig,
+    update: &BotProfileUpdate,
+    conn: &mut SqliteConnection,
+) -> Result<WagerBotUser, Error> {
+    let bot = get_wager_bot(config, &mut *conn).await?;
+    if *update == BotProfileUpdate::default() {
+        return Ok(bot);
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE user
+        SET
+            display_name = IFNULL($2, display_name),
+            avatar = CASE WHEN $3 THEN $4 ELSE avatar END,
+            bot_wager_amount = IFNULL($5, bot_wager_amount),
+            bot_strategy = IFNULL($6, bot_strategy),
+            updated_at = $7
+        WHERE id = $1
+        "#,
+    )
+    .bind(bot.id)
+    .bind(update.display_name.as_deref())
+    .bind(update.avatar.is_some())
+    .bind(update.avatar.clone().flatten())
+    .bind(update.wager_amount)
+    .bind(update.strategy.map(u8::from))
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+
+    get_wager_bot(config, &mut *conn).await
+}
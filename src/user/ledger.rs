@@ -0,0 +1,123 @@
+//! Mobiums granted outside of wagers.
+//!
+//! Every grant is recorded in the ledger, so operators can tell where
+//! mobiums came from.
+
+use chrono::Utc;
+
+use ring_channel_model::UserId;
+
+use sqlx::SqliteConnection;
+
+use crate::error::Error;
+
+/// Why mobiums were granted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LedgerReason {
+    /// The user was just created.
+    StartingBalance,
+    /// The user placed their first wager.
+    OnboardingBonus,
+    /// An administrator corrected the user's balance.
+    Correction,
+    /// The user placed their first wager during a promotional event.
+    PromoBonus,
+}
+
+impl LedgerReason {
+    /// The reason as it is stored in the ledger.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LedgerReason::StartingBalance => "starting-balance",
+            LedgerReason::OnboardingBonus => "onboarding-bonus",
+            LedgerReason::Correction => "correction",
+            LedgerReason::PromoBonus => "promo-bonus",
+        }
+    }
+}
+
+/// Records a grant in the ledger.
+///
+/// This does not change the user's mobiums.
+pub async fn record(
+    user_id: UserId,
+    amount: i64,
+    reason: LedgerReason,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    record_with_note(user_id, amount, reason, None, conn).await
+}
+
+/// Records a grant in the ledger, along with why it was made.
+///
+/// This does not change the user's mobiums.
+pub async fn record_with_note(
+    user_id: UserId,
+    amount: i64,
+    reason: LedgerReason,
+    note: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO ledger (user_id, amount, reason, note, inserted_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(user_id)
+    .bind(amount)
+    .bind(reason.as_str())
+    .bind(note)
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Grants the onboarding bonus to a user about to place their first wager.
+///
+/// Returns the user's new mobiums, or `None` if the user already wagered
+/// before or was already granted the bonus.
+pub async fn grant_onboarding_bonus(
+    user_id: UserId,
+    bonus: i64,
+    conn: &mut SqliteConnection,
+) -> Result<Option<i64>, Error> {
+    if bonus <= 0 {
+        return Ok(None);
+    }
+
+    let (onboarded,) = sqlx::query_as::<_, (bool,)>(
+        r#"
+        SELECT
+            EXISTS (SELECT 1 FROM wager WHERE user_id = $1)
+            OR EXISTS (SELECT 1 FROM ledger WHERE user_id = $1 AND reason = $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(LedgerReason::OnboardingBonus.as_str())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if onboarded {
+        return Ok(None);
+    }
+
+    let (mobiums,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        UPDATE user
+        SET mobiums = mobiums + $2
+        WHERE id = $1
+        RETURNING mobiums
+        "#,
+    )
+    .bind(user_id)
+    .bind(bonus)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    record(user_id, bonus, LedgerReason::OnboardingBonus, conn).await?;
+
+    Ok(Some(mobiums))
+}
@@ -0,0 +1,349 @@
+//! Account linking.
+//!
+//! Users that switch Discord accounts can carry everything over by
+//! generating a code on their old account, and redeeming it on the new one.
+//! The old account is then merged into the new one, and lookups of the old
+//! account are redirected to the new one.
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use rand::distr::{Alphanumeric, SampleString};
+
+use ring_channel_model::{UserId, battle::BattleStatus, user::LinkCode};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{
+    battle::BAILOUT_MOBIUMS,
+    error::{Error, ErrorKind},
+    squad::leave_squad,
+    user::ledger::LedgerReason,
+};
+
+/// How long link codes can be redeemed for.
+pub const LINK_CODE_LIFETIME: TimeDelta = TimeDelta::minutes(15);
+
+/// The length of link codes.
+pub const LINK_CODE_LENGTH: usize = 12;
+
+/// Generates a link code for a user, replacing any code they had before.
+pub async fn create_code(user_id: UserId, conn: &mut SqliteConnection) -> Result<LinkCode, Error> {
+    let now = Utc::now();
+    let expires_at = now + LINK_CODE_LIFETIME;
+    let code = Alphanumeric.sample_string(&mut rand::rng(), LINK_CODE_LENGTH);
+
+    sqlx::query(
+        r#"
+        INSERT INTO link_code (user_id, code, expires_at, inserted_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id) DO UPDATE
+        SET
+            code = $2,
+            expires_at = $3,
+            inserted_at = $4
+        "#,
+    )
+    .bind(user_id)
+    .bind(&code)
+    .bind(expires_at)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(LinkCode { code, expires_at })
+}
+
+/// Redeems a link code, returning the user that generated it.
+///
+/// Codes can only be redeemed once.
+pub async fn redeem_code(code: &str, conn: &mut SqliteConnection) -> Result<UserId, Error> {
+    #[derive(FromRow)]
+    struct CodeQuery {
+        user_id: UserId,
+        expires_at: DateTime<Utc>,
+    }
+
+    let query = sqlx::query_as::<_, CodeQuery>(
+        r#"
+        DELETE FROM link_code
+        WHERE code = $1
+        RETURNING user_id, expires_at
+        "#,
+    )
+    .bind(code)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match query {
+        Some(query) if query.expires_at > Utc::now() => Ok(query.user_id),
+        _ => Err(ErrorKind::InvalidData("Invalid or expired link code".into()).into()),
+    }
+}
+
+/// Merges the user `from` into `into`, returning `into`'s new mobiums.
+///
+/// Stats and wager history are all moved over, and `from` is marked as
+/// merged. Neither user can have a wager on an ongoing match.
+///
+/// Only mobiums `from` actually won are moved over. Its starting balance,
+/// bonuses and bailouts stay behind, so fresh accounts can't be farmed for
+/// them.
+pub async fn merge(from: UserId, into: UserId, conn: &mut SqliteConnection) -> Result<i64, Error> {
+    if from == into {
+        return Err(ErrorKind::InvalidData("Cannot link an account to itself".into()).into());
+    }
+
+    let now = Utc::now();
+
+    let (open_wagers,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COUNT(*)
+        FROM wager w
+        INNER JOIN battle b ON b.id = w.match_id
+        WHERE
+            w.user_id IN ($1, $2)
+            AND w.mobiums > 0
+            AND b.status IN ($3, $4)
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .bind(BattleStatus::Ongoing)
+    .bind(BattleStatus::Provisional)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if open_wagers > 0 {
+        return Err(ErrorKind::InvalidData(
+            "Accounts cannot be linked while they have wagers on ongoing matches".into(),
+        )
+        .into());
+    }
+
+    #[derive(FromRow)]
+    struct StatsQuery {
+        mobiums: i64,
+        mobiums_gained: i64,
+        mobiums_lost: i64,
+        bailout_count: i64,
+    }
+
+    let old = sqlx::query_as::<_, StatsQuery>(
+        r#"
+        SELECT mobiums, mobiums_gained, mobiums_lost, bailout_count
+        FROM user
+        WHERE id = $1 AND merged_into IS NULL
+        "#,
+    )
+    .bind(from)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(old) = old else {
+        return Err(ErrorKind::InvalidData("Account was already linked".into()).into());
+    };
+
+    let (granted,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COALESCE(SUM(amount), 0)
+        FROM ledger
+        WHERE user_id = $1 AND reason IN ($2, $3, $4)
+        "#,
+    )
+    .bind(from)
+    .bind(LedgerReason::StartingBalance.as_str())
+    .bind(LedgerReason::OnboardingBonus.as_str())
+    .bind(LedgerReason::PromoBonus.as_str())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let winnings = (old.mobiums - granted - old.bailout_count * BAILOUT_MOBIUMS).max(0);
+
+    // move the balance and stats over
+    let (mobiums,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        UPDATE user
+        SET
+            mobiums = mobiums + $2,
+            mobiums_gained = mobiums_gained + $3,
+            mobiums_lost = mobiums_lost + $4,
+            bailout_count = bailout_count + $5,
+            updated_at = $6
+        WHERE id = $1
+        RETURNING mobiums
+        "#,
+    )
+    .bind(into)
+    .bind(winnings)
+    .bind(old.mobiums_gained)
+    .bind(old.mobiums_lost)
+    .bind(old.bailout_count)
+    .bind(now)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE user
+        SET
+            mobiums = 0,
+            mobiums_gained = 0,
+            mobiums_lost = 0,
+            bailout_count = 0,
+            merged_into = $2,
+            updated_at = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    // keep redirects one hop deep
+    sqlx::query(
+        r#"
+        UPDATE user
+        SET merged_into = $2
+        WHERE merged_into = $1
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *conn)
+    .await?;
+
+    // a user only has one wager per market of a match, so wagers on markets
+    // both users bet on stay behind
+    sqlx::query(
+        r#"
+        UPDATE wager
+        SET user_id = $2
+        WHERE
+            user_id = $1
+            AND NOT EXISTS (
+                SELECT 1
+                FROM wager o
+                WHERE
+                    o.user_id = $2
+                    AND o.match_id = wager.match_id
+                    AND o.market = wager.market
+            )
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE bailout
+        SET user_id = $2
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE message
+        SET user_id = $2
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *conn)
+    .await?;
+
+    // reactions both users left count once
+    sqlx::query(
+        r#"
+        UPDATE OR IGNORE message_reaction
+        SET user_id = $2
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM message_reaction
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(from)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE player
+        SET user_id = $2
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *conn)
+    .await?;
+
+    // the account being merged can't wager anymore
+    leave_squad(from, now, &mut *conn).await?;
+
+    Ok(mobiums)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{testing, user::ledger::record};
+
+    #[tokio::test]
+    async fn test_merge_moves_only_winnings() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let into = testing::create_user("into", 1000, &mut conn).await;
+        let from = testing::create_user("from", 1500, &mut conn).await;
+        record(from, 1000, LedgerReason::StartingBalance, &mut conn)
+            .await
+            .unwrap();
+        record(from, 200, LedgerReason::OnboardingBonus, &mut conn)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE user SET bailout_count = 1 WHERE id = $1")
+            .bind(from)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        let mobiums = merge(from, into, &mut conn).await.unwrap();
+        assert_eq!(mobiums, 1200);
+        assert_eq!(testing::mobiums(from, &mut conn).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_merge_of_losing_account_moves_nothing() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let into = testing::create_user("into", 1000, &mut conn).await;
+        let from = testing::create_user("from", 900, &mut conn).await;
+        record(from, 1000, LedgerReason::StartingBalance, &mut conn)
+            .await
+            .unwrap();
+
+        let mobiums = merge(from, into, &mut conn).await.unwrap();
+        assert_eq!(mobiums, 1000);
+    }
+}
@@ -1,21 +1,35 @@
 //! User structs and utilities.
 
+pub mod auto_bet;
 pub mod bot;
+pub mod ledger;
+pub mod link;
+pub mod penalty;
 
-use ring_channel_model::{User, user::UserFlags};
+use ring_channel_model::{
+    BattleId, Mobiums, User, UserId,
+    battle::{BattleStatus, WagerMarket},
+    user::{UserFlags, WagerBalance},
+};
 
-use sqlx::FromRow;
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::error::Error;
+
+/// How many mobiums new users start with, by default.
+pub const DEFAULT_STARTING_MOBIUMS: i64 = 400;
 
 /// A user schema.
 #[derive(FromRow)]
 pub struct UserSchema {
-    pub id: i32,
+    pub id: UserId,
     pub username: String,
     pub avatar: Option<String>,
     pub display_name: String,
     pub mobiums: i64,
     pub mobiums_gained: i64,
     pub mobiums_lost: i64,
+    pub streak: i32,
     #[sqlx(try_from = "i32")]
     pub flags: UserFlags,
 }
@@ -26,9 +40,10 @@ impl From<UserSchema> for User {
             username: value.username,
             avatar: value.avatar,
             display_name: value.display_name,
-            mobiums: value.mobiums,
-            mobiums_gained: value.mobiums_gained,
-            mobiums_lost: value.mobiums_lost,
+            mobiums: value.mobiums.into(),
+            mobiums_gained: value.mobiums_gained.into(),
+            mobiums_lost: value.mobiums_lost.into(),
+            streak: value.streak,
             flags: value.flags,
         }
     }
@@ -40,10 +55,121 @@ impl From<&UserSchema> for User {
             username: value.username.clone(),
             avatar: value.avatar.clone(),
             display_name: value.display_name.clone(),
-            mobiums: value.mobiums,
-            mobiums_gained: value.mobiums_gained,
-            mobiums_lost: value.mobiums_lost,
+            mobiums: value.mobiums.into(),
+            mobiums_gained: value.mobiums_gained.into(),
+            mobiums_lost: value.mobiums_lost.into(),
+            streak: value.streak,
             flags: value.flags,
         }
     }
 }
+
+/// Gets a user's mobiums, and how many are staked on matches that haven't
+/// been paid out yet.
+///
+/// The user's wager in the market of a match in `except`, if they have one,
+/// isn't counted as staked.
+pub async fn wager_balance(
+    user_id: UserId,
+    except: Option<(BattleId, WagerMarket)>,
+    conn: &mut SqliteConnection,
+) -> Result<WagerBalance, Error> {
+    let (mobiums, reserved) = sqlx::query_as::<_, (Mobiums, Mobiums)>(
+        r#"
+        SELECT
+            u.mobiums,
+            IFNULL(
+                (
+                    SELECT SUM(w.mobiums)
+                    FROM wager w
+                    INNER JOIN battle b ON b.id = w.match_id
+                    WHERE
+                        w.user_id = u.id
+                        AND b.status IN ($2, $3)
+                        AND ($4 IS NULL OR w.match_id != $4 OR w.market != $5)
+                ),
+                0
+            )
+        FROM user u
+        WHERE u.id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(BattleStatus::Ongoing)
+    .bind(BattleStatus::Provisional)
+    .bind(except.map(|(battle_id, _)| battle_id))
+    .bind(except.map(|(_, market)| market))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(WagerBalance {
+        mobiums,
+        reserved,
+        available: mobiums
+            .checked_sub(reserved)
+            .unwrap_or(Mobiums::ZERO)
+            .max(Mobiums::ZERO),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing;
+
+    #[tokio::test]
+    async fn test_wagers_on_open_matches_are_reserved() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let user = testing::create_user("sonic", 1000, &mut conn).await;
+        let ongoing = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+        let provisional = testing::create_battle(BattleStatus::Provisional, &mut conn).await;
+        let concluded = testing::create_battle(BattleStatus::Concluded, &mut conn).await;
+
+        testing::place(user, ongoing, WagerMarket::Winner, 0, 300, &mut conn).await;
+        testing::place(user, ongoing, WagerMarket::FinishTime, 1, 100, &mut conn).await;
+        testing::place(user, provisional, WagerMarket::Winner, 1, 200, &mut conn).await;
+        // already paid out, so it's out of the balance already
+        testing::place(user, concluded, WagerMarket::Winner, 0, 50, &mut conn).await;
+
+        let balance = wager_balance(user, None, &mut conn).await.unwrap();
+        assert_eq!(balance.mobiums, Mobiums(1000));
+        assert_eq!(balance.reserved, Mobiums(600));
+        assert_eq!(balance.available, Mobiums(400));
+
+        // changing a wager frees what is already on it, and nothing else
+        let balance = wager_balance(user, Some((ongoing, WagerMarket::Winner)), &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(balance.reserved, Mobiums(300));
+        assert_eq!(balance.available, Mobiums(700));
+
+        let balance = wager_balance(
+            user,
+            Some((provisional, WagerMarket::FinishTime)),
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        assert_eq!(balance.reserved, Mobiums(600));
+    }
+
+    #[tokio::test]
+    async fn test_available_never_goes_negative() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        let user = testing::create_user("tails", 100, &mut conn).await;
+        let first = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+        let second = testing::create_battle(BattleStatus::Ongoing, &mut conn).await;
+
+        testing::place(user, first, WagerMarket::Winner, 0, 80, &mut conn).await;
+        testing::place(user, second, WagerMarket::Winner, 1, 80, &mut conn).await;
+
+        let balance = wager_balance(user, None, &mut conn).await.unwrap();
+        assert_eq!(balance.reserved, Mobiums(160));
+        assert_eq!(balance.available, Mobiums::ZERO);
+    }
+}
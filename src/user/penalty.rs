@@ -0,0 +1,184 @@
+//! Account standing.
+//!
+//! Administrators can warn abusive users, ban them from wagering, or mute them
+//! in chat. Penalties given without an expiry escalate: each penalty lasts
+//! longer than the last one of its kind.
+
+use axum::{
+    RequestPartsExt as _,
+    extract::{FromRef, FromRequestParts},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use derive_more::Deref;
+
+use http::request::Parts;
+
+use ring_channel_model::{
+    UserId,
+    user::{Penalty, PenaltyKind},
+};
+
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{
+    app::AppState,
+    error::{Error, ErrorKind},
+    session::SessionUser,
+};
+
+/// How long penalties given without an expiry last, by how many penalties of
+/// the same kind the user already had.
+///
+/// Users past the end of the ladder get the last step again.
+pub const PENALTY_LADDER: [TimeDelta; 4] = [
+    TimeDelta::hours(1),
+    TimeDelta::days(1),
+    TimeDelta::days(7),
+    TimeDelta::days(30),
+];
+
+/// How long warnings given without an expiry count against a user.
+pub const WARNING_LIFETIME: TimeDelta = TimeDelta::days(30);
+
+/// A penalty schema.
+#[derive(FromRow)]
+pub struct PenaltySchema {
+    pub id: i32,
+    #[sqlx(try_from = "u8")]
+    pub kind: PenaltyKind,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub inserted_at: DateTime<Utc>,
+}
+
+impl From<PenaltySchema> for Penalty {
+    fn from(value: PenaltySchema) -> Self {
+        Penalty {
+            id: value.id,
+            kind: value.kind,
+            reason: value.reason,
+            expires_at: value.expires_at,
+            revoked_at: value.revoked_at,
+            created_at: value.inserted_at,
+        }
+    }
+}
+
+/// An authenticated user who isn't banned from wagering.
+#[derive(Clone, Debug, Deref)]
+pub struct WagerUser(pub SessionUser);
+
+impl<S> FromRequestParts<S> for WagerUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = parts.extract_with_state::<SessionUser, S>(state).await?;
+
+        let state = AppState::from_ref(state);
+        let mut conn = state.db.acquire().await?;
+        check_penalty(user.identity(), PenaltyKind::WagerBan, &mut conn).await?;
+
+        Ok(WagerUser(user))
+    }
+}
+
+/// Lists a user's penalties that haven't expired or been revoked, newest
+/// first.
+pub async fn active_penalties(
+    user_id: UserId,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<Penalty>, Error> {
+    let penalties = sqlx::query_as::<_, PenaltySchema>(
+        r#"
+        SELECT id, kind, reason, expires_at, revoked_at, inserted_at
+        FROM penalty
+        WHERE
+            user_id = $1
+            AND revoked_at IS NULL
+            AND (expires_at IS NULL OR expires_at > $2)
+        ORDER BY inserted_at DESC, id DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(now)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(penalties.into_iter().map(Penalty::from).collect())
+}
+
+/// Checks that a user isn't under a penalty of `kind`.
+///
+/// Warnings never keep users from doing anything.
+pub async fn check_penalty(
+    user_id: UserId,
+    kind: PenaltyKind,
+    conn: &mut SqliteConnection,
+) -> Result<(), Error> {
+    if kind == PenaltyKind::Warning {
+        return Ok(());
+    }
+
+    // the penalty that lasts longest is the one that matters
+    let penalty = sqlx::query_as::<_, (Option<DateTime<Utc>>,)>(
+        r#"
+        SELECT expires_at
+        FROM penalty
+        WHERE
+            user_id = $1
+            AND kind = $2
+            AND revoked_at IS NULL
+            AND (expires_at IS NULL OR expires_at > $3)
+        ORDER BY expires_at IS NULL DESC, expires_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(u8::from(kind))
+    .bind(Utc::now())
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match penalty {
+        Some((expires_at,)) => Err(ErrorKind::Penalized { kind, expires_at }.into()),
+        None => Ok(()),
+    }
+}
+
+/// Finds when a new penalty of `kind` should expire, going by the user's
+/// history.
+///
+/// Revoked penalties don't count.
+pub async fn escalated_expiry(
+    user_id: UserId,
+    kind: PenaltyKind,
+    now: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<DateTime<Utc>, Error> {
+    if kind == PenaltyKind::Warning {
+        return Ok(now + WARNING_LIFETIME);
+    }
+
+    let (previous,) = sqlx::query_as::<_, (i64,)>(
+        r#"
+        SELECT COUNT(*)
+        FROM penalty
+        WHERE user_id = $1 AND kind = $2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(u8::from(kind))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let step = (previous as usize).min(PENALTY_LADDER.len() - 1);
+    Ok(now + PENALTY_LADDER[step])
+}
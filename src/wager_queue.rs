@@ -0,0 +1,300 @@
+//! Wager write coalescing.
+//!
+//! Betting closes on a timer, so most wagers on a popular match land in its
+//! last few seconds. Instead of every request fighting over SQLite's write
+//! lock, wagers are sent to a [`WagerQueue`], and a single writer places them
+//! in batches, one transaction per batch.
+//!
+//! Each wager gets its own savepoint, so one bad wager doesn't take the rest
+//! of its batch down with it.
+
+use std::time::Duration;
+
+use ring_channel_model::{
+    Mobiums,
+    battle::{LineSide, LineWagerReceipt, PlayerTeam, WagerReceipt},
+    request::battle::WagerAmount,
+};
+
+use sqlx::{Acquire as _, Connection as _};
+
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+    time::{Instant, timeout_at},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    app::AppState,
+    audit::Audit,
+    error::{Error, ErrorKind},
+    metrics::Metrics,
+    routes::battle::{
+        line::{PlacedLineWager, write_line_wager},
+        wager::{PlacedWager, write_wager},
+    },
+    session::SessionUser,
+    user::bot::get_wager_bot,
+};
+
+/// How many wagers can wait to be written before new ones are turned away.
+pub const WAGER_QUEUE_CAPACITY: usize = 256;
+
+/// How long the writer waits for more wagers before writing a batch.
+pub const BATCH_WINDOW: Duration = Duration::from_millis(50);
+
+/// The most wagers written in a single transaction.
+pub const MAX_BATCH_SIZE: usize = 64;
+
+/// A wager waiting to be written.
+#[derive(Debug)]
+pub struct WagerRequest {
+    /// The user placing the wager.
+    pub user: SessionUser,
+    /// The audit entry of the request that placed the wager.
+    pub audit: Audit,
+    /// The uuid of the match.
+    pub match_id: Uuid,
+    /// The team the wager is on.
+    pub victor: PlayerTeam,
+    /// How many mobiums are wagered.
+    pub amount: WagerAmount,
+    /// The token the client placed the wager with, if any.
+    pub client_token: Option<String>,
+    /// Whether the user's auto-bet is placing the wager.
+    ///
+    /// Auto-bets never replace a wager the user already has, and count
+    /// against the rule's daily cap.
+    pub auto_bet: bool,
+}
+
+/// A wager on a match's finish time line waiting to be written.
+#[derive(Debug)]
+pub struct LineWagerRequest {
+    /// The user placing the wager.
+    pub user: SessionUser,
+    /// The audit entry of the request that placed the wager.
+    pub audit: Audit,
+    /// The uuid of the match.
+    pub match_id: Uuid,
+    /// The side of the line the wager is on.
+    pub side: LineSide,
+    /// How many mobiums are wagered.
+    pub mobiums: Mobiums,
+    /// The token the client placed the wager with, if any.
+    pub client_token: Option<String>,
+}
+
+#[derive(Debug)]
+enum QueuedWager {
+    Winner {
+        request: WagerRequest,
+        respond: oneshot::Sender<Result<WagerReceipt, Error>>,
+    },
+    Line {
+        request: LineWagerRequest,
+        respond: oneshot::Sender<Result<LineWagerReceipt, Error>>,
+    },
+}
+
+/// A wager that was written, or failed to be, waiting to be answered.
+enum WrittenWager {
+    Winner {
+        respond: oneshot::Sender<Result<WagerReceipt, Error>>,
+        result: Result<PlacedWager, Error>,
+    },
+    Line {
+        respond: oneshot::Sender<Result<LineWagerReceipt, Error>>,
+        result: Result<PlacedLineWager, Error>,
+    },
+}
+
+impl WrittenWager {
+    fn is_ok(&self) -> bool {
+        match self {
+            WrittenWager::Winner { result, .. } => result.is_ok(),
+            WrittenWager::Line { result, .. } => result.is_ok(),
+        }
+    }
+
+    /// Answers the request, announcing the wager if it was placed.
+    fn answer(self, state: &AppState) {
+        match self {
+            WrittenWager::Winner { respond, result } => {
+                let _ = respond.send(result.map(|placed| placed.announce(state)));
+            }
+            WrittenWager::Line { respond, result } => {
+                let _ = respond.send(result.map(|placed| placed.announce(state)));
+            }
+        }
+    }
+
+    /// Fails the request, even if the wager was written.
+    fn fail(self) {
+        let err = || ErrorKind::Other(eyre::eyre!("failed to write wager batch")).into();
+        match self {
+            WrittenWager::Winner { respond, .. } => {
+                let _ = respond.send(Err(err()));
+            }
+            WrittenWager::Line { respond, .. } => {
+                let _ = respond.send(Err(err()));
+            }
+        }
+    }
+}
+
+/// Queues wagers for the writer.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct WagerQueue {
+    tx: mpsc::Sender<QueuedWager>,
+    metrics: Metrics,
+}
+
+/// The receiving end of a [`WagerQueue`].
+///
+/// Pass this to [`run_wager_writer`].
+#[derive(Debug)]
+pub struct WagerReceiver {
+    rx: mpsc::Receiver<QueuedWager>,
+}
+
+impl WagerQueue {
+    /// Creates a new `WagerQueue`.
+    pub fn new(metrics: Metrics) -> (WagerQueue, WagerReceiver) {
+        let (tx, rx) = mpsc::channel(WAGER_QUEUE_CAPACITY);
+        (WagerQueue { tx, metrics }, WagerReceiver { rx })
+    }
+
+    /// Queues a wager, waiting for it to be written.
+    ///
+    /// Fails with [`ErrorKind::Busy`] instead of waiting if the queue is full.
+    pub async fn place(&self, request: WagerRequest) -> Result<WagerReceipt, Error> {
+        let (respond, rx) = oneshot::channel();
+        self.send(QueuedWager::Winner { request, respond })?;
+
+        rx.await
+            .map_err(|_| ErrorKind::Other(eyre::eyre!("wager writer dropped a wager")))?
+    }
+
+    /// Queues a wager on a match's finish time line, waiting for it to be
+    /// written.
+    ///
+    /// Fails like [`WagerQueue::place`].
+    pub async fn place_line(&self, request: LineWagerRequest) -> Result<LineWagerReceipt, Error> {
+        let (respond, rx) = oneshot::channel();
+        self.send(QueuedWager::Line { request, respond })?;
+
+        rx.await
+            .map_err(|_| ErrorKind::Other(eyre::eyre!("wager writer dropped a wager")))?
+    }
+
+    fn send(&self, wager: QueuedWager) -> Result<(), Error> {
+        match self.tx.try_send(wager) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.metrics.increment("wager_queue_full_total", &[]);
+                Err(ErrorKind::Busy.into())
+            }
+            Err(TrySendError::Closed(_)) => {
+                Err(ErrorKind::Other(eyre::eyre!("wager writer is not running")).into())
+            }
+        }
+    }
+}
+
+/// Writes queued wagers until every [`WagerQueue`] is dropped.
+pub async fn run_wager_writer(state: AppState, receiver: &mut WagerReceiver) {
+    while let Some(first) = receiver.rx.recv().await {
+        let mut batch = vec![first];
+
+        // give stragglers a moment to join the batch
+        let deadline = Instant::now() + BATCH_WINDOW;
+        while batch.len() < MAX_BATCH_SIZE {
+            match timeout_at(deadline, receiver.rx.recv()).await {
+                Ok(Some(wager)) => batch.push(wager),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        state.metrics.increment("wager_batches_total", &[]);
+        state
+            .metrics
+            .add("wager_batched_total", &[], batch.len() as u64);
+
+        let started_at = Instant::now();
+        write_batch(&state, batch).await;
+        state.metrics.observe(
+            "wager_batch_duration_seconds",
+            &[],
+            started_at.elapsed().as_secs_f64(),
+        );
+    }
+}
+
+/// Writes a batch of wagers in one transaction, then answers every request.
+async fn write_batch(state: &AppState, batch: Vec<QueuedWager>) {
+    let mut results = Vec::with_capacity(batch.len());
+
+    let committed = async {
+        let mut conn = state.db.acquire().await?;
+
+        let wager_bot = if state.bot.enabled() {
+            Some(get_wager_bot(&state.config.server.bot, &mut conn).await?)
+        } else {
+            None
+        };
+
+        // take the write lock up front, so nothing else can write wagers
+        // between a wager being read and written back
+        let mut tx = conn.begin_with("BEGIN IMMEDIATE").await?;
+
+        for wager in batch {
+            let mut savepoint = tx.begin().await?;
+
+            let written = match wager {
+                QueuedWager::Winner { request, respond } => WrittenWager::Winner {
+                    respond,
+                    result: write_wager(state, wager_bot.as_ref(), &request, &mut savepoint).await,
+                },
+                QueuedWager::Line { request, respond } => WrittenWager::Line {
+                    respond,
+                    result: write_line_wager(state, &request, &mut savepoint).await,
+                },
+            };
+            if written.is_ok() {
+                savepoint.commit().await?;
+            } else {
+                savepoint.rollback().await?;
+            }
+
+            results.push(written);
+        }
+
+        tx.commit().await?;
+
+        Ok::<_, Error>(())
+    }
+    .await;
+
+    if let Err(err) = committed {
+        tracing::error!(?err, "failed to write wager batch");
+        state.metrics.increment("wager_batch_failures_total", &[]);
+
+        // nothing in the batch was written
+        // wagers that were never gotten to were dropped, which fails them too
+        for written in results {
+            written.fail();
+        }
+        return;
+    }
+
+    for written in results {
+        written.answer(state);
+    }
+}
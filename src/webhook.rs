@@ -0,0 +1,426 @@
+//! Outbound webhooks.
+//!
+//! Match lifecycle events are queued in the database alongside the change
+//! that caused them, and sent by a [`Dispatcher`] in the background. Failed
+//! deliveries are retried with exponential backoff.
+//!
+//! Every delivery is signed with the webhook's secret, so receivers can check
+//! it actually came from us. The signature is the hex HMAC-SHA256 of the
+//! timestamp header, a `.`, and the body.
+
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+
+use hmac::{Hmac, Mac as _};
+
+use http::{HeaderName, header};
+
+use ring_channel_model::webhook::{WebhookEvent, WebhookPayload};
+
+use sha2::Sha256;
+
+use sqlx::{FromRow, SqliteConnection, SqlitePool};
+
+use crate::error::Error;
+
+pub const X_WEBHOOK_EVENT: HeaderName = HeaderName::from_static("x-webhook-event");
+pub const X_WEBHOOK_DELIVERY: HeaderName = HeaderName::from_static("x-webhook-delivery");
+pub const X_WEBHOOK_TIMESTAMP: HeaderName = HeaderName::from_static("x-webhook-timestamp");
+pub const X_WEBHOOK_SIGNATURE: HeaderName = HeaderName::from_static("x-webhook-signature");
+
+/// How many times a delivery is attempted before it is given up on.
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 8;
+
+/// How long to wait before retrying a delivery for the first time.
+///
+/// This doubles every failed attempt.
+pub const BASE_RETRY_DELAY: TimeDelta = TimeDelta::seconds(10);
+
+/// How long receivers have to respond.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many deliveries are sent per dispatch.
+pub const DISPATCH_BATCH_SIZE: i32 = 50;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Queues an event for every registered webhook.
+///
+/// Run this in the same transaction as the change, so events are never sent
+/// for changes that were rolled back.
+pub async fn enqueue(event: WebhookEvent, conn: &mut SqliteConnection) -> Result<(), Error> {
+    let now = Utc::now();
+    let name = event.name();
+
+    let payload = serde_json::to_string(&WebhookPayload {
+        created_at: now,
+        event,
+    })
+    .map_err(Error::new)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO webhook_delivery
+            (webhook_id, event, payload, attempts, next_attempt_at, inserted_at)
+        SELECT id, $1, $2, 0, $3, $3
+        FROM webhook
+        "#,
+    )
+    .bind(name)
+    .bind(payload)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Signs a delivery.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+
+    base16::encode_lower(&mac.finalize().into_bytes())
+}
+
+/// How long to wait after a delivery failed `attempts` times.
+pub fn retry_delay(attempts: i32) -> TimeDelta {
+    BASE_RETRY_DELAY * 2i32.pow(attempts.saturating_sub(1).clamp(0, 16) as u32)
+}
+
+/// Sends queued webhook deliveries.
+///
+/// Cheaply cloneable.
+#[derive(Clone, Debug)]
+pub struct Dispatcher {
+    http_client: reqwest::Client,
+}
+
+impl Dispatcher {
+    /// Creates a new `Dispatcher`.
+    pub fn new() -> Result<Dispatcher, Error> {
+        let http_client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()?;
+
+        Ok(Dispatcher { http_client })
+    }
+
+    /// Sends every delivery that is due.
+    pub async fn dispatch(&self, db: &SqlitePool) -> Result<(), Error> {
+        #[derive(FromRow)]
+        struct DeliveryQuery {
+            id: i32,
+            event: String,
+            payload: String,
+            attempts: i32,
+            url: String,
+            secret: String,
+        }
+
+        let now = Utc::now();
+
+        let deliveries = sqlx::query_as::<_, DeliveryQuery>(
+            r#"
+            SELECT d.id, d.event, d.payload, d.attempts, w.url, w.secret
+            FROM webhook_delivery d
+            INNER JOIN webhook w ON w.id = d.webhook_id
+            WHERE d.next_attempt_at <= $1
+            ORDER BY d.next_attempt_at
+            LIMIT $2
+            "#,
+        )
+        .bind(now)
+        .bind(DISPATCH_BATCH_SIZE)
+        .fetch_all(db)
+        .await?;
+
+        for delivery in deliveries {
+            let attempts = delivery.attempts + 1;
+
+            let result = self
+                .send(
+                    delivery.id,
+                    &delivery.event,
+                    &delivery.url,
+                    &delivery.secret,
+                    delivery.payload,
+                )
+                .await;
+
+            let now = Utc::now();
+
+            match result {
+                Ok(()) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE webhook_delivery
+                        SET attempts = $2, next_attempt_at = NULL, delivered_at = $3,
+                            last_error = NULL
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(delivery.id)
+                    .bind(attempts)
+                    .bind(now)
+                    .execute(db)
+                    .await?;
+                }
+                Err(err) if attempts >= MAX_DELIVERY_ATTEMPTS => {
+                    tracing::warn!(id = delivery.id, url = delivery.url, %err, "giving up on webhook delivery");
+
+                    sqlx::query(
+                        r#"
+                        UPDATE webhook_delivery
+                        SET attempts = $2, next_attempt_at = NULL, failed_at = $3,
+                            last_error = $4
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(delivery.id)
+                    .bind(attempts)
+                    .bind(now)
+                    .bind(err.to_string())
+                    .execute(db)
+                    .await?;
+                }
+                Err(err) => {
+                    tracing::debug!(id = delivery.id, url = delivery.url, %err, attempts, "webhook delivery failed");
+
+                    sqlx::query(
+                        r#"
+                        UPDATE webhook_delivery
+                        SET attempts = $2, next_attempt_at = $3, last_error = $4
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(delivery.id)
+                    .bind(attempts)
+                    .bind(now + retry_delay(attempts))
+                    .bind(err.to_string())
+                    .execute(db)
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        id: i32,
+        event: &str,
+        url: &str,
+        secret: &str,
+        payload: String,
+    ) -> Result<(), reqwest::Error> {
+        let timestamp = Utc::now().timestamp();
+        let signature = sign(secret, timestamp, &payload);
+
+        self.http_client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(X_WEBHOOK_EVENT, event)
+            .header(X_WEBHOOK_DELIVERY, id.to_string())
+            .header(X_WEBHOOK_TIMESTAMP, timestamp.to_string())
+            .header(X_WEBHOOK_SIGNATURE, format!("sha256={}", signature))
+            .body(payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::{Router, routing::post};
+
+    use chrono::DateTime;
+
+    use http::{HeaderMap, StatusCode};
+
+    use ring_channel_model::battle::{Battle, BattleStatus};
+
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use crate::{battle::get_battle_schema, testing};
+
+    /// Serves a receiver that answers every delivery with `status`.
+    ///
+    /// Returns its url, and what it was sent.
+    async fn receiver(
+        status: StatusCode,
+    ) -> (String, mpsc::UnboundedReceiver<(HeaderMap, String)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/",
+            post(move |headers: HeaderMap, body: String| async move {
+                tx.send((headers, body)).unwrap();
+                status
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        (url, rx)
+    }
+
+    async fn create_webhook(url: &str, secret: &str, conn: &mut SqliteConnection) {
+        sqlx::query("INSERT INTO webhook (url, secret, inserted_at) VALUES ($1, $2, $3)")
+            .bind(url)
+            .bind(secret)
+            .bind(Utc::now())
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+    }
+
+    async fn enqueue_created(conn: &mut SqliteConnection) {
+        let battle_id = testing::create_battle(BattleStatus::Ongoing, &mut *conn).await;
+        let schema = get_battle_schema(battle_id, &mut *conn).await.unwrap();
+
+        enqueue(
+            WebhookEvent::MatchCreated(Battle::from(&schema)),
+            &mut *conn,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[derive(FromRow)]
+    struct DeliveryState {
+        attempts: i32,
+        next_attempt_at: Option<DateTime<Utc>>,
+        delivered_at: Option<DateTime<Utc>>,
+        failed_at: Option<DateTime<Utc>>,
+        last_error: Option<String>,
+    }
+
+    async fn delivery(conn: &mut SqliteConnection) -> DeliveryState {
+        sqlx::query_as::<_, DeliveryState>(
+            r#"
+            SELECT attempts, next_attempt_at, delivered_at, failed_at, last_error
+            FROM webhook_delivery
+            "#,
+        )
+        .fetch_one(&mut *conn)
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        assert_eq!(retry_delay(1), BASE_RETRY_DELAY);
+        assert_eq!(retry_delay(2), BASE_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(4), BASE_RETRY_DELAY * 8);
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_queues_for_every_webhook() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+
+        create_webhook("http://a.example/", "a", &mut conn).await;
+        create_webhook("http://b.example/", "b", &mut conn).await;
+        enqueue_created(&mut conn).await;
+
+        let deliveries = sqlx::query_as::<_, (String, i32, Option<String>)>(
+            "SELECT event, attempts, next_attempt_at FROM webhook_delivery",
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap();
+
+        assert_eq!(deliveries.len(), 2);
+        for (event, attempts, next_attempt_at) in deliveries {
+            assert_eq!(event, "match-created");
+            assert_eq!(attempts, 0);
+            assert!(next_attempt_at.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_are_signed() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+        let (url, mut sent) = receiver(StatusCode::OK).await;
+
+        create_webhook(&url, "hunter2", &mut conn).await;
+        enqueue_created(&mut conn).await;
+
+        Dispatcher::new()
+            .unwrap()
+            .dispatch(&state.db)
+            .await
+            .unwrap();
+
+        let (headers, body) = sent.recv().await.unwrap();
+        let timestamp = headers[&X_WEBHOOK_TIMESTAMP]
+            .to_str()
+            .unwrap()
+            .parse::<i64>()
+            .unwrap();
+        assert_eq!(headers[&X_WEBHOOK_EVENT], "match-created");
+        assert_eq!(
+            headers[&X_WEBHOOK_SIGNATURE].to_str().unwrap(),
+            format!("sha256={}", sign("hunter2", timestamp, &body)),
+        );
+        assert_ne!(
+            headers[&X_WEBHOOK_SIGNATURE].to_str().unwrap(),
+            format!("sha256={}", sign("hunter3", timestamp, &body)),
+        );
+
+        let delivery = delivery(&mut conn).await;
+        assert_eq!(delivery.attempts, 1);
+        assert!(delivery.delivered_at.is_some());
+        assert!(delivery.next_attempt_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries_are_retried() {
+        let state = testing::state().await;
+        let mut conn = state.db.acquire().await.unwrap();
+        let (url, _sent) = receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let dispatcher = Dispatcher::new().unwrap();
+
+        create_webhook(&url, "hunter2", &mut conn).await;
+        enqueue_created(&mut conn).await;
+
+        let before = Utc::now();
+        dispatcher.dispatch(&state.db).await.unwrap();
+
+        let retried = delivery(&mut conn).await;
+        assert_eq!(retried.attempts, 1);
+        assert!(retried.last_error.is_some());
+        assert!(retried.delivered_at.is_none());
+        assert!(retried.next_attempt_at.unwrap() >= before + retry_delay(1));
+
+        // not due yet
+        dispatcher.dispatch(&state.db).await.unwrap();
+        assert_eq!(delivery(&mut conn).await.attempts, 1);
+
+        // the last attempt gives up
+        sqlx::query("UPDATE webhook_delivery SET attempts = $1, next_attempt_at = $2")
+            .bind(MAX_DELIVERY_ATTEMPTS - 1)
+            .bind(Utc::now())
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        dispatcher.dispatch(&state.db).await.unwrap();
+
+        let given_up = delivery(&mut conn).await;
+        assert_eq!(given_up.attempts, MAX_DELIVERY_ATTEMPTS);
+        assert!(given_up.failed_at.is_some());
+        assert!(given_up.next_attempt_at.is_none());
+    }
+}